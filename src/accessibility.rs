@@ -0,0 +1,251 @@
+use std::{
+    f32::consts::PI,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    color::{Alpha, Color, Luminance},
+    math::{Quat, Vec2},
+    prelude::{
+        default, BuildChildren, Children, Commands, Component, DespawnRecursiveExt, Entity, Or, Query, Res,
+        ResMut, Resource, Transform, With, Without,
+    },
+    sprite::Anchor,
+    text::{Text, Text2dBounds, Text2dBundle},
+};
+use log::info;
+
+use crate::{
+    boop::Knockback,
+    config::Config,
+    frame_budget::Cosmetic,
+    moderation::{DustPuff, DustingOff},
+    opacity::BaseAlpha,
+    bubble_themes::Typewriter,
+    pyramids::EmoteRaindrop,
+    weather::WeatherParticle,
+    BubbleTail, MessageBubble, Wave,
+};
+
+/// How long a fade standing in for a hop or shake lasts
+const FADE_DURATION: Duration = Duration::from_millis(800);
+/// How much of its alpha a minawan loses at the bottom of a fade
+const FADE_DEPTH: f32 = 0.5;
+/// Least alpha message boxes have with HIGH_CONTRAST, whatever BUBBLE_COLOR says
+const HIGH_CONTRAST_ALPHA: f32 = 0.92;
+/// How far outline copies of message text are shifted from it
+const OUTLINE_WIDTH: f32 = 1.5;
+const OUTLINE_OFFSETS: [Vec2; 8] = [
+    Vec2::new(-1.0, 0.0),
+    Vec2::new(1.0, 0.0),
+    Vec2::new(0.0, -1.0),
+    Vec2::new(0.0, 1.0),
+    Vec2::new(-0.7, -0.7),
+    Vec2::new(0.7, -0.7),
+    Vec2::new(-0.7, 0.7),
+    Vec2::new(0.7, 0.7),
+];
+/// Outlines sit over the message box and under its text, emotes stay on top of both
+const OUTLINE_Z: f32 = 0.1;
+const OUTLINED_TEXT_Z: f32 = 0.2;
+
+/// What effects may do for the viewers, from HIGH_CONTRAST and REDUCED_MOTION, and what the frame
+/// budget has shed. Effects check this rather than the config, so everything follows the same rules.
+#[derive(Resource, Default, Clone, Copy, PartialEq, Debug)]
+pub(crate) struct EffectPolicy {
+    pub(crate) high_contrast: bool,
+    pub(crate) reduced_motion: bool,
+    /// How many cosmetics the frame budget has shed
+    pub(crate) shed: usize,
+}
+
+impl EffectPolicy {
+    fn from_config(config: &Config, shed: usize) -> Self {
+        Self {
+            high_contrast: config.high_contrast,
+            reduced_motion: config.reduced_motion,
+            shed,
+        }
+    }
+
+    /// Whether a cosmetic is kept, the frame budget sheds them in the order they're declared
+    pub(crate) fn allows(&self, cosmetic: Cosmetic) -> bool {
+        cosmetic as usize >= self.shed
+    }
+
+    /// Whether minawan may hop, tip over and shake, and things may fly across the screen
+    pub(crate) fn motion(&self) -> bool {
+        !self.reduced_motion
+    }
+
+    /// Whether avatars and message boxes may be tinted for the mood of the stream
+    pub(crate) fn tinting(&self) -> bool {
+        !self.high_contrast
+    }
+}
+
+/// A minawan dipping in alpha and back, in place of a hop or shake with REDUCED_MOTION
+#[derive(Component)]
+pub(crate) struct GentleFade {
+    started: Instant,
+    /// Its own alpha, to go back to
+    alpha: f32,
+}
+
+/// A message box whose text has its outline
+#[derive(Component)]
+pub(crate) struct Outlined {}
+
+/// A copy of message text in a contrasting colour, shifted to outline it
+#[derive(Component)]
+pub(crate) struct TextOutline {}
+
+// Follow HIGH_CONTRAST and REDUCED_MOTION as config.toml changes
+pub(crate) fn update_effect_policy(config: Res<Config>, mut policy: ResMut<EffectPolicy>) {
+    let updated = EffectPolicy::from_config(&config, policy.shed);
+    if *policy == updated {
+        return;
+    }
+    if policy.high_contrast != updated.high_contrast {
+        info!("High contrast {}", if updated.high_contrast { "on" } else { "off" });
+    }
+    if policy.reduced_motion != updated.reduced_motion {
+        info!("Reduced motion {}", if updated.reduced_motion { "on" } else { "off" });
+    }
+    *policy = updated;
+}
+
+// With REDUCED_MOTION, fade minawan instead of letting them hop, dance, shake off dust or get
+// knocked back, and take away trails and particles flying across the screen as they appear
+#[allow(clippy::type_complexity)]
+pub(crate) fn calm_motion(
+    mut commands: Commands,
+    policy: Res<EffectPolicy>,
+    mut moving_query: Query<
+        (Entity, &mut Transform, Option<&Wave>, Option<&BaseAlpha>, Option<&GentleFade>),
+        Or<(With<Wave>, With<Knockback>, With<DustingOff>)>,
+    >,
+    flying_query: Query<Entity, Or<(With<WeatherParticle>, With<EmoteRaindrop>, With<DustPuff>)>>,
+) {
+    if policy.motion() {
+        return;
+    }
+    let now = Instant::now();
+    for (entity, mut transform, wave, alpha, fading) in moving_query.iter_mut() {
+        if let Some(wave) = wave {
+            transform.translation.y = wave.base_y;
+        }
+        transform.rotation = Quat::IDENTITY;
+        let mut entity_commands = commands.entity(entity);
+        entity_commands.remove::<(Wave, Knockback, DustingOff)>();
+        // Dances hop over and over, a fade under way is left to finish first
+        if let (Some(alpha), None) = (alpha, fading) {
+            entity_commands.insert(GentleFade {
+                started: now,
+                alpha: alpha.0,
+            });
+        }
+    }
+    for entity in flying_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// Dip fading minawan towards transparent and back
+pub(crate) fn animate_gentle_fades(
+    mut commands: Commands,
+    mut fade_query: Query<(Entity, &mut BaseAlpha, &GentleFade)>,
+) {
+    for (entity, mut alpha, fade) in fade_query.iter_mut() {
+        let progress = fade.started.elapsed().as_secs_f32() / FADE_DURATION.as_secs_f32();
+        if progress >= 1.0 {
+            alpha.0 = fade.alpha;
+            commands.entity(entity).remove::<GentleFade>();
+            continue;
+        }
+        alpha.0 = fade.alpha * (1.0 - FADE_DEPTH * (progress * PI).sin());
+    }
+}
+
+/// Black around light text, white around dark text
+fn outline_color(text_color: Color) -> Color {
+    if text_color.luminance() > 0.5 {
+        Color::BLACK
+    } else {
+        Color::WHITE
+    }
+}
+
+// With HIGH_CONTRAST, make message boxes nearly opaque and outline their text. Turning it off
+// gives boxes their BUBBLE_COLOR alpha back and takes the outlines away.
+#[allow(clippy::type_complexity)]
+pub(crate) fn apply_high_contrast(
+    mut commands: Commands,
+    policy: Res<EffectPolicy>,
+    config: Res<Config>,
+    mut box_query: Query<&mut BaseAlpha, Or<(With<MessageBubble>, With<BubbleTail>)>>,
+    bubble_query: Query<(Entity, &Children), (With<MessageBubble>, Without<Outlined>)>,
+    mut text_query: Query<(&Text, &Text2dBounds, &Anchor, &mut Transform), Without<TextOutline>>,
+    typing_query: Query<(), With<Typewriter>>,
+    outline_query: Query<Entity, With<TextOutline>>,
+    outlined_query: Query<Entity, With<Outlined>>,
+) {
+    if !policy.high_contrast {
+        if policy.is_changed() {
+            let alpha = config.bubble_theme.color(&config).alpha();
+            for mut base in box_query.iter_mut() {
+                base.0 = alpha;
+            }
+            for entity in outline_query.iter() {
+                commands.entity(entity).despawn_recursive();
+            }
+            for entity in outlined_query.iter() {
+                commands.entity(entity).remove::<Outlined>();
+            }
+        }
+        return;
+    }
+
+    // Boxes get their BaseAlpha the frame after they appear, they're caught up then
+    let alpha = config.bubble_theme.color(&config).alpha().max(HIGH_CONTRAST_ALPHA);
+    for mut base in box_query.iter_mut() {
+        if base.0 != alpha {
+            base.0 = alpha;
+        }
+    }
+
+    let color = outline_color(config.text_color);
+    for (bubble, children) in bubble_query.iter() {
+        // Outlined once it's all typed out
+        if children.iter().any(|child| typing_query.contains(*child)) {
+            continue;
+        }
+        for child in children.iter() {
+            let Ok((text, bounds, anchor, mut transform)) = text_query.get_mut(*child) else {
+                continue;
+            };
+            transform.translation.z = OUTLINED_TEXT_Z;
+            let mut outline = text.clone();
+            for section in outline.sections.iter_mut() {
+                section.style.color = color.with_alpha(section.style.color.alpha());
+            }
+            commands.entity(bubble).with_children(|builder| {
+                for offset in OUTLINE_OFFSETS {
+                    builder.spawn((
+                        Text2dBundle {
+                            text: outline.clone(),
+                            text_anchor: *anchor,
+                            text_2d_bounds: *bounds,
+                            transform: Transform::from_translation(
+                                (transform.translation.truncate() + offset * OUTLINE_WIDTH).extend(OUTLINE_Z),
+                            ),
+                            ..default()
+                        },
+                        TextOutline {},
+                    ));
+                }
+            });
+        }
+        commands.entity(bubble).insert(Outlined {});
+    }
+}
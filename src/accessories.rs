@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+
+use bevy::{
+    asset::{AssetServer, Assets, Handle},
+    hierarchy::{BuildChildren, DespawnRecursiveExt, Parent},
+    math::Vec2,
+    prelude::{
+        default, Commands, Component, Entity, Event, EventReader, Image, Query, Res, ResMut, Resource, Transform,
+        With, Without,
+    },
+    sprite::{Sprite, SpriteBundle},
+};
+use log::{info, warn};
+
+use crate::{
+    api::ApiEvent,
+    config::{AccessoryReward, Config},
+    birthdays::BirthdayHat,
+    eventsub::EventSubEvent,
+    sizes::AVATAR_HEIGHT,
+    AppState, UserMarker,
+};
+
+/// Over the avatar, under its badges and crown
+const ACCESSORY_Z: f32 = 0.5;
+/// What a viewer types to take their accessory off with a reward that offers a choice
+const NO_ACCESSORY: &str = "none";
+
+/// Take off a user's accessory, sent by the strip admin command
+#[derive(Event, Debug, Clone)]
+pub(crate) struct StripAccessories {
+    pub(crate) user: String,
+}
+
+/// The accessory each user has on, saved with the party snapshot
+#[derive(Resource, Default)]
+pub(crate) struct Accessories {
+    equipped: HashMap<String, String>,
+}
+
+impl Accessories {
+    pub(crate) fn new(equipped: HashMap<String, String>) -> Self {
+        Self { equipped }
+    }
+
+    pub(crate) fn equipped(&self) -> &HashMap<String, String> {
+        &self.equipped
+    }
+
+    /// Forget the accessory a Twitch user chose, whatever the case of their name
+    pub(crate) fn forget(&mut self, login: &str) {
+        self.equipped.retain(|user, _| !user.eq_ignore_ascii_case(login));
+    }
+}
+
+/// An accessory worn by the minawan it's a child of
+#[derive(Component)]
+pub(crate) struct Accessory {}
+
+/// The accessory a redemption asks for, None to take it off. Rewards with one accessory
+/// ignore the text, the others need it to name one they offer.
+fn choose_accessory(reward: &AccessoryReward, user_input: &str) -> Result<Option<String>, String> {
+    if let [accessory] = reward.accessories.as_slice() {
+        return Ok(Some(accessory.clone()));
+    }
+    let words = user_input
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase);
+    for word in words {
+        if word == NO_ACCESSORY {
+            return Ok(None);
+        }
+        if reward.accessories.contains(&word) {
+            return Ok(Some(word));
+        }
+    }
+    Err(format!("{:?} isn't one of {}", user_input, reward.accessories.join(", ")))
+}
+
+/// Put an accessory on a minawan, returning the accessory. It's sized once both images have loaded.
+pub(crate) fn wear(
+    commands: &mut Commands,
+    entity: Entity,
+    accessory: &str,
+    config: &Config,
+    asset_server: &AssetServer,
+) -> Entity {
+    let path = format!("{}/{}.png", config.accessory_folder.trim_end_matches('/'), accessory);
+    let accessory = commands
+        .spawn((
+            SpriteBundle {
+                texture: asset_server.load(path),
+                transform: Transform::from_xyz(0.0, 0.0, ACCESSORY_Z),
+                ..default()
+            },
+            Accessory {},
+        ))
+        .id();
+    commands.entity(entity).add_child(accessory);
+    accessory
+}
+
+/// Take off the accessory a minawan chose, a birthday hat stays on
+fn take_off(
+    commands: &mut Commands,
+    entity: Entity,
+    accessory_query: &Query<(Entity, &Parent), (With<Accessory>, Without<BirthdayHat>)>,
+) {
+    for (accessory, _) in accessory_query.iter().filter(|(_, parent)| parent.get() == entity) {
+        commands.entity(accessory).despawn_recursive();
+    }
+}
+
+// Put on and take off accessories for redemptions and the strip command. Only the accessory
+// is changed on a minawan at the party, the choice is kept for when the user is back.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn handle_accessory_redemptions(
+    mut commands: Commands,
+    mut eventsub_events: EventReader<EventSubEvent>,
+    mut strip_events: EventReader<StripAccessories>,
+    mut accessories: ResMut<Accessories>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    asset_server: Res<AssetServer>,
+    accessory_query: Query<(Entity, &Parent), (With<Accessory>, Without<BirthdayHat>)>,
+) {
+    let redemptions = eventsub_events.read().filter_map(|event| match event {
+        EventSubEvent::Redemption {
+            user_login,
+            reward_title,
+            user_input,
+        } => {
+            let reward = config
+                .accessory_rewards
+                .iter()
+                .find(|reward| reward.title.eq_ignore_ascii_case(reward_title))?;
+            match choose_accessory(reward, user_input) {
+                Ok(accessory) => Some((user_login.to_lowercase(), accessory)),
+                Err(err) => {
+                    warn!("{} redeemed {} but {}, nothing changes", user_login, reward_title, err);
+                    None
+                }
+            }
+        }
+        _ => None,
+    });
+    let strips = strip_events
+        .read()
+        .map(|event| (event.user.trim_start_matches('@').to_lowercase(), None));
+    let changes: Vec<(String, Option<String>)> = redemptions.chain(strips).collect();
+
+    for (user, accessory) in changes {
+        let entity = app_state.active_users.get(&user).map(|user| user.entity);
+        if let Some(entity) = entity {
+            take_off(&mut commands, entity, &accessory_query);
+        }
+        match accessory {
+            Some(accessory) => {
+                info!("{} puts on the {}", user, accessory);
+                if let Some(entity) = entity {
+                    wear(&mut commands, entity, &accessory, &config, &asset_server);
+                }
+                accessories.equipped.insert(user, accessory);
+            }
+            None => {
+                if accessories.equipped.remove(&user).is_some() {
+                    info!("{} takes off their accessory", user);
+                }
+            }
+        }
+    }
+}
+
+// Dress minawan in their accessory when they arrive
+pub(crate) fn put_on_accessories(
+    mut commands: Commands,
+    mut party_events: EventReader<ApiEvent>,
+    accessories: Res<Accessories>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    asset_server: Res<AssetServer>,
+) {
+    for event in party_events.read() {
+        let ApiEvent::UserSpawned { user } = event else {
+            continue;
+        };
+        let Some(accessory) = accessories.equipped.get(user) else {
+            continue;
+        };
+        // An accessory taken out of ACCESSORIES stays chosen in case it comes back
+        if !config.accessory_names.contains(accessory) {
+            continue;
+        }
+        if let Some(entity) = app_state.active_users.get(user).map(|user| user.entity) {
+            wear(&mut commands, entity, accessory, &config, &asset_server);
+        }
+    }
+}
+
+// Keep accessories the size of their minawan, centered on it and facing the same way. Accessory
+// images are made for a normal minawan. This follows giants and size changes as they grow, the
+// sprite being anchored at its feet.
+pub(crate) fn fit_accessories(
+    images: Res<Assets<Image>>,
+    mut accessory_query: Query<(&Parent, &Handle<Image>, &mut Sprite, &mut Transform), With<Accessory>>,
+    avatar_query: Query<&Sprite, (With<UserMarker>, Without<Accessory>)>,
+) {
+    for (parent, texture, mut sprite, mut transform) in accessory_query.iter_mut() {
+        let Ok(avatar) = avatar_query.get(parent.get()) else {
+            continue;
+        };
+        let (Some(avatar_size), Some(image)) = (avatar.custom_size, images.get(texture)) else {
+            continue;
+        };
+        let size = image.size_f32() * avatar_size.y / AVATAR_HEIGHT;
+        if sprite.custom_size != Some(size) {
+            sprite.custom_size = Some(size);
+        }
+        if sprite.flip_x != avatar.flip_x {
+            sprite.flip_x = avatar.flip_x;
+        }
+        let center: Vec2 = -avatar.anchor.as_vec() * avatar_size;
+        if transform.translation.truncate() != center {
+            transform.translation = center.extend(ACCESSORY_Z);
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::{path::Path, process::Command};
+
+use bevy::{
+    app::AppExit,
+    prelude::{
+        Commands, Entity, Event, EventReader, EventWriter, Has, Query, ResMut, With,
+    },
+    window::{PrimaryWindow, Window},
+};
+use log::{info, warn};
+
+use crate::{
+    commands::AdminCommand,
+    config::{CaptureVisibility, Config},
+    debug::DebugOverlay,
+    logging::notify,
+    opacity::{MAX_OPACITY, MIN_OPACITY, OPACITY_STEP},
+    pool::EntityPools,
+    reload::ConfigWatcher,
+    theme::export_theme,
+    AppState, MessageBubble, MessageSpawnTime,
+};
+
+/// Controls for the overlay itself, sent by the global hotkeys and the tray menu
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub(crate) enum OverlayAction {
+    ToggleVisibility,
+    ToggleClickThrough,
+    ToggleCapture,
+    ToggleZenMode,
+    ToggleDebug,
+    ClearParty,
+    PickWinner,
+    Screenshot,
+    ScreenshotBurst,
+    OpacityUp,
+    OpacityDown,
+    OpenConfig,
+    ExportTheme,
+    ReloadConfig,
+    Quit,
+}
+
+/// Open a file with the program the OS uses for it
+fn open_with_default_app(path: &str) -> std::io::Result<()> {
+    #[cfg(windows)]
+    let mut command = {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    };
+    #[cfg(target_os = "macos")]
+    let mut command = Command::new("open");
+    #[cfg(not(any(windows, target_os = "macos")))]
+    let mut command = Command::new("xdg-open");
+
+    command.arg(path).spawn().map(|_| ())
+}
+
+// Run overlay actions from hotkeys and the tray
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn handle_overlay_actions(
+    mut commands: Commands,
+    mut events: EventReader<OverlayAction>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+    mut app_state: ResMut<AppState>,
+    mut watcher: ResMut<ConfigWatcher>,
+    mut config: ResMut<Config>,
+    mut pools: ResMut<EntityPools>,
+    mut debug_overlay: ResMut<DebugOverlay>,
+    message_query: Query<(Entity, Has<MessageBubble>), With<MessageSpawnTime>>,
+    mut admin_commands: EventWriter<AdminCommand>,
+    mut exit: EventWriter<AppExit>,
+) {
+    for action in events.read() {
+        match action {
+            OverlayAction::ToggleVisibility => {
+                for mut window in windows.iter_mut() {
+                    window.visible = !window.visible;
+                    info!("Overlay {}", if window.visible { "shown" } else { "hidden" });
+                }
+            }
+            OverlayAction::ToggleClickThrough => {
+                for mut window in windows.iter_mut() {
+                    window.cursor.hit_test = !window.cursor.hit_test;
+                    info!(
+                        "Overlay {}",
+                        if window.cursor.hit_test { "accepts clicks" } else { "is click-through" }
+                    );
+                }
+            }
+            OverlayAction::ToggleCapture => {
+                config.capture_visibility = match config.capture_visibility {
+                    CaptureVisibility::Include => CaptureVisibility::Exclude,
+                    CaptureVisibility::Exclude => CaptureVisibility::Include,
+                };
+            }
+            OverlayAction::ToggleZenMode => {
+                app_state.zen_mode = !app_state.zen_mode;
+                if app_state.zen_mode {
+                    info!("Zen mode on, hiding messages");
+                    for (entity, bubble) in message_query.iter() {
+                        pools.release_message(&mut commands, entity, bubble, &config);
+                    }
+                } else {
+                    info!("Zen mode off, showing messages");
+                }
+            }
+            OverlayAction::ToggleDebug => {
+                debug_overlay.visible = !debug_overlay.visible;
+            }
+            OverlayAction::ClearParty => {
+                admin_commands.send(AdminCommand::Clear);
+            }
+            OverlayAction::PickWinner => {
+                admin_commands.send(AdminCommand::Pick(None));
+            }
+            OverlayAction::Screenshot | OverlayAction::ScreenshotBurst => {
+                admin_commands.send(AdminCommand::Screenshot {
+                    burst: *action == OverlayAction::ScreenshotBurst,
+                });
+            }
+            OverlayAction::OpacityUp | OverlayAction::OpacityDown => {
+                let step = match action {
+                    OverlayAction::OpacityUp => OPACITY_STEP,
+                    _ => -OPACITY_STEP,
+                };
+                config.opacity = (config.opacity + step).clamp(MIN_OPACITY, MAX_OPACITY);
+                info!("Opacity set to {:.1}", config.opacity);
+            }
+            OverlayAction::OpenConfig => {
+                if let Err(err) = open_with_default_app(watcher.path()) {
+                    warn!("Failed to open {}: {}", watcher.path(), err);
+                }
+            }
+            OverlayAction::ExportTheme => {
+                let theme_path = Path::new(watcher.path()).with_file_name(format!("theme-{}.toml", config.today()));
+                match export_theme(watcher.path(), config.profile.as_deref(), &theme_path) {
+                    Ok(()) => notify(format!("Theme saved to {}", theme_path.display())),
+                    Err(err) => warn!("Failed to export the theme: {}", err),
+                }
+            }
+            OverlayAction::ReloadConfig => watcher.request_reload(),
+            OverlayAction::Quit => {
+                info!("Quitting");
+                exit.send(AppExit::Success);
+            }
+        }
+    }
+}
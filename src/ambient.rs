@@ -0,0 +1,123 @@
+use std::time::{Duration, Instant};
+
+use bevy::{
+    color::{Alpha, Color, LinearRgba, Mix},
+    prelude::{Commands, Component, Entity, Local, Or, Query, Ref, Res, ResMut, Resource, With, Without},
+    sprite::Sprite,
+};
+use chrono::{NaiveTime, Timelike};
+
+use crate::{accessibility::EffectPolicy, config::Config, pool::Pooled, BubbleTail, MessageBubble, UserMarker};
+
+/// How often the time of day is looked at
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Smallest change in the tint worth recolouring everything for
+const TINT_EPSILON: f32 = 0.002;
+const EVENING_TINT: Color = Color::srgb(1.0, 0.82, 0.65);
+const NIGHT_TINT: Color = Color::srgb(0.62, 0.68, 0.9);
+
+/// An avatar's or bubble's own colour, before the time of day tints it. Anything colouring
+/// one should change this rather than the sprite colour, so the two multiply.
+#[derive(Component)]
+pub(crate) struct BaseTint(pub(crate) Color);
+
+/// The colour the time of day multiplies avatars and bubbles by, white in the daytime
+#[derive(Resource)]
+pub(crate) struct AmbientTint {
+    color: LinearRgba,
+}
+
+impl Default for AmbientTint {
+    fn default() -> Self {
+        Self {
+            color: LinearRgba::WHITE,
+        }
+    }
+}
+
+/// Hours since midnight
+fn hours(time: NaiveTime) -> f32 {
+    time.num_seconds_from_midnight() as f32 / 3600.0
+}
+
+/// The tint for a time of day. Each of day, evening and night starts at its configured time,
+/// and the change from one to the next is spread over TRANSITION_MINUTES around it.
+fn tint_at(time: NaiveTime, config: &Config) -> LinearRgba {
+    let strength = config.ambient_strength;
+    let phases = [
+        (hours(config.ambient_morning), Color::WHITE),
+        (hours(config.ambient_evening), Color::WHITE.mix(&EVENING_TINT, strength)),
+        (hours(config.ambient_night), Color::WHITE.mix(&NIGHT_TINT, strength)),
+    ];
+    let now = hours(time);
+    let since = |start: f32| (now - start).rem_euclid(24.0);
+    // The phases in the order they started, the current one first
+    let mut order: Vec<usize> = (0..phases.len()).collect();
+    order.sort_by(|a, b| since(phases[*a].0).total_cmp(&since(phases[*b].0)));
+    let (current, previous, next) = (order[0], order[1], order[2]);
+
+    let half = config.ambient_transition.as_secs_f32() / 3600.0 / 2.0;
+    let into = since(phases[current].0);
+    let until = (phases[next].0 - now).rem_euclid(24.0);
+    let color = if half > 0.0 && into < half {
+        phases[previous].1.mix(&phases[current].1, 0.5 + into / half / 2.0)
+    } else if half > 0.0 && until < half {
+        phases[current].1.mix(&phases[next].1, 0.5 - until / half / 2.0)
+    } else {
+        phases[current].1
+    };
+    color.into()
+}
+
+fn tinted(base: Color, tint: LinearRgba, alpha: f32) -> Color {
+    let base = LinearRgba::from(base);
+    LinearRgba::new(base.red * tint.red, base.green * tint.green, base.blue * tint.blue, alpha).into()
+}
+
+// Tint avatars and message bubbles for the time of day where the streamer is, unless
+// HIGH_CONTRAST is on. Only the colour is changed, OPACITY and fading work on the alpha.
+pub(crate) fn apply_ambient_tint(
+    mut commands: Commands,
+    config: Res<Config>,
+    policy: Res<EffectPolicy>,
+    mut ambient: ResMut<AmbientTint>,
+    mut last_check: Local<Option<Instant>>,
+    mut new_sprites: Query<
+        (Entity, &mut Sprite),
+        (Or<(With<UserMarker>, With<MessageBubble>, With<BubbleTail>)>, Without<BaseTint>, Without<Pooled>),
+    >,
+    mut sprites: Query<(&mut Sprite, Ref<BaseTint>)>,
+) {
+    let due = last_check.map_or(true, |checked| checked.elapsed() >= CHECK_INTERVAL);
+    let mut tint_changed = false;
+    if due || config.is_changed() || policy.is_changed() {
+        *last_check = Some(Instant::now());
+        let tint = if config.ambient_enabled && policy.tinting() {
+            tint_at(config.ambient_time_override.unwrap_or_else(|| config.local_time()), &config)
+        } else {
+            LinearRgba::WHITE
+        };
+        let difference = (tint.red - ambient.color.red)
+            .abs()
+            .max((tint.green - ambient.color.green).abs())
+            .max((tint.blue - ambient.color.blue).abs());
+        // Turning it off goes straight back to white, however close the tint was
+        if difference > TINT_EPSILON || (tint == LinearRgba::WHITE && ambient.color != tint) {
+            ambient.color = tint;
+            tint_changed = true;
+        }
+    }
+    let tint = ambient.color;
+
+    // Remember the colour new avatars and bubbles were spawned with, pooled ones once they're reused
+    for (entity, mut sprite) in new_sprites.iter_mut() {
+        let base = sprite.color.with_alpha(1.0);
+        sprite.color = tinted(base, tint, sprite.color.alpha());
+        commands.entity(entity).insert(BaseTint(base));
+    }
+    for (mut sprite, base) in sprites.iter_mut() {
+        if tint_changed || base.is_changed() {
+            sprite.color = tinted(base.0, tint, sprite.color.alpha());
+        }
+    }
+}
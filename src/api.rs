@@ -0,0 +1,400 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Instant,
+};
+
+use bevy::prelude::{
+    Commands, Event, EventReader, EventWriter, Query, Res, ResMut, Resource, Transform, With, Without,
+};
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::{broadcast, mpsc},
+};
+use tokio_tungstenite::{
+    accept_hdr_async,
+    tungstenite::{
+        self,
+        handshake::server::{ErrorResponse, Request, Response},
+        http::{header::ORIGIN, StatusCode},
+        Message,
+    },
+};
+
+use crate::{
+    actions::OverlayAction, commands::AdminCommand, config::Config, giant::MakeGiant, AppState, ChatMessage,
+    EmoteStorage, Platform, UserMarker, Wave,
+};
+
+/// Events kept for a client that's reading slowly, it's told how many it missed past this
+pub(crate) const API_EVENT_BUFFER: usize = 256;
+
+/// Something that happened at the party, sent to every API client as JSON
+#[derive(Event, Serialize, Debug, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum ApiEvent {
+    UserSpawned { user: String },
+    UserDespawned { user: String },
+    MessageDisplayed { user: String, message: String },
+    EmoteUsed { user: String, emote: String },
+    GiveawayWon { user: String },
+    /// Everyone who raced, the winner first
+    RaceFinished { winner: String, results: Vec<String> },
+}
+
+impl ApiEvent {
+    /// Events for a message going up, the message itself and every emote in it
+    pub(crate) fn for_message(user: &str, message: &str, emote_store: &EmoteStorage) -> Vec<Self> {
+        let emotes = message
+            .split_whitespace()
+            .filter(|word| emote_store.all.contains_key(*word))
+            .map(|emote| ApiEvent::EmoteUsed {
+                user: user.to_string(),
+                emote: emote.to_string(),
+            });
+        std::iter::once(ApiEvent::MessageDisplayed {
+            user: user.to_string(),
+            message: message.to_string(),
+        })
+        .chain(emotes)
+        .collect()
+    }
+}
+
+/// Effects a command can start for one user
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ApiEffect {
+    Giant,
+    Wave,
+}
+
+/// A command from an API client
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub(crate) enum ApiCommand {
+    Clear,
+    Zen {
+        enabled: bool,
+    },
+    SpawnTestUser {
+        #[serde(default)]
+        user: Option<String>,
+        #[serde(default)]
+        message: Option<String>,
+    },
+    Effect {
+        effect: ApiEffect,
+        user: String,
+    },
+    Pick {
+        #[serde(default)]
+        keyword: Option<String>,
+    },
+    Reroll,
+}
+
+/// Sends events to the API clients, there's no one listening while the API is disabled
+#[derive(Resource)]
+pub(crate) struct ApiEvents {
+    pub(crate) sender: broadcast::Sender<String>,
+}
+
+// Wrap the mpsc::Receiver in a struct and derive Resource
+#[derive(Resource)]
+pub(crate) struct ApiCommandReceiver {
+    pub(crate) receiver: mpsc::Receiver<ApiCommand>,
+}
+
+/// The WebSocket server. Events come in from Bevy through `events`, commands go back through
+/// `commands`, apart from test users which are sent as chat messages.
+pub(crate) struct ApiServer {
+    bind: IpAddr,
+    port: u16,
+    token: Option<String>,
+    allowed_origins: Vec<String>,
+    events: broadcast::Sender<String>,
+    commands: mpsc::Sender<ApiCommand>,
+    chat: mpsc::Sender<ChatMessage>,
+}
+
+impl ApiServer {
+    pub(crate) fn from_config(
+        config: &Config,
+        events: broadcast::Sender<String>,
+        commands: mpsc::Sender<ApiCommand>,
+        chat: mpsc::Sender<ChatMessage>,
+    ) -> Self {
+        Self {
+            bind: config.api_bind,
+            port: config.api_port,
+            token: config.api_token.clone(),
+            allowed_origins: config.api_allowed_origins.clone(),
+            events,
+            commands,
+            chat,
+        }
+    }
+
+    pub(crate) async fn run(self) {
+        let addr = SocketAddr::new(self.bind, self.port);
+        let listener = match TcpListener::bind(addr).await {
+            Ok(listener) => listener,
+            Err(err) => {
+                error!("Could not start the API on {}: {}", addr, err);
+                return;
+            }
+        };
+        info!("API listening on ws://{}", addr);
+        if self.token.is_none() && !self.bind.is_loopback() {
+            warn!("The API can be reached from other machines and has no TOKEN, anyone there can control the party");
+        }
+
+        let server = Arc::new(self);
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(err) => {
+                    warn!("API connection failed: {}", err);
+                    continue;
+                }
+            };
+            let server = server.clone();
+            tokio::spawn(async move {
+                match server.serve(stream, peer).await {
+                    Ok(()) => info!("API client {} disconnected", peer),
+                    Err(err) => debug!("API client {} dropped: {}", peer, err),
+                }
+            });
+        }
+    }
+
+    /// Whether the request carries the token, as `?token=`
+    fn authorized(&self, request: &Request) -> bool {
+        let Some(token) = &self.token else {
+            return true;
+        };
+        request
+            .uri()
+            .query()
+            .unwrap_or_default()
+            .split('&')
+            .filter_map(|pair| pair.strip_prefix("token="))
+            .any(|given| given == token)
+    }
+
+    /// Whether a web page may connect. Browsers send the page's origin with the handshake, any
+    /// web page can open a WebSocket to loopback, so ones not in ALLOWED_ORIGINS are refused.
+    /// Programs don't send one.
+    fn origin_allowed(&self, request: &Request) -> bool {
+        let Some(origin) = request.headers().get(ORIGIN) else {
+            return true;
+        };
+        let origin = origin.to_str().unwrap_or_default().trim_end_matches('/').to_lowercase();
+        self.allowed_origins.contains(&origin)
+    }
+
+    async fn serve(&self, stream: TcpStream, peer: SocketAddr) -> Result<(), tungstenite::Error> {
+        let check_token = |request: &Request, response: Response| {
+            let (status, reason) = if !self.origin_allowed(request) {
+                warn!("API client {} is a web page not in ALLOWED_ORIGINS: {:?}", peer, request.headers().get(ORIGIN));
+                (StatusCode::FORBIDDEN, "Origin not allowed")
+            } else if !self.authorized(request) {
+                warn!("API client {} has the wrong token", peer);
+                (StatusCode::UNAUTHORIZED, "Missing or wrong token")
+            } else {
+                return Ok(response);
+            };
+            let mut rejection = ErrorResponse::new(Some(reason.to_string()));
+            *rejection.status_mut() = status;
+            Err(rejection)
+        };
+        let socket = accept_hdr_async(stream, check_token).await?;
+        info!("API client {} connected", peer);
+
+        let (mut sink, mut messages) = socket.split();
+        let mut events = self.events.subscribe();
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(json) => sink.send(Message::Text(json)).await?,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        let frame = error_frame(&format!("Missed {} events, reading too slowly", missed));
+                        sink.send(Message::Text(frame)).await?;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                },
+                message = messages.next() => match message {
+                    Some(Ok(Message::Text(text))) => {
+                        if let Err(err) = self.run_command(&text).await {
+                            sink.send(Message::Text(error_frame(&err))).await?;
+                        }
+                    }
+                    Some(Ok(Message::Binary(_))) => {
+                        sink.send(Message::Text(error_frame("Commands have to be sent as text"))).await?;
+                    }
+                    Some(Ok(Message::Close(_))) | None => return Ok(()),
+                    // Pings are answered by tungstenite
+                    Some(Ok(_)) => {}
+                    Some(Err(err)) => return Err(err),
+                },
+            }
+        }
+    }
+
+    /// Pass a command on, or say what's wrong with it
+    async fn run_command(&self, text: &str) -> Result<(), String> {
+        let command: ApiCommand = serde_json::from_str(text).map_err(|err| format!("Invalid command: {}", err))?;
+        debug!("API command: {:?}", command);
+        let sent = match command {
+            // Goes through chat so it spawns like any new chatter would
+            ApiCommand::SpawnTestUser { user, message } => {
+                let user = user
+                    .map(|user| user.trim_start_matches('@').to_lowercase())
+                    .filter(|user| !user.is_empty())
+                    .unwrap_or_else(|| format!("test_minawan_{}", rand::random::<u16>()));
+                let mut chat_message = ChatMessage::synthetic(Platform::Twitch, &user);
+                chat_message.message = message.unwrap_or_else(|| "Hello from the API".to_string());
+                self.chat.send(chat_message).await.is_ok()
+            }
+            command => self.commands.send(command).await.is_ok(),
+        };
+        if sent {
+            Ok(())
+        } else {
+            Err("The overlay is shutting down".to_string())
+        }
+    }
+}
+
+/// Reply to a single client when something it sent didn't work
+fn error_frame(message: &str) -> String {
+    json!({ "event": "error", "message": message }).to_string()
+}
+
+// Send this frame's events to the API clients
+pub(crate) fn broadcast_api_events(mut events: EventReader<ApiEvent>, api: Res<ApiEvents>) {
+    if api.sender.receiver_count() == 0 {
+        events.clear();
+        return;
+    }
+    for event in events.read() {
+        match serde_json::to_string(event) {
+            Ok(json) => {
+                let _ = api.sender.send(json);
+            }
+            Err(err) => warn!("Could not encode API event {:?}: {}", event, err),
+        }
+    }
+}
+
+// Run commands sent by API clients
+pub(crate) fn handle_api_commands(
+    mut commands: Commands,
+    mut receiver: ResMut<ApiCommandReceiver>,
+    app_state: Res<AppState>,
+    avatar_query: Query<&Transform, (With<UserMarker>, Without<Wave>)>,
+    mut admin_commands: EventWriter<AdminCommand>,
+    mut actions: EventWriter<OverlayAction>,
+    mut giant_events: EventWriter<MakeGiant>,
+) {
+    // Zen mode only flips once the actions run, keep track of it for several commands in a frame
+    let mut zen_mode = app_state.zen_mode;
+    while let Ok(command) = receiver.receiver.try_recv() {
+        match command {
+            ApiCommand::Clear => {
+                admin_commands.send(AdminCommand::Clear);
+            }
+            ApiCommand::Pick { keyword } => {
+                admin_commands.send(AdminCommand::Pick(keyword));
+            }
+            ApiCommand::Reroll => {
+                admin_commands.send(AdminCommand::Reroll);
+            }
+            ApiCommand::Zen { enabled } => {
+                if enabled != zen_mode {
+                    zen_mode = enabled;
+                    actions.send(OverlayAction::ToggleZenMode);
+                }
+            }
+            // The server sends these as chat messages
+            ApiCommand::SpawnTestUser { .. } => {}
+            ApiCommand::Effect {
+                effect: ApiEffect::Giant,
+                user,
+            } => {
+                giant_events.send(MakeGiant { user });
+            }
+            ApiCommand::Effect {
+                effect: ApiEffect::Wave,
+                user,
+            } => {
+                let user = user.trim_start_matches('@').to_lowercase();
+                let Some(entity) = app_state.active_users.get(&user).map(|user| user.entity) else {
+                    info!("{} isn't at the party, can't make them wave", user);
+                    continue;
+                };
+                // Already waving otherwise
+                if let Ok(transform) = avatar_query.get(entity) {
+                    commands.entity(entity).insert(Wave {
+                        started: Instant::now(),
+                        base_y: transform.translation.y,
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(token: Option<&str>, allowed_origins: &[&str]) -> ApiServer {
+        ApiServer {
+            bind: IpAddr::from([127, 0, 0, 1]),
+            port: 7270,
+            token: token.map(str::to_string),
+            allowed_origins: allowed_origins.iter().map(|origin| origin.to_string()).collect(),
+            events: broadcast::channel(1).0,
+            commands: mpsc::channel(1).0,
+            chat: mpsc::channel(1).0,
+        }
+    }
+
+    fn request(uri: &str, origin: Option<&str>) -> Request {
+        let mut request = Request::builder().uri(uri);
+        if let Some(origin) = origin {
+            request = request.header(ORIGIN, origin);
+        }
+        request.body(()).unwrap()
+    }
+
+    #[test]
+    fn programs_without_an_origin_connect() {
+        assert!(server(None, &[]).origin_allowed(&request("/", None)));
+    }
+
+    #[test]
+    fn web_pages_not_allowed_are_refused() {
+        let server = server(None, &["http://localhost:8080"]);
+        assert!(!server.origin_allowed(&request("/", Some("https://example.com"))));
+        assert!(!server.origin_allowed(&request("/", Some("http://localhost:9000"))));
+        assert!(!server.origin_allowed(&request("/", Some("null"))));
+        assert!(server.origin_allowed(&request("/", Some("http://localhost:8080"))));
+        assert!(server.origin_allowed(&request("/", Some("HTTP://LOCALHOST:8080/"))));
+    }
+
+    #[test]
+    fn token_is_needed_once_set() {
+        assert!(server(None, &[]).authorized(&request("/", None)));
+        let server = server(Some("secret"), &[]);
+        assert!(!server.authorized(&request("/", None)));
+        assert!(!server.authorized(&request("/?token=wrong", None)));
+        assert!(server.authorized(&request("/?other=1&token=secret", None)));
+    }
+}
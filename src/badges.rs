@@ -0,0 +1,355 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use bevy::{
+    asset::AssetServer,
+    color::Color,
+    hierarchy::BuildChildren,
+    math::{Vec2, Vec3},
+    prelude::{default, Commands, Component, Entity, Query, Res, ResMut, Resource, SpatialBundle, Transform},
+    sprite::{Anchor, Sprite, SpriteBundle},
+    text::{Text, Text2dBundle, TextStyle},
+};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::{
+    config::Config,
+    framerate::{forward_with_wake, FrameWaker},
+    handles::HandleCache,
+    helix::{HelixBadgeSet, HelixClient},
+    locale::t,
+    paths::data_dir,
+    state::{read_state_file, unix_now, write_state_file},
+    ChatMessage,
+};
+
+const BADGES_FILE: &str = "badges.toml";
+/// Folder next to party.toml the badge images are kept in
+const BADGE_DIR: &str = "badges";
+/// Badges asked about and not answered yet, anyone past this gets the built in icon
+const LOOKUP_QUEUE: usize = 50;
+/// Badges shown before any other, in order. Anything else comes after, in the order Twitch sends them.
+const PRIORITY: [&str; 6] = ["broadcaster", "moderator", "vip", "hype-train", "founder", "subscriber"];
+/// Position of the badge relative to the avatar's center, where other platforms have their platform badge
+const BADGE_OFFSET: Vec3 = Vec3::new(-20.0, 20.0, 1.0);
+const BADGE_SIZE: f32 = 12.0;
+/// Side of the square drawn for a badge without an image
+const ICON_SIZE: f32 = 8.0;
+
+/// Links to the badge images from the last time they were fetched, by `set/version`
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct BadgeLinks {
+    /// Seconds since the Unix epoch
+    fetched: u64,
+    channel_id: String,
+    global: HashMap<String, String>,
+    /// The channel's own badges, used over the global ones
+    channel: HashMap<String, String>,
+}
+
+/// Badge images found this session, and the channels to the task finding them
+#[derive(Resource)]
+pub(crate) struct TwitchBadges {
+    requests: mpsc::Sender<String>,
+    results: mpsc::Receiver<(String, Option<PathBuf>)>,
+    /// The image on disk for each badge, None for ones without one
+    known: HashMap<String, Option<PathBuf>>,
+    pending: HashSet<String>,
+}
+
+/// The badge a Twitch chatter's minawan shows, picked when it spawns
+#[derive(Component)]
+pub(crate) struct ChatBadge {
+    /// Like `subscriber/2012`
+    badge: String,
+    /// Months, and the tier above tier 1, shown next to sub badges
+    label: Option<String>,
+    drawn: bool,
+}
+
+/// Start finding badge images in the background, if ENABLED. Without CLIENT_ID and OAUTH_TOKEN
+/// only the images from earlier runs are used.
+pub(crate) fn start_badge_lookups(config: &Config, helix: Option<HelixClient>, waker: FrameWaker) -> TwitchBadges {
+    let (request_tx, request_rx) = mpsc::channel::<String>(LOOKUP_QUEUE);
+    let (result_tx, result_rx) = mpsc::channel::<(String, Option<PathBuf>)>(LOOKUP_QUEUE);
+    if config.badges_enabled {
+        let channel_id = config.channel_id.clone();
+        tokio::spawn(look_up_badges(helix, channel_id, config.badges_cache_time, request_rx, result_tx));
+    }
+    TwitchBadges {
+        requests: request_tx,
+        results: forward_with_wake(result_rx, waker),
+        known: HashMap::new(),
+        pending: HashSet::new(),
+    }
+}
+
+/// The badge to show for a message's sender, None when they have none or aren't on Twitch
+pub(crate) fn choose_badge(chat_message: &ChatMessage) -> Option<ChatBadge> {
+    let (set, version) = chat_message
+        .badge_versions
+        .iter()
+        .filter_map(|badge| badge.split_once('/'))
+        .min_by_key(|(set, _)| PRIORITY.iter().position(|first| first == set).unwrap_or(PRIORITY.len()))?;
+    let label = match set {
+        "subscriber" | "founder" => {
+            // Tier 2 and 3 sub badges are 2000 and 3000 plus the milestone
+            let version = version.parse::<u32>().unwrap_or_default();
+            let months = chat_message.sub_months.unwrap_or(version % 1000).max(1);
+            match version / 1000 {
+                tier @ (2 | 3) => Some(t!("badges.tier_months", tier = tier, count = months)),
+                _ => Some(t!("badges.months", count = months)),
+            }
+        }
+        _ => None,
+    };
+    Some(ChatBadge {
+        badge: format!("{}/{}", set, version),
+        label,
+        drawn: false,
+    })
+}
+
+/// Every image link in the badge sets by `set/version`
+fn image_links(sets: Vec<HelixBadgeSet>) -> HashMap<String, String> {
+    sets.into_iter()
+        .flat_map(|set| {
+            let set_id = set.set_id;
+            set.versions
+                .into_iter()
+                .map(move |version| (format!("{}/{}", set_id, version.id), version.image_url_2x))
+        })
+        .collect()
+}
+
+/// The link for a badge. Channels only have images for some subscriber milestones, the months
+/// in between use the last milestone of the same tier.
+fn closest_link<'a>(links: &'a HashMap<String, String>, badge: &str) -> Option<&'a String> {
+    if let Some(link) = links.get(badge) {
+        return Some(link);
+    }
+    let (set, version) = badge.split_once('/')?;
+    let version = version.parse::<u32>().ok()?;
+    links
+        .iter()
+        .filter_map(|(key, link)| {
+            let (other_set, other) = key.split_once('/')?;
+            let other = other.parse::<u32>().ok()?;
+            (other_set == set && other / 1000 == version / 1000 && other <= version).then_some((other, link))
+        })
+        .max_by_key(|(other, _)| *other)
+        .map(|(_, link)| link)
+}
+
+/// Where a badge image is kept, named after the id in its link so new art gets a new file
+fn image_path(link: &str) -> Option<PathBuf> {
+    // Links look like https://static-cdn.jtvnw.net/badges/v1/<id>/2
+    let id = link.trim_end_matches('/').rsplit('/').nth(1)?;
+    let valid = !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    valid.then(|| data_dir().join(BADGE_DIR).join(format!("{}.png", id)))
+}
+
+async fn fetch_links(helix: &HelixClient, channel_id: &str) -> Result<BadgeLinks, reqwest::Error> {
+    let global = image_links(helix.get_global_badges().await?);
+    let channel = if channel_id.is_empty() {
+        HashMap::new()
+    } else {
+        image_links(helix.get_channel_badges(channel_id).await?)
+    };
+    Ok(BadgeLinks {
+        fetched: unix_now(),
+        channel_id: channel_id.to_string(),
+        global,
+        channel,
+    })
+}
+
+/// The links kept from the last run while they're younger than CACHE_HOURS, otherwise fetched
+/// again. When fetching fails the old ones are better than none.
+async fn load_links(helix: Option<&HelixClient>, channel_id: &str, cache_time: Duration) -> BadgeLinks {
+    let cached = read_state_file::<BadgeLinks>(BADGES_FILE).filter(|links| links.channel_id == channel_id);
+    let fresh = cached
+        .as_ref()
+        .is_some_and(|links| unix_now().saturating_sub(links.fetched) < cache_time.as_secs());
+    match (helix, cached) {
+        (_, Some(cached)) if fresh => cached,
+        (Some(helix), cached) => match fetch_links(helix, channel_id).await {
+            Ok(links) => {
+                if let Err(err) = write_state_file(BADGES_FILE, &links) {
+                    debug!("Failed to save the badge links: {}", err);
+                }
+                links
+            }
+            Err(err) => {
+                warn!("Failed to get the Twitch badges: {}", err);
+                cached.unwrap_or_default()
+            }
+        },
+        (None, cached) => {
+            info!("Twitch badges need CLIENT_ID and OAUTH_TOKEN, using the ones saved earlier or the built in icons");
+            cached.unwrap_or_default()
+        }
+    }
+}
+
+async fn download(client: &reqwest::Client, link: &str, path: &Path) -> Result<(), String> {
+    let bytes = client
+        .get(link)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| err.to_string())?
+        .bytes()
+        .await
+        .map_err(|err| err.to_string())?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|err| format!("Failed to create {}: {}", dir.display(), err))?;
+    }
+    fs::write(path, bytes).map_err(|err| format!("Failed to write {}: {}", path.display(), err))
+}
+
+/// The image for a badge on disk, downloaded the first time anyone has it
+async fn image_file(client: &reqwest::Client, links: &BadgeLinks, badge: &str) -> Option<PathBuf> {
+    let link = closest_link(&links.channel, badge).or_else(|| closest_link(&links.global, badge))?;
+    let path = image_path(link)?;
+    if !path.exists() {
+        if let Err(err) = download(client, link, &path).await {
+            debug!("Failed to download the image for the {} badge: {}", badge, err);
+            return None;
+        }
+    }
+    Some(path)
+}
+
+/// Answer which image each badge has one at a time. Badges without one are answered with None
+/// and get the built in icon.
+async fn look_up_badges(
+    helix: Option<HelixClient>,
+    channel_id: String,
+    cache_time: Duration,
+    mut requests: mpsc::Receiver<String>,
+    results: mpsc::Sender<(String, Option<PathBuf>)>,
+) {
+    let links = load_links(helix.as_ref(), &channel_id, cache_time).await;
+    let client = reqwest::Client::new();
+    while let Some(badge) = requests.recv().await {
+        let image = image_file(&client, &links, &badge).await;
+        if results.send((badge, image)).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Colour of the square drawn for a badge without an image, None for badges that get nothing
+fn icon_color(set: &str) -> Option<Color> {
+    match set {
+        "broadcaster" => Some(Color::srgb_u8(0xE9, 0x19, 0x16)),
+        "moderator" => Some(Color::srgb_u8(0x00, 0xAD, 0x03)),
+        "vip" => Some(Color::srgb_u8(0xE0, 0x05, 0xB9)),
+        "subscriber" | "founder" => Some(Color::srgb_u8(0x91, 0x46, 0xFF)),
+        "hype-train" => Some(Color::srgb_u8(0xFF, 0x8C, 0x00)),
+        _ => None,
+    }
+}
+
+/// Put the badge next to a minawan, the months to the left of it
+fn draw_badge(
+    commands: &mut Commands,
+    handles: &mut HandleCache,
+    asset_server: &AssetServer,
+    config: &Config,
+    entity: Entity,
+    badge: &ChatBadge,
+    image: Option<&PathBuf>,
+) {
+    let set = badge.badge.split_once('/').map_or(badge.badge.as_str(), |(set, _)| set);
+    let icon = match image {
+        Some(path) => SpriteBundle {
+            sprite: Sprite {
+                custom_size: Some(Vec2::splat(BADGE_SIZE)),
+                ..default()
+            },
+            texture: handles.load(asset_server, &path.to_string_lossy()),
+            ..default()
+        },
+        None => {
+            let Some(color) = icon_color(set) else {
+                return;
+            };
+            SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(Vec2::splat(ICON_SIZE)),
+                    ..default()
+                },
+                ..default()
+            }
+        }
+    };
+    commands.entity(entity).with_children(|parent| {
+        parent
+            .spawn(SpatialBundle::from_transform(Transform::from_translation(BADGE_OFFSET)))
+            .with_children(|badge_parent| {
+                badge_parent.spawn(icon);
+                if let Some(label) = &badge.label {
+                    badge_parent.spawn(Text2dBundle {
+                        text: Text::from_section(
+                            label.clone(),
+                            TextStyle {
+                                font: asset_server.load(&config.font_url),
+                                font_size: config.font_size * 0.6,
+                                color: Color::WHITE,
+                            },
+                        ),
+                        text_anchor: Anchor::CenterRight,
+                        transform: Transform::from_xyz(-BADGE_SIZE / 2.0 - 2.0, 0.0, 0.1),
+                        ..default()
+                    });
+                }
+            });
+    });
+}
+
+// Draw the badge on Twitch chatters' minawan, asking for its image the first time anyone has it.
+// Minawan wait for the answer, which is quick once the image is on disk.
+pub(crate) fn show_twitch_badges(
+    mut commands: Commands,
+    mut badges: ResMut<TwitchBadges>,
+    mut handles: ResMut<HandleCache>,
+    asset_server: Res<AssetServer>,
+    config: Res<Config>,
+    mut avatar_query: Query<(Entity, &mut ChatBadge)>,
+) {
+    let badges = &mut *badges;
+    while let Ok((badge, image)) = badges.results.try_recv() {
+        badges.pending.remove(&badge);
+        badges.known.insert(badge, image);
+    }
+
+    for (entity, mut badge) in avatar_query.iter_mut() {
+        if badge.drawn {
+            continue;
+        }
+        let image = match badges.known.get(&badge.badge) {
+            Some(image) => image.as_ref(),
+            None if badges.pending.contains(&badge.badge) => continue,
+            None => {
+                if badges.requests.try_send(badge.badge.clone()).is_ok() {
+                    badges.pending.insert(badge.badge.clone());
+                    continue;
+                }
+                // Too many waiting, or lookups are off
+                None
+            }
+        };
+        draw_badge(&mut commands, &mut handles, &asset_server, &config, entity, &badge, image);
+        badge.drawn = true;
+    }
+}
@@ -0,0 +1,238 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    time::{Duration, Instant, SystemTime},
+};
+
+use bevy::{
+    asset::AssetServer,
+    color::Color,
+    hierarchy::{BuildChildren, DespawnRecursiveExt},
+    math::Vec2,
+    prelude::{
+        default, Commands, Component, Entity, EventReader, Local, Or, Query, Res, ResMut, Resource, SpatialBundle,
+        Transform, With,
+    },
+    sprite::{Sprite, SpriteBundle},
+};
+use chrono::{Datelike, NaiveDate};
+use log::{debug, info, warn};
+
+use crate::{accessories::wear, api::ApiEvent, config::Config, weather::burst_confetti, AppState};
+
+/// How often the birthdays file is checked for changes and the date for midnight
+const BIRTHDAY_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+/// Confetti thrown over a birthday minawan the first time they chat that day
+const CONFETTI_PIECES: usize = 40;
+/// Position of the cake relative to the avatar's center, over the platform badge
+const CAKE_OFFSET: Vec2 = Vec2::new(-20.0, 32.0);
+const CAKE_COLOR: Color = Color::srgb(0.95, 0.55, 0.7);
+const ICING_COLOR: Color = Color::srgb(1.0, 0.97, 0.9);
+const CANDLE_COLOR: Color = Color::srgb(0.4, 0.7, 1.0);
+const FLAME_COLOR: Color = Color::srgb(1.0, 0.8, 0.2);
+
+/// Everyone's birthday from the birthdays FILE, and who has been celebrated today
+#[derive(Resource, Default)]
+pub(crate) struct Birthdays {
+    /// Month and day for each user, keyed like the party's users but in lowercase
+    dates: HashMap<String, (u32, u32)>,
+    /// The file the dates were read from and when it was last changed
+    source: Option<(String, Option<SystemTime>)>,
+    /// The day minawan at the party were last dressed for
+    day: Option<NaiveDate>,
+    /// Users whose confetti went off today
+    celebrated: HashSet<String>,
+}
+
+impl Birthdays {
+    fn is_birthday(&self, user: &str, today: NaiveDate) -> bool {
+        let Some(&(month, day)) = self.dates.get(&user.to_lowercase()) else {
+            return false;
+        };
+        // Leap day birthdays are on the 28th in the other years
+        let leap_day_moved = (month, day) == (2, 29) && !today.leap_year() && (today.month(), today.day()) == (2, 28);
+        (today.month(), today.day()) == (month, day) || leap_day_moved
+    }
+}
+
+/// The party hat worn on a minawan's birthday, an accessory of its own
+#[derive(Component)]
+pub(crate) struct BirthdayHat {}
+
+/// The cake badge on a minawan whose birthday it is
+#[derive(Component)]
+pub(crate) struct BirthdayCake {}
+
+/// A `username = MM-DD` line of the birthdays file
+fn parse_line(line: &str) -> Result<(String, (u32, u32)), String> {
+    let (user, date) = line
+        .split_once('=')
+        .ok_or_else(|| "expected username = MM-DD".to_string())?;
+    let user = user.trim().trim_start_matches('@').to_lowercase();
+    if user.is_empty() {
+        return Err("missing the username".to_string());
+    }
+    let date = date.trim().trim_matches('"');
+    let (month, day) = date
+        .split_once('-')
+        .and_then(|(month, day)| Some((month.parse().ok()?, day.parse().ok()?)))
+        .ok_or_else(|| format!("{:?} isn't a date like 07-31", date))?;
+    // Any leap year will do to check the day exists
+    if NaiveDate::from_ymd_opt(2000, month, day).is_none() {
+        return Err(format!("{:?} isn't a day of the year", date));
+    }
+    Ok((user, (month, day)))
+}
+
+/// Read the birthdays file. A broken line is reported and skipped, the rest still count.
+fn read_birthdays(path: &str) -> HashMap<String, (u32, u32)> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) => {
+            warn!("Failed to read the birthdays from {}: {}", path, err);
+            return HashMap::new();
+        }
+    };
+    let mut dates = HashMap::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_line(line) {
+            Ok((user, date)) => {
+                dates.insert(user, date);
+            }
+            Err(err) => warn!("{} line {}: {}, skipping it", path, number + 1, err),
+        }
+    }
+    info!("Read {} birthdays from {}", dates.len(), path);
+    dates
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// A party hat and a cake with a candle for a minawan
+fn dress(commands: &mut Commands, entity: Entity, config: &Config, asset_server: &AssetServer) {
+    if let Some(hat) = &config.birthday_hat {
+        let hat = wear(commands, entity, hat, config, asset_server);
+        commands.entity(hat).insert(BirthdayHat {});
+    }
+    commands.entity(entity).with_children(|parent| {
+        parent
+            .spawn((
+                SpatialBundle::from_transform(Transform::from_translation(CAKE_OFFSET.extend(1.0))),
+                BirthdayCake {},
+            ))
+            .with_children(|cake| {
+                for (color, size, y) in [
+                    (CAKE_COLOR, Vec2::new(10.0, 5.0), -2.5),
+                    (ICING_COLOR, Vec2::new(10.0, 2.0), 1.0),
+                    (CANDLE_COLOR, Vec2::new(2.0, 4.0), 4.0),
+                    (FLAME_COLOR, Vec2::new(2.0, 2.0), 7.0),
+                ] {
+                    cake.spawn(SpriteBundle {
+                        sprite: Sprite {
+                            color,
+                            custom_size: Some(size),
+                            ..default()
+                        },
+                        transform: Transform::from_xyz(0.0, y, 0.0),
+                        ..default()
+                    });
+                }
+            });
+    });
+}
+
+// Dress birthday minawan as they arrive and throw confetti the first time they chat that day.
+// The birthdays FILE is read again whenever it changes, and at midnight the minawan at the
+// party are dressed for the new day.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn celebrate_birthdays(
+    mut commands: Commands,
+    mut party_events: EventReader<ApiEvent>,
+    mut birthdays: ResMut<Birthdays>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    asset_server: Res<AssetServer>,
+    dressing_query: Query<Entity, Or<(With<BirthdayHat>, With<BirthdayCake>)>>,
+    transform_query: Query<&Transform>,
+    mut last_check: Local<Option<Instant>>,
+) {
+    let today = config.today();
+    let mut dressed = HashSet::new();
+    if last_check.map_or(true, |last| last.elapsed() >= BIRTHDAY_CHECK_INTERVAL) {
+        *last_check = Some(Instant::now());
+        let source = config
+            .birthdays_file
+            .as_ref()
+            .map(|path| (path.clone(), modified_time(path)));
+        if source != birthdays.source {
+            birthdays.dates = match &source {
+                Some((path, _)) => read_birthdays(path),
+                None => HashMap::new(),
+            };
+            birthdays.source = source;
+            // Dress everyone again for the new dates
+            birthdays.day = None;
+        }
+        if birthdays.day != Some(today) {
+            if birthdays.day.is_some() {
+                birthdays.celebrated.clear();
+            }
+            birthdays.day = Some(today);
+            for entity in dressing_query.iter() {
+                commands.entity(entity).despawn_recursive();
+            }
+            for (key, user) in app_state.active_users.iter() {
+                if birthdays.is_birthday(key, today) {
+                    info!("It's {}'s birthday", key);
+                    dress(&mut commands, user.entity, &config, &asset_server);
+                    dressed.insert(user.entity);
+                }
+            }
+        }
+    }
+
+    if birthdays.dates.is_empty() {
+        party_events.clear();
+        return;
+    }
+    for event in party_events.read() {
+        match event {
+            ApiEvent::UserSpawned { user } if birthdays.is_birthday(user, today) => {
+                let Some(entity) = app_state.active_users.get(user).map(|user| user.entity) else {
+                    continue;
+                };
+                if dressed.insert(entity) {
+                    debug!("{} arrives on their birthday", user);
+                    dress(&mut commands, entity, &config, &asset_server);
+                }
+            }
+            ApiEvent::MessageDisplayed { user, .. } if birthdays.is_birthday(user, today) => {
+                if !birthdays.celebrated.insert(user.to_lowercase()) {
+                    continue;
+                }
+                let Some(transform) = app_state
+                    .active_users
+                    .get(user)
+                    .and_then(|user| transform_query.get(user.entity).ok())
+                else {
+                    continue;
+                };
+                info!("Happy birthday {}!", user);
+                let position = transform.translation;
+                burst_confetti(
+                    &mut commands,
+                    (position.x - 40.0, position.x + 40.0),
+                    (position.y + 40.0, position.y + 100.0),
+                    CONFETTI_PIECES,
+                );
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    color::Color,
+    hierarchy::{BuildChildren, Children, DespawnRecursiveExt},
+    math::{Quat, Vec2, Vec3},
+    prelude::{
+        default, Camera, Commands, Component, Entity, EventReader, Has, Query, Res, ResMut, Resource,
+        Transform, With, Without,
+    },
+    sprite::{Sprite, SpriteBundle},
+    time::Time,
+};
+use log::debug;
+
+use crate::{
+    api::ApiEvent,
+    config::Config,
+    opacity::{fade, BaseAlpha},
+    sizes::{avatar_size, head_y, SizeClass},
+    users::clamp_to_ranges,
+    AppState, HypeTrain, OverlayCamera, UserAction, UserActionDetails, UserMarker, Wave,
+};
+
+/// How much faster than wandering a minawan walks over to boop
+const BOOP_SPEED_FACTOR: f32 = 1.5;
+/// How far the booped minawan slides away
+const KNOCKBACK_DISTANCE: f32 = 40.0;
+const KNOCKBACK_TIME: Duration = Duration::from_millis(300);
+/// How long the star floats up and fades
+const PARTICLE_TIME: Duration = Duration::from_secs(1);
+const PARTICLE_RISE: f32 = 40.0;
+const PARTICLE_SIZE: f32 = 14.0;
+const PARTICLE_COLOR: Color = Color::srgb(1.0, 0.45, 0.7);
+
+/// A minawan on its way to boop another one, it doesn't wander meanwhile
+#[derive(Component)]
+pub(crate) struct Booping {
+    target: Entity,
+    started: Instant,
+}
+
+/// A booped minawan sliding away from the one that booped it
+#[derive(Component)]
+pub(crate) struct Knockback {
+    started: Instant,
+    /// -1 to slide left, 1 to slide right
+    direction: f32,
+}
+
+/// The star that pops up where two minawan bumped
+#[derive(Component)]
+pub(crate) struct BoopParticle {
+    started: Instant,
+    base_y: f32,
+}
+
+/// When each user last booped someone
+#[derive(Resource, Default)]
+pub(crate) struct BoopCooldowns {
+    last_boop: HashMap<String, Instant>,
+}
+
+/// The target of a boop command, or None when the message isn't one
+fn boop_target<'a>(message: &'a str, config: &Config) -> Option<&'a str> {
+    let mut words = message.split_whitespace();
+    if !words.next()?.eq_ignore_ascii_case(&config.boop_command) {
+        return None;
+    }
+    words.next().map(|target| target.trim_start_matches('@'))
+}
+
+/// A target's AppState key. Without a platform prefix of their own they're looked for on the
+/// booper's platform.
+fn target_key(booper: &str, target: &str) -> String {
+    let target = target.to_lowercase();
+    if target.contains(':') {
+        return target;
+    }
+    match booper.split_once(':') {
+        Some((platform, _)) => format!("{}:{}", platform, target),
+        None => target,
+    }
+}
+
+// Send minawan off to boop whoever their chatter named. Unknown targets, minawan already
+// on their way and users still cooling down are skipped without a word.
+pub(crate) fn start_boops(
+    mut commands: Commands,
+    mut party_events: EventReader<ApiEvent>,
+    mut cooldowns: ResMut<BoopCooldowns>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    booping_query: Query<(), With<Booping>>,
+) {
+    if !config.boop_enabled {
+        party_events.clear();
+        return;
+    }
+    let now = Instant::now();
+    for event in party_events.read() {
+        let ApiEvent::MessageDisplayed { user, message } = event else {
+            continue;
+        };
+        let Some(target) = boop_target(message, &config) else {
+            continue;
+        };
+        let target = target_key(user, target);
+        if target == *user {
+            continue;
+        }
+        let (Some(booper), Some(booped)) = (app_state.active_users.get(user), app_state.active_users.get(&target))
+        else {
+            continue;
+        };
+        if booping_query.contains(booper.entity) {
+            continue;
+        }
+        cooldowns
+            .last_boop
+            .retain(|_, time| now.duration_since(*time) < config.boop_cooldown);
+        if cooldowns.last_boop.contains_key(user) {
+            debug!("{} has to wait before booping again", user);
+            continue;
+        }
+        cooldowns.last_boop.insert(user.clone(), now);
+        debug!("{} is on their way to boop {}", user, target);
+        commands.entity(booper.entity).insert(Booping {
+            target: booped.entity,
+            started: now,
+        });
+    }
+}
+
+// Walk booping minawan over to their target. On reaching it the target is knocked back with a
+// star and both go back to wandering. Gives up when the target leaves or takes too long to reach.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn walk_to_boop(
+    mut commands: Commands,
+    booper_query: Query<(Entity, &Booping)>,
+    mut avatar_query: Query<
+        (&mut Transform, &mut Sprite, &mut UserActionDetails, Option<&SizeClass>, Has<Wave>),
+        With<UserMarker>,
+    >,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    time: Res<Time>,
+    config: Res<Config>,
+    hype_train: Res<HypeTrain>,
+) {
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+    let ranges = config.walk_ranges(rect.max.x);
+    let speed = config.avatar_move_speed * hype_train.speed_multiplier(&config) * BOOP_SPEED_FACTOR;
+    let now = Instant::now();
+
+    for (entity, booping) in booper_query.iter() {
+        let target = avatar_query
+            .get(booping.target)
+            .ok()
+            .map(|(transform, sprite, _, size_class, waving)| {
+                let size = avatar_size(sprite, size_class);
+                (transform.translation, size, head_y(sprite, size), waving)
+            });
+        let Ok((mut transform, mut sprite, mut action, size_class, _)) = avatar_query.get_mut(entity) else {
+            continue;
+        };
+        let Some((target_translation, target_size, target_head, target_waving)) =
+            target.filter(|_| now.duration_since(booping.started) < config.boop_timeout)
+        else {
+            stop_booping(&mut commands, entity, &mut action, now);
+            continue;
+        };
+
+        let distance = target_translation.x - transform.translation.x;
+        let reach = (avatar_size(&sprite, size_class).x + target_size.x) * 0.3;
+        if distance.abs() > reach {
+            let step = (speed * time.delta_seconds()).min(distance.abs() - reach);
+            let moved = clamp_to_ranges(transform.translation.x + step * distance.signum(), &ranges);
+            // A wall between the two, it's never getting there
+            if step > 0.0 && moved == transform.translation.x {
+                stop_booping(&mut commands, entity, &mut action, now);
+                continue;
+            }
+            transform.translation.x = moved;
+            sprite.flip_x = distance < 0.0;
+            continue;
+        }
+
+        // Boop
+        commands.entity(booping.target).insert(Knockback {
+            started: now,
+            direction: distance.signum(),
+        });
+        if !target_waving {
+            commands.entity(booping.target).insert(Wave {
+                started: now,
+                base_y: target_translation.y,
+            });
+        }
+        let contact = Vec3::new(
+            (transform.translation.x + target_translation.x) / 2.0,
+            target_translation.y + target_head,
+            4.0,
+        );
+        spawn_star(&mut commands, contact);
+        stop_booping(&mut commands, entity, &mut action, now);
+    }
+}
+
+/// Back to wandering, starting with a short stop
+fn stop_booping(commands: &mut Commands, entity: Entity, action: &mut UserActionDetails, now: Instant) {
+    commands.entity(entity).remove::<Booping>();
+    action.last_action = UserAction::Stop;
+    action.time = now;
+}
+
+/// Two squares turned against each other make an eight pointed star
+fn spawn_star(commands: &mut Commands, translation: Vec3) {
+    commands
+        .spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: PARTICLE_COLOR,
+                    custom_size: Some(Vec2::splat(PARTICLE_SIZE)),
+                    ..default()
+                },
+                transform: Transform::from_translation(translation),
+                ..default()
+            },
+            BoopParticle {
+                started: Instant::now(),
+                base_y: translation.y,
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn(SpriteBundle {
+                sprite: Sprite {
+                    color: PARTICLE_COLOR,
+                    custom_size: Some(Vec2::splat(PARTICLE_SIZE)),
+                    ..default()
+                },
+                transform: Transform::from_rotation(Quat::from_rotation_z(std::f32::consts::FRAC_PI_4)),
+                ..default()
+            });
+        });
+}
+
+// Slide booped minawan away, and float the stars up while they fade
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn animate_boops(
+    mut commands: Commands,
+    time: Res<Time>,
+    config: Res<Config>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    mut knockback_query: Query<(Entity, &mut Transform, &Knockback), Without<BoopParticle>>,
+    mut particle_query: Query<(Entity, &mut Transform, Option<&mut BaseAlpha>, &BoopParticle)>,
+    mut child_alphas: Query<&mut BaseAlpha, Without<BoopParticle>>,
+    children_query: Query<&Children, With<BoopParticle>>,
+) {
+    let now = Instant::now();
+    if let Some(rect) = camera_query.single().logical_viewport_rect() {
+        let ranges = config.walk_ranges(rect.max.x);
+        let speed = KNOCKBACK_DISTANCE / KNOCKBACK_TIME.as_secs_f32();
+        for (entity, mut transform, knockback) in knockback_query.iter_mut() {
+            if now.duration_since(knockback.started) >= KNOCKBACK_TIME {
+                commands.entity(entity).remove::<Knockback>();
+                continue;
+            }
+            let x = transform.translation.x + knockback.direction * speed * time.delta_seconds();
+            transform.translation.x = clamp_to_ranges(x, &ranges);
+        }
+    }
+
+    for (entity, mut transform, alpha, particle) in particle_query.iter_mut() {
+        let progress = now.duration_since(particle.started).as_secs_f32() / PARTICLE_TIME.as_secs_f32();
+        if progress >= 1.0 {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        transform.translation.y = particle.base_y + PARTICLE_RISE * progress;
+        transform.rotate_z(time.delta_seconds() * 2.0);
+        fade(alpha, 1.0 - progress);
+        for child in children_query.get(entity).into_iter().flatten() {
+            fade(child_alphas.get_mut(*child).ok(), 1.0 - progress);
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use std::time::{Duration, Instant};
+
+use bevy::{
+    asset::{AssetServer, Assets, Handle},
+    audio::{Pitch, PitchBundle, PlaybackSettings, Volume},
+    color::{Alpha, Color},
+    math::{Vec2, Vec3},
+    prelude::{Commands, Component, Entity, Image, Local, Query, Res, ResMut, Transform, Without},
+    sprite::{BorderRect, ImageScaleMode, SliceScaleMode, Sprite, TextureSlicer},
+    text::{Text, TextSection},
+};
+
+use crate::{
+    accessibility::EffectPolicy, config::Config, frame_budget::Cosmetic, handles::HandleCache, pool::EntityPools,
+};
+
+/// How long a bubble takes to pop in or out, or fold shut
+const ANIMATION_DURATION: Duration = Duration::from_millis(180);
+/// How small a popping bubble starts
+const POP_START_SCALE: f32 = 0.6;
+/// Least time between typing blips, fast chat would otherwise turn them into a drone
+const BLIP_INTERVAL: Duration = Duration::from_millis(60);
+const BLIP_LENGTH: Duration = Duration::from_millis(25);
+const BLIP_VOLUME: f32 = 0.15;
+
+/// Where a bubble's colour comes from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum BubbleColors {
+    /// BUBBLE_COLOR tints the image, which is drawn in white
+    Tinted,
+    /// The image keeps its own colours, drawn at this alpha
+    Own { alpha: f32 },
+}
+
+/// How a bubble comes in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Appear {
+    Instant,
+    /// Grows out of its middle
+    Pop,
+    /// The text is typed out a character at a time, with a blip at this pitch
+    Typewriter { chars_per_sec: f32, blip_hz: f32 },
+}
+
+/// How a bubble goes away
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Vanish {
+    Instant,
+    /// Shrinks into its middle
+    Pop,
+    /// Closes from top and bottom like a game's text window
+    Fold,
+}
+
+/// The look of speech bubbles, picked with BUBBLE_THEME. A new theme is its images in the
+/// assets folder and an entry in BUBBLE_THEMES.
+#[derive(Debug, PartialEq)]
+pub(crate) struct BubbleTheme {
+    pub(crate) name: &'static str,
+    /// Nine-slice image in the assets folder, a plain box without one
+    image: Option<&'static str>,
+    /// Pixels along the image's edges that keep their size as the bubble stretches
+    slice_border: f32,
+    colors: BubbleColors,
+    /// Font in the assets folder, FONT_URL without one. It has to be monospace like FONT_URL.
+    font: Option<&'static str>,
+    /// Space between the bubble's edges and its text
+    pub(crate) padding: f32,
+    /// TEXT_COLOR without one
+    text_color: Option<Color>,
+    /// Whether the bubble has a tail when BUBBLE_TAIL is on
+    pub(crate) tail: bool,
+    pub(crate) appear: Appear,
+    pub(crate) vanish: Vanish,
+}
+
+/// The first one is the default, as the overlay always looked
+pub(crate) const BUBBLE_THEMES: &[BubbleTheme] = &[
+    BubbleTheme {
+        name: "plain",
+        image: None,
+        slice_border: 0.0,
+        colors: BubbleColors::Tinted,
+        font: None,
+        padding: 0.0,
+        text_color: None,
+        tail: true,
+        appear: Appear::Instant,
+        vanish: Vanish::Instant,
+    },
+    BubbleTheme {
+        name: "rounded",
+        image: Some("bubble_rounded.png"),
+        slice_border: 12.0,
+        colors: BubbleColors::Tinted,
+        font: None,
+        padding: 8.0,
+        text_color: None,
+        tail: true,
+        appear: Appear::Pop,
+        vanish: Vanish::Pop,
+    },
+    BubbleTheme {
+        name: "pixel",
+        image: Some("bubble_pixel.png"),
+        slice_border: 4.0,
+        colors: BubbleColors::Tinted,
+        font: None,
+        padding: 6.0,
+        text_color: None,
+        tail: true,
+        appear: Appear::Instant,
+        vanish: Vanish::Instant,
+    },
+    BubbleTheme {
+        name: "rpg",
+        image: Some("bubble_rpg.png"),
+        slice_border: 4.0,
+        colors: BubbleColors::Own { alpha: 0.92 },
+        font: None,
+        padding: 10.0,
+        text_color: Some(Color::WHITE),
+        tail: false,
+        appear: Appear::Typewriter {
+            chars_per_sec: 40.0,
+            blip_hz: 660.0,
+        },
+        vanish: Vanish::Fold,
+    },
+];
+
+/// Parse a BUBBLE_THEME value
+pub(crate) fn bubble_theme_named(name: &str) -> Result<&'static BubbleTheme, String> {
+    BUBBLE_THEMES
+        .iter()
+        .find(|theme| theme.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("Unknown bubble theme: {}", name))
+}
+
+impl BubbleTheme {
+    /// Colour of the bubble sprite, before OPACITY
+    pub(crate) fn color(&self, config: &Config) -> Color {
+        match self.colors {
+            BubbleColors::Tinted => config.bubble_color,
+            BubbleColors::Own { alpha } => Color::WHITE.with_alpha(alpha),
+        }
+    }
+
+    pub(crate) fn text_color(&self, config: &Config) -> Color {
+        self.text_color.unwrap_or(config.text_color)
+    }
+
+    pub(crate) fn font_url<'a>(&self, config: &'a Config) -> &'a str {
+        self.font.unwrap_or(&config.font_url)
+    }
+
+    /// The bubble's image and how it stretches, None for a plain box
+    pub(crate) fn image(
+        &self,
+        handles: &mut HandleCache,
+        asset_server: &AssetServer,
+    ) -> Option<(Handle<Image>, ImageScaleMode)> {
+        let image = self.image?;
+        let slicer = TextureSlicer {
+            border: BorderRect::square(self.slice_border),
+            center_scale_mode: SliceScaleMode::Stretch,
+            sides_scale_mode: SliceScaleMode::Stretch,
+            max_corner_scale: 1.0,
+        };
+        Some((handles.load(asset_server, image), ImageScaleMode::Sliced(slicer)))
+    }
+
+    /// Where a bubble's text and emotes go, inside its padding
+    pub(crate) fn content_offset(&self) -> Vec3 {
+        Vec3::new(self.padding, -self.padding, 0.0)
+    }
+}
+
+/// A bubble popping in
+#[derive(Component)]
+pub(crate) struct Appearing {
+    pub(crate) started: Instant,
+}
+
+/// How a bubble goes away, from the theme it was shown with
+#[derive(Component)]
+pub(crate) struct BubbleVanish(pub(crate) Vanish);
+
+/// A bubble on its way out, released to the pool once it's gone
+#[derive(Component)]
+pub(crate) struct Vanishing {
+    started: Instant,
+}
+
+/// How far a bubble was moved to scale around its middle, so other systems can keep moving it
+#[derive(Component, Default)]
+pub(crate) struct ScaleOffset(Vec2);
+
+/// Message text being typed out, the sections are filled in from `lines`
+#[derive(Component)]
+pub(crate) struct Typewriter {
+    started: Instant,
+    lines: Vec<String>,
+    chars_per_sec: f32,
+    blip_hz: f32,
+    /// Characters typed so far
+    shown: usize,
+}
+
+impl Typewriter {
+    /// Empty the sections, they're typed back in line by line
+    pub(crate) fn new(sections: &mut [TextSection], chars_per_sec: f32, blip_hz: f32) -> Self {
+        Self {
+            started: Instant::now(),
+            lines: sections.iter_mut().map(|section| std::mem::take(&mut section.value)).collect(),
+            chars_per_sec,
+            blip_hz,
+            shown: 0,
+        }
+    }
+}
+
+/// Start a bubble's way out. Returns false when it should be released right away.
+pub(crate) fn start_vanishing(commands: &mut Commands, bubble: Entity, vanish: Vanish, policy: &EffectPolicy) -> bool {
+    if vanish == Vanish::Instant || !policy.motion() {
+        return false;
+    }
+    commands.entity(bubble).remove::<Appearing>().insert(Vanishing {
+        started: Instant::now(),
+    });
+    true
+}
+
+/// Scale a bubble, whose origin is its top left corner, around its middle
+fn scale_around_middle(transform: &mut Transform, offset: &mut ScaleOffset, sprite: &Sprite, scale: Vec2) {
+    let size = sprite.custom_size.unwrap_or_default();
+    let moved = Vec2::new(size.x * (1.0 - scale.x), -size.y * (1.0 - scale.y)) / 2.0;
+    transform.translation += (moved - offset.0).extend(0.0);
+    transform.scale = scale.extend(1.0);
+    offset.0 = moved;
+}
+
+// Pop bubbles in and out, and fold them shut, as their theme says. A bubble that's gone is
+// released to the pool. With REDUCED_MOTION they're shown right away.
+pub(crate) fn animate_bubbles(
+    mut commands: Commands,
+    mut pools: ResMut<EntityPools>,
+    config: Res<Config>,
+    policy: Res<EffectPolicy>,
+    mut appearing_query: Query<(Entity, &Appearing, &Sprite, &mut Transform, &mut ScaleOffset)>,
+    mut vanishing_query: Query<
+        (Entity, &Vanishing, &BubbleVanish, &Sprite, &mut Transform, &mut ScaleOffset),
+        Without<Appearing>,
+    >,
+) {
+    for (entity, appearing, sprite, mut transform, mut offset) in appearing_query.iter_mut() {
+        let progress = appearing.started.elapsed().as_secs_f32() / ANIMATION_DURATION.as_secs_f32();
+        if progress >= 1.0 || !policy.motion() {
+            scale_around_middle(&mut transform, &mut offset, sprite, Vec2::ONE);
+            commands.entity(entity).remove::<Appearing>();
+            continue;
+        }
+        let scale = POP_START_SCALE + (1.0 - POP_START_SCALE) * progress;
+        scale_around_middle(&mut transform, &mut offset, sprite, Vec2::splat(scale));
+    }
+
+    for (entity, vanishing, vanish, sprite, mut transform, mut offset) in vanishing_query.iter_mut() {
+        let progress = vanishing.started.elapsed().as_secs_f32() / ANIMATION_DURATION.as_secs_f32();
+        if progress >= 1.0 || !policy.motion() {
+            pools.release_message(&mut commands, entity, true, &config);
+            continue;
+        }
+        let left = 1.0 - progress;
+        let scale = match vanish.0 {
+            Vanish::Fold => Vec2::new(1.0, left),
+            Vanish::Pop | Vanish::Instant => Vec2::splat(left),
+        };
+        scale_around_middle(&mut transform, &mut offset, sprite, scale);
+    }
+}
+
+// Type out message text a character at a time, blipping as it goes when BUBBLE_SOUNDS is on.
+// The rest of the text is shown at once while the frame budget has shed text effects.
+pub(crate) fn type_bubble_text(
+    mut commands: Commands,
+    config: Res<Config>,
+    policy: Res<EffectPolicy>,
+    pitches: Option<ResMut<Assets<Pitch>>>,
+    mut blip: Local<Option<(f32, Handle<Pitch>)>>,
+    mut last_blip: Local<Option<Instant>>,
+    mut text_query: Query<(Entity, &mut Text, &mut Typewriter)>,
+) {
+    let mut blip_hz = None;
+    let typing = policy.allows(Cosmetic::TextEffects);
+    for (entity, mut text, mut typewriter) in text_query.iter_mut() {
+        let total: usize = typewriter.lines.iter().map(|line| line.chars().count()).sum();
+        let due = if typing {
+            ((typewriter.started.elapsed().as_secs_f32() * typewriter.chars_per_sec) as usize).min(total)
+        } else {
+            total
+        };
+        if due == typewriter.shown {
+            continue;
+        }
+        let typed = typewriter
+            .lines
+            .iter()
+            .flat_map(|line| line.chars())
+            .skip(typewriter.shown)
+            .take(due - typewriter.shown)
+            .any(|c| !c.is_whitespace());
+        if typed && typing {
+            blip_hz = Some(typewriter.blip_hz);
+        }
+        let mut left = due;
+        for (section, line) in text.sections.iter_mut().zip(typewriter.lines.iter()) {
+            let count = line.chars().count().min(left);
+            section.value = line.chars().take(count).collect();
+            left -= count;
+        }
+        typewriter.shown = due;
+        if due == total {
+            commands.entity(entity).remove::<Typewriter>();
+        }
+    }
+
+    let (Some(hz), Some(mut pitches)) = (blip_hz, pitches) else {
+        return;
+    };
+    if !config.bubble_sounds || last_blip.is_some_and(|last| last.elapsed() < BLIP_INTERVAL) {
+        return;
+    }
+    let source = match blip.as_ref() {
+        Some((cached, handle)) if *cached == hz => handle.clone(),
+        _ => {
+            let handle = pitches.add(Pitch::new(hz, BLIP_LENGTH));
+            *blip = Some((hz, handle.clone()));
+            handle
+        }
+    };
+    commands.spawn(PitchBundle {
+        source,
+        settings: PlaybackSettings::DESPAWN.with_volume(Volume::new(BLIP_VOLUME)),
+    });
+    *last_blip = Some(Instant::now());
+}
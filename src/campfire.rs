@@ -0,0 +1,139 @@
+use std::time::Instant;
+
+use bevy::{
+    asset::AssetServer,
+    math::{Vec2, Vec3},
+    prelude::{default, Camera, Commands, Component, Entity, Query, Res, Transform, Visibility, With, Without},
+    sprite::{Sprite, SpriteBundle},
+};
+
+use crate::{
+    boop::Booping, config::Config, race::Racing, users::range_for, AdjustScale, AppState, OverlayCamera, UserAction,
+    UserActionDetails, UserMarker,
+};
+
+/// Just behind the minawan
+const CAMPFIRE_Z: f32 = -1.0;
+/// Size of the campfire assumed while its image is still loading
+const FALLBACK_CAMPFIRE_SIZE: Vec2 = Vec2::splat(46.0);
+/// How far from its spot a minawan can stand before walking back to it
+const LOITER_TOLERANCE: f32 = 6.0;
+
+/// The decoration quiet minawan gather around
+#[derive(Component)]
+pub(crate) struct Campfire {
+    /// The IMAGE it was spawned from, to notice when that changes
+    image: String,
+}
+
+// Put the campfire on the floor at X, following the window size and the config
+pub(crate) fn place_campfire(
+    mut commands: Commands,
+    config: Res<Config>,
+    asset_server: Res<AssetServer>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    mut campfire_query: Query<(Entity, &Campfire, &mut Transform)>,
+) {
+    let mut placed = false;
+    for (entity, campfire, _) in campfire_query.iter() {
+        if config.campfire_enabled && campfire.image == config.campfire_image && !placed {
+            placed = true;
+        } else {
+            commands.entity(entity).despawn();
+        }
+    }
+    if !config.campfire_enabled {
+        return;
+    }
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+    let translation = Vec3::new(
+        -rect.max.x / 2.0 + config.campfire_x.resolve(rect.max.x),
+        config.floor_y(rect.max.y),
+        CAMPFIRE_Z,
+    );
+    if !placed {
+        commands.spawn((
+            SpriteBundle {
+                texture: asset_server.load(&config.campfire_image),
+                transform: Transform::from_translation(translation),
+                // Shown once it's been scaled to the height of a minawan
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            AdjustScale {},
+            Campfire {
+                image: config.campfire_image.clone(),
+            },
+        ));
+        return;
+    }
+    for (_, _, mut transform) in campfire_query.iter_mut() {
+        if transform.translation != translation {
+            transform.translation = translation;
+        }
+    }
+}
+
+// Walk the minawan of chatters quiet for GATHER_AFTER_SECS over to the campfire. Each gets a
+// spot of its own, alternating sides and further out the more there are, the ones quiet the
+// longest closest to the fire. Chatting again takes them off the list and they wander off.
+#[allow(clippy::type_complexity)]
+pub(crate) fn gather_at_campfire(
+    config: Res<Config>,
+    app_state: Res<AppState>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    campfire_query: Query<(&Transform, &Sprite), With<Campfire>>,
+    mut user_query: Query<
+        (&Transform, &mut Sprite, &mut UserActionDetails),
+        (With<UserMarker>, Without<Campfire>, Without<Booping>, Without<Racing>),
+    >,
+) {
+    let Ok((campfire, campfire_sprite)) = campfire_query.get_single() else {
+        return;
+    };
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+    let ranges = config.walk_ranges(rect.max.x);
+    let fire_x = campfire.translation.x;
+    let half_width = campfire_sprite.custom_size.unwrap_or(FALLBACK_CAMPFIRE_SIZE).x / 2.0;
+    let now = Instant::now();
+
+    let mut quiet: Vec<_> = app_state
+        .active_users
+        .values()
+        .filter(|user| now.duration_since(user.last_message_time) >= config.campfire_gather_after)
+        .collect();
+    quiet.sort_by_key(|user| user.last_message_time);
+    for (index, user) in quiet.iter().enumerate() {
+        let Ok((transform, mut sprite, mut action)) = user_query.get_mut(user.entity) else {
+            continue;
+        };
+        let side = if index % 2 == 0 { -1.0 } else { 1.0 };
+        let distance = half_width + config.campfire_spacing * ((index / 2) as f32 + 0.5);
+        let x = transform.translation.x;
+        let (start, end) = range_for(x, &ranges);
+        let spot = (fire_x + side * distance).clamp(start, end);
+        let offset = x - spot;
+        if offset.abs() <= LOITER_TOLERANCE {
+            // Hang around facing the fire
+            action.last_action = UserAction::Stop;
+            action.time = now;
+            let facing_left = fire_x < x;
+            if sprite.flip_x != facing_left {
+                sprite.flip_x = facing_left;
+            }
+            continue;
+        }
+        let towards = if offset > 0.0 {
+            UserAction::MoveLeft
+        } else {
+            UserAction::MoveRight
+        };
+        // Keep walking that way rather than letting move_users pick again
+        action.last_action = towards;
+        action.time = now;
+    }
+}
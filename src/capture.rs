@@ -0,0 +1,68 @@
+use bevy::{
+    prelude::{Local, NonSend, Query, Ref, Res, With},
+    window::{PrimaryWindow, RawHandleWrapper},
+    winit::WinitWindows,
+};
+use log::{info, warn};
+
+use crate::config::{CaptureVisibility, Config};
+
+/// Set the window's display affinity. This only decides what screen capture sees, the window's
+/// transparency and click-through styles are left alone.
+#[cfg(windows)]
+fn set_capture_visibility(handle: &RawHandleWrapper, visibility: CaptureVisibility) -> Result<(), String> {
+    use raw_window_handle::RawWindowHandle;
+    use windows::Win32::{
+        Foundation::HWND,
+        UI::WindowsAndMessaging::{SetWindowDisplayAffinity, WDA_EXCLUDEFROMCAPTURE, WDA_NONE},
+    };
+
+    let RawWindowHandle::Win32(handle) = handle.window_handle else {
+        return Err("the window isn't a Win32 window".to_string());
+    };
+    let affinity = match visibility {
+        CaptureVisibility::Include => WDA_NONE,
+        // Needs Windows 10 2004, older versions return an error
+        CaptureVisibility::Exclude => WDA_EXCLUDEFROMCAPTURE,
+    };
+    unsafe { SetWindowDisplayAffinity(HWND(handle.hwnd.get() as _), affinity) }
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(not(windows))]
+fn set_capture_visibility(_handle: &RawHandleWrapper, visibility: CaptureVisibility) -> Result<(), String> {
+    match visibility {
+        CaptureVisibility::Include => Ok(()),
+        CaptureVisibility::Exclude => Err("this is only supported on Windows".to_string()),
+    }
+}
+
+// Include or exclude the overlay from screen capture when CAPTURE_VISIBILITY changes, and again
+// whenever the window is recreated since the setting belongs to the OS window.
+// Takes WinitWindows to run on the main thread, which owns the window.
+pub(crate) fn apply_capture_visibility(
+    config: Res<Config>,
+    _winit_windows: NonSend<WinitWindows>,
+    windows: Query<Ref<RawHandleWrapper>, With<PrimaryWindow>>,
+    mut applied: Local<Option<CaptureVisibility>>,
+) {
+    let Ok(handle) = windows.get_single() else {
+        return;
+    };
+    let visibility = config.capture_visibility;
+    if *applied == Some(visibility) && !handle.is_changed() {
+        return;
+    }
+    let first_time = applied.is_none();
+    *applied = Some(visibility);
+
+    match set_capture_visibility(&handle, visibility) {
+        // Capture sees windows by default, no need to mention it on startup
+        Ok(()) if first_time && visibility == CaptureVisibility::Include => {}
+        Ok(()) => match visibility {
+            CaptureVisibility::Include => info!("Overlay is shown in screen capture"),
+            CaptureVisibility::Exclude => info!("Overlay is hidden from screen capture"),
+        },
+        Err(err) => warn!("Can't change whether screen capture sees the overlay: {}", err),
+    }
+}
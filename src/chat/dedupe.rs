@@ -0,0 +1,92 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::Resource;
+
+/// Most message ids remembered at once, keeps memory bounded during raids
+const MAX_RECENT_MESSAGES: usize = 1024;
+
+/// Ring buffer of recently displayed message ids used to drop duplicate deliveries
+#[derive(Resource, Default)]
+pub(crate) struct RecentMessages {
+    order: VecDeque<(String, Instant)>,
+    ids: HashSet<String>,
+    /// Duplicates dropped since startup
+    pub(crate) dropped: u64,
+}
+
+impl RecentMessages {
+    /// Record a message id, returns true if it was already seen within the window
+    pub(crate) fn is_duplicate(&mut self, id: &str, now: Instant, window: Duration) -> bool {
+        // Forget ids older than the window, or the oldest ones if the buffer is full
+        while let Some((oldest, time)) = self.order.front() {
+            if now.duration_since(*time) > window || self.order.len() >= MAX_RECENT_MESSAGES {
+                self.ids.remove(oldest);
+                self.order.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        if self.ids.contains(id) {
+            self.dropped += 1;
+            return true;
+        }
+        self.ids.insert(id.to_string());
+        self.order.push_back((id.to_string(), now));
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use twitch_irc::message::{IRCMessage, PrivmsgMessage};
+
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(60);
+    const PRIVMSG: &str = "@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;\
+        id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;\
+        turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam";
+
+    fn privmsg() -> PrivmsgMessage {
+        PrivmsgMessage::try_from(IRCMessage::parse(PRIVMSG).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn same_privmsg_twice_is_delivered_once() {
+        let mut recent = RecentMessages::default();
+        let now = Instant::now();
+        let delivered = [privmsg(), privmsg()]
+            .iter()
+            .filter(|msg| !recent.is_duplicate(&msg.message_id, now, WINDOW))
+            .count();
+        assert_eq!(delivered, 1);
+        assert_eq!(recent.dropped, 1);
+    }
+
+    #[test]
+    fn id_is_forgotten_after_the_window() {
+        let mut recent = RecentMessages::default();
+        let id = privmsg().message_id;
+        let now = Instant::now();
+        assert!(!recent.is_duplicate(&id, now, WINDOW));
+        assert!(recent.is_duplicate(&id, now + WINDOW, WINDOW));
+        assert!(!recent.is_duplicate(&id, now + WINDOW * 3, WINDOW));
+    }
+
+    #[test]
+    fn buffer_stays_bounded() {
+        let mut recent = RecentMessages::default();
+        let now = Instant::now();
+        for index in 0..MAX_RECENT_MESSAGES * 2 {
+            recent.is_duplicate(&index.to_string(), now, WINDOW);
+        }
+        assert_eq!(recent.order.len(), MAX_RECENT_MESSAGES);
+        assert_eq!(recent.ids.len(), MAX_RECENT_MESSAGES);
+        // The oldest ids made room for the newest
+        assert!(!recent.is_duplicate("0", now, WINDOW));
+    }
+}
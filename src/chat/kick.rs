@@ -0,0 +1,323 @@
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use futures_util::{SinkExt, StreamExt};
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::{
+    sync::{mpsc, watch},
+    time::sleep,
+};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+use crate::{
+    chat::{update_new_emote_meta, ChatSource},
+    emotes::emote_types::Emote,
+    ChatMessage, Platform, SourceStatus,
+};
+
+const KICK_API_URL: &str = "https://kick.com/api/v2/channels/";
+const KICK_PUSHER_URL: &str =
+    "wss://ws-us2.pusher.com/app/32cbd69e4b950bf97679?protocol=7&client=js&version=8.4.0-rc2&flash=false";
+const CHAT_MESSAGE_EVENT: &str = "App\\Events\\ChatMessageEvent";
+const FIRST_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+type KickError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Chat source reading a Kick chatroom through Kick's Pusher websocket
+pub(crate) struct KickSource {
+    channel: String,
+    chatroom_id: Option<String>,
+}
+
+/// Tells the rest of the overlay when Kick chat goes down and comes back
+struct KickStatus {
+    status_tx: mpsc::Sender<SourceStatus>,
+    /// When the connection went down, while it's down
+    down_since: Option<Instant>,
+    attempts: u32,
+}
+
+impl KickStatus {
+    fn new(status_tx: mpsc::Sender<SourceStatus>) -> Self {
+        Self {
+            status_tx,
+            down_since: None,
+            attempts: 0,
+        }
+    }
+
+    /// Subscribed to the chatroom, on the first connection or after an outage
+    async fn joined(&mut self) {
+        self.attempts = 0;
+        let down_for = self.down_since.take().map(|since| since.elapsed());
+        if let Some(down_for) = down_for {
+            info!("Back in Kick chat after {} seconds", down_for.as_secs());
+        }
+        let _ = self.status_tx.send(SourceStatus::Connected { platform: Platform::Kick, down_for, missed: 0 }).await;
+    }
+
+    /// The connection is gone, or never came up
+    async fn lost(&mut self) {
+        if self.down_since.is_some() {
+            return;
+        }
+        self.down_since = Some(Instant::now());
+        let _ = self.status_tx.send(SourceStatus::Disconnected { platform: Platform::Kick }).await;
+    }
+
+    /// Count a reconnect attempt, returning how long to wait before making it
+    async fn reconnecting(&mut self) -> Duration {
+        self.attempts += 1;
+        let _ = self
+            .status_tx
+            .send(SourceStatus::Reconnecting {
+                platform: Platform::Kick,
+                attempt: self.attempts,
+            })
+            .await;
+        FIRST_BACKOFF.saturating_mul(1u32 << (self.attempts - 1).min(6)).min(MAX_BACKOFF)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct KickChannel {
+    chatroom: KickChatroom,
+}
+
+#[derive(Deserialize, Debug)]
+struct KickChatroom {
+    id: u64,
+}
+
+#[derive(Deserialize, Debug)]
+struct PusherMessage {
+    event: String,
+    #[serde(default)]
+    data: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct KickChatMessage {
+    id: String,
+    content: String,
+    sender: KickSender,
+}
+
+#[derive(Deserialize, Debug)]
+struct KickSender {
+    username: String,
+    #[serde(default)]
+    identity: Option<KickIdentity>,
+}
+
+#[derive(Deserialize, Debug)]
+struct KickIdentity {
+    #[serde(default)]
+    badges: Vec<KickBadge>,
+}
+
+#[derive(Deserialize, Debug)]
+struct KickBadge {
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+impl KickSource {
+    pub(crate) fn new(channel: String, chatroom_id: Option<String>) -> Self {
+        Self {
+            channel,
+            chatroom_id,
+        }
+    }
+
+    /// Look up the chatroom id from the channel slug using Kick's public API
+    async fn resolve_chatroom_id(&self) -> Result<String, reqwest::Error> {
+        let channel: KickChannel = reqwest::get(format!("{}{}", KICK_API_URL, self.channel))
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(channel.chatroom.id.to_string())
+    }
+
+    /// Read from one websocket connection until it drops or shutdown is requested.
+    /// Returns true when the source should stop.
+    async fn run_session(
+        &self,
+        chatroom_id: &str,
+        tx: &mpsc::Sender<ChatMessage>,
+        status: &mut KickStatus,
+        shutdown: &mut watch::Receiver<bool>,
+        seen_emotes: &mut HashSet<String>,
+    ) -> Result<bool, KickError> {
+        let (mut socket, _) = connect_async(KICK_PUSHER_URL).await?;
+        let subscribe = json!({
+            "event": "pusher:subscribe",
+            "data": { "auth": "", "channel": format!("chatrooms.{}.v2", chatroom_id) },
+        });
+        socket.send(Message::Text(subscribe.to_string())).await?;
+        info!("Connected to Kick chatroom {}", chatroom_id);
+        status.joined().await;
+
+        loop {
+            let frame = tokio::select! {
+                frame = socket.next() => match frame {
+                    Some(frame) => frame?,
+                    None => return Ok(false),
+                },
+                _ = shutdown.changed() => return Ok(true),
+            };
+            let text = match frame {
+                Message::Text(text) => text,
+                Message::Ping(data) => {
+                    socket.send(Message::Pong(data)).await?;
+                    continue;
+                }
+                Message::Close(_) => return Ok(false),
+                _ => continue,
+            };
+
+            // One odd frame isn't worth dropping the connection over
+            let message: PusherMessage = match serde_json::from_str(&text) {
+                Ok(message) => message,
+                Err(err) => {
+                    warn!("Skipping a Kick frame that couldn't be read: {}", err);
+                    continue;
+                }
+            };
+            match message.event.as_str() {
+                "pusher:ping" => {
+                    socket
+                        .send(Message::Text(json!({ "event": "pusher:pong", "data": {} }).to_string()))
+                        .await?;
+                }
+                CHAT_MESSAGE_EVENT => {
+                    // Pusher double encodes the event data
+                    let Some(data) = message.data else {
+                        continue;
+                    };
+                    let raw = match serde_json::from_str(&data) {
+                        Ok(raw) => raw,
+                        Err(err) => {
+                            warn!("Skipping a Kick chat message that couldn't be read: {}", err);
+                            continue;
+                        }
+                    };
+                    let mut chat_message = to_chat_message(raw);
+                    info!("[Kick] {}: {}", chat_message.user, chat_message.message);
+                    update_new_emote_meta(&mut chat_message.emotes, seen_emotes).await;
+                    if tx.send(chat_message).await.is_err() {
+                        return Ok(true);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl ChatSource for KickSource {
+    fn name(&self) -> String {
+        format!("Kick ({})", self.channel)
+    }
+
+    async fn start(
+        self: Box<Self>,
+        tx: mpsc::Sender<ChatMessage>,
+        status_tx: mpsc::Sender<SourceStatus>,
+        mut shutdown: watch::Receiver<bool>,
+    ) {
+        let mut chatroom_id = self.chatroom_id.clone();
+        let mut status = KickStatus::new(status_tx);
+        let mut seen_emotes: HashSet<String> = HashSet::new();
+
+        loop {
+            // Looked up inside the loop so a network blip at startup is retried like a dropped socket
+            let session = match &chatroom_id {
+                Some(chatroom_id) => {
+                    self.run_session(chatroom_id, &tx, &mut status, &mut shutdown, &mut seen_emotes).await
+                }
+                None => match self.resolve_chatroom_id().await {
+                    Ok(resolved) => {
+                        chatroom_id = Some(resolved);
+                        continue;
+                    }
+                    Err(err) => Err(format!("couldn't resolve the chatroom for {}: {}", self.channel, err).into()),
+                },
+            };
+            match session {
+                Ok(true) => return,
+                Ok(false) => warn!("Kick connection closed"),
+                Err(err) => warn!("Kick connection failed: {}", err),
+            }
+            status.lost().await;
+
+            let backoff = status.reconnecting().await;
+            info!("Reconnecting to Kick in {:?}", backoff);
+            tokio::select! {
+                _ = sleep(backoff) => {}
+                _ = shutdown.changed() => return,
+            }
+        }
+    }
+}
+
+fn to_chat_message(raw: KickChatMessage) -> ChatMessage {
+    let (message, emotes) = parse_kick_emotes(&raw.content);
+    let badges = raw
+        .sender
+        .identity
+        .map(|identity| identity.badges.into_iter().map(|badge| badge.kind).collect())
+        .unwrap_or_default();
+
+    ChatMessage {
+        id: Some(raw.id),
+        platform: Platform::Kick,
+        user: raw.sender.username,
+        display_name: None,
+        message,
+        emotes,
+        origin_room_id: None,
+        badges,
+        badge_versions: vec![],
+        sub_months: None,
+        whisper: false,
+        bits: 0,
+    }
+}
+
+/// Replace Kick's `[emote:id:name]` tokens with the emote name and collect the emotes used
+fn parse_kick_emotes(content: &str) -> (String, Vec<Emote>) {
+    let mut message = String::new();
+    let mut emotes: Vec<Emote> = vec![];
+    let mut rest = content;
+
+    while let Some(start) = rest.find("[emote:") {
+        let Some(end) = rest[start..].find(']').map(|end| start + end) else {
+            break;
+        };
+        let mut parts = rest[start + "[emote:".len()..end].splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some(id), Some(name)) if !id.is_empty() && !name.is_empty() => {
+                // Pad with spaces so the emote is tokenised as its own word
+                message.push_str(&rest[..start]);
+                message.push_str(&format!(" {} ", name));
+                if !emotes.iter().any(|emote| emote.name == name) {
+                    emotes.push(Emote::from_kick(id, name));
+                }
+            }
+            _ => message.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    message.push_str(rest);
+
+    (message.split_whitespace().collect::<Vec<&str>>().join(" "), emotes)
+}
@@ -0,0 +1,136 @@
+pub mod dedupe;
+pub mod twitch;
+pub mod youtube;
+pub mod kick;
+pub mod simulate;
+
+use std::{collections::HashSet, sync::Arc};
+
+use async_trait::async_trait;
+use bevy::{
+    app::AppExit,
+    prelude::{EventReader, Res},
+};
+use log::{info, warn};
+use tokio::sync::{mpsc, watch, Notify};
+
+use crate::{
+    chat::{
+        kick::KickSource, simulate::SimulatedSource, twitch::TwitchSource, youtube::YouTubeSource,
+    },
+    config::Config,
+    emotes::{emote_types::Emote, update_emote_meta},
+    gifts::GiftNotice,
+    lurkers::Presence,
+    moderation::ModerationNotice,
+    startup::StartupReporter,
+    ChatMessage, ChatShutdown, SourceStatus,
+};
+
+/// A platform that chat messages can be read from
+#[async_trait]
+pub(crate) trait ChatSource: Send {
+    /// Human readable name used in logs
+    fn name(&self) -> String;
+
+    /// Read messages until the source ends or shutdown is signalled, sending each one to Bevy.
+    /// Connection and login changes are reported through `status_tx`.
+    async fn start(
+        self: Box<Self>,
+        tx: mpsc::Sender<ChatMessage>,
+        status_tx: mpsc::Sender<SourceStatus>,
+        shutdown: watch::Receiver<bool>,
+    );
+}
+
+/// Build the chat sources enabled in the config. Twitch also reports who joins and leaves
+/// chat to `presence_tx` when it's given, gifted subs to `gift_tx`, and timeouts and bans to
+/// `moderation_tx`, and when it has joined the channel to `startup`. It connects again straight
+/// away when `reconnect` is notified, and says what comes in on `outbound_rx` in chat.
+pub(crate) fn sources_from_config(
+    config: &Config,
+    presence_tx: Option<mpsc::Sender<Presence>>,
+    gift_tx: mpsc::Sender<GiftNotice>,
+    moderation_tx: mpsc::Sender<ModerationNotice>,
+    startup: Option<StartupReporter>,
+    reconnect: Arc<Notify>,
+    outbound_rx: mpsc::Receiver<String>,
+) -> Vec<Box<dyn ChatSource>> {
+    let mut sources: Vec<Box<dyn ChatSource>> = vec![];
+    // Only one Twitch source can speak for the overlay
+    let mut outbound_rx = Some(outbound_rx);
+    for name in config.chat_sources.iter() {
+        match name.as_str() {
+            "twitch" if config.channel_name.is_empty() => {
+                warn!("Twitch chat source needs a CHANNEL_NAME, skipping")
+            }
+            "twitch" => sources.push(Box::new(TwitchSource::new(
+                config.channel_name.clone(),
+                config.twitch_username.clone().zip(config.twitch_token.clone()),
+                presence_tx.clone(),
+                gift_tx.clone(),
+                moderation_tx.clone(),
+                startup.clone(),
+                reconnect.clone(),
+                outbound_rx.take(),
+            ))),
+            "youtube" => match &config.youtube_api_key {
+                Some(api_key) => sources.push(Box::new(YouTubeSource::new(
+                    api_key.clone(),
+                    config.youtube_live_chat_id.clone(),
+                    config.youtube_video_id.clone(),
+                ))),
+                None => warn!("YouTube chat source needs an API_KEY, skipping"),
+            },
+            "kick" => match &config.kick_channel {
+                Some(channel) => sources.push(Box::new(KickSource::new(
+                    channel.clone(),
+                    config.kick_chatroom_id.clone(),
+                ))),
+                None => warn!("Kick chat source needs a CHANNEL, skipping"),
+            },
+            "simulate" => sources.push(Box::new(SimulatedSource {})),
+            _ => warn!("Unknown chat source: {}", name),
+        }
+    }
+    sources
+}
+
+/// Fetch size and format for emotes that haven't been seen before
+pub(crate) async fn update_new_emote_meta(emotes: &mut [Emote], seen_emotes: &mut HashSet<String>) {
+    let mut new_emotes: HashSet<String> = HashSet::new();
+
+    for emote in emotes
+        .iter_mut()
+        .filter(|emote| !seen_emotes.contains(&emote.name))
+    {
+        update_emote_meta(emote).await;
+        new_emotes.insert(emote.name.clone());
+    }
+    seen_emotes.extend(new_emotes);
+}
+
+/// Spawn every source on the tokio runtime, merging their messages into one channel
+pub(crate) fn spawn_sources(
+    sources: Vec<Box<dyn ChatSource>>,
+    tx: mpsc::Sender<ChatMessage>,
+    status_tx: mpsc::Sender<SourceStatus>,
+    shutdown: watch::Receiver<bool>,
+) {
+    for source in sources {
+        info!("Starting chat source: {}", source.name());
+        let tx = tx.clone();
+        let status_tx = status_tx.clone();
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            source.start(tx, status_tx, shutdown).await;
+        });
+    }
+}
+
+// Tell the chat sources to stop when the app exits
+pub(crate) fn shutdown_chat_sources(exit_events: EventReader<AppExit>, shutdown: Res<ChatShutdown>) {
+    if !exit_events.is_empty() {
+        let _ = shutdown.sender.send(true);
+    }
+}
@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use rand::{seq::SliceRandom, Rng};
+use tokio::{
+    sync::{mpsc, watch},
+    time::sleep,
+};
+
+use crate::{chat::ChatSource, ChatMessage, Platform, SourceStatus};
+
+const SIMULATED_USERS: [&str; 8] = [
+    "minawan_one",
+    "minawan_two",
+    "cerbfan",
+    "lurker123",
+    "wanwanwan",
+    "goodboy",
+    "sleepy_pup",
+    "treat_enjoyer",
+];
+
+const SIMULATED_MESSAGES: [&str; 8] = [
+    "hi chat",
+    "wan wan",
+    "this is such a long message that it should wrap onto a few lines in the bubble",
+    "lol",
+    "gn everyone",
+    "first time here, hello!",
+    "woof",
+    "minawan",
+];
+
+/// Chat source sending fake messages, used to test the overlay without a live channel
+pub(crate) struct SimulatedSource {}
+
+#[async_trait]
+impl ChatSource for SimulatedSource {
+    fn name(&self) -> String {
+        "Simulated chat".to_string()
+    }
+
+    async fn start(
+        self: Box<Self>,
+        tx: mpsc::Sender<ChatMessage>,
+        _status_tx: mpsc::Sender<SourceStatus>,
+        mut shutdown: watch::Receiver<bool>,
+    ) {
+        loop {
+            let (wait, chat_message) = {
+                let mut rng = rand::thread_rng();
+                let user = SIMULATED_USERS.choose(&mut rng).unwrap();
+                let message = SIMULATED_MESSAGES.choose(&mut rng).unwrap();
+                (
+                    Duration::from_millis(rng.gen_range(300..2000)),
+                    ChatMessage {
+                        message: message.to_string(),
+                        ..ChatMessage::synthetic(Platform::Twitch, user)
+                    },
+                )
+            };
+
+            if tx.send(chat_message).await.is_err() {
+                return;
+            }
+            tokio::select! {
+                _ = sleep(wait) => {}
+                _ = shutdown.changed() => return,
+            }
+        }
+    }
+}
@@ -0,0 +1,566 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use log::{error, info, warn};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tokio::{
+    sync::{mpsc, watch, Notify},
+    time::{sleep, sleep_until},
+};
+use twitch_irc::{
+    login::StaticLoginCredentials,
+    message::{ClearChatAction, ServerMessage, UserNoticeEvent},
+    ClientConfig, SecureTCPTransport, TwitchIRCClient,
+};
+
+use crate::{
+    chat::{update_new_emote_meta, ChatSource},
+    gifts::GiftNotice,
+    lurkers::Presence,
+    moderation::ModerationNotice,
+    startup::{StartupReporter, StartupStep, StepResult},
+    ChatMessage, Platform, SourceStatus,
+};
+
+const VALIDATE_URL: &str = "https://id.twitch.tv/oauth2/validate";
+/// The client pings Twitch every 30 seconds, a connection that hears nothing for longer is gone
+const STALE_AFTER: Duration = Duration::from_secs(40);
+/// Chat before an outage counted to guess how much was missed during it
+const RATE_WINDOW: Duration = Duration::from_secs(300);
+/// Wait added before each reconnect attempt after the first, up to MAX_RECONNECT_DELAY
+const RECONNECT_STEP: Duration = Duration::from_secs(5);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+/// Twitch drops messages past 20 in 30 seconds from an account that isn't a moderator
+const SEND_LIMIT: usize = 20;
+const SEND_WINDOW: Duration = Duration::from_secs(30);
+
+/// Chat source reading a Twitch channel over IRC
+pub(crate) struct TwitchSource {
+    channel: String,
+    /// Username and OAuth token, anonymous when not set
+    credentials: Option<(String, String)>,
+    /// Where JOIN and PART go, when lurkers are shown
+    presence_tx: Option<mpsc::Sender<Presence>>,
+    /// Where gifted subs go
+    gift_tx: mpsc::Sender<GiftNotice>,
+    /// Where timeouts and bans go
+    moderation_tx: mpsc::Sender<ModerationNotice>,
+    /// Told once the channel has been joined, for the loading panel
+    startup: Option<StartupReporter>,
+    /// Notified to drop the connection and make a new one right away
+    reconnect: Arc<Notify>,
+    /// What the overlay wants to say in chat, taken by the task when it starts
+    outbound_rx: Option<mpsc::Receiver<String>>,
+}
+
+/// Why the configured credentials could not be used
+enum AuthProblem {
+    WrongFormat,
+    Expired,
+    UsernameMismatch { token_login: String },
+    Rejected(String),
+}
+
+impl fmt::Display for AuthProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthProblem::WrongFormat => write!(
+                f,
+                "OAUTH_TOKEN is not a valid token, it should be the 30 letters and numbers after \"oauth:\""
+            ),
+            AuthProblem::Expired => write!(
+                f,
+                "OAUTH_TOKEN has expired or been revoked, generate a new one"
+            ),
+            AuthProblem::UsernameMismatch { token_login } => write!(
+                f,
+                "OAUTH_TOKEN belongs to {} but USERNAME is set to someone else",
+                token_login
+            ),
+            AuthProblem::Rejected(reason) => write!(f, "Twitch rejected the login: {}", reason),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ValidateResponse {
+    login: String,
+}
+
+/// How a connection to chat finished
+enum ClientEnd {
+    AuthFailed(AuthProblem),
+    /// Nothing came in for too long, or a reconnect was asked for
+    Lost,
+    Stopped,
+}
+
+/// Keeps Bevy told how the connection to chat is doing, and how busy chat was in case it drops
+struct ConnectionTracker {
+    status_tx: mpsc::Sender<SourceStatus>,
+    connected: bool,
+    /// When anything last came in from Twitch
+    last_seen: Instant,
+    /// When chat stopped coming in, while it's down
+    down_since: Option<Instant>,
+    attempts: u32,
+    /// When recent chat messages came in, within RATE_WINDOW
+    recent: VecDeque<Instant>,
+}
+
+impl ConnectionTracker {
+    fn new(status_tx: mpsc::Sender<SourceStatus>) -> Self {
+        Self {
+            status_tx,
+            connected: false,
+            last_seen: Instant::now(),
+            down_since: None,
+            attempts: 0,
+            recent: VecDeque::new(),
+        }
+    }
+
+    fn seen(&mut self) {
+        self.last_seen = Instant::now();
+    }
+
+    fn message(&mut self) {
+        let now = Instant::now();
+        self.recent.push_back(now);
+        while self.recent.front().is_some_and(|time| now.duration_since(*time) > RATE_WINDOW) {
+            self.recent.pop_front();
+        }
+    }
+
+    /// The channel has been joined, on the first connection or after an outage
+    async fn joined(&mut self) {
+        if self.connected {
+            return;
+        }
+        self.connected = true;
+        self.attempts = 0;
+        let down_since = self.down_since.take();
+        let down_for = down_since.map(|since| since.elapsed());
+        // As busy as chat was just before it went down
+        let missed = down_since.zip(down_for).map_or(0, |(since, down_for)| {
+            let before = self.recent.iter().filter(|time| since.duration_since(**time) <= RATE_WINDOW).count();
+            (before as f32 / RATE_WINDOW.as_secs_f32() * down_for.as_secs_f32()).round() as usize
+        });
+        if let Some(down_for) = down_for {
+            info!("Back in Twitch chat after {} seconds", down_for.as_secs());
+        }
+        let _ = self.status_tx.send(SourceStatus::Connected { platform: Platform::Twitch, down_for, missed }).await;
+    }
+
+    /// The connection is gone, it went down when the last thing came in
+    async fn lost(&mut self) {
+        self.connected = false;
+        if self.down_since.is_some() {
+            return;
+        }
+        info!("Lost the connection to Twitch chat");
+        self.down_since = Some(self.last_seen);
+        let _ = self.status_tx.send(SourceStatus::Disconnected { platform: Platform::Twitch }).await;
+    }
+
+    /// Count a reconnect attempt, returning how long to wait before making it
+    async fn reconnecting(&mut self) -> Duration {
+        self.attempts += 1;
+        info!("Reconnecting to Twitch chat, attempt {}", self.attempts);
+        let _ = self
+            .status_tx
+            .send(SourceStatus::Reconnecting {
+                platform: Platform::Twitch,
+                attempt: self.attempts,
+            })
+            .await;
+        (RECONNECT_STEP * (self.attempts - 1)).min(MAX_RECONNECT_DELAY)
+    }
+}
+
+/// Messages for chat, held back so the account stays inside Twitch's rate limit
+struct Outbox {
+    rx: Option<mpsc::Receiver<String>>,
+    waiting: VecDeque<String>,
+    /// When recent messages went out, within SEND_WINDOW
+    sent: VecDeque<Instant>,
+}
+
+impl Outbox {
+    fn new(rx: Option<mpsc::Receiver<String>>) -> Self {
+        Self {
+            rx,
+            waiting: VecDeque::new(),
+            sent: VecDeque::new(),
+        }
+    }
+
+    /// The next message Bevy asks for, None once it can't ask for any more
+    async fn recv(&mut self) -> Option<String> {
+        match &mut self.rx {
+            Some(rx) => rx.recv().await,
+            None => None,
+        }
+    }
+
+    /// When the next waiting message may go out
+    fn next_slot(&self) -> Instant {
+        match self.sent.front() {
+            Some(oldest) if self.sent.len() >= SEND_LIMIT => *oldest + SEND_WINDOW,
+            _ => Instant::now(),
+        }
+    }
+
+    /// Take the next waiting message, counting it as sent
+    fn take(&mut self) -> Option<String> {
+        let text = self.waiting.pop_front()?;
+        let now = Instant::now();
+        while self.sent.front().is_some_and(|time| now.duration_since(*time) >= SEND_WINDOW) {
+            self.sent.pop_front();
+        }
+        self.sent.push_back(now);
+        Some(text)
+    }
+}
+
+impl TwitchSource {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        channel: String,
+        credentials: Option<(String, String)>,
+        presence_tx: Option<mpsc::Sender<Presence>>,
+        gift_tx: mpsc::Sender<GiftNotice>,
+        moderation_tx: mpsc::Sender<ModerationNotice>,
+        startup: Option<StartupReporter>,
+        reconnect: Arc<Notify>,
+        outbound_rx: Option<mpsc::Receiver<String>>,
+    ) -> Self {
+        Self {
+            channel,
+            credentials,
+            presence_tx,
+            gift_tx,
+            moderation_tx,
+            startup,
+            reconnect,
+            outbound_rx,
+        }
+    }
+
+    /// Check the token before connecting so problems can be explained clearly
+    async fn validate_credentials(username: &str, token: &str) -> Result<(), AuthProblem> {
+        if token.is_empty() || !token.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(AuthProblem::WrongFormat);
+        }
+
+        let response = match reqwest::Client::new()
+            .get(VALIDATE_URL)
+            .header(reqwest::header::AUTHORIZATION, format!("OAuth {}", token))
+            .send()
+            .await
+        {
+            Ok(response) => response,
+            Err(err) => {
+                // Can't reach Twitch to check, let the IRC login decide
+                warn!("Could not validate OAUTH_TOKEN: {}", err);
+                return Ok(());
+            }
+        };
+        if response.status() == StatusCode::UNAUTHORIZED {
+            return Err(AuthProblem::Expired);
+        }
+
+        match response.json::<ValidateResponse>().await {
+            Ok(validated) if !validated.login.eq_ignore_ascii_case(username) => {
+                Err(AuthProblem::UsernameMismatch {
+                    token_login: validated.login,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Connect to chat and forward messages until shutdown, a failed login or the connection
+    /// going quiet. Messages in the outbox are said in chat while logged in.
+    async fn run_client(
+        &self,
+        credentials: Option<(String, String)>,
+        tx: &mpsc::Sender<ChatMessage>,
+        tracker: &mut ConnectionTracker,
+        outbox: &mut Outbox,
+        shutdown: &mut watch::Receiver<bool>,
+    ) -> ClientEnd {
+        let authenticated = credentials.is_some();
+        let credentials = match credentials {
+            Some((username, token)) => StaticLoginCredentials::new(username, Some(token)),
+            None => StaticLoginCredentials::anonymous(),
+        };
+        let config = ClientConfig::new_simple(credentials);
+
+        let (mut incoming_messages, client) =
+            TwitchIRCClient::<SecureTCPTransport, StaticLoginCredentials>::new(config);
+
+        if let Err(err) = client.join(self.channel.clone()) {
+            error!("Can't join Twitch channel {:?}: {}", self.channel, err);
+            if let Some(startup) = &self.startup {
+                startup.failed(StartupStep::TwitchChat, err.to_string());
+            }
+            return ClientEnd::Stopped;
+        }
+
+        tracker.seen();
+        sleep(Duration::from_millis(2000)).await;
+
+        let mut seen_emotes: HashSet<String> = HashSet::new();
+
+        // Listen to incoming Twitch messages and send them to Bevy via the channel
+        loop {
+            let stale_at = tokio::time::Instant::from_std(tracker.last_seen + STALE_AFTER);
+            let send_at = tokio::time::Instant::from_std(outbox.next_slot());
+            let message = tokio::select! {
+                message = incoming_messages.recv() => match message {
+                    Some(message) => message,
+                    None => return ClientEnd::Stopped,
+                },
+                _ = sleep_until(stale_at) => {
+                    tracker.lost().await;
+                    return ClientEnd::Lost;
+                }
+                _ = self.reconnect.notified() => {
+                    info!("Dropping the Twitch chat connection to make a new one");
+                    tracker.lost().await;
+                    return ClientEnd::Lost;
+                }
+                Some(text) = outbox.recv() => {
+                    if authenticated {
+                        outbox.waiting.push_back(text);
+                    } else {
+                        info!("Not logged in to Twitch chat, can't say {:?}", text);
+                    }
+                    continue;
+                }
+                _ = sleep_until(send_at), if tracker.connected && !outbox.waiting.is_empty() => {
+                    if let Some(text) = outbox.take() {
+                        info!("Saying in chat: {}", text);
+                        if let Err(err) = client.say(self.channel.clone(), text).await {
+                            warn!("Couldn't send a message to Twitch chat: {}", err);
+                        }
+                    }
+                    continue;
+                }
+                _ = shutdown.changed() => return ClientEnd::Stopped,
+            };
+            tracker.seen();
+
+            match message {
+                ServerMessage::Privmsg(msg) => {
+                    info!("{}: {}", msg.sender.name, msg.message_text);
+                    tracker.message();
+                    // Shared Chat tags messages with the room they were originally sent in
+                    let origin_room_id = msg
+                        .source
+                        .tags
+                        .0
+                        .get("source-room-id")
+                        .cloned()
+                        .flatten()
+                        .filter(|room_id| *room_id != msg.channel_id);
+                    let mut chat_message = ChatMessage {
+                        id: Some(msg.message_id.clone()),
+                        platform: Platform::Twitch,
+                        user: msg.sender.name.clone(),
+                        display_name: None,
+                        message: msg.message_text.clone(),
+                        emotes: msg.emotes.into_iter().map(|emote| emote.into()).collect(),
+                        origin_room_id,
+                        badges: msg.badges.iter().map(|badge| badge.name.clone()).collect(),
+                        badge_versions: msg
+                            .badges
+                            .iter()
+                            .map(|badge| format!("{}/{}", badge.name, badge.version))
+                            .collect(),
+                        sub_months: msg
+                            .badge_info
+                            .iter()
+                            .find(|info| info.name == "subscriber" || info.name == "founder")
+                            .and_then(|info| info.version.parse().ok()),
+                        whisper: false,
+                        bits: msg.bits.unwrap_or_default(),
+                    };
+
+                    update_new_emote_meta(&mut chat_message.emotes, &mut seen_emotes).await;
+                    // Bevy has gone away when nothing is receiving
+                    if tx.send(chat_message).await.is_err() {
+                        return ClientEnd::Stopped;
+                    }
+                }
+                // Whispers are only ever used as admin commands, so never need emote metadata
+                ServerMessage::Whisper(msg) => {
+                    let sent = tx.send(ChatMessage {
+                        id: None,
+                        platform: Platform::Twitch,
+                        user: msg.sender.login.clone(),
+                        display_name: None,
+                        message: msg.message_text,
+                        emotes: vec![],
+                        origin_room_id: None,
+                        badges: msg.badges.iter().map(|badge| badge.name.clone()).collect(),
+                        badge_versions: vec![],
+                        sub_months: None,
+                        whisper: true,
+                        bits: 0,
+                    })
+                    .await;
+                    if sent.is_err() {
+                        return ClientEnd::Stopped;
+                    }
+                }
+                // Who's in chat, whether or not they say anything
+                ServerMessage::Join(msg) => {
+                    if let Some(presence_tx) = &self.presence_tx {
+                        let _ = presence_tx.send(Presence::Joined(msg.user_login)).await;
+                    }
+                }
+                ServerMessage::Part(msg) => {
+                    if let Some(presence_tx) = &self.presence_tx {
+                        let _ = presence_tx.send(Presence::Left(msg.user_login)).await;
+                    }
+                }
+                // A gift bomb comes as one notice for the whole bomb and then one per recipient
+                ServerMessage::UserNotice(msg) => {
+                    let notice = match msg.event {
+                        UserNoticeEvent::SubMysteryGift { mass_gift_count, .. } => GiftNotice::Bomb {
+                            gifter: Some(msg.sender.login),
+                            count: mass_gift_count,
+                        },
+                        UserNoticeEvent::AnonSubMysteryGift { mass_gift_count, .. } => GiftNotice::Bomb {
+                            gifter: None,
+                            count: mass_gift_count,
+                        },
+                        UserNoticeEvent::SubGift {
+                            is_sender_anonymous,
+                            recipient,
+                            ..
+                        } => GiftNotice::Gift {
+                            gifter: (!is_sender_anonymous).then_some(msg.sender.login),
+                            recipient: recipient.login,
+                        },
+                        _ => continue,
+                    };
+                    let _ = self.gift_tx.send(notice).await;
+                }
+                // Clearing the whole chat doesn't clear the party
+                ServerMessage::ClearChat(msg) => {
+                    let notice = match msg.action {
+                        ClearChatAction::UserTimedOut {
+                            user_login,
+                            timeout_length,
+                            ..
+                        } => ModerationNotice::TimedOut {
+                            user: user_login,
+                            duration: timeout_length,
+                        },
+                        ClearChatAction::UserBanned { user_login, .. } => ModerationNotice::Banned { user: user_login },
+                        ClearChatAction::ChatCleared => continue,
+                    };
+                    let _ = self.moderation_tx.send(notice).await;
+                }
+                // Sent when the channel has been joined
+                ServerMessage::RoomState(_) => {
+                    if let Some(startup) = &self.startup {
+                        let result = if authenticated { StepResult::Ok } else { StepResult::ReadOnly };
+                        startup.done(StartupStep::TwitchChat, result);
+                    }
+                    tracker.joined().await;
+                }
+                // Sent once the login has been accepted
+                ServerMessage::GlobalUserState(_) if authenticated => {
+                    info!("Logged in to Twitch chat");
+                    let _ = tracker.status_tx.send(SourceStatus::Authenticated).await;
+                }
+                // Twitch is about to drop the connection, the client joins again on a new one
+                ServerMessage::Reconnect(_) => tracker.lost().await,
+                // Twitch reports bad logins as a notice before closing the connection
+                ServerMessage::Notice(notice)
+                    if authenticated
+                        && (notice.message_text.contains("Login authentication failed")
+                            || notice.message_text.contains("Improperly formatted auth")) =>
+                {
+                    return ClientEnd::AuthFailed(AuthProblem::Rejected(notice.message_text));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl ChatSource for TwitchSource {
+    fn name(&self) -> String {
+        format!("Twitch ({})", self.channel)
+    }
+
+    async fn start(
+        mut self: Box<Self>,
+        tx: mpsc::Sender<ChatMessage>,
+        status_tx: mpsc::Sender<SourceStatus>,
+        mut shutdown: watch::Receiver<bool>,
+    ) {
+        let mut credentials = match &self.credentials {
+            Some((username, token)) => {
+                let username = username.to_lowercase();
+                let token = token.trim_start_matches("oauth:").to_string();
+                match Self::validate_credentials(&username, &token).await {
+                    Ok(()) => Some((username, token)),
+                    Err(problem) => {
+                        fall_back_to_anonymous(&problem, &status_tx).await;
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+
+        let mut tracker = ConnectionTracker::new(status_tx.clone());
+        // Messages still waiting when the connection drops go out on the next one
+        let mut outbox = Outbox::new(self.outbound_rx.take());
+        loop {
+            match self
+                .run_client(credentials.clone(), &tx, &mut tracker, &mut outbox, &mut shutdown)
+                .await
+            {
+                ClientEnd::AuthFailed(problem) => {
+                    credentials = None;
+                    fall_back_to_anonymous(&problem, &status_tx).await;
+                }
+                ClientEnd::Lost => {
+                    let delay = tracker.reconnecting().await;
+                    // Clicking the status chip skips the wait
+                    tokio::select! {
+                        _ = sleep(delay) => {}
+                        _ = self.reconnect.notified() => {}
+                        _ = shutdown.changed() => return,
+                    }
+                }
+                ClientEnd::Stopped => return,
+            }
+        }
+    }
+}
+
+/// Explain the credential problem and let Bevy show the warning chip
+async fn fall_back_to_anonymous(problem: &AuthProblem, status_tx: &mpsc::Sender<SourceStatus>) {
+    warn!("Twitch login failed: {}", problem);
+    warn!("Connecting to Twitch anonymously, the overlay will be read only");
+    let _ = status_tx
+        .send(SourceStatus::AuthFailed {
+            reason: problem.to_string(),
+        })
+        .await;
+}
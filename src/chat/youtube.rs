@@ -0,0 +1,363 @@
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use log::{info, warn};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tokio::{
+    sync::{mpsc, watch},
+    time::sleep,
+};
+
+use crate::{chat::ChatSource, ChatMessage, Platform, SourceStatus};
+
+const YOUTUBE_API_URL: &str = "https://www.googleapis.com/youtube/v3";
+const MIN_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Chat source polling a YouTube live chat through the Data API
+pub(crate) struct YouTubeSource {
+    client: reqwest::Client,
+    api_key: String,
+    live_chat_id: Option<String>,
+    video_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LiveChatResponse {
+    next_page_token: Option<String>,
+    polling_interval_millis: Option<u64>,
+    #[serde(default)]
+    items: Vec<LiveChatItem>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LiveChatItem {
+    id: String,
+    snippet: LiveChatSnippet,
+    author_details: LiveChatAuthor,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LiveChatSnippet {
+    #[serde(default)]
+    display_message: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LiveChatAuthor {
+    /// Stays the same when the user changes their name, unlike the display name
+    channel_id: String,
+    display_name: String,
+    #[serde(default)]
+    is_chat_owner: bool,
+    #[serde(default)]
+    is_chat_sponsor: bool,
+    #[serde(default)]
+    is_chat_moderator: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct VideosResponse {
+    items: Vec<VideoItem>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct VideoItem {
+    live_streaming_details: Option<LiveStreamingDetails>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LiveStreamingDetails {
+    active_live_chat_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ApiErrorResponse {
+    error: ApiError,
+}
+
+#[derive(Deserialize, Debug)]
+struct ApiError {
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    errors: Vec<ApiErrorDetail>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ApiErrorDetail {
+    #[serde(default)]
+    reason: String,
+}
+
+/// Error reasons that only mean slow down, every other 403 is for good
+const THROTTLE_REASONS: [&str; 4] =
+    ["quotaExceeded", "rateLimitExceeded", "dailyLimitExceeded", "userRateLimitExceeded"];
+
+/// Why a poll did not return messages
+enum PollError {
+    /// Quota or rate limit hit, poll slower
+    Throttled,
+    /// The chat ended or was switched off, or the key can't read it. Polling again won't help.
+    Fatal(String),
+    /// Network trouble or a server error, worth trying again
+    Other(String),
+}
+
+impl From<reqwest::Error> for PollError {
+    fn from(err: reqwest::Error) -> Self {
+        PollError::Other(err.to_string())
+    }
+}
+
+impl PollError {
+    /// Sort an error answer by its status and the reason YouTube gives
+    async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status();
+        let error = response.json::<ApiErrorResponse>().await.ok().map(|body| body.error);
+        let reason = error
+            .as_ref()
+            .and_then(|error| error.errors.first())
+            .map_or("", |detail| detail.reason.as_str());
+        if status == StatusCode::TOO_MANY_REQUESTS || THROTTLE_REASONS.contains(&reason) {
+            return PollError::Throttled;
+        }
+        let message = error.as_ref().map_or("", |error| error.message.as_str());
+        let description = format!("{} {} {}", status, reason, message).trim().to_string();
+        if status.is_client_error() {
+            PollError::Fatal(description)
+        } else {
+            PollError::Other(description)
+        }
+    }
+}
+
+impl YouTubeSource {
+    pub(crate) fn new(api_key: String, live_chat_id: Option<String>, video_id: Option<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_key,
+            live_chat_id,
+            video_id,
+        }
+    }
+
+    /// Look up the live chat id of a video that is currently live
+    async fn resolve_live_chat_id(&self, video_id: &str) -> Result<Option<String>, reqwest::Error> {
+        let response: VideosResponse = self
+            .client
+            .get(format!("{}/videos", YOUTUBE_API_URL))
+            .query(&[
+                ("part", "liveStreamingDetails"),
+                ("id", video_id),
+                ("key", self.api_key.as_str()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response
+            .items
+            .into_iter()
+            .next()
+            .and_then(|item| item.live_streaming_details)
+            .and_then(|details| details.active_live_chat_id))
+    }
+
+    async fn poll(&self, live_chat_id: &str, page_token: Option<&str>) -> Result<LiveChatResponse, PollError> {
+        let mut query = vec![
+            ("liveChatId", live_chat_id),
+            ("part", "snippet,authorDetails"),
+            ("key", self.api_key.as_str()),
+        ];
+        if let Some(page_token) = page_token {
+            query.push(("pageToken", page_token));
+        }
+        let response = self
+            .client
+            .get(format!("{}/liveChat/messages", YOUTUBE_API_URL))
+            .query(&query)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(PollError::from_response(response).await);
+        }
+        Ok(response.json::<LiveChatResponse>().await?)
+    }
+}
+
+#[async_trait]
+impl ChatSource for YouTubeSource {
+    fn name(&self) -> String {
+        "YouTube".to_string()
+    }
+
+    async fn start(
+        self: Box<Self>,
+        tx: mpsc::Sender<ChatMessage>,
+        status_tx: mpsc::Sender<SourceStatus>,
+        mut shutdown: watch::Receiver<bool>,
+    ) {
+        let live_chat_id = match (&self.live_chat_id, &self.video_id) {
+            (Some(live_chat_id), _) => live_chat_id.clone(),
+            (None, Some(video_id)) => match self.resolve_live_chat_id(video_id).await {
+                Ok(Some(live_chat_id)) => live_chat_id,
+                Ok(None) => {
+                    stopped(&status_tx, format!("video {} has no active live chat", video_id)).await;
+                    return;
+                }
+                Err(err) => {
+                    stopped(&status_tx, format!("failed to resolve the live chat: {}", err)).await;
+                    return;
+                }
+            },
+            (None, None) => {
+                stopped(&status_tx, "it needs LIVE_CHAT_ID or VIDEO_ID".to_string()).await;
+                return;
+            }
+        };
+
+        let mut page_token: Option<String> = None;
+        let mut interval = MIN_POLL_INTERVAL;
+        // The first page is recent history, only show messages sent after we connected
+        let mut first_page = true;
+        // Polls failed in a row, and when the first of them did
+        let mut failures = 0;
+        let mut down_since: Option<Instant> = None;
+
+        loop {
+            match self.poll(&live_chat_id, page_token.as_deref()).await {
+                Ok(response) => {
+                    if first_page || failures > 0 {
+                        let down_for = down_since.take().map(|since| since.elapsed());
+                        if let Some(down_for) = down_for {
+                            info!("Back in YouTube chat after {} seconds", down_for.as_secs());
+                        }
+                        failures = 0;
+                        let _ = status_tx
+                            .send(SourceStatus::Connected {
+                                platform: Platform::YouTube,
+                                down_for,
+                                missed: 0,
+                            })
+                            .await;
+                    }
+                    page_token = response.next_page_token;
+                    interval = Duration::from_millis(response.polling_interval_millis.unwrap_or(0))
+                        .max(MIN_POLL_INTERVAL);
+                    if !first_page {
+                        for item in response.items {
+                            if let Some(chat_message) = to_chat_message(item) {
+                                info!("[YouTube] {}: {}", chat_message.name(), chat_message.message);
+                                if tx.send(chat_message).await.is_err() {
+                                    return;
+                                }
+                            }
+                        }
+                    }
+                    first_page = false;
+                }
+                Err(PollError::Throttled) => {
+                    interval = (interval * 2).min(MAX_POLL_INTERVAL);
+                    warn!("YouTube quota or rate limit hit, polling every {:?}", interval);
+                }
+                Err(PollError::Fatal(reason)) => {
+                    stopped(&status_tx, reason).await;
+                    return;
+                }
+                Err(PollError::Other(err)) => {
+                    warn!("Failed to poll YouTube chat: {}", err);
+                    failures += 1;
+                    if down_since.is_none() {
+                        down_since = Some(Instant::now());
+                        let _ = status_tx
+                            .send(SourceStatus::Disconnected {
+                                platform: Platform::YouTube,
+                            })
+                            .await;
+                    }
+                    let _ = status_tx
+                        .send(SourceStatus::Reconnecting {
+                            platform: Platform::YouTube,
+                            attempt: failures,
+                        })
+                        .await;
+                }
+            }
+
+            tokio::select! {
+                _ = sleep(interval) => {}
+                _ = shutdown.changed() => return,
+            }
+        }
+    }
+}
+
+/// Give up on the chat for good, saying why
+async fn stopped(status_tx: &mpsc::Sender<SourceStatus>, reason: String) {
+    warn!("Stopped reading YouTube chat: {}", reason);
+    let _ = status_tx
+        .send(SourceStatus::Stopped {
+            source: "YouTube".to_string(),
+            reason,
+        })
+        .await;
+}
+
+fn to_chat_message(item: LiveChatItem) -> Option<ChatMessage> {
+    let message = item
+        .snippet
+        .display_message
+        .split_whitespace()
+        .map(convert_emoji_shortcode)
+        .collect::<Vec<&str>>()
+        .join(" ");
+    if message.is_empty() {
+        return None;
+    }
+
+    let author = item.author_details;
+    let mut badges = vec![];
+    if author.is_chat_owner {
+        badges.push("broadcaster".to_string());
+    }
+    if author.is_chat_moderator {
+        badges.push("moderator".to_string());
+    }
+    if author.is_chat_sponsor {
+        badges.push("member".to_string());
+    }
+
+    Some(ChatMessage {
+        id: Some(item.id),
+        platform: Platform::YouTube,
+        user: author.channel_id,
+        display_name: Some(author.display_name),
+        message,
+        emotes: vec![],
+        origin_room_id: None,
+        badges,
+        badge_versions: vec![],
+        sub_months: None,
+        whisper: false,
+        bits: 0,
+    })
+}
+
+/// YouTube sends channel emoji as `:_name:` shortcodes, strip them down to the bare name
+/// so they match 7TV or Twitch emotes with the same name
+fn convert_emoji_shortcode(word: &str) -> &str {
+    if word.len() > 2 && word.starts_with(':') && word.ends_with(':') {
+        word[1..word.len() - 1].trim_start_matches('_')
+    } else {
+        word
+    }
+}
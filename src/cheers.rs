@@ -0,0 +1,257 @@
+use std::{
+    collections::HashMap,
+    f32::consts::PI,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    asset::AssetServer,
+    color::Color,
+    hierarchy::{BuildChildren, DespawnRecursiveExt, Parent},
+    math::{Vec2, Vec3},
+    prelude::{
+        default, Commands, Component, Entity, GlobalTransform, Query, Res, ResMut, Resource, SpatialBundle,
+        Transform, With, Without,
+    },
+    sprite::{Anchor, Sprite},
+    text::{Text, Text2dBundle, TextStyle},
+};
+use chrono::{Datelike, NaiveDate};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    giveaway::spawn_crown,
+    leaderboard::LeaderCrown,
+    sizes::{avatar_size, feet_y, head_y, SizeClass},
+    AppState, UserMarker,
+};
+
+/// How long the crown takes to fly over to a new top cheerer
+const HANDOVER_TIME: Duration = Duration::from_millis(1200);
+/// How high the crown arcs on its way over
+const HANDOVER_ARC: f32 = 80.0;
+/// The top cheerer's name is this much bigger than the message text
+const NAME_SCALE: f32 = 1.2;
+const NAME_COLOR: Color = Color::srgb(1.0, 0.84, 0.0);
+
+/// Bits each user cheered, since startup or since the start of the month when MONTHLY is on
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub(crate) struct BitTotals {
+    /// First day of the month these are for, None when they're for the whole session
+    month: Option<NaiveDate>,
+    totals: HashMap<String, u64>,
+}
+
+impl BitTotals {
+    /// Totals saved by the last run, if they're for this month
+    pub(crate) fn restored(saved: Option<BitTotals>, month: Option<NaiveDate>) -> Self {
+        saved
+            .filter(|saved| month.is_some() && saved.month == month)
+            .unwrap_or_default()
+    }
+
+    /// Start counting afresh when the month changes, or when MONTHLY is turned on or off
+    fn start_month(&mut self, month: Option<NaiveDate>) {
+        if self.month != month {
+            self.month = month;
+            self.totals.clear();
+        }
+    }
+
+    pub(crate) fn add(&mut self, user: &str, bits: u64, month: Option<NaiveDate>) {
+        self.start_month(month);
+        *self.totals.entry(user.to_string()).or_default() += bits;
+    }
+
+    /// Totals worth keeping across a restart, only the month's ones are
+    pub(crate) fn monthly(&self) -> Option<BitTotals> {
+        self.month.is_some().then(|| self.clone())
+    }
+
+    /// Forget a Twitch user's bits, whatever the case of their name
+    pub(crate) fn forget(&mut self, login: &str) {
+        self.totals.retain(|user, _| !user.eq_ignore_ascii_case(login));
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.totals.clear();
+    }
+
+    /// Who cheered the most, the holder keeps the lead on a tie
+    fn leader(&self, holder: Option<&str>) -> Option<String> {
+        let best = self.totals.values().copied().max().filter(|best| *best > 0)?;
+        holder
+            .filter(|holder| self.totals.get(*holder) == Some(&best))
+            .map(str::to_string)
+            .or_else(|| {
+                // Ties go by name so the crown doesn't move around between runs
+                self.totals
+                    .iter()
+                    .filter(|(_, total)| **total == best)
+                    .map(|(user, _)| user)
+                    .min()
+                    .cloned()
+            })
+    }
+}
+
+/// The month bits are counted for, None to count since startup
+pub(crate) fn counting_month(config: &Config) -> Option<NaiveDate> {
+    config.bits_monthly.then(|| config.today().with_day(1)).flatten()
+}
+
+/// Who wears the bits crown
+#[derive(Resource, Default)]
+pub(crate) struct TopCheerer {
+    leader: Option<String>,
+}
+
+/// The top cheerer's crown, flying over from the last one after a handover
+#[derive(Component)]
+pub(crate) struct BitsCrown {
+    flight: Option<CrownFlight>,
+}
+
+struct CrownFlight {
+    /// Where it took off from, relative to the avatar it's flying to
+    from: Vec3,
+    started: Instant,
+}
+
+/// The top cheerer's name under their minawan
+#[derive(Component)]
+pub(crate) struct TopCheererLabel {}
+
+// Keep the bits crown and the name label on the top cheerer at the party. When someone else
+// takes the lead the crown flies over from the old leader if they're both here.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn crown_top_cheerer(
+    mut commands: Commands,
+    mut top: ResMut<TopCheerer>,
+    mut app_state: ResMut<AppState>,
+    config: Res<Config>,
+    asset_server: Res<AssetServer>,
+    crown_query: Query<(Entity, &Parent, &GlobalTransform), With<BitsCrown>>,
+    label_query: Query<Entity, With<TopCheererLabel>>,
+    avatar_query: Query<(&Transform, &Sprite, Option<&SizeClass>), With<UserMarker>>,
+) {
+    let leader = if config.bits_enabled {
+        let month = counting_month(&config);
+        if app_state.bit_totals.month != month {
+            app_state.bit_totals.start_month(month);
+        }
+        app_state.bit_totals.leader(top.leader.as_deref())
+    } else {
+        None
+    };
+    let leader_entity = leader
+        .as_ref()
+        .and_then(|user| app_state.active_users.get(user))
+        .map(|user| user.entity);
+    let crowned = crown_query.iter().any(|(_, parent, _)| Some(parent.get()) == leader_entity);
+    if leader == top.leader && (crowned || leader_entity.is_none()) {
+        return;
+    }
+
+    let handover = top.leader.is_some() && leader != top.leader;
+    let old_crown = crown_query
+        .iter()
+        .find(|(_, parent, _)| Some(parent.get()) != leader_entity)
+        .map(|(_, _, transform)| transform.translation());
+    for entity in crown_query.iter().map(|(entity, _, _)| entity).chain(label_query.iter()) {
+        commands.entity(entity).despawn_recursive();
+    }
+    let name = leader.as_ref().map(|user| {
+        app_state
+            .active_users
+            .get(user)
+            .map_or(user.clone(), |user| user.name.clone())
+    });
+    if handover {
+        if let Some(name) = &name {
+            info!("{} is the new top cheerer", name);
+        }
+    }
+    top.leader = leader;
+
+    let Some((entity, (transform, sprite, size_class))) =
+        leader_entity.and_then(|entity| avatar_query.get(entity).ok().map(|avatar| (entity, avatar)))
+    else {
+        return;
+    };
+    let size = avatar_size(sprite, size_class);
+    let flight = old_crown.filter(|_| handover).map(|from| CrownFlight {
+        // Crowns are children of the avatar, which giants scale up
+        from: (from - transform.translation) / transform.scale,
+        started: Instant::now(),
+    });
+    let start = flight.as_ref().map_or(crown_rest(sprite, size_class, false), |flight| flight.from);
+    commands.entity(entity).with_children(|parent| {
+        parent
+            .spawn((
+                SpatialBundle::from_transform(Transform::from_translation(start)),
+                BitsCrown { flight },
+            ))
+            .with_children(|crown| spawn_crown(crown, size.x * 0.45));
+        parent.spawn((
+            Text2dBundle {
+                text: Text::from_section(
+                    name.unwrap_or_default(),
+                    TextStyle {
+                        font: asset_server.load(&config.font_url),
+                        font_size: config.font_size * NAME_SCALE,
+                        color: NAME_COLOR,
+                    },
+                ),
+                text_anchor: Anchor::TopCenter,
+                transform: Transform::from_xyz(0.0, feet_y(sprite, size), 1.0),
+                ..default()
+            },
+            TopCheererLabel {},
+        ));
+    });
+}
+
+/// Where the crown sits on an avatar, over the chat leader's crown when they wear both
+fn crown_rest(sprite: &Sprite, size_class: Option<&SizeClass>, stacked: bool) -> Vec3 {
+    let size = avatar_size(sprite, size_class);
+    let above = if stacked { size.x * 0.3 } else { 0.0 };
+    Vec3::new(0.0, head_y(sprite, size) + 4.0 + above, 2.1)
+}
+
+// Fly a handed over crown in an arc to its new wearer, and keep crowns clear of the chat
+// leader's crown
+pub(crate) fn animate_bits_crown(
+    mut crown_query: Query<(&Parent, &mut Transform, &mut BitsCrown)>,
+    avatar_query: Query<(&Sprite, Option<&SizeClass>), (With<UserMarker>, Without<BitsCrown>)>,
+    leader_crown_query: Query<&Parent, With<LeaderCrown>>,
+) {
+    for (parent, mut transform, mut crown) in crown_query.iter_mut() {
+        let Ok((sprite, size_class)) = avatar_query.get(parent.get()) else {
+            continue;
+        };
+        let stacked = leader_crown_query.iter().any(|leader_parent| leader_parent.get() == parent.get());
+        let rest = crown_rest(sprite, size_class, stacked);
+        let flight = crown.flight.as_ref().map(|flight| {
+            let progress = flight.started.elapsed().as_secs_f32() / HANDOVER_TIME.as_secs_f32();
+            (flight.from, progress)
+        });
+        let translation = match flight {
+            Some((from, progress)) if progress < 1.0 => {
+                let eased = progress * progress * (3.0 - 2.0 * progress);
+                from.lerp(rest, eased) + Vec3::Y * HANDOVER_ARC * (progress * PI).sin()
+            }
+            Some(_) => {
+                crown.flight = None;
+                rest
+            }
+            None => rest,
+        };
+        if transform.translation != translation {
+            transform.translation = translation;
+        }
+    }
+}
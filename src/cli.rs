@@ -0,0 +1,238 @@
+use std::{env, process};
+
+use crate::{config::Config, paths::default_config_path};
+
+const USAGE: &str = "\
+Usage: minawan-watch-party [OPTIONS]
+
+Options:
+  --config <PATH>       Config file to load [default: config.toml in the config folder]
+  --portable            Keep the config and everything the overlay saves next to the executable
+  --profile <NAME>      Profile from the config file to use, overrides PROFILE
+  --channel <NAME>      Twitch channel to watch, overrides CHANNEL_NAME
+  --channel-id <ID>     Twitch channel id, overrides CHANNEL_ID
+  --scale <SCALE>       Scale on top of the monitor's own, overrides SCALE
+  --log-level <LEVEL>   Log filter such as info or debug, overrides LOG_LEVEL
+  --simulate            Add a chat source that sends fake messages, for testing
+  --settings            Open the settings window on startup
+  --headless            Run without a window or GPU, for trying changes with --simulate
+  --export-theme <PATH> Write the overlay's look and the files it uses to a theme file, then exit
+  --import-theme <PATH> Merge a theme file into the config and unpack its files, then exit
+  -h, --help            Print this help
+";
+
+/// Command line options, these take precedence over the environment and the config file
+#[derive(Clone, Debug)]
+pub struct CliArgs {
+    pub(crate) config_path: String,
+    pub(crate) profile: Option<String>,
+    pub(crate) channel: Option<String>,
+    pub(crate) channel_id: Option<String>,
+    pub(crate) scale: Option<f32>,
+    pub(crate) log_level: Option<String>,
+    pub(crate) simulate: bool,
+    pub(crate) settings: bool,
+    pub(crate) headless: bool,
+    pub(crate) portable: bool,
+    pub(crate) export_theme: Option<String>,
+    pub(crate) import_theme: Option<String>,
+}
+
+impl Default for CliArgs {
+    fn default() -> Self {
+        Self {
+            config_path: default_config_path(false),
+            profile: None,
+            channel: None,
+            channel_id: None,
+            scale: None,
+            log_level: None,
+            simulate: false,
+            settings: false,
+            headless: false,
+            portable: false,
+            export_theme: None,
+            import_theme: None,
+        }
+    }
+}
+
+impl CliArgs {
+    /// Parse the process arguments, printing usage and exiting on `--help` or a bad argument
+    pub fn parse() -> Self {
+        match Self::parse_from(env::args().skip(1)) {
+            Ok(Some(args)) => args,
+            Ok(None) => {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            Err(err) => {
+                eprintln!("{}\n\n{}", err, USAGE);
+                process::exit(2);
+            }
+        }
+    }
+
+    /// Returns None when help was requested
+    fn parse_from(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut parsed = CliArgs::default();
+        let mut config_path = None;
+
+        while let Some(arg) = args.next() {
+            // Accept both `--flag value` and `--flag=value`
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("{} needs a value", flag))
+            };
+
+            match flag.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "--config" => config_path = Some(value()?),
+                "--profile" => parsed.profile = Some(value()?),
+                "--channel" => parsed.channel = Some(value()?.to_lowercase()),
+                "--channel-id" => parsed.channel_id = Some(value()?),
+                "--scale" => {
+                    let scale = value()?;
+                    parsed.scale = Some(
+                        scale
+                            .parse()
+                            .map_err(|_| format!("Invalid --scale: {}", scale))?,
+                    );
+                }
+                "--log-level" => parsed.log_level = Some(value()?),
+                "--simulate" => parsed.simulate = true,
+                "--settings" => parsed.settings = true,
+                "--headless" => parsed.headless = true,
+                "--portable" => parsed.portable = true,
+                "--export-theme" => parsed.export_theme = Some(value()?),
+                "--import-theme" => parsed.import_theme = Some(value()?),
+                _ => return Err(format!("Unknown argument: {}", flag)),
+            }
+        }
+        parsed.config_path = config_path.unwrap_or_else(|| default_config_path(parsed.portable));
+        Ok(Some(parsed))
+    }
+
+    /// Override config values with the ones given on the command line
+    pub(crate) fn apply(&self, config: &mut Config) {
+        if let Some(channel) = &self.channel {
+            config.channel_name = channel.clone();
+        }
+        if let Some(channel_id) = &self.channel_id {
+            config.channel_id = channel_id.clone();
+        }
+        if let Some(scale) = self.scale {
+            config.scale = scale;
+        }
+        if self.simulate && !config.chat_sources.iter().any(|source| source == "simulate") {
+            config.chat_sources.push("simulate".to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Mutex, time::Duration};
+
+    use super::*;
+    use crate::config::load_config;
+
+    const FILE: &str = r#"
+[Channel]
+CHANNEL_NAME = "from_file"
+CHANNEL_ID = "1234"
+
+[General]
+SCALE = 1.5
+
+[Avatars]
+AVATAR_MOVE_SPEED = 50.0
+USER_DESPAWN_TIME_SECS = 600
+"#;
+
+    /// The environment is shared by every test, only one of them changes it at a time
+    static ENV: Mutex<()> = Mutex::new(());
+
+    fn parse(args: &[&str]) -> CliArgs {
+        CliArgs::parse_from(args.iter().map(|arg| arg.to_string())).unwrap().unwrap()
+    }
+
+    #[test]
+    fn command_line_beats_environment_beats_file() {
+        let _env = ENV.lock().unwrap();
+        let path = env::temp_dir().join(format!("mwp-precedence-{}.toml", process::id()));
+        fs::write(&path, FILE).unwrap();
+        let vars = [
+            ("MWP_CHANNEL_NAME", "from_env"),
+            ("MWP_GENERAL_SCALE", "2.0"),
+            ("MWP_AVATARS_USER_DESPAWN_TIME_SECS", "900"),
+        ];
+        for (name, value) in vars {
+            env::set_var(name, value);
+        }
+        let loaded = load_config(path.to_str().unwrap(), None);
+        for (name, _) in vars {
+            env::remove_var(name);
+        }
+        fs::remove_file(&path).unwrap();
+        let mut config = loaded.unwrap();
+        parse(&["--channel", "From_CLI", "--scale", "3"]).apply(&mut config);
+
+        // Only in the file
+        assert_eq!(config.channel_id, "1234");
+        assert_eq!(config.avatar_move_speed, 50.0);
+        // In the file and the environment
+        assert_eq!(config.user_despawn_time, Duration::from_secs(900));
+        // In all three
+        assert_eq!(config.channel_name, "from_cli");
+        assert_eq!(config.scale, 3.0);
+
+        let runtime = config.runtime_values();
+        assert!(runtime.contains(&("AVATAR_MOVE_SPEED", "50".to_string())));
+        assert!(runtime.contains(&("USER_DESPAWN_TIME_SECS", "900".to_string())));
+    }
+
+    #[test]
+    fn environment_beats_file_without_command_line() {
+        let _env = ENV.lock().unwrap();
+        let path = env::temp_dir().join(format!("mwp-precedence-env-{}.toml", process::id()));
+        fs::write(&path, FILE).unwrap();
+        env::set_var("MWP_CHANNEL_CHANNEL_ID", "5678");
+        let loaded = load_config(path.to_str().unwrap(), None);
+        env::remove_var("MWP_CHANNEL_CHANNEL_ID");
+        fs::remove_file(&path).unwrap();
+        let mut config = loaded.unwrap();
+        parse(&[]).apply(&mut config);
+
+        assert_eq!(config.channel_id, "5678");
+        assert_eq!(config.channel_name, "from_file");
+        assert_eq!(config.scale, 1.5);
+    }
+
+    #[test]
+    fn malformed_environment_value_is_skipped() {
+        let _env = ENV.lock().unwrap();
+        let path = env::temp_dir().join(format!("mwp-precedence-bad-env-{}.toml", process::id()));
+        fs::write(&path, FILE).unwrap();
+        let vars = [("MWP_GENERAL_SCALE", "huge"), ("MWP_AVATARS_USER_DESPAWN_TIME_SECS", "900")];
+        for (name, value) in vars {
+            env::set_var(name, value);
+        }
+        let loaded = load_config(path.to_str().unwrap(), None);
+        for (name, _) in vars {
+            env::remove_var(name);
+        }
+        fs::remove_file(&path).unwrap();
+        let config = loaded.unwrap();
+
+        // The bad value leaves the file's in place, the good one still applies
+        assert_eq!(config.scale, 1.5);
+        assert_eq!(config.user_despawn_time, Duration::from_secs(900));
+    }
+}
@@ -0,0 +1,291 @@
+use std::time::Duration;
+
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::{Commands, Event, EventReader, EventWriter, Query, Res, ResMut, With},
+};
+use log::{debug, info, warn};
+
+use crate::{
+    accessories::StripAccessories, api::ApiEvent, config::Config, downloads::Downloads, giant::MakeGiant, giveaway::PickWinner, handles::HandleCache, marquee::{parse_marquee_command, MarqueeCommand}, pool::EntityPools, progress::{parse_progress_command, ProgressCommand}, race::{parse_race_command, StartRace}, rain::{parse_rain_command, RainCommand}, screenshot::TakeScreenshot, settings::ToggleSettingsWindow, signs::ClearSign, sizes::{ChangeSize, SizeClass}, timer::{parse_timer_command, TimerCommand}, weather::WeatherEvent, AppState, ChatMessage, EmoteStorage,
+    MessageSpawnTime, UserMarker,
+};
+
+/// Commands the broadcaster and admins can send from chat
+#[derive(Event, Debug, Clone)]
+pub(crate) enum AdminCommand {
+    Clear,
+    Pause,
+    Resume,
+    Set { key: String, value: String },
+    Stats,
+    Settings,
+    Giant(String),
+    /// Pick a giveaway winner, from those who said the keyword if there is one
+    Pick(Option<String>),
+    Reroll,
+    Weather(WeatherEvent),
+    /// Take off a user's accessory
+    Strip(String),
+    /// Save the party as a PNG, or a burst of them
+    Screenshot { burst: bool },
+    /// Start counting the bits for the crown again
+    ResetBits,
+    /// Take down a user's sign
+    ClearSign(String),
+    /// Make a user's minawan smol, normal or chonk
+    Size { user: String, size: SizeClass },
+    Unknown(String),
+}
+
+/// Whether the sender of a message may control the overlay
+pub(crate) fn is_admin(chat_message: &ChatMessage, config: &Config) -> bool {
+    chat_message.badges.iter().any(|badge| badge == "broadcaster")
+        || config
+            .admin_users
+            .iter()
+            .any(|admin| admin.eq_ignore_ascii_case(&chat_message.user_key()))
+}
+
+/// Whether the sender of a message may run the commands moderators can
+pub(crate) fn is_moderator(chat_message: &ChatMessage, config: &Config) -> bool {
+    chat_message.badges.iter().any(|badge| badge == "moderator") || is_admin(chat_message, config)
+}
+
+/// Where commands sent from chat go
+#[derive(SystemParam)]
+pub(crate) struct ChatCommands<'w> {
+    admin: EventWriter<'w, AdminCommand>,
+    timer: EventWriter<'w, TimerCommand>,
+    progress: EventWriter<'w, ProgressCommand>,
+    race: EventWriter<'w, StartRace>,
+    marquee: EventWriter<'w, MarqueeCommand>,
+    rain: EventWriter<'w, RainCommand>,
+}
+
+impl ChatCommands<'_> {
+    /// Send a message on to whatever it's a command for. Returns false when it isn't a
+    /// command and should go through the normal display pipeline.
+    pub(crate) fn send(&mut self, chat_message: &ChatMessage, config: &Config) -> bool {
+        if let Some(command) = parse_admin_command(chat_message, config) {
+            self.admin.send(command);
+        } else if let Some(command) = parse_timer_command(chat_message, config) {
+            self.timer.send(command);
+        } else if let Some(command) = parse_progress_command(chat_message, config) {
+            self.progress.send(command);
+        } else if let Some(command) = parse_race_command(chat_message, config) {
+            self.race.send(command);
+        } else if let Some(command) = parse_marquee_command(chat_message, config) {
+            self.marquee.send(command);
+        } else if let Some(command) = parse_rain_command(chat_message, config) {
+            self.rain.send(command);
+        } else {
+            return false;
+        }
+        true
+    }
+}
+
+/// Events admin commands hand over to the systems that carry them out
+#[derive(SystemParam)]
+pub(crate) struct AdminHandover<'w> {
+    settings: EventWriter<'w, ToggleSettingsWindow>,
+    giant: EventWriter<'w, MakeGiant>,
+    pick: EventWriter<'w, PickWinner>,
+    weather: EventWriter<'w, WeatherEvent>,
+    strip: EventWriter<'w, StripAccessories>,
+    screenshot: EventWriter<'w, TakeScreenshot>,
+    sign: EventWriter<'w, ClearSign>,
+    size: EventWriter<'w, ChangeSize>,
+}
+
+/// Parse an admin command from a whisper or a prefixed channel message.
+/// Returns None when the message should go through the normal display pipeline.
+pub(crate) fn parse_admin_command(chat_message: &ChatMessage, config: &Config) -> Option<AdminCommand> {
+    if !is_admin(chat_message, config) {
+        return None;
+    }
+
+    let text = chat_message.message.trim();
+    let text = match text.strip_prefix(&config.command_prefix) {
+        Some(text) => text,
+        // Whispers don't need the prefix
+        None if chat_message.whisper => text,
+        None => return None,
+    };
+
+    let mut words = text.split_whitespace();
+    let command = match words.next().map(|word| word.to_lowercase()).as_deref() {
+        Some("clear") => AdminCommand::Clear,
+        Some("pause") => AdminCommand::Pause,
+        Some("resume") => AdminCommand::Resume,
+        Some("stats") => AdminCommand::Stats,
+        Some("settings") => AdminCommand::Settings,
+        Some("giant") => match words.next() {
+            Some(user) => AdminCommand::Giant(user.to_string()),
+            None => AdminCommand::Unknown(text.to_string()),
+        },
+        Some("strip") => match words.next() {
+            Some(user) => AdminCommand::Strip(user.to_string()),
+            None => AdminCommand::Unknown(text.to_string()),
+        },
+        Some("pick") => AdminCommand::Pick(words.next().map(str::to_string)),
+        Some("reroll") => AdminCommand::Reroll,
+        Some("screenshot") => match words.next().map(|word| word.to_lowercase()).as_deref() {
+            None => AdminCommand::Screenshot { burst: false },
+            Some("burst") => AdminCommand::Screenshot { burst: true },
+            Some(_) => AdminCommand::Unknown(text.to_string()),
+        },
+        Some("sign") => match (words.next().map(|word| word.to_lowercase()).as_deref(), words.next()) {
+            (Some("clear"), Some(user)) => AdminCommand::ClearSign(user.to_string()),
+            _ => AdminCommand::Unknown(text.to_string()),
+        },
+        Some("size") => match (words.next(), words.next().map(str::parse)) {
+            (Some(user), Some(Ok(size))) => AdminCommand::Size {
+                user: user.to_string(),
+                size,
+            },
+            _ => AdminCommand::Unknown(text.to_string()),
+        },
+        Some("bits") => match words.next().map(|word| word.to_lowercase()).as_deref() {
+            Some("reset") => AdminCommand::ResetBits,
+            _ => AdminCommand::Unknown(text.to_string()),
+        },
+        Some("weather") => match words.next().map(|word| word.to_lowercase()).as_deref() {
+            Some("stop") => AdminCommand::Weather(WeatherEvent::Stop),
+            Some(kind) => match kind.parse() {
+                Ok(kind) => AdminCommand::Weather(WeatherEvent::Start {
+                    kind,
+                    duration: words.next().and_then(|secs| secs.parse().ok()).map(Duration::from_secs),
+                }),
+                Err(_) => AdminCommand::Unknown(text.to_string()),
+            },
+            None => AdminCommand::Unknown(text.to_string()),
+        },
+        Some("set") => match (words.next(), words.next()) {
+            (Some(key), Some(value)) => AdminCommand::Set {
+                key: key.to_string(),
+                value: value.to_string(),
+            },
+            _ => AdminCommand::Unknown(text.to_string()),
+        },
+        _ => AdminCommand::Unknown(text.to_string()),
+    };
+    Some(command)
+}
+
+// Run admin commands sent from chat
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn handle_admin_commands(
+    mut commands: Commands,
+    mut events: EventReader<AdminCommand>,
+    mut app_state: ResMut<AppState>,
+    mut pools: ResMut<EntityPools>,
+    mut config: ResMut<Config>,
+    emote_store: Res<EmoteStorage>,
+    downloads: Res<Downloads>,
+    handles: Res<HandleCache>,
+    user_query: Query<(), With<UserMarker>>,
+    message_query: Query<(), With<MessageSpawnTime>>,
+    mut handover: AdminHandover,
+    mut api_events: EventWriter<ApiEvent>,
+) {
+    for command in events.read() {
+        match command {
+            AdminCommand::Clear => {
+                info!("Clearing {} users", app_state.active_users.len());
+                let party = std::mem::take(&mut app_state.active_users);
+                app_state.despawn_queue.clear();
+                for (user_name, user) in party {
+                    // Pinned minawan stay
+                    if config.is_pinned(&user_name) {
+                        app_state.insert_user(user_name, user);
+                        continue;
+                    }
+                    pools.release_avatar(&mut commands, user.entity, &config);
+                    api_events.send(ApiEvent::UserDespawned { user: user_name });
+                }
+            }
+            AdminCommand::Pause => {
+                info!("Pausing new user spawns");
+                app_state.spawning_paused = true;
+            }
+            AdminCommand::Resume => {
+                info!("Resuming new user spawns");
+                app_state.spawning_paused = false;
+            }
+            AdminCommand::Set { key, value } => match config.set_runtime_value(key, value) {
+                Ok(()) => info!("Set {} to {}", key, value),
+                Err(err) => warn!("Could not set {}: {}", key, err),
+            },
+            AdminCommand::Stats => {
+                info!(
+                    "Stats: {} active users, {} avatars, {} messages, {} emotes known, {} emotes loaded",
+                    app_state.active_users.len(),
+                    user_query.iter().count(),
+                    message_query.iter().count(),
+                    emote_store.all.len(),
+                    emote_store.loaded.len(),
+                );
+                info!(
+                    "Pools: {} avatars pooled with {:.0}% reused, {} bubbles pooled with {:.0}% reused",
+                    pools.avatars.len(),
+                    pools.avatars.hit_rate() * 100.0,
+                    pools.bubbles.len(),
+                    pools.bubbles.hit_rate() * 100.0,
+                );
+                let download_stats = downloads.0.stats();
+                info!(
+                    "Downloads: {} queued, {} in flight, {} failed",
+                    download_stats.queued, download_stats.in_flight, download_stats.failed,
+                );
+                info!(
+                    "Assets: {} cached with {:.0}% of loads reused",
+                    handles.len(),
+                    handles.hit_rate() * 100.0,
+                );
+            }
+            AdminCommand::Settings => {
+                handover.settings.send(ToggleSettingsWindow {});
+            }
+            AdminCommand::Giant(user) => {
+                handover.giant.send(MakeGiant { user: user.clone() });
+            }
+            AdminCommand::Pick(keyword) => {
+                handover.pick.send(PickWinner {
+                    keyword: keyword.clone(),
+                    reroll: false,
+                });
+            }
+            AdminCommand::Reroll => {
+                handover.pick.send(PickWinner {
+                    keyword: None,
+                    reroll: true,
+                });
+            }
+            AdminCommand::Weather(event) => {
+                handover.weather.send(event.clone());
+            }
+            AdminCommand::Strip(user) => {
+                handover.strip.send(StripAccessories { user: user.clone() });
+            }
+            AdminCommand::Screenshot { burst } => {
+                handover.screenshot.send(TakeScreenshot { burst: *burst });
+            }
+            AdminCommand::ResetBits => {
+                info!("Resetting the bits totals");
+                app_state.bit_totals.reset();
+            }
+            AdminCommand::ClearSign(user) => {
+                handover.sign.send(ClearSign { user: user.clone() });
+            }
+            AdminCommand::Size { user, size } => {
+                handover.size.send(ChangeSize {
+                    user: user.clone(),
+                    size: *size,
+                });
+            }
+            AdminCommand::Unknown(text) => debug!("Ignoring unknown admin command: {}", text),
+        }
+    }
+}
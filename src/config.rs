@@ -1,26 +1,781 @@
-use std::time::Duration;
+use std::{collections::HashMap, env, fs, net::IpAddr, path::Path, str::FromStr, time::Duration};
 
-use bevy::prelude::Resource;
+use bevy::{
+    color::{Color, ColorToPacked, Srgba},
+    prelude::Resource,
+    window::PresentMode,
+};
+use chrono::{Local, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
 use ini::Ini;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use toml_edit::{DocumentMut, Item, Value};
 
-#[derive(Clone, Resource)]
-pub(crate) struct Config {
+use crate::{
+    bubble_themes::{bubble_theme_named, BubbleTheme},
+    locale::Locale,
+    opacity::{MAX_OPACITY, MIN_OPACITY},
+    schedule::ScheduledEvent,
+    widgets::DEFAULT_WIDGET_UPDATE_INTERVAL,
+};
+
+/// Prefix for environment variables that override config values
+const ENV_PREFIX: &str = "MWP_";
+
+/// Commented config written on first run, every key the overlay understands is listed here
+const DEFAULT_CONFIG: &str = include_str!("default_config.toml");
+
+/// Edge of the screen a widget is attached to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ScreenEdge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl FromStr for ScreenEdge {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "top" => Ok(ScreenEdge::Top),
+            "bottom" => Ok(ScreenEdge::Bottom),
+            "left" => Ok(ScreenEdge::Left),
+            "right" => Ok(ScreenEdge::Right),
+            _ => Err(format!("Unknown screen edge: {}", s)),
+        }
+    }
+}
+
+/// Corner of the screen a widget is placed in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ScreenCorner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl FromStr for ScreenCorner {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace(['-', ' '], "_").as_str() {
+            "top_left" => Ok(ScreenCorner::TopLeft),
+            "top_right" => Ok(ScreenCorner::TopRight),
+            "bottom_left" => Ok(ScreenCorner::BottomLeft),
+            "bottom_right" => Ok(ScreenCorner::BottomRight),
+            _ => Err(format!("Unknown screen corner: {}", s)),
+        }
+    }
+}
+
+/// Whether screen capture such as OBS display capture can see the overlay
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum CaptureVisibility {
+    Include,
+    Exclude,
+}
+
+impl FromStr for CaptureVisibility {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "include" => Ok(CaptureVisibility::Include),
+            "exclude" => Ok(CaptureVisibility::Exclude),
+            _ => Err(format!("Unknown capture visibility: {}", s)),
+        }
+    }
+}
+
+/// What's drawn behind the party
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum BackgroundMode {
+    Transparent,
+    /// A solid KEY_COLOR for capture that can't handle transparency
+    Chroma,
+}
+
+impl FromStr for BackgroundMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "transparent" => Ok(BackgroundMode::Transparent),
+            "chroma" => Ok(BackgroundMode::Chroma),
+            _ => Err(format!("Unknown background mode: {}", s)),
+        }
+    }
+}
+
+/// Move a colour one step away from the chroma key if it's the same, so it isn't keyed out.
+/// Alpha doesn't matter, a see-through bubble in the key colour still disappears.
+pub(crate) fn avoid_key_color(color: Color, key: Color) -> Color {
+    let [red, green, blue, alpha] = color.to_srgba().to_u8_array();
+    let [key_red, key_green, key_blue, _] = key.to_srgba().to_u8_array();
+    if (red, green, blue) != (key_red, key_green, key_blue) {
+        return color;
+    }
+    let step = |channel: u8| if channel == u8::MAX { channel - 1 } else { channel + 1 };
+    Color::srgba_u8(step(red), step(green), step(blue), alpha)
+}
+
+/// Where else the overlay is sent besides its window
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum OutputBackend {
+    None,
+    Ndi,
+}
+
+impl FromStr for OutputBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "" | "none" => Ok(OutputBackend::None),
+            "ndi" => Ok(OutputBackend::Ndi),
+            _ => Err(format!("Unknown output: {}, expected none or ndi", s)),
+        }
+    }
+}
+
+/// Graphics API to render with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum GpuBackend {
+    Auto,
+    Vulkan,
+    Dx12,
+    Metal,
+    Gl,
+}
+
+impl FromStr for GpuBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(GpuBackend::Auto),
+            "vulkan" => Ok(GpuBackend::Vulkan),
+            "dx12" => Ok(GpuBackend::Dx12),
+            "metal" => Ok(GpuBackend::Metal),
+            "gl" => Ok(GpuBackend::Gl),
+            _ => Err(format!("Unknown GPU backend: {}", s)),
+        }
+    }
+}
+
+/// A distance across the screen, either in pixels or as a percentage of the window width,
+/// or of its height for distances up and down
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Length {
+    Px(f32),
+    Percent(f32),
+}
+
+impl Length {
+    /// Pixels for a window of the given width, or height for distances up and down
+    pub(crate) fn resolve(self, width: f32) -> f32 {
+        match self {
+            Length::Px(px) => px,
+            Length::Percent(percent) => width * percent / 100.0,
+        }
+    }
+}
+
+impl FromStr for Length {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (number, percent) = match s.strip_suffix('%') {
+            Some(number) => (number, true),
+            None => (s.strip_suffix("px").unwrap_or(s), false),
+        };
+        match number.trim().parse::<f32>() {
+            Ok(number) if number >= 0.0 && percent => Ok(Length::Percent(number)),
+            Ok(number) if number >= 0.0 => Ok(Length::Px(number)),
+            _ => Err(format!("Invalid length: {}", s)),
+        }
+    }
+}
+
+/// Part of the window avatars can walk in, measured from the left edge
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct SpawnRange {
+    pub(crate) start: Length,
+    pub(crate) end: Length,
+}
+
+impl FromStr for SpawnRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("Invalid range, expected start-end: {}", s))?;
+        Ok(SpawnRange {
+            start: start.parse()?,
+            end: end.parse()?,
+        })
+    }
+}
+
+/// Something on stream, like a webcam frame, that minawan walk around or pass behind.
+/// Measured from the top left corner of the window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct StageProp {
+    pub(crate) left: Length,
+    pub(crate) top: Length,
+    pub(crate) width: Length,
+    pub(crate) height: Length,
+    /// Minawan walk behind it faded out, instead of turning back at its edges
+    pub(crate) behind: bool,
+}
+
+impl StageProp {
+    /// World x of its left and right edges for a window of the given width
+    pub(crate) fn x_range(&self, width: f32) -> (f32, f32) {
+        let left = -width / 2.0 + self.left.resolve(width);
+        (left, left + self.width.resolve(width))
+    }
+
+    /// World y of its bottom and top edges for a window of the given height
+    pub(crate) fn y_range(&self, height: f32) -> (f32, f32) {
+        let top = height / 2.0 - self.top.resolve(height);
+        (top - self.height.resolve(height), top)
+    }
+}
+
+impl FromStr for StageProp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let rect = words.next().ok_or_else(|| "Empty stage prop".to_string())?;
+        let behind = match words.next().map(|word| word.to_lowercase()).as_deref() {
+            None | Some("wall") => false,
+            Some("behind") => true,
+            Some(word) => return Err(format!("{:?} isn't wall or behind", word)),
+        };
+        let lengths = rect
+            .split(',')
+            .map(str::parse::<Length>)
+            .collect::<Result<Vec<Length>, String>>()?;
+        let [left, top, width, height] = lengths[..] else {
+            return Err(format!("Invalid stage prop, expected left,top,width,height: {}", s));
+        };
+        if words.next().is_some() {
+            return Err(format!("Invalid stage prop, too many words: {}", s));
+        }
+        Ok(StageProp {
+            left,
+            top,
+            width,
+            height,
+            behind,
+        })
+    }
+}
+
+/// Take `(start, end)` out of sorted ranges, splitting any it falls inside
+fn cut_ranges(ranges: &[(f32, f32)], (start, end): (f32, f32)) -> Vec<(f32, f32)> {
+    ranges
+        .iter()
+        .flat_map(|range| [(range.0, range.1.min(start)), (range.0.max(end), range.1)])
+        .filter(|(start, end)| start < end)
+        .collect()
+}
+
+/// A channel point reward that puts on an accessory, `Title = hat` or `Title = hat, scarf` for
+/// a reward whose viewers pick one
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct AccessoryReward {
+    pub(crate) title: String,
+    pub(crate) accessories: Vec<String>,
+}
+
+impl FromStr for AccessoryReward {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Titles may have an = of their own, accessory names don't
+        let (title, accessories) = s
+            .rsplit_once('=')
+            .ok_or_else(|| format!("Invalid reward, expected title = accessory: {}", s))?;
+        let title = title.trim().to_string();
+        let accessories = names(accessories.split(',').map(str::to_string).collect());
+        if title.is_empty() || accessories.is_empty() {
+            return Err(format!("Invalid reward, expected title = accessory: {}", s));
+        }
+        Ok(AccessoryReward { title, accessories })
+    }
+}
+
+/// Explicit position and size for the overlay window, in physical pixels relative to its monitor
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct WindowGeometry {
+    pub(crate) x: i32,
+    pub(crate) y: i32,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+}
+
+/// Edge of the monitor a strip window sits against
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) enum StripEdge {
+    Top,
+    Bottom,
+}
+
+impl FromStr for StripEdge {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "top" => Ok(StripEdge::Top),
+            "bottom" => Ok(StripEdge::Bottom),
+            _ => Err(format!("Unknown strip edge: {}", s)),
+        }
+    }
+}
+
+/// A window as wide as its monitor but only `height` physical pixels tall, instead of filling it
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct WindowStrip {
+    pub(crate) edge: StripEdge,
+    pub(crate) height: u32,
+}
+
+/// Parse a present mode, bevy's `PresentMode` has no `FromStr`
+fn parse_present_mode(s: &str) -> Result<PresentMode, String> {
+    match s.to_lowercase().replace(['-', ' '], "_").as_str() {
+        "auto_vsync" => Ok(PresentMode::AutoVsync),
+        "auto_no_vsync" => Ok(PresentMode::AutoNoVsync),
+        "mailbox" => Ok(PresentMode::Mailbox),
+        "fifo" => Ok(PresentMode::Fifo),
+        _ => Err(format!("Unknown present mode: {}", s)),
+    }
+}
+
+#[derive(Clone, PartialEq, Resource)]
+pub struct Config {
     pub(crate) channel_name: String,
     pub(crate) channel_id: String,
+    pub(crate) chat_sources: Vec<String>,
+    pub(crate) dedupe_window: Duration,
+    /// Profile merged over the base settings, if any
+    pub(crate) profile: Option<String>,
     pub(crate) scale: f32,
+    pub(crate) backend: GpuBackend,
+    pub(crate) present_mode: PresentMode,
+    /// How long nothing may be drawn before the watchdog steps in, zero to leave rendering alone
+    pub(crate) gpu_watchdog: Duration,
+    /// Language of the overlay's own text
+    pub(crate) locale: Locale,
+    /// Time zone calendar days are counted in, the computer's own when None
+    pub(crate) timezone: Option<Tz>,
+    /// Frame rate limit, 0 for none
+    pub(crate) fps_cap: f32,
+    /// How long nothing has to move before rendering slows down, zero to never idle
+    pub(crate) idle_after: Duration,
+    pub(crate) idle_fps: f32,
+    /// How long chat has to be silent before the party dozes off
+    pub(crate) quiet_after: Duration,
+    pub(crate) quiet_fps: f32,
+    /// Multiplies the alpha of avatars, emotes and messages
+    pub(crate) opacity: f32,
+    /// Hidden avatars and speech bubbles kept around for reuse
+    pub(crate) avatar_pool_size: usize,
+    pub(crate) message_pool_size: usize,
+    /// Monitor index or part of its name, the primary monitor when unset
+    pub(crate) target_monitor: Option<String>,
+    /// Place the window here instead of maximizing it
+    pub(crate) window_geometry: Option<WindowGeometry>,
+    /// Place the window along an edge of the monitor instead of maximizing it
+    pub(crate) window_strip: Option<WindowStrip>,
+    pub(crate) capture_visibility: CaptureVisibility,
+    pub(crate) background_mode: BackgroundMode,
+    pub(crate) key_color: Color,
+    pub(crate) mirror_enabled: bool,
+    /// Monitor index or part of its name for the mirror window
+    pub(crate) mirror_monitor: Option<String>,
+    /// Zoom of the mirror, 1 fits the whole overlay in
+    pub(crate) mirror_scale: f32,
+    pub(crate) mirror_click_through: bool,
+    pub(crate) mirror_always_on_top: bool,
+    pub(crate) output: OutputBackend,
+    /// Name the output shows up as in OBS
+    pub(crate) output_name: String,
+    pub(crate) output_width: u32,
+    pub(crate) output_height: u32,
+    /// Only send the party to the output, without showing the window
+    pub(crate) output_hide_window: bool,
+    pub(crate) log_level: String,
+    pub(crate) log_file: Option<String>,
+    /// Size in bytes a log file grows to before a new one is started
+    pub(crate) log_max_size: u64,
+    pub(crate) log_files_kept: usize,
+    pub(crate) show_toasts: bool,
+    pub(crate) toast_duration: Duration,
+    pub(crate) debug_corner: ScreenCorner,
+    pub(crate) debug_font_url: String,
+    pub(crate) debug_font_size: f32,
+    /// Show the debug overlay without pressing its hotkey first
+    pub(crate) debug_show_on_start: bool,
+    /// Shed cosmetic effects one at a time while frames run long
+    pub(crate) frame_budget_enabled: bool,
+    /// Average frame time over which cosmetics are shed
+    pub(crate) frame_budget_slow: Duration,
+    /// Average frame time under which shed cosmetics come back
+    pub(crate) frame_budget_fast: Duration,
+    /// How long frames have to stay fast before each cosmetic comes back
+    pub(crate) frame_budget_restore: Duration,
+    /// Show the dot saying how the connection to Twitch chat is doing
+    pub(crate) connection_chip_enabled: bool,
+    pub(crate) connection_chip_corner: ScreenCorner,
+    /// How long the chip says what changed when the connection comes and goes
+    pub(crate) connection_chip_detail: Duration,
+    pub(crate) hotkey_toggle_visibility: Option<String>,
+    pub(crate) hotkey_toggle_click_through: Option<String>,
+    pub(crate) hotkey_clear_party: Option<String>,
+    pub(crate) hotkey_opacity_up: Option<String>,
+    pub(crate) hotkey_opacity_down: Option<String>,
+    pub(crate) hotkey_toggle_capture: Option<String>,
+    pub(crate) hotkey_toggle_debug: Option<String>,
+    pub(crate) hotkey_screenshot: Option<String>,
+    pub(crate) hotkey_screenshot_burst: Option<String>,
     pub(crate) avatar_url: String,
     pub(crate) random_avatars: bool,
     pub(crate) action_duration: Duration,
     pub(crate) wait_duration: Duration,
     pub(crate) avatar_move_speed: f32,
     pub(crate) user_despawn_time: Duration,
+    /// Most minawan at the party at once, the one quiet for longest leaves to make room for a
+    /// new chatter. Zero for no limit.
+    pub(crate) max_users: usize,
     pub(crate) edge_buffer: f32,
+    pub(crate) spawn_left_margin: Length,
+    pub(crate) spawn_right_margin: Length,
+    /// Replace the margins when set, avatars stay inside these ranges
+    pub(crate) spawn_ranges: Vec<SpawnRange>,
+    /// Parts of the window minawan walk around or behind, and messages keep clear of
+    pub(crate) stage_props: Vec<StageProp>,
+    /// Opacity of minawan walking behind a stage prop
+    pub(crate) stage_prop_behind_opacity: f32,
+    /// New minawan spawned per second at most, the rest wait their turn. Zero for no limit.
+    pub(crate) spawn_rate: f32,
+    /// Most new chatters waiting for their minawan, any more are left out
+    pub(crate) spawn_queue_max: usize,
+    /// Height of the avatars' feet above the bottom of the screen
+    pub(crate) floor_offset: f32,
     pub(crate) font_url: String,
     pub(crate) font_size: f32,
     pub(crate) emote_size_multiplier: f32,
     pub(crate) message_box_vertical_offset: f32,
     pub(crate) message_box_width: f32,
     pub(crate) message_despawn_time: Duration,
+    pub(crate) bubble_color: Color,
+    pub(crate) text_color: Color,
+    /// Draw a tail from message boxes to whoever is speaking
+    pub(crate) bubble_tail: bool,
+    /// Look of the message boxes, and how they come and go
+    pub(crate) bubble_theme: &'static BubbleTheme,
+    /// Play the sounds bubble themes make, like the rpg theme's typing blips
+    pub(crate) bubble_sounds: bool,
+    /// Count a chatter saying the same thing again on the message still shown instead of showing another
+    pub(crate) collapse_repeats: bool,
+    /// Whether repeats that only differ in case are counted
+    pub(crate) repeats_ignore_case: bool,
+    /// Near-opaque message boxes with outlined text, and no ambient tint
+    pub(crate) high_contrast: bool,
+    /// Fade minawan instead of making them hop, dance or shake, and leave out trails and flying particles
+    pub(crate) reduced_motion: bool,
+    pub(crate) twitch_client_id: Option<String>,
+    pub(crate) twitch_username: Option<String>,
+    pub(crate) twitch_token: Option<String>,
+    pub(crate) youtube_api_key: Option<String>,
+    pub(crate) youtube_live_chat_id: Option<String>,
+    pub(crate) youtube_video_id: Option<String>,
+    pub(crate) kick_channel: Option<String>,
+    pub(crate) kick_chatroom_id: Option<String>,
+    pub(crate) idle_when_offline: bool,
+    pub(crate) stream_poll_interval: Duration,
+    pub(crate) offline_user_despawn_time: Duration,
+    pub(crate) offline_fps: f32,
+    pub(crate) hype_train_bar: Option<ScreenEdge>,
+    pub(crate) hype_train_bar_thickness: f32,
+    pub(crate) hype_train_speed_per_level: f32,
+    pub(crate) show_shared_chat: bool,
+    pub(crate) shared_chat_border_color: Color,
+    pub(crate) follows_enabled: bool,
+    pub(crate) follow_wave_radius: f32,
+    pub(crate) follow_spam_threshold: usize,
+    /// Title of the channel point reward that makes the redeemer's minawan giant
+    pub(crate) giant_reward: Option<String>,
+    pub(crate) giant_duration: Duration,
+    pub(crate) giant_scale: f32,
+    /// Most of the screen's height a giant may cover
+    pub(crate) giant_max_screen_fraction: f32,
+    /// Title of the channel point reward that makes the redeemer's minawan smol, normal or chonk
+    pub(crate) size_reward: Option<String>,
+    /// How long the winner keeps the crown and banner
+    pub(crate) giveaway_duration: Duration,
+    /// How long the spotlight hops around before it lands
+    pub(crate) giveaway_roulette: Duration,
+    /// How recently someone has to have said the keyword to be in a giveaway
+    pub(crate) giveaway_keyword_window: Duration,
+    /// Folder the accessory images are in, each one is named after its accessory
+    pub(crate) accessory_folder: String,
+    /// Accessories there are images for
+    pub(crate) accessory_names: Vec<String>,
+    pub(crate) accessory_rewards: Vec<AccessoryReward>,
+    /// File of `username = MM-DD` lines, None to celebrate no birthdays
+    pub(crate) birthdays_file: Option<String>,
+    /// Accessory image worn on a birthday, None for just the cake
+    pub(crate) birthday_hat: Option<String>,
+    pub(crate) gift_bombs_enabled: bool,
+    /// Most boxes dropped for one gift bomb, bigger bombs still drop this many
+    pub(crate) gift_max_boxes: usize,
+    /// How long an opened box shows its recipient's name
+    pub(crate) gift_label_time: Duration,
+    pub(crate) boop_enabled: bool,
+    /// Chat command that starts a boop, the target's name follows it
+    pub(crate) boop_command: String,
+    /// How long a user has to wait between two boops
+    pub(crate) boop_cooldown: Duration,
+    /// How long a minawan tries to reach its target before giving up
+    pub(crate) boop_timeout: Duration,
+    /// Whether clicking a minawan while the overlay accepts clicks pets it
+    pub(crate) petting_enabled: bool,
+    /// How long the overlay keeps accepting clicks without the mouse moving, zero for as long as it's left on
+    pub(crate) petting_idle: Duration,
+    /// Particles spawned per second for each kind of weather falling
+    pub(crate) weather_density: f32,
+    /// How long weather falls when nothing says otherwise
+    pub(crate) weather_duration: Duration,
+    pub(crate) weather_max_particles: usize,
+    /// Sideways drift in pixels per second, negative blows left
+    pub(crate) weather_wind: f32,
+    /// Snow all through December
+    pub(crate) weather_seasonal_snow: bool,
+    pub(crate) weather_rain_reward: Option<String>,
+    pub(crate) weather_snow_reward: Option<String>,
+    pub(crate) weather_confetti_reward: Option<String>,
+    pub(crate) leaderboard_enabled: bool,
+    pub(crate) leaderboard_corner: ScreenCorner,
+    /// How many chatters the leaderboard lists
+    pub(crate) leaderboard_size: usize,
+    pub(crate) leaderboard_update_interval: Duration,
+    pub(crate) leaderboard_font_url: String,
+    pub(crate) leaderboard_font_size: f32,
+    /// Whether the top chatter at the party wears a crown
+    pub(crate) leaderboard_crown: bool,
+    /// Count messages for the day, across restarts, rather than since startup
+    pub(crate) leaderboard_daily: bool,
+    pub(crate) pronouns_enabled: bool,
+    /// How long looked up pronouns are kept on disk, zero to look them up every session
+    pub(crate) pronouns_cache_time: Duration,
+    /// Draw Twitch chatters' badges with Twitch's own images
+    pub(crate) badges_enabled: bool,
+    /// How long the list of badge images is kept on disk before it's fetched again
+    pub(crate) badges_cache_time: Duration,
+    pub(crate) streaks_enabled: bool,
+    /// Days in a row a user has to chat on before their minawan gets a flame
+    pub(crate) streak_threshold: u32,
+    pub(crate) greetings_enabled: bool,
+    /// Shown over a returning chatter's minawan the first time they chat each day
+    pub(crate) greeting_text: String,
+    /// Greetings of their own for some users, keyed like the party's users but in lowercase
+    pub(crate) greeting_custom: HashMap<String, String>,
+    /// More first messages of the day than this within RAID_WINDOW_SECS is taken for a raid
+    pub(crate) greeting_raid_threshold: usize,
+    pub(crate) greeting_raid_window: Duration,
+    pub(crate) reactions_enabled: bool,
+    /// Words and emotes, in lowercase, that make a minawan droop with a blue tint
+    pub(crate) reaction_sad: Vec<String>,
+    /// Words and emotes that make a minawan jump twice
+    pub(crate) reaction_hype: Vec<String>,
+    /// Words and emotes that float a heart up from a minawan
+    pub(crate) reaction_love: Vec<String>,
+    /// Least time between two reactions of the same user
+    pub(crate) reaction_cooldown: Duration,
+    pub(crate) timer_enabled: bool,
+    /// Chat command that starts a countdown, the broadcaster and moderators can use it
+    pub(crate) timer_command: String,
+    pub(crate) timer_corner: ScreenCorner,
+    pub(crate) timer_font_url: String,
+    pub(crate) timer_font_size: f32,
+    /// How long the badge flashes once the countdown reaches zero
+    pub(crate) timer_flash: Duration,
+    pub(crate) pyramids_enabled: bool,
+    /// Rows a pyramid has to reach at its widest to be celebrated
+    pub(crate) pyramid_min_height: usize,
+    /// Whether someone else chatting while a pyramid is built ruins it
+    pub(crate) pyramid_interruptions_break: bool,
+    /// How long the pyramid's emote rains down
+    pub(crate) pyramid_rain: Duration,
+    pub(crate) progress_enabled: bool,
+    /// Chat command that moves the progress bar, the broadcaster and moderators can use it
+    pub(crate) progress_command: String,
+    /// Top or bottom, the bar runs across the walk area
+    pub(crate) progress_edge: ScreenEdge,
+    pub(crate) progress_thickness: f32,
+    /// Names of the episodes or segments, the bar has one step per name
+    pub(crate) progress_segments: Vec<String>,
+    /// Whether recent chatters gather around the bar's marker
+    pub(crate) progress_cluster: bool,
+    pub(crate) marquee_enabled: bool,
+    /// Chat command that sets the marquee, the text follows it
+    pub(crate) marquee_command: String,
+    /// How fast text too wide for the window scrolls, in pixels per second
+    pub(crate) marquee_speed: f32,
+    pub(crate) marquee_background: Color,
+    pub(crate) rain_enabled: bool,
+    /// Chat command that makes an emote rain, moderators can use it
+    pub(crate) rain_command: String,
+    pub(crate) rain_duration: Duration,
+    /// Most emotes falling at once
+    pub(crate) rain_max_drops: usize,
+    /// Where screenshots are saved, relative to the working directory unless absolute
+    pub(crate) screenshot_folder: String,
+    /// Screenshots a burst takes over one second
+    pub(crate) screenshot_burst_frames: u32,
+    pub(crate) polls_enabled: bool,
+    /// How long the results stay up after a poll ends
+    pub(crate) poll_results_time: Duration,
+    pub(crate) ambient_enabled: bool,
+    /// When the day, evening and night tints start
+    pub(crate) ambient_morning: NaiveTime,
+    pub(crate) ambient_evening: NaiveTime,
+    pub(crate) ambient_night: NaiveTime,
+    /// How long the change from one tint to the next takes, centred on its start
+    pub(crate) ambient_transition: Duration,
+    /// How far the evening and night tints go, 0 leaves the colours alone
+    pub(crate) ambient_strength: f32,
+    /// Time of day to tint for instead of the clock, for trying the tints out
+    pub(crate) ambient_time_override: Option<NaiveTime>,
+    pub(crate) floor_glow_enabled: bool,
+    /// How far the glow reaches up from the floor
+    pub(crate) floor_glow_height: f32,
+    /// Messages per minute are counted over this much of the latest chat
+    pub(crate) floor_glow_window: Duration,
+    /// Messages per minute at or under which the glow is all QUIET_COLOR
+    pub(crate) floor_glow_quiet_rate: f32,
+    /// Messages per minute from which the glow is all BUSY_COLOR
+    pub(crate) floor_glow_busy_rate: f32,
+    pub(crate) floor_glow_quiet_color: Color,
+    pub(crate) floor_glow_busy_color: Color,
+    pub(crate) campfire_enabled: bool,
+    /// Image of the campfire, relative to the assets folder
+    pub(crate) campfire_image: String,
+    /// Where the campfire stands, from the left edge of the window
+    pub(crate) campfire_x: Length,
+    /// How long a chatter has to be quiet before their minawan wanders over to the campfire
+    pub(crate) campfire_gather_after: Duration,
+    /// Room each minawan keeps around the campfire
+    pub(crate) campfire_spacing: f32,
+    pub(crate) footprints_enabled: bool,
+    /// Image of a footprint, relative to the assets folder
+    pub(crate) footprint_image: String,
+    /// Colour the footprint image is multiplied by, its alpha is where the fading starts
+    pub(crate) footprint_tint: Color,
+    /// How long a footprint takes to fade away
+    pub(crate) footprint_lifetime: Duration,
+    /// Pixels a minawan walks between two footprints
+    pub(crate) footprint_stride: f32,
+    /// Most footprints on screen at once, walking minawan leave none while there are this many
+    pub(crate) footprint_max: usize,
+    /// No footprints are left while more users than this are at the party
+    pub(crate) footprint_max_users: usize,
+    /// Whether the top cheerer wears the bits crown
+    pub(crate) bits_enabled: bool,
+    /// Count bits for the calendar month across restarts instead of since startup
+    pub(crate) bits_monthly: bool,
+    pub(crate) lurkers_enabled: bool,
+    /// Most lurkers shown as ghosts at once
+    pub(crate) lurker_max_ghosts: usize,
+    /// How often one ghost makes room for another lurker
+    pub(crate) lurker_rotation: Duration,
+    /// Channel point reward that has the redeemer hold up a sign with their text
+    pub(crate) sign_reward: Option<String>,
+    /// How long a sign stays up
+    pub(crate) sign_duration: Duration,
+    /// Longest text a sign takes, in characters
+    pub(crate) sign_max_length: usize,
+    /// Words starred out on signs, compared without case or punctuation
+    pub(crate) sign_blocked_words: Vec<String>,
+    /// Recurring and one-off events, checked every second
+    pub(crate) schedule: Vec<ScheduledEvent>,
+    pub(crate) race_enabled: bool,
+    /// What the broadcaster types to open a race
+    pub(crate) race_command: String,
+    /// What viewers type to join one
+    pub(crate) race_join_command: String,
+    pub(crate) race_join_time: Duration,
+    pub(crate) race_max_racers: usize,
+    /// Least time from the end of one race to the start of the next
+    pub(crate) race_cooldown: Duration,
+    pub(crate) download_max_concurrent: usize,
+    /// Least time between two downloads from the same host
+    pub(crate) download_host_delay: Duration,
+    pub(crate) download_max_in_flight_kb: usize,
+    pub(crate) api_enabled: bool,
+    /// Address the API listens on, loopback unless other machines should reach it
+    pub(crate) api_bind: IpAddr,
+    pub(crate) api_port: u16,
+    /// Clients have to connect with `?token=` set to this, anyone may connect when it's unset
+    pub(crate) api_token: Option<String>,
+    /// Web pages that may connect, as `scheme://host:port`. Browsers say which page is
+    /// connecting and any other page is turned away, programs that don't say may connect.
+    pub(crate) api_allowed_origins: Vec<String>,
+    pub(crate) stats_page_enabled: bool,
+    /// Port the stats page is served on, always on loopback
+    pub(crate) stats_page_port: u16,
+    /// How many of the latest messages the stats page lists
+    pub(crate) stats_page_recent_messages: usize,
+    /// How often the stats are published and the page fetches them
+    pub(crate) stats_page_refresh: Duration,
+    pub(crate) scripts_enabled: bool,
+    pub(crate) script_folder: String,
+    /// Most operations one hook call may take before it's stopped
+    pub(crate) script_max_operations: u64,
+    pub(crate) snapshot_enabled: bool,
+    /// Snapshots older than this are ignored, the party has moved on
+    pub(crate) snapshot_max_age: Duration,
+    /// How often the party is saved while running, only on exit when zero
+    pub(crate) snapshot_autosave: Duration,
+    /// Time between two restored minawan appearing
+    pub(crate) snapshot_stagger: Duration,
+    pub(crate) command_prefix: String,
+    pub(crate) admin_users: Vec<String>,
+    /// Remove the minawan of chatters who are timed out or banned
+    pub(crate) moderation_enabled: bool,
+    /// How long after a timeout ends a chatter's minawan comes back where it stood
+    pub(crate) moderation_return_grace: Duration,
+    /// Said in Twitch chat when a giveaway winner is picked, None to say nothing
+    pub(crate) reply_giveaway_winner: Option<String>,
+    /// Said in Twitch chat to thank a raid, None to say nothing
+    pub(crate) reply_raid: Option<String>,
+    /// Said in Twitch chat when someone sends `party_command`, None to say nothing
+    pub(crate) reply_party: Option<String>,
+    pub(crate) party_command: String,
+    /// Time after answering the party command before it's answered again
+    pub(crate) party_cooldown: Duration,
+    /// AppState keys of users whose minawan never leave, as written in the config
+    pub(crate) pinned_users: Vec<String>,
+    pub(crate) pin_broadcaster: bool,
+    /// Bring pinned minawan to the party on startup, before they chat
+    pub(crate) pinned_spawn_on_start: bool,
+    pub(crate) viewer_widget_enabled: bool,
+    pub(crate) viewer_widget_corner: ScreenCorner,
+    pub(crate) viewer_widget_font_url: String,
+    pub(crate) viewer_widget_font_size: f32,
+    pub(crate) viewer_widget_update_interval: Duration,
+    pub(crate) viewer_widget_show_viewers: bool,
+    pub(crate) viewer_widget_show_minawan: bool,
 }
 
 impl Config {
@@ -39,145 +794,3357 @@ impl Config {
     pub(crate) fn line_space(&self) -> f32 {
         self.font_height() * 0.43
     }
+
+    /// Change a value while running, only keys that are safe to change at runtime are allowed
+    pub(crate) fn set_runtime_value(&mut self, key: &str, value: &str) -> Result<(), String> {
+        fn parse<T: FromStr>(value: &str) -> Result<T, String> {
+            value
+                .parse::<T>()
+                .map_err(|_| format!("invalid value {}", value))
+        }
+
+        match key.to_uppercase().as_str() {
+            "ACTION_DURATION_MILIS" => self.action_duration = Duration::from_millis(parse(value)?),
+            "WAIT_DURATION_MILIS" => self.wait_duration = Duration::from_millis(parse(value)?),
+            "AVATAR_MOVE_SPEED" => self.avatar_move_speed = parse(value)?,
+            "USER_DESPAWN_TIME_SECS" => self.user_despawn_time = Duration::from_secs(parse(value)?),
+            "EDGE_BUFFER" => self.edge_buffer = parse(value)?,
+            "FONT_SIZE" => self.font_size = parse(value)?,
+            "EMOTE_SIZE_MULTIPLIER" => self.emote_size_multiplier = parse(value)?,
+            "MESSAGE_BOX_WIDTH" => self.message_box_width = parse(value)?,
+            "MESSAGE_DESPAWN_TIME_MILIS" => {
+                self.message_despawn_time = Duration::from_millis(parse(value)?)
+            }
+            _ => return Err("key cannot be changed at runtime".to_string()),
+        }
+        Ok(())
+    }
+
+    /// Current values of the keys `set_runtime_value` can change
+    pub(crate) fn runtime_values(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("ACTION_DURATION_MILIS", self.action_duration.as_millis().to_string()),
+            ("WAIT_DURATION_MILIS", self.wait_duration.as_millis().to_string()),
+            ("AVATAR_MOVE_SPEED", self.avatar_move_speed.to_string()),
+            ("USER_DESPAWN_TIME_SECS", self.user_despawn_time.as_secs().to_string()),
+            ("EDGE_BUFFER", self.edge_buffer.to_string()),
+            ("FONT_SIZE", self.font_size.to_string()),
+            ("EMOTE_SIZE_MULTIPLIER", self.emote_size_multiplier.to_string()),
+            ("MESSAGE_BOX_WIDTH", self.message_box_width.to_string()),
+            ("MESSAGE_DESPAWN_TIME_MILIS", self.message_despawn_time.as_millis().to_string()),
+        ]
+    }
+
+    /// Take the values from a reloaded config that are safe to change while running.
+    /// Returns the keys that were applied and the changed keys that need a restart.
+    pub(crate) fn apply_reloaded(&mut self, new: &Config) -> (Vec<&'static str>, Vec<&'static str>) {
+        let mut applied = vec![];
+        let mut needs_restart = vec![];
+
+        macro_rules! runtime {
+            ($($field:ident => $key:literal),* $(,)?) => {
+                $(if self.$field != new.$field {
+                    self.$field = new.$field.clone();
+                    applied.push($key);
+                })*
+            };
+        }
+        macro_rules! restart {
+            ($($field:ident => $key:literal),* $(,)?) => {
+                $(if self.$field != new.$field {
+                    needs_restart.push($key);
+                })*
+            };
+        }
+
+        runtime!(
+            dedupe_window => "DEDUPE_WINDOW_SECS",
+            profile => "PROFILE",
+            scale => "SCALE",
+            present_mode => "PRESENT_MODE",
+            locale => "LOCALE",
+            timezone => "TIMEZONE",
+            fps_cap => "FPS_CAP",
+            idle_after => "IDLE_AFTER_SECS",
+            idle_fps => "IDLE_FPS",
+            quiet_after => "QUIET_AFTER_SECS",
+            quiet_fps => "QUIET_FPS",
+            opacity => "OPACITY",
+            avatar_pool_size => "AVATAR_POOL_SIZE",
+            message_pool_size => "MESSAGE_POOL_SIZE",
+            target_monitor => "TARGET_MONITOR",
+            window_geometry => "WINDOW_X/Y/WIDTH/HEIGHT",
+            window_strip => "STRIP_HEIGHT/STRIP_EDGE",
+            capture_visibility => "CAPTURE_VISIBILITY",
+            mirror_monitor => "MONITOR",
+            mirror_scale => "SCALE",
+            mirror_click_through => "CLICK_THROUGH",
+            mirror_always_on_top => "ALWAYS_ON_TOP",
+            log_level => "LOG_LEVEL",
+            show_toasts => "SHOW_TOASTS",
+            toast_duration => "TOAST_SECS",
+            script_max_operations => "MAX_OPERATIONS",
+            snapshot_autosave => "AUTOSAVE_SECS",
+            snapshot_stagger => "STAGGER_MILIS",
+            avatar_url => "AVATAR_URL",
+            random_avatars => "RANDOM_AVATARS",
+            action_duration => "ACTION_DURATION_MILIS",
+            wait_duration => "WAIT_DURATION_MILIS",
+            avatar_move_speed => "AVATAR_MOVE_SPEED",
+            user_despawn_time => "USER_DESPAWN_TIME_SECS",
+            max_users => "MAX_USERS",
+            edge_buffer => "EDGE_BUFFER",
+            spawn_left_margin => "LEFT_MARGIN",
+            spawn_right_margin => "RIGHT_MARGIN",
+            spawn_ranges => "RANGES",
+            stage_props => "REGIONS",
+            stage_prop_behind_opacity => "BEHIND_OPACITY",
+            spawn_rate => "SPAWNS_PER_SEC",
+            spawn_queue_max => "MAX_QUEUED",
+            floor_offset => "FLOOR_OFFSET",
+            font_url => "FONT_URL",
+            font_size => "FONT_SIZE",
+            emote_size_multiplier => "EMOTE_SIZE_MULTIPLIER",
+            message_box_vertical_offset => "MESSAGE_BOX_VERTICAL_OFFSET",
+            message_box_width => "MESSAGE_BOX_WIDTH",
+            message_despawn_time => "MESSAGE_DESPAWN_TIME_MILIS",
+            bubble_color => "BUBBLE_COLOR",
+            text_color => "TEXT_COLOR",
+            bubble_tail => "BUBBLE_TAIL",
+            bubble_theme => "BUBBLE_THEME",
+            bubble_sounds => "BUBBLE_SOUNDS",
+            collapse_repeats => "COLLAPSE_REPEATS",
+            repeats_ignore_case => "REPEATS_IGNORE_CASE",
+            high_contrast => "HIGH_CONTRAST",
+            reduced_motion => "REDUCED_MOTION",
+            offline_user_despawn_time => "OFFLINE_USER_DESPAWN_TIME_SECS",
+            offline_fps => "OFFLINE_FPS",
+            hype_train_bar_thickness => "BAR_THICKNESS",
+            hype_train_speed_per_level => "SPEED_PER_LEVEL",
+            show_shared_chat => "SHOW_SHARED_CHAT",
+            shared_chat_border_color => "BORDER_COLOR",
+            follow_wave_radius => "WAVE_RADIUS",
+            follow_spam_threshold => "SPAM_THRESHOLD",
+            giant_duration => "DURATION_SECS",
+            giant_scale => "SCALE",
+            giant_max_screen_fraction => "MAX_SCREEN_FRACTION",
+            giveaway_duration => "DURATION_SECS",
+            giveaway_roulette => "ROULETTE_SECS",
+            giveaway_keyword_window => "KEYWORD_WINDOW_SECS",
+            accessory_folder => "FOLDER",
+            accessory_names => "ACCESSORIES",
+            birthdays_file => "FILE",
+            birthday_hat => "HAT",
+            gift_bombs_enabled => "ENABLED",
+            gift_max_boxes => "MAX_BOXES",
+            gift_label_time => "LABEL_SECS",
+            boop_enabled => "ENABLED",
+            boop_command => "COMMAND",
+            boop_cooldown => "COOLDOWN_SECS",
+            boop_timeout => "TIMEOUT_SECS",
+            petting_enabled => "ENABLED",
+            petting_idle => "IDLE_SECS",
+            weather_density => "DENSITY",
+            weather_duration => "DURATION_SECS",
+            weather_max_particles => "MAX_PARTICLES",
+            weather_wind => "WIND",
+            weather_seasonal_snow => "SEASONAL_SNOW",
+            leaderboard_enabled => "ENABLED",
+            leaderboard_corner => "CORNER",
+            leaderboard_size => "SIZE",
+            leaderboard_update_interval => "UPDATE_SECS",
+            leaderboard_font_url => "FONT_URL",
+            leaderboard_font_size => "FONT_SIZE",
+            leaderboard_crown => "CROWN",
+            leaderboard_daily => "DAILY",
+            streaks_enabled => "ENABLED",
+            streak_threshold => "THRESHOLD",
+            greetings_enabled => "ENABLED",
+            greeting_text => "TEXT",
+            greeting_custom => "CUSTOM",
+            greeting_raid_threshold => "RAID_THRESHOLD",
+            greeting_raid_window => "RAID_WINDOW_SECS",
+            reactions_enabled => "ENABLED",
+            reaction_sad => "SAD",
+            reaction_hype => "HYPE",
+            reaction_love => "LOVE",
+            reaction_cooldown => "COOLDOWN_SECS",
+            timer_enabled => "ENABLED",
+            timer_command => "COMMAND",
+            timer_corner => "CORNER",
+            timer_font_url => "FONT_URL",
+            timer_font_size => "FONT_SIZE",
+            timer_flash => "FLASH_SECS",
+            pyramids_enabled => "ENABLED",
+            pyramid_min_height => "MIN_HEIGHT",
+            pyramid_interruptions_break => "INTERRUPTIONS_BREAK",
+            pyramid_rain => "RAIN_SECS",
+            progress_enabled => "ENABLED",
+            progress_command => "COMMAND",
+            progress_edge => "EDGE",
+            progress_thickness => "THICKNESS",
+            progress_segments => "SEGMENTS",
+            progress_cluster => "CLUSTER",
+            marquee_enabled => "ENABLED",
+            marquee_command => "COMMAND",
+            marquee_speed => "SPEED",
+            marquee_background => "BACKGROUND_COLOR",
+            rain_enabled => "ENABLED",
+            rain_command => "COMMAND",
+            rain_duration => "DURATION_SECS",
+            rain_max_drops => "MAX_DROPS",
+            screenshot_folder => "FOLDER",
+            screenshot_burst_frames => "BURST_FRAMES",
+            poll_results_time => "RESULTS_SECS",
+            ambient_enabled => "ENABLED",
+            ambient_morning => "MORNING",
+            ambient_evening => "EVENING",
+            ambient_night => "NIGHT",
+            ambient_transition => "TRANSITION_MINUTES",
+            ambient_strength => "STRENGTH",
+            ambient_time_override => "TIME_OVERRIDE",
+            floor_glow_enabled => "ENABLED",
+            floor_glow_height => "HEIGHT",
+            floor_glow_window => "WINDOW_SECS",
+            floor_glow_quiet_rate => "QUIET_RATE",
+            floor_glow_busy_rate => "BUSY_RATE",
+            floor_glow_quiet_color => "QUIET_COLOR",
+            floor_glow_busy_color => "BUSY_COLOR",
+            campfire_enabled => "ENABLED",
+            campfire_image => "IMAGE",
+            campfire_x => "X",
+            campfire_gather_after => "GATHER_AFTER_SECS",
+            campfire_spacing => "SPACING",
+            footprints_enabled => "ENABLED",
+            footprint_image => "IMAGE",
+            footprint_tint => "TINT",
+            footprint_lifetime => "LIFETIME_MILIS",
+            footprint_stride => "STRIDE",
+            footprint_max => "MAX_FOOTPRINTS",
+            footprint_max_users => "MAX_USERS",
+            bits_enabled => "ENABLED",
+            bits_monthly => "MONTHLY",
+            lurker_max_ghosts => "MAX_GHOSTS",
+            lurker_rotation => "ROTATE_SECS",
+            sign_duration => "DURATION_SECS",
+            sign_max_length => "MAX_LENGTH",
+            sign_blocked_words => "BLOCKED_WORDS",
+            schedule => "EVENTS",
+            race_enabled => "ENABLED",
+            race_command => "COMMAND",
+            race_join_command => "JOIN_COMMAND",
+            race_join_time => "JOIN_SECS",
+            race_max_racers => "MAX_RACERS",
+            race_cooldown => "COOLDOWN_SECS",
+            stats_page_recent_messages => "RECENT_MESSAGES",
+            stats_page_refresh => "REFRESH_SECS",
+            command_prefix => "PREFIX",
+            admin_users => "ADMINS",
+            moderation_enabled => "ENABLED",
+            moderation_return_grace => "RETURN_GRACE_SECS",
+            reply_giveaway_winner => "GIVEAWAY_WINNER",
+            reply_party => "PARTY",
+            party_command => "PARTY_COMMAND",
+            party_cooldown => "PARTY_COOLDOWN_SECS",
+            pinned_users => "USERS",
+            pin_broadcaster => "BROADCASTER",
+            pinned_spawn_on_start => "SPAWN_ON_START",
+            viewer_widget_update_interval => "UPDATE_SECS",
+            viewer_widget_show_viewers => "SHOW_VIEWERS",
+            viewer_widget_show_minawan => "SHOW_MINAWAN",
+            connection_chip_detail => "DETAIL_SECS",
+            frame_budget_enabled => "ENABLED",
+            frame_budget_slow => "SLOW_FRAME_MILIS",
+            frame_budget_fast => "FAST_FRAME_MILIS",
+            frame_budget_restore => "RESTORE_SECS",
+        );
+        restart!(
+            backend => "BACKEND",
+            gpu_watchdog => "GPU_WATCHDOG_SECS",
+            log_file => "LOG_FILE",
+            background_mode => "BACKGROUND_MODE",
+            key_color => "KEY_COLOR",
+            mirror_enabled => "ENABLED",
+            output => "OUTPUT",
+            output_name => "SOURCE_NAME",
+            output_width => "WIDTH",
+            output_height => "HEIGHT",
+            output_hide_window => "HIDE_WINDOW",
+            log_max_size => "LOG_MAX_SIZE_KB",
+            log_files_kept => "LOG_FILES_KEPT",
+            hotkey_toggle_visibility => "TOGGLE_VISIBILITY",
+            hotkey_toggle_click_through => "TOGGLE_CLICK_THROUGH",
+            hotkey_clear_party => "CLEAR_PARTY",
+            hotkey_opacity_up => "OPACITY_UP",
+            hotkey_opacity_down => "OPACITY_DOWN",
+            hotkey_toggle_capture => "TOGGLE_CAPTURE",
+            hotkey_toggle_debug => "TOGGLE_DEBUG",
+            hotkey_screenshot => "SCREENSHOT",
+            hotkey_screenshot_burst => "SCREENSHOT_BURST",
+            channel_name => "CHANNEL_NAME",
+            channel_id => "CHANNEL_ID",
+            chat_sources => "SOURCES",
+            twitch_client_id => "CLIENT_ID",
+            twitch_username => "USERNAME",
+            twitch_token => "OAUTH_TOKEN",
+            youtube_api_key => "API_KEY",
+            youtube_live_chat_id => "LIVE_CHAT_ID",
+            youtube_video_id => "VIDEO_ID",
+            kick_channel => "CHANNEL",
+            kick_chatroom_id => "CHATROOM_ID",
+            idle_when_offline => "IDLE_WHEN_OFFLINE",
+            stream_poll_interval => "STREAM_POLL_SECS",
+            hype_train_bar => "BAR_EDGE",
+            follows_enabled => "ENABLED",
+            polls_enabled => "ENABLED",
+            lurkers_enabled => "ENABLED",
+            pronouns_enabled => "ENABLED",
+            pronouns_cache_time => "CACHE_HOURS",
+            badges_enabled => "ENABLED",
+            badges_cache_time => "CACHE_HOURS",
+            giant_reward => "REWARD",
+            size_reward => "REWARD",
+            accessory_rewards => "REWARDS",
+            weather_rain_reward => "RAIN_REWARD",
+            weather_snow_reward => "SNOW_REWARD",
+            weather_confetti_reward => "CONFETTI_REWARD",
+            sign_reward => "REWARD",
+            download_max_concurrent => "MAX_CONCURRENT",
+            download_host_delay => "HOST_DELAY_MILIS",
+            download_max_in_flight_kb => "MAX_IN_FLIGHT_KB",
+            api_enabled => "ENABLED",
+            api_bind => "BIND",
+            api_port => "PORT",
+            api_token => "TOKEN",
+            api_allowed_origins => "ALLOWED_ORIGINS",
+            stats_page_enabled => "ENABLED",
+            stats_page_port => "PORT",
+            scripts_enabled => "ENABLED",
+            script_folder => "FOLDER",
+            snapshot_enabled => "ENABLED",
+            snapshot_max_age => "MAX_AGE_SECS",
+            viewer_widget_enabled => "ENABLED",
+            viewer_widget_corner => "CORNER",
+            viewer_widget_font_url => "FONT_URL",
+            viewer_widget_font_size => "FONT_SIZE",
+            debug_corner => "CORNER",
+            debug_font_url => "FONT_URL",
+            debug_font_size => "FONT_SIZE",
+            debug_show_on_start => "SHOW_ON_START",
+            connection_chip_enabled => "ENABLED",
+            connection_chip_corner => "CORNER",
+            reply_raid => "RAID",
+        );
+
+        (applied, needs_restart)
+    }
+
+    /// Where avatars may be for a viewport of the given width, as sorted world x ranges.
+    /// Percentages are worked out from the current width so they follow resizes. Stage props
+    /// minawan don't walk behind are walls, unless they'd leave nowhere to walk.
+    pub(crate) fn walk_ranges(&self, width: f32) -> Vec<(f32, f32)> {
+        let ranges = self.area_ranges(width);
+        let walled = self
+            .stage_props
+            .iter()
+            .filter(|prop| !prop.behind)
+            .fold(ranges.clone(), |ranges, prop| cut_ranges(&ranges, prop.x_range(width)));
+        if walled.is_empty() {
+            ranges
+        } else {
+            walled
+        }
+    }
+
+    /// Where new minawan may appear, which is anywhere they can walk but not behind a stage prop
+    pub(crate) fn arrival_ranges(&self, width: f32) -> Vec<(f32, f32)> {
+        let ranges = self.walk_ranges(width);
+        let open = self
+            .stage_props
+            .iter()
+            .fold(ranges.clone(), |ranges, prop| cut_ranges(&ranges, prop.x_range(width)));
+        if open.is_empty() {
+            ranges
+        } else {
+            open
+        }
+    }
+
+    /// The spawn area's ranges, before stage props are taken out
+    fn area_ranges(&self, width: f32) -> Vec<(f32, f32)> {
+        let left = -width / 2.0;
+        let mut ranges: Vec<(f32, f32)> = if self.spawn_ranges.is_empty() {
+            vec![(
+                left + self.spawn_left_margin.resolve(width),
+                left + width - self.spawn_right_margin.resolve(width),
+            )]
+        } else {
+            self.spawn_ranges
+                .iter()
+                .map(|range| (left + range.start.resolve(width), left + range.end.resolve(width)))
+                .collect()
+        };
+        ranges.retain(|(start, end)| start < end);
+        ranges.sort_by(|a, b| a.0.total_cmp(&b.0));
+        // Margins bigger than the window leave nothing, use the whole width rather than lose everyone
+        if ranges.is_empty() {
+            ranges.push((left, left + width));
+        }
+        ranges
+    }
+
+    /// World y of the avatars' feet for a viewport of the given height
+    pub(crate) fn floor_y(&self, height: f32) -> f32 {
+        -(height / 2.0) + self.floor_offset
+    }
+
+    /// AppState keys of the pinned users, the broadcaster first
+    pub(crate) fn pinned_keys(&self) -> Vec<String> {
+        let broadcaster = (self.pin_broadcaster && !self.channel_name.is_empty()).then(|| self.channel_name.clone());
+        let mut keys: Vec<String> = broadcaster.into_iter().chain(self.pinned_users.iter().cloned()).collect();
+        let mut seen = vec![];
+        keys.retain(|key| {
+            let new = !seen.iter().any(|seen: &String| seen.eq_ignore_ascii_case(key));
+            seen.push(key.clone());
+            new
+        });
+        keys
+    }
+
+    /// Whether a user's minawan stays at the party however long they're quiet. Twitch keys are
+    /// display names, so they match in any case.
+    pub(crate) fn is_pinned(&self, key: &str) -> bool {
+        (self.pin_broadcaster && key.eq_ignore_ascii_case(&self.channel_name))
+            || self.pinned_users.iter().any(|pinned| pinned.eq_ignore_ascii_case(key))
+    }
+
+    /// A freshly generated config has no channel to watch yet
+    pub(crate) fn setup_needed(&self) -> bool {
+        self.channel_name.is_empty()
+    }
+
+    /// Today's date where the streamer is
+    pub(crate) fn today(&self) -> NaiveDate {
+        match self.timezone {
+            Some(timezone) => Utc::now().with_timezone(&timezone).date_naive(),
+            None => Local::now().date_naive(),
+        }
+    }
+
+    /// The time of day where the streamer is
+    pub(crate) fn local_time(&self) -> NaiveTime {
+        match self.timezone {
+            Some(timezone) => Utc::now().with_timezone(&timezone).time(),
+            None => Local::now().time(),
+        }
+    }
+
+    /// The date and time where the streamer is
+    pub(crate) fn local_now(&self) -> NaiveDateTime {
+        match self.timezone {
+            Some(timezone) => Utc::now().with_timezone(&timezone).naive_local(),
+            None => Local::now().naive_local(),
+        }
+    }
+
+    /// Whether anything reacts to hype trains, if not there's no need to subscribe
+    pub(crate) fn hype_train_enabled(&self) -> bool {
+        self.hype_train_bar.is_some() || self.hype_train_speed_per_level != 0.0
+    }
+}
+
+/// config.toml as written on disk, every key has a serde default so any of them can be left out
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "PascalCase")]
+struct ConfigFile {
+    channel: ChannelSection,
+    chat: ChatSection,
+    twitch: TwitchSection,
+    #[serde(rename = "YouTube")]
+    youtube: YouTubeSection,
+    kick: KickSection,
+    commands: CommandsSection,
+    moderation: ModerationSection,
+    replies: RepliesSection,
+    pinned: PinnedSection,
+    stream: StreamSection,
+    hype_train: HypeTrainSection,
+    follows: FollowsSection,
+    giant: GiantSection,
+    sizes: SizesSection,
+    giveaway: GiveawaySection,
+    accessories: AccessoriesSection,
+    birthdays: BirthdaysSection,
+    gifts: GiftsSection,
+    boop: BoopSection,
+    petting: PettingSection,
+    weather: WeatherSection,
+    streaks: StreaksSection,
+    greetings: GreetingsSection,
+    reactions: ReactionsSection,
+    pronouns: PronounsSection,
+    badges: BadgesSection,
+    leaderboard: LeaderboardSection,
+    timer: TimerSection,
+    pyramids: PyramidsSection,
+    progress: ProgressSection,
+    marquee: MarqueeSection,
+    rain: RainSection,
+    screenshots: ScreenshotsSection,
+    polls: PollsSection,
+    ambient: AmbientSection,
+    floor_glow: FloorGlowSection,
+    campfire: CampfireSection,
+    footprints: FootprintsSection,
+    bits: BitsSection,
+    lurkers: LurkersSection,
+    signs: SignsSection,
+    schedule: ScheduleSection,
+    race: RaceSection,
+    downloads: DownloadsSection,
+    api: ApiSection,
+    stats_page: StatsPageSection,
+    scripts: ScriptsSection,
+    snapshot: SnapshotSection,
+    viewer_count: ViewerCountSection,
+    shared_chat: SharedChatSection,
+    general: GeneralSection,
+    window: WindowSection,
+    mirror: MirrorSection,
+    output: OutputSection,
+    logging: LoggingSection,
+    debug: DebugSection,
+    frame_budget: FrameBudgetSection,
+    connection: ConnectionSection,
+    spawn_area: SpawnAreaSection,
+    stage_props: StagePropsSection,
+    spawn_queue: SpawnQueueSection,
+    hotkeys: HotkeysSection,
+    avatars: AvatarsSection,
+    messages: MessagesSection,
+    accessibility: AccessibilitySection,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct ChannelSection {
+    channel_name: String,
+    channel_id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct ChatSection {
+    sources: Vec<String>,
+    dedupe_window_secs: u64,
+}
+
+impl Default for ChatSection {
+    fn default() -> Self {
+        Self {
+            sources: vec!["twitch".to_string()],
+            dedupe_window_secs: 60,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct TwitchSection {
+    username: String,
+    client_id: String,
+    oauth_token: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct YouTubeSection {
+    api_key: String,
+    live_chat_id: String,
+    video_id: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct KickSection {
+    channel: String,
+    chatroom_id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct CommandsSection {
+    prefix: String,
+    admins: Vec<String>,
+}
+
+impl Default for CommandsSection {
+    fn default() -> Self {
+        Self {
+            prefix: "!!".to_string(),
+            admins: vec![],
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct ModerationSection {
+    enabled: bool,
+    return_grace_secs: u64,
+}
+
+impl Default for ModerationSection {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            return_grace_secs: 600,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct RepliesSection {
+    giveaway_winner: String,
+    raid: String,
+    party: String,
+    party_command: String,
+    party_cooldown_secs: u64,
+}
+
+impl Default for RepliesSection {
+    fn default() -> Self {
+        Self {
+            giveaway_winner: String::new(),
+            raid: String::new(),
+            party: String::new(),
+            party_command: "!party".to_string(),
+            party_cooldown_secs: 30,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct PinnedSection {
+    users: Vec<String>,
+    broadcaster: bool,
+    spawn_on_start: bool,
+}
+
+impl Default for PinnedSection {
+    fn default() -> Self {
+        Self {
+            users: vec![],
+            broadcaster: true,
+            spawn_on_start: false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct StreamSection {
+    idle_when_offline: bool,
+    stream_poll_secs: u64,
+    offline_user_despawn_time_secs: u64,
+    offline_fps: f32,
+}
+
+impl Default for StreamSection {
+    fn default() -> Self {
+        Self {
+            idle_when_offline: false,
+            stream_poll_secs: 60,
+            offline_user_despawn_time_secs: 120,
+            offline_fps: 1.0,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct HypeTrainSection {
+    bar_edge: String,
+    bar_thickness: f32,
+    speed_per_level: f32,
+}
+
+impl Default for HypeTrainSection {
+    fn default() -> Self {
+        Self {
+            bar_edge: "bottom".to_string(),
+            bar_thickness: 8.0,
+            speed_per_level: 0.25,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct FollowsSection {
+    enabled: bool,
+    wave_radius: f32,
+    spam_threshold: usize,
+}
+
+impl Default for FollowsSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            wave_radius: 150.0,
+            spam_threshold: 5,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct GiantSection {
+    reward: String,
+    duration_secs: u64,
+    scale: f32,
+    max_screen_fraction: f32,
+}
+
+impl Default for GiantSection {
+    fn default() -> Self {
+        Self {
+            reward: "".to_string(),
+            duration_secs: 30,
+            scale: 4.0,
+            max_screen_fraction: 0.5,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct SizesSection {
+    reward: String,
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct GiveawaySection {
+    duration_secs: u64,
+    roulette_secs: u64,
+    keyword_window_secs: u64,
+}
+
+impl Default for GiveawaySection {
+    fn default() -> Self {
+        Self {
+            duration_secs: 30,
+            roulette_secs: 5,
+            keyword_window_secs: 300,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct BoopSection {
+    enabled: bool,
+    command: String,
+    cooldown_secs: u64,
+    timeout_secs: u64,
+}
+
+impl Default for BoopSection {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            command: "!boop".to_string(),
+            cooldown_secs: 30,
+            timeout_secs: 10,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct PettingSection {
+    enabled: bool,
+    idle_secs: u64,
+}
+
+impl Default for PettingSection {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            idle_secs: 60,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct AccessoriesSection {
+    folder: String,
+    accessories: Vec<String>,
+    rewards: Vec<String>,
+}
+
+impl Default for AccessoriesSection {
+    fn default() -> Self {
+        Self {
+            folder: "accessories".to_string(),
+            accessories: vec!["hat".to_string(), "sunglasses".to_string(), "scarf".to_string()],
+            rewards: vec![],
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct BirthdaysSection {
+    file: String,
+    hat: String,
+}
+
+impl Default for BirthdaysSection {
+    fn default() -> Self {
+        Self {
+            file: String::new(),
+            hat: "party_hat".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct GiftsSection {
+    enabled: bool,
+    max_boxes: usize,
+    label_secs: u64,
+}
+
+impl Default for GiftsSection {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_boxes: 20,
+            label_secs: 2,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct WeatherSection {
+    density: f32,
+    duration_secs: u64,
+    max_particles: usize,
+    wind: f32,
+    seasonal_snow: bool,
+    rain_reward: String,
+    snow_reward: String,
+    confetti_reward: String,
+}
+
+impl Default for WeatherSection {
+    fn default() -> Self {
+        Self {
+            density: 40.0,
+            duration_secs: 30,
+            max_particles: 400,
+            wind: 20.0,
+            seasonal_snow: false,
+            rain_reward: String::new(),
+            snow_reward: String::new(),
+            confetti_reward: String::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct PronounsSection {
+    enabled: bool,
+    cache_hours: u64,
+}
+
+impl Default for PronounsSection {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            cache_hours: 24,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct BadgesSection {
+    enabled: bool,
+    cache_hours: u64,
+}
+
+impl Default for BadgesSection {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            cache_hours: 24,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct StreaksSection {
+    enabled: bool,
+    threshold: u32,
+}
+
+impl Default for StreaksSection {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold: 3,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct GreetingsSection {
+    enabled: bool,
+    text: String,
+    custom: Vec<String>,
+    raid_threshold: usize,
+    raid_window_secs: u64,
+}
+
+impl Default for GreetingsSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            text: "o/ welcome back".to_string(),
+            custom: vec![],
+            raid_threshold: 5,
+            raid_window_secs: 10,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct ReactionsSection {
+    enabled: bool,
+    sad: Vec<String>,
+    hype: Vec<String>,
+    love: Vec<String>,
+    cooldown_secs: u64,
+}
+
+impl Default for ReactionsSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sad: vec![
+                "sad".to_string(),
+                ":(".to_string(),
+                "D:".to_string(),
+                "BibleThump".to_string(),
+                "Sadge".to_string(),
+                "PepeHands".to_string(),
+            ],
+            hype: vec![
+                "hype".to_string(),
+                "letsgo".to_string(),
+                "PogChamp".to_string(),
+                "Pog".to_string(),
+                "POGGERS".to_string(),
+                "PogU".to_string(),
+            ],
+            love: vec![
+                "love".to_string(),
+                "<3".to_string(),
+                "ily".to_string(),
+                "peepoLove".to_string(),
+                "widepeepoHappy".to_string(),
+            ],
+            cooldown_secs: 10,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct LeaderboardSection {
+    enabled: bool,
+    corner: String,
+    size: usize,
+    update_secs: u64,
+    font_url: String,
+    font_size: f32,
+    crown: bool,
+    daily: bool,
+}
+
+impl Default for LeaderboardSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            corner: "bottom_right".to_string(),
+            size: 5,
+            update_secs: DEFAULT_WIDGET_UPDATE_INTERVAL.as_secs(),
+            font_url: String::new(),
+            font_size: 20.0,
+            crown: true,
+            daily: false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct TimerSection {
+    enabled: bool,
+    command: String,
+    corner: String,
+    font_url: String,
+    font_size: f32,
+    flash_secs: u64,
+}
+
+impl Default for TimerSection {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            command: "!timer".to_string(),
+            corner: "top_left".to_string(),
+            font_url: String::new(),
+            font_size: 32.0,
+            flash_secs: 5,
+        }
+    }
 }
 
-pub(crate) fn load_config(filename: &str) -> Config {
-    let conf = Ini::load_from_file(filename).expect("Failed to load config.ini");
-
-    // Load [Channel] section
-    let channel_section = conf
-        .section(Some("Channel"))
-        .expect("Missing [Channel] section");
-
-    let channel_name = channel_section
-        .get("CHANNEL_NAME")
-        .expect("Missing CHANNEL_NAME")
-        .to_string();
-
-    let channel_id = channel_section
-        .get("CHANNEL_ID")
-        .expect("Missing CHANNEL_ID")
-        .to_string();
-
-    // Load [General] section
-    let general_section = conf
-        .section(Some("General"))
-        .expect("Missing [General] section");
-
-    let scale = general_section
-        .get("SCALE")
-        .expect("Missing SCALE")
-        .parse::<f32>()
-        .expect("Invalid SCALE");
-
-    // Load [Avatars] section
-    let avatars_section = conf
-        .section(Some("Avatars"))
-        .expect("Missing [Avatars] section");
-
-    let avatar_url = avatars_section
-        .get("AVATAR_URL")
-        .expect("Missing AVATAR_URL")
-        .to_string();
-
-    let random_avatars = avatars_section
-        .get("RANDOM_AVATARS")
-        .expect("Missing RANDOM_AVATARS")
-        .parse::<bool>()
-        .expect("Invalid RANDOM_AVATARS");
-
-    let action_duration = Duration::from_millis(
-        avatars_section
-            .get("ACTION_DURATION_MILIS")
-            .expect("Missing ACTION_DURATION_MILIS")
-            .parse::<u64>()
-            .expect("Invalid ACTION_DURATION_MILIS")
-    );
-
-    let wait_duration = Duration::from_millis(
-        avatars_section
-            .get("WAIT_DURATION_MILIS")
-            .expect("Missing WAIT_DURATION_MILIS")
-            .parse::<u64>()
-            .expect("Invalid WAIT_DURATION_MILIS")
-    );
-
-    let avatar_move_speed = avatars_section
-        .get("AVATAR_MOVE_SPEED")
-        .expect("Missing AVATAR_MOVE_SPEED")
-        .parse::<f32>()
-        .expect("Invalid AVATAR_MOVE_SPEED");
-
-    let user_despawn_time = Duration::from_secs(
-        avatars_section
-            .get("USER_DESPAWN_TIME_SECS")
-            .expect("Missing USER_DESPAWN_TIME_SECS")
-            .parse::<u64>()
-            .expect("Invalid USER_DESPAWN_TIME_SECS")
-    );
-
-    let edge_buffer = avatars_section
-        .get("EDGE_BUFFER")
-        .expect("Missing EDGE_BUFFER")
-        .parse::<f32>()
-        .expect("Invalid EDGE_BUFFER");
-
-    // Load [Messages] section
-    let general_section = conf.section(Some("Messages")).expect("Missing [General] section");
-
-    let font_url = general_section
-        .get("FONT_URL")
-        .expect("Missing FONT_URL")
-        .to_string();
-
-    let font_size = general_section
-        .get("FONT_SIZE")
-        .expect("Missing FONT_SIZE")
-        .parse::<f32>()
-        .expect("Invalid FONT_SIZE");
-
-    let emote_size_multiplier = general_section
-        .get("EMOTE_SIZE_MULTIPLIER")
-        .expect("Missing EMOTE_SIZE_MULTIPLIER")
-        .parse::<f32>()
-        .expect("Invalid EMOTE_SIZE_MULTIPLIER");
-
-    let message_box_vertical_offset = general_section
-        .get("MESSAGE_BOX_VERTICAL_OFFSET")
-        .expect("Missing MESSAGE_BOX_VERTICAL_OFFSET")
-        .parse::<f32>()
-        .expect("Invalid MESSAGE_BOX_VERTICAL_OFFSET");
-
-    let message_box_width = general_section
-        .get("MESSAGE_BOX_WIDTH")
-        .expect("Missing MESSAGE_BOX_WIDTH")
-        .parse::<f32>()
-        .expect("Invalid MESSAGE_BOX_WIDTH");
-
-    let message_despawn_time = Duration::from_millis(
-        general_section
-            .get("MESSAGE_DESPAWN_TIME_MILIS")
-            .expect("Missing MESSAGE_DESPAWN_TIME_MILIS")
-            .parse::<u64>()
-            .expect("Invalid MESSAGE_DESPAWN_TIME_MILIS")
-    );
-
-    Config {
-        channel_name,
-        channel_id,
-        scale,
-        avatar_url,
-        random_avatars,
-        action_duration,
-        wait_duration,
-        avatar_move_speed,
-        user_despawn_time,
-        edge_buffer,
-        font_url,
-        font_size,
-        emote_size_multiplier,
-        message_box_vertical_offset,
-        message_box_width,
-        message_despawn_time,
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct PyramidsSection {
+    enabled: bool,
+    min_height: usize,
+    interruptions_break: bool,
+    rain_secs: u64,
+}
+
+impl Default for PyramidsSection {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_height: 3,
+            interruptions_break: true,
+            rain_secs: 5,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct ProgressSection {
+    enabled: bool,
+    command: String,
+    edge: String,
+    thickness: f32,
+    segments: Vec<String>,
+    cluster: bool,
+}
+
+impl Default for ProgressSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            command: "!progress".to_string(),
+            edge: "bottom".to_string(),
+            thickness: 8.0,
+            segments: Vec::new(),
+            cluster: false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct MarqueeSection {
+    enabled: bool,
+    command: String,
+    speed: f32,
+    background_color: String,
+}
+
+impl Default for MarqueeSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            command: "!watching".to_string(),
+            speed: 60.0,
+            background_color: "00000099".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct RainSection {
+    enabled: bool,
+    command: String,
+    duration_secs: u64,
+    max_drops: usize,
+}
+
+impl Default for RainSection {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            command: "!rain".to_string(),
+            duration_secs: 15,
+            max_drops: 60,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct ScreenshotsSection {
+    folder: String,
+    burst_frames: u32,
+}
+
+impl Default for ScreenshotsSection {
+    fn default() -> Self {
+        Self {
+            folder: "screenshots".to_string(),
+            burst_frames: 10,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct PollsSection {
+    enabled: bool,
+    results_secs: u64,
+}
+
+impl Default for PollsSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            results_secs: 5,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct AmbientSection {
+    enabled: bool,
+    morning: String,
+    evening: String,
+    night: String,
+    transition_minutes: u64,
+    strength: f32,
+    time_override: String,
+}
+
+impl Default for AmbientSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            morning: "06:00".to_string(),
+            evening: "18:00".to_string(),
+            night: "22:00".to_string(),
+            transition_minutes: 60,
+            strength: 0.3,
+            time_override: String::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct FloorGlowSection {
+    enabled: bool,
+    height: f32,
+    window_secs: u64,
+    quiet_rate: f32,
+    busy_rate: f32,
+    quiet_color: String,
+    busy_color: String,
+}
+
+impl Default for FloorGlowSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            height: 12.0,
+            window_secs: 60,
+            quiet_rate: 2.0,
+            busy_rate: 30.0,
+            quiet_color: "4080FF40".to_string(),
+            busy_color: "FF9030A0".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct CampfireSection {
+    enabled: bool,
+    image: String,
+    x: String,
+    gather_after_secs: u64,
+    spacing: f32,
+}
+
+impl Default for CampfireSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            image: "campfire.png".to_string(),
+            x: "50%".to_string(),
+            gather_after_secs: 120,
+            spacing: 40.0,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct FootprintsSection {
+    enabled: bool,
+    image: String,
+    tint: String,
+    lifetime_milis: u64,
+    stride: f32,
+    max_footprints: usize,
+    max_users: usize,
+}
+
+impl Default for FootprintsSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            image: "footprint.png".to_string(),
+            tint: "FFFFFF80".to_string(),
+            lifetime_milis: 1500,
+            stride: 14.0,
+            max_footprints: 150,
+            max_users: 40,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct BitsSection {
+    enabled: bool,
+    monthly: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct LurkersSection {
+    enabled: bool,
+    max_ghosts: usize,
+    rotate_secs: u64,
+}
+
+impl Default for LurkersSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_ghosts: 15,
+            rotate_secs: 120,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct SignsSection {
+    reward: String,
+    duration_secs: u64,
+    max_length: usize,
+    blocked_words: Vec<String>,
+}
+
+impl Default for SignsSection {
+    fn default() -> Self {
+        Self {
+            reward: String::new(),
+            duration_secs: 30,
+            max_length: 40,
+            blocked_words: vec![],
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct ScheduleSection {
+    events: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct RaceSection {
+    enabled: bool,
+    command: String,
+    join_command: String,
+    join_secs: u64,
+    max_racers: usize,
+    cooldown_secs: u64,
+}
+
+impl Default for RaceSection {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            command: "!race".to_string(),
+            join_command: "!join".to_string(),
+            join_secs: 30,
+            max_racers: 10,
+            cooldown_secs: 300,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct DownloadsSection {
+    max_concurrent: usize,
+    host_delay_milis: u64,
+    max_in_flight_kb: usize,
+}
+
+impl Default for DownloadsSection {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 6,
+            host_delay_milis: 50,
+            max_in_flight_kb: 16384,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct ApiSection {
+    enabled: bool,
+    bind: String,
+    port: u16,
+    token: String,
+    allowed_origins: Vec<String>,
+}
+
+impl Default for ApiSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: "127.0.0.1".to_string(),
+            port: 7270,
+            token: String::new(),
+            allowed_origins: vec![],
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct StatsPageSection {
+    enabled: bool,
+    port: u16,
+    recent_messages: usize,
+    refresh_secs: u64,
+}
+
+impl Default for StatsPageSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 7271,
+            recent_messages: 10,
+            refresh_secs: 2,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct ScriptsSection {
+    enabled: bool,
+    folder: String,
+    max_operations: u64,
+}
+
+impl Default for ScriptsSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            folder: "scripts".to_string(),
+            max_operations: 100_000,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct SnapshotSection {
+    enabled: bool,
+    max_age_secs: u64,
+    autosave_secs: u64,
+    stagger_milis: u64,
+}
+
+impl Default for SnapshotSection {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_age_secs: 600,
+            autosave_secs: 60,
+            stagger_milis: 200,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct ViewerCountSection {
+    enabled: bool,
+    corner: String,
+    font_url: String,
+    font_size: f32,
+    update_secs: u64,
+    show_viewers: bool,
+    show_minawan: bool,
+}
+
+impl Default for ViewerCountSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            corner: "top_right".to_string(),
+            font_url: String::new(),
+            font_size: 20.0,
+            update_secs: DEFAULT_WIDGET_UPDATE_INTERVAL.as_secs(),
+            show_viewers: true,
+            show_minawan: true,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct SharedChatSection {
+    show_shared_chat: bool,
+    border_color: String,
+}
+
+impl Default for SharedChatSection {
+    fn default() -> Self {
+        Self {
+            show_shared_chat: true,
+            border_color: "FFB000".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct GeneralSection {
+    scale: f32,
+    backend: String,
+    present_mode: String,
+    gpu_watchdog_secs: u64,
+    locale: String,
+    timezone: String,
+    fps_cap: f32,
+    idle_after_secs: u64,
+    idle_fps: f32,
+    quiet_after_secs: u64,
+    quiet_fps: f32,
+    opacity: f32,
+    avatar_pool_size: usize,
+    message_pool_size: usize,
+}
+
+impl Default for GeneralSection {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            backend: "auto".to_string(),
+            present_mode: "mailbox".to_string(),
+            gpu_watchdog_secs: 30,
+            locale: "en".to_string(),
+            timezone: String::new(),
+            fps_cap: 0.0,
+            idle_after_secs: 5,
+            idle_fps: 1.0,
+            quiet_after_secs: 120,
+            quiet_fps: 1.0,
+            opacity: 1.0,
+            avatar_pool_size: 50,
+            message_pool_size: 100,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct WindowSection {
+    target_monitor: String,
+    window_x: i32,
+    window_y: i32,
+    window_width: u32,
+    window_height: u32,
+    strip_height: u32,
+    strip_edge: String,
+    capture_visibility: String,
+    background_mode: String,
+    key_color: String,
+}
+
+impl Default for WindowSection {
+    fn default() -> Self {
+        Self {
+            target_monitor: "".to_string(),
+            window_x: 0,
+            window_y: 0,
+            window_width: 0,
+            window_height: 0,
+            strip_height: 0,
+            strip_edge: "bottom".to_string(),
+            capture_visibility: "include".to_string(),
+            background_mode: "transparent".to_string(),
+            key_color: "00FF00".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct MirrorSection {
+    enabled: bool,
+    monitor: String,
+    scale: f32,
+    click_through: bool,
+    always_on_top: bool,
+}
+
+impl Default for MirrorSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            monitor: "".to_string(),
+            scale: 1.0,
+            click_through: false,
+            always_on_top: false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct OutputSection {
+    output: String,
+    source_name: String,
+    width: u32,
+    height: u32,
+    hide_window: bool,
+}
+
+impl Default for OutputSection {
+    fn default() -> Self {
+        Self {
+            output: "none".to_string(),
+            source_name: "Minawan Watch Party".to_string(),
+            width: 1920,
+            height: 1080,
+            hide_window: false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct LoggingSection {
+    log_level: String,
+    log_file: String,
+    log_max_size_kb: u64,
+    log_files_kept: usize,
+    show_toasts: bool,
+    toast_secs: u64,
+}
+
+impl Default for LoggingSection {
+    fn default() -> Self {
+        Self {
+            log_level: "info".to_string(),
+            log_file: "minawan-watch-party.log".to_string(),
+            log_max_size_kb: 1024,
+            log_files_kept: 3,
+            show_toasts: true,
+            toast_secs: 6,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct DebugSection {
+    corner: String,
+    font_url: String,
+    font_size: f32,
+    show_on_start: bool,
+}
+
+impl Default for DebugSection {
+    fn default() -> Self {
+        Self {
+            corner: "bottom_left".to_string(),
+            font_url: String::new(),
+            font_size: 14.0,
+            show_on_start: false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct FrameBudgetSection {
+    enabled: bool,
+    slow_frame_milis: u64,
+    fast_frame_milis: u64,
+    restore_secs: u64,
+}
+
+impl Default for FrameBudgetSection {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            slow_frame_milis: 28,
+            fast_frame_milis: 20,
+            restore_secs: 5,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct ConnectionSection {
+    enabled: bool,
+    corner: String,
+    detail_secs: u64,
+}
+
+impl Default for ConnectionSection {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            corner: "top_right".to_string(),
+            detail_secs: 5,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct HotkeysSection {
+    toggle_visibility: String,
+    toggle_click_through: String,
+    clear_party: String,
+    opacity_up: String,
+    opacity_down: String,
+    toggle_capture: String,
+    toggle_debug: String,
+    screenshot: String,
+    screenshot_burst: String,
+}
+
+impl Default for HotkeysSection {
+    fn default() -> Self {
+        Self {
+            toggle_visibility: "ctrl+alt+H".to_string(),
+            toggle_click_through: "ctrl+alt+I".to_string(),
+            clear_party: "ctrl+alt+C".to_string(),
+            opacity_up: "ctrl+alt+PageUp".to_string(),
+            opacity_down: "ctrl+alt+PageDown".to_string(),
+            toggle_capture: "ctrl+alt+K".to_string(),
+            toggle_debug: "ctrl+alt+D".to_string(),
+            screenshot: "ctrl+alt+S".to_string(),
+            screenshot_burst: String::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct SpawnAreaSection {
+    left_margin: String,
+    right_margin: String,
+    ranges: Vec<String>,
+}
+
+impl Default for SpawnAreaSection {
+    fn default() -> Self {
+        Self {
+            left_margin: "0".to_string(),
+            right_margin: "0".to_string(),
+            ranges: vec![],
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct StagePropsSection {
+    regions: Vec<String>,
+    behind_opacity: f32,
+}
+
+impl Default for StagePropsSection {
+    fn default() -> Self {
+        Self {
+            regions: vec![],
+            behind_opacity: 0.35,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct SpawnQueueSection {
+    spawns_per_sec: f32,
+    max_queued: usize,
+}
+
+impl Default for SpawnQueueSection {
+    fn default() -> Self {
+        Self {
+            spawns_per_sec: 5.0,
+            max_queued: 100,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct AvatarsSection {
+    avatar_url: String,
+    random_avatars: bool,
+    action_duration_milis: u64,
+    wait_duration_milis: u64,
+    avatar_move_speed: f32,
+    user_despawn_time_secs: u64,
+    max_users: usize,
+    edge_buffer: f32,
+    floor_offset: f32,
+}
+
+impl Default for AvatarsSection {
+    fn default() -> Self {
+        Self {
+            avatar_url: "avatars/avatar.png".to_string(),
+            random_avatars: false,
+            action_duration_milis: 800,
+            wait_duration_milis: 2000,
+            avatar_move_speed: 100.0,
+            user_despawn_time_secs: 1800,
+            max_users: 0,
+            edge_buffer: 20.0,
+            floor_offset: 25.0,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct MessagesSection {
+    font_url: String,
+    font_size: f32,
+    emote_size_multiplier: f32,
+    message_box_vertical_offset: f32,
+    message_box_width: f32,
+    message_despawn_time_milis: u64,
+    bubble_color: String,
+    text_color: String,
+    bubble_tail: bool,
+    bubble_theme: String,
+    bubble_sounds: bool,
+    collapse_repeats: bool,
+    repeats_ignore_case: bool,
+}
+
+impl Default for MessagesSection {
+    fn default() -> Self {
+        Self {
+            font_url: "fonts/ComicMono.ttf".to_string(),
+            font_size: 20.0,
+            emote_size_multiplier: 1.7,
+            message_box_vertical_offset: 35.0,
+            message_box_width: 200.0,
+            message_despawn_time_milis: 10000,
+            bubble_color: "00000066".to_string(),
+            text_color: "FFFFFF".to_string(),
+            bubble_tail: true,
+            bubble_theme: "plain".to_string(),
+            bubble_sounds: true,
+            collapse_repeats: true,
+            repeats_ignore_case: false,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "SCREAMING_SNAKE_CASE")]
+struct AccessibilitySection {
+    high_contrast: bool,
+    reduced_motion: bool,
+}
+
+/// Treat an empty string as unset
+fn optional(value: String) -> Option<String> {
+    let value = value.trim().to_string();
+    (!value.is_empty()).then_some(value)
+}
+
+/// Normalise a list of names such as chat sources or admins
+fn names(values: Vec<String>) -> Vec<String> {
+    values
+        .iter()
+        .map(|value| value.trim().to_lowercase())
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+impl ConfigFile {
+    /// Convert the on-disk values into the runtime config. Values that don't parse are
+    /// reported in `issues` and replaced by their default.
+    fn into_config(self, issues: &mut Vec<ConfigIssue>) -> Config {
+        let hype_train_bar = parse_or_default(
+            issues,
+            ("HypeTrain", "BAR_EDGE"),
+            &self.hype_train.bar_edge,
+            &HypeTrainSection::default().bar_edge,
+            |edge| match edge {
+                edge if edge.eq_ignore_ascii_case("none") => Ok(None),
+                "" => Ok(Some(ScreenEdge::Bottom)),
+                edge => edge.parse::<ScreenEdge>().map(Some),
+            },
+        );
+        let progress_edge = parse_or_default(
+            issues,
+            ("Progress", "EDGE"),
+            &self.progress.edge,
+            &ProgressSection::default().edge,
+            |edge| match edge.parse::<ScreenEdge>()? {
+                ScreenEdge::Left | ScreenEdge::Right => Err(format!("The progress bar can't run along {}", edge)),
+                edge => Ok(edge),
+            },
+        );
+        let time_of_day = |value: &str| NaiveTime::parse_from_str(value, "%H:%M").map_err(|err| err.to_string());
+        let ambient_morning = parse_or_default(
+            issues,
+            ("Ambient", "MORNING"),
+            &self.ambient.morning,
+            &AmbientSection::default().morning,
+            time_of_day,
+        );
+        let ambient_evening = parse_or_default(
+            issues,
+            ("Ambient", "EVENING"),
+            &self.ambient.evening,
+            &AmbientSection::default().evening,
+            time_of_day,
+        );
+        let ambient_night = parse_or_default(
+            issues,
+            ("Ambient", "NIGHT"),
+            &self.ambient.night,
+            &AmbientSection::default().night,
+            time_of_day,
+        );
+        let ambient_time_override = parse_or_default(
+            issues,
+            ("Ambient", "TIME_OVERRIDE"),
+            &self.ambient.time_override,
+            &AmbientSection::default().time_override,
+            |value| optional(value.to_string()).map(|time| time_of_day(&time)).transpose(),
+        );
+        let backend = parse_or_default(
+            issues,
+            ("General", "BACKEND"),
+            &self.general.backend,
+            &GeneralSection::default().backend,
+            str::parse::<GpuBackend>,
+        );
+        let present_mode = parse_or_default(
+            issues,
+            ("General", "PRESENT_MODE"),
+            &self.general.present_mode,
+            &GeneralSection::default().present_mode,
+            parse_present_mode,
+        );
+        let locale = parse_or_default(
+            issues,
+            ("General", "LOCALE"),
+            &self.general.locale,
+            &GeneralSection::default().locale,
+            str::parse::<Locale>,
+        );
+        let timezone = parse_or_default(
+            issues,
+            ("General", "TIMEZONE"),
+            &self.general.timezone,
+            &GeneralSection::default().timezone,
+            |value| {
+                optional(value.to_string())
+                    .map(|name| name.parse::<Tz>().map_err(|err| err.to_string()))
+                    .transpose()
+            },
+        );
+        let capture_visibility = parse_or_default(
+            issues,
+            ("Window", "CAPTURE_VISIBILITY"),
+            &self.window.capture_visibility,
+            &WindowSection::default().capture_visibility,
+            str::parse::<CaptureVisibility>,
+        );
+        let output = parse_or_default(
+            issues,
+            ("Output", "OUTPUT"),
+            &self.output.output,
+            &OutputSection::default().output,
+            str::parse::<OutputBackend>,
+        );
+        let viewer_widget_corner = parse_or_default(
+            issues,
+            ("ViewerCount", "CORNER"),
+            &self.viewer_count.corner,
+            &ViewerCountSection::default().corner,
+            str::parse::<ScreenCorner>,
+        );
+        let leaderboard_corner = parse_or_default(
+            issues,
+            ("Leaderboard", "CORNER"),
+            &self.leaderboard.corner,
+            &LeaderboardSection::default().corner,
+            str::parse::<ScreenCorner>,
+        );
+        let timer_corner = parse_or_default(
+            issues,
+            ("Timer", "CORNER"),
+            &self.timer.corner,
+            &TimerSection::default().corner,
+            str::parse::<ScreenCorner>,
+        );
+        let debug_corner = parse_or_default(
+            issues,
+            ("Debug", "CORNER"),
+            &self.debug.corner,
+            &DebugSection::default().corner,
+            str::parse::<ScreenCorner>,
+        );
+        let connection_chip_corner = parse_or_default(
+            issues,
+            ("Connection", "CORNER"),
+            &self.connection.corner,
+            &ConnectionSection::default().corner,
+            str::parse::<ScreenCorner>,
+        );
+        let api_bind = parse_or_default(
+            issues,
+            ("Api", "BIND"),
+            &self.api.bind,
+            &ApiSection::default().bind,
+            |value| value.parse::<IpAddr>().map_err(|err| err.to_string()),
+        );
+        let color = |value: &str| {
+            Srgba::hex(value)
+                .map(Color::Srgba)
+                .map_err(|err| err.to_string())
+        };
+        let shared_chat_border_color = parse_or_default(
+            issues,
+            ("SharedChat", "BORDER_COLOR"),
+            &self.shared_chat.border_color,
+            &SharedChatSection::default().border_color,
+            color,
+        );
+        let bubble_color = parse_or_default(
+            issues,
+            ("Messages", "BUBBLE_COLOR"),
+            &self.messages.bubble_color,
+            &MessagesSection::default().bubble_color,
+            color,
+        );
+        let text_color = parse_or_default(
+            issues,
+            ("Messages", "TEXT_COLOR"),
+            &self.messages.text_color,
+            &MessagesSection::default().text_color,
+            color,
+        );
+        let bubble_theme = parse_or_default(
+            issues,
+            ("Messages", "BUBBLE_THEME"),
+            &self.messages.bubble_theme,
+            &MessagesSection::default().bubble_theme,
+            bubble_theme_named,
+        );
+        let marquee_background = parse_or_default(
+            issues,
+            ("Marquee", "BACKGROUND_COLOR"),
+            &self.marquee.background_color,
+            &MarqueeSection::default().background_color,
+            color,
+        );
+        let floor_glow_quiet_color = parse_or_default(
+            issues,
+            ("FloorGlow", "QUIET_COLOR"),
+            &self.floor_glow.quiet_color,
+            &FloorGlowSection::default().quiet_color,
+            color,
+        );
+        let floor_glow_busy_color = parse_or_default(
+            issues,
+            ("FloorGlow", "BUSY_COLOR"),
+            &self.floor_glow.busy_color,
+            &FloorGlowSection::default().busy_color,
+            color,
+        );
+        let footprint_tint = parse_or_default(
+            issues,
+            ("Footprints", "TINT"),
+            &self.footprints.tint,
+            &FootprintsSection::default().tint,
+            color,
+        );
+        let strip_edge = parse_or_default(
+            issues,
+            ("Window", "STRIP_EDGE"),
+            &self.window.strip_edge,
+            &WindowSection::default().strip_edge,
+            str::parse::<StripEdge>,
+        );
+        let window_strip = (self.window.strip_height > 0).then_some(WindowStrip {
+            edge: strip_edge,
+            height: self.window.strip_height,
+        });
+        let background_mode = parse_or_default(
+            issues,
+            ("Window", "BACKGROUND_MODE"),
+            &self.window.background_mode,
+            &WindowSection::default().background_mode,
+            str::parse::<BackgroundMode>,
+        );
+        let key_color = parse_or_default(
+            issues,
+            ("Window", "KEY_COLOR"),
+            &self.window.key_color,
+            &WindowSection::default().key_color,
+            color,
+        );
+        let campfire_x = parse_or_default(
+            issues,
+            ("Campfire", "X"),
+            &self.campfire.x,
+            &CampfireSection::default().x,
+            str::parse::<Length>,
+        );
+        let spawn_left_margin = parse_or_default(
+            issues,
+            ("SpawnArea", "LEFT_MARGIN"),
+            &self.spawn_area.left_margin,
+            &SpawnAreaSection::default().left_margin,
+            str::parse::<Length>,
+        );
+        let spawn_right_margin = parse_or_default(
+            issues,
+            ("SpawnArea", "RIGHT_MARGIN"),
+            &self.spawn_area.right_margin,
+            &SpawnAreaSection::default().right_margin,
+            str::parse::<Length>,
+        );
+        let spawn_ranges = self
+            .spawn_area
+            .ranges
+            .iter()
+            .filter_map(|range| match range.parse::<SpawnRange>() {
+                Ok(range) => Some(range),
+                Err(_) => {
+                    issues.push(ConfigIssue::new(
+                        "SpawnArea",
+                        "RANGES",
+                        format!("{:?} is not a valid range, ignoring it", range),
+                    ));
+                    None
+                }
+            })
+            .collect();
+        let stage_props = self
+            .stage_props
+            .regions
+            .iter()
+            .filter_map(|region| match region.parse::<StageProp>() {
+                Ok(prop) => Some(prop),
+                Err(err) => {
+                    issues.push(ConfigIssue::new(
+                        "StageProps",
+                        "REGIONS",
+                        format!("{:?} is not a valid region, ignoring it: {}", region, err),
+                    ));
+                    None
+                }
+            })
+            .collect();
+        let greeting_custom = self
+            .greetings
+            .custom
+            .iter()
+            .filter_map(|entry| {
+                // Greetings may have an = of their own, usernames don't
+                let parsed = entry.split_once('=').and_then(|(user, greeting)| {
+                    let user = user.trim().trim_start_matches('@').to_lowercase();
+                    let greeting = greeting.trim().to_string();
+                    (!user.is_empty() && !greeting.is_empty()).then_some((user, greeting))
+                });
+                if parsed.is_none() {
+                    issues.push(ConfigIssue::new(
+                        "Greetings",
+                        "CUSTOM",
+                        format!("{:?} is not a valid greeting, expected username = greeting, ignoring it", entry),
+                    ));
+                }
+                parsed
+            })
+            .collect();
+        let accessory_names = names(self.accessories.accessories);
+        let accessory_rewards = self
+            .accessories
+            .rewards
+            .iter()
+            .filter_map(|reward| match reward.parse::<AccessoryReward>() {
+                Ok(mut reward) => {
+                    let (known, unknown): (Vec<String>, Vec<String>) =
+                        reward.accessories.into_iter().partition(|name| accessory_names.contains(name));
+                    if !unknown.is_empty() {
+                        issues.push(ConfigIssue::new(
+                            "Accessories",
+                            "REWARDS",
+                            format!(
+                                "{:?} offers {} which aren't in ACCESSORIES, leaving them out",
+                                reward.title,
+                                unknown.join(", ")
+                            ),
+                        ));
+                    }
+                    reward.accessories = known;
+                    (!reward.accessories.is_empty()).then_some(reward)
+                }
+                Err(_) => {
+                    issues.push(ConfigIssue::new(
+                        "Accessories",
+                        "REWARDS",
+                        format!("{:?} is not a valid reward, ignoring it", reward),
+                    ));
+                    None
+                }
+            })
+            .collect();
+        // Without a size the window is maximized on its monitor
+        let window_geometry = (self.window.window_width > 0 && self.window.window_height > 0)
+            .then_some(WindowGeometry {
+                x: self.window.window_x,
+                y: self.window.window_y,
+                width: self.window.window_width,
+                height: self.window.window_height,
+            });
+        let schedule = self
+            .schedule
+            .events
+            .iter()
+            .filter_map(|event| match event.parse::<ScheduledEvent>() {
+                Ok(event) => Some(event),
+                Err(err) => {
+                    issues.push(ConfigIssue::new(
+                        "Schedule",
+                        "EVENTS",
+                        format!("{:?} is not a valid event, ignoring it: {}", event, err),
+                    ));
+                    None
+                }
+            })
+            .collect();
+        let viewer_widget_font_url = optional(self.viewer_count.font_url)
+            .unwrap_or_else(|| self.messages.font_url.clone());
+        let leaderboard_font_url =
+            optional(self.leaderboard.font_url).unwrap_or_else(|| self.messages.font_url.clone());
+        let timer_font_url = optional(self.timer.font_url).unwrap_or_else(|| self.messages.font_url.clone());
+        let debug_font_url = optional(self.debug.font_url).unwrap_or_else(|| self.messages.font_url.clone());
+
+        Config {
+            channel_name: self.channel.channel_name.trim().to_string(),
+            channel_id: self.channel.channel_id.trim().to_string(),
+            chat_sources: names(self.chat.sources),
+            dedupe_window: Duration::from_secs(self.chat.dedupe_window_secs),
+            profile: None,
+            scale: self.general.scale,
+            backend,
+            present_mode,
+            gpu_watchdog: Duration::from_secs(self.general.gpu_watchdog_secs),
+            locale,
+            timezone,
+            fps_cap: self.general.fps_cap,
+            idle_after: Duration::from_secs(self.general.idle_after_secs),
+            idle_fps: self.general.idle_fps,
+            quiet_after: Duration::from_secs(self.general.quiet_after_secs),
+            quiet_fps: self.general.quiet_fps,
+            opacity: self.general.opacity,
+            avatar_pool_size: self.general.avatar_pool_size,
+            message_pool_size: self.general.message_pool_size,
+            target_monitor: optional(self.window.target_monitor),
+            window_geometry,
+            window_strip,
+            capture_visibility,
+            background_mode,
+            key_color,
+            mirror_enabled: self.mirror.enabled,
+            mirror_monitor: optional(self.mirror.monitor),
+            mirror_scale: self.mirror.scale,
+            mirror_click_through: self.mirror.click_through,
+            mirror_always_on_top: self.mirror.always_on_top,
+            output,
+            output_name: optional(self.output.source_name)
+                .unwrap_or_else(|| OutputSection::default().source_name),
+            output_width: self.output.width,
+            output_height: self.output.height,
+            output_hide_window: self.output.hide_window,
+            log_level: optional(self.logging.log_level).unwrap_or_else(|| "info".to_string()),
+            log_file: optional(self.logging.log_file),
+            log_max_size: self.logging.log_max_size_kb * 1024,
+            log_files_kept: self.logging.log_files_kept,
+            show_toasts: self.logging.show_toasts,
+            toast_duration: Duration::from_secs(self.logging.toast_secs),
+            debug_corner,
+            debug_font_url,
+            debug_font_size: self.debug.font_size,
+            debug_show_on_start: self.debug.show_on_start,
+            frame_budget_enabled: self.frame_budget.enabled,
+            frame_budget_slow: Duration::from_millis(self.frame_budget.slow_frame_milis),
+            frame_budget_fast: Duration::from_millis(self.frame_budget.fast_frame_milis),
+            frame_budget_restore: Duration::from_secs(self.frame_budget.restore_secs),
+            connection_chip_enabled: self.connection.enabled,
+            connection_chip_corner,
+            connection_chip_detail: Duration::from_secs(self.connection.detail_secs),
+            hotkey_toggle_visibility: optional(self.hotkeys.toggle_visibility),
+            hotkey_toggle_click_through: optional(self.hotkeys.toggle_click_through),
+            hotkey_clear_party: optional(self.hotkeys.clear_party),
+            hotkey_opacity_up: optional(self.hotkeys.opacity_up),
+            hotkey_opacity_down: optional(self.hotkeys.opacity_down),
+            hotkey_toggle_capture: optional(self.hotkeys.toggle_capture),
+            hotkey_toggle_debug: optional(self.hotkeys.toggle_debug),
+            hotkey_screenshot: optional(self.hotkeys.screenshot),
+            hotkey_screenshot_burst: optional(self.hotkeys.screenshot_burst),
+            avatar_url: self.avatars.avatar_url,
+            random_avatars: self.avatars.random_avatars,
+            action_duration: Duration::from_millis(self.avatars.action_duration_milis),
+            wait_duration: Duration::from_millis(self.avatars.wait_duration_milis),
+            avatar_move_speed: self.avatars.avatar_move_speed,
+            user_despawn_time: Duration::from_secs(self.avatars.user_despawn_time_secs),
+            max_users: self.avatars.max_users,
+            edge_buffer: self.avatars.edge_buffer,
+            spawn_left_margin,
+            spawn_right_margin,
+            spawn_ranges,
+            stage_props,
+            stage_prop_behind_opacity: self.stage_props.behind_opacity,
+            spawn_rate: self.spawn_queue.spawns_per_sec,
+            spawn_queue_max: self.spawn_queue.max_queued,
+            floor_offset: self.avatars.floor_offset,
+            font_url: self.messages.font_url,
+            font_size: self.messages.font_size,
+            emote_size_multiplier: self.messages.emote_size_multiplier,
+            message_box_vertical_offset: self.messages.message_box_vertical_offset,
+            message_box_width: self.messages.message_box_width,
+            message_despawn_time: Duration::from_millis(self.messages.message_despawn_time_milis),
+            bubble_color,
+            text_color,
+            bubble_tail: self.messages.bubble_tail,
+            bubble_theme,
+            bubble_sounds: self.messages.bubble_sounds,
+            collapse_repeats: self.messages.collapse_repeats,
+            repeats_ignore_case: self.messages.repeats_ignore_case,
+            high_contrast: self.accessibility.high_contrast,
+            reduced_motion: self.accessibility.reduced_motion,
+            twitch_client_id: optional(self.twitch.client_id),
+            twitch_username: optional(self.twitch.username),
+            twitch_token: optional(self.twitch.oauth_token),
+            youtube_api_key: optional(self.youtube.api_key),
+            youtube_live_chat_id: optional(self.youtube.live_chat_id),
+            youtube_video_id: optional(self.youtube.video_id),
+            kick_channel: optional(self.kick.channel),
+            kick_chatroom_id: optional(self.kick.chatroom_id),
+            idle_when_offline: self.stream.idle_when_offline,
+            stream_poll_interval: Duration::from_secs(self.stream.stream_poll_secs),
+            offline_user_despawn_time: Duration::from_secs(
+                self.stream.offline_user_despawn_time_secs,
+            ),
+            offline_fps: self.stream.offline_fps,
+            hype_train_bar,
+            hype_train_bar_thickness: self.hype_train.bar_thickness,
+            hype_train_speed_per_level: self.hype_train.speed_per_level,
+            show_shared_chat: self.shared_chat.show_shared_chat,
+            shared_chat_border_color,
+            follows_enabled: self.follows.enabled,
+            follow_wave_radius: self.follows.wave_radius,
+            follow_spam_threshold: self.follows.spam_threshold,
+            giant_reward: optional(self.giant.reward),
+            giant_duration: Duration::from_secs(self.giant.duration_secs),
+            giant_scale: self.giant.scale,
+            giant_max_screen_fraction: self.giant.max_screen_fraction,
+            size_reward: optional(self.sizes.reward),
+            giveaway_duration: Duration::from_secs(self.giveaway.duration_secs),
+            giveaway_roulette: Duration::from_secs(self.giveaway.roulette_secs),
+            giveaway_keyword_window: Duration::from_secs(self.giveaway.keyword_window_secs),
+            accessory_folder: self.accessories.folder,
+            accessory_names,
+            accessory_rewards,
+            birthdays_file: optional(self.birthdays.file),
+            birthday_hat: optional(self.birthdays.hat.trim().to_lowercase()),
+            gift_bombs_enabled: self.gifts.enabled,
+            gift_max_boxes: self.gifts.max_boxes,
+            gift_label_time: Duration::from_secs(self.gifts.label_secs),
+            boop_enabled: self.boop.enabled,
+            boop_command: self.boop.command.trim().to_string(),
+            boop_cooldown: Duration::from_secs(self.boop.cooldown_secs),
+            boop_timeout: Duration::from_secs(self.boop.timeout_secs),
+            petting_enabled: self.petting.enabled,
+            petting_idle: Duration::from_secs(self.petting.idle_secs),
+            weather_density: self.weather.density,
+            weather_duration: Duration::from_secs(self.weather.duration_secs),
+            weather_max_particles: self.weather.max_particles,
+            weather_wind: self.weather.wind,
+            weather_seasonal_snow: self.weather.seasonal_snow,
+            weather_rain_reward: optional(self.weather.rain_reward),
+            weather_snow_reward: optional(self.weather.snow_reward),
+            weather_confetti_reward: optional(self.weather.confetti_reward),
+            leaderboard_enabled: self.leaderboard.enabled,
+            leaderboard_corner,
+            leaderboard_size: self.leaderboard.size,
+            leaderboard_update_interval: Duration::from_secs(self.leaderboard.update_secs),
+            leaderboard_font_url,
+            leaderboard_font_size: self.leaderboard.font_size,
+            leaderboard_crown: self.leaderboard.crown,
+            leaderboard_daily: self.leaderboard.daily,
+            pronouns_enabled: self.pronouns.enabled,
+            pronouns_cache_time: Duration::from_secs(self.pronouns.cache_hours.saturating_mul(60 * 60)),
+            badges_enabled: self.badges.enabled,
+            badges_cache_time: Duration::from_secs(self.badges.cache_hours.saturating_mul(60 * 60)),
+            streaks_enabled: self.streaks.enabled,
+            streak_threshold: self.streaks.threshold,
+            greetings_enabled: self.greetings.enabled,
+            greeting_text: self.greetings.text.trim().to_string(),
+            greeting_custom,
+            greeting_raid_threshold: self.greetings.raid_threshold,
+            greeting_raid_window: Duration::from_secs(self.greetings.raid_window_secs),
+            reactions_enabled: self.reactions.enabled,
+            reaction_sad: names(self.reactions.sad),
+            reaction_hype: names(self.reactions.hype),
+            reaction_love: names(self.reactions.love),
+            reaction_cooldown: Duration::from_secs(self.reactions.cooldown_secs),
+            timer_enabled: self.timer.enabled,
+            timer_command: self.timer.command.trim().to_string(),
+            timer_corner,
+            timer_font_url,
+            timer_font_size: self.timer.font_size,
+            timer_flash: Duration::from_secs(self.timer.flash_secs),
+            pyramids_enabled: self.pyramids.enabled,
+            pyramid_min_height: self.pyramids.min_height,
+            pyramid_interruptions_break: self.pyramids.interruptions_break,
+            pyramid_rain: Duration::from_secs(self.pyramids.rain_secs),
+            progress_enabled: self.progress.enabled,
+            progress_command: self.progress.command.trim().to_string(),
+            progress_edge,
+            progress_thickness: self.progress.thickness,
+            progress_segments: self
+                .progress
+                .segments
+                .iter()
+                .map(|segment| segment.trim().to_string())
+                .filter(|segment| !segment.is_empty())
+                .collect(),
+            progress_cluster: self.progress.cluster,
+            marquee_enabled: self.marquee.enabled,
+            marquee_command: self.marquee.command.trim().to_string(),
+            marquee_speed: self.marquee.speed,
+            marquee_background,
+            rain_enabled: self.rain.enabled,
+            rain_command: self.rain.command.trim().to_string(),
+            rain_duration: Duration::from_secs(self.rain.duration_secs),
+            rain_max_drops: self.rain.max_drops,
+            screenshot_folder: self.screenshots.folder.trim().to_string(),
+            screenshot_burst_frames: self.screenshots.burst_frames,
+            polls_enabled: self.polls.enabled,
+            poll_results_time: Duration::from_secs(self.polls.results_secs),
+            ambient_enabled: self.ambient.enabled,
+            ambient_morning,
+            ambient_evening,
+            ambient_night,
+            ambient_transition: Duration::from_secs(self.ambient.transition_minutes * 60),
+            ambient_strength: self.ambient.strength,
+            ambient_time_override,
+            floor_glow_enabled: self.floor_glow.enabled,
+            floor_glow_height: self.floor_glow.height,
+            floor_glow_window: Duration::from_secs(self.floor_glow.window_secs),
+            floor_glow_quiet_rate: self.floor_glow.quiet_rate,
+            floor_glow_busy_rate: self.floor_glow.busy_rate,
+            floor_glow_quiet_color,
+            floor_glow_busy_color,
+            campfire_enabled: self.campfire.enabled,
+            campfire_image: self.campfire.image.trim().to_string(),
+            campfire_x,
+            campfire_gather_after: Duration::from_secs(self.campfire.gather_after_secs),
+            campfire_spacing: self.campfire.spacing,
+            footprints_enabled: self.footprints.enabled,
+            footprint_image: self.footprints.image.trim().to_string(),
+            footprint_tint,
+            footprint_lifetime: Duration::from_millis(self.footprints.lifetime_milis),
+            footprint_stride: self.footprints.stride,
+            footprint_max: self.footprints.max_footprints,
+            footprint_max_users: self.footprints.max_users,
+            bits_enabled: self.bits.enabled,
+            bits_monthly: self.bits.monthly,
+            lurkers_enabled: self.lurkers.enabled,
+            lurker_max_ghosts: self.lurkers.max_ghosts,
+            lurker_rotation: Duration::from_secs(self.lurkers.rotate_secs),
+            sign_reward: optional(self.signs.reward),
+            sign_duration: Duration::from_secs(self.signs.duration_secs),
+            sign_max_length: self.signs.max_length,
+            sign_blocked_words: names(self.signs.blocked_words),
+            schedule,
+            race_enabled: self.race.enabled,
+            race_command: self.race.command.trim().to_string(),
+            race_join_command: self.race.join_command.trim().to_string(),
+            race_join_time: Duration::from_secs(self.race.join_secs),
+            race_max_racers: self.race.max_racers,
+            race_cooldown: Duration::from_secs(self.race.cooldown_secs),
+            download_max_concurrent: self.downloads.max_concurrent,
+            download_host_delay: Duration::from_millis(self.downloads.host_delay_milis),
+            download_max_in_flight_kb: self.downloads.max_in_flight_kb,
+            api_enabled: self.api.enabled,
+            api_bind,
+            api_port: self.api.port,
+            api_token: optional(self.api.token),
+            api_allowed_origins: self
+                .api
+                .allowed_origins
+                .iter()
+                .map(|origin| origin.trim().trim_end_matches('/').to_lowercase())
+                .filter(|origin| !origin.is_empty())
+                .collect(),
+            stats_page_enabled: self.stats_page.enabled,
+            stats_page_port: self.stats_page.port,
+            stats_page_recent_messages: self.stats_page.recent_messages,
+            stats_page_refresh: Duration::from_secs(self.stats_page.refresh_secs),
+            scripts_enabled: self.scripts.enabled,
+            script_folder: self.scripts.folder,
+            script_max_operations: self.scripts.max_operations,
+            snapshot_enabled: self.snapshot.enabled,
+            snapshot_max_age: Duration::from_secs(self.snapshot.max_age_secs),
+            snapshot_autosave: Duration::from_secs(self.snapshot.autosave_secs),
+            snapshot_stagger: Duration::from_millis(self.snapshot.stagger_milis),
+            command_prefix: self.commands.prefix,
+            admin_users: names(self.commands.admins),
+            moderation_enabled: self.moderation.enabled,
+            moderation_return_grace: Duration::from_secs(self.moderation.return_grace_secs),
+            reply_giveaway_winner: optional(self.replies.giveaway_winner),
+            reply_raid: optional(self.replies.raid),
+            reply_party: optional(self.replies.party),
+            party_command: self.replies.party_command.trim().to_string(),
+            party_cooldown: Duration::from_secs(self.replies.party_cooldown_secs),
+            // Kept as written, a pinned user spawned on startup goes by this name until they chat
+            pinned_users: self
+                .pinned
+                .users
+                .iter()
+                .map(|user| user.trim().to_string())
+                .filter(|user| !user.is_empty())
+                .collect(),
+            pin_broadcaster: self.pinned.broadcaster,
+            pinned_spawn_on_start: self.pinned.spawn_on_start,
+            viewer_widget_enabled: self.viewer_count.enabled,
+            viewer_widget_corner,
+            viewer_widget_font_url,
+            viewer_widget_font_size: self.viewer_count.font_size,
+            viewer_widget_update_interval: Duration::from_secs(self.viewer_count.update_secs),
+            viewer_widget_show_viewers: self.viewer_count.show_viewers,
+            viewer_widget_show_minawan: self.viewer_count.show_minawan,
+        }
+    }
+}
+
+/// Parse a value, falling back to the default and reporting an issue if it doesn't parse
+fn parse_or_default<T>(
+    issues: &mut Vec<ConfigIssue>,
+    (section, key): (&str, &str),
+    value: &str,
+    default: &str,
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> T {
+    parse(value.trim()).unwrap_or_else(|_| {
+        issues.push(ConfigIssue::new(section, key, format!("{:?} is not a valid value", value)));
+        parse(default).expect("Default config values are valid")
+    })
+}
+
+pub(crate) fn default_document() -> DocumentMut {
+    DEFAULT_CONFIG
+        .parse::<DocumentMut>()
+        .expect("Default config is valid")
+}
+
+/// Line number of a key in the config file, used to point at problems
+fn line_of(text: &str, section: &str, key: &str) -> Option<usize> {
+    let mut current_section = "";
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            current_section = name.trim();
+        } else if current_section == section
+            && line.split('=').next().map(str::trim) == Some(key)
+        {
+            return Some(number + 1);
+        }
+    }
+    None
+}
+
+/// Prefix a problem with the line it was found on
+fn at_line(text: &str, section: &str, key: &str, problem: String) -> String {
+    match line_of(text, section, key) {
+        Some(line) => format!("line {}: {}", line, problem),
+        None => problem,
+    }
+}
+
+/// A problem with one value in the config. Bad values are replaced by their default
+/// rather than stopping the overlay, and every issue is logged.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ConfigIssue {
+    pub(crate) section: String,
+    /// Empty when the whole section is the problem
+    pub(crate) key: String,
+    pub(crate) problem: String,
+}
+
+impl ConfigIssue {
+    fn new(section: &str, key: &str, problem: impl Into<String>) -> Self {
+        Self {
+            section: section.to_string(),
+            key: key.to_string(),
+            problem: problem.into(),
+        }
+    }
+
+    /// Describe the issue, pointing at the line in `text` it comes from when it can be found
+    pub(crate) fn describe(&self, text: &str) -> String {
+        if self.key.is_empty() {
+            let header = format!("[{}]", self.section);
+            let problem = format!("{} {}", header, self.problem);
+            return match text.lines().position(|line| line.trim() == header) {
+                Some(index) => format!("line {}: {}", index + 1, problem),
+                None => problem,
+            };
+        }
+        at_line(
+            text,
+            &self.section,
+            &self.key,
+            format!("[{}] {} {}", self.section, self.key, self.problem),
+        )
+    }
+}
+
+/// Keys and sections the overlay doesn't understand, these are usually typos
+fn unknown_keys(document: &DocumentMut) -> Vec<ConfigIssue> {
+    let known = default_document();
+    let mut issues = vec![];
+    for (section, item) in document.iter().filter(|(section, _)| *section != "profile") {
+        let Some(table) = item.as_table() else {
+            continue;
+        };
+        match known.get(section).and_then(Item::as_table) {
+            Some(known_section) => issues.extend(
+                table
+                    .iter()
+                    .filter(|(key, _)| !known_section.contains_key(key))
+                    .map(|(key, _)| ConfigIssue::new(section, key, "is not a known key, ignoring it")),
+            ),
+            None => issues.push(ConfigIssue::new(section, "", "is not a known section, ignoring it")),
+        }
+    }
+    issues
+}
+
+/// Keys that may be negative, every other whole number must not be
+const SIGNED_KEYS: [&str; 2] = ["WINDOW_X", "WINDOW_Y"];
+
+/// Remove values of the wrong type so their default is used, and turn whole numbers
+/// given for decimal keys into decimals
+fn check_types(document: &mut DocumentMut) -> Vec<ConfigIssue> {
+    let defaults = default_document();
+    let mut issues = vec![];
+    for (section, default_table) in defaults
+        .iter()
+        .filter_map(|(section, item)| item.as_table().map(|table| (section, table)))
+    {
+        let Some(table) = document.get_mut(section).and_then(Item::as_table_mut) else {
+            continue;
+        };
+        for (key, default) in default_table.iter() {
+            let (Some(value), Some(default)) = (
+                table.get(key).and_then(Item::as_value).cloned(),
+                default.as_value(),
+            ) else {
+                continue;
+            };
+            let problem = match (default, &value) {
+                (Value::Float(_), Value::Integer(number)) => {
+                    let decor = value.decor().clone();
+                    let mut float = Value::from(*number.value() as f64);
+                    *float.decor_mut() = decor;
+                    table[key] = Item::Value(float);
+                    None
+                }
+                // Pixel values such as LEFT_MARGIN = 100
+                (Value::String(_), Value::Integer(_) | Value::Float(_)) => {
+                    let mut text = Value::from(value.to_string().trim().to_string());
+                    *text.decor_mut() = value.decor().clone();
+                    table[key] = Item::Value(text);
+                    None
+                }
+                (Value::Integer(_), Value::Integer(number))
+                    if *number.value() < 0 && !SIGNED_KEYS.contains(&key) =>
+                {
+                    Some("must not be negative, using the default".to_string())
+                }
+                (Value::Array(_), Value::Array(items)) if items.iter().any(|item| !item.is_str()) => {
+                    Some("must be a list of strings, using the default".to_string())
+                }
+                (default, value) if default.type_name() != value.type_name() => Some(format!(
+                    "should be a {} but is a {}, using the default",
+                    default.type_name(),
+                    value.type_name()
+                )),
+                _ => None,
+            };
+            if let Some(problem) = problem {
+                table.remove(key);
+                issues.push(ConfigIssue::new(section, key, problem));
+            }
+        }
+    }
+    issues
+}
+
+/// Check values that parse but would break the overlay. The config itself isn't changed,
+/// `use_defaults` replaces the values these issues are about.
+pub(crate) fn validate(config: &Config) -> Vec<ConfigIssue> {
+    let checks = [
+        ("General", "SCALE", config.scale > 0.0 && config.scale <= 10.0, "must be between 0 and 10"),
+        ("General", "FPS_CAP", config.fps_cap >= 0.0, "must not be negative"),
+        ("General", "IDLE_FPS", config.idle_fps > 0.0, "must be greater than 0"),
+        ("General", "QUIET_FPS", config.quiet_fps > 0.0, "must be greater than 0"),
+        (
+            "General",
+            "OPACITY",
+            (MIN_OPACITY..=MAX_OPACITY).contains(&config.opacity),
+            "must be between 0.1 and 1",
+        ),
+        ("Avatars", "ACTION_DURATION_MILIS", !config.action_duration.is_zero(), "must be at least 1"),
+        ("Avatars", "AVATAR_MOVE_SPEED", config.avatar_move_speed >= 0.0, "must not be negative"),
+        ("Avatars", "USER_DESPAWN_TIME_SECS", !config.user_despawn_time.is_zero(), "must be at least 1"),
+        ("Avatars", "EDGE_BUFFER", config.edge_buffer >= 0.0, "must not be negative"),
+        (
+            "StageProps",
+            "BEHIND_OPACITY",
+            (0.0..=1.0).contains(&config.stage_prop_behind_opacity),
+            "must be between 0 and 1",
+        ),
+        ("SpawnQueue", "SPAWNS_PER_SEC", config.spawn_rate >= 0.0, "must not be negative"),
+        ("Messages", "FONT_SIZE", config.font_size > 0.0, "must be greater than 0"),
+        ("Messages", "EMOTE_SIZE_MULTIPLIER", config.emote_size_multiplier > 0.0, "must be greater than 0"),
+        ("Messages", "MESSAGE_BOX_WIDTH", config.message_box_width > 0.0, "must be greater than 0"),
+        ("Messages", "MESSAGE_DESPAWN_TIME_MILIS", !config.message_despawn_time.is_zero(), "must be at least 1"),
+        ("Mirror", "SCALE", config.mirror_scale > 0.0 && config.mirror_scale <= 10.0, "must be between 0 and 10"),
+        ("Output", "WIDTH", config.output_width > 0, "must be at least 1"),
+        ("Output", "HEIGHT", config.output_height > 0, "must be at least 1"),
+        ("Logging", "LOG_MAX_SIZE_KB", config.log_max_size > 0, "must be at least 1"),
+        (
+            "FrameBudget",
+            "FAST_FRAME_MILIS",
+            config.frame_budget_fast <= config.frame_budget_slow,
+            "must not be more than SLOW_FRAME_MILIS",
+        ),
+        ("Replies", "PARTY_COMMAND", !config.party_command.is_empty(), "must not be empty"),
+        ("Stream", "STREAM_POLL_SECS", !config.stream_poll_interval.is_zero(), "must be at least 1"),
+        ("Stream", "OFFLINE_FPS", config.offline_fps > 0.0, "must be greater than 0"),
+        ("HypeTrain", "BAR_THICKNESS", config.hype_train_bar_thickness >= 0.0, "must not be negative"),
+        ("Follows", "SPAM_THRESHOLD", config.follow_spam_threshold > 0, "must be at least 1"),
+        ("Giant", "DURATION_SECS", !config.giant_duration.is_zero(), "must be at least 1"),
+        ("Giant", "SCALE", config.giant_scale >= 1.0 && config.giant_scale <= 10.0, "must be between 1 and 10"),
+        (
+            "Giant",
+            "MAX_SCREEN_FRACTION",
+            config.giant_max_screen_fraction > 0.0 && config.giant_max_screen_fraction <= 1.0,
+            "must be between 0 and 1",
+        ),
+        ("Giveaway", "DURATION_SECS", !config.giveaway_duration.is_zero(), "must be at least 1"),
+        ("Giveaway", "ROULETTE_SECS", !config.giveaway_roulette.is_zero(), "must be at least 1"),
+        ("Giveaway", "KEYWORD_WINDOW_SECS", !config.giveaway_keyword_window.is_zero(), "must be at least 1"),
+        ("Gifts", "MAX_BOXES", config.gift_max_boxes > 0, "must be at least 1"),
+        ("Boop", "COMMAND", !config.boop_command.is_empty(), "must not be empty"),
+        ("Boop", "TIMEOUT_SECS", !config.boop_timeout.is_zero(), "must be at least 1"),
+        ("Weather", "DENSITY", config.weather_density > 0.0, "must be greater than 0"),
+        ("Weather", "DURATION_SECS", !config.weather_duration.is_zero(), "must be at least 1"),
+        ("Leaderboard", "SIZE", config.leaderboard_size > 0, "must be at least 1"),
+        ("Leaderboard", "UPDATE_SECS", !config.leaderboard_update_interval.is_zero(), "must be at least 1"),
+        ("Leaderboard", "FONT_SIZE", config.leaderboard_font_size > 0.0, "must be greater than 0"),
+        ("Streaks", "THRESHOLD", config.streak_threshold > 0, "must be at least 1"),
+        ("Greetings", "TEXT", !config.greeting_text.is_empty(), "must not be empty"),
+        ("Greetings", "RAID_THRESHOLD", config.greeting_raid_threshold > 0, "must be at least 1"),
+        ("Greetings", "RAID_WINDOW_SECS", !config.greeting_raid_window.is_zero(), "must be at least 1"),
+        ("Timer", "COMMAND", !config.timer_command.is_empty(), "must not be empty"),
+        ("Timer", "FONT_SIZE", config.timer_font_size > 0.0, "must be greater than 0"),
+        ("Pyramids", "MIN_HEIGHT", config.pyramid_min_height >= 2, "must be at least 2"),
+        ("Progress", "COMMAND", !config.progress_command.is_empty(), "must not be empty"),
+        ("Marquee", "COMMAND", !config.marquee_command.is_empty(), "must not be empty"),
+        ("Marquee", "SPEED", config.marquee_speed > 0.0, "must be greater than 0"),
+        ("Rain", "COMMAND", !config.rain_command.is_empty(), "must not be empty"),
+        ("Rain", "DURATION_SECS", !config.rain_duration.is_zero(), "must be at least 1"),
+        ("Rain", "MAX_DROPS", config.rain_max_drops > 0, "must be at least 1"),
+        ("Progress", "THICKNESS", config.progress_thickness > 0.0, "must be greater than 0"),
+        ("Screenshots", "FOLDER", !config.screenshot_folder.is_empty(), "must not be empty"),
+        (
+            "Screenshots",
+            "BURST_FRAMES",
+            (1..=60).contains(&config.screenshot_burst_frames),
+            "must be from 1 to 60",
+        ),
+        (
+            "Ambient",
+            "STRENGTH",
+            (0.0..=1.0).contains(&config.ambient_strength),
+            "must be between 0 and 1",
+        ),
+        ("FloorGlow", "HEIGHT", config.floor_glow_height > 0.0, "must be greater than 0"),
+        ("FloorGlow", "WINDOW_SECS", !config.floor_glow_window.is_zero(), "must be at least 1"),
+        ("FloorGlow", "QUIET_RATE", config.floor_glow_quiet_rate >= 0.0, "must not be negative"),
+        ("FloorGlow", "BUSY_RATE", config.floor_glow_busy_rate > 0.0, "must be greater than 0"),
+        ("Campfire", "IMAGE", !config.campfire_image.is_empty(), "must not be empty"),
+        ("Campfire", "SPACING", config.campfire_spacing > 0.0, "must be greater than 0"),
+        ("Footprints", "IMAGE", !config.footprint_image.is_empty(), "must not be empty"),
+        ("Footprints", "LIFETIME_MILIS", !config.footprint_lifetime.is_zero(), "must be at least 1"),
+        ("Footprints", "STRIDE", config.footprint_stride > 0.0, "must be greater than 0"),
+        ("Lurkers", "ROTATE_SECS", !config.lurker_rotation.is_zero(), "must be at least 1"),
+        ("Signs", "DURATION_SECS", !config.sign_duration.is_zero(), "must be at least 1"),
+        ("Signs", "MAX_LENGTH", config.sign_max_length > 0, "must be at least 1"),
+        ("Race", "COMMAND", !config.race_command.is_empty(), "must not be empty"),
+        ("Race", "JOIN_COMMAND", !config.race_join_command.is_empty(), "must not be empty"),
+        ("Race", "JOIN_SECS", !config.race_join_time.is_zero(), "must be at least 1"),
+        ("Race", "MAX_RACERS", config.race_max_racers >= 2, "must be at least 2"),
+        ("Downloads", "MAX_CONCURRENT", config.download_max_concurrent > 0, "must be at least 1"),
+        ("Downloads", "MAX_IN_FLIGHT_KB", config.download_max_in_flight_kb > 0, "must be at least 1"),
+        ("StatsPage", "REFRESH_SECS", !config.stats_page_refresh.is_zero(), "must be at least 1"),
+        ("ViewerCount", "FONT_SIZE", config.viewer_widget_font_size > 0.0, "must be greater than 0"),
+        ("ViewerCount", "UPDATE_SECS", !config.viewer_widget_update_interval.is_zero(), "must be at least 1"),
+        ("Debug", "FONT_SIZE", config.debug_font_size > 0.0, "must be greater than 0"),
+        ("Scripts", "MAX_OPERATIONS", config.script_max_operations > 0, "must be at least 1"),
+        ("Snapshot", "MAX_AGE_SECS", !config.snapshot_max_age.is_zero(), "must be at least 1"),
+    ];
+
+    let mut issues: Vec<ConfigIssue> = checks
+        .iter()
+        .filter(|(_, _, valid, _)| !valid)
+        .map(|(section, key, _, expected)| {
+            ConfigIssue::new(section, key, format!("{}, using the default", expected))
+        })
+        .collect();
+    if config.channel_name.is_empty() {
+        issues.push(ConfigIssue::new(
+            "Channel",
+            "CHANNEL_NAME",
+            "is empty, there's no channel to watch until it's set",
+        ));
+    }
+    issues
+}
+
+/// The config with every value at its documented default
+fn default_config() -> Config {
+    ConfigFile::default().into_config(&mut vec![])
+}
+
+/// Replace the values `validate` found problems with by their defaults
+fn use_defaults(config: &mut Config, issues: &[ConfigIssue]) {
+    let defaults = default_config();
+    for issue in issues {
+        match (issue.section.as_str(), issue.key.as_str()) {
+            ("General", "SCALE") => config.scale = defaults.scale,
+            ("General", "FPS_CAP") => config.fps_cap = defaults.fps_cap,
+            ("General", "IDLE_FPS") => config.idle_fps = defaults.idle_fps,
+            ("General", "QUIET_FPS") => config.quiet_fps = defaults.quiet_fps,
+            ("General", "OPACITY") => config.opacity = defaults.opacity,
+            ("Avatars", "ACTION_DURATION_MILIS") => config.action_duration = defaults.action_duration,
+            ("Avatars", "AVATAR_MOVE_SPEED") => config.avatar_move_speed = defaults.avatar_move_speed,
+            ("Avatars", "USER_DESPAWN_TIME_SECS") => config.user_despawn_time = defaults.user_despawn_time,
+            ("Avatars", "EDGE_BUFFER") => config.edge_buffer = defaults.edge_buffer,
+            ("StageProps", "BEHIND_OPACITY") => {
+                config.stage_prop_behind_opacity = defaults.stage_prop_behind_opacity
+            }
+            ("SpawnQueue", "SPAWNS_PER_SEC") => config.spawn_rate = defaults.spawn_rate,
+            ("Messages", "FONT_SIZE") => config.font_size = defaults.font_size,
+            ("Messages", "EMOTE_SIZE_MULTIPLIER") => {
+                config.emote_size_multiplier = defaults.emote_size_multiplier
+            }
+            ("Messages", "MESSAGE_BOX_WIDTH") => config.message_box_width = defaults.message_box_width,
+            ("Messages", "MESSAGE_DESPAWN_TIME_MILIS") => {
+                config.message_despawn_time = defaults.message_despawn_time
+            }
+            ("Mirror", "SCALE") => config.mirror_scale = defaults.mirror_scale,
+            ("Output", "WIDTH") => config.output_width = defaults.output_width,
+            ("Output", "HEIGHT") => config.output_height = defaults.output_height,
+            ("Logging", "LOG_MAX_SIZE_KB") => config.log_max_size = defaults.log_max_size,
+            ("FrameBudget", "FAST_FRAME_MILIS") => {
+                config.frame_budget_fast = defaults.frame_budget_fast.min(config.frame_budget_slow)
+            }
+            ("Replies", "PARTY_COMMAND") => config.party_command = defaults.party_command.clone(),
+            ("Stream", "STREAM_POLL_SECS") => config.stream_poll_interval = defaults.stream_poll_interval,
+            ("Stream", "OFFLINE_FPS") => config.offline_fps = defaults.offline_fps,
+            ("HypeTrain", "BAR_THICKNESS") => {
+                config.hype_train_bar_thickness = defaults.hype_train_bar_thickness
+            }
+            ("Follows", "SPAM_THRESHOLD") => config.follow_spam_threshold = defaults.follow_spam_threshold,
+            ("Giant", "DURATION_SECS") => config.giant_duration = defaults.giant_duration,
+            ("Giant", "SCALE") => config.giant_scale = defaults.giant_scale,
+            ("Giant", "MAX_SCREEN_FRACTION") => {
+                config.giant_max_screen_fraction = defaults.giant_max_screen_fraction
+            }
+            ("Giveaway", "DURATION_SECS") => config.giveaway_duration = defaults.giveaway_duration,
+            ("Giveaway", "ROULETTE_SECS") => config.giveaway_roulette = defaults.giveaway_roulette,
+            ("Giveaway", "KEYWORD_WINDOW_SECS") => {
+                config.giveaway_keyword_window = defaults.giveaway_keyword_window
+            }
+            ("Gifts", "MAX_BOXES") => config.gift_max_boxes = defaults.gift_max_boxes,
+            ("Boop", "COMMAND") => config.boop_command = defaults.boop_command.clone(),
+            ("Boop", "TIMEOUT_SECS") => config.boop_timeout = defaults.boop_timeout,
+            ("Weather", "DENSITY") => config.weather_density = defaults.weather_density,
+            ("Weather", "DURATION_SECS") => config.weather_duration = defaults.weather_duration,
+            ("Leaderboard", "SIZE") => config.leaderboard_size = defaults.leaderboard_size,
+            ("Leaderboard", "UPDATE_SECS") => {
+                config.leaderboard_update_interval = defaults.leaderboard_update_interval
+            }
+            ("Leaderboard", "FONT_SIZE") => config.leaderboard_font_size = defaults.leaderboard_font_size,
+            ("Streaks", "THRESHOLD") => config.streak_threshold = defaults.streak_threshold,
+            ("Greetings", "TEXT") => config.greeting_text = defaults.greeting_text.clone(),
+            ("Greetings", "RAID_THRESHOLD") => config.greeting_raid_threshold = defaults.greeting_raid_threshold,
+            ("Greetings", "RAID_WINDOW_SECS") => config.greeting_raid_window = defaults.greeting_raid_window,
+            ("Timer", "COMMAND") => config.timer_command = defaults.timer_command.clone(),
+            ("Timer", "FONT_SIZE") => config.timer_font_size = defaults.timer_font_size,
+            ("Pyramids", "MIN_HEIGHT") => config.pyramid_min_height = defaults.pyramid_min_height,
+            ("Progress", "COMMAND") => config.progress_command = defaults.progress_command.clone(),
+            ("Marquee", "COMMAND") => config.marquee_command = defaults.marquee_command.clone(),
+            ("Marquee", "SPEED") => config.marquee_speed = defaults.marquee_speed,
+            ("Rain", "COMMAND") => config.rain_command = defaults.rain_command.clone(),
+            ("Rain", "DURATION_SECS") => config.rain_duration = defaults.rain_duration,
+            ("Rain", "MAX_DROPS") => config.rain_max_drops = defaults.rain_max_drops,
+            ("Progress", "THICKNESS") => config.progress_thickness = defaults.progress_thickness,
+            ("Screenshots", "FOLDER") => config.screenshot_folder = defaults.screenshot_folder.clone(),
+            ("Screenshots", "BURST_FRAMES") => config.screenshot_burst_frames = defaults.screenshot_burst_frames,
+            ("Ambient", "STRENGTH") => config.ambient_strength = defaults.ambient_strength,
+            ("FloorGlow", "HEIGHT") => config.floor_glow_height = defaults.floor_glow_height,
+            ("FloorGlow", "WINDOW_SECS") => config.floor_glow_window = defaults.floor_glow_window,
+            ("FloorGlow", "QUIET_RATE") => config.floor_glow_quiet_rate = defaults.floor_glow_quiet_rate,
+            ("FloorGlow", "BUSY_RATE") => config.floor_glow_busy_rate = defaults.floor_glow_busy_rate,
+            ("Campfire", "IMAGE") => config.campfire_image = defaults.campfire_image.clone(),
+            ("Campfire", "SPACING") => config.campfire_spacing = defaults.campfire_spacing,
+            ("Footprints", "IMAGE") => config.footprint_image = defaults.footprint_image.clone(),
+            ("Footprints", "LIFETIME_MILIS") => config.footprint_lifetime = defaults.footprint_lifetime,
+            ("Footprints", "STRIDE") => config.footprint_stride = defaults.footprint_stride,
+            ("Lurkers", "ROTATE_SECS") => config.lurker_rotation = defaults.lurker_rotation,
+            ("Signs", "DURATION_SECS") => config.sign_duration = defaults.sign_duration,
+            ("Signs", "MAX_LENGTH") => config.sign_max_length = defaults.sign_max_length,
+            ("Race", "COMMAND") => config.race_command = defaults.race_command.clone(),
+            ("Race", "JOIN_COMMAND") => config.race_join_command = defaults.race_join_command.clone(),
+            ("Race", "JOIN_SECS") => config.race_join_time = defaults.race_join_time,
+            ("Race", "MAX_RACERS") => config.race_max_racers = defaults.race_max_racers,
+            ("Downloads", "MAX_CONCURRENT") => config.download_max_concurrent = defaults.download_max_concurrent,
+            ("Downloads", "MAX_IN_FLIGHT_KB") => {
+                config.download_max_in_flight_kb = defaults.download_max_in_flight_kb
+            }
+            ("StatsPage", "REFRESH_SECS") => config.stats_page_refresh = defaults.stats_page_refresh,
+            ("ViewerCount", "FONT_SIZE") => config.viewer_widget_font_size = defaults.viewer_widget_font_size,
+            ("ViewerCount", "UPDATE_SECS") => {
+                config.viewer_widget_update_interval = defaults.viewer_widget_update_interval
+            }
+            ("Debug", "FONT_SIZE") => config.debug_font_size = defaults.debug_font_size,
+            ("Scripts", "MAX_OPERATIONS") => config.script_max_operations = defaults.script_max_operations,
+            ("Snapshot", "MAX_AGE_SECS") => config.snapshot_max_age = defaults.snapshot_max_age,
+            _ => {}
+        }
+    }
+}
+
+/// Parse a plain string value into the type of the key's default, so `60` stays a number
+/// while a CHANNEL_ID stays a string. Lists are comma separated. Returns None if it doesn't parse.
+fn typed_value(defaults: &DocumentMut, section: &str, key: &str, raw: &str) -> Option<Value> {
+    let default = defaults.get(section).and_then(|table| table.get(key));
+    let value = match default.and_then(Item::as_value) {
+        Some(Value::Integer(_)) => raw.parse::<i64>().ok()?.into(),
+        Some(Value::Float(_)) => raw.parse::<f64>().ok()?.into(),
+        Some(Value::Boolean(_)) => raw.parse::<bool>().ok()?.into(),
+        Some(Value::Array(_)) => raw
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .collect::<toml_edit::Array>()
+            .into(),
+        _ => raw.into(),
+    };
+    Some(value)
+}
+
+/// Set a value, keeping any comment above the key
+pub(crate) fn set_value(document: &mut DocumentMut, section: &str, key: &str, value: Value) {
+    if let Some(existing) = document
+        .get_mut(section)
+        .and_then(Item::as_table_mut)
+        .and_then(|table| table.get_mut(key))
+        .and_then(Item::as_value_mut)
+    {
+        let decor = existing.decor().clone();
+        *existing = value;
+        *existing.decor_mut() = decor;
+        return;
+    }
+    document[section][key] = Item::Value(value);
+}
+
+/// Convert an old config.ini into the commented TOML layout
+fn ini_to_toml(text: &str) -> Result<DocumentMut, String> {
+    let conf = Ini::load_from_str(text).map_err(|err| format!("Failed to parse ini: {}", err))?;
+
+    let defaults = default_document();
+    let mut document = default_document();
+    for (section, properties) in conf.iter() {
+        let Some(section) = section else {
+            continue;
+        };
+        for (key, raw) in properties.iter() {
+            let raw = raw.trim();
+            // Empty ini values meant "use the default"
+            if raw.is_empty() {
+                continue;
+            }
+            let value = typed_value(&defaults, section, key, raw)
+                .ok_or_else(|| at_line(text, section, key, format!("Invalid {}", key)))?;
+            set_value(&mut document, section, key, value);
+        }
+    }
+    Ok(document)
+}
+
+/// A config value set from the environment
+struct EnvOverride {
+    /// Variable the value came from, only the name is ever logged
+    name: String,
+    section: String,
+    key: String,
+    value: Value,
+}
+
+/// Read values from the environment so secrets can stay out of the config file.
+/// Every key can be set with `MWP_<SECTION>_<KEY>`, such as `MWP_TWITCH_OAUTH_TOKEN`, and keys
+/// found in only one section can also be set with `MWP_<KEY>`, such as `MWP_CHANNEL_NAME`.
+/// Values from the environment are never logged. A value that doesn't fit its key is reported in
+/// `issues` and left out, so the file's value or the default is used instead.
+fn env_overrides(issues: &mut Vec<ConfigIssue>) -> Vec<EnvOverride> {
+    let defaults = default_document();
+    let keys: Vec<(&str, &str)> = defaults
+        .iter()
+        .filter_map(|(section, item)| item.as_table().map(|table| (section, table)))
+        .flat_map(|(section, table)| table.iter().map(move |(key, _)| (section, key)))
+        .collect();
+
+    let mut overrides = vec![];
+    for (section, key) in keys.iter() {
+        let unique = keys.iter().filter(|(_, other)| other == key).count() == 1;
+        let mut names = vec![format!("{}{}_{}", ENV_PREFIX, section.to_uppercase(), key)];
+        if unique {
+            names.push(format!("{}{}", ENV_PREFIX, key));
+        }
+        let Some((name, raw)) = names
+            .into_iter()
+            .find_map(|name| env::var(&name).ok().map(|raw| (name, raw)))
+        else {
+            continue;
+        };
+
+        let Some(value) = typed_value(&defaults, section, key, raw.trim()) else {
+            issues.push(ConfigIssue::new(section, key, format!("from {} isn't valid, it was ignored", name)));
+            continue;
+        };
+        overrides.push(EnvOverride {
+            name,
+            section: section.to_string(),
+            key: key.to_string(),
+            value,
+        });
+    }
+    overrides
+}
+
+/// Merge a named profile from the `[profile.<name>]` tables over the base settings
+pub(crate) fn apply_profile(text: &str, document: &mut DocumentMut, name: &str) -> Result<(), String> {
+    let profiles = document.get("profile").and_then(Item::as_table);
+    let Some(profile) = profiles
+        .and_then(|profiles| profiles.get(name))
+        .and_then(Item::as_table)
+        .cloned()
+    else {
+        let available: Vec<&str> = profiles
+            .map(|profiles| profiles.iter().map(|(name, _)| name).collect())
+            .unwrap_or_default();
+        return Err(if available.is_empty() {
+            format!("Unknown profile {}, the config doesn't define any profiles", name)
+        } else {
+            format!("Unknown profile {}, available profiles are {}", name, available.join(", "))
+        });
+    };
+
+    let defaults = default_document();
+    for (section, item) in profile.iter() {
+        let Some(table) = item.as_table() else {
+            continue;
+        };
+        let profile_section = format!("profile.{}.{}", name, section);
+        for (key, item) in table.iter() {
+            let known = defaults
+                .get(section)
+                .and_then(Item::as_table)
+                .is_some_and(|known| known.contains_key(key));
+            if !known {
+                warn!(
+                    "{}",
+                    at_line(text, &profile_section, key, format!("Unknown key {} in [{}]", key, profile_section))
+                );
+            }
+            if let Some(value) = item.as_value() {
+                set_value(document, section, key, value.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Work out which config file to use. Writes the default config on first run and
+/// migrates an old config.ini to config.toml when there's no config.toml yet.
+pub(crate) fn prepare_config_file(filename: &str) -> Result<String, String> {
+    let path = Path::new(filename);
+    let toml_path = path.with_extension("toml");
+    let ini_path = path.with_extension("ini");
+
+    if toml_path.exists() && (path == toml_path || !path.exists()) {
+        return Ok(toml_path.to_string_lossy().to_string());
+    }
+    if ini_path.exists() && !toml_path.exists() {
+        let text = fs::read_to_string(&ini_path)
+            .map_err(|err| format!("Failed to load {}: {}", ini_path.display(), err))?;
+        let document = ini_to_toml(&text)?;
+        fs::write(&toml_path, document.to_string())
+            .map_err(|err| format!("Failed to write {}: {}", toml_path.display(), err))?;
+        info!(
+            "Migrated {} to {}, edit {} from now on",
+            ini_path.display(),
+            toml_path.display(),
+            toml_path.display()
+        );
+        return Ok(toml_path.to_string_lossy().to_string());
+    }
+    // An ini was asked for by name even though a toml exists
+    if path.exists() {
+        return Ok(filename.to_string());
+    }
+
+    if let Some(dir) = toml_path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|err| format!("Failed to create {}: {}", dir.display(), err))?;
+    }
+    fs::write(&toml_path, DEFAULT_CONFIG)
+        .map_err(|err| format!("Failed to write a default config to {}: {}", toml_path.display(), err))?;
+    info!("No config found, wrote a default config to {}", toml_path.display());
+    Ok(toml_path.to_string_lossy().to_string())
+}
+
+/// Load config.toml, or an old config.ini which is read through the same TOML layout.
+/// A profile picked with `profile` or the PROFILE key is merged over the base settings, then
+/// environment variables override both, see `env_overrides` for their names.
+/// Problems with single values are logged and the value's default used instead, only a file
+/// that can't be read or parsed at all is an error.
+pub(crate) fn load_config(filename: &str, profile: Option<&str>) -> Result<Config, String> {
+    let text = fs::read_to_string(filename)
+        .map_err(|err| format!("Failed to load {}: {}", filename, err))?;
+
+    let mut document = if filename.ends_with(".ini") {
+        ini_to_toml(&text)?
+    } else {
+        text.parse::<DocumentMut>()
+            .map_err(|err| format!("Failed to parse {}: {}", filename, err))?
+    };
+    let mut issues = unknown_keys(&document);
+
+    // The command line picks the profile first, then the environment, then the file
+    let overrides = env_overrides(&mut issues);
+    let profile = profile
+        .map(str::to_string)
+        .or_else(|| {
+            overrides
+                .iter()
+                .find(|env| env.section == "General" && env.key == "PROFILE")
+                .and_then(|env| env.value.as_str().map(str::to_string))
+        })
+        .or_else(|| {
+            document
+                .get("General")
+                .and_then(|general| general.get("PROFILE"))
+                .and_then(Item::as_str)
+                .map(str::to_string)
+        })
+        .filter(|profile| !profile.trim().is_empty());
+    if let Some(profile) = &profile {
+        apply_profile(&text, &mut document, profile)?;
+    }
+    document.remove("profile");
+
+    for env in overrides {
+        set_value(&mut document, &env.section, &env.key, env.value);
+        info!("Using {} from the environment", env.name);
+    }
+
+    issues.extend(check_types(&mut document));
+    let file: ConfigFile = toml::from_str(&document.to_string())
+        .map_err(|err| format!("Invalid value in {}: {}", filename, err))?;
+    let mut config = file.into_config(&mut issues);
+    config.profile = profile;
+
+    let invalid = validate(&config);
+    use_defaults(&mut config, &invalid);
+    issues.extend(invalid);
+    if config.background_mode == BackgroundMode::Chroma {
+        config.bubble_color = avoid_key_color(config.bubble_color, config.key_color);
+        config.text_color = avoid_key_color(config.text_color, config.key_color);
+        config.shared_chat_border_color = avoid_key_color(config.shared_chat_border_color, config.key_color);
+        config.marquee_background = avoid_key_color(config.marquee_background, config.key_color);
+        config.floor_glow_quiet_color = avoid_key_color(config.floor_glow_quiet_color, config.key_color);
+        config.floor_glow_busy_color = avoid_key_color(config.floor_glow_busy_color, config.key_color);
+        config.footprint_tint = avoid_key_color(config.footprint_tint, config.key_color);
+    }
+    for issue in issues.iter() {
+        warn!("{}: {}", filename, issue.describe(&text));
+    }
+    Ok(config)
+}
+
+/// A config from TOML text rather than a file, for tests. The environment isn't read, keys left
+/// out keep their defaults and bad values are replaced by them as `load_config` does.
+pub(crate) fn config_from_str(text: &str) -> Result<Config, String> {
+    let mut document = text
+        .parse::<DocumentMut>()
+        .map_err(|err| format!("Failed to parse config: {}", err))?;
+    let mut issues = unknown_keys(&document);
+    issues.extend(check_types(&mut document));
+    let file: ConfigFile =
+        toml::from_str(&document.to_string()).map_err(|err| format!("Invalid value in config: {}", err))?;
+    let mut config = file.into_config(&mut issues);
+
+    let invalid = validate(&config);
+    use_defaults(&mut config, &invalid);
+    issues.extend(invalid);
+    for issue in issues.iter() {
+        warn!("{}", issue.describe(text));
+    }
+    Ok(config)
+}
+
+/// Round a float for writing back to the config file, f32 to f64 adds noise digits
+fn rounded(value: f32) -> Value {
+    ((value as f64 * 1000.0).round() / 1000.0).into()
+}
+
+/// Write the values the settings window can change back to the config file, keeping its comments.
+/// With a profile active the values go into the profile so the base settings are left alone.
+pub(crate) fn save_config(filename: &str, config: &Config) -> Result<(), String> {
+    if filename.ends_with(".ini") {
+        return Err("Saving is only supported for config.toml".to_string());
+    }
+    let text = fs::read_to_string(filename)
+        .map_err(|err| format!("Failed to load {}: {}", filename, err))?;
+    let mut document = text
+        .parse::<DocumentMut>()
+        .map_err(|err| format!("Failed to parse {}: {}", filename, err))?;
+
+    let values: [(&str, &str, Value); 11] = [
+        ("General", "SCALE", rounded(config.scale)),
+        ("General", "OPACITY", rounded(config.opacity)),
+        ("Avatars", "FLOOR_OFFSET", rounded(config.floor_offset)),
+        ("Avatars", "AVATAR_MOVE_SPEED", rounded(config.avatar_move_speed)),
+        ("Messages", "FONT_SIZE", rounded(config.font_size)),
+        ("Messages", "MESSAGE_BOX_WIDTH", rounded(config.message_box_width)),
+        (
+            "Messages",
+            "MESSAGE_DESPAWN_TIME_MILIS",
+            (config.message_despawn_time.as_millis() as i64).into(),
+        ),
+        ("Messages", "BUBBLE_COLOR", hex(config.bubble_color).into()),
+        ("Messages", "TEXT_COLOR", hex(config.text_color).into()),
+        ("SharedChat", "SHOW_SHARED_CHAT", config.show_shared_chat.into()),
+        ("SharedChat", "BORDER_COLOR", hex(config.shared_chat_border_color).into()),
+    ];
+    for (section, key, value) in values {
+        match &config.profile {
+            Some(profile) => document["profile"][profile.as_str()][section][key] = Item::Value(value),
+            None => set_value(&mut document, section, key, value),
+        }
+    }
+
+    fs::write(filename, document.to_string())
+        .map_err(|err| format!("Failed to write {}: {}", filename, err))
+}
+
+/// Hex form of a colour as used in the config file
+fn hex(color: Color) -> String {
+    color.to_srgba().to_hex().trim_start_matches('#').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A config with nothing wrong with it, the defaults leave the channel empty
+    fn valid_config() -> Config {
+        let mut config = default_config();
+        config.channel_name = "minawan".to_string();
+        config
+    }
+
+    /// Configs that parse but are broken in one value, with the key that should be reported
+    const MALFORMED: [(&str, &str, fn(&mut Config)); 16] = [
+        ("General", "SCALE", |config| config.scale = 0.0),
+        ("General", "SCALE", |config| config.scale = 25.0),
+        ("General", "FPS_CAP", |config| config.fps_cap = -30.0),
+        ("General", "IDLE_FPS", |config| config.idle_fps = 0.0),
+        ("General", "OPACITY", |config| config.opacity = 0.01),
+        ("Avatars", "ACTION_DURATION_MILIS", |config| config.action_duration = Duration::ZERO),
+        ("Avatars", "AVATAR_MOVE_SPEED", |config| config.avatar_move_speed = -1.0),
+        ("Avatars", "EDGE_BUFFER", |config| config.edge_buffer = -10.0),
+        ("StageProps", "BEHIND_OPACITY", |config| config.stage_prop_behind_opacity = 1.5),
+        ("Messages", "FONT_SIZE", |config| config.font_size = 0.0),
+        ("Messages", "MESSAGE_DESPAWN_TIME_MILIS", |config| config.message_despawn_time = Duration::ZERO),
+        ("Output", "WIDTH", |config| config.output_width = 0),
+        ("Replies", "PARTY_COMMAND", |config| config.party_command = String::new()),
+        ("Pyramids", "MIN_HEIGHT", |config| config.pyramid_min_height = 1),
+        ("Ambient", "STRENGTH", |config| config.ambient_strength = -0.5),
+        ("FrameBudget", "FAST_FRAME_MILIS", |config| {
+            config.frame_budget_fast = config.frame_budget_slow + Duration::from_millis(1)
+        }),
+    ];
+
+    #[test]
+    fn valid_config_has_no_issues() {
+        assert_eq!(validate(&valid_config()), vec![]);
+    }
+
+    #[test]
+    fn each_malformed_value_is_reported() {
+        for (section, key, break_config) in MALFORMED {
+            let mut config = valid_config();
+            break_config(&mut config);
+            let issues = validate(&config);
+            assert_eq!(issues.len(), 1, "[{}] {}: {:?}", section, key, issues);
+            assert_eq!((issues[0].section.as_str(), issues[0].key.as_str()), (section, key));
+            assert!(issues[0].problem.ends_with("using the default"), "{:?}", issues[0]);
+        }
+    }
+
+    #[test]
+    fn malformed_values_are_replaced_by_their_defaults() {
+        for (section, key, break_config) in MALFORMED {
+            let mut config = valid_config();
+            break_config(&mut config);
+            let issues = validate(&config);
+            use_defaults(&mut config, &issues);
+            assert!(config == valid_config(), "[{}] {} kept its bad value", section, key);
+        }
+    }
+
+    #[test]
+    fn empty_channel_name_is_reported() {
+        let issues = validate(&default_config());
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].section, "Channel");
+        assert_eq!(issues[0].key, "CHANNEL_NAME");
+    }
+
+    #[test]
+    fn unknown_keys_and_sections_are_reported() {
+        let text = "
+[Avatars]
+AVATAR_MOVE_SPED = 100.0
+
+[Avatar]
+AVATAR_MOVE_SPEED = 100.0
+";
+        let document = text.parse::<DocumentMut>().unwrap();
+        let issues = unknown_keys(&document);
+        assert_eq!(issues, vec![
+            ConfigIssue::new("Avatars", "AVATAR_MOVE_SPED", "is not a known key, ignoring it"),
+            ConfigIssue::new("Avatar", "", "is not a known section, ignoring it"),
+        ]);
+    }
+
+    #[test]
+    fn negative_durations_and_wrong_types_are_removed() {
+        let text = r#"
+[Avatars]
+USER_DESPAWN_TIME_SECS = -60
+
+[General]
+SCALE = "big"
+
+[Window]
+WINDOW_X = -1920
+"#;
+        let mut document = text.parse::<DocumentMut>().unwrap();
+        let issues = check_types(&mut document);
+        assert_eq!(issues, vec![
+            ConfigIssue::new("General", "SCALE", "should be a float but is a string, using the default"),
+            ConfigIssue::new("Avatars", "USER_DESPAWN_TIME_SECS", "must not be negative, using the default"),
+        ]);
+        assert!(document["Avatars"].get("USER_DESPAWN_TIME_SECS").is_none());
+        assert!(document["General"].get("SCALE").is_none());
+        // Window positions can be left of or above the main monitor
+        assert_eq!(document["Window"]["WINDOW_X"].as_integer(), Some(-1920));
+    }
+
+    #[test]
+    fn whole_numbers_for_decimal_keys_are_accepted() {
+        let mut document = "[General]\nSCALE = 2\n".parse::<DocumentMut>().unwrap();
+        assert_eq!(check_types(&mut document), vec![]);
+        assert_eq!(document["General"]["SCALE"].as_float(), Some(2.0));
     }
 }
@@ -0,0 +1,135 @@
+use std::{
+    backtrace::Backtrace,
+    env::consts::{ARCH, OS},
+    fs, io, panic,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+};
+
+use chrono::Local;
+use toml_edit::{DocumentMut, TableLike};
+
+use crate::{logging::recent_log_lines, paths::data_dir};
+
+/// Folder in the data dir crash reports are written to
+const CRASH_DIR: &str = "crashes";
+/// Keys left out of crash reports, people attach these to public issues
+const SECRET_KEYS: [&str; 3] = ["OAUTH_TOKEN", "API_KEY", "TOKEN"];
+
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+/// Set by the first panic, the ones that follow on other threads don't get a report of their own
+static CRASHED: AtomicBool = AtomicBool::new(false);
+
+/// Write a crash report and tell the user where it is when anything panics. Without a console
+/// the overlay would otherwise just vanish.
+pub(crate) fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        if CRASHED.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let message = info.to_string();
+        let text = match write_report(&crash_report(&message)) {
+            Ok(path) => format!(
+                "Minawan Watch Party crashed.\n\nA crash report was saved to\n{}\n\nPlease attach it when reporting the problem.",
+                path.display()
+            ),
+            Err(err) => format!(
+                "Minawan Watch Party crashed and the crash report couldn't be saved: {}\n\n{}",
+                err, message
+            ),
+        };
+        show_crash_dialog(&text);
+    }));
+}
+
+/// Config file to include in crash reports, once it's known which one is used
+pub(crate) fn set_crash_config_path(path: &str) {
+    let _ = CONFIG_PATH.set(PathBuf::from(path));
+}
+
+fn crash_report(message: &str) -> String {
+    let mut report = format!(
+        "Minawan Watch Party {} on {} {}\nCrashed at {}\n\n{}\n\nBacktrace:\n{}\n",
+        env!("CARGO_PKG_VERSION"),
+        OS,
+        ARCH,
+        Local::now().to_rfc3339(),
+        message,
+        Backtrace::force_capture(),
+    );
+    report.push_str("\nConfig:\n");
+    report.push_str(&sanitized_config());
+    report.push_str("\nLast log lines:\n");
+    for line in recent_log_lines() {
+        report.push_str(&line);
+        report.push('\n');
+    }
+    report
+}
+
+/// The config file with secrets hidden
+fn sanitized_config() -> String {
+    let Some(path) = CONFIG_PATH.get() else {
+        return "Not loaded yet\n".to_string();
+    };
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) => return format!("Couldn't read {}: {}\n", path.display(), err),
+    };
+    // Leaving out a config that doesn't parse beats leaking a token from it
+    let mut document = match text.parse::<DocumentMut>() {
+        Ok(document) => document,
+        Err(err) => return format!("{} doesn't parse, left out: {}\n", path.display(), err),
+    };
+    hide_secrets(document.as_table_mut());
+    document.to_string()
+}
+
+fn hide_secrets(table: &mut dyn TableLike) {
+    for (key, item) in table.iter_mut() {
+        if let Some(inner) = item.as_table_like_mut() {
+            hide_secrets(inner);
+        } else if SECRET_KEYS.contains(&key.get()) && item.as_str().is_some_and(|value| !value.is_empty()) {
+            *item = toml_edit::value("<hidden>");
+        }
+    }
+}
+
+fn write_report(report: &str) -> io::Result<PathBuf> {
+    let dir = data_dir().join(CRASH_DIR);
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(Local::now().format("crash-%Y%m%d-%H%M%S.txt").to_string());
+    fs::write(&path, report)?;
+    Ok(path)
+}
+
+#[cfg(windows)]
+fn show_crash_dialog(text: &str) {
+    use windows::{
+        core::HSTRING,
+        Win32::{
+            Foundation::HWND,
+            UI::WindowsAndMessaging::{MessageBoxW, MB_ICONERROR, MB_OK},
+        },
+    };
+    unsafe {
+        MessageBoxW(
+            HWND::default(),
+            &HSTRING::from(text),
+            &HSTRING::from("Minawan Watch Party"),
+            MB_OK | MB_ICONERROR,
+        );
+    }
+}
+
+// Started from a terminal or a desktop that shows stderr
+#[cfg(not(windows))]
+fn show_crash_dialog(text: &str) {
+    eprintln!("{}", text);
+}
@@ -0,0 +1,235 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    asset::AssetServer,
+    color::{Alpha, Color},
+    prelude::{
+        default, BackgroundColor, BuildChildren, Commands, Component, Local, NodeBundle, Query, Real,
+        Res, ResMut, Resource, Time, Visibility, With,
+    },
+    text::{Text, TextStyle},
+    ui::{node_bundles::TextBundle, AlignItems, FlexDirection, Style, Val},
+};
+
+use crate::{
+    chat::dedupe::RecentMessages, config::Config, downloads::Downloads, frame_budget::FrameBudget,
+    pool::EntityPools, widgets::corner_style, AppState, ChatReceiver, EmoteSprite, EmoteStorage, MessageBubble,
+    UserMarker,
+};
+
+/// How often the numbers are redrawn, frame times are still sampled every frame
+const DEBUG_REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+/// Frames shown in the frame time graph
+const GRAPH_SAMPLES: usize = 120;
+/// Frame time that fills the graph's height, anything slower is cut off
+const GRAPH_MAX_FRAME_TIME: f32 = 0.05;
+const GRAPH_HEIGHT: f32 = 40.0;
+const GRAPH_BAR_WIDTH: f32 = 2.0;
+/// Frames slower than this are drawn in red
+const SLOW_FRAME_TIME: f32 = 1.0 / 30.0;
+
+/// Whether the debug overlay is showing, flipped by its hotkey
+#[derive(Resource, Default)]
+pub(crate) struct DebugOverlay {
+    pub(crate) visible: bool,
+}
+
+/// Marker for the debug overlay's root node
+#[derive(Component)]
+pub(crate) struct DebugOverlayRoot {}
+
+/// Marker for the debug overlay's text
+#[derive(Component)]
+pub(crate) struct DebugText {}
+
+/// One bar of the frame time graph, 0 is the oldest frame
+#[derive(Component)]
+pub(crate) struct DebugGraphBar(usize);
+
+/// Run condition for the systems that only matter while the overlay is showing
+pub(crate) fn debug_overlay_visible(overlay: Res<DebugOverlay>) -> bool {
+    overlay.visible
+}
+
+// Spawn the debug overlay, hidden unless SHOW_ON_START is set
+pub(crate) fn setup_debug_overlay(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<Config>,
+    mut overlay: ResMut<DebugOverlay>,
+) {
+    overlay.visible = config.debug_show_on_start;
+
+    let mut style = corner_style(config.debug_corner);
+    style.flex_direction = FlexDirection::Column;
+    commands
+        .spawn((
+            NodeBundle {
+                style,
+                background_color: Color::BLACK.with_alpha(0.6).into(),
+                visibility: if overlay.visible { Visibility::Visible } else { Visibility::Hidden },
+                ..default()
+            },
+            DebugOverlayRoot {},
+        ))
+        .with_children(|builder| {
+            builder.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        // Monospace keeps the columns from jumping around as the numbers change
+                        font: asset_server.load(&config.debug_font_url),
+                        font_size: config.debug_font_size,
+                        color: Color::WHITE,
+                    },
+                ),
+                DebugText {},
+            ));
+            builder
+                .spawn(NodeBundle {
+                    style: Style {
+                        height: Val::Px(GRAPH_HEIGHT),
+                        align_items: AlignItems::FlexEnd,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|graph| {
+                    for index in 0..GRAPH_SAMPLES {
+                        graph.spawn((
+                            NodeBundle {
+                                style: Style {
+                                    width: Val::Px(GRAPH_BAR_WIDTH),
+                                    height: Val::Px(0.0),
+                                    ..default()
+                                },
+                                background_color: Color::WHITE.into(),
+                                ..default()
+                            },
+                            DebugGraphBar(index),
+                        ));
+                    }
+                });
+        });
+}
+
+// Show or hide the overlay when its hotkey flips it
+pub(crate) fn show_debug_overlay(
+    overlay: Res<DebugOverlay>,
+    mut root_query: Query<&mut Visibility, With<DebugOverlayRoot>>,
+) {
+    for mut visibility in root_query.iter_mut() {
+        *visibility = if overlay.visible {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+// Sample the frame time and redraw the overlay a few times a second. Only runs while it's showing.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn update_debug_overlay(
+    overlay: Res<DebugOverlay>,
+    time: Res<Time<Real>>,
+    emote_store: Res<EmoteStorage>,
+    chat_receiver: Res<ChatReceiver>,
+    recent_messages: Res<RecentMessages>,
+    downloads: Res<Downloads>,
+    pools: Res<EntityPools>,
+    app_state: Res<AppState>,
+    budget: Res<FrameBudget>,
+    avatar_query: Query<(), With<UserMarker>>,
+    bubble_query: Query<(), With<MessageBubble>>,
+    emote_query: Query<(), With<EmoteSprite>>,
+    mut text_query: Query<&mut Text, With<DebugText>>,
+    mut bar_query: Query<(&DebugGraphBar, &mut Style, &mut BackgroundColor)>,
+    mut frame_times: Local<VecDeque<f32>>,
+    mut last_update: Local<Option<Instant>>,
+) {
+    // Frames from before it was last hidden would show up as one long gap
+    if overlay.is_changed() {
+        frame_times.clear();
+    }
+    if frame_times.len() == GRAPH_SAMPLES {
+        frame_times.pop_front();
+    }
+    frame_times.push_back(time.delta_seconds());
+
+    if last_update.is_some_and(|time| time.elapsed() < DEBUG_REFRESH_INTERVAL) {
+        return;
+    }
+    *last_update = Some(Instant::now());
+
+    let average = frame_times.iter().sum::<f32>() / frame_times.len() as f32;
+    let slowest = frame_times.iter().copied().fold(0.0, f32::max);
+    let fps = if average > 0.0 { 1.0 / average } else { 0.0 };
+    let download_stats = downloads.0.stats();
+    let shed: Vec<&str> = budget.shed().iter().map(|cosmetic| cosmetic.name()).collect();
+    let last_change = budget.last_change().map_or(String::new(), |(time, change)| {
+        format!("\nlast: {} ({}s ago)", change, time.elapsed().as_secs())
+    });
+    let lines = [
+        format!("fps {:>6.1}  frame {:>5.1}ms  max {:>5.1}ms", fps, average * 1000.0, slowest * 1000.0),
+        format!(
+            "avatars {:>4}  bubbles {:>4}  emotes {:>4}",
+            avatar_query.iter().count(),
+            bubble_query.iter().count(),
+            emote_query.iter().count(),
+        ),
+        format!(
+            "emote store: {} known, {} loaded, {} in atlas",
+            emote_store.all.len(),
+            emote_store.loaded.len(),
+            emote_store.atlas.len(),
+        ),
+        format!(
+            "chat queue {:>3}  duplicates dropped {}",
+            chat_receiver.receiver.len(),
+            recent_messages.dropped,
+        ),
+        format!(
+            "spawn queue {:>3}  left out {}",
+            app_state.spawn_queue.len(),
+            app_state.spawn_queue.dropped(),
+        ),
+        format!(
+            "downloads: {} queued, {} in flight, {} failed",
+            download_stats.queued, download_stats.in_flight, download_stats.failed,
+        ),
+        format!(
+            "pools: avatars {} free ({:.0}% reused), bubbles {} free ({:.0}% reused)",
+            pools.avatars.len(),
+            pools.avatars.hit_rate() * 100.0,
+            pools.bubbles.len(),
+            pools.bubbles.hit_rate() * 100.0,
+        ),
+        format!(
+            "budget {}  shed: {}{}",
+            budget
+                .average()
+                .map_or("   --".to_string(), |average| format!("{:>5.1}ms", average * 1000.0)),
+            if shed.is_empty() { "nothing".to_string() } else { shed.join(", ") },
+            last_change,
+        ),
+    ];
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = lines.join("\n");
+    }
+
+    // Newest frame on the right, bars before the first sample stay empty
+    let missing = GRAPH_SAMPLES - frame_times.len();
+    for (bar, mut style, mut color) in bar_query.iter_mut() {
+        let frame_time = bar.0.checked_sub(missing).map_or(0.0, |index| frame_times[index]);
+        style.height = Val::Px(GRAPH_HEIGHT * (frame_time / GRAPH_MAX_FRAME_TIME).min(1.0));
+        *color = if frame_time > SLOW_FRAME_TIME {
+            Color::srgb(1.0, 0.3, 0.3)
+        } else {
+            Color::WHITE
+        }
+        .into();
+    }
+}
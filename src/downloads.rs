@@ -0,0 +1,291 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use bevy::{
+    app::{App, Plugin},
+    asset::{
+        io::{AssetReader, AssetReaderError, AssetSource, AssetSourceId, PathStream, Reader, VecReader},
+        AssetApp, AssetServer, LoadState,
+    },
+    prelude::{Changed, Handle, Image, Query, Res, Resource},
+};
+use log::{debug, warn};
+use tokio::{
+    runtime::Handle as RuntimeHandle,
+    sync::{oneshot, Semaphore},
+    time::{sleep_until, Instant},
+};
+use vleue_kinetoscope::AnimatedImage;
+
+use crate::config::Config;
+
+/// Size assumed for a response that doesn't say how big it is
+const UNKNOWN_SIZE: usize = 256 * 1024;
+
+/// A download waiting for a free slot
+struct Waiter {
+    url: String,
+    ready: oneshot::Sender<()>,
+}
+
+/// Slots in use and the downloads queued for one
+#[derive(Default)]
+struct Slots {
+    in_use: usize,
+    queue: VecDeque<Waiter>,
+}
+
+/// Limits how many web assets download at once, how quickly one host is asked for more and
+/// how many bytes can be in flight. Shared between Bevy and the asset readers.
+pub(crate) struct DownloadGovernor {
+    max_concurrent: usize,
+    host_delay: Duration,
+    byte_budget: usize,
+    slots: Mutex<Slots>,
+    bytes: Semaphore,
+    /// When each host may next be asked for something
+    next_request: Mutex<HashMap<String, Instant>>,
+    /// Assets something on screen is waiting for, these skip the queue
+    visible: Mutex<HashSet<String>>,
+    queued: AtomicUsize,
+    in_flight: AtomicUsize,
+    failed: AtomicUsize,
+}
+
+/// Snapshot of the governor's counters
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct DownloadStats {
+    pub(crate) queued: usize,
+    pub(crate) in_flight: usize,
+    pub(crate) failed: usize,
+}
+
+impl DownloadGovernor {
+    pub(crate) fn from_config(config: &Config) -> Self {
+        let byte_budget = config.download_max_in_flight_kb.saturating_mul(1024).max(1);
+        Self {
+            max_concurrent: config.download_max_concurrent.max(1),
+            host_delay: config.download_host_delay,
+            byte_budget,
+            slots: Mutex::new(Slots::default()),
+            bytes: Semaphore::new(byte_budget),
+            next_request: Mutex::new(HashMap::new()),
+            visible: Mutex::new(HashSet::new()),
+            queued: AtomicUsize::new(0),
+            in_flight: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn stats(&self) -> DownloadStats {
+        DownloadStats {
+            queued: self.queued.load(Ordering::Relaxed),
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Move a queued download ahead of the ones nothing is showing yet
+    fn prioritize(&self, url: String) {
+        self.visible.lock().unwrap().insert(url);
+    }
+
+    /// Wait for a free slot
+    async fn acquire(&self, url: &str) {
+        let ready = {
+            let mut slots = self.slots.lock().unwrap();
+            if slots.in_use < self.max_concurrent {
+                slots.in_use += 1;
+                return;
+            }
+            let (ready, wait) = oneshot::channel();
+            slots.queue.push_back(Waiter {
+                url: url.to_string(),
+                ready,
+            });
+            wait
+        };
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        // The slot is handed over by `release`, so it's already counted
+        let _ = ready.await;
+        self.queued.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Hand the slot to the next download, visible ones first and otherwise in order
+    fn release(&self) {
+        let visible = self.visible.lock().unwrap();
+        let mut slots = self.slots.lock().unwrap();
+        loop {
+            let next = slots
+                .queue
+                .iter()
+                .position(|waiter| visible.contains(&waiter.url))
+                .or((!slots.queue.is_empty()).then_some(0));
+            let Some(next) = next else {
+                slots.in_use -= 1;
+                return;
+            };
+            let waiter = slots.queue.remove(next).unwrap();
+            // A cancelled load no longer needs the slot
+            if waiter.ready.send(()).is_ok() {
+                return;
+            }
+        }
+    }
+
+    /// Wait until the host has had a break since the last request to it
+    async fn wait_for_host(&self, host: &str) {
+        let start = {
+            let mut next_request = self.next_request.lock().unwrap();
+            let now = Instant::now();
+            let start = next_request.get(host).map_or(now, |next| (*next).max(now));
+            next_request.insert(host.to_string(), start + self.host_delay);
+            start
+        };
+        sleep_until(start).await;
+    }
+
+    async fn download(&self, url: &str) -> Result<Vec<u8>, AssetReaderError> {
+        let host = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default();
+        self.wait_for_host(&host).await;
+
+        let response = reqwest::get(url).await.map_err(io_error)?;
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Err(AssetReaderError::NotFound(url.into()));
+        }
+        if !status.is_success() {
+            return Err(AssetReaderError::HttpError(status.as_u16()));
+        }
+
+        // Big downloads wait for room in the byte budget, anything over it goes through alone
+        let size = response
+            .content_length()
+            .map_or(UNKNOWN_SIZE, |length| length as usize)
+            .clamp(1, self.byte_budget);
+        let _bytes = self
+            .bytes
+            .acquire_many(size.min(u32::MAX as usize) as u32)
+            .await
+            .map_err(io_error)?;
+        Ok(response.bytes().await.map_err(io_error)?.to_vec())
+    }
+
+    async fn fetch(&self, url: String) -> Result<Vec<u8>, AssetReaderError> {
+        self.acquire(&url).await;
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        let result = self.download(&url).await;
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+        self.visible.lock().unwrap().remove(&url);
+        self.release();
+
+        if let Err(err) = &result {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+            warn!("Failed to download {}: {}", url, err);
+        }
+        result
+    }
+}
+
+fn io_error(err: impl ToString) -> AssetReaderError {
+    AssetReaderError::Io(Arc::new(io::Error::new(io::ErrorKind::Other, err.to_string())))
+}
+
+/// Reads http and https assets through the governor
+struct GovernedReader {
+    scheme: &'static str,
+    governor: Arc<DownloadGovernor>,
+    runtime: RuntimeHandle,
+}
+
+impl AssetReader for GovernedReader {
+    async fn read<'a>(&'a self, path: &'a Path) -> Result<Box<Reader<'a>>, AssetReaderError> {
+        let url = format!("{}://{}", self.scheme, path.display());
+        debug!("Downloading {}", url);
+        // Asset loading doesn't run on tokio, which reqwest needs
+        let governor = self.governor.clone();
+        let bytes = self
+            .runtime
+            .spawn(async move { governor.fetch(url).await })
+            .await
+            .map_err(io_error)??;
+        let reader: Box<Reader<'a>> = Box::new(VecReader::new(bytes));
+        Ok(reader)
+    }
+
+    async fn read_meta<'a>(&'a self, path: &'a Path) -> Result<Box<Reader<'a>>, AssetReaderError> {
+        // Web assets never come with .meta files, don't ask the host for them
+        Err(AssetReaderError::NotFound(path.to_path_buf()))
+    }
+
+    async fn read_directory<'a>(&'a self, path: &'a Path) -> Result<Box<PathStream>, AssetReaderError> {
+        Err(AssetReaderError::NotFound(path.to_path_buf()))
+    }
+
+    async fn is_directory<'a>(&'a self, _path: &'a Path) -> Result<bool, AssetReaderError> {
+        Ok(false)
+    }
+}
+
+/// Lets anything in Bevy see the governor's counters
+#[derive(Resource, Clone)]
+pub(crate) struct Downloads(pub(crate) Arc<DownloadGovernor>);
+
+/// Loads http and https assets through a `DownloadGovernor`. Has to be added before
+/// DefaultPlugins, from inside the tokio runtime.
+pub struct DownloadPlugin {
+    pub(crate) governor: Arc<DownloadGovernor>,
+}
+
+impl Plugin for DownloadPlugin {
+    fn build(&self, app: &mut App) {
+        let runtime = RuntimeHandle::current();
+        for scheme in ["http", "https"] {
+            let governor = self.governor.clone();
+            let runtime = runtime.clone();
+            app.register_asset_source(
+                AssetSourceId::Name(scheme.into()),
+                AssetSource::build().with_reader(move || {
+                    Box::new(GovernedReader {
+                        scheme,
+                        governor: governor.clone(),
+                        runtime: runtime.clone(),
+                    })
+                }),
+            );
+        }
+        app.insert_resource(Downloads(self.governor.clone()));
+    }
+}
+
+// Put downloads for images that just appeared on screen ahead of everything else
+pub(crate) fn prioritize_visible_downloads(
+    downloads: Res<Downloads>,
+    asset_server: Res<AssetServer>,
+    images: Query<&Handle<Image>, Changed<Handle<Image>>>,
+    animated_images: Query<&Handle<AnimatedImage>, Changed<Handle<AnimatedImage>>>,
+) {
+    let ids = images
+        .iter()
+        .map(|handle| handle.id().untyped())
+        .chain(animated_images.iter().map(|handle| handle.id().untyped()));
+    for id in ids {
+        if !matches!(asset_server.get_load_state(id), Some(LoadState::Loading)) {
+            continue;
+        }
+        if let Some(path) = asset_server.get_path(id) {
+            downloads.0.prioritize(path.to_string());
+        }
+    }
+}
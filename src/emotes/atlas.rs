@@ -0,0 +1,213 @@
+use bevy::{
+    asset::{Assets, Handle},
+    math::{URect, UVec2},
+    prelude::{Image, ResMut, Resource},
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    },
+    sprite::TextureAtlasLayout,
+    utils::HashSet,
+};
+use log::{debug, info};
+
+use crate::{emotes::emote_types::AtlasRegion, EmoteStorage};
+
+/// Width and height of each atlas texture
+const ATLAS_SIZE: u32 = 2048;
+/// Atlas textures to make before leaving further emotes as their own textures
+const MAX_PAGES: usize = 4;
+/// Transparent gap around each emote so filtering never picks up a neighbour
+const PADDING: u32 = 1;
+
+/// A row of emotes, as tall as the emote that started it
+struct Shelf {
+    y: u32,
+    height: u32,
+    /// Where the next emote on this shelf goes
+    x: u32,
+}
+
+/// One atlas texture and the space left in it
+struct AtlasPage {
+    image: Handle<Image>,
+    layout: Handle<TextureAtlasLayout>,
+    shelves: Vec<Shelf>,
+    /// Space reserved for each layout index, padding included
+    slots: Vec<URect>,
+    /// Layout indexes whose emote was evicted, their slots can be reused
+    free: Vec<usize>,
+}
+
+impl AtlasPage {
+    fn new(images: &mut Assets<Image>, layouts: &mut Assets<TextureAtlasLayout>) -> Self {
+        let image = Image::new_fill(
+            Extent3d {
+                width: ATLAS_SIZE,
+                height: ATLAS_SIZE,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &[0, 0, 0, 0],
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        );
+        Self {
+            image: images.add(image),
+            layout: layouts.add(TextureAtlasLayout::new_empty(UVec2::splat(ATLAS_SIZE))),
+            shelves: vec![],
+            slots: vec![],
+            free: vec![],
+        }
+    }
+
+    /// Find room for an emote of `size`, returning the layout index and the space taken
+    fn allocate(&mut self, size: UVec2, layout: &mut TextureAtlasLayout) -> Option<(usize, URect)> {
+        let padded = size + UVec2::splat(PADDING * 2);
+
+        // Evicted slots first, any slot big enough will do
+        if let Some(position) = self
+            .free
+            .iter()
+            .position(|index| self.slots[*index].size().cmpge(padded).all())
+        {
+            let index = self.free.swap_remove(position);
+            let rect = URect::from_corners(self.slots[index].min, self.slots[index].min + padded);
+            layout.textures[index] = inset(rect);
+            return Some((index, rect));
+        }
+
+        let rect = match self
+            .shelves
+            .iter_mut()
+            .find(|shelf| shelf.height >= padded.y && shelf.x + padded.x <= ATLAS_SIZE)
+        {
+            Some(shelf) => {
+                let rect = URect::new(shelf.x, shelf.y, shelf.x + padded.x, shelf.y + padded.y);
+                shelf.x += padded.x;
+                rect
+            }
+            None => {
+                let y = self.shelves.last().map_or(0, |shelf| shelf.y + shelf.height);
+                if y + padded.y > ATLAS_SIZE {
+                    return None;
+                }
+                self.shelves.push(Shelf {
+                    y,
+                    height: padded.y,
+                    x: padded.x,
+                });
+                URect::new(0, y, padded.x, y + padded.y)
+            }
+        };
+        self.slots.push(rect);
+        Some((layout.add_texture(inset(rect)), rect))
+    }
+}
+
+/// The part of a slot the emote is drawn from
+fn inset(slot: URect) -> URect {
+    URect::from_corners(slot.min + UVec2::splat(PADDING), slot.max - UVec2::splat(PADDING))
+}
+
+/// Packs static emotes into a few large textures so they draw without a texture switch each
+#[derive(Resource, Default)]
+pub(crate) struct EmoteAtlas {
+    pages: Vec<AtlasPage>,
+    /// Emotes too big for a page or left over once every page is full
+    unpacked: HashSet<String>,
+}
+
+impl EmoteAtlas {
+    /// Give an evicted emote's space back. Its sprites must be gone, the next emote packed
+    /// into the space overwrites it.
+    #[allow(dead_code)]
+    pub(crate) fn release(&mut self, region: &AtlasRegion) {
+        if let Some(page) = self.pages.get_mut(region.page) {
+            page.free.push(region.index);
+        }
+    }
+}
+
+// Copy static emotes into the atlas once their images have loaded. Messages already showing an
+// emote keep their own texture, messages after this draw it from the atlas.
+pub(crate) fn pack_emote_atlas(
+    mut atlas: ResMut<EmoteAtlas>,
+    mut emote_store: ResMut<EmoteStorage>,
+    mut images: ResMut<Assets<Image>>,
+    mut layouts: ResMut<Assets<TextureAtlasLayout>>,
+) {
+    let waiting: Vec<(String, Handle<Image>)> = emote_store
+        .loaded
+        .iter()
+        .filter(|(name, _)| !emote_store.atlas.contains_key(*name) && !atlas.unpacked.contains(*name))
+        .filter_map(|(name, handles)| Some((name.clone(), handles.static_image.clone()?)))
+        .collect();
+
+    for (name, handle) in waiting {
+        let Some(image) = images.get(&handle) else {
+            continue;
+        };
+        let Some(image) = image.convert(TextureFormat::Rgba8UnormSrgb) else {
+            debug!("Can't pack emote {}, unsupported texture format", name);
+            atlas.unpacked.insert(name);
+            continue;
+        };
+        let size = image.size();
+        if size.cmpgt(UVec2::splat(ATLAS_SIZE - PADDING * 2)).any() {
+            debug!("Emote {} is too big for the atlas", name);
+            atlas.unpacked.insert(name);
+            continue;
+        }
+
+        let mut allocation = None;
+        for (page_index, page) in atlas.pages.iter_mut().enumerate() {
+            let Some(layout) = layouts.get_mut(&page.layout) else {
+                continue;
+            };
+            if let Some((index, rect)) = page.allocate(size, layout) {
+                allocation = Some((page_index, index, rect));
+                break;
+            }
+        }
+        if allocation.is_none() && atlas.pages.len() < MAX_PAGES {
+            let mut page = AtlasPage::new(&mut images, &mut layouts);
+            if let Some(layout) = layouts.get_mut(&page.layout) {
+                allocation = page.allocate(size, layout).map(|(index, rect)| (atlas.pages.len(), index, rect));
+            }
+            info!("Started emote atlas page {}", atlas.pages.len() + 1);
+            atlas.pages.push(page);
+        }
+        let Some((page_index, index, rect)) = allocation else {
+            debug!("No room in the emote atlas for {}", name);
+            atlas.unpacked.insert(name);
+            continue;
+        };
+
+        let page = &atlas.pages[page_index];
+        let Some(page_image) = images.get_mut(&page.image) else {
+            continue;
+        };
+        // Clear the padding too, a reused slot still has the old emote in it
+        let offset = |x: u32, y: u32| (y as usize * ATLAS_SIZE as usize + x as usize) * 4;
+        for y in rect.min.y..rect.max.y {
+            page_image.data[offset(rect.min.x, y)..offset(rect.max.x, y)].fill(0);
+        }
+        let inner = inset(rect);
+        let row_bytes = size.x as usize * 4;
+        for row in 0..size.y {
+            let source = row as usize * row_bytes;
+            let target = offset(inner.min.x, inner.min.y + row);
+            page_image.data[target..target + row_bytes].copy_from_slice(&image.data[source..source + row_bytes]);
+        }
+        emote_store.atlas.insert(
+            name,
+            AtlasRegion {
+                image: page.image.clone(),
+                layout: page.layout.clone(),
+                page: page_index,
+                index,
+            },
+        );
+    }
+}
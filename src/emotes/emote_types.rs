@@ -1,4 +1,4 @@
-use bevy::{asset::Handle, prelude::Image, render::texture::ImageFormat};
+use bevy::{asset::Handle, prelude::Image, render::texture::ImageFormat, sprite::TextureAtlasLayout};
 use serde::{Deserialize, Serialize};
 use vleue_kinetoscope::AnimatedImage;
 
@@ -13,6 +13,14 @@ pub(crate) struct EmoteHandles {
     pub(crate) static_image: Option<Handle<Image>>,
 }
 
+/// Where a static emote was packed into the emote atlas
+pub(crate) struct AtlasRegion {
+    pub(crate) image: Handle<Image>,
+    pub(crate) layout: Handle<TextureAtlasLayout>,
+    pub(crate) page: usize,
+    pub(crate) index: usize,
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct Emote {
     pub(crate) _id: String,
@@ -57,6 +65,21 @@ impl From<twitch_irc::message::Emote> for Emote {
     }
 }
 
+impl Emote {
+    /// Kick emotes are referenced inline as `[emote:id:name]`
+    pub(crate) fn from_kick(id: &str, name: &str) -> Self {
+        Self {
+            _id: id.to_string(),
+            name: name.to_string(),
+            animated: false,
+            emote_url: format!("https://files.kick.com/emotes/{}/fullsize", id),
+            format: None,
+            width: None,
+            height: None,
+        }
+    }
+}
+
 impl From<RawSevenTVEmote> for Emote {
     fn from(raw_emote: RawSevenTVEmote) -> Self {
         let largest_width_file = raw_emote
@@ -1,80 +1,131 @@
+pub mod atlas;
 pub mod emote_types;
-use bevy::{render::texture::ImageFormat, utils::HashMap};
+use bevy::{
+    asset::AssetServer,
+    ecs::query::Has,
+    hierarchy::Parent,
+    prelude::{Commands, Entity, Query, Res, ResMut},
+    render::texture::ImageFormat,
+    utils::HashMap,
+};
 use log::{info, warn};
 
-use crate::emotes::emote_types::{Emote, EmoteMeta, SevenTVResponse};
+use crate::{
+    config::Config,
+    emotes::emote_types::{Emote, EmoteMeta, SevenTVResponse},
+    handles::HandleCache,
+    messages::display_message,
+    pool::EntityPools,
+    ChannelEmoteReceiver, EmoteStorage, MessageBubble, MessageSpawnTime, MessageText,
+};
 
 const SEVEN_TV_URL: &str = "https://7tv.io/v3/users/twitch/";
 
-pub(crate) async fn get_seventv_emotes(channel_id: String) -> HashMap<String, Emote> {
+pub(crate) async fn get_seventv_emotes(channel_id: String) -> Result<HashMap<String, Emote>, reqwest::Error> {
     info!("Getting the 7TV channel emotes");
-    let response = reqwest::get(format!("{}{}", SEVEN_TV_URL, channel_id)).await;
-    if response.is_err() {
-        panic!("Cannot get 7tv emotes");
-    }
-
-    let response: SevenTVResponse = response.unwrap().json::<SevenTVResponse>().await.unwrap();
-    response
+    let response: SevenTVResponse = reqwest::get(format!("{}{}", SEVEN_TV_URL, channel_id))
+        .await?
+        .json::<SevenTVResponse>()
+        .await?;
+    Ok(response
         .emote_set
         .emotes
         .iter()
         .map(|emote| (emote.data.name.clone(), Emote::from(emote.data.clone())))
-        .collect()
+        .collect())
+}
+
+// Merge the channel emotes in once they arrive. Messages shown before then are redrawn with
+// the emotes they used, keeping their original despawn time.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn receive_channel_emotes(
+    mut commands: Commands,
+    mut pools: ResMut<EntityPools>,
+    mut handles: ResMut<HandleCache>,
+    asset_server: Res<AssetServer>,
+    config: Res<Config>,
+    mut emote_receiver: ResMut<ChannelEmoteReceiver>,
+    mut emote_store: ResMut<EmoteStorage>,
+    message_query: Query<(Entity, &MessageText, &MessageSpawnTime, &Parent, Has<MessageBubble>)>,
+) {
+    let emotes = match emote_receiver.receiver.try_recv() {
+        Ok(emotes) => emotes,
+        // Not fetched yet, or failed or there's no channel, how it went is on the loading panel
+        Err(_) => return,
+    };
+    info!("Got {} 7TV channel emotes", emotes.len());
+
+    let outdated: Vec<_> = message_query
+        .iter()
+        .filter(|(_, message, _, _, _)| message.text.split_whitespace().any(|word| emotes.contains_key(word)))
+        .map(|(entity, message, spawn_time, parent, bubble)| {
+            (entity, message.text.clone(), message.shared_chat, spawn_time.0, parent.get(), bubble)
+        })
+        .collect();
+    emote_store.all.extend(emotes);
+
+    for (entity, text, shared_chat, spawn_time, user, bubble) in outdated {
+        pools.release_message(&mut commands, entity, bubble, &config);
+        let message = display_message(
+            &mut commands,
+            &mut pools,
+            &mut handles,
+            &asset_server,
+            &mut emote_store,
+            &config,
+            user,
+            text,
+            shared_chat,
+        );
+        commands.entity(message).insert(MessageSpawnTime(spawn_time));
+    }
 }
 
-async fn get_image_meta(url: &str) -> EmoteMeta {
+async fn get_image_meta(url: &str) -> Result<EmoteMeta, String> {
     // Initialize an HTTP client
     let client = reqwest::Client::new();
 
     // Fetch the first 4096 bytes (this should be enough for most image formats)
-    let response = client
+    let bytes = client
         .get(url)
         .header(reqwest::header::RANGE, "bytes=0-8096")
         .send()
         .await
-        .expect("Successful emote header request")
-        .bytes();
-
-    // Create a cursor to read the in-memory byte stream
-    let cursor = std::io::Cursor::new(response);
+        .map_err(|err| format!("header request failed: {}", err))?
+        .bytes()
+        .await
+        .map_err(|err| format!("header download failed: {}", err))?;
 
-    // Use the `image` crate to read the image dimensions from the cursor
-    let bytes = cursor.into_inner().await.unwrap();
+    // Use the `image` crate to read the image dimensions from the bytes
     let reader = image::ImageReader::new(std::io::Cursor::new(bytes))
         .with_guessed_format()
-        .expect("Guessed format");
+        .map_err(|err| format!("couldn't guess the format: {}", err))?;
 
-    let image_format = reader.format().expect("Image format from metadata");
+    let image_format = reader.format().ok_or("unknown image format")?;
 
     // Decode the image header to get the dimensions
-    let dimensions = match reader.into_dimensions() {
-        Ok(dimensions) => dimensions,
-        Err(err) => {
-            warn!("Error reading image dimensions: {}", err);
-            warn!("URL: {}", url);
-            return EmoteMeta {
-                width: 0,
-                height: 0,
-                format: ImageFormat::Png,
-            };
-        }
-    };
+    let dimensions = reader
+        .into_dimensions()
+        .map_err(|err| format!("couldn't read the dimensions: {}", err))?;
     let format = ImageFormat::from_image_crate_format(image_format)
-        .expect("Image format converted to Bevy format");
-
-    // debug!("Image format: {:?}", image_format);
-    // debug!("Width: {}, Height: {}", dimensions.0, dimensions.1);
-    // debug!("Format: {:?}", format);
+        .ok_or_else(|| format!("{:?} isn't supported", image_format))?;
 
-    EmoteMeta {
+    Ok(EmoteMeta {
         width: dimensions.0,
         height: dimensions.1,
         format,
-    }
+    })
 }
 
 pub(crate) async fn update_emote_meta(emote: &mut Emote) {
-    let meta = get_image_meta(&emote.emote_url).await;
+    let meta = get_image_meta(&emote.emote_url).await.unwrap_or_else(|err| {
+        warn!("Error reading emote {} at {}: {}", emote.name, emote.emote_url, err);
+        EmoteMeta {
+            width: 0,
+            height: 0,
+            format: ImageFormat::Png,
+        }
+    });
     emote.width = Some(meta.width);
     emote.height = Some(meta.height);
     emote.format = Some(meta.format);
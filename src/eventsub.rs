@@ -0,0 +1,432 @@
+use std::time::Duration;
+
+use bevy::prelude::{Event, EventWriter, ResMut};
+use chrono::{DateTime, Utc};
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, info, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::{sync::mpsc, time::sleep};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+use crate::{
+    config::Config,
+    helix::HelixClient,
+    startup::{StartupReporter, StartupStep, StepResult},
+    EventSubReceiver,
+};
+
+const EVENTSUB_URL: &str = "wss://eventsub.wss.twitch.tv/ws";
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+type EventSubError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Notifications forwarded from EventSub into the Bevy world
+#[derive(Event, Debug, Clone)]
+pub(crate) enum EventSubEvent {
+    HypeTrainProgress {
+        level: u32,
+        progress: u32,
+        goal: u32,
+        expires_in: Duration,
+    },
+    HypeTrainEnd,
+    Follow {
+        user_login: String,
+    },
+    Redemption {
+        user_login: String,
+        reward_title: String,
+        /// What the viewer typed, empty for rewards that don't ask for text
+        user_input: String,
+    },
+    /// A poll started or got votes
+    PollProgress {
+        title: String,
+        choices: Vec<PollChoice>,
+    },
+    /// A poll finished with these results, or was taken down when `cancelled`
+    PollEnd {
+        choices: Vec<PollChoice>,
+        cancelled: bool,
+    },
+    /// Another channel raided this one
+    Raid {
+        from_login: String,
+        from_name: String,
+        viewers: u32,
+    },
+}
+
+/// One of a poll's options and the votes it has so far
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct PollChoice {
+    pub(crate) title: String,
+    #[serde(default)]
+    pub(crate) votes: u32,
+}
+
+/// A topic to subscribe to once the websocket session is established
+pub(crate) struct EventSubSubscription {
+    pub(crate) kind: &'static str,
+    pub(crate) version: &'static str,
+    pub(crate) condition: Value,
+}
+
+impl EventSubSubscription {
+    /// Subscription that needs a moderator of the channel, such as the token's own user
+    pub(crate) fn moderator(
+        kind: &'static str,
+        version: &'static str,
+        broadcaster_id: &str,
+        moderator_id: &str,
+    ) -> Self {
+        Self {
+            kind,
+            version,
+            condition: json!({
+                "broadcaster_user_id": broadcaster_id,
+                "moderator_user_id": moderator_id,
+            }),
+        }
+    }
+
+    /// Subscription whose only condition is the broadcaster id
+    pub(crate) fn broadcaster(kind: &'static str, version: &'static str, broadcaster_id: &str) -> Self {
+        Self {
+            kind,
+            version,
+            condition: json!({ "broadcaster_user_id": broadcaster_id }),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct EventSubMessage {
+    metadata: EventSubMetadata,
+    payload: Value,
+}
+
+#[derive(Deserialize, Debug)]
+struct EventSubMetadata {
+    message_type: String,
+    subscription_type: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SessionPayload {
+    session: Session,
+}
+
+#[derive(Deserialize, Debug)]
+struct Session {
+    id: String,
+    reconnect_url: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RawFollow {
+    user_login: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct RawRedemption {
+    user_login: String,
+    #[serde(default)]
+    user_input: String,
+    reward: RawReward,
+}
+
+#[derive(Deserialize, Debug)]
+struct RawReward {
+    title: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct RawRaid {
+    from_broadcaster_user_login: String,
+    from_broadcaster_user_name: String,
+    viewers: u32,
+}
+
+#[derive(Deserialize, Debug)]
+struct RawHypeTrainProgress {
+    #[serde(default)]
+    level: u32,
+    progress: u32,
+    goal: u32,
+    expires_at: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct RawPoll {
+    #[serde(default)]
+    title: String,
+    choices: Vec<PollChoice>,
+    /// Only on poll.end, `archived` when the poll was removed rather than ended
+    #[serde(default)]
+    status: String,
+}
+
+/// Build the EventSub topics the enabled features need
+pub(crate) async fn subscriptions_from_config(
+    config: &Config,
+    helix: &HelixClient,
+) -> Vec<EventSubSubscription> {
+    let mut subscriptions = vec![];
+    if config.hype_train_enabled() {
+        for kind in [
+            "channel.hype_train.begin",
+            "channel.hype_train.progress",
+            "channel.hype_train.end",
+        ] {
+            subscriptions.push(EventSubSubscription::broadcaster(kind, "1", &config.channel_id));
+        }
+    }
+    if config.polls_enabled {
+        for kind in ["channel.poll.begin", "channel.poll.progress", "channel.poll.end"] {
+            subscriptions.push(EventSubSubscription::broadcaster(kind, "1", &config.channel_id));
+        }
+    }
+    // Raids are only needed to thank the raider in chat
+    if config.reply_raid.is_some() {
+        subscriptions.push(EventSubSubscription {
+            kind: "channel.raid",
+            version: "1",
+            condition: json!({ "to_broadcaster_user_id": config.channel_id }),
+        });
+    }
+    if config.follows_enabled {
+        // Follows need the id of a moderator, which is whoever the token belongs to
+        match helix.get_token_user_id().await {
+            Ok(Some(moderator_id)) => subscriptions.push(EventSubSubscription::moderator(
+                "channel.follow",
+                "2",
+                &config.channel_id,
+                &moderator_id,
+            )),
+            Ok(None) => warn!("OAUTH_TOKEN has no user, follow events disabled"),
+            Err(err) => warn!("Failed to look up token user, follow events disabled: {}", err),
+        }
+    }
+    // Scripts may react to any reward
+    if config.giant_reward.is_some()
+        || !config.accessory_rewards.is_empty()
+        || config.scripts_enabled
+        || config.weather_rain_reward.is_some()
+        || config.weather_snow_reward.is_some()
+        || config.weather_confetti_reward.is_some()
+        || config.sign_reward.is_some()
+    {
+        subscriptions.push(EventSubSubscription::broadcaster(
+            "channel.channel_points_custom_reward_redemption.add",
+            "1",
+            &config.channel_id,
+        ));
+    }
+    subscriptions
+}
+
+/// How a websocket session finished
+enum SessionEnd {
+    Reconnect(String),
+    Closed,
+}
+
+/// Connect to EventSub, subscribe to the requested topics and forward notifications to Bevy.
+/// Reconnects with backoff whenever the socket drops. How the first subscriptions went is
+/// reported to `startup`.
+pub(crate) async fn start_eventsub(
+    helix: HelixClient,
+    subscriptions: Vec<EventSubSubscription>,
+    tx: mpsc::Sender<EventSubEvent>,
+    startup: StartupReporter,
+) {
+    let mut url = EVENTSUB_URL.to_string();
+    let mut subscribe = true;
+    let mut backoff = Duration::from_secs(1);
+    let mut startup = Some(startup);
+
+    loop {
+        match run_session(&helix, &url, &subscriptions, subscribe, &tx, &mut startup).await {
+            Ok(SessionEnd::Reconnect(reconnect_url)) => {
+                // Subscriptions carry over to the new session
+                info!("EventSub asked us to reconnect");
+                url = reconnect_url;
+                subscribe = false;
+                backoff = Duration::from_secs(1);
+                continue;
+            }
+            Ok(SessionEnd::Closed) => warn!("EventSub connection closed"),
+            Err(err) => {
+                warn!("EventSub connection failed: {}", err);
+                if let Some(startup) = &startup {
+                    startup.failed(StartupStep::EventSub, err.to_string());
+                }
+            }
+        }
+        if tx.is_closed() {
+            break;
+        }
+
+        url = EVENTSUB_URL.to_string();
+        subscribe = true;
+        sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+async fn run_session(
+    helix: &HelixClient,
+    url: &str,
+    subscriptions: &[EventSubSubscription],
+    subscribe: bool,
+    tx: &mpsc::Sender<EventSubEvent>,
+    startup: &mut Option<StartupReporter>,
+) -> Result<SessionEnd, EventSubError> {
+    let (mut socket, _) = connect_async(url).await?;
+
+    while let Some(frame) = socket.next().await {
+        let text = match frame? {
+            Message::Text(text) => text,
+            Message::Ping(data) => {
+                socket.send(Message::Pong(data)).await?;
+                continue;
+            }
+            Message::Close(_) => return Ok(SessionEnd::Closed),
+            _ => continue,
+        };
+
+        let message: EventSubMessage = serde_json::from_str(&text)?;
+        match message.metadata.message_type.as_str() {
+            "session_welcome" => {
+                let payload: SessionPayload = serde_json::from_value(message.payload)?;
+                info!("EventSub session started: {}", payload.session.id);
+                if subscribe {
+                    let mut subscribed = 0;
+                    let mut first_error = None;
+                    for subscription in subscriptions {
+                        if let Err(err) = helix
+                            .create_eventsub_subscription(
+                                subscription.kind,
+                                subscription.version,
+                                &subscription.condition,
+                                &payload.session.id,
+                            )
+                            .await
+                        {
+                            warn!("Failed to subscribe to {}: {}", subscription.kind, err);
+                            first_error.get_or_insert_with(|| format!("{}: {}", subscription.kind, err));
+                        } else {
+                            subscribed += 1;
+                        }
+                    }
+                    // Some topics working is enough to go on, the rest are in the log
+                    match first_error {
+                        Some(err) if subscribed == 0 => {
+                            if let Some(startup) = startup {
+                                startup.failed(StartupStep::EventSub, err);
+                            }
+                        }
+                        _ => {
+                            if let Some(startup) = startup.take() {
+                                startup.done(StartupStep::EventSub, StepResult::Subscribed(subscribed));
+                            }
+                        }
+                    }
+                }
+            }
+            "session_reconnect" => {
+                let payload: SessionPayload = serde_json::from_value(message.payload)?;
+                if let Some(reconnect_url) = payload.session.reconnect_url {
+                    return Ok(SessionEnd::Reconnect(reconnect_url));
+                }
+            }
+            "notification" => {
+                let kind = message.metadata.subscription_type.unwrap_or_default();
+                if let Some(event) = parse_notification(&kind, &message.payload["event"]) {
+                    tx.send(event).await?;
+                }
+            }
+            "revocation" => warn!(
+                "EventSub subscription revoked: {:?}",
+                message.metadata.subscription_type
+            ),
+            _ => {}
+        }
+    }
+
+    Ok(SessionEnd::Closed)
+}
+
+/// Convert a notification payload into an event for Bevy
+fn parse_notification(kind: &str, event: &Value) -> Option<EventSubEvent> {
+    debug!("EventSub notification: {}", kind);
+    match kind {
+        "channel.hype_train.begin" | "channel.hype_train.progress" => {
+            let raw: RawHypeTrainProgress = serde_json::from_value(event.clone()).ok()?;
+            Some(EventSubEvent::HypeTrainProgress {
+                level: raw.level.max(1),
+                progress: raw.progress,
+                goal: raw.goal,
+                expires_in: duration_until(&raw.expires_at),
+            })
+        }
+        "channel.hype_train.end" => Some(EventSubEvent::HypeTrainEnd),
+        "channel.poll.begin" | "channel.poll.progress" => {
+            let raw: RawPoll = serde_json::from_value(event.clone()).ok()?;
+            Some(EventSubEvent::PollProgress {
+                title: raw.title,
+                choices: raw.choices,
+            })
+        }
+        "channel.poll.end" => {
+            let raw: RawPoll = serde_json::from_value(event.clone()).ok()?;
+            Some(EventSubEvent::PollEnd {
+                choices: raw.choices,
+                cancelled: raw.status == "archived",
+            })
+        }
+        "channel.raid" => {
+            let raw: RawRaid = serde_json::from_value(event.clone()).ok()?;
+            Some(EventSubEvent::Raid {
+                from_login: raw.from_broadcaster_user_login,
+                from_name: raw.from_broadcaster_user_name,
+                viewers: raw.viewers,
+            })
+        }
+        "channel.follow" => {
+            let raw: RawFollow = serde_json::from_value(event.clone()).ok()?;
+            Some(EventSubEvent::Follow {
+                user_login: raw.user_login,
+            })
+        }
+        "channel.channel_points_custom_reward_redemption.add" => {
+            let raw: RawRedemption = serde_json::from_value(event.clone()).ok()?;
+            Some(EventSubEvent::Redemption {
+                user_login: raw.user_login,
+                reward_title: raw.reward.title,
+                user_input: raw.user_input,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Time remaining until an RFC 3339 timestamp, zero if it has passed or cannot be parsed
+pub(crate) fn duration_until(timestamp: &str) -> Duration {
+    DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .and_then(|time| (time.with_timezone(&Utc) - Utc::now()).to_std().ok())
+        .unwrap_or_default()
+}
+
+// Forward notifications from the EventSub task into Bevy events
+pub(crate) fn forward_eventsub_events(
+    mut eventsub_receiver: ResMut<EventSubReceiver>,
+    mut events: EventWriter<EventSubEvent>,
+) {
+    while let Ok(event) = eventsub_receiver.receiver.try_recv() {
+        events.send(event);
+    }
+}
@@ -0,0 +1,153 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    asset::Assets,
+    color::{Alpha, Color, Mix},
+    math::{Vec2, Vec3},
+    prelude::{
+        default, Camera, Commands, Component, Entity, Image, Local, Query, Res, ResMut, Resource, Transform, With,
+    },
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    },
+    sprite::{Anchor, Sprite, SpriteBundle},
+};
+
+use crate::{
+    config::Config,
+    opacity::{fade, BaseAlpha},
+    OverlayCamera,
+};
+
+/// How often the glow's colour and size are worked out again
+const UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+/// Behind the minawan and the poll zones
+const GLOW_Z: f32 = -3.0;
+/// Rows of the glow's texture, fading out towards the top
+const GRADIENT_STEPS: u32 = 16;
+
+/// When the latest messages came in, to tell how busy chat is
+#[derive(Resource, Default)]
+pub(crate) struct ChatHeat {
+    messages: VecDeque<Instant>,
+}
+
+impl ChatHeat {
+    pub(crate) fn record(&mut self, now: Instant, window: Duration) {
+        self.messages.push_back(now);
+        self.forget(now, window);
+    }
+
+    fn forget(&mut self, now: Instant, window: Duration) {
+        while self.messages.front().is_some_and(|time| now.duration_since(*time) > window) {
+            self.messages.pop_front();
+        }
+    }
+
+    /// Messages per minute over the last `window`
+    fn per_minute(&mut self, now: Instant, window: Duration) -> f32 {
+        self.forget(now, window);
+        self.messages.len() as f32 * 60.0 / window.as_secs_f32()
+    }
+}
+
+/// The strip glowing along the floor
+#[derive(Component)]
+pub(crate) struct FloorGlow {}
+
+/// White, fully opaque at the bottom and fading out to the top. The sprite colour tints it.
+fn gradient() -> Image {
+    let data = (0..GRADIENT_STEPS)
+        .flat_map(|row| {
+            let strength = (row + 1) as f32 / GRADIENT_STEPS as f32;
+            [255, 255, 255, (strength * strength * 255.0) as u8]
+        })
+        .collect();
+    Image::new(
+        Extent3d {
+            width: 1,
+            height: GRADIENT_STEPS,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    )
+}
+
+/// QUIET_COLOR when chat is at QUIET_RATE or slower, BUSY_COLOR from BUSY_RATE, mixed in between
+fn glow_color(rate: f32, config: &Config) -> Color {
+    let span = (config.floor_glow_busy_rate - config.floor_glow_quiet_rate).max(f32::EPSILON);
+    let heat = ((rate - config.floor_glow_quiet_rate) / span).clamp(0.0, 1.0);
+    config.floor_glow_quiet_color.mix(&config.floor_glow_busy_color, heat)
+}
+
+// Colour the glow along the floor for how many messages a minute chat has had lately. It
+// spans the walk area and follows the window size, at most twice a second.
+pub(crate) fn update_floor_glow(
+    mut commands: Commands,
+    mut heat: ResMut<ChatHeat>,
+    mut images: ResMut<Assets<Image>>,
+    config: Res<Config>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    mut glow_query: Query<(Entity, &mut Transform, &mut Sprite, &mut BaseAlpha), With<FloorGlow>>,
+    mut last_update: Local<Option<Instant>>,
+) {
+    if !config.floor_glow_enabled {
+        heat.messages.clear();
+        for (entity, ..) in glow_query.iter() {
+            commands.entity(entity).despawn();
+        }
+        return;
+    }
+    if glow_query.is_empty() {
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::NONE,
+                    anchor: Anchor::BottomCenter,
+                    ..default()
+                },
+                texture: images.add(gradient()),
+                ..default()
+            },
+            BaseAlpha(0.0),
+            FloorGlow {},
+        ));
+        *last_update = None;
+        return;
+    }
+    if last_update.is_some_and(|last| last.elapsed() < UPDATE_INTERVAL) && !config.is_changed() {
+        return;
+    }
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+    let now = Instant::now();
+    *last_update = Some(now);
+
+    let ranges = config.walk_ranges(rect.max.x);
+    let left = ranges.first().map_or(-rect.max.x / 2.0, |range| range.0);
+    let right = ranges.last().map_or(rect.max.x / 2.0, |range| range.1);
+    let translation = Vec3::new((left + right) / 2.0, config.floor_y(rect.max.y), GLOW_Z);
+    let size = Vec2::new(right - left, config.floor_glow_height);
+    let color = glow_color(heat.per_minute(now, config.floor_glow_window), &config);
+    for (_, mut transform, mut sprite, alpha) in glow_query.iter_mut() {
+        if transform.translation != translation {
+            transform.translation = translation;
+        }
+        if sprite.custom_size != Some(size) {
+            sprite.custom_size = Some(size);
+        }
+        let tint = color.with_alpha(sprite.color.alpha());
+        if sprite.color != tint {
+            sprite.color = tint;
+        }
+        fade(Some(alpha), color.alpha());
+    }
+}
@@ -0,0 +1,159 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    asset::AssetServer,
+    color::Color,
+    math::Vec3,
+    prelude::{
+        default, BuildChildren, Camera, Commands, Component, Entity, EventReader, Query, Res,
+        ResMut, Resource, Transform, With,
+    },
+    text::{Text, Text2dBundle, TextStyle},
+};
+use log::info;
+
+use crate::{
+    config::Config, eventsub::EventSubEvent, handles::HandleCache, locale::t, pool::EntityPools,
+    users::spawn_user, AppState, ChatMessage, MessageSpawnTime, OverlayCamera, Platform, User, WaveNearby,
+};
+
+/// Window used to detect follow spam
+const FOLLOW_SPAM_WINDOW: Duration = Duration::from_secs(60);
+
+/// Follows seen recently, used to collapse follow spam into one banner
+#[derive(Resource, Default)]
+pub(crate) struct FollowTracker {
+    recent: VecDeque<Instant>,
+    banner: Option<Entity>,
+}
+
+/// Marker for the collapsed "N new followers" banner
+#[derive(Component)]
+pub(crate) struct FollowBanner {}
+
+// Welcome new followers, collapsing bursts of follows into a single banner
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn handle_follow_events(
+    mut commands: Commands,
+    mut events: EventReader<EventSubEvent>,
+    mut tracker: ResMut<FollowTracker>,
+    mut app_state: ResMut<AppState>,
+    mut pools: ResMut<EntityPools>,
+    mut handles: ResMut<HandleCache>,
+    asset_server: Res<AssetServer>,
+    config: Res<Config>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    mut banner_query: Query<(&mut Text, &mut MessageSpawnTime), With<FollowBanner>>,
+) {
+    let now = Instant::now();
+    let mut collapsed = false;
+
+    for event in events.read() {
+        let EventSubEvent::Follow { user_login } = event else {
+            continue;
+        };
+        info!("New follower: {}", user_login);
+
+        tracker
+            .recent
+            .retain(|time| now.duration_since(*time) < FOLLOW_SPAM_WINDOW);
+        tracker.recent.push_back(now);
+        if tracker.recent.len() > config.follow_spam_threshold {
+            collapsed = true;
+            continue;
+        }
+
+        // Reuse the follower's avatar if they are already at the party
+        let entity = match app_state.active_users.get_mut(user_login) {
+            Some(user) => {
+                user.last_message_time = now;
+                user.entity
+            }
+            None if !app_state.offline_idle(&config) && !app_state.spawning_paused && !app_state.quiet_hours => {
+                let Some(rect) = camera_query.single().logical_viewport_rect() else {
+                    continue;
+                };
+                let chat_message = ChatMessage::synthetic(Platform::Twitch, user_login);
+                let entity = spawn_user(
+                    &mut commands,
+                    &mut pools,
+                    &mut handles,
+                    &asset_server,
+                    &chat_message,
+                    &config,
+                    rect,
+                );
+                app_state.insert_user(user_login.clone(), User::new(entity, user_login.clone(), now));
+                entity
+            }
+            None => continue,
+        };
+
+        commands
+            .entity(entity)
+            .insert(WaveNearby {
+                radius: config.follow_wave_radius,
+            })
+            .with_children(|parent| {
+                parent.spawn((
+                    Text2dBundle {
+                        text: Text::from_section(
+                            t!("follows.thanks"),
+                            TextStyle {
+                                font: asset_server.load(&config.font_url),
+                                font_size: config.font_size,
+                                color: Color::WHITE,
+                            },
+                        ),
+                        transform: Transform::from_translation(Vec3::new(0.0, 50.0, 3.0)),
+                        ..default()
+                    },
+                    MessageSpawnTime(now),
+                ));
+            });
+    }
+
+    if !collapsed {
+        return;
+    }
+
+    // Update the existing banner, or show a new one near the floor
+    let text = t!("follows.new_followers", count = tracker.recent.len());
+    if let Some((mut banner_text, mut spawn_time)) = tracker
+        .banner
+        .and_then(|banner| banner_query.get_mut(banner).ok())
+    {
+        banner_text.sections[0].value = text;
+        spawn_time.0 = now;
+        return;
+    }
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+    let banner = commands
+        .spawn((
+            Text2dBundle {
+                text: Text::from_section(
+                    text,
+                    TextStyle {
+                        font: asset_server.load(&config.font_url),
+                        font_size: config.font_size * 1.5,
+                        color: Color::WHITE,
+                    },
+                ),
+                transform: Transform::from_translation(Vec3::new(
+                    0.0,
+                    -(rect.max.y / 2.0) + 120.0,
+                    5.0,
+                )),
+                ..default()
+            },
+            MessageSpawnTime(now),
+            FollowBanner {},
+        ))
+        .id();
+    tracker.banner = Some(banner);
+}
@@ -0,0 +1,122 @@
+use std::time::{Duration, Instant};
+
+use bevy::{
+    asset::AssetServer,
+    color::Alpha,
+    prelude::{default, Camera, Commands, Component, Entity, Query, Res, ResMut, Transform, With},
+    sprite::{Sprite, SpriteBundle},
+};
+
+use crate::{
+    accessibility::EffectPolicy, config::Config, frame_budget::Cosmetic, opacity::BaseAlpha, pool::EntityPools,
+    AppState, OverlayCamera, UserMarker,
+};
+
+/// Behind the minawan and the campfire, over the floor glow
+const FOOTPRINT_Z: f32 = -2.0;
+/// How far under a minawan's center its feet are
+const FEET_DROP: f32 = 21.0;
+/// How far apart the left and right footprints are
+const FOOT_SPREAD: f32 = 3.0;
+/// Shortest time between two footprints of the same minawan, however fast it goes
+const MIN_STEP_INTERVAL: Duration = Duration::from_millis(120);
+
+/// A footprint fading away on the floor
+#[derive(Component)]
+pub(crate) struct Footprint {
+    left_at: Instant,
+}
+
+/// Where a minawan last left a footprint
+#[derive(Component)]
+pub(crate) struct FootprintTrail {
+    x: f32,
+    time: Instant,
+    left_foot: bool,
+}
+
+// Leave a footprint behind walking minawan every STRIDE pixels, as long as there are fewer
+// than MAX_FOOTPRINTS on screen and no more than MAX_USERS at the party. There's no trail
+// with REDUCED_MOTION or while the frame budget has shed footprints.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn leave_footprints(
+    mut commands: Commands,
+    mut pools: ResMut<EntityPools>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    policy: Res<EffectPolicy>,
+    asset_server: Res<AssetServer>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    mut avatar_query: Query<(Entity, &Transform, &Sprite, Option<&mut FootprintTrail>), With<UserMarker>>,
+    footprint_query: Query<(), With<Footprint>>,
+) {
+    if !config.footprints_enabled || !policy.motion() || !policy.allows(Cosmetic::Footprints) {
+        return;
+    }
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+    let feet_y = config.floor_y(rect.max.y) - FEET_DROP;
+    let crowded = app_state.active_users.len() > config.footprint_max_users;
+    let mut live = footprint_query.iter().count();
+    let now = Instant::now();
+    for (entity, transform, sprite, trail) in avatar_query.iter_mut() {
+        let x = transform.translation.x;
+        let Some(mut trail) = trail else {
+            commands.entity(entity).insert(FootprintTrail {
+                x,
+                time: now,
+                left_foot: false,
+            });
+            continue;
+        };
+        if (x - trail.x).abs() < config.footprint_stride || now.duration_since(trail.time) < MIN_STEP_INTERVAL {
+            continue;
+        }
+        trail.x = x;
+        trail.time = now;
+        // Still walking, just not leaving prints for now
+        if crowded || live >= config.footprint_max {
+            continue;
+        }
+        trail.left_foot = !trail.left_foot;
+        let y = if trail.left_foot { feet_y + FOOT_SPREAD } else { feet_y };
+        pools.spawn_footprint(
+            &mut commands,
+            (
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: config.footprint_tint,
+                        flip_x: sprite.flip_x,
+                        ..default()
+                    },
+                    texture: asset_server.load(&config.footprint_image),
+                    transform: Transform::from_xyz(x, y, FOOTPRINT_Z),
+                    ..default()
+                },
+                Footprint { left_at: now },
+            ),
+        );
+        live += 1;
+    }
+}
+
+// Fade footprints out over LIFETIME_MILIS and put them back in the pool
+pub(crate) fn fade_footprints(
+    mut commands: Commands,
+    mut pools: ResMut<EntityPools>,
+    config: Res<Config>,
+    mut footprint_query: Query<(Entity, &Footprint, Option<&mut BaseAlpha>)>,
+) {
+    for (entity, footprint, alpha) in footprint_query.iter_mut() {
+        let faded = footprint.left_at.elapsed().as_secs_f32() / config.footprint_lifetime.as_secs_f32();
+        if faded >= 1.0 {
+            pools.release_footprint(&mut commands, entity, &config);
+            continue;
+        }
+        // apply_opacity adds it the frame after the footprint is spawned
+        if let Some(mut alpha) = alpha {
+            alpha.0 = config.footprint_tint.alpha() * (1.0 - faded);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    prelude::{Added, Commands, DespawnRecursiveExt, Entity, Or, Query, Real, Res, ResMut, Resource, Time},
+    winit::{UpdateMode, WinitSettings},
+};
+use log::info;
+
+use crate::{
+    accessibility::EffectPolicy, config::Config, moderation::DustPuff, pyramids::EmoteRaindrop,
+    weather::WeatherParticle,
+};
+
+/// Frames averaged to tell whether the overlay is keeping up
+const AVERAGE_FRAMES: usize = 30;
+/// How long frames have to stay slow before one more thing is shed
+const SHED_AFTER: Duration = Duration::from_secs(1);
+
+/// Cosmetic work the overlay gives up when frames run long, declared in the order it's given up
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Cosmetic {
+    /// Footprint trails behind walking minawan
+    Footprints,
+    /// Weather, confetti, emote rain and dust
+    Particles,
+    /// Minawan hopping along to a dance
+    Dances,
+    /// Message text typed out a character at a time
+    TextEffects,
+}
+
+const SHED_ORDER: [Cosmetic; 4] = [
+    Cosmetic::Footprints,
+    Cosmetic::Particles,
+    Cosmetic::Dances,
+    Cosmetic::TextEffects,
+];
+
+impl Cosmetic {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Cosmetic::Footprints => "footprints",
+            Cosmetic::Particles => "particles",
+            Cosmetic::Dances => "dances",
+            Cosmetic::TextEffects => "text effects",
+        }
+    }
+}
+
+/// How long recent frames took and what has been shed to speed them up
+#[derive(Resource, Default)]
+pub(crate) struct FrameBudget {
+    /// Recent frame times in seconds, only frames meant to be drawn at full speed
+    frame_times: VecDeque<f32>,
+    /// How many of SHED_ORDER are shed
+    shed: usize,
+    /// When the average went over SLOW_FRAME_MILIS, while it stays over
+    slow_since: Option<Instant>,
+    /// When the average went under FAST_FRAME_MILIS, while it stays under
+    fast_since: Option<Instant>,
+    /// What was last shed or restored and when, for the debug overlay
+    last_change: Option<(Instant, String)>,
+}
+
+impl FrameBudget {
+    /// Average of the recent frame times in seconds, None until enough have been seen
+    pub(crate) fn average(&self) -> Option<f32> {
+        (self.frame_times.len() >= AVERAGE_FRAMES)
+            .then(|| self.frame_times.iter().sum::<f32>() / self.frame_times.len() as f32)
+    }
+
+    pub(crate) fn shed(&self) -> &[Cosmetic] {
+        &SHED_ORDER[..self.shed]
+    }
+
+    pub(crate) fn last_change(&self) -> Option<&(Instant, String)> {
+        self.last_change.as_ref()
+    }
+
+    /// Shed or restore cosmetics until `shed` of them are shed
+    fn set_shed(&mut self, shed: usize, change: String) {
+        info!("{}", change);
+        self.shed = shed;
+        self.last_change = Some((Instant::now(), change));
+        // Frames from before the change don't say how it's going now
+        self.frame_times.clear();
+        self.slow_since = None;
+        self.fast_since = None;
+    }
+}
+
+// Average the frame time and shed cosmetics one at a time while it stays over SLOW_FRAME_MILIS,
+// restoring them one at a time once it's been under FAST_FRAME_MILIS for RESTORE_SECS. Frames
+// are meant to be slow while the overlay idles, so only full speed frames count. Headless there's
+// no winit and every frame is at full speed.
+pub(crate) fn track_frame_budget(
+    time: Res<Time<Real>>,
+    winit_settings: Option<Res<WinitSettings>>,
+    config: Res<Config>,
+    mut budget: ResMut<FrameBudget>,
+    mut policy: ResMut<EffectPolicy>,
+) {
+    if !config.frame_budget_enabled {
+        if budget.shed > 0 {
+            budget.set_shed(0, "Frame budget turned off, restoring everything".to_string());
+        }
+    } else if winit_settings.is_some_and(|settings| settings.focused_mode != UpdateMode::Continuous) {
+        budget.frame_times.clear();
+        budget.slow_since = None;
+        budget.fast_since = None;
+    } else {
+        if budget.frame_times.len() == AVERAGE_FRAMES {
+            budget.frame_times.pop_front();
+        }
+        budget.frame_times.push_back(time.delta_seconds());
+
+        if let Some(average) = budget.average() {
+            let now = Instant::now();
+            let millis = average * 1000.0;
+            if average > config.frame_budget_slow.as_secs_f32() {
+                budget.fast_since = None;
+                let slow_since = *budget.slow_since.get_or_insert(now);
+                if now.duration_since(slow_since) >= SHED_AFTER && budget.shed < SHED_ORDER.len() {
+                    let shed = budget.shed + 1;
+                    let change = format!("Frames average {:.1}ms, shedding {}", millis, SHED_ORDER[shed - 1].name());
+                    budget.set_shed(shed, change);
+                }
+            } else if average < config.frame_budget_fast.as_secs_f32() {
+                budget.slow_since = None;
+                let fast_since = *budget.fast_since.get_or_insert(now);
+                if now.duration_since(fast_since) >= config.frame_budget_restore && budget.shed > 0 {
+                    let shed = budget.shed - 1;
+                    let change = format!("Frames average {:.1}ms, restoring {}", millis, SHED_ORDER[shed].name());
+                    budget.set_shed(shed, change);
+                }
+            } else {
+                budget.slow_since = None;
+                budget.fast_since = None;
+            }
+        }
+    }
+
+    if policy.shed != budget.shed {
+        policy.shed = budget.shed;
+    }
+}
+
+// While particles are shed, take away the ones effects throw out as they appear
+#[allow(clippy::type_complexity)]
+pub(crate) fn shed_particles(
+    mut commands: Commands,
+    policy: Res<EffectPolicy>,
+    particle_query: Query<Entity, Or<(Added<WeatherParticle>, Added<EmoteRaindrop>, Added<DustPuff>)>>,
+) {
+    if policy.allows(Cosmetic::Particles) {
+        return;
+    }
+    for entity in particle_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
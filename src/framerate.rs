@@ -0,0 +1,339 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    prelude::{
+        Added, Commands, Entity, EventReader, Local, Or, Query, Res, ResMut, Resource, Transform, With, Without,
+    },
+    time::{Time, Virtual},
+    window::{PrimaryWindow, Window, WindowOccluded, WindowResized},
+    winit::{EventLoopProxy, UpdateMode, WakeUp, WinitSettings},
+};
+use log::{debug, info};
+use tokio::sync::mpsc;
+
+use crate::{
+    config::{Config, OutputBackend},
+    footprints::Footprint,
+    giant::Giant,
+    gifts::GiftBox,
+    greetings::GreetingPuff,
+    marquee::Marquee,
+    moderation::{DustPuff, DustingOff},
+    petting::Heart,
+    progress::WatchProgress,
+    pyramids::EmoteRaindrop,
+    race::Racing,
+    reactions::Drooping,
+    screenshot::Screenshots,
+    settings::SettingsWindow,
+    timer::TimerBadge,
+    weather::WeatherParticle,
+    AdjustScale, AppState, HypeTrain, MessageSpawnTime, UserAction, UserActionDetails, UserMarker, Wave,
+};
+
+/// Last time anything on screen was moving, the overlay idles once this gets old enough
+#[derive(Resource)]
+pub(crate) struct ActivityTracker {
+    last_activity: Instant,
+}
+
+impl Default for ActivityTracker {
+    fn default() -> Self {
+        Self {
+            last_activity: Instant::now(),
+        }
+    }
+}
+
+/// Whether chat has been silent long enough for the party to doze off
+#[derive(Resource)]
+pub(crate) struct ChatQuiet {
+    last_message: Instant,
+    pub(crate) quiet: bool,
+}
+
+impl Default for ChatQuiet {
+    fn default() -> Self {
+        Self {
+            last_message: Instant::now(),
+            quiet: false,
+        }
+    }
+}
+
+/// Frame rate while nobody can see the overlay. Chat still wakes it, so the party stays up to date
+const HIDDEN_FPS: f32 = 1.0;
+
+/// Whether the overlay window can't be seen, because a fullscreen program covers it,
+/// it's minimized or it was hidden with the hotkey
+#[derive(Resource, Default)]
+pub(crate) struct WindowOcclusion {
+    occluded: bool,
+    minimized: bool,
+    hidden: bool,
+}
+
+impl WindowOcclusion {
+    /// Nothing needs rendering, which is never the case while frames are sent to OUTPUT
+    pub(crate) fn unseen(&self, config: &Config) -> bool {
+        !self.on_screen() && config.output == OutputBackend::None
+    }
+
+    /// Whether the window is shown, whatever OUTPUT is
+    pub(crate) fn on_screen(&self) -> bool {
+        !(self.occluded || self.minimized || self.hidden)
+    }
+}
+
+/// Wakes the event loop from outside Bevy so an idle overlay reacts to chat straight away.
+/// The event loop only exists once the app is built, until then waking does nothing.
+#[derive(Resource, Clone, Default)]
+pub(crate) struct FrameWaker {
+    wake: Arc<Mutex<Option<Box<dyn Fn() + Send>>>>,
+}
+
+impl FrameWaker {
+    pub(crate) fn wake(&self) {
+        if let Some(wake) = self.wake.lock().unwrap().as_ref() {
+            wake();
+        }
+    }
+}
+
+/// Pass everything from `receiver` through to the returned receiver, waking the overlay for each item
+pub(crate) fn forward_with_wake<T: Send + 'static>(
+    mut receiver: mpsc::Receiver<T>,
+    waker: FrameWaker,
+) -> mpsc::Receiver<T> {
+    let (tx, rx) = mpsc::channel::<T>(100);
+    tokio::spawn(async move {
+        while let Some(item) = receiver.recv().await {
+            if tx.send(item).await.is_err() {
+                // Bevy side has shut down
+                break;
+            }
+            waker.wake();
+        }
+    });
+    rx
+}
+
+// Hand the event loop to the waker once it exists
+pub(crate) fn setup_frame_waker(proxy: Res<EventLoopProxy<WakeUp>>, waker: Res<FrameWaker>) {
+    let proxy = (**proxy).clone();
+    *waker.wake.lock().unwrap() = Some(Box::new(move || {
+        let _ = proxy.send_event(WakeUp);
+    }));
+}
+
+// Note the time whenever something on screen is moving or about to change
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn track_activity(
+    mut tracker: ResMut<ActivityTracker>,
+    actions: Query<&UserActionDetails>,
+    messages: Query<(), With<MessageSpawnTime>>,
+    waves: Query<(), With<Wave>>,
+    loading: Query<(), With<AdjustScale>>,
+    settings: Query<(), With<SettingsWindow>>,
+    weather: Query<(), With<WeatherParticle>>,
+    timers: Query<(), With<TimerBadge>>,
+    raindrops: Query<(), With<EmoteRaindrop>>,
+    racers: Query<(), With<Racing>>,
+    effects: Query<
+        (),
+        Or<(
+            With<Heart>,
+            With<GiftBox>,
+            With<Footprint>,
+            With<GreetingPuff>,
+            With<Drooping>,
+            With<DustPuff>,
+            With<DustingOff>,
+        )>,
+    >,
+    hype_train: Res<HypeTrain>,
+    progress: Res<WatchProgress>,
+    marquee: Res<Marquee>,
+    screenshots: Option<Res<Screenshots>>,
+) {
+    let walking = actions
+        .iter()
+        .any(|action| !matches!(action.last_action, UserAction::Stop));
+    if walking
+        || !messages.is_empty()
+        || !waves.is_empty()
+        || !loading.is_empty()
+        || !settings.is_empty()
+        || !weather.is_empty()
+        || !timers.is_empty()
+        || !raindrops.is_empty()
+        || !racers.is_empty()
+        || !effects.is_empty()
+        || hype_train.active()
+        || progress.animating()
+        || marquee.scrolling()
+        || screenshots.is_some_and(|screenshots| screenshots.capturing())
+    {
+        tracker.last_activity = Instant::now();
+    }
+}
+
+// Put the party to sleep once chat has been silent for QUIET_AFTER_SECS and nothing is
+// animating, and wake it with a hop on the next message or new minawan. It sleeps through
+// chat during the [Schedule]'s quiet hours.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn track_quiet_chat(
+    mut commands: Commands,
+    mut quiet: ResMut<ChatQuiet>,
+    config: Res<Config>,
+    app_state: Res<AppState>,
+    hype_train: Res<HypeTrain>,
+    new_messages: Query<(), Added<MessageSpawnTime>>,
+    new_avatars: Query<(), Added<UserMarker>>,
+    messages: Query<(), With<MessageSpawnTime>>,
+    waves: Query<(), With<Wave>>,
+    giants: Query<(), With<Giant>>,
+    avatar_query: Query<(Entity, &Transform), (With<UserMarker>, Without<Wave>)>,
+) {
+    let now = Instant::now();
+    if app_state.quiet_hours {
+        if !quiet.quiet {
+            quiet.quiet = true;
+            info!("Quiet hours, the party is going to sleep");
+        }
+        return;
+    }
+    if !new_messages.is_empty() || !new_avatars.is_empty() || (quiet.quiet && config.quiet_after.is_zero()) {
+        quiet.last_message = now;
+        if quiet.quiet {
+            quiet.quiet = false;
+            info!("Chat is back, waking the party up");
+            for (entity, transform) in avatar_query.iter() {
+                commands.entity(entity).insert(Wave {
+                    started: now,
+                    base_y: transform.translation.y,
+                });
+            }
+        }
+        return;
+    }
+    if quiet.quiet || config.quiet_after.is_zero() {
+        return;
+    }
+
+    let animating = !messages.is_empty() || !waves.is_empty() || !giants.is_empty() || hype_train.active();
+    if !animating && now.duration_since(quiet.last_message) >= config.quiet_after {
+        quiet.quiet = true;
+        info!(
+            "No chat for {} seconds, the party is dozing off",
+            now.duration_since(quiet.last_message).as_secs()
+        );
+    }
+}
+
+// Notice when the window is covered, minimized or hidden.
+// Not every platform reports occlusion, minimizing shows up as a resize to nothing.
+pub(crate) fn track_occlusion(
+    mut occluded_events: EventReader<WindowOccluded>,
+    mut resized_events: EventReader<WindowResized>,
+    windows: Query<(Entity, &Window), With<PrimaryWindow>>,
+    config: Res<Config>,
+    mut occlusion: ResMut<WindowOcclusion>,
+) {
+    let Ok((primary, window)) = windows.get_single() else {
+        return;
+    };
+    let was_unseen = occlusion.unseen(&config);
+    for event in occluded_events.read().filter(|event| event.window == primary) {
+        occlusion.occluded = event.occluded;
+    }
+    for event in resized_events.read().filter(|event| event.window == primary) {
+        occlusion.minimized = event.width == 0.0 || event.height == 0.0;
+    }
+    occlusion.hidden = !window.visible;
+
+    let unseen = occlusion.unseen(&config);
+    if unseen != was_unseen {
+        if unseen {
+            info!("Overlay can't be seen, pausing animations");
+        } else {
+            info!("Overlay is visible again");
+        }
+    }
+}
+
+// Stop walking and animated emotes while nobody can see them or the party is dozing
+pub(crate) fn pause_animations(
+    occlusion: Res<WindowOcclusion>,
+    quiet: Res<ChatQuiet>,
+    config: Res<Config>,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    let paused = occlusion.unseen(&config) || quiet.quiet;
+    if paused != time.is_paused() {
+        if paused {
+            time.pause();
+        } else {
+            time.unpause();
+        }
+    }
+}
+
+// Render slowly while the stream is offline, nothing has moved for a while, chat is quiet or the
+// window can't be seen.
+// Chat and events wake the overlay through `FrameWaker` so it never lags behind them.
+pub(crate) fn update_frame_rate(
+    tracker: Res<ActivityTracker>,
+    occlusion: Res<WindowOcclusion>,
+    quiet: Res<ChatQuiet>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    mut winit_settings: ResMut<WinitSettings>,
+) {
+    let offline = app_state.offline_idle(&config);
+    let inactive = !config.idle_after.is_zero() && tracker.last_activity.elapsed() >= config.idle_after;
+
+    let fps = match (offline, inactive) {
+        (true, true) => Some(config.offline_fps.min(config.idle_fps)),
+        (true, false) => Some(config.offline_fps),
+        (false, true) => Some(config.idle_fps),
+        (false, false) => None,
+    };
+    let fps = if quiet.quiet {
+        Some(fps.map_or(config.quiet_fps, |fps| fps.min(config.quiet_fps)))
+    } else {
+        fps
+    };
+    let fps = if occlusion.unseen(&config) {
+        Some(fps.map_or(HIDDEN_FPS, |fps| fps.min(HIDDEN_FPS)))
+    } else {
+        fps
+    };
+    let mode = match fps {
+        Some(fps) => UpdateMode::reactive_low_power(Duration::from_secs_f32(1.0 / fps.max(0.01))),
+        None => UpdateMode::Continuous,
+    };
+
+    if winit_settings.focused_mode != mode {
+        debug!("Switching update mode to {:?}", mode);
+        winit_settings.focused_mode = mode;
+        winit_settings.unfocused_mode = mode;
+    }
+}
+
+// Sleep off the rest of the frame when FPS_CAP is set, runs last so the whole frame is counted
+pub(crate) fn limit_frame_rate(config: Res<Config>, mut last_frame: Local<Option<Instant>>) {
+    if config.fps_cap > 0.0 {
+        let target = Duration::from_secs_f32(1.0 / config.fps_cap);
+        if let Some(elapsed) = last_frame.map(|last| last.elapsed()) {
+            if elapsed < target {
+                thread::sleep(target - elapsed);
+            }
+        }
+    }
+    *last_frame = Some(Instant::now());
+}
@@ -0,0 +1,116 @@
+use std::time::{Duration, Instant};
+
+use bevy::{
+    math::Vec2,
+    prelude::{Camera, Commands, Component, Entity, Event, EventReader, Query, Res, With, Without},
+    sprite::Sprite,
+};
+use log::info;
+
+use crate::{
+    config::Config,
+    eventsub::EventSubEvent,
+    sizes::{feet_anchor, Resizing},
+    AdjustScale, AppState, OverlayCamera, UserMarker,
+};
+
+/// How long growing and shrinking back take
+const GROW_TIME: Duration = Duration::from_millis(500);
+
+/// Ask for a user's minawan to go giant, sent by the giant admin command
+#[derive(Event, Debug, Clone)]
+pub(crate) struct MakeGiant {
+    pub(crate) user: String,
+}
+
+/// A minawan that's giant until `ends`
+#[derive(Component)]
+pub(crate) struct Giant {
+    started: Instant,
+    ends: Instant,
+    /// Size of the sprite before it grew
+    base_size: Vec2,
+}
+
+// Start the effect for admin commands and matching channel point redemptions.
+// Going giant again while giant adds to the time left instead of growing further.
+pub(crate) fn start_giants(
+    mut commands: Commands,
+    mut giant_events: EventReader<MakeGiant>,
+    mut eventsub_events: EventReader<EventSubEvent>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    mut giant_query: Query<&mut Giant>,
+    sprite_query: Query<&Sprite, (With<UserMarker>, Without<AdjustScale>, Without<Resizing>)>,
+) {
+    let redemptions = eventsub_events.read().filter_map(|event| match event {
+        EventSubEvent::Redemption { user_login, reward_title, .. }
+            if config.giant_reward.as_deref().is_some_and(|reward| reward.eq_ignore_ascii_case(reward_title)) =>
+        {
+            Some(user_login.clone())
+        }
+        _ => None,
+    });
+    let users: Vec<String> = giant_events
+        .read()
+        .map(|event| event.user.clone())
+        .chain(redemptions)
+        .collect();
+
+    let now = Instant::now();
+    for user in users {
+        let user = user.trim_start_matches('@').to_lowercase();
+        let Some(entity) = app_state.active_users.get(&user).map(|user| user.entity) else {
+            info!("{} isn't at the party, can't make them giant", user);
+            continue;
+        };
+        if let Ok(mut giant) = giant_query.get_mut(entity) {
+            giant.ends = giant.ends.max(now) + config.giant_duration;
+            info!("{} stays giant for longer", user);
+            continue;
+        }
+        // The avatar's size is only known once its image has loaded
+        let Some(base_size) = sprite_query.get(entity).ok().and_then(|sprite| sprite.custom_size) else {
+            info!("{}'s minawan is still loading or changing size, can't make them giant", user);
+            continue;
+        };
+        info!("{} goes giant", user);
+        commands.entity(entity).insert(Giant {
+            started: now,
+            ends: now + config.giant_duration,
+            base_size,
+        });
+    }
+}
+
+// Grow and shrink giants. The sprite itself is resized with its feet kept on the floor,
+// follow_heads keeps their messages above the new head.
+pub(crate) fn update_giants(
+    mut commands: Commands,
+    config: Res<Config>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    mut giant_query: Query<(Entity, &Giant, &mut Sprite)>,
+) {
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+    let now = Instant::now();
+    let ramp = |time: Duration| (time.as_secs_f32() / GROW_TIME.as_secs_f32()).min(1.0);
+
+    for (entity, giant, mut sprite) in giant_query.iter_mut() {
+        // However big the source image is, a giant never covers more than MAX_SCREEN_FRACTION
+        let max_scale = (config.giant_max_screen_fraction * rect.max.y / giant.base_size.y).max(1.0);
+        let target = config.giant_scale.min(max_scale);
+        let grown = ramp(now - giant.started).min(ramp(giant.ends.saturating_duration_since(now)));
+        let scale = 1.0 + (target - 1.0) * grown;
+
+        sprite.custom_size = Some(giant.base_size * scale);
+        sprite.anchor = feet_anchor(giant.base_size.y * scale);
+
+        if now >= giant.ends {
+            sprite.custom_size = Some(giant.base_size);
+            sprite.anchor = feet_anchor(giant.base_size.y);
+            commands.entity(entity).remove::<Giant>();
+        }
+    }
+}
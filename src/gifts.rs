@@ -0,0 +1,336 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    asset::AssetServer,
+    color::Color,
+    hierarchy::{BuildChildren, DespawnRecursiveExt},
+    math::Vec2,
+    prelude::{
+        default, Camera, Commands, Component, Entity, Query, Res, ResMut, Resource, SpatialBundle, Transform, With,
+        Without,
+    },
+    sprite::{Sprite, SpriteBundle},
+    text::{Text, Text2dBundle, TextStyle},
+    time::Time,
+};
+use log::{debug, info};
+use rand::Rng;
+use tokio::sync::mpsc;
+
+use crate::{
+    config::Config, locale::t, weather::burst_confetti, AppState, OverlayCamera, User, UserMarker, Wave,
+};
+
+/// Time between two boxes of a bomb
+const DROP_INTERVAL: Duration = Duration::from_millis(150);
+const BOX_SIZE: f32 = 24.0;
+/// Over the minawan, with the boop stars
+const BOX_Z: f32 = 4.0;
+const GRAVITY: f32 = 900.0;
+/// How much of its speed a box keeps when it bounces
+const BOUNCE: f32 = 0.4;
+/// Confetti thrown over a recipient at the party
+const CONFETTI_PIECES: usize = 30;
+const BOX_COLOR: Color = Color::srgb(0.85, 0.25, 0.3);
+const RIBBON_COLOR: Color = Color::srgb(1.0, 0.8, 0.25);
+const HALO_COLOR: Color = Color::srgb(1.0, 0.9, 0.4);
+/// How far over the gifter's head the halo floats
+const HALO_HEIGHT: f32 = 30.0;
+
+/// Gifted subs, from Twitch IRC's USERNOTICE
+#[derive(Debug)]
+pub(crate) enum GiftNotice {
+    /// Someone gifted `count` subs at once, None when they're anonymous
+    Bomb { gifter: Option<String>, count: u64 },
+    /// One gifted sub. A bomb's recipients each come as one of these after the bomb.
+    Gift { gifter: Option<String>, recipient: String },
+}
+
+#[derive(Resource)]
+pub(crate) struct GiftReceiver {
+    pub(crate) receiver: mpsc::Receiver<GiftNotice>,
+}
+
+struct GiftBomb {
+    gifter: Option<String>,
+    /// Boxes to drop, the gift count capped at MAX_BOXES
+    boxes: usize,
+    dropped: usize,
+    /// Recipients not yet shown, in the order they were announced
+    recipients: VecDeque<String>,
+    /// Recipients Twitch hasn't announced yet
+    expected: u64,
+    /// Subs gifted in the bomb, shown under the gifter's halo
+    count: u64,
+}
+
+/// Gift bombs waiting to play, the first one is dropping. One plays at a time so back to back
+/// bombs don't bury the party in boxes.
+#[derive(Resource, Default)]
+pub(crate) struct GiftBombs {
+    queue: VecDeque<GiftBomb>,
+    next_drop: Option<Instant>,
+}
+
+/// A gifted sub falling onto the party, opening into its recipient's name
+#[derive(Component)]
+pub(crate) struct GiftBox {
+    velocity: f32,
+    bounced: bool,
+    opened: Option<Instant>,
+}
+
+/// Shown over the gifter's minawan while their bomb plays
+#[derive(Component)]
+pub(crate) struct GiftHalo {}
+
+/// A Twitch user at the party by their login, which their key has in another case
+fn find_user<'a>(app_state: &'a AppState, login: &str) -> Option<&'a User> {
+    app_state
+        .active_users
+        .iter()
+        .find(|(key, _)| !key.contains(':') && key.eq_ignore_ascii_case(login))
+        .map(|(_, user)| user)
+}
+
+// Queue gift bombs as they're announced and hand each its recipients as they come in
+pub(crate) fn receive_gifts(
+    mut gift_receiver: ResMut<GiftReceiver>,
+    mut bombs: ResMut<GiftBombs>,
+    config: Res<Config>,
+) {
+    while let Ok(notice) = gift_receiver.receiver.try_recv() {
+        if !config.gift_bombs_enabled {
+            continue;
+        }
+        match notice {
+            GiftNotice::Bomb { gifter, count } => {
+                info!("{} gifted {} subs", gifter.as_deref().unwrap_or("Someone"), count);
+                bombs.queue.push_back(GiftBomb {
+                    gifter,
+                    boxes: (count as usize).min(config.gift_max_boxes),
+                    dropped: 0,
+                    recipients: VecDeque::new(),
+                    expected: count,
+                    count,
+                });
+            }
+            GiftNotice::Gift { gifter, recipient } => {
+                // Gifts that aren't part of a bomb are left to the chat's own announcement
+                let Some(bomb) = bombs
+                    .queue
+                    .iter_mut()
+                    .find(|bomb| bomb.gifter == gifter && bomb.expected > 0)
+                else {
+                    debug!("{} was gifted a sub", recipient);
+                    continue;
+                };
+                bomb.expected -= 1;
+                if bomb.recipients.len() + bomb.dropped < bomb.boxes {
+                    bomb.recipients.push_back(recipient);
+                }
+            }
+        }
+    }
+}
+
+fn spawn_box(commands: &mut Commands, x: f32, y: f32) {
+    commands
+        .spawn((
+            SpatialBundle::from_transform(Transform::from_xyz(x, y, BOX_Z)),
+            GiftBox {
+                velocity: 0.0,
+                bounced: false,
+                opened: None,
+            },
+        ))
+        .with_children(|gift| {
+            for (color, size, y, z) in [
+                (BOX_COLOR, Vec2::splat(BOX_SIZE), 0.0, 0.0),
+                (RIBBON_COLOR, Vec2::new(BOX_SIZE * 0.2, BOX_SIZE), 0.0, 0.1),
+                (RIBBON_COLOR, Vec2::new(BOX_SIZE, BOX_SIZE * 0.2), 0.0, 0.1),
+                (RIBBON_COLOR, Vec2::new(BOX_SIZE * 0.5, BOX_SIZE * 0.2), BOX_SIZE * 0.6, 0.1),
+            ] {
+                gift.spawn(SpriteBundle {
+                    sprite: Sprite {
+                        color,
+                        custom_size: Some(size),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(0.0, y, z),
+                    ..default()
+                });
+            }
+        });
+}
+
+fn spawn_halo(commands: &mut Commands, entity: Entity, label: String, style: TextStyle) {
+    commands.entity(entity).with_children(|parent| {
+        parent
+            .spawn((
+                SpatialBundle::from_transform(Transform::from_xyz(0.0, HALO_HEIGHT, 1.0)),
+                GiftHalo {},
+            ))
+            .with_children(|halo| {
+                // A ring seen from the side, a gold bar with a darker middle
+                for (color, size, z) in [
+                    (HALO_COLOR, Vec2::new(28.0, 6.0), 0.0),
+                    (Color::srgb(0.8, 0.6, 0.1), Vec2::new(20.0, 2.0), 0.1),
+                ] {
+                    halo.spawn(SpriteBundle {
+                        sprite: Sprite {
+                            color,
+                            custom_size: Some(size),
+                            ..default()
+                        },
+                        transform: Transform::from_xyz(0.0, 0.0, z),
+                        ..default()
+                    });
+                }
+                halo.spawn(Text2dBundle {
+                    text: Text::from_section(label, style),
+                    transform: Transform::from_xyz(0.0, 14.0, 0.1),
+                    ..default()
+                });
+            });
+    });
+}
+
+// Drop the first bomb's boxes one after the other across the walk area. Each falls, bounces
+// once and opens into its recipient's name for LABEL_SECS, or into confetti over the
+// recipient's own minawan when they're at the party. The gifter wears a halo with the sub count
+// meanwhile.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn drop_gift_boxes(
+    mut commands: Commands,
+    mut bombs: ResMut<GiftBombs>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    mut box_query: Query<(Entity, &mut Transform, &mut GiftBox)>,
+    halo_query: Query<Entity, With<GiftHalo>>,
+    avatar_query: Query<&Transform, (With<UserMarker>, Without<GiftBox>)>,
+    wave_query: Query<(), With<Wave>>,
+) {
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+    let now = Instant::now();
+    let delta = time.delta_seconds();
+    let floor = config.floor_y(rect.max.y) + BOX_SIZE / 2.0;
+
+    let mut boxes = 0;
+    for (entity, mut transform, mut gift) in box_query.iter_mut() {
+        boxes += 1;
+        if let Some(opened) = gift.opened {
+            if now.duration_since(opened) >= config.gift_label_time {
+                commands.entity(entity).despawn_recursive();
+            }
+            continue;
+        }
+        gift.velocity -= GRAVITY * delta;
+        transform.translation.y += gift.velocity * delta;
+        if transform.translation.y > floor {
+            continue;
+        }
+        transform.translation.y = floor;
+        if !gift.bounced {
+            gift.bounced = true;
+            gift.velocity = -gift.velocity * BOUNCE;
+            continue;
+        }
+
+        // Landed for good, open it
+        gift.opened = Some(now);
+        commands.entity(entity).despawn_descendants();
+        let recipient = bombs.queue.front_mut().and_then(|bomb| bomb.recipients.pop_front());
+        let at_party = recipient.as_deref().and_then(|login| find_user(&app_state, login));
+        match (recipient, at_party) {
+            (Some(_), Some(user)) => {
+                if let Ok(avatar) = avatar_query.get(user.entity) {
+                    let position = avatar.translation;
+                    burst_confetti(
+                        &mut commands,
+                        (position.x - 30.0, position.x + 30.0),
+                        (position.y + 30.0, position.y + 80.0),
+                        CONFETTI_PIECES,
+                    );
+                    if !wave_query.contains(user.entity) {
+                        commands.entity(user.entity).insert(Wave {
+                            started: now,
+                            base_y: position.y,
+                        });
+                    }
+                }
+                // Nothing left to show where the box was
+                commands.entity(entity).despawn_recursive();
+            }
+            (Some(recipient), None) => {
+                commands.entity(entity).with_children(|gift| {
+                    gift.spawn(Text2dBundle {
+                        text: Text::from_section(
+                            recipient,
+                            TextStyle {
+                                font: asset_server.load(&config.font_url),
+                                font_size: config.font_size * 0.8,
+                                color: Color::WHITE,
+                            },
+                        ),
+                        ..default()
+                    });
+                });
+            }
+            (None, _) => {
+                let position = transform.translation;
+                burst_confetti(
+                    &mut commands,
+                    (position.x - BOX_SIZE, position.x + BOX_SIZE),
+                    (position.y, position.y + BOX_SIZE * 2.0),
+                    CONFETTI_PIECES / 3,
+                );
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+
+    let GiftBombs { queue, next_drop } = &mut *bombs;
+    let Some(bomb) = queue.front_mut() else {
+        return;
+    };
+    if bomb.dropped >= bomb.boxes {
+        // Done once every box has gone, then the next bomb gets its turn
+        if boxes == 0 {
+            for halo in halo_query.iter() {
+                commands.entity(halo).despawn_recursive();
+            }
+            queue.pop_front();
+            *next_drop = None;
+        }
+        return;
+    }
+    if next_drop.is_some_and(|next| now < next) {
+        return;
+    }
+    if bomb.dropped == 0 {
+        if let Some(gifter) = bomb.gifter.as_deref().and_then(|login| find_user(&app_state, login)) {
+            let style = TextStyle {
+                font: asset_server.load(&config.font_url),
+                font_size: config.font_size * 0.8,
+                color: HALO_COLOR,
+            };
+            spawn_halo(&mut commands, gifter.entity, t!("gifts.gifted", count = bomb.count), style);
+        }
+    }
+    bomb.dropped += 1;
+    let ranges = config.walk_ranges(rect.max.x);
+    let left = ranges.first().map_or(-rect.max.x / 2.0, |range| range.0);
+    let right = ranges.last().map_or(rect.max.x / 2.0, |range| range.1);
+    let x = rand::thread_rng().gen_range(left..=right.max(left));
+    spawn_box(&mut commands, x, rect.max.y / 2.0 + BOX_SIZE);
+    *next_drop = Some(now + DROP_INTERVAL);
+}
@@ -0,0 +1,353 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    asset::AssetServer,
+    color::{Alpha, Color},
+    hierarchy::{BuildChildren, ChildBuilder, DespawnRecursiveExt},
+    math::{Quat, Vec2, Vec3},
+    prelude::{
+        default, Commands, Component, Entity, Event, EventReader, EventWriter, Query, Res, ResMut,
+        Resource, SpatialBundle, Transform, Visibility, With, Without,
+    },
+    sprite::{Anchor, Sprite, SpriteBundle},
+    text::TextStyle,
+    ui::{
+        node_bundles::{NodeBundle, TextBundle},
+        JustifyContent, PositionType, Style, UiRect, Val,
+    },
+};
+use log::{info, warn};
+use rand::seq::SliceRandom;
+
+use crate::{
+    api::ApiEvent,
+    config::Config,
+    locale::t,
+    sizes::{avatar_size, feet_y, head_y, HeadOffset, SizeClass},
+    AppState, UserMarker,
+};
+
+/// Times the spotlight moves before it lands on the winner
+const HOPS: u32 = 20;
+/// Tall enough to reach the top of any screen from the floor
+const SPOTLIGHT_HEIGHT: f32 = 4000.0;
+const SPOTLIGHT_COLOR: Color = Color::srgba(1.0, 0.9, 0.4, 0.3);
+const CROWN_COLOR: Color = Color::srgb(1.0, 0.84, 0.0);
+
+/// Pick a winner from the party, sent by the pick and reroll commands and the tray
+#[derive(Event, Debug, Clone)]
+pub(crate) struct PickWinner {
+    /// Only users who said this recently can win
+    pub(crate) keyword: Option<String>,
+    /// Pick again from the same giveaway, leaving out everyone who already won it
+    pub(crate) reroll: bool,
+}
+
+/// The spotlight hopping between candidates
+struct Roulette {
+    candidates: Vec<String>,
+    /// User the spotlight is on
+    current: Option<String>,
+    started: Instant,
+    hops: u32,
+    spotlight: Entity,
+}
+
+/// Who said what recently, and the giveaway that's running
+#[derive(Resource, Default)]
+pub(crate) struct Giveaway {
+    /// Words each user said, with when they last said them
+    said: HashMap<String, HashMap<String, Instant>>,
+    last_prune: Option<Instant>,
+    keyword: Option<String>,
+    /// Everyone who won this giveaway, a reroll won't pick them again
+    winners: Vec<String>,
+    roulette: Option<Roulette>,
+}
+
+impl Giveaway {
+    /// Remember the words in a chat message, for giveaways that need a keyword
+    pub(crate) fn record(&mut self, user: &str, message: &str, now: Instant, window: Duration) {
+        let words = self.said.entry(user.to_string()).or_default();
+        for word in message.split_whitespace() {
+            words.insert(word.to_lowercase(), now);
+        }
+        // Forget what's too old to count, now and then so a busy chat doesn't pay for it
+        if self.last_prune.map_or(true, |last| now.duration_since(last) > window) {
+            self.last_prune = Some(now);
+            self.said.retain(|_, words| {
+                words.retain(|_, said| now.duration_since(*said) <= window);
+                !words.is_empty()
+            });
+        }
+    }
+
+    fn said_recently(&self, user: &str, keyword: &str, now: Instant, window: Duration) -> bool {
+        self.said
+            .get(user)
+            .and_then(|words| words.get(keyword))
+            .is_some_and(|said| now.duration_since(*said) <= window)
+    }
+}
+
+/// The light that hops between candidates
+#[derive(Component)]
+pub(crate) struct Spotlight {}
+
+/// Crown on the winner's head until `until`
+#[derive(Component)]
+pub(crate) struct Crown {
+    until: Instant,
+}
+
+/// Banner with the winner's name
+#[derive(Component)]
+pub(crate) struct WinnerBanner {
+    until: Instant,
+}
+
+/// When the spotlight makes its nth hop. The hops get further apart so it slows down.
+fn hop_time(hop: u32, total: Duration) -> Duration {
+    total.mul_f32((hop as f32 / HOPS as f32).powi(2))
+}
+
+// Start a giveaway for the pick and reroll commands
+pub(crate) fn start_giveaways(
+    mut commands: Commands,
+    mut pick_events: EventReader<PickWinner>,
+    mut giveaway: ResMut<Giveaway>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    crown_query: Query<Entity, With<Crown>>,
+    banner_query: Query<Entity, With<WinnerBanner>>,
+) {
+    let now = Instant::now();
+    for event in pick_events.read() {
+        if giveaway.roulette.is_some() {
+            info!("Already picking a winner");
+            continue;
+        }
+        if !event.reroll {
+            giveaway.keyword = event.keyword.as_ref().map(|keyword| keyword.to_lowercase());
+            giveaway.winners.clear();
+        }
+
+        let window = config.giveaway_keyword_window;
+        let candidates: Vec<String> = app_state
+            .active_users
+            .keys()
+            .filter(|user| !giveaway.winners.contains(user))
+            .filter(|user| {
+                giveaway
+                    .keyword
+                    .as_ref()
+                    .map_or(true, |keyword| giveaway.said_recently(user, keyword, now, window))
+            })
+            .cloned()
+            .collect();
+        if candidates.is_empty() {
+            match &giveaway.keyword {
+                Some(keyword) => warn!(
+                    "No one at the party who hasn't won yet said {} in the last {} minutes",
+                    keyword,
+                    window.as_secs() / 60
+                ),
+                None => warn!("No one at the party who hasn't won yet to pick"),
+            }
+            continue;
+        }
+        info!("Picking a winner from {} minawan", candidates.len());
+
+        // The last winner steps down
+        for entity in crown_query.iter().chain(banner_query.iter()) {
+            commands.entity(entity).despawn_recursive();
+        }
+        let spotlight = commands
+            .spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: SPOTLIGHT_COLOR,
+                        anchor: Anchor::BottomCenter,
+                        ..default()
+                    },
+                    visibility: Visibility::Hidden,
+                    ..default()
+                },
+                Spotlight {},
+            ))
+            .id();
+        giveaway.roulette = Some(Roulette {
+            candidates,
+            current: None,
+            started: now,
+            hops: 0,
+            spotlight,
+        });
+    }
+}
+
+// Hop the spotlight between candidates, slowing down until it lands on the winner. The winner
+// gets a crown and a banner with their name.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn run_giveaway_roulette(
+    mut commands: Commands,
+    mut giveaway: ResMut<Giveaway>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    asset_server: Res<AssetServer>,
+    avatar_query: Query<(&Transform, &Sprite, Option<&SizeClass>), With<UserMarker>>,
+    mut spotlight_query: Query<(&mut Transform, &mut Sprite, &mut Visibility), (With<Spotlight>, Without<UserMarker>)>,
+    mut api_events: EventWriter<ApiEvent>,
+) {
+    let Some(roulette) = giveaway.roulette.as_mut() else {
+        return;
+    };
+    // Anyone who left during the roulette is out
+    roulette.candidates.retain(|user| app_state.active_users.contains_key(user));
+    let elapsed = roulette.started.elapsed();
+    let hops = (1..=HOPS)
+        .take_while(|hop| hop_time(*hop, config.giveaway_roulette) <= elapsed)
+        .count() as u32;
+    let mut rng = rand::thread_rng();
+    if roulette.current.is_none() || hops > roulette.hops {
+        roulette.hops = hops;
+        // Never stay on the same minawan when there's anyone else
+        let others: Vec<&String> = roulette
+            .candidates
+            .iter()
+            .filter(|user| roulette.current.as_ref() != Some(*user))
+            .collect();
+        roulette.current = others
+            .choose(&mut rng)
+            .map(|user| user.to_string())
+            .or_else(|| roulette.candidates.first().cloned());
+    }
+
+    let avatar = roulette
+        .current
+        .as_ref()
+        .and_then(|user| app_state.active_users.get(user))
+        .and_then(|user| avatar_query.get(user.entity).ok());
+    if let Ok((mut transform, mut sprite, mut visibility)) = spotlight_query.get_mut(roulette.spotlight) {
+        if let Some((avatar_transform, avatar_sprite, size_class)) = avatar {
+            let size = avatar_size(avatar_sprite, size_class);
+            let feet = avatar_transform.translation.y + feet_y(avatar_sprite, size);
+            transform.translation = Vec3::new(avatar_transform.translation.x, feet, -1.0);
+            sprite.custom_size = Some(Vec2::new(size.x * 1.3, SPOTLIGHT_HEIGHT));
+            *visibility = Visibility::Visible;
+        } else {
+            *visibility = Visibility::Hidden;
+        }
+    }
+
+    if hops < HOPS && !roulette.candidates.is_empty() {
+        return;
+    }
+    let roulette = giveaway.roulette.take().expect("Checked above");
+    commands.entity(roulette.spotlight).despawn_recursive();
+    let Some(winner) = roulette.current.filter(|user| roulette.candidates.contains(user)) else {
+        warn!("Everyone in the giveaway left before a winner was picked");
+        return;
+    };
+    let Some(user) = app_state.active_users.get(&winner) else {
+        return;
+    };
+    info!("{} wins the giveaway", user.name);
+    giveaway.winners.push(winner.clone());
+    api_events.send(ApiEvent::GiveawayWon { user: winner });
+
+    let until = Instant::now() + config.giveaway_duration;
+    let (sprite, size_class) = avatar_query
+        .get(user.entity)
+        .map(|(_, sprite, size_class)| (sprite.clone(), size_class.copied()))
+        .unwrap_or_default();
+    let size = avatar_size(&sprite, size_class.as_ref());
+    let head = head_y(&sprite, size);
+    commands.entity(user.entity).with_children(|parent| {
+        parent
+            .spawn((
+                SpatialBundle::from_transform(Transform::from_xyz(0.0, head + 6.0, 2.0)),
+                Crown { until },
+                HeadOffset::of(&sprite),
+            ))
+            .with_children(|crown| spawn_crown(crown, size.x * 0.5));
+    });
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(16.0),
+                    width: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            WinnerBanner { until },
+        ))
+        .with_children(|builder| {
+            builder.spawn(
+                TextBundle::from_section(
+                    t!("giveaway.winner", user = user.name),
+                    TextStyle {
+                        font: asset_server.load(&config.font_url),
+                        font_size: config.font_size * 2.0,
+                        color: CROWN_COLOR,
+                    },
+                )
+                .with_style(Style {
+                    padding: UiRect::all(Val::Px(8.0)),
+                    ..default()
+                })
+                .with_background_color(Color::BLACK.with_alpha(0.6)),
+            );
+        });
+}
+
+/// A band with three points on it, `width` wide
+pub(crate) fn spawn_crown(crown: &mut ChildBuilder, width: f32) {
+    let band = Vec2::new(width, width * 0.25);
+    crown.spawn(SpriteBundle {
+        sprite: Sprite {
+            color: CROWN_COLOR,
+            custom_size: Some(band),
+            ..default()
+        },
+        ..default()
+    });
+    let point = width * 0.22;
+    for x in [-0.4, 0.0, 0.4] {
+        crown.spawn(SpriteBundle {
+            sprite: Sprite {
+                color: CROWN_COLOR,
+                custom_size: Some(Vec2::splat(point)),
+                ..default()
+            },
+            transform: Transform::from_xyz(width * x, band.y / 2.0, 0.0)
+                .with_rotation(Quat::from_rotation_z(std::f32::consts::FRAC_PI_4)),
+            ..default()
+        });
+    }
+}
+
+// Take the crown and banner down once the winner has had their moment
+pub(crate) fn expire_giveaway_winner(
+    mut commands: Commands,
+    crown_query: Query<(Entity, &Crown)>,
+    banner_query: Query<(Entity, &WinnerBanner)>,
+) {
+    let now = Instant::now();
+    let expired = crown_query
+        .iter()
+        .map(|(entity, crown)| (entity, crown.until))
+        .chain(banner_query.iter().map(|(entity, banner)| (entity, banner.until)));
+    for (entity, until) in expired {
+        if now >= until {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
@@ -0,0 +1,383 @@
+use std::{
+    env,
+    process::{self, Command},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    app::{App, AppExit, Last, Plugin},
+    prelude::{EventWriter, IntoSystemConfigs, Query, Res, ResMut, Resource, With},
+    render::{
+        renderer::RenderAdapterInfo, settings::Backends, view::ExtractedWindows, Render, RenderApp, RenderSet,
+    },
+    window::{PresentMode, PrimaryWindow, Window},
+    winit::{UpdateMode, WinitSettings},
+};
+use log::{error, info, warn};
+
+use crate::{
+    chat::shutdown_chat_sources, config::GpuBackend, framerate::WindowOcclusion, snapshot::save_snapshot,
+    state::save_state_on_exit, AppState, ProgramState,
+};
+
+/// Config override the restarted overlay picks its backend up from
+const BACKEND_ENV: &str = "MWP_GENERAL_BACKEND";
+/// Set for the restarted overlay to the backend that froze, so it can say it recovered
+const RECOVERED_FROM_ENV: &str = "MWP_RECOVERED_FROM";
+/// How often the watchdog thread checks the main loop is still going
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(1);
+/// A gap this long between the watchdog thread's checks means the computer was asleep
+const SLEEP_GAP: Duration = Duration::from_secs(5);
+/// Frames may be this many times further apart than the frame rate asks before it counts as a stall
+const FRAME_GAP_SLACK: u32 = 3;
+
+/// Backends to try one after the other when rendering freezes, best first
+const FALLBACK_ORDER: &[GpuBackend] = if cfg!(windows) {
+    &[GpuBackend::Dx12, GpuBackend::Vulkan, GpuBackend::Gl]
+} else if cfg!(target_os = "macos") {
+    &[GpuBackend::Metal]
+} else {
+    &[GpuBackend::Vulkan, GpuBackend::Gl]
+};
+
+impl GpuBackend {
+    fn backends(self) -> Backends {
+        match self {
+            GpuBackend::Auto => Backends::all(),
+            GpuBackend::Vulkan => Backends::VULKAN,
+            GpuBackend::Dx12 => Backends::DX12,
+            GpuBackend::Metal => Backends::METAL,
+            GpuBackend::Gl => Backends::GL,
+        }
+    }
+
+    fn from_wgpu(backend: wgpu::Backend) -> Option<Self> {
+        match backend {
+            wgpu::Backend::Vulkan => Some(GpuBackend::Vulkan),
+            wgpu::Backend::Dx12 => Some(GpuBackend::Dx12),
+            wgpu::Backend::Metal => Some(GpuBackend::Metal),
+            wgpu::Backend::Gl => Some(GpuBackend::Gl),
+            _ => None,
+        }
+    }
+
+    /// As written in the config file
+    fn name(self) -> &'static str {
+        match self {
+            GpuBackend::Auto => "auto",
+            GpuBackend::Vulkan => "vulkan",
+            GpuBackend::Dx12 => "dx12",
+            GpuBackend::Metal => "metal",
+            GpuBackend::Gl => "gl",
+        }
+    }
+
+    /// The backend to fall back to when this one froze, None when it's the last one
+    fn next(self) -> Option<Self> {
+        let position = FALLBACK_ORDER.iter().position(|backend| *backend == self)?;
+        FALLBACK_ORDER.get(position + 1).copied()
+    }
+}
+
+/// Backends to hand to wgpu. An explicit backend with no usable adapter, such as Vulkan on a
+/// machine with broken drivers, falls back to auto so the overlay always comes up.
+pub(crate) fn choose_backends(backend: GpuBackend) -> Backends {
+    let backends = backend.backends();
+    if backend == GpuBackend::Auto {
+        return backends;
+    }
+
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends,
+        ..Default::default()
+    });
+    if instance.enumerate_adapters(backends).is_empty() {
+        warn!("No {:?} adapter found, falling back to auto", backend);
+        return GpuBackend::Auto.backends();
+    }
+    backends
+}
+
+// Log which adapter and backend wgpu picked, and whether that's after the watchdog restarted
+// the overlay
+pub(crate) fn log_render_adapter(adapter_info: Option<Res<RenderAdapterInfo>>) {
+    if let Some(adapter_info) = adapter_info {
+        info!(
+            "Rendering on {} using {:?}",
+            adapter_info.name, adapter_info.backend
+        );
+        if let Ok(stalled) = env::var(RECOVERED_FROM_ENV) {
+            warn!(
+                "Restarted after rendering froze on {}, now using {:?}",
+                stalled, adapter_info.backend
+            );
+        }
+    }
+}
+
+/// Start the overlay again on another backend, with the same arguments
+fn relaunch(backend: GpuBackend, stalled: GpuBackend) -> bool {
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(err) => {
+            error!("Can't restart the overlay, its executable wasn't found: {}", err);
+            return false;
+        }
+    };
+    let spawned = Command::new(exe)
+        .args(env::args_os().skip(1))
+        .env(BACKEND_ENV, backend.name())
+        .env(RECOVERED_FROM_ENV, stalled.name())
+        .spawn();
+    match spawned {
+        Ok(_) => true,
+        Err(err) => {
+            error!("Failed to restart the overlay: {}", err);
+            false
+        }
+    }
+}
+
+/// Counters the watchdog shares between the main loop, the render world and its own thread
+#[derive(Default)]
+struct Heartbeat {
+    /// Frames the main loop got through
+    frames: AtomicU64,
+    /// Frames the window had something to draw into
+    presented: AtomicU64,
+    /// Longest the main loop may go between frames at the frame rate it's set to, in milliseconds
+    allowed_gap: AtomicU64,
+    /// The backend wgpu picked, once the renderer is up
+    backend: OnceLock<GpuBackend>,
+}
+
+/// How far the watchdog has gone to get rendering back
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Recovery {
+    Watching,
+    /// The window's surface was recreated, restarting is next
+    SurfaceRecreated,
+    /// Nothing left to try
+    GaveUp,
+}
+
+#[derive(Resource)]
+struct GpuWatchdog {
+    heartbeat: Arc<Heartbeat>,
+    stall: Duration,
+    /// Presented frames when they last went up, and when that was
+    presented: u64,
+    last_progress: Instant,
+    last_frame: Instant,
+    recovery: Recovery,
+    /// Present mode to put back the frame after switching it to recreate the surface
+    restore_present_mode: Option<PresentMode>,
+}
+
+/// The render world's handle on the presented frame counter
+#[derive(Resource)]
+struct PresentedFrames(Arc<Heartbeat>);
+
+/// Notices the overlay freezing, after the computer sleeps or a driver reset. It recreates the
+/// window's surface when nothing is drawn and restarts the overlay on the next backend if that
+/// doesn't help, or straight away when the main loop itself stopped. The restarted overlay
+/// brings the party back from its snapshot.
+pub(crate) struct GpuWatchdogPlugin {
+    pub(crate) stall: Duration,
+}
+
+impl Plugin for GpuWatchdogPlugin {
+    fn build(&self, app: &mut App) {
+        if self.stall.is_zero() {
+            return;
+        }
+        let heartbeat = Arc::new(Heartbeat::default());
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .insert_resource(PresentedFrames(heartbeat.clone()))
+            .add_systems(Render, count_presented_frames.after(RenderSet::ManageViews).before(RenderSet::Render));
+
+        let watched = heartbeat.clone();
+        let stall = self.stall;
+        if let Err(err) = thread::Builder::new()
+            .name("gpu-watchdog".to_string())
+            .spawn(move || watch_main_loop(watched, stall))
+        {
+            warn!("Failed to start the GPU watchdog thread: {}", err);
+        }
+
+        app.insert_resource(GpuWatchdog {
+            heartbeat,
+            stall,
+            presented: 0,
+            last_progress: Instant::now(),
+            last_frame: Instant::now(),
+            recovery: Recovery::Watching,
+            restore_present_mode: None,
+        })
+        .add_systems(
+            Last,
+            watch_rendering
+                .before(save_snapshot)
+                .before(save_state_on_exit)
+                .before(shutdown_chat_sources),
+        );
+    }
+}
+
+// Count the frames the primary window got a surface texture to draw into
+fn count_presented_frames(frames: Res<PresentedFrames>, windows: Res<ExtractedWindows>) {
+    let presented = windows
+        .primary
+        .and_then(|primary| windows.windows.get(&primary))
+        .is_some_and(|window| window.swap_chain_texture_view.is_some());
+    if presented {
+        frames.0.presented.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// Keep the watchdog thread posted on the main loop, and step in when the window stops being
+// drawn while it's on screen: recreate its surface first, then restart on the next backend
+fn watch_rendering(
+    mut watchdog: ResMut<GpuWatchdog>,
+    app_state: Res<AppState>,
+    occlusion: Res<WindowOcclusion>,
+    winit_settings: Res<WinitSettings>,
+    adapter_info: Option<Res<RenderAdapterInfo>>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let watchdog = &mut *watchdog;
+    let heartbeat = &watchdog.heartbeat;
+    heartbeat.frames.fetch_add(1, Ordering::Relaxed);
+    let wait = match winit_settings.focused_mode {
+        UpdateMode::Reactive { wait, .. } => wait,
+        UpdateMode::Continuous => Duration::ZERO,
+    };
+    heartbeat
+        .allowed_gap
+        .store((wait * FRAME_GAP_SLACK).as_millis() as u64, Ordering::Relaxed);
+    let backend = adapter_info.and_then(|info| GpuBackend::from_wgpu(info.backend));
+    if let Some(backend) = backend {
+        let _ = heartbeat.backend.set(backend);
+    }
+    let Ok(mut window) = windows.get_single_mut() else {
+        return;
+    };
+    if let Some(mode) = watchdog.restore_present_mode.take() {
+        window.present_mode = mode;
+    }
+
+    let now = Instant::now();
+    // Waking from sleep, the frame before it doesn't count towards a stall
+    let asleep = now.duration_since(watchdog.last_frame) >= SLEEP_GAP.max(wait * FRAME_GAP_SLACK);
+    watchdog.last_frame = now;
+    let presented = heartbeat.presented.load(Ordering::Relaxed);
+    let drawable = window.physical_width() > 0 && window.physical_height() > 0 && window.visible;
+    let expected = matches!(app_state.program_state, ProgramState::Running) && occlusion.on_screen() && drawable;
+    if presented != watchdog.presented || !expected || asleep {
+        if presented != watchdog.presented && watchdog.recovery == Recovery::SurfaceRecreated {
+            warn!("Rendering is back after recreating the window surface");
+            watchdog.recovery = Recovery::Watching;
+        }
+        watchdog.presented = presented;
+        watchdog.last_progress = now;
+        return;
+    }
+    let allowed = watchdog.stall.max(wait * FRAME_GAP_SLACK);
+    if now.duration_since(watchdog.last_progress) < allowed {
+        return;
+    }
+
+    watchdog.last_progress = now;
+    match watchdog.recovery {
+        Recovery::Watching => {
+            warn!(
+                "Nothing was drawn for {} seconds, recreating the window surface",
+                allowed.as_secs()
+            );
+            // Switching the present mode has Bevy configure the surface again, the
+            // configured one goes back on the next frame
+            watchdog.restore_present_mode = Some(window.present_mode);
+            window.present_mode = match window.present_mode {
+                PresentMode::AutoVsync => PresentMode::AutoNoVsync,
+                _ => PresentMode::AutoVsync,
+            };
+            watchdog.recovery = Recovery::SurfaceRecreated;
+        }
+        Recovery::SurfaceRecreated => match backend.and_then(|backend| backend.next().map(|next| (backend, next))) {
+            Some((stalled, next)) => {
+                error!(
+                    "Still nothing drawn on {}, restarting the overlay on {}",
+                    stalled.name(),
+                    next.name()
+                );
+                if relaunch(next, stalled) {
+                    exit.send(AppExit::Success);
+                }
+                watchdog.recovery = Recovery::GaveUp;
+            }
+            None => {
+                error!("Still nothing drawn and there's no other graphics API to fall back to");
+                watchdog.recovery = Recovery::GaveUp;
+            }
+        },
+        Recovery::GaveUp => {}
+    }
+}
+
+/// Runs on its own thread, as a frozen main loop can't notice itself. With no way to reach the
+/// renderer from here it goes straight to restarting on the next backend, the party comes back
+/// from the last autosave.
+fn watch_main_loop(heartbeat: Arc<Heartbeat>, stall: Duration) {
+    let mut frames = 0;
+    let mut last_frame = Instant::now();
+    let mut last_check = Instant::now();
+    loop {
+        thread::sleep(WATCHDOG_INTERVAL);
+        let now = Instant::now();
+        // Waking from sleep, the main loop gets its full time to pick up again
+        if now.duration_since(last_check) >= SLEEP_GAP {
+            last_frame = now;
+        }
+        last_check = now;
+
+        let current = heartbeat.frames.load(Ordering::Relaxed);
+        // The first frame can take a while with shaders to compile
+        if current != frames || current == 0 {
+            frames = current;
+            last_frame = now;
+            continue;
+        }
+        let allowed = stall.max(Duration::from_millis(heartbeat.allowed_gap.load(Ordering::Relaxed)));
+        if now.duration_since(last_frame) < allowed {
+            continue;
+        }
+        let Some(stalled) = heartbeat.backend.get().copied() else {
+            continue;
+        };
+        let Some(next) = stalled.next() else {
+            error!(
+                "The overlay stopped responding {} seconds ago and there's no other graphics API to fall back to",
+                allowed.as_secs()
+            );
+            return;
+        };
+        error!(
+            "The overlay stopped responding {} seconds ago, restarting it on {}",
+            allowed.as_secs(),
+            next.name()
+        );
+        if relaunch(next, stalled) {
+            process::exit(1);
+        }
+        return;
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    collections::{BTreeSet, VecDeque},
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    asset::AssetServer,
+    color::Color,
+    hierarchy::{BuildChildren, DespawnRecursiveExt},
+    math::Vec2,
+    prelude::{default, Commands, Component, Entity, EventReader, Query, Res, ResMut, Resource, Transform},
+    text::{Text, Text2dBundle, TextStyle},
+};
+use chrono::NaiveDate;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::ApiEvent,
+    config::Config,
+    opacity::{fade, BaseAlpha},
+    streaks::ChatStreaks,
+    AppState,
+};
+
+/// How long a greeting floats up and fades
+const PUFF_DURATION: Duration = Duration::from_millis(2500);
+/// Where a greeting starts relative to the avatar's center, just over its head
+const PUFF_OFFSET: Vec2 = Vec2::new(0.0, 30.0);
+const PUFF_RISE: f32 = 16.0;
+const PUFF_COLOR: Color = Color::srgb(1.0, 0.95, 0.7);
+
+/// Who was greeted on a day, saved with the party snapshot
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub(crate) struct GreetedToday {
+    day: Option<NaiveDate>,
+    users: BTreeSet<String>,
+}
+
+/// Who has been greeted today, and when the latest first messages of the day came in to
+/// tell a raid apart
+#[derive(Resource, Default)]
+pub(crate) struct Greetings {
+    greeted: GreetedToday,
+    arrivals: VecDeque<Instant>,
+}
+
+impl Greetings {
+    /// Who the last run greeted, if that was today
+    pub(crate) fn restored(saved: Option<GreetedToday>, today: NaiveDate) -> Self {
+        Self {
+            greeted: saved.filter(|saved| saved.day == Some(today)).unwrap_or_default(),
+            ..default()
+        }
+    }
+
+    /// Who was greeted today, None when nobody was
+    pub(crate) fn greeted(&self) -> Option<GreetedToday> {
+        (!self.greeted.users.is_empty()).then(|| self.greeted.clone())
+    }
+
+    /// Forget that a Twitch user was greeted today
+    pub(crate) fn forget(&mut self, login: &str) {
+        self.greeted.users.remove(&login.to_lowercase());
+    }
+
+    /// Note that a user had their first message of the day, false if they already had
+    fn first_today(&mut self, user: &str, today: NaiveDate) -> bool {
+        if self.greeted.day != Some(today) {
+            self.greeted = GreetedToday {
+                day: Some(today),
+                users: BTreeSet::new(),
+            };
+        }
+        self.greeted.users.insert(user.to_lowercase())
+    }
+
+    /// Note a first message of the day and tell whether there have been too many lately
+    fn raiding(&mut self, now: Instant, config: &Config) -> bool {
+        self.arrivals.push_back(now);
+        while self
+            .arrivals
+            .front()
+            .is_some_and(|time| now.duration_since(*time) > config.greeting_raid_window)
+        {
+            self.arrivals.pop_front();
+        }
+        self.arrivals.len() > config.greeting_raid_threshold
+    }
+}
+
+/// A greeting floating up from a minawan
+#[derive(Component)]
+pub(crate) struct GreetingPuff {
+    started: Instant,
+}
+
+// Greet returning chatters over their minawan the first time they chat each day. Nobody is
+// greeted while first messages come in faster than RAID_THRESHOLD per RAID_WINDOW_SECS, but
+// they still count as greeted.
+pub(crate) fn greet_returning_chatters(
+    mut commands: Commands,
+    mut party_events: EventReader<ApiEvent>,
+    mut greetings: ResMut<Greetings>,
+    streaks: Res<ChatStreaks>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    asset_server: Res<AssetServer>,
+) {
+    if !config.greetings_enabled {
+        party_events.clear();
+        return;
+    }
+    let today = config.today();
+    let now = Instant::now();
+    for event in party_events.read() {
+        let ApiEvent::MessageDisplayed { user, .. } = event else {
+            continue;
+        };
+        if !greetings.first_today(user, today) || !streaks.chatted_before(user, today) {
+            continue;
+        }
+        if greetings.raiding(now, &config) {
+            debug!("Too many first messages at once, not greeting {}", user);
+            continue;
+        }
+        let Some(entity) = app_state.active_users.get(user).map(|user| user.entity) else {
+            continue;
+        };
+        let greeting = config
+            .greeting_custom
+            .get(&user.to_lowercase())
+            .unwrap_or(&config.greeting_text);
+        info!("Greeting {}: {}", user, greeting);
+        commands.entity(entity).with_children(|parent| {
+            parent.spawn((
+                Text2dBundle {
+                    text: Text::from_section(
+                        greeting.clone(),
+                        TextStyle {
+                            font: asset_server.load(&config.font_url),
+                            font_size: config.font_size * 0.7,
+                            color: PUFF_COLOR,
+                        },
+                    ),
+                    transform: Transform::from_translation(PUFF_OFFSET.extend(4.0)),
+                    ..default()
+                },
+                BaseAlpha(1.0),
+                GreetingPuff { started: now },
+            ));
+        });
+    }
+}
+
+// Float greetings up while they fade, and clear them away at the top
+pub(crate) fn animate_greetings(
+    mut commands: Commands,
+    mut puff_query: Query<(Entity, &mut Transform, &mut BaseAlpha, &GreetingPuff)>,
+) {
+    for (entity, mut transform, alpha, puff) in puff_query.iter_mut() {
+        let progress = puff.started.elapsed().as_secs_f32() / PUFF_DURATION.as_secs_f32();
+        if progress >= 1.0 {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        transform.translation.y = PUFF_OFFSET.y + PUFF_RISE * progress;
+        // Stays solid for the first half, then fades
+        fade(Some(alpha), (2.0 - progress * 2.0).min(1.0));
+    }
+}
@@ -0,0 +1,142 @@
+use std::{any::TypeId, collections::HashMap, sync::Arc, time::Duration};
+
+use bevy::{
+    asset::{meta::Settings, Asset, AssetServer, Handle, UntypedHandle},
+    prelude::{ResMut, Resource},
+};
+use log::debug;
+
+/// How often assets nothing uses any more are let go
+pub(crate) const HANDLE_CACHE_PRUNE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Handles for everything loaded by path or URL, so the same emote or avatar is only asked for
+/// once however many messages use it. Entries are strong so an asset stays loaded while on screen.
+#[derive(Resource, Default)]
+pub(crate) struct HandleCache {
+    entries: HashMap<(TypeId, String), UntypedHandle>,
+    hits: u64,
+    loads: u64,
+}
+
+impl HandleCache {
+    /// Load an asset, or hand out the handle from the first time it was loaded
+    pub(crate) fn load<A: Asset>(&mut self, asset_server: &AssetServer, path: &str) -> Handle<A> {
+        self.get_or_load(path, |path| asset_server.load::<A>(path))
+    }
+
+    /// Like `load`, the settings only apply to the first load of a path
+    pub(crate) fn load_with_settings<A: Asset, S: Settings>(
+        &mut self,
+        asset_server: &AssetServer,
+        path: &str,
+        settings: impl Fn(&mut S) + Send + Sync + 'static,
+    ) -> Handle<A> {
+        self.get_or_load(path, |path| asset_server.load_with_settings::<A, S>(path, settings))
+    }
+
+    fn get_or_load<A: Asset>(&mut self, path: &str, load: impl FnOnce(String) -> Handle<A>) -> Handle<A> {
+        let key = (TypeId::of::<A>(), canonical(path));
+        if let Some(handle) = self.entries.get(&key) {
+            self.hits += 1;
+            return handle.clone().typed::<A>();
+        }
+        self.loads += 1;
+        let handle = load(key.1.clone());
+        self.entries.insert(key, handle.clone().untyped());
+        handle
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Share of requests that were answered from the cache
+    pub(crate) fn hit_rate(&self) -> f32 {
+        let total = self.hits + self.loads;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f32 / total as f32
+        }
+    }
+}
+
+/// One spelling for each path or URL, so `./avatars/a.png` and `avatars\a.png` are the same file
+fn canonical(path: &str) -> String {
+    let path = path.trim();
+    if let Ok(url) = reqwest::Url::parse(path) {
+        if matches!(url.scheme(), "http" | "https") {
+            return url.to_string();
+        }
+    }
+    path.replace('\\', "/").trim_start_matches("./").to_string()
+}
+
+// Forget assets only the cache still holds, which lets Bevy unload them
+pub(crate) fn prune_handle_cache(mut handles: ResMut<HandleCache>) {
+    let before = handles.entries.len();
+    handles.entries.retain(|_, handle| match handle {
+        UntypedHandle::Strong(handle) => Arc::strong_count(handle) > 1,
+        UntypedHandle::Weak(_) => false,
+    });
+    let pruned = before - handles.entries.len();
+    if pruned > 0 {
+        debug!("Let go of {} unused assets, {} still cached", pruned, handles.entries.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        app::App,
+        asset::{AssetApp, AssetPlugin},
+        prelude::Image,
+        MinimalPlugins,
+    };
+
+    use super::*;
+
+    fn asset_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default())).init_asset::<Image>();
+        app
+    }
+
+    #[test]
+    fn same_path_is_loaded_once() {
+        let app = asset_app();
+        let asset_server = app.world().resource::<AssetServer>();
+        let mut cache = HandleCache::default();
+        let first = cache.load::<Image>(asset_server, "avatars/minawan.png");
+        let second = cache.load::<Image>(asset_server, "avatars/minawan.png");
+        assert_eq!(first, second);
+        assert_eq!((cache.loads, cache.hits, cache.len()), (1, 1, 1));
+    }
+
+    #[test]
+    fn spellings_of_a_path_share_a_handle() {
+        let app = asset_app();
+        let asset_server = app.world().resource::<AssetServer>();
+        let mut cache = HandleCache::default();
+        let first = cache.load::<Image>(asset_server, "./avatars/minawan.png");
+        let second = cache.load::<Image>(asset_server, "avatars\\minawan.png");
+        assert_eq!(first, second);
+        assert_eq!(cache.loads, 1);
+    }
+
+    #[test]
+    fn url_requested_twice_is_loaded_once() {
+        let url = "https://cdn.7tv.app/emote/01F6MQ33FG000FFJ97ZB8MWV52/4x.webp";
+        let mut cache = HandleCache::default();
+        let padded = format!(" {} ", url);
+        let mut loads = vec![];
+        for requested in [url, padded.as_str()] {
+            cache.get_or_load::<Image>(requested, |path| {
+                loads.push(path);
+                Handle::default()
+            });
+        }
+        assert_eq!(loads, vec![url.to_string()]);
+        assert_eq!((cache.loads, cache.hits), (1, 1));
+    }
+}
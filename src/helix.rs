@@ -0,0 +1,183 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+
+use crate::config::Config;
+
+const HELIX_URL: &str = "https://api.twitch.tv/helix";
+
+/// Client for authenticated Helix requests, shared by every feature that talks to the Twitch API
+#[derive(Clone)]
+pub(crate) struct HelixClient {
+    client: reqwest::Client,
+    client_id: String,
+    token: String,
+}
+
+/// Helix wraps every list response in a `data` array
+#[derive(Deserialize, Debug)]
+pub(crate) struct HelixResponse<T> {
+    pub data: Vec<T>,
+}
+
+/// A page of a longer list, the cursor asks for the next one
+#[derive(Deserialize, Debug)]
+pub(crate) struct HelixPage<T> {
+    pub data: Vec<T>,
+    #[serde(default)]
+    pub pagination: HelixPagination,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub(crate) struct HelixPagination {
+    pub cursor: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct HelixChatter {
+    pub user_login: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct HelixStream {
+    pub viewer_count: u64,
+    pub started_at: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct HelixUser {
+    pub id: String,
+}
+
+/// A badge and each of its versions, like the subscriber badge for every milestone
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct HelixBadgeSet {
+    pub set_id: String,
+    pub versions: Vec<HelixBadgeVersion>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct HelixBadgeVersion {
+    pub id: String,
+    pub image_url_2x: String,
+}
+
+impl HelixClient {
+    /// Build a client from the config, returns None when no credentials are configured
+    pub(crate) fn from_config(config: &Config) -> Option<Self> {
+        let client_id = config.twitch_client_id.clone()?;
+        let token = config.twitch_token.clone()?;
+        Some(Self {
+            client: reqwest::Client::new(),
+            client_id,
+            // IRC tokens are prefixed with "oauth:", Helix wants the bare token
+            token: token.trim_start_matches("oauth:").to_string(),
+        })
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T, reqwest::Error> {
+        self.client
+            .get(format!("{}{}", HELIX_URL, path))
+            .query(query)
+            .header("Client-Id", &self.client_id)
+            .bearer_auth(&self.token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<T>()
+            .await
+    }
+
+    async fn post<B: Serialize>(&self, path: &str, body: &B) -> Result<(), reqwest::Error> {
+        self.client
+            .post(format!("{}{}", HELIX_URL, path))
+            .header("Client-Id", &self.client_id)
+            .bearer_auth(&self.token)
+            .json(body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Subscribe an EventSub websocket session to a topic
+    pub(crate) async fn create_eventsub_subscription(
+        &self,
+        kind: &str,
+        version: &str,
+        condition: &serde_json::Value,
+        session_id: &str,
+    ) -> Result<(), reqwest::Error> {
+        let body = json!({
+            "type": kind,
+            "version": version,
+            "condition": condition,
+            "transport": {
+                "method": "websocket",
+                "session_id": session_id,
+            },
+        });
+        self.post("/eventsub/subscriptions", &body).await
+    }
+
+    /// Get the id of the user the token belongs to
+    pub(crate) async fn get_token_user_id(&self) -> Result<Option<String>, reqwest::Error> {
+        let response: HelixResponse<HelixUser> = self.get("/users", &[]).await?;
+        Ok(response.data.into_iter().next().map(|user| user.id))
+    }
+
+    /// Get the current stream for a broadcaster, None when they are offline
+    pub(crate) async fn get_stream(
+        &self,
+        broadcaster_id: &str,
+    ) -> Result<Option<HelixStream>, reqwest::Error> {
+        let response: HelixResponse<HelixStream> =
+            self.get("/streams", &[("user_id", broadcaster_id)]).await?;
+        Ok(response.data.into_iter().next())
+    }
+
+    /// Get the badges every channel has
+    pub(crate) async fn get_global_badges(&self) -> Result<Vec<HelixBadgeSet>, reqwest::Error> {
+        let response: HelixResponse<HelixBadgeSet> = self.get("/chat/badges/global", &[]).await?;
+        Ok(response.data)
+    }
+
+    /// Get a channel's own badges, its subscriber badges and bits badges
+    pub(crate) async fn get_channel_badges(
+        &self,
+        broadcaster_id: &str,
+    ) -> Result<Vec<HelixBadgeSet>, reqwest::Error> {
+        let response: HelixResponse<HelixBadgeSet> =
+            self.get("/chat/badges", &[("broadcaster_id", broadcaster_id)]).await?;
+        Ok(response.data)
+    }
+
+    /// Get the logins of everyone in chat, the token needs the moderator:read:chatters scope
+    pub(crate) async fn get_chatters(
+        &self,
+        broadcaster_id: &str,
+        moderator_id: &str,
+    ) -> Result<Vec<String>, reqwest::Error> {
+        let mut chatters = vec![];
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut query = vec![
+                ("broadcaster_id", broadcaster_id),
+                ("moderator_id", moderator_id),
+                ("first", "1000"),
+            ];
+            if let Some(cursor) = &cursor {
+                query.push(("after", cursor));
+            }
+            let page: HelixPage<HelixChatter> = self.get("/chat/chatters", &query).await?;
+            chatters.extend(page.data.into_iter().map(|chatter| chatter.user_login));
+            match page.pagination.cursor {
+                Some(next) if !next.is_empty() => cursor = Some(next),
+                _ => return Ok(chatters),
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::sync::mpsc;
+
+use bevy::prelude::{EventWriter, NonSend};
+use global_hotkey::{hotkey::HotKey, GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState};
+use log::{error, info};
+
+use crate::{actions::OverlayAction, config::Config, framerate::FrameWaker};
+
+/// OS level hotkeys that work while another program, such as a game, has focus.
+/// Kept as a non-send resource because the OS hooks belong to the main thread.
+#[derive(Default)]
+pub(crate) struct Hotkeys {
+    /// Unregisters the hotkeys when dropped
+    _manager: Option<GlobalHotKeyManager>,
+    actions: Vec<(u32, OverlayAction)>,
+    receiver: Option<mpsc::Receiver<GlobalHotKeyEvent>>,
+}
+
+/// Register the hotkeys from the config. Combos that don't parse, are used twice or are
+/// taken by another program are logged as errors and skipped.
+pub(crate) fn register_hotkeys(config: &Config, waker: FrameWaker) -> Hotkeys {
+    let mut hotkeys = Hotkeys::default();
+    let bindings = [
+        (OverlayAction::ToggleVisibility, "TOGGLE_VISIBILITY", &config.hotkey_toggle_visibility),
+        (OverlayAction::ToggleClickThrough, "TOGGLE_CLICK_THROUGH", &config.hotkey_toggle_click_through),
+        (OverlayAction::ClearParty, "CLEAR_PARTY", &config.hotkey_clear_party),
+        (OverlayAction::OpacityUp, "OPACITY_UP", &config.hotkey_opacity_up),
+        (OverlayAction::OpacityDown, "OPACITY_DOWN", &config.hotkey_opacity_down),
+        (OverlayAction::ToggleCapture, "TOGGLE_CAPTURE", &config.hotkey_toggle_capture),
+        (OverlayAction::ToggleDebug, "TOGGLE_DEBUG", &config.hotkey_toggle_debug),
+        (OverlayAction::Screenshot, "SCREENSHOT", &config.hotkey_screenshot),
+        (OverlayAction::ScreenshotBurst, "SCREENSHOT_BURST", &config.hotkey_screenshot_burst),
+    ];
+    if bindings.iter().all(|(_, _, combo)| combo.is_none()) {
+        return hotkeys;
+    }
+
+    let manager = match GlobalHotKeyManager::new() {
+        Ok(manager) => manager,
+        Err(err) => {
+            error!("Global hotkeys aren't available: {}", err);
+            return hotkeys;
+        }
+    };
+    let mut registered: Vec<(HotKey, &str)> = vec![];
+    for (action, key, combo) in bindings {
+        let Some(combo) = combo else {
+            continue;
+        };
+        let hotkey = match combo.parse::<HotKey>() {
+            Ok(hotkey) => hotkey,
+            Err(err) => {
+                error!("Invalid hotkey {} for {}: {}", combo, key, err);
+                continue;
+            }
+        };
+        if let Some((_, other)) = registered.iter().find(|(other, _)| *other == hotkey) {
+            error!("Hotkey {} for {} is already used for {}", combo, key, other);
+            continue;
+        }
+        if let Err(err) = manager.register(hotkey) {
+            error!("Failed to register hotkey {} for {}, another program may be using it: {}", combo, key, err);
+            continue;
+        }
+        info!("Registered hotkey {} for {}", combo, key);
+        registered.push((hotkey, key));
+        hotkeys.actions.push((hotkey.id(), action));
+    }
+
+    // Hotkeys arrive on the main thread's message loop, wake Bevy so an idle overlay reacts at once
+    let (tx, receiver) = mpsc::channel();
+    GlobalHotKeyEvent::set_event_handler(Some(move |event: GlobalHotKeyEvent| {
+        let _ = tx.send(event);
+        waker.wake();
+    }));
+    hotkeys._manager = Some(manager);
+    hotkeys.receiver = Some(receiver);
+    hotkeys
+}
+
+// Send the action for each hotkey pressed since the last frame
+pub(crate) fn handle_hotkeys(hotkeys: NonSend<Hotkeys>, mut actions: EventWriter<OverlayAction>) {
+    let Some(receiver) = &hotkeys.receiver else {
+        return;
+    };
+    while let Ok(event) = receiver.try_recv() {
+        if event.state != HotKeyState::Pressed {
+            continue;
+        }
+        if let Some((_, action)) = hotkeys.actions.iter().find(|(id, _)| *id == event.id) {
+            actions.send(*action);
+        }
+    }
+}
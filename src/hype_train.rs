@@ -0,0 +1,234 @@
+use std::time::Instant;
+
+use bevy::{
+    asset::AssetServer,
+    color::{Alpha, Color, Srgba},
+    math::{Vec2, Vec3},
+    prelude::{
+        default, Camera, Commands, Component, EventReader, Query, Res, ResMut, Transform,
+        Visibility, With, Without,
+    },
+    sprite::{Anchor, Sprite, SpriteBundle},
+    text::{Text, Text2dBundle, TextStyle},
+};
+use log::info;
+
+use crate::{
+    config::{Config, ScreenEdge},
+    eventsub::EventSubEvent,
+    locale::t,
+    HypeTrain, OverlayCamera,
+};
+
+/// Marker for the background of the hype train bar
+#[derive(Component)]
+pub(crate) struct HypeTrainBar {}
+
+/// Marker for the filled part of the hype train bar
+#[derive(Component)]
+pub(crate) struct HypeTrainFill {}
+
+/// Marker for the level label next to the hype train bar
+#[derive(Component)]
+pub(crate) struct HypeTrainLabel {}
+
+/// Spawn the hidden hype train bar, it is shown once a hype train begins
+pub(crate) fn setup_hype_train_bar(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<Config>,
+) {
+    if config.hype_train_bar.is_none() {
+        return;
+    }
+
+    // Sits behind the minawan
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::BLACK.with_alpha(0.3),
+                ..default()
+            },
+            transform: Transform::from_translation(Vec3::new(0.0, 0.0, -2.0)),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        HypeTrainBar {},
+    ));
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::Srgba(Srgba::hex("9146FF").unwrap()).with_alpha(0.8),
+                ..default()
+            },
+            transform: Transform::from_translation(Vec3::new(0.0, 0.0, -1.0)),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        HypeTrainFill {},
+    ));
+    commands.spawn((
+        Text2dBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load(&config.font_url),
+                    font_size: config.font_size,
+                    color: Color::WHITE,
+                },
+            ),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        HypeTrainLabel {},
+    ));
+}
+
+// Track hype train progress from EventSub and end it when it expires
+pub(crate) fn handle_hype_train_events(
+    mut events: EventReader<EventSubEvent>,
+    mut hype_train: ResMut<HypeTrain>,
+) {
+    for event in events.read() {
+        match event {
+            EventSubEvent::HypeTrainProgress {
+                level,
+                progress,
+                goal,
+                expires_in,
+            } => {
+                if !hype_train.active() {
+                    info!("Hype train started");
+                }
+                hype_train.level = *level;
+                hype_train.progress = if *goal > 0 {
+                    (*progress as f32 / *goal as f32).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                hype_train.expires_at = Some(Instant::now() + *expires_in);
+            }
+            EventSubEvent::HypeTrainEnd => {
+                info!("Hype train ended at level {}", hype_train.level);
+                *hype_train = HypeTrain::default();
+            }
+            _ => {}
+        }
+    }
+
+    // The end notification can be missed if the connection drops
+    if hype_train
+        .expires_at
+        .is_some_and(|expires_at| Instant::now() > expires_at)
+    {
+        info!("Hype train expired");
+        *hype_train = HypeTrain::default();
+    }
+}
+
+// Position and fill the hype train bar along the configured edge
+#[allow(clippy::type_complexity)]
+pub(crate) fn update_hype_train_bar(
+    hype_train: Res<HypeTrain>,
+    config: Res<Config>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    mut bar_query: Query<
+        (&mut Transform, &mut Sprite, &mut Visibility),
+        (With<HypeTrainBar>, Without<HypeTrainFill>),
+    >,
+    mut fill_query: Query<
+        (&mut Transform, &mut Sprite, &mut Visibility),
+        (With<HypeTrainFill>, Without<HypeTrainBar>),
+    >,
+    mut label_query: Query<
+        (&mut Transform, &mut Text, &mut Visibility),
+        (With<HypeTrainLabel>, Without<HypeTrainBar>, Without<HypeTrainFill>),
+    >,
+) {
+    let Some(edge) = config.hype_train_bar else {
+        return;
+    };
+    let (Ok(bar), Ok(fill), Ok(label)) = (
+        bar_query.get_single_mut(),
+        fill_query.get_single_mut(),
+        label_query.get_single_mut(),
+    ) else {
+        return;
+    };
+    let (mut bar_transform, mut bar_sprite, mut bar_visibility) = bar;
+    let (mut fill_transform, mut fill_sprite, mut fill_visibility) = fill;
+    let (mut label_transform, mut label_text, mut label_visibility) = label;
+
+    let visibility = if hype_train.active() {
+        Visibility::Visible
+    } else {
+        Visibility::Hidden
+    };
+    *bar_visibility = visibility;
+    *fill_visibility = visibility;
+    *label_visibility = visibility;
+    if !hype_train.active() {
+        return;
+    }
+
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+    let half = rect.max / 2.0;
+    let thickness = config.hype_train_bar_thickness;
+
+    // Bar center, bar size, fill anchor and fill size for each edge
+    let (center, size, anchor, fill_size) = match edge {
+        ScreenEdge::Bottom | ScreenEdge::Top => {
+            let y = if edge == ScreenEdge::Bottom {
+                -half.y + thickness / 2.0
+            } else {
+                half.y - thickness / 2.0
+            };
+            (
+                Vec2::new(0.0, y),
+                Vec2::new(rect.max.x, thickness),
+                Anchor::CenterLeft,
+                Vec2::new(rect.max.x * hype_train.progress, thickness),
+            )
+        }
+        ScreenEdge::Left | ScreenEdge::Right => {
+            let x = if edge == ScreenEdge::Left {
+                -half.x + thickness / 2.0
+            } else {
+                half.x - thickness / 2.0
+            };
+            (
+                Vec2::new(x, 0.0),
+                Vec2::new(thickness, rect.max.y),
+                Anchor::BottomCenter,
+                Vec2::new(thickness, rect.max.y * hype_train.progress),
+            )
+        }
+    };
+
+    bar_transform.translation = center.extend(bar_transform.translation.z);
+    bar_sprite.custom_size = Some(size);
+
+    fill_sprite.anchor = anchor;
+    fill_sprite.custom_size = Some(fill_size);
+    fill_transform.translation = match anchor {
+        Anchor::CenterLeft => Vec2::new(-half.x, center.y),
+        _ => Vec2::new(center.x, -half.y),
+    }
+    .extend(fill_transform.translation.z);
+
+    // Keep the label just inside the screen next to the bar
+    let label_offset = match edge {
+        ScreenEdge::Bottom => Vec2::new(0.0, thickness + config.font_size / 2.0),
+        ScreenEdge::Top => Vec2::new(0.0, -(thickness + config.font_size / 2.0)),
+        ScreenEdge::Left => Vec2::new(thickness + config.font_size * 4.0, 0.0),
+        ScreenEdge::Right => Vec2::new(-(thickness + config.font_size * 4.0), 0.0),
+    };
+    label_transform.translation = (center + label_offset).extend(0.0);
+    label_text.sections[0].value = t!(
+        "hype_train.label",
+        level = hype_train.level,
+        percent = format!("{:.0}", hype_train.progress * 100.0),
+    );
+}
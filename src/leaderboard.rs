@@ -0,0 +1,277 @@
+use std::{
+    collections::HashMap,
+    f32::consts::TAU,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    asset::AssetServer,
+    color::{Alpha, Color},
+    hierarchy::{BuildChildren, DespawnRecursiveExt, Parent},
+    math::{Quat, Vec2, Vec3},
+    prelude::{
+        default, Commands, Component, Entity, Local, Query, Res, ResMut, Resource, SpatialBundle, Transform,
+        With,
+    },
+    sprite::{Sprite, SpriteBundle},
+    text::{Text, TextStyle},
+    time::Time,
+    ui::{node_bundles::TextBundle, Style},
+};
+use chrono::NaiveDate;
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    giveaway::spawn_crown,
+    locale::t,
+    opacity::{fade, BaseAlpha},
+    sizes::{avatar_size, head_y, HeadOffset, SizeClass},
+    widgets::corner_style,
+    AppState, UserMarker,
+};
+
+const SPARKLES: usize = 8;
+const SPARKLE_TIME: Duration = Duration::from_millis(800);
+const SPARKLE_SPEED: f32 = 60.0;
+const SPARKLE_SIZE: f32 = 5.0;
+const SPARKLE_COLOR: Color = Color::srgb(1.0, 0.95, 0.6);
+
+/// Messages each user sent, since startup or since midnight when DAILY is on
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub(crate) struct MessageCounts {
+    /// The day these are for, None when they're for the whole session
+    day: Option<NaiveDate>,
+    counts: HashMap<String, u32>,
+}
+
+impl MessageCounts {
+    /// Counts saved by the last run, if they're for today
+    pub(crate) fn restored(saved: Option<MessageCounts>, today: Option<NaiveDate>) -> Self {
+        saved
+            .filter(|saved| today.is_some() && saved.day == today)
+            .unwrap_or_default()
+    }
+
+    /// Start counting afresh when the day changes, or when DAILY is turned on or off
+    fn start_day(&mut self, today: Option<NaiveDate>) {
+        if self.day != today {
+            self.day = today;
+            self.counts.clear();
+        }
+    }
+
+    pub(crate) fn add(&mut self, user: &str, today: Option<NaiveDate>) {
+        self.start_day(today);
+        *self.counts.entry(user.to_string()).or_default() += 1;
+    }
+
+    /// Forget a Twitch user's messages, whatever the case of their name
+    pub(crate) fn forget(&mut self, login: &str) {
+        self.counts.retain(|user, _| !user.eq_ignore_ascii_case(login));
+    }
+
+    /// Counts worth keeping across a restart, only the day's ones are
+    pub(crate) fn daily(&self) -> Option<MessageCounts> {
+        self.day.is_some().then(|| self.clone())
+    }
+
+    /// The most active users with their counts, most messages first
+    pub(crate) fn top(&self, size: usize) -> Vec<(&str, u32)> {
+        let mut top: Vec<(&str, u32)> = self.counts.iter().map(|(user, count)| (user.as_str(), *count)).collect();
+        // Ties go by name so the order doesn't shuffle between updates
+        top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        top.truncate(size);
+        top
+    }
+}
+
+/// Who wears the leader's crown
+#[derive(Resource, Default)]
+pub(crate) struct Leaderboard {
+    leader: Option<String>,
+}
+
+/// Marker for the leaderboard widget
+#[derive(Component)]
+pub(crate) struct LeaderboardWidget {}
+
+/// The top chatter's crown
+#[derive(Component)]
+pub(crate) struct LeaderCrown {}
+
+/// A spark flying off the new leader
+#[derive(Component)]
+pub(crate) struct LeaderSparkle {
+    started: Instant,
+    velocity: Vec2,
+}
+
+/// Name to show for a user key, without the platform prefix
+fn display_name(key: &str) -> &str {
+    key.split_once(':').map_or(key, |(_, name)| name)
+}
+
+/// Name to show for a user key, the name they chat under while they're at the party. YouTube
+/// keys hold a channel id rather than a name.
+pub(crate) fn shown_name<'a>(app_state: &'a AppState, key: &'a str) -> &'a str {
+    app_state.active_users.get(key).map_or_else(|| display_name(key), |user| user.name.as_str())
+}
+
+/// The day counts are kept for, None to count since startup
+pub(crate) fn counting_day(config: &Config) -> Option<NaiveDate> {
+    config.leaderboard_daily.then(|| config.today())
+}
+
+// Refresh the leaderboard widget every UPDATE_SECS and hand the crown to the top chatter at
+// the party. The widget follows ENABLED, CORNER and the font as the config changes.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn update_leaderboard(
+    mut commands: Commands,
+    mut app_state: ResMut<AppState>,
+    mut leaderboard: ResMut<Leaderboard>,
+    config: Res<Config>,
+    asset_server: Res<AssetServer>,
+    mut widget_query: Query<(Entity, &mut Text, &mut Style), With<LeaderboardWidget>>,
+    crown_query: Query<(Entity, &Parent), With<LeaderCrown>>,
+    avatar_query: Query<(&Transform, &Sprite, Option<&SizeClass>), With<UserMarker>>,
+    mut last_update: Local<Option<Instant>>,
+) {
+    let due = last_update.map_or(true, |time| time.elapsed() >= config.leaderboard_update_interval);
+    if !due && !config.is_changed() {
+        return;
+    }
+    *last_update = Some(Instant::now());
+
+    if !config.leaderboard_enabled {
+        for (entity, _, _) in widget_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        for (entity, _) in crown_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        leaderboard.leader = None;
+        return;
+    }
+
+    app_state.message_counts.start_day(counting_day(&config));
+    let style = TextStyle {
+        font: asset_server.load(&config.leaderboard_font_url),
+        font_size: config.leaderboard_font_size,
+        color: Color::WHITE,
+    };
+    let top = app_state.message_counts.top(config.leaderboard_size);
+    let mut lines = vec![t!("leaderboard.title")];
+    lines.extend(
+        top.iter()
+            .enumerate()
+            .map(|(place, (user, count))| format!("{}. {}  {}", place + 1, shown_name(&app_state, user), count)),
+    );
+    let text = lines.join("\n");
+    match widget_query.get_single_mut() {
+        Ok((_, mut widget_text, mut widget_style)) => {
+            if config.is_changed() {
+                *widget_style = corner_style(config.leaderboard_corner);
+                widget_text.sections[0].style = style;
+            }
+            if widget_text.sections[0].value != text {
+                widget_text.sections[0].value = text;
+            }
+        }
+        Err(_) => {
+            commands.spawn((
+                TextBundle::from_section(text, style)
+                    .with_style(corner_style(config.leaderboard_corner))
+                    .with_background_color(Color::BLACK.with_alpha(0.4)),
+                LeaderboardWidget {},
+            ));
+        }
+    }
+
+    // The crown goes to the top chatter who's at the party, and stays put on a tie
+    let leader = config
+        .leaderboard_crown
+        .then(|| {
+            let counts = &app_state.message_counts.counts;
+            let present = |user: &&String| app_state.active_users.contains_key(user.as_str());
+            let best = counts.iter().filter(|(user, _)| present(user)).map(|(_, count)| *count).max()?;
+            let holder = leaderboard.leader.as_ref().filter(|user| present(user) && counts.get(*user) == Some(&best));
+            holder
+                .or_else(|| counts.iter().find(|(user, count)| present(user) && **count == best).map(|(user, _)| user))
+                .cloned()
+        })
+        .flatten();
+    let leader_entity = leader
+        .as_ref()
+        .and_then(|user| app_state.active_users.get(user))
+        .map(|user| user.entity);
+    let crowned = crown_query.iter().any(|(_, parent)| Some(parent.get()) == leader_entity);
+    if leader == leaderboard.leader && crowned {
+        return;
+    }
+    for (entity, _) in crown_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let Some((entity, (transform, sprite, size_class))) =
+        leader_entity.and_then(|entity| avatar_query.get(entity).ok().map(|avatar| (entity, avatar)))
+    else {
+        leaderboard.leader = None;
+        return;
+    };
+    let size = avatar_size(sprite, size_class);
+    let head = head_y(sprite, size);
+    commands.entity(entity).with_children(|parent| {
+        parent
+            .spawn((
+                SpatialBundle::from_transform(Transform::from_xyz(0.0, head + 4.0, 2.0)),
+                LeaderCrown {},
+                HeadOffset::of(sprite),
+            ))
+            .with_children(|crown| spawn_crown(crown, size.x * 0.35));
+    });
+    // A handover gets a burst of sparkles, picking up the crown after a restart doesn't
+    if leaderboard.leader.is_some() && leader != leaderboard.leader {
+        if let Some(user) = &leader {
+            info!("{} is the new top chatter", shown_name(&app_state, user));
+        }
+        let head = transform.translation + Vec3::new(0.0, head, 3.0);
+        for index in 0..SPARKLES {
+            let angle = TAU * index as f32 / SPARKLES as f32;
+            commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: SPARKLE_COLOR,
+                        custom_size: Some(Vec2::splat(SPARKLE_SIZE)),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(head).with_rotation(Quat::from_rotation_z(angle)),
+                    ..default()
+                },
+                LeaderSparkle {
+                    started: Instant::now(),
+                    velocity: Vec2::from_angle(angle) * SPARKLE_SPEED,
+                },
+            ));
+        }
+    }
+    leaderboard.leader = leader;
+}
+
+// Fly the handover sparkles outwards while they fade
+pub(crate) fn animate_leader_sparkles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut sparkle_query: Query<(Entity, &mut Transform, Option<&mut BaseAlpha>, &LeaderSparkle)>,
+) {
+    for (entity, mut transform, alpha, sparkle) in sparkle_query.iter_mut() {
+        let progress = sparkle.started.elapsed().as_secs_f32() / SPARKLE_TIME.as_secs_f32();
+        if progress >= 1.0 {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        transform.translation += (sparkle.velocity * time.delta_seconds()).extend(0.0);
+        fade(alpha, 1.0 - progress);
+    }
+}
@@ -0,0 +1,1266 @@
+use bevy::{
+    app::ScheduleRunnerPlugin,
+    prelude::*,
+    render::{
+        camera::CameraPlugin, settings::{RenderCreation, WgpuSettings}, RenderPlugin
+    },
+    time::common_conditions::on_real_timer,
+    utils::HashMap,
+    window::{
+        CompositeAlphaMode, PrimaryWindow, WindowBackendScaleFactorChanged, WindowFocused, WindowResized,
+        WindowScaleFactorChanged,
+    },
+};
+use bevy_egui::EguiPlugin;
+use emotes::{
+    atlas::{pack_emote_atlas, EmoteAtlas},
+    emote_types::Emote,
+    get_seventv_emotes, receive_channel_emotes,
+};
+use log::{debug, error, info, warn};
+use std::{
+    collections::BinaryHeap,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::{broadcast, mpsc, watch, Notify};
+use vleue_kinetoscope::AnimatedImagePlugin;
+
+mod types;
+use types::*;
+pub use types::{AppState, ChatMessage, ChatSender, EmoteStorage};
+
+mod users;
+use users::{
+    animate_waves, clamp_to_ranges, despawn_users, make_room, move_users, snap_users_to_floor, spawn_pinned_users,
+    spawn_user, trigger_nearby_waves, USER_DESPAWN_CHECK_INTERVAL,
+};
+
+mod messages;
+use messages::{
+    despawn_messages, display_message, point_bubble_tails, MessageExpiry, MESSAGE_DESPAWN_CHECK_INTERVAL,
+};
+
+mod emotes;
+
+mod chat;
+use chat::{dedupe::RecentMessages, shutdown_chat_sources, sources_from_config, spawn_sources};
+
+mod config;
+pub use config::Config;
+use config::{BackgroundMode, OutputBackend, load_config, prepare_config_file};
+
+mod logging;
+use logging::{apply_log_level, configure_logging, init_logging};
+
+mod locale;
+use locale::{apply_locale, set_locale};
+
+mod crash;
+use crash::{install_panic_hook, set_crash_config_path};
+
+mod cli;
+pub use cli::CliArgs;
+
+mod reload;
+use reload::{reload_config, ConfigWatcher};
+
+mod gpu;
+use gpu::{choose_backends, log_render_adapter, GpuWatchdogPlugin};
+
+mod framerate;
+use framerate::{
+    forward_with_wake, limit_frame_rate, pause_animations, setup_frame_waker, track_activity,
+    track_occlusion, track_quiet_chat, update_frame_rate, ActivityTracker, ChatQuiet, FrameWaker,
+    WindowOcclusion,
+};
+
+mod monitor;
+use monitor::place_window;
+
+mod state;
+use state::{load_state, save_state_on_exit};
+
+mod paths;
+use paths::{default_config_path, migrate_working_dir_config, portable, set_portable};
+
+mod helix;
+use helix::HelixClient;
+
+mod stream;
+use stream::{handle_stream_status, poll_stream_status};
+
+mod eventsub;
+use eventsub::{forward_eventsub_events, start_eventsub, subscriptions_from_config, EventSubEvent};
+
+mod widgets;
+use widgets::{setup_viewer_widget, update_viewer_widget};
+
+mod settings;
+use settings::{settings_ui, toggle_settings_window, ToggleSettingsWindow};
+
+mod toasts;
+use toasts::{setup_toasts, show_toasts};
+
+mod opacity;
+use opacity::apply_opacity;
+
+mod capture;
+use capture::apply_capture_visibility;
+
+mod output;
+use output::FrameOutputPlugin;
+
+mod mirror;
+use mirror::{setup_mirror_window, update_mirror_window};
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "linux")]
+use linux::apply_linux_window;
+#[cfg(not(target_os = "linux"))]
+fn apply_linux_window() {}
+
+mod actions;
+use actions::{handle_overlay_actions, OverlayAction};
+
+mod tray;
+use tray::{handle_tray_events, setup_tray, tray_channel};
+
+mod hotkeys;
+use hotkeys::{handle_hotkeys, register_hotkeys, Hotkeys};
+
+mod status;
+use status::{handle_source_status, setup_status_chips, update_connection_chip, ChatConnection};
+
+mod commands;
+use commands::{handle_admin_commands, AdminCommand, ChatCommands};
+
+mod follows;
+use follows::{handle_follow_events, FollowTracker};
+
+mod hype_train;
+use hype_train::{handle_hype_train_events, setup_hype_train_bar, update_hype_train_bar};
+
+mod downloads;
+use downloads::{prioritize_visible_downloads, DownloadGovernor};
+pub use downloads::DownloadPlugin;
+
+mod pool;
+use pool::EntityPools;
+
+mod handles;
+use handles::{prune_handle_cache, HandleCache, HANDLE_CACHE_PRUNE_INTERVAL};
+
+mod giant;
+use giant::{start_giants, update_giants, MakeGiant};
+
+mod boop;
+use boop::{animate_boops, start_boops, walk_to_boop, BoopCooldowns};
+
+mod petting;
+use petting::{animate_hearts, pet_minawan, setup_interaction_border};
+
+mod weather;
+use weather::{handle_weather_events, run_weather, Weather, WeatherEvent};
+
+mod timer;
+use timer::{handle_timer_commands, update_timer, CountdownTimer, TimerCommand};
+
+mod streaks;
+use streaks::{show_streak_badges, ChatStreaks};
+
+mod pronouns;
+use pronouns::{pronouns_enabled, show_pronouns, start_pronoun_lookups};
+
+mod badges;
+use badges::{show_twitch_badges, start_badge_lookups};
+
+mod pyramids;
+use pyramids::{celebrate_pyramids, run_emote_rain, Pyramids};
+
+mod screenshot;
+use screenshot::{ScreenshotPlugin, TakeScreenshot};
+
+mod ambient;
+use ambient::{apply_ambient_tint, AmbientTint};
+
+mod lurkers;
+use lurkers::{lurkers_enabled, poll_chatters, update_lurker_ghosts, Lurkers, Presence, PresenceReceiver};
+
+mod cheers;
+use cheers::{animate_bits_crown, counting_month, crown_top_cheerer, TopCheerer};
+
+mod schedule;
+use schedule::{run_schedule, Scheduler, SCHEDULE_CHECK_INTERVAL};
+
+mod race;
+use race::{join_races, run_race, show_race_banner, start_races, Race, StartRace};
+
+mod signs;
+use signs::{handle_sign_redemptions, ClearSign};
+
+mod polls;
+use polls::{handle_poll_events, update_poll_zones, walk_to_poll_zones, Poll};
+
+mod progress;
+use progress::{cluster_at_progress, handle_progress_commands, update_progress_bar, ProgressCommand, WatchProgress};
+
+mod leaderboard;
+use leaderboard::{animate_leader_sparkles, counting_day, update_leaderboard, Leaderboard};
+
+mod birthdays;
+use birthdays::{celebrate_birthdays, Birthdays};
+
+mod gifts;
+use gifts::{drop_gift_boxes, receive_gifts, GiftBombs, GiftNotice, GiftReceiver};
+
+mod floor_glow;
+use floor_glow::{update_floor_glow, ChatHeat};
+
+mod campfire;
+use campfire::{gather_at_campfire, place_campfire};
+
+mod marquee;
+use marquee::{handle_marquee_commands, update_marquee, MarqueeCommand};
+
+mod footprints;
+use footprints::{fade_footprints, leave_footprints};
+
+mod greetings;
+use greetings::{animate_greetings, greet_returning_chatters};
+
+mod spawn_queue;
+use spawn_queue::{spawn_queued_users, SpawnQueue};
+
+mod reactions;
+use reactions::{animate_droops, react_to_messages};
+
+mod theme;
+use theme::{export_theme, import_theme};
+
+mod moderation;
+use moderation::{animate_dust_offs, handle_moderation, return_to_spot, ModerationNotice, ModerationReceiver};
+
+mod accessibility;
+use accessibility::{animate_gentle_fades, apply_high_contrast, calm_motion, update_effect_policy, EffectPolicy};
+
+mod accessories;
+use accessories::{fit_accessories, handle_accessory_redemptions, put_on_accessories, StripAccessories};
+
+mod giveaway;
+use giveaway::{expire_giveaway_winner, run_giveaway_roulette, start_giveaways, Giveaway, PickWinner};
+
+mod debug;
+use debug::{debug_overlay_visible, setup_debug_overlay, show_debug_overlay, update_debug_overlay, DebugOverlay};
+
+mod api;
+use api::{
+    broadcast_api_events, handle_api_commands, ApiCommand, ApiCommandReceiver, ApiEvent, ApiEvents, ApiServer,
+    API_EVENT_BUFFER,
+};
+
+mod stats_page;
+use stats_page::{collect_party_stats, publish_party_stats, PartyStats, StatsPublisher, StatsServer};
+
+mod scripts;
+use scripts::{animate_dances, apply_script_effects, reload_scripts, run_script_hooks, scripts_enabled, Scripts};
+
+mod snapshot;
+use snapshot::{load_snapshot, restore_party, restore_pending, save_snapshot};
+
+mod startup;
+use startup::{
+    setup_loading_panel, update_startup_progress, StartupProgress, StartupReporter, StartupStep, StartupUpdate,
+    StepResult,
+};
+
+mod repeats;
+use repeats::{show_repeat_badges, Duplicates};
+
+mod rain;
+use rain::{handle_rain_commands, run_rain, Rain, RainCommand};
+
+mod bubble_themes;
+use bubble_themes::{animate_bubbles, type_bubble_text};
+
+mod sizes;
+use sizes::{
+    apply_saved_sizes, feet_anchor, follow_heads, handle_size_changes, resize_avatars, ChangeSize, SizeClass,
+    AVATAR_HEIGHT,
+};
+
+mod replies;
+use replies::{reply_to_party_events, thank_raids, PartyCommandCooldown};
+
+mod frame_budget;
+use frame_budget::{shed_particles, track_frame_budget, FrameBudget};
+
+mod stage_props;
+use stage_props::{keep_bubbles_clear_of_props, outline_stage_props, walk_behind_props};
+
+#[doc(hidden)]
+pub mod testing;
+
+/// Set up logging and crash reports and load the config the command line asks for. Writes a
+/// default config on first run, and migrates an old config.ini and a config left in the working
+/// directory by older versions.
+pub fn load_startup_config(args: &mut CliArgs) -> Config {
+    set_portable(args.portable);
+    init_logging(args.log_level.as_deref());
+    install_panic_hook();
+    if portable() {
+        info!("Portable mode, keeping everything next to the executable");
+    }
+
+    if args.config_path == default_config_path(args.portable) {
+        migrate_working_dir_config(&args.config_path);
+    }
+    args.config_path = prepare_config_file(&args.config_path).unwrap_or_else(|err| panic!("{}", err));
+    set_crash_config_path(&args.config_path);
+
+    let mut config = load_config(&args.config_path, args.profile.as_deref())
+        .unwrap_or_else(|err| panic!("{}", err));
+    args.apply(&mut config);
+    configure_logging(&config);
+    if let Some(profile) = &config.profile {
+        info!("Using profile {}", profile);
+    }
+    config
+}
+
+/// Run `--export-theme` or `--import-theme` if given, returning the exit code. None when
+/// neither was, and the overlay should start.
+pub fn run_theme_command(args: &CliArgs, config: &Config) -> Option<i32> {
+    let result = if let Some(theme_path) = &args.export_theme {
+        export_theme(&args.config_path, config.profile.as_deref(), Path::new(theme_path))
+    } else if let Some(theme_path) = &args.import_theme {
+        import_theme(&args.config_path, Path::new(theme_path)).map(|changes| {
+            for change in &changes {
+                info!("{}", change);
+            }
+            info!("Imported {} with {} changed settings", theme_path, changes.len());
+        })
+    } else {
+        return None;
+    };
+    Some(match result {
+        Ok(()) => 0,
+        Err(err) => {
+            error!("{}", err);
+            1
+        }
+    })
+}
+
+/// The watch party: chat sources, resources, systems and the plugins it draws with. The window
+/// and renderer are left to the app, `primary_window` and `render_plugin` give the overlay's own.
+/// Has to be added from inside the tokio runtime, after DefaultPlugins or `headless_plugins`.
+pub struct MinawanPlugin {
+    config: Config,
+    /// Reapplied on every config reload
+    args: CliArgs,
+    download_governor: Arc<DownloadGovernor>,
+}
+
+impl MinawanPlugin {
+    pub fn with_config(config: Config) -> Self {
+        Self {
+            download_governor: Arc::new(DownloadGovernor::from_config(&config)),
+            config,
+            args: CliArgs::default(),
+        }
+    }
+
+    /// Command line options, which keep precedence over the config file when it's reloaded
+    pub fn with_cli_args(mut self, args: CliArgs) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Loads web assets within the DOWNLOADS limits, has to be added before DefaultPlugins or
+    /// `headless_plugins`
+    pub fn downloads(&self) -> DownloadPlugin {
+        DownloadPlugin {
+            governor: self.download_governor.clone(),
+        }
+    }
+
+    /// Whether `--headless` asked for no window or GPU. The app then adds `headless_plugins`
+    /// instead of DefaultPlugins, and the plugin skips the tray, hotkeys, settings window and frame
+    /// output.
+    pub fn headless(&self) -> bool {
+        self.args.headless
+    }
+
+    /// Chroma mode is captured rather than overlaid, so it's a normal window
+    fn chroma(&self) -> bool {
+        self.config.background_mode == BackgroundMode::Chroma
+    }
+
+    /// The overlay window, transparent and on top of everything unless BACKGROUND_MODE is chroma
+    pub fn primary_window(&self) -> Window {
+        let chroma = self.chroma();
+        Window {
+            title: "Transparent Window".to_string(),
+            transparent: !chroma,
+            decorations: false,
+            present_mode: self.config.present_mode,
+            visible: self.config.output == OutputBackend::None || !self.config.output_hide_window,
+            // Mesa's Vulkan driver only blends a transparent window with premultiplied alpha
+            #[cfg(target_os = "linux")]
+            composite_alpha_mode: if chroma {
+                CompositeAlphaMode::Auto
+            } else {
+                CompositeAlphaMode::PreMultiplied
+            },
+            window_level: if chroma {
+                bevy::window::WindowLevel::Normal
+            } else {
+                bevy::window::WindowLevel::AlwaysOnTop
+            },
+            ..default()
+        }
+    }
+
+    /// Renders with the configured BACKEND
+    pub fn render_plugin(&self) -> RenderPlugin {
+        RenderPlugin {
+            render_creation: RenderCreation::Automatic(WgpuSettings {
+                backends: Some(choose_backends(self.config.backend)),
+                ..Default::default()
+            }),
+            synchronous_pipeline_compilation: false,
+        }
+    }
+
+    /// What to add instead of DefaultPlugins when headless, running a frame every `frame_time`
+    pub fn headless_plugins(&self, frame_time: Duration) -> HeadlessPlugins {
+        HeadlessPlugins {
+            window: self.primary_window(),
+            frame_time,
+        }
+    }
+}
+
+/// The little of Bevy the party needs without a window or GPU: MinimalPlugins, the primary
+/// window and camera without winit or a renderer so the overlay still has a size, and the asset
+/// types it loads registered without loaders. Avatars and emotes stay waiting for their images.
+pub struct HeadlessPlugins {
+    window: Window,
+    frame_time: Duration,
+}
+
+impl Plugin for HeadlessPlugins {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(self.frame_time)))
+            .add_plugins((
+                TransformPlugin,
+                HierarchyPlugin,
+                WindowPlugin {
+                    primary_window: Some(self.window.clone()),
+                    ..default()
+                },
+                AssetPlugin::default(),
+                CameraPlugin,
+            ))
+            .init_asset::<Image>()
+            .init_asset::<Font>()
+            .init_asset::<TextureAtlasLayout>();
+    }
+}
+
+impl Plugin for MinawanPlugin {
+    fn build(&self, app: &mut App) {
+        let config = self.config.clone();
+        let open_settings = self.args.settings;
+        let chroma = self.chroma();
+        let setup_with_click_through = move |commands: Commands, windows: Query<&mut Window, With<PrimaryWindow>>| {
+            setup(commands, windows, !chroma)
+        };
+
+        // Create a channel to communicate between the chat sources and Bevy
+        let (tx, rx) = mpsc::channel::<ChatMessage>(100);
+        let (status_tx, status_rx) = mpsc::channel::<SourceStatus>(10);
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let reconnect = Arc::new(Notify::new());
+
+        // Anything arriving from outside Bevy wakes the overlay if it's idling
+        let waker = FrameWaker::default();
+        let rx = forward_with_wake(rx, waker.clone());
+        let status_rx = forward_with_wake(status_rx, waker.clone());
+
+        // The tasks below say how they got on, for the loading panel. Each one started adds its step.
+        let (startup_tx, startup_rx) = mpsc::channel::<StartupUpdate>(20);
+        let startup_rx = forward_with_wake(startup_rx, waker.clone());
+        let startup = StartupReporter::new(startup_tx);
+        let mut startup_steps = vec![];
+
+        // Let stream tools follow the party and control it over a local WebSocket
+        let (api_event_tx, _) = broadcast::channel::<String>(API_EVENT_BUFFER);
+        let (api_command_tx, api_command_rx) = mpsc::channel::<ApiCommand>(100);
+        let api_command_rx = forward_with_wake(api_command_rx, waker.clone());
+        if config.api_enabled {
+            let server = ApiServer::from_config(&config, api_event_tx.clone(), api_command_tx, tx.clone());
+            tokio::spawn(server.run());
+        }
+
+        // Serve the stats page from snapshots Bevy publishes, so a browser never waits on a frame
+        let stats_publisher = config.stats_page_enabled.then(|| {
+            let (sender, receiver) = watch::channel(Arc::default());
+            tokio::spawn(StatsServer::from_config(&config, receiver).run());
+            StatsPublisher { sender }
+        });
+
+        let chat_sender = ChatSender { sender: tx.clone() };
+
+        // Who's in chat without saying anything, for the lurker ghosts
+        let (presence_tx, presence_rx) = mpsc::channel::<Presence>(1000);
+        let presence_rx = forward_with_wake(presence_rx, waker.clone());
+        let presence_tx = config.lurkers_enabled.then_some(presence_tx);
+
+        // Gifted sub bombs from Twitch, dropped on the party as boxes
+        let (gift_tx, gift_rx) = mpsc::channel::<GiftNotice>(100);
+        let gift_rx = forward_with_wake(gift_rx, waker.clone());
+
+        // Timeouts and bans from Twitch, which take minawan off the party
+        let (moderation_tx, moderation_rx) = mpsc::channel::<ModerationNotice>(100);
+        let moderation_rx = forward_with_wake(moderation_rx, waker.clone());
+
+        // Replies the overlay says in Twitch chat
+        let (outbound_tx, outbound_rx) = mpsc::channel::<String>(20);
+
+        // Start each chat source in a separate async task
+        if config.chat_sources.iter().any(|name| name == "twitch") && !config.channel_name.is_empty() {
+            startup_steps.push(StartupStep::TwitchChat);
+        }
+        spawn_sources(
+            sources_from_config(
+                &config,
+                presence_tx.clone(),
+                gift_tx,
+                moderation_tx,
+                Some(startup.clone()),
+                reconnect.clone(),
+                outbound_rx,
+            ),
+            tx,
+            status_tx,
+            shutdown_rx,
+        );
+
+        let helix = HelixClient::from_config(&config);
+        if let Some(presence_tx) = presence_tx {
+            match helix.clone() {
+                Some(helix) => {
+                    let channel_id = config.channel_id.clone();
+                    tokio::spawn(poll_chatters(helix, channel_id, presence_tx));
+                }
+                None => warn!("Lurkers only come from JOIN and PART without CLIENT_ID and OAUTH_TOKEN"),
+            }
+        }
+
+        // Poll the stream state so the overlay can idle while offline and show the viewer count
+        let (stream_tx, stream_rx) = mpsc::channel::<StreamStatus>(10);
+        let stream_rx = forward_with_wake(stream_rx, waker.clone());
+        if config.idle_when_offline || config.viewer_widget_enabled {
+            match helix.clone() {
+                Some(helix) => {
+                    let channel_id = config.channel_id.clone();
+                    let interval = config.stream_poll_interval;
+                    let startup = startup.clone();
+                    startup_steps.push(StartupStep::StreamStatus);
+                    tokio::spawn(async move {
+                        poll_stream_status(helix, channel_id, stream_tx, interval, startup).await;
+                    });
+                }
+                None => warn!("IDLE_WHEN_OFFLINE and the viewer count need CLIENT_ID and OAUTH_TOKEN, ignoring"),
+            }
+        }
+
+        // Subscribe to the EventSub topics the enabled features need
+        let (eventsub_tx, eventsub_rx) = mpsc::channel::<EventSubEvent>(100);
+        let eventsub_rx = forward_with_wake(eventsub_rx, waker.clone());
+        if let Some(helix) = helix.clone() {
+            let config = config.clone();
+            let startup = startup.clone();
+            startup_steps.push(StartupStep::EventSub);
+            tokio::spawn(async move {
+                let subscriptions = subscriptions_from_config(&config, &helix).await;
+                if subscriptions.is_empty() {
+                    startup.done(StartupStep::EventSub, StepResult::NotNeeded);
+                } else {
+                    start_eventsub(helix, subscriptions, eventsub_tx, startup).await;
+                }
+            });
+        }
+
+        // Fetch the channel's 7TV emotes without holding up the window
+        let (emote_tx, emote_rx) = mpsc::channel::<HashMap<String, Emote>>(1);
+        let emote_rx = forward_with_wake(emote_rx, waker.clone());
+        // There's no channel to get emotes for until setup is done
+        if !config.channel_id.is_empty() {
+            let channel_id = config.channel_id.clone();
+            startup_steps.push(StartupStep::ChannelEmotes);
+            tokio::spawn(async move {
+                match get_seventv_emotes(channel_id).await {
+                    Ok(emotes) => {
+                        startup.done(StartupStep::ChannelEmotes, StepResult::EmotesLoaded(emotes.len()));
+                        let _ = emote_tx.send(emotes).await;
+                    }
+                    Err(err) => {
+                        warn!("Failed to get the 7TV channel emotes: {}", err);
+                        startup.failed(StartupStep::ChannelEmotes, err.to_string());
+                    }
+                }
+            });
+        }
+
+        let clear_color = if chroma { config.key_color } else { Color::NONE };
+        let runtime_state = load_state();
+        let scripts = Scripts::from_config(&config);
+        let restored = load_snapshot(&config);
+        let pronouns = start_pronoun_lookups(&config, waker.clone());
+        let twitch_badges = start_badge_lookups(&config, helix, waker.clone());
+        // Startup systems already spawn text
+        set_locale(config.locale);
+        let headless = self.headless();
+        let hotkeys = if headless {
+            Hotkeys::default()
+        } else {
+            register_hotkeys(&config, waker.clone())
+        };
+        let (tray_setup, tray_receiver) = tray_channel(waker.clone());
+
+        app.insert_resource(config)
+            .insert_resource(ClearColor(clear_color))
+            .insert_resource(ChatReceiver { receiver: rx })
+            .insert_resource(SourceStatusReceiver { receiver: status_rx })
+            .insert_resource(ChatShutdown { sender: shutdown_tx })
+            .insert_resource(ChatReconnect { notify: reconnect })
+            .insert_resource(ChatOutbox { sender: outbound_tx })
+            .insert_resource(StreamStatusReceiver { receiver: stream_rx })
+            .insert_resource(PresenceReceiver { receiver: presence_rx })
+            .insert_resource(GiftReceiver { receiver: gift_rx })
+            .insert_resource(ModerationReceiver { receiver: moderation_rx })
+            .insert_resource(EventSubReceiver { receiver: eventsub_rx })
+            .insert_resource(ChannelEmoteReceiver { receiver: emote_rx })
+            .insert_resource(StartupProgress::new(startup_steps, startup_rx))
+            .insert_resource(ApiEvents { sender: api_event_tx })
+            .insert_resource(ApiCommandReceiver { receiver: api_command_rx })
+            .insert_resource(chat_sender)
+            .insert_resource(scripts)
+            .insert_resource(restored.party)
+            .insert_resource(restored.streaks)
+            .insert_resource(restored.accessories)
+            .insert_resource(restored.sizes)
+            .insert_resource(restored.marquee)
+            .insert_resource(restored.greetings)
+            .insert_resource(pronouns)
+            .insert_resource(twitch_badges)
+            .insert_resource(ConfigWatcher::new(self.args.clone()))
+            .insert_resource(waker)
+            .insert_non_send_resource(hotkeys)
+            .insert_resource(tray_setup)
+            .insert_resource(tray_receiver)
+            .init_resource::<ActivityTracker>()
+            .init_resource::<WindowOcclusion>()
+            .init_resource::<ChatQuiet>()
+            .init_resource::<HypeTrain>()
+            .init_resource::<RecentMessages>()
+            .init_resource::<FollowTracker>()
+            .init_resource::<EmoteAtlas>()
+            .init_resource::<EntityPools>()
+            .init_resource::<HandleCache>()
+            .init_resource::<MessageExpiry>()
+            .init_resource::<DebugOverlay>()
+            .init_resource::<FrameBudget>()
+            .init_resource::<Giveaway>()
+            .init_resource::<BoopCooldowns>()
+            .init_resource::<PartyCommandCooldown>()
+            .init_resource::<Weather>()
+            .init_resource::<ChatConnection>()
+            .init_resource::<CountdownTimer>()
+            .init_resource::<Leaderboard>()
+            .init_resource::<Pyramids>()
+            .init_resource::<Rain>()
+            .init_resource::<WatchProgress>()
+            .init_resource::<Poll>()
+            .init_resource::<AmbientTint>()
+            .init_resource::<TopCheerer>()
+            .init_resource::<Lurkers>()
+            .init_resource::<Scheduler>()
+            .init_resource::<Race>()
+            .init_resource::<PartyStats>()
+            .init_resource::<Birthdays>()
+            .init_resource::<GiftBombs>()
+            .init_resource::<ChatHeat>()
+            .init_resource::<EffectPolicy>()
+            .insert_resource(EmoteStorage {
+                all: HashMap::new(),
+                loaded: HashMap::new(),
+                atlas: HashMap::new(),
+            })
+            .insert_resource(AppState {
+                active_users: HashMap::new(),
+                despawn_queue: BinaryHeap::new(),
+                spawn_queue: SpawnQueue::default(),
+                program_state: ProgramState::Loading,
+                stream_live: true,
+                viewer_count: None,
+                spawning_paused: runtime_state.spawning_paused,
+                quiet_hours: false,
+                zen_mode: runtime_state.zen_mode,
+                message_counts: restored.message_counts,
+                bit_totals: restored.bit_totals,
+                timeouts: restored.timeouts,
+            })
+            .insert_resource(runtime_state)
+            .add_plugins(AnimatedImagePlugin)
+            .add_event::<EventSubEvent>()
+            .add_event::<AdminCommand>()
+            .add_event::<ToggleSettingsWindow>()
+            .add_event::<OverlayAction>()
+            .add_event::<MakeGiant>()
+            .add_event::<PickWinner>()
+            .add_event::<WeatherEvent>()
+            .add_event::<TimerCommand>()
+            .add_event::<StripAccessories>()
+            .add_event::<ProgressCommand>()
+            .add_event::<TakeScreenshot>()
+            .add_event::<ClearSign>()
+            .add_event::<MarqueeCommand>()
+            .add_event::<RainCommand>()
+            .add_event::<ChangeSize>()
+            .add_event::<StartRace>()
+            .add_event::<ApiEvent>()
+            .add_systems(Startup, (setup_with_click_through, setup_hype_train_bar, setup_status_chips, setup_viewer_widget, log_render_adapter, setup_toasts, setup_mirror_window, setup_debug_overlay, setup_loading_panel))
+            .add_systems(Startup, move |mut events: EventWriter<ToggleSettingsWindow>| {
+                if open_settings {
+                    events.send(ToggleSettingsWindow {});
+                }
+            })
+            .add_systems(
+                Update,
+                (
+                    move_users,
+                    despawn_users.run_if(on_real_timer(USER_DESPAWN_CHECK_INTERVAL)),
+                    despawn_messages.run_if(on_real_timer(MESSAGE_DESPAWN_CHECK_INTERVAL)),
+                    handle_chat_messages,
+                    handle_admin_commands.after(handle_chat_messages),
+                    handle_stream_status,
+                    handle_source_status,
+                    handle_window_events.run_if(
+                        on_event::<WindowMoved>()
+                            .or_else(on_event::<WindowResized>())
+                            .or_else(on_event::<WindowFocused>())
+                            .or_else(on_event::<WindowScaleFactorChanged>()),
+                    ),
+                    adjust_sprite_scale_system.run_if(
+                        any_with_component::<AdjustScale>
+                            .and_then(avatar_added.or_else(on_event::<AssetEvent<Image>>())),
+                    ),
+                    forward_eventsub_events,
+                    handle_hype_train_events.after(forward_eventsub_events),
+                    update_hype_train_bar.after(handle_hype_train_events),
+                    handle_follow_events.after(forward_eventsub_events),
+                    trigger_nearby_waves,
+                    animate_waves.after(move_users),
+                    update_viewer_widget,
+                    start_giants.after(handle_admin_commands).after(forward_eventsub_events),
+                    update_giants.after(start_giants).after(adjust_sprite_scale_system),
+                    receive_channel_emotes.before(handle_chat_messages),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    reload_config,
+                    apply_window_settings.after(reload_config),
+                    snap_users_to_floor.after(reload_config).run_if(resource_changed::<Config>),
+                    place_window.after(reload_config),
+                    apply_log_level.after(reload_config),
+                    apply_locale.after(reload_config),
+                    show_toasts,
+                    handle_hotkeys,
+                    handle_tray_events,
+                    handle_overlay_actions.after(handle_hotkeys).after(handle_tray_events),
+                    track_activity,
+                    track_occlusion.after(handle_overlay_actions),
+                    apply_opacity.after(reload_config).after(handle_overlay_actions),
+                    apply_linux_window.after(handle_overlay_actions),
+                    update_mirror_window.after(reload_config),
+                    pack_emote_atlas.run_if(on_event::<AssetEvent<Image>>()),
+                    prioritize_visible_downloads,
+                    run_schedule.after(reload_config).run_if(on_real_timer(SCHEDULE_CHECK_INTERVAL)),
+                    (start_races, join_races, run_race, show_race_banner)
+                        .chain()
+                        .after(handle_chat_messages)
+                        .after(move_users),
+                    (collect_party_stats, publish_party_stats)
+                        .chain()
+                        .after(handle_chat_messages)
+                        .run_if(resource_exists::<StatsPublisher>),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    show_debug_overlay
+                        .after(handle_overlay_actions)
+                        .run_if(resource_changed::<DebugOverlay>),
+                    update_debug_overlay.run_if(debug_overlay_visible),
+                    track_quiet_chat.after(handle_chat_messages),
+                    pause_animations.after(track_occlusion).after(track_quiet_chat),
+                    prune_handle_cache.run_if(on_real_timer(HANDLE_CACHE_PRUNE_INTERVAL)),
+                    handle_api_commands.before(handle_admin_commands).before(start_giants),
+                    broadcast_api_events,
+                    (
+                        reload_scripts,
+                        run_script_hooks.after(handle_chat_messages).after(forward_eventsub_events),
+                        apply_script_effects,
+                    )
+                        .chain()
+                        .run_if(scripts_enabled),
+                    animate_dances.after(apply_script_effects),
+                    restore_party.after(handle_chat_messages).run_if(restore_pending),
+                    start_giveaways.after(handle_admin_commands),
+                    run_giveaway_roulette.after(start_giveaways).after(move_users),
+                    expire_giveaway_winner,
+                    start_boops.after(handle_chat_messages),
+                    walk_to_boop.after(start_boops).after(move_users),
+                    animate_boops.after(walk_to_boop),
+                    handle_weather_events.after(handle_admin_commands).after(forward_eventsub_events),
+                    run_weather.after(handle_weather_events),
+                    update_lurker_ghosts
+                        .after(handle_chat_messages)
+                        .after(restore_party)
+                        .run_if(lurkers_enabled),
+                    handle_sign_redemptions.after(handle_admin_commands).after(forward_eventsub_events),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    handle_timer_commands.after(handle_chat_messages),
+                    update_timer.after(handle_timer_commands).after(reload_config),
+                    show_streak_badges.after(handle_chat_messages).after(restore_party),
+                    update_leaderboard.after(handle_chat_messages).after(reload_config),
+                    animate_leader_sparkles.after(update_leaderboard),
+                    show_pronouns
+                        .after(handle_chat_messages)
+                        .after(restore_party)
+                        .run_if(pronouns_enabled),
+                    handle_accessory_redemptions.after(handle_admin_commands).after(forward_eventsub_events),
+                    put_on_accessories.after(handle_chat_messages).after(restore_party),
+                    fit_accessories
+                        .after(handle_accessory_redemptions)
+                        .after(put_on_accessories)
+                        .after(update_giants),
+                    celebrate_pyramids.after(handle_chat_messages),
+                    run_emote_rain.after(celebrate_pyramids),
+                    handle_progress_commands.after(handle_chat_messages),
+                    update_progress_bar.after(handle_progress_commands).after(reload_config),
+                    cluster_at_progress.after(update_progress_bar).before(move_users),
+                    handle_poll_events.after(forward_eventsub_events),
+                    update_poll_zones.after(handle_poll_events).after(reload_config),
+                    walk_to_poll_zones
+                        .after(handle_poll_events)
+                        .after(cluster_at_progress)
+                        .before(move_users),
+                    apply_ambient_tint
+                        .after(reload_config)
+                        .after(apply_script_effects)
+                        .after(update_effect_policy),
+                    crown_top_cheerer
+                        .after(handle_chat_messages)
+                        .after(handle_admin_commands)
+                        .after(restore_party),
+                    animate_bits_crown.after(crown_top_cheerer).after(update_leaderboard),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    celebrate_birthdays
+                        .after(handle_chat_messages)
+                        .after(restore_party)
+                        .before(fit_accessories),
+                    (receive_gifts, drop_gift_boxes).chain().after(move_users),
+                    update_floor_glow.after(handle_chat_messages).after(reload_config),
+                    place_campfire.after(reload_config),
+                    gather_at_campfire
+                        .after(place_campfire)
+                        .after(adjust_sprite_scale_system)
+                        .before(cluster_at_progress),
+                    handle_marquee_commands.after(handle_chat_messages),
+                    update_marquee.after(handle_marquee_commands).after(reload_config),
+                    (leave_footprints, fade_footprints).chain().after(move_users),
+                    (greet_returning_chatters, animate_greetings).chain().after(handle_chat_messages),
+                    spawn_queued_users.after(handle_chat_messages).before(move_users),
+                    (react_to_messages, animate_droops).chain().after(handle_chat_messages),
+                    handle_moderation.before(handle_chat_messages),
+                    animate_dust_offs,
+                    point_bubble_tails.after(move_users),
+                    spawn_pinned_users.after(restore_party),
+                    update_startup_progress,
+                    show_repeat_badges.after(handle_chat_messages),
+                    update_effect_policy.after(reload_config).run_if(resource_changed::<Config>),
+                    (calm_motion, animate_gentle_fades)
+                        .chain()
+                        .after(update_effect_policy)
+                        .before(animate_waves)
+                        .before(animate_boops)
+                        .before(animate_dust_offs),
+                    apply_high_contrast
+                        .after(update_effect_policy)
+                        .after(handle_chat_messages)
+                        .before(apply_opacity),
+                ),
+            )
+            .add_systems(
+                Update,
+                (handle_rain_commands, run_rain)
+                    .chain()
+                    .after(handle_chat_messages)
+                    .after(update_effect_policy),
+            )
+            .add_systems(
+                Update,
+                (
+                    animate_bubbles.after(despawn_messages).after(update_effect_policy),
+                    type_bubble_text.before(apply_high_contrast),
+                )
+                    .after(handle_chat_messages),
+            )
+            .add_systems(
+                Update,
+                (
+                    handle_size_changes.after(handle_admin_commands).after(forward_eventsub_events),
+                    apply_saved_sizes
+                        .after(handle_chat_messages)
+                        .after(restore_party)
+                        .after(spawn_queued_users)
+                        .after(spawn_pinned_users)
+                        .before(adjust_sprite_scale_system),
+                    resize_avatars
+                        .after(handle_size_changes)
+                        .after(apply_saved_sizes)
+                        .after(update_giants)
+                        .before(fit_accessories),
+                    follow_heads
+                        .after(resize_avatars)
+                        .after(spawn_queued_users)
+                        .before(point_bubble_tails),
+                ),
+            )
+            .add_systems(Update, update_connection_chip.after(handle_source_status))
+            .add_systems(
+                Update,
+                (
+                    reply_to_party_events.after(run_giveaway_roulette),
+                    thank_raids.after(forward_eventsub_events),
+                ),
+            )
+            .add_systems(
+                Update,
+                (track_frame_budget, shed_particles)
+                    .chain()
+                    .after(update_effect_policy)
+                    .before(leave_footprints)
+                    .before(run_rain)
+                    .before(run_weather),
+            )
+            .add_systems(
+                Update,
+                (
+                    walk_behind_props.after(move_users).after(reload_config).before(apply_opacity),
+                    keep_bubbles_clear_of_props
+                        .after(move_users)
+                        .after(follow_heads)
+                        .before(point_bubble_tails),
+                    outline_stage_props.after(reload_config).after(handle_overlay_actions),
+                ),
+            )
+            .add_systems(Update, show_twitch_badges.after(handle_chat_messages).after(spawn_queued_users))
+            .add_systems(Last, (shutdown_chat_sources, save_state_on_exit, save_snapshot, limit_frame_rate));
+
+        if let Some(stats_publisher) = stats_publisher {
+            app.insert_resource(stats_publisher);
+        }
+
+        if headless {
+            info!("Running headless, there's no window, tray icon or hotkeys");
+            return;
+        }
+
+        // Everything below needs winit, the renderer or the desktop
+        app.add_plugins(FrameOutputPlugin)
+            .add_plugins(ScreenshotPlugin)
+            .add_plugins(GpuWatchdogPlugin {
+                stall: self.config.gpu_watchdog,
+            })
+            .add_plugins(EguiPlugin)
+            .add_systems(Startup, (setup_frame_waker, setup_tray, setup_interaction_border))
+            .add_systems(
+                Update,
+                (
+                    toggle_settings_window,
+                    settings_ui,
+                    update_frame_rate
+                        .after(track_activity)
+                        .after(track_occlusion)
+                        .after(track_quiet_chat),
+                    apply_capture_visibility
+                        .after(reload_config)
+                        .after(handle_overlay_actions),
+                    pet_minawan.after(handle_overlay_actions).after(move_users),
+                    animate_hearts,
+                ),
+            );
+    }
+}
+
+// Set up the camera and window
+fn setup(
+    mut commands: Commands,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+    click_through: bool,
+) {
+    commands.spawn((Camera2dBundle::default(), OverlayCamera {}));
+    match windows.get_single_mut() {
+        Ok(mut window) => window.cursor.hit_test = !click_through,
+        Err(err) => warn!("No primary window to set up: {}", err),
+    }
+}
+
+/// System to handle incoming chat messages
+#[allow(clippy::too_many_arguments)]
+fn handle_chat_messages(
+    mut commands: Commands,
+    mut pools: ResMut<EntityPools>,
+    mut handles: ResMut<HandleCache>,
+    asset_server: Res<AssetServer>,
+    mut emote_rec: ResMut<EmoteStorage>,
+    query: Query<&Camera, With<OverlayCamera>>,
+    mut app_state: ResMut<AppState>,
+    config: Res<Config>,
+    mut chat_receiver: ResMut<ChatReceiver>,
+    mut chat_commands: ChatCommands,
+    mut duplicates: Duplicates,
+    mut api_events: EventWriter<ApiEvent>,
+    mut giveaway: ResMut<Giveaway>,
+    mut streaks: ResMut<ChatStreaks>,
+    mut pyramids: ResMut<Pyramids>,
+    mut chat_heat: ResMut<ChatHeat>,
+) {
+    while let Ok(chat_message) = chat_receiver.receiver.try_recv() {
+        // Reconnects and server swaps can deliver the same message twice
+        if let Some(id) = &chat_message.id {
+            if duplicates.recent.is_duplicate(id, Instant::now(), config.dedupe_window) {
+                debug!("Dropping duplicate message: {}", id);
+                continue;
+            }
+        }
+        // Hidden Shared Chat users don't get to join in through commands either
+        if chat_message.origin_room_id.is_some() && !config.show_shared_chat {
+            debug!("Hiding shared chat message from: {}", chat_message.user);
+            continue;
+        }
+        // Commands are handled separately and never displayed
+        if chat_commands.send(&chat_message, &config) {
+            continue;
+        }
+        if chat_message.whisper {
+            continue;
+        }
+        // Messages sent just before a timeout can still arrive after it
+        if app_state.timeouts.suppressed(&chat_message.user_key(), Instant::now()) {
+            debug!("Dropping message from timed out user: {}", chat_message.user);
+            continue;
+        }
+        // Add any new emotes to the storage
+        for emote in chat_message.emotes.iter() {
+            emote_rec
+                .all
+                .entry(emote.name.clone())
+                .or_insert(emote.clone());
+        }
+        // Check if the user already exists
+        let user_key = chat_message.user_key();
+        giveaway.record(&user_key, &chat_message.message, Instant::now(), config.giveaway_keyword_window);
+        if config.streaks_enabled || config.greetings_enabled {
+            streaks.record(&user_key, config.today());
+        }
+        app_state.message_counts.add(&user_key, counting_day(&config));
+        // Bits cheered in the other channel of a Shared Chat session aren't for this one
+        if chat_message.bits > 0 && chat_message.origin_room_id.is_none() {
+            app_state.bit_totals.add(&user_key, chat_message.bits, counting_month(&config));
+        }
+        pyramids.observe(&user_key, &chat_message.message, |word| emote_rec.all.contains_key(word), &config);
+        if config.floor_glow_enabled {
+            chat_heat.record(Instant::now(), config.floor_glow_window);
+        }
+        let zen_mode = app_state.zen_mode;
+        let pinned = config.is_pinned(&user_key);
+        if pinned {
+            app_state.rekey_user(&user_key);
+        }
+        if let Some(user) = app_state.active_users.get_mut(&user_key) {
+            // Update the user's last message time and display the message, unless it's counted
+            // on the same message they're still showing
+            if !zen_mode {
+                api_events.send_batch(ApiEvent::for_message(&user_key, &chat_message.message, &emote_rec));
+                if !duplicates.repeat(&mut commands, user.entity, &chat_message.message, &config) {
+                    display_message(
+                        &mut commands,
+                        &mut pools,
+                        &mut handles,
+                        &asset_server,
+                        &mut emote_rec,
+                        &config,
+                        user.entity,
+                        chat_message.message,
+                        chat_message.origin_room_id.is_some(),
+                    );
+                }
+            }
+            // user.last_message = Some(message);
+            user.last_message_time = Instant::now();
+        } else if app_state.offline_idle(&config) {
+            // Don't bring new minawan to the party while the stream is offline
+            debug!("Stream offline, not spawning user: {}", chat_message.user);
+        } else if app_state.spawning_paused {
+            debug!("Spawning paused, not spawning user: {}", chat_message.user);
+        } else if app_state.quiet_hours {
+            debug!("Quiet hours, not spawning user: {}", chat_message.user);
+        } else if !pinned && !app_state.spawn_queue.admit(Instant::now(), &config) {
+            // Over SPAWNS_PER_SEC, their minawan arrives with this message a little later. Pinned
+            // users never wait.
+            debug!("Queueing spawn of user: {}", chat_message.user);
+            app_state.spawn_queue.push(chat_message, Instant::now(), &config);
+        } else {
+            // Add new user and spawn their avatar
+            let Some(rect) = query.single().logical_viewport_rect() else {
+                warn!("The overlay has no size yet, not spawning user: {}", chat_message.user);
+                continue;
+            };
+            make_room(&mut commands, &mut app_state, &mut pools, &config, &mut api_events);
+            let entity = spawn_user(
+                &mut commands,
+                &mut pools,
+                &mut handles,
+                &asset_server,
+                &chat_message,
+                &config,
+                rect,
+            );
+            let name = chat_message.name().to_string();
+            if let Some(x) = app_state.timeouts.returning(&user_key, Instant::now(), &config) {
+                return_to_spot(&mut commands, entity, x, rect, &config);
+            }
+            api_events.send(ApiEvent::UserSpawned { user: user_key.clone() });
+            if !zen_mode {
+                api_events.send_batch(ApiEvent::for_message(&user_key, &chat_message.message, &emote_rec));
+                display_message(
+                    &mut commands,
+                    &mut pools,
+                    &mut handles,
+                    &asset_server,
+                    &mut emote_rec,
+                    &config,
+                    entity,
+                    chat_message.message,
+                    chat_message.origin_room_id.is_some(),
+                );
+            }
+            app_state.insert_user(user_key, User::new(entity, name, Instant::now()));
+        }
+    }
+}
+
+fn handle_window_events(
+    mut window_moved_events: EventReader<WindowMoved>,
+    mut window_resized_events: EventReader<WindowResized>,
+    mut window_focused_events: EventReader<WindowFocused>,
+    mut scale_factor_events: EventReader<WindowScaleFactorChanged>,
+    windows: Query<(Entity, &Window), With<PrimaryWindow>>,
+    mut avatar_query: Query<&mut Transform, With<UserMarker>>,
+    config: Res<Config>,
+) {
+    let Ok((primary, window)) = windows.get_single() else {
+        return;
+    };
+    // Only the overlay window decides where the minawan can walk, not the mirror or settings windows
+    let moved = window_moved_events.read().filter(|event| event.window == primary).count();
+    let resized = window_resized_events.read().filter(|event| event.window == primary).count();
+    let focused = window_focused_events.read().filter(|event| event.window == primary).count();
+    let rescaled = scale_factor_events.read().filter(|event| event.window == primary).count();
+    if moved + resized + focused + rescaled > 0 {
+        // Percentage ranges follow the new size
+        let ranges = config.walk_ranges(window.width());
+        let floor_y = config.floor_y(window.height());
+        for mut transform in avatar_query.iter_mut() {
+            let x = clamp_to_ranges(transform.translation.x, &ranges);
+            // Only touch avatars that actually move, so change detection stays quiet
+            if transform.translation.x != x || transform.translation.y != floor_y {
+                transform.translation.x = x;
+                transform.translation.y = floor_y;
+            }
+        }
+    }
+}
+
+// Apply the configured scale and present mode to the overlay window when they change.
+// SCALE multiplies the monitor's own scale, which changes when the window moves to another monitor.
+fn apply_window_settings(
+    config: Res<Config>,
+    mut scale_factor_events: EventReader<WindowBackendScaleFactorChanged>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let monitor_changed = scale_factor_events.read().count() > 0;
+    if !config.is_changed() && !monitor_changed {
+        return;
+    }
+    if let Ok(mut window) = windows.get_single_mut() {
+        let scale_factor = window.resolution.base_scale_factor() * config.scale;
+        if window.resolution.scale_factor_override() != Some(scale_factor) {
+            window.resolution.set_scale_factor_override(Some(scale_factor));
+        }
+        if window.present_mode != config.present_mode {
+            window.present_mode = config.present_mode;
+        }
+    }
+}
+
+/// Run condition for avatars that were just spawned, their image may already be loaded
+fn avatar_added(query: Query<(), Added<AdjustScale>>) -> bool {
+    !query.is_empty()
+}
+
+// Size avatars once their image has loaded. Only runs when an avatar is waiting and either
+// one was just added or an image finished loading.
+#[allow(clippy::type_complexity)]
+fn adjust_sprite_scale_system(
+    mut commands: Commands,
+    mut query: Query<(Entity, &Handle<Image>, &mut Sprite, &mut Visibility, Option<&SizeClass>), With<AdjustScale>>,
+    images: Res<Assets<Image>>,
+) {
+    for (entity, texture_handle, mut sprite, mut visibility, size_class) in query.iter_mut() {
+        if let Some(image) = images.get(texture_handle) {
+            let texture_height = image.texture_descriptor.size.height as f32;
+            // Drawn at their user's size from the start, standing on the floor like the others
+            let height = size_class.map_or(AVATAR_HEIGHT, |size_class| size_class.height());
+            let scale_factor = height / texture_height;
+            
+            // Modify sprite custom size and make visible
+            sprite.custom_size.replace(Vec2::new(image.texture_descriptor.size.width as f32 * scale_factor, texture_height * scale_factor));
+            sprite.anchor = feet_anchor(height);
+            *visibility = Visibility::Visible;
+
+            // Remove the marker component
+            commands.entity(entity).remove::<AdjustScale>();
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,138 @@
+use bevy::{
+    prelude::{Local, Query, Ref, With},
+    window::{PrimaryWindow, RawHandleWrapper, Window},
+};
+use log::{info, warn};
+use raw_window_handle::RawWindowHandle;
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        shape::{ConnectionExt as _, SK, SO},
+        xproto::{ClipOrdering, ConnectionExt as _},
+    },
+    rust_connection::RustConnection,
+    NONE,
+};
+
+/// Connection to the X server and the overlay's window on it
+pub(crate) struct X11Window {
+    connection: RustConnection,
+    screen: usize,
+    window: u32,
+    click_through: Option<bool>,
+}
+
+impl X11Window {
+    fn connect(window: u32) -> Result<Self, String> {
+        let (connection, screen) = x11rb::connect(None).map_err(|err| err.to_string())?;
+        Ok(Self {
+            connection,
+            screen,
+            window,
+            click_through: None,
+        })
+    }
+
+    /// Transparency needs a compositing manager, without one the background is black
+    fn has_compositor(&self) -> Result<bool, String> {
+        let name = format!("_NET_WM_CM_S{}", self.screen);
+        let atom = self
+            .connection
+            .intern_atom(false, name.as_bytes())
+            .map_err(|err| err.to_string())?
+            .reply()
+            .map_err(|err| err.to_string())?
+            .atom;
+        let owner = self
+            .connection
+            .get_selection_owner(atom)
+            .map_err(|err| err.to_string())?
+            .reply()
+            .map_err(|err| err.to_string())?
+            .owner;
+        Ok(owner != NONE)
+    }
+
+    /// An empty input shape lets clicks through to the window underneath on every compositor,
+    /// resetting it to no shape makes the whole window take clicks again
+    fn set_click_through(&mut self, click_through: bool) -> Result<(), String> {
+        if click_through {
+            self.connection
+                .shape_rectangles(SO::SET, SK::INPUT, ClipOrdering::UNSORTED, self.window, 0, 0, &[])
+                .map_err(|err| err.to_string())?;
+        } else {
+            self.connection
+                .shape_mask(SO::SET, SK::INPUT, self.window, 0, 0, NONE)
+                .map_err(|err| err.to_string())?;
+        }
+        self.connection.flush().map_err(|err| err.to_string())?;
+        self.click_through = Some(click_through);
+        Ok(())
+    }
+}
+
+/// What's known about the display server once the window exists
+#[derive(Default)]
+pub(crate) enum LinuxWindow {
+    #[default]
+    Unchecked,
+    X11(X11Window),
+    /// Wayland, or X11 that couldn't be reached
+    Other,
+}
+
+// Make click-through and transparency work on X11 and explain what doesn't work on Wayland.
+// Checks the session once the window exists and whenever it's recreated.
+pub(crate) fn apply_linux_window(
+    windows: Query<(&Window, Ref<RawHandleWrapper>), With<PrimaryWindow>>,
+    mut state: Local<LinuxWindow>,
+) {
+    let Ok((window, handle)) = windows.get_single() else {
+        return;
+    };
+
+    if handle.is_changed() {
+        *state = match handle.window_handle {
+            RawWindowHandle::Xlib(xlib) => connect_x11(xlib.window as u32, window.transparent),
+            RawWindowHandle::Xcb(xcb) => connect_x11(xcb.window.get(), window.transparent),
+            RawWindowHandle::Wayland(_) => {
+                warn!(
+                    "Running on Wayland, where the overlay can't keep itself on top or choose its monitor and \
+                     click-through depends on the compositor. Start it with WAYLAND_DISPLAY unset to use X11 instead"
+                );
+                LinuxWindow::Other
+            }
+            _ => LinuxWindow::Other,
+        };
+    }
+
+    if let LinuxWindow::X11(x11) = &mut *state {
+        let click_through = !window.cursor.hit_test;
+        if x11.click_through != Some(click_through) {
+            if let Err(err) = x11.set_click_through(click_through) {
+                warn!("Failed to set the window's input shape: {}", err);
+                *state = LinuxWindow::Other;
+            }
+        }
+    }
+}
+
+fn connect_x11(window: u32, transparent: bool) -> LinuxWindow {
+    let x11 = match X11Window::connect(window) {
+        Ok(x11) => x11,
+        Err(err) => {
+            warn!("Can't reach the X server, click-through may not work: {}", err);
+            return LinuxWindow::Other;
+        }
+    };
+    match x11.has_compositor() {
+        Ok(true) => info!("Running on X11 with a compositor"),
+        Ok(false) if transparent => warn!(
+            "No compositor is running, so the overlay's background will be black. \
+             Enable compositing in your window manager or start one such as picom"
+        ),
+        Ok(false) => {}
+        Err(err) => warn!("Couldn't check for a compositor: {}", err),
+    }
+    LinuxWindow::X11(x11)
+}
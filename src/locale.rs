@@ -0,0 +1,220 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
+};
+
+use bevy::prelude::Res;
+
+use crate::config::Config;
+
+/// A language the overlay's own text can be shown in. Chat is shown as it was written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Locale {
+    English,
+    German,
+    Polish,
+}
+
+/// Every locale with its code and bundled translations, English first as the fallback
+const LOCALES: [(Locale, &str, &str); 3] = [
+    (Locale::English, "en", include_str!("locales/en.lang")),
+    (Locale::German, "de", include_str!("locales/de.lang")),
+    (Locale::Polish, "pl", include_str!("locales/pl.lang")),
+];
+
+/// Index into LOCALES of the language in use
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static TRANSLATIONS: OnceLock<Vec<HashMap<&'static str, &'static str>>> = OnceLock::new();
+
+impl Locale {
+    fn index(self) -> usize {
+        LOCALES
+            .iter()
+            .position(|(locale, _, _)| *locale == self)
+            .expect("Every locale is in LOCALES")
+    }
+
+    /// Plural form of a count, named like CLDR does
+    fn plural_category(self, count: u64) -> &'static str {
+        match self {
+            Locale::English | Locale::German => {
+                if count == 1 {
+                    "one"
+                } else {
+                    "other"
+                }
+            }
+            Locale::Polish => {
+                if count == 1 {
+                    "one"
+                } else if (2..=4).contains(&(count % 10)) && !(12..=14).contains(&(count % 100)) {
+                    "few"
+                } else {
+                    "many"
+                }
+            }
+        }
+    }
+}
+
+/// Takes a code like `de`, `de-AT` or `pl_PL`
+impl FromStr for Locale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let language = s.split(['-', '_']).next().unwrap_or_default().to_lowercase();
+        LOCALES
+            .iter()
+            .find(|(_, code, _)| *code == language)
+            .map(|(locale, _, _)| *locale)
+            .ok_or_else(|| {
+                let codes: Vec<&str> = LOCALES.iter().map(|(_, code, _)| *code).collect();
+                format!("{:?} isn't one of {}", s, codes.join(", "))
+            })
+    }
+}
+
+/// `key = value` lines, blank lines and lines starting with # are skipped
+fn parse_translations(text: &'static str) -> HashMap<&'static str, &'static str> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim(), value.trim()))
+        .collect()
+}
+
+fn translations() -> &'static [HashMap<&'static str, &'static str>] {
+    TRANSLATIONS.get_or_init(|| {
+        LOCALES
+            .iter()
+            .map(|(_, _, text)| parse_translations(text))
+            .collect()
+    })
+}
+
+/// Show overlay text in this language from now on
+pub(crate) fn set_locale(locale: Locale) {
+    CURRENT.store(locale.index(), Ordering::Relaxed);
+}
+
+/// Text for a key in the current language, English if it has no translation and the key
+/// itself if English has none either. With a `count` argument the plural form for it is
+/// used when there is one.
+pub(crate) fn translate(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    translate_in(translations(), CURRENT.load(Ordering::Relaxed), key, args)
+}
+
+/// The lookup behind translate, given the tables for each of LOCALES and the index of the one in use
+fn translate_in(
+    translations: &[HashMap<&'static str, &'static str>],
+    current: usize,
+    key: &str,
+    args: &[(&str, &dyn Display)],
+) -> String {
+    let count = args
+        .iter()
+        .find(|(name, _)| *name == "count")
+        .and_then(|(_, value)| value.to_string().parse::<u64>().ok());
+
+    let lookup = |index: usize| {
+        let table = &translations[index];
+        let plural = count.and_then(|count| {
+            let category = LOCALES[index].0.plural_category(count);
+            table
+                .get(format!("{}.{}", key, category).as_str())
+                .or_else(|| table.get(format!("{}.other", key).as_str()))
+        });
+        plural.or_else(|| table.get(key)).copied()
+    };
+    let Some(template) = lookup(current).or_else(|| lookup(0)) else {
+        return key.to_string();
+    };
+
+    let mut text = template.to_string();
+    for (name, value) in args {
+        text = text.replace(&format!("{{{}}}", name), &value.to_string());
+    }
+    text
+}
+
+/// Overlay text in the configured language: `t!("key")` or `t!("key", count = n, user = name)`
+macro_rules! t {
+    ($key:expr) => {
+        $crate::locale::translate($key, &[])
+    };
+    ($key:expr, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::locale::translate($key, &[$((stringify!($name), &$value as &dyn std::fmt::Display)),+])
+    };
+}
+pub(crate) use t;
+
+// Switch language when LOCALE changes in the config
+pub(crate) fn apply_locale(config: Res<Config>) {
+    if config.is_changed() {
+        set_locale(config.locale);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// English, German and Polish tables. German has no lines so everything falls back.
+    fn tables(polish: &'static str) -> Vec<HashMap<&'static str, &'static str>> {
+        let english = "
+            votes.one = {count} vote
+            votes.other = {count} votes
+            title = leaderboard
+        ";
+        vec![parse_translations(english), HashMap::new(), parse_translations(polish)]
+    }
+
+    #[test]
+    fn polish_plural_categories() {
+        for (count, category) in [(1, "one"), (2, "few"), (5, "many"), (12, "many"), (22, "few"), (112, "many")] {
+            assert_eq!(Locale::Polish.plural_category(count), category, "{}", count);
+        }
+    }
+
+    #[test]
+    fn missing_plural_form_uses_other() {
+        let tables = tables("votes.one = {count} głos\nvotes.other = {count} głosów");
+        let polish = Locale::Polish.index();
+        assert_eq!(translate_in(&tables, polish, "votes", &[("count", &1)]), "1 głos");
+        assert_eq!(translate_in(&tables, polish, "votes", &[("count", &3)]), "3 głosów");
+        assert_eq!(translate_in(&tables, polish, "votes", &[("count", &5)]), "5 głosów");
+    }
+
+    #[test]
+    fn missing_translation_uses_english() {
+        let tables = tables("");
+        let polish = Locale::Polish.index();
+        assert_eq!(translate_in(&tables, polish, "votes", &[("count", &1)]), "1 vote");
+        assert_eq!(translate_in(&tables, polish, "votes", &[("count", &3)]), "3 votes");
+        assert_eq!(translate_in(&tables, polish, "title", &[]), "leaderboard");
+    }
+
+    #[test]
+    fn missing_key_is_shown_as_is() {
+        let tables = tables("");
+        let polish = Locale::Polish.index();
+        assert_eq!(translate_in(&tables, polish, "no.such.key", &[]), "no.such.key");
+        assert_eq!(translate_in(&tables, polish, "no.such.key", &[("count", &2)]), "no.such.key");
+    }
+
+    #[test]
+    fn bundled_polish_has_every_plural_form() {
+        let polish = &translations()[Locale::Polish.index()];
+        for key in polish.keys().filter_map(|key| key.strip_suffix(".one")) {
+            for category in ["few", "many"] {
+                assert!(polish.contains_key(format!("{}.{}", key, category).as_str()), "{}.{}", key, category);
+            }
+        }
+    }
+}
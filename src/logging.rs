@@ -0,0 +1,271 @@
+use std::{
+    collections::VecDeque,
+    env,
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock, RwLock},
+};
+
+use bevy::prelude::Res;
+use env_logger::{Builder, Target, WriteStyle};
+use log::{Level, Log, Metadata, Record};
+
+use crate::{config::Config, paths::in_data_dir};
+
+/// Environment variable that sets the log filter, overrides LOG_LEVEL in the config
+const LOG_LEVEL_ENV: &str = "LOG_LEVEL";
+const DEFAULT_LOG_LEVEL: &str = "info";
+
+/// Warnings waiting to be shown as toasts, older ones are dropped past this
+const MAX_PENDING_TOASTS: usize = 20;
+/// Log lines kept in memory for crash reports
+const RECENT_LINES: usize = 200;
+
+/// A warning, error or notice to flash up on the overlay
+pub(crate) struct Toast {
+    pub(crate) level: Level,
+    pub(crate) message: String,
+}
+
+/// Log file that starts a new file once it gets too big, keeping a few old ones around
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    kept: usize,
+}
+
+/// `path.N`, the Nth most recent old log
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+impl RotatingFile {
+    fn open(path: &Path, max_size: u64, kept: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            size: file.metadata()?.len(),
+            file,
+            max_size,
+            kept,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for index in (1..self.kept).rev() {
+            let from = rotated_path(&self.path, index);
+            if from.exists() {
+                fs::rename(from, rotated_path(&self.path, index + 1))?;
+            }
+        }
+        if self.kept > 0 {
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(buf)?;
+        self.size += buf.len() as u64;
+        Ok(())
+    }
+}
+
+/// The log file, and the lines logged before the config said where it goes
+struct LogOutput {
+    file: Option<RotatingFile>,
+    early: Option<Vec<u8>>,
+}
+
+static OUTPUT: Mutex<LogOutput> = Mutex::new(LogOutput {
+    file: None,
+    early: Some(Vec::new()),
+});
+
+/// The last lines logged, newest last
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Writes each line to stderr and the log file
+struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Without a console this goes nowhere, which is fine
+        let _ = io::stderr().write_all(buf);
+        {
+            let mut recent = RECENT.lock().unwrap();
+            for line in String::from_utf8_lossy(buf).lines() {
+                if recent.len() >= RECENT_LINES {
+                    recent.pop_front();
+                }
+                recent.push_back(line.to_string());
+            }
+        }
+        let mut output = OUTPUT.lock().unwrap();
+        if let Some(file) = output.file.as_mut() {
+            // There's nowhere left to report a failing log file
+            let _ = file.write(buf);
+        } else if let Some(early) = output.early.as_mut() {
+            early.extend_from_slice(buf);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// env_logger with a filter that can be changed after startup, also collecting warnings for toasts
+struct OverlayLogger {
+    inner: RwLock<env_logger::Logger>,
+    /// Set when the filter came from the command line or environment, LOG_LEVEL is ignored then
+    fixed_filter: bool,
+    toasts: Mutex<VecDeque<Toast>>,
+}
+
+impl Log for OverlayLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.read().unwrap().enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        let inner = self.inner.read().unwrap();
+        if !inner.matches(record) {
+            return;
+        }
+        inner.log(record);
+
+        // Only our own problems, dependencies can be noisy
+        if record.level() <= Level::Warn && record.target().starts_with(env!("CARGO_CRATE_NAME")) {
+            self.push_toast(record.level(), record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.read().unwrap().flush();
+    }
+}
+
+impl OverlayLogger {
+    fn push_toast(&self, level: Level, message: String) {
+        let mut toasts = self.toasts.lock().unwrap();
+        if toasts.len() >= MAX_PENDING_TOASTS {
+            toasts.pop_front();
+        }
+        toasts.push_back(Toast { level, message });
+    }
+}
+
+static LOGGER: OnceLock<OverlayLogger> = OnceLock::new();
+
+fn build_logger(filters: &str) -> env_logger::Logger {
+    Builder::new()
+        .parse_filters(filters)
+        .write_style(WriteStyle::Never)
+        .target(Target::Pipe(Box::new(LogWriter)))
+        .build()
+}
+
+/// Start logging to stderr straight away, the log file is opened by `configure_logging`.
+/// `--log-level` takes precedence over the LOG_LEVEL environment variable, which takes
+/// precedence over LOG_LEVEL in the config.
+pub(crate) fn init_logging(cli_filter: Option<&str>) {
+    // Release builds have no console of their own, borrow the terminal they were started from
+    #[cfg(windows)]
+    unsafe {
+        use windows::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+        let _ = AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+
+    let fixed = cli_filter
+        .map(str::to_string)
+        .or_else(|| env::var(LOG_LEVEL_ENV).ok());
+    let logger = LOGGER.get_or_init(|| OverlayLogger {
+        inner: RwLock::new(build_logger(fixed.as_deref().unwrap_or(DEFAULT_LOG_LEVEL))),
+        fixed_filter: fixed.is_some(),
+        toasts: Mutex::new(VecDeque::new()),
+    });
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(logger.inner.read().unwrap().filter());
+    }
+}
+
+/// Apply LOG_LEVEL unless the command line or environment already set the filter
+fn set_filter(filter: &str) {
+    let Some(logger) = LOGGER.get() else {
+        return;
+    };
+    if logger.fixed_filter {
+        return;
+    }
+    let new = build_logger(filter);
+    log::set_max_level(new.filter());
+    *logger.inner.write().unwrap() = new;
+}
+
+/// Open the log file from the config, writing everything logged so far into it
+pub(crate) fn configure_logging(config: &Config) {
+    set_filter(&config.log_level);
+
+    let mut output = OUTPUT.lock().unwrap();
+    let early = output.early.take().unwrap_or_default();
+    let Some(path) = &config.log_file else {
+        return;
+    };
+    let path = in_data_dir(path);
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    match RotatingFile::open(&path, config.log_max_size, config.log_files_kept) {
+        Ok(mut file) => {
+            let _ = file.write(&early);
+            output.file = Some(file);
+        }
+        Err(err) => {
+            drop(output);
+            log::warn!("Failed to open log file {}: {}", path.display(), err);
+        }
+    }
+}
+
+/// The last lines logged, for crash reports. Still works after a panic while logging.
+pub(crate) fn recent_log_lines() -> Vec<String> {
+    let recent = RECENT.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    recent.iter().cloned().collect()
+}
+
+/// Show a notice as a toast, for things worth telling the streamer that aren't a problem.
+/// It's logged at info level as well.
+pub(crate) fn notify(message: String) {
+    log::info!("{}", message);
+    if let Some(logger) = LOGGER.get() {
+        logger.push_toast(Level::Info, message);
+    }
+}
+
+/// Warnings, errors and notices since the last call
+pub(crate) fn take_toasts() -> Vec<Toast> {
+    LOGGER
+        .get()
+        .map(|logger| logger.toasts.lock().unwrap().drain(..).collect())
+        .unwrap_or_default()
+}
+
+// Follow LOG_LEVEL changes in the config while running
+pub(crate) fn apply_log_level(config: Res<Config>) {
+    if config.is_changed() && !config.is_added() {
+        set_filter(&config.log_level);
+    }
+}
@@ -0,0 +1,201 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    asset::AssetServer,
+    prelude::{Camera, Commands, Component, Entity, Query, Res, ResMut, Resource, Transform, With},
+};
+use log::{debug, info, warn};
+use rand::seq::IteratorRandom;
+use tokio::{sync::mpsc, time::sleep};
+
+use crate::{
+    config::Config, handles::HandleCache, helix::HelixClient, opacity::BaseAlpha, pool::EntityPools,
+    users::spawn_user, AppState, ChatMessage, OverlayCamera, Platform, UserMarker,
+};
+
+/// How faint ghosts are
+const GHOST_ALPHA: f32 = 0.3;
+/// How often the full chatters list is fetched, JOIN and PART fill in between
+const CHATTERS_POLL_INTERVAL: Duration = Duration::from_secs(120);
+
+/// Who's in chat, from Twitch IRC's JOIN and PART and the Helix chatters list
+#[derive(Debug)]
+pub(crate) enum Presence {
+    Joined(String),
+    Left(String),
+    /// Everyone in chat right now
+    Chatters(Vec<String>),
+}
+
+#[derive(Resource)]
+pub(crate) struct PresenceReceiver {
+    pub(crate) receiver: mpsc::Receiver<Presence>,
+}
+
+/// Fetch the chatters list every few minutes. JOIN and PART are batched and stop in channels
+/// with over a thousand chatters, the list catches what they miss.
+pub(crate) async fn poll_chatters(helix: HelixClient, channel_id: String, tx: mpsc::Sender<Presence>) {
+    // The chatters list needs the id of a moderator, which is whoever the token belongs to
+    let moderator_id = match helix.get_token_user_id().await {
+        Ok(Some(moderator_id)) => moderator_id,
+        Ok(None) => {
+            warn!("OAUTH_TOKEN has no user, lurkers only come from JOIN and PART");
+            return;
+        }
+        Err(err) => {
+            warn!("Failed to look up token user, lurkers only come from JOIN and PART: {}", err);
+            return;
+        }
+    };
+    loop {
+        match helix.get_chatters(&channel_id, &moderator_id).await {
+            Ok(chatters) => {
+                debug!("{} chatters in chat", chatters.len());
+                if tx.send(Presence::Chatters(chatters)).await.is_err() {
+                    // Bevy side has shut down
+                    break;
+                }
+            }
+            Err(err) => warn!("Failed to get the chatters list: {}", err),
+        }
+        sleep(CHATTERS_POLL_INTERVAL).await;
+    }
+}
+
+/// Viewers in chat, and the ones shown as ghosts
+#[derive(Resource, Default)]
+pub(crate) struct Lurkers {
+    /// Logins of everyone in chat
+    present: HashSet<String>,
+    ghosts: HashMap<String, Entity>,
+    next_rotation: Option<Instant>,
+}
+
+/// A faint minawan for someone in chat who hasn't said anything
+#[derive(Component)]
+pub(crate) struct Ghost {}
+
+/// Run condition for showing lurkers
+pub(crate) fn lurkers_enabled(config: Res<Config>) -> bool {
+    config.lurkers_enabled
+}
+
+// Keep a sample of up to MAX_GHOSTS lurkers at the party as ghosts. A ghost who chats turns
+// into their real minawan where the ghost stood, one who leaves chat goes away, and every
+// ROTATE_SECS one ghost makes room for another lurker.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn update_lurker_ghosts(
+    mut commands: Commands,
+    mut presence_receiver: ResMut<PresenceReceiver>,
+    mut lurkers: ResMut<Lurkers>,
+    mut pools: ResMut<EntityPools>,
+    mut handles: ResMut<HandleCache>,
+    asset_server: Res<AssetServer>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    mut avatar_query: Query<&mut Transform, With<UserMarker>>,
+) {
+    while let Ok(presence) = presence_receiver.receiver.try_recv() {
+        match presence {
+            Presence::Joined(login) => {
+                lurkers.present.insert(login.to_lowercase());
+            }
+            Presence::Left(login) => {
+                lurkers.present.remove(&login.to_lowercase());
+            }
+            Presence::Chatters(logins) => {
+                lurkers.present = logins.into_iter().map(|login| login.to_lowercase()).collect();
+            }
+        }
+    }
+
+    // Twitch users are keyed by name, which is their login in another case
+    let chatted: HashMap<String, Entity> = app_state
+        .active_users
+        .iter()
+        .filter(|(key, _)| !key.contains(':'))
+        .map(|(key, user)| (key.to_lowercase(), user.entity))
+        .collect();
+    let Lurkers { present, ghosts, .. } = &mut *lurkers;
+    ghosts.retain(|login, ghost| {
+        if let Some(avatar) = chatted.get(login) {
+            debug!("{} chatted, their ghost turns into their minawan", login);
+            let ghost_x = avatar_query.get(*ghost).map(|transform| transform.translation.x);
+            if let (Ok(x), Ok(mut transform)) = (ghost_x, avatar_query.get_mut(*avatar)) {
+                transform.translation.x = x;
+            }
+        } else if present.contains(login) {
+            return true;
+        }
+        pools.release_avatar(&mut commands, *ghost, &config);
+        false
+    });
+
+    let mut rng = rand::thread_rng();
+    // A lower MAX_GHOSTS sends the extras away
+    while lurkers.ghosts.len() > config.lurker_max_ghosts {
+        let Some(login) = lurkers.ghosts.keys().choose(&mut rng).cloned() else {
+            break;
+        };
+        if let Some(ghost) = lurkers.ghosts.remove(&login) {
+            pools.release_avatar(&mut commands, ghost, &config);
+        }
+    }
+    if app_state.spawning_paused || app_state.quiet_hours || app_state.offline_idle(&config) {
+        return;
+    }
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+
+    let now = Instant::now();
+    let rotate = config.lurker_max_ghosts > 0
+        && lurkers.ghosts.len() == config.lurker_max_ghosts
+        && lurkers.next_rotation.is_some_and(|next| now >= next);
+    if rotate || lurkers.next_rotation.is_none() {
+        lurkers.next_rotation = Some(now + config.lurker_rotation);
+    }
+    let hidden = |login: &String| {
+        !chatted.contains_key(login)
+            && !lurkers.ghosts.contains_key(login)
+            && *login != config.channel_name.to_lowercase()
+            && config
+                .twitch_username
+                .as_ref()
+                .map_or(true, |username| *login != username.to_lowercase())
+    };
+    let missing = config.lurker_max_ghosts - lurkers.ghosts.len();
+    let wanted = if rotate { 1 } else { missing };
+    let newcomers: Vec<String> = lurkers
+        .present
+        .iter()
+        .filter(|login| hidden(login))
+        .cloned()
+        .choose_multiple(&mut rng, wanted);
+    if rotate && !newcomers.is_empty() {
+        let leaving = lurkers.ghosts.keys().choose(&mut rng).cloned();
+        if let Some(ghost) = leaving.and_then(|login| lurkers.ghosts.remove(&login)) {
+            pools.release_avatar(&mut commands, ghost, &config);
+        }
+    }
+    for login in newcomers {
+        if rotate {
+            info!("{} takes a turn as a ghost", login);
+        }
+        let entity = spawn_user(
+            &mut commands,
+            &mut pools,
+            &mut handles,
+            &asset_server,
+            &ChatMessage::synthetic(Platform::Twitch, &login),
+            &config,
+            rect,
+        );
+        commands.entity(entity).insert((Ghost {}, BaseAlpha(GHOST_ALPHA)));
+        lurkers.ghosts.insert(login, entity);
+    }
+}
@@ -0,0 +1,343 @@
+use bevy::{
+    asset::AssetServer,
+    color::Color,
+    hierarchy::{BuildChildren, ChildBuilder, DespawnRecursiveExt},
+    math::{Vec2, Vec3},
+    prelude::{
+        default, Camera, Commands, Component, Entity, Event, EventReader, Image, Query, Res, ResMut, Resource,
+        SpatialBundle, Transform, With,
+    },
+    render::texture::{ImageFormatSetting, ImageLoaderSettings},
+    sprite::{Anchor, Sprite, SpriteBundle},
+    text::{Text, Text2dBundle, TextStyle},
+    time::Time,
+};
+use log::{debug, info};
+use vleue_kinetoscope::AnimatedImage;
+
+use crate::{
+    commands::is_admin,
+    config::{Config, ScreenEdge},
+    emotes::emote_types::Emote,
+    handles::HandleCache,
+    signs::{piece_width, pieces, spawn_emote, Piece},
+    ChatMessage, EmoteStorage, OverlayCamera,
+};
+
+/// Over the party and its signs, under the weather
+const MARQUEE_Z: f32 = 6.5;
+/// Space around the text inside the strip
+const PADDING: f32 = 6.0;
+/// Space between the end of the text and the start of its next copy
+const COPY_GAP: f32 = 80.0;
+/// Longest text the marquee shows, the rest is cut off
+const MAX_LENGTH: usize = 300;
+const TEXT_COLOR: Color = Color::WHITE;
+
+/// Set what the party is watching from chat
+#[derive(Event, Debug, Clone)]
+pub(crate) enum MarqueeCommand {
+    /// The text, with the Twitch emotes in it
+    Set { text: String, emotes: Vec<Emote> },
+    Clear,
+    Unknown(String),
+}
+
+/// How the text was laid out, it's laid out again when any of this changes
+struct MarqueeLayout {
+    /// Width of the window it was laid out for
+    window_width: f32,
+    /// Emotes in the text known at the time
+    emotes: usize,
+    text_width: f32,
+    /// Width of one copy of the text and the gap after it, None when it fits and stands still
+    cycle: Option<f32>,
+}
+
+/// What the party is watching, kept in the snapshot so it survives a restart
+#[derive(Resource, Default)]
+pub(crate) struct Marquee {
+    text: Option<String>,
+    layout: Option<MarqueeLayout>,
+    /// How far the text has scrolled into its cycle
+    offset: f32,
+}
+
+impl Marquee {
+    pub(crate) fn new(text: Option<String>) -> Self {
+        Self {
+            text,
+            ..default()
+        }
+    }
+
+    pub(crate) fn text(&self) -> Option<&String> {
+        self.text.as_ref()
+    }
+
+    /// Whether the text is moving, it only stands still when it fits on screen
+    pub(crate) fn scrolling(&self) -> bool {
+        self.layout.as_ref().is_some_and(|layout| layout.cycle.is_some())
+    }
+}
+
+/// The strip along the top, the background and copies of the text are its children
+#[derive(Component)]
+pub(crate) struct MarqueeStrip {}
+
+/// One copy of the text, enough of them follow each other to fill the width
+#[derive(Component)]
+pub(crate) struct MarqueeCopy {
+    index: usize,
+}
+
+/// Parse a marquee command from the broadcaster or an admin.
+/// Returns None when the message should go through the normal display pipeline.
+pub(crate) fn parse_marquee_command(chat_message: &ChatMessage, config: &Config) -> Option<MarqueeCommand> {
+    if !config.marquee_enabled {
+        return None;
+    }
+    let mut words = chat_message.message.split_whitespace();
+    if !words.next()?.eq_ignore_ascii_case(&config.marquee_command) {
+        return None;
+    }
+    if !is_admin(chat_message, config) {
+        return None;
+    }
+
+    let text = words.collect::<Vec<&str>>().join(" ");
+    let command = match text.to_lowercase().as_str() {
+        "" => MarqueeCommand::Unknown(chat_message.message.clone()),
+        "off" => MarqueeCommand::Clear,
+        _ => MarqueeCommand::Set {
+            text: text.chars().take(MAX_LENGTH).collect::<String>().trim_end().to_string(),
+            emotes: chat_message.emotes.clone(),
+        },
+    };
+    Some(command)
+}
+
+// Change or clear the marquee for marquee commands
+pub(crate) fn handle_marquee_commands(
+    mut events: EventReader<MarqueeCommand>,
+    mut marquee: ResMut<Marquee>,
+    mut emote_store: ResMut<EmoteStorage>,
+) {
+    for event in events.read() {
+        match event {
+            MarqueeCommand::Set { text, emotes } => {
+                info!("Now watching: {}", text);
+                // Commands skip the pipeline that learns a message's emotes
+                for emote in emotes {
+                    emote_store.all.entry(emote.name.clone()).or_insert(emote.clone());
+                }
+                marquee.text = Some(text.clone());
+            }
+            MarqueeCommand::Clear => {
+                info!("Clearing the marquee");
+                marquee.text = None;
+            }
+            MarqueeCommand::Unknown(text) => {
+                debug!("Ignoring marquee command without any text: {}", text);
+                continue;
+            }
+        }
+        marquee.layout = None;
+        marquee.offset = 0.0;
+    }
+}
+
+/// Emotes in the text that can be drawn, whether or not they have loaded yet
+fn known_emotes(text: &str, emote_store: &EmoteStorage) -> usize {
+    text.split(' ')
+        .filter(|word| {
+            emote_store.atlas.contains_key(*word)
+                || emote_store.loaded.contains_key(*word)
+                || emote_store
+                    .all
+                    .get(*word)
+                    .is_some_and(|emote| emote.animated || emote.format.is_some())
+        })
+        .count()
+}
+
+/// Start loading the emotes in the text that nothing has drawn yet, like messages do
+pub(crate) fn load_emotes(
+    text: &str,
+    emote_store: &mut EmoteStorage,
+    handles: &mut HandleCache,
+    asset_server: &AssetServer,
+) {
+    for word in text.split(' ') {
+        if emote_store.atlas.contains_key(word) || emote_store.loaded.contains_key(word) {
+            continue;
+        }
+        let Some(emote) = emote_store.all.get(word) else {
+            continue;
+        };
+        let loaded = match (emote.animated, emote.format) {
+            (true, _) => emote.add_animated(handles.load::<AnimatedImage>(asset_server, &emote.emote_url)),
+            (false, Some(format)) => emote.add_static(handles.load_with_settings::<Image, ImageLoaderSettings>(
+                asset_server,
+                &emote.emote_url,
+                move |settings: &mut ImageLoaderSettings| settings.format = ImageFormatSetting::Format(format),
+            )),
+            // Its size and format are still being looked up
+            (false, None) => continue,
+        };
+        emote_store.loaded.insert(word.to_string(), loaded);
+    }
+}
+
+/// Lay out one copy of the text from its left end
+fn spawn_copy(
+    strip: &mut ChildBuilder,
+    index: usize,
+    pieces: &[Piece],
+    config: &Config,
+    asset_server: &AssetServer,
+    emote_store: &EmoteStorage,
+) {
+    strip
+        .spawn((
+            SpatialBundle::from_transform(Transform::from_xyz(0.0, 0.0, 0.1)),
+            MarqueeCopy { index },
+        ))
+        .with_children(|line| {
+            let space = config.font_width();
+            let mut x = 0.0;
+            for piece in pieces {
+                let width = piece_width(piece, config);
+                match piece {
+                    Piece::Text(text) => {
+                        line.spawn(Text2dBundle {
+                            text: Text::from_section(
+                                text.clone(),
+                                TextStyle {
+                                    font: asset_server.load(&config.font_url),
+                                    font_size: config.font_size,
+                                    color: TEXT_COLOR,
+                                },
+                            ),
+                            text_anchor: Anchor::CenterLeft,
+                            transform: Transform::from_xyz(x, 0.0, 0.0),
+                            ..default()
+                        });
+                    }
+                    Piece::Emote { name, .. } => spawn_emote(
+                        line,
+                        emote_store,
+                        name,
+                        Vec2::new(width, config.font_height()),
+                        Vec3::new(x + width / 2.0, 0.0, 0.0),
+                    ),
+                }
+                x += width + space;
+            }
+        });
+}
+
+// Show the marquee along the top of the overlay, under the progress bar when that's at the
+// top too. Text wider than the window scrolls at SPEED and wraps around, text that fits
+// stands still in the middle. It's laid out again when the window, the config or the
+// emotes it knows about change.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn update_marquee(
+    mut commands: Commands,
+    mut marquee: ResMut<Marquee>,
+    mut emote_store: ResMut<EmoteStorage>,
+    mut handles: ResMut<HandleCache>,
+    config: Res<Config>,
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    strip_query: Query<Entity, With<MarqueeStrip>>,
+    mut copy_query: Query<(&MarqueeCopy, &mut Transform)>,
+) {
+    let text = marquee.text.clone().filter(|_| config.marquee_enabled);
+    let Some((text, rect)) = text.zip(camera_query.single().logical_viewport_rect()) else {
+        for entity in strip_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        if marquee.layout.is_some() {
+            marquee.layout = None;
+        }
+        return;
+    };
+
+    let stale = match &marquee.layout {
+        None => true,
+        Some(layout) => {
+            layout.window_width != rect.max.x
+                || config.is_changed()
+                || (emote_store.is_changed() && layout.emotes != known_emotes(&text, &emote_store))
+        }
+    };
+    if stale {
+        for entity in strip_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        load_emotes(&text, &mut emote_store, &mut handles, &asset_server);
+        let pieces = pieces(&text, &emote_store, &config);
+        let text_width = pieces.iter().map(|piece| piece_width(piece, &config)).sum::<f32>()
+            + config.font_width() * pieces.len().saturating_sub(1) as f32;
+        let fits = text_width <= rect.max.x - PADDING * 2.0;
+        let cycle = (!fits).then_some(text_width + COPY_GAP);
+        let copies = cycle.map_or(1, |cycle| (rect.max.x / cycle).ceil() as usize + 1);
+
+        let height = config.font_height() + PADDING * 2.0;
+        let progress_bar = match config.progress_edge {
+            ScreenEdge::Top if config.progress_enabled => config.progress_thickness,
+            _ => 0.0,
+        };
+        let y = rect.max.y / 2.0 - progress_bar - height / 2.0;
+        commands
+            .spawn((
+                SpatialBundle::from_transform(Transform::from_xyz(0.0, y, MARQUEE_Z)),
+                MarqueeStrip {},
+            ))
+            .with_children(|strip| {
+                strip.spawn(SpriteBundle {
+                    sprite: Sprite {
+                        color: config.marquee_background,
+                        custom_size: Some(Vec2::new(rect.max.x, height)),
+                        ..default()
+                    },
+                    ..default()
+                });
+                for index in 0..copies {
+                    spawn_copy(strip, index, &pieces, &config, &asset_server, &emote_store);
+                }
+            });
+        marquee.layout = Some(MarqueeLayout {
+            window_width: rect.max.x,
+            emotes: known_emotes(&text, &emote_store),
+            text_width,
+            cycle,
+        });
+        if !fits {
+            marquee.offset %= text_width + COPY_GAP;
+        }
+        debug!("Marquee laid out {} wide, {}", text_width, if fits { "standing still" } else { "scrolling" });
+        // The copies are placed once they exist
+        return;
+    }
+
+    let Some((cycle, text_width)) = marquee.layout.as_ref().map(|layout| (layout.cycle, layout.text_width)) else {
+        return;
+    };
+    if let Some(cycle) = cycle {
+        marquee.offset = (marquee.offset + config.marquee_speed * time.delta_seconds()).rem_euclid(cycle);
+    }
+    let left = -rect.max.x / 2.0 + PADDING;
+    let offset = marquee.offset;
+    for (copy, mut transform) in copy_query.iter_mut() {
+        let x = match cycle {
+            Some(cycle) => left - offset + copy.index as f32 * cycle,
+            None => -text_width / 2.0,
+        };
+        if transform.translation.x != x {
+            transform.translation.x = x;
+        }
+    }
+}
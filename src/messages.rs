@@ -1,31 +1,65 @@
-use std::time::Instant;
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    time::{Duration, Instant},
+};
 
 use bevy::{
-    asset::{AssetServer, Handle}, color::{Alpha, Color}, math::{Vec2, Vec3}, prelude::{
-        default, BuildChildren, Commands, DespawnRecursiveExt, Entity, Image, Query, Res, ResMut,
-        Transform,
-    }, render::texture::{ImageFormatSetting, ImageLoaderSettings}, sprite::{Anchor, Sprite, SpriteBundle}, text::{
+    asset::{AssetServer, Handle}, color::Color, math::{Quat, Vec2, Vec3}, prelude::{
+        default, BuildChildren, Changed, Commands, Entity, Has, Image, Parent, Query, Res, ResMut,
+        Resource, Transform, Visibility, With, Without,
+    }, render::texture::{ImageFormatSetting, ImageLoaderSettings}, sprite::{Anchor, Sprite, SpriteBundle, TextureAtlas}, text::{
         BreakLineOn, Font, JustifyText, Text, Text2dBounds, Text2dBundle, TextSection, TextStyle,
     }
 };
 use log::{debug, info};
 use vleue_kinetoscope::{AnimatedImage, AnimatedImageBundle};
 
-use crate::{config::Config, EmoteStorage, MessageSpawnTime};
+use crate::{
+    accessibility::EffectPolicy,
+    bubble_themes::{start_vanishing, Appear, Appearing, BubbleVanish, ScaleOffset, Typewriter},
+    config::Config,
+    handles::HandleCache,
+    pool::EntityPools,
+    sizes::{avatar_size, head_y, SizeClass, AVATAR_HEIGHT},
+    BubbleTail, EmoteSprite, EmoteStorage, MessageBubble, MessageSpawnTime, MessageText, SharedChatBorder, UserMarker,
+};
+
+/// How often messages are checked for expiry
+pub(crate) const MESSAGE_DESPAWN_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+/// Width of the outline drawn around Shared Chat messages
+const SHARED_CHAT_BORDER: f32 = 2.0;
+/// White triangle pointing down from its top edge, tinted the bubble's colour
+const BUBBLE_TAIL_IMAGE: &str = "bubble_tail.png";
+/// Width of a bubble tail where it leaves the bubble
+const TAIL_WIDTH: f32 = 12.0;
+/// Space left between the tip of a bubble tail and the speaker's head
+const TAIL_GAP: f32 = 2.0;
+/// Shortest tail drawn, for bubbles sitting right on the speaker's head
+const MIN_TAIL_LENGTH: f32 = 4.0;
 
-// System to display message above the avatar's head
+// System to display message above the avatar's head, returns the message's entity
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn display_message(
     commands: &mut Commands,
+    pools: &mut EntityPools,
+    handles: &mut HandleCache,
     asset_server: &Res<AssetServer>,
     emote_store: &mut ResMut<EmoteStorage>,
     config: &Res<Config>,
     entity: Entity,
     message: String,
-) {
+    shared_chat: bool,
+) -> Entity {
     info!("Displaying message: {}", message);
+    let message_text = MessageText {
+        text: message.clone(),
+        shared_chat,
+    };
 
+    let theme = config.bubble_theme;
     // Font MUST be monospace or the emotes will not align correctly
-    let font = asset_server.load(&config.font_url);
+    let font = handles.load(asset_server, theme.font_url(config));
 
     // Configure the message box
     let mut box_size = Vec2::new(config.message_box_width, 50.0);
@@ -35,8 +69,8 @@ pub(crate) fn display_message(
     // debug!("Font height: {}", font_height);
     // debug!("Font width: {}", font_width);
 
-    let (text_sections, mut anim_emote_bundles, mut static_emote_bundles, lines, entries) =
-        create_message_sections(asset_server, message, emote_store, font, config);
+    let (mut text_sections, mut anim_emote_bundles, mut static_emote_bundles, lines, entries) =
+        create_message_sections(handles, asset_server, message, emote_store, font, theme.text_color(config), config);
 
     // If there is only one emote, display it large above the avatar
     if entries == 1 {
@@ -44,39 +78,73 @@ pub(crate) fn display_message(
             let mut emote = anim_emote_bundles.pop().unwrap();
             emote.transform = Transform::from_translation(Vec3::new(0.0, 50.0, 3.0))
                 .with_scale(Vec3::splat(0.45));
-            commands.entity(entity).with_children(|parent| {
-                parent.spawn(emote).insert(MessageSpawnTime(Instant::now()));
-            });
-            return;
+            return commands
+                .spawn(emote)
+                .set_parent(entity)
+                .insert((MessageSpawnTime(Instant::now()), message_text, EmoteSprite {}))
+                .id();
         } else if static_emote_bundles.len() == 1 {
-            let mut emote = static_emote_bundles.pop().unwrap();
+            let (mut emote, atlas) = static_emote_bundles.pop().unwrap();
             emote.transform = Transform::from_translation(Vec3::new(0.0, 50.0, 3.0))
                 .with_scale(Vec3::splat(0.45));
-            commands.entity(entity).with_children(|parent| {
-                parent.spawn(emote).insert(MessageSpawnTime(Instant::now()));
-            });
-            return;
+            let mut emote = commands.spawn(emote);
+            if let Some(atlas) = atlas {
+                emote.insert(atlas);
+            }
+            return emote
+                .set_parent(entity)
+                .insert((MessageSpawnTime(Instant::now()), message_text, EmoteSprite {}))
+                .id();
         }
     }
 
     box_size.y = (lines + 1.0) * (config.font_height() + config.line_space()) + config.top_margin() + 10.0;
-    box_position.y += box_size.y;
-
-    commands
-        .spawn(SpriteBundle {
-            sprite: Sprite {
-                color: Color::BLACK.with_alpha(0.4),
-                custom_size: Some(Vec2::new(box_size.x, box_size.y)),
-                anchor: Anchor::TopLeft,
-                ..default()
-            },
-            transform: Transform::from_translation(box_position.extend(0.0)),
+    // The theme's padding goes around the space the text wraps in
+    let bubble_size = box_size + Vec2::splat(theme.padding * 2.0);
+    box_position.x -= theme.padding;
+    box_position.y += bubble_size.y;
+    let content_offset = theme.content_offset();
+    let tail_texture = (config.bubble_tail && theme.tail)
+        .then(|| handles.load::<Image>(asset_server, BUBBLE_TAIL_IMAGE));
+    let bubble_color = theme.color(config);
+    let image = theme.image(handles, asset_server);
+    let typewriter = match theme.appear {
+        Appear::Typewriter { chars_per_sec, blip_hz } => {
+            Some(Typewriter::new(&mut text_sections, chars_per_sec, blip_hz))
+        }
+        _ => None,
+    };
+
+    let mut bubble = pools.spawn_bubble(commands, SpriteBundle {
+        sprite: Sprite {
+            color: bubble_color,
+            custom_size: Some(bubble_size),
+            anchor: Anchor::TopLeft,
             ..default()
-        })
+        },
+        texture: image.as_ref().map(|(texture, _)| texture.clone()).unwrap_or_default(),
+        transform: Transform::from_translation(box_position.extend(0.0)),
+        ..default()
+    });
+    if let Some((_, scale_mode)) = image {
+        bubble.insert(scale_mode);
+    }
+    if theme.appear == Appear::Pop {
+        bubble.insert(Appearing {
+            started: Instant::now(),
+        });
+    }
+    bubble
         .set_parent(entity)
-        .insert(MessageSpawnTime(Instant::now()))
+        .insert((
+            MessageSpawnTime(Instant::now()),
+            message_text,
+            MessageBubble {},
+            BubbleVanish(theme.vanish),
+            ScaleOffset::default(),
+        ))
         .with_children(|builder| {
-            builder.spawn(Text2dBundle {
+            let mut text = builder.spawn(Text2dBundle {
                 text: Text {
                     sections: text_sections,
                     justify: JustifyText::Left,
@@ -85,17 +153,111 @@ pub(crate) fn display_message(
                 text_anchor: Anchor::TopLeft,
                 // Wrap text in the rectangle
                 text_2d_bounds: Text2dBounds { size: box_size },
-                // ensure the text is drawn on top of the box
-                // transform: Transform::from_translation(box_position.extend(1.0)),
+                transform: Transform::from_translation(content_offset),
                 ..default()
             });
-            for emote_bundle in anim_emote_bundles {
-                builder.spawn(emote_bundle);
+            if let Some(typewriter) = typewriter {
+                text.insert(typewriter);
             }
-            for emote_bundle in static_emote_bundles {
-                builder.spawn(emote_bundle);
+            for mut emote_bundle in anim_emote_bundles {
+                emote_bundle.transform.translation += content_offset;
+                builder.spawn((emote_bundle, EmoteSprite {}));
             }
-        });
+            for (mut emote_bundle, atlas) in static_emote_bundles {
+                emote_bundle.transform.translation += content_offset;
+                let mut emote = builder.spawn((emote_bundle, EmoteSprite {}));
+                if let Some(atlas) = atlas {
+                    emote.insert(atlas);
+                }
+            }
+            // Outline messages from the other channel in a Shared Chat session
+            if shared_chat {
+                builder.spawn((
+                    SpriteBundle {
+                        sprite: Sprite {
+                            color: config.shared_chat_border_color,
+                            custom_size: Some(bubble_size + Vec2::splat(SHARED_CHAT_BORDER * 2.0)),
+                            anchor: Anchor::TopLeft,
+                            ..default()
+                        },
+                        transform: Transform::from_translation(Vec3::new(
+                            -SHARED_CHAT_BORDER,
+                            SHARED_CHAT_BORDER,
+                            -0.1,
+                        )),
+                        ..default()
+                    },
+                    SharedChatBorder {},
+                ));
+            }
+            // Hidden until point_bubble_tails aims it at the speaker
+            if let Some(texture) = tail_texture {
+                builder.spawn((
+                    SpriteBundle {
+                        sprite: Sprite {
+                            color: bubble_color,
+                            custom_size: Some(Vec2::new(TAIL_WIDTH, MIN_TAIL_LENGTH)),
+                            anchor: Anchor::TopCenter,
+                            ..default()
+                        },
+                        texture,
+                        transform: Transform::from_xyz(bubble_size.x / 2.0, -bubble_size.y, 0.0),
+                        visibility: Visibility::Hidden,
+                        ..default()
+                    },
+                    BubbleTail {},
+                ));
+            }
+        })
+        .id()
+}
+
+// Aim bubble tails from the bottom of their bubble at the top of the speaker's head, wherever
+// the bubble sits. The tail leaves the bubble as close to the speaker as its edges allow and is
+// hidden when the bubble overlaps the speaker's head.
+#[allow(clippy::type_complexity)]
+pub(crate) fn point_bubble_tails(
+    mut tail_query: Query<(&Parent, &mut Transform, &mut Sprite, &mut Visibility), With<BubbleTail>>,
+    bubble_query: Query<(&Parent, &Transform, &Sprite), (With<MessageBubble>, Without<BubbleTail>)>,
+    avatar_query: Query<(&Sprite, Option<&SizeClass>), (With<UserMarker>, Without<MessageBubble>, Without<BubbleTail>)>,
+) {
+    for (bubble, mut transform, mut sprite, mut visibility) in tail_query.iter_mut() {
+        let Ok((avatar, bubble_transform, bubble_sprite)) = bubble_query.get(bubble.get()) else {
+            continue;
+        };
+        let Some(bubble_size) = bubble_sprite.custom_size else {
+            continue;
+        };
+        let head_y = avatar_query
+            .get(avatar.get())
+            .map_or(AVATAR_HEIGHT / 2.0, |(avatar, size_class)| head_y(avatar, avatar_size(avatar, size_class)));
+        // The speaker's head in the bubble's own space, where the top left corner is the origin
+        let head = Vec2::new(0.0, head_y + TAIL_GAP) - bubble_transform.translation.truncate();
+        let base = Vec2::new(
+            head.x.clamp(TAIL_WIDTH / 2.0, (bubble_size.x - TAIL_WIDTH / 2.0).max(TAIL_WIDTH / 2.0)),
+            -bubble_size.y,
+        );
+        let reach = head - base;
+        let shown = reach.y < 0.0;
+        let target_visibility = if shown { Visibility::Inherited } else { Visibility::Hidden };
+        if *visibility != target_visibility {
+            *visibility = target_visibility;
+        }
+        if !shown {
+            continue;
+        }
+        let length = reach.length().max(MIN_TAIL_LENGTH);
+        // The image points down, turn it towards the head whichever side of the bubble that is
+        let aimed = Transform::from_translation(base.extend(transform.translation.z))
+            .with_rotation(Quat::from_rotation_z(reach.x.atan2(-reach.y)));
+        if *transform != aimed {
+            *transform = aimed;
+        }
+        let size = Some(Vec2::new(TAIL_WIDTH, length));
+        if sprite.custom_size != size {
+            sprite.custom_size = size;
+        }
+    }
 }
 
 /// Calculate the transform for an emote based on the current line and line length
@@ -116,21 +278,23 @@ fn calculate_emote_transform(
 
 /// Create the message sections and emote bundles by calculating the line breaks and emote positions
 fn create_message_sections(
+    handles: &mut HandleCache,
     asset_server: &Res<AssetServer>,
     message: String,
     emote_store: &mut ResMut<EmoteStorage>,
     font: Handle<Font>,
+    color: Color,
     config: &Config,
 ) -> (
     Vec<TextSection>,
     Vec<AnimatedImageBundle>,
-    Vec<SpriteBundle>,
+    Vec<(SpriteBundle, Option<TextureAtlas>)>,
     f32,
     i32,
 ) {
     let mut text_sections: Vec<TextSection> = vec![];
     let mut anim_emote_bundles: Vec<AnimatedImageBundle> = vec![];
-    let mut static_emote_bundles: Vec<SpriteBundle> = vec![];
+    let mut static_emote_bundles: Vec<(SpriteBundle, Option<TextureAtlas>)> = vec![];
 
     let mut line: String = "".to_string();
     let mut line_length = 0.0;
@@ -139,7 +303,7 @@ fn create_message_sections(
     let text_style = TextStyle {
         font,
         font_size: config.font_size,
-        color: Color::WHITE,
+        color,
     };
 
     let mut entries = 0;
@@ -179,7 +343,7 @@ fn create_message_sections(
                             .expect("Loaded animated emote has handle")
                             .clone_weak();
                     } else {
-                        handle = asset_server.load::<AnimatedImage>(&emote.emote_url);
+                        handle = handles.load::<AnimatedImage>(asset_server, &emote.emote_url);
                         emote_store
                             .loaded
                             .insert(emote.name.clone(), emote.add_animated(handle.clone()));
@@ -202,14 +366,23 @@ fn create_message_sections(
                 }
                 false => {
                     let handle: Handle<Image>;
-                    if let Some(loaded_emote) = emote_store.loaded.get(&emote.name) {
+                    let mut atlas = None;
+                    // Packed emotes are drawn from the shared atlas texture
+                    if let Some(region) = emote_store.atlas.get(&emote.name) {
+                        handle = region.image.clone_weak();
+                        atlas = Some(TextureAtlas {
+                            layout: region.layout.clone_weak(),
+                            index: region.index,
+                        });
+                    } else if let Some(loaded_emote) = emote_store.loaded.get(&emote.name) {
                         handle = loaded_emote
                             .static_image
                             .as_ref()
                             .expect("Loaded static emote has handle")
                             .clone_weak();
                     } else {
-                        handle = asset_server.load_with_settings::<Image, ImageLoaderSettings>(
+                        handle = handles.load_with_settings::<Image, ImageLoaderSettings>(
+                            asset_server,
                             &emote.emote_url,
                             move |s: &mut ImageLoaderSettings| {
                                 s.format = ImageFormatSetting::Format(
@@ -221,21 +394,24 @@ fn create_message_sections(
                             .loaded
                             .insert(emote.name.clone(), emote.add_static(handle.clone()));
                     };
-                    static_emote_bundles.push(SpriteBundle {
-                        texture: handle,
-                        transform: calculate_emote_transform(
-                            line_length,
-                            line_number,
-                            spacing_width,
-                            emote_norm,
-                            config
-                        ),
-                        sprite: Sprite {
-                            color: Color::WHITE,
+                    static_emote_bundles.push((
+                        SpriteBundle {
+                            texture: handle,
+                            transform: calculate_emote_transform(
+                                line_length,
+                                line_number,
+                                spacing_width,
+                                emote_norm,
+                                config
+                            ),
+                            sprite: Sprite {
+                                color: Color::WHITE,
+                                ..default()
+                            },
                             ..default()
                         },
-                        ..default()
-                    });
+                        atlas,
+                    ));
                 }
             }
         } else {
@@ -266,12 +442,93 @@ fn create_message_sections(
     )
 }
 
-// System to handle despawning messages after a certain time
-pub(crate) fn despawn_messages(mut commands: Commands, query: Query<(Entity, &MessageSpawnTime)>, config: Res<Config>) {
+/// Messages by spawn time, oldest first, so despawning only looks at the ones that are due
+#[derive(Resource, Default)]
+pub(crate) struct MessageExpiry {
+    queue: BinaryHeap<Reverse<(Instant, Entity)>>,
+}
+
+// Despawn messages once they've been shown for MESSAGE_DESPAWN_TIME. Messages spawned or
+// given a new spawn time since the last check are queued, entries for messages that are
+// gone or were respawned since are skipped. Bubbles whose theme animates them out are left
+// to animate_bubbles.
+pub(crate) fn despawn_messages(
+    mut commands: Commands,
+    mut pools: ResMut<EntityPools>,
+    mut expiry: ResMut<MessageExpiry>,
+    new_messages: Query<(Entity, &MessageSpawnTime), Changed<MessageSpawnTime>>,
+    query: Query<(&MessageSpawnTime, Has<MessageBubble>, Option<&BubbleVanish>)>,
+    config: Res<Config>,
+    policy: Res<EffectPolicy>,
+) {
+    for (entity, spawn_time) in new_messages.iter() {
+        expiry.queue.push(Reverse((spawn_time.0, entity)));
+    }
+
     let now = Instant::now();
-    for (entity, spawn_time) in query.iter() {
-        if now.duration_since(spawn_time.0) > config.message_despawn_time {
-            commands.entity(entity).despawn_recursive();
+    while let Some(Reverse((spawn_time, _))) = expiry.queue.peek() {
+        if now.duration_since(*spawn_time) <= config.message_despawn_time {
+            break;
         }
+        let Some(Reverse((spawn_time, entity))) = expiry.queue.pop() else {
+            break;
+        };
+        if let Ok((current, bubble, vanish)) = query.get(entity) {
+            if current.0 != spawn_time {
+                continue;
+            }
+            if let Some(vanish) = vanish {
+                if start_vanishing(&mut commands, entity, vanish.0, &policy) {
+                    continue;
+                }
+            }
+            pools.release_message(&mut commands, entity, bubble, &config);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::{system::RunSystemOnce, world::World};
+
+    use super::*;
+    use crate::config::config_from_str;
+
+    /// Messages on screen at once, far more than any real chat
+    const MESSAGES: usize = 5_000;
+    /// How many of them have been shown long enough to go
+    const DUE: usize = 50;
+    /// How many have a stale entry from before they were shown again
+    const RESPAWNED: usize = 25;
+
+    #[test]
+    fn despawn_only_touches_due_messages() {
+        let now = Instant::now();
+        let long_ago = now - Duration::from_secs(2);
+        let mut world = World::new();
+        world.insert_resource(config_from_str("[Messages]\nMESSAGE_DESPAWN_TIME_MILIS = 1000\n").unwrap());
+        world.init_resource::<EntityPools>();
+        world.init_resource::<EffectPolicy>();
+        let mut expiry = MessageExpiry::default();
+        let messages: Vec<Entity> = (0..MESSAGES)
+            .map(|index| {
+                let spawned = if index < DUE { long_ago } else { now };
+                world.spawn(MessageSpawnTime(spawned)).id()
+            })
+            .collect();
+        for entity in &messages[DUE..DUE + RESPAWNED] {
+            expiry.queue.push(Reverse((long_ago, *entity)));
+        }
+        world.insert_resource(expiry);
+
+        world.run_system_once(despawn_messages);
+
+        let gone: Vec<Entity> = messages.iter().copied().filter(|entity| world.get_entity(*entity).is_none()).collect();
+        assert_eq!(gone, messages[..DUE].to_vec());
+        // Only the due and stale entries were taken off the queue
+        let expiry = world.resource::<MessageExpiry>();
+        assert_eq!(expiry.queue.len(), MESSAGES - DUE);
+        let oldest = expiry.queue.peek().map(|Reverse((spawned, _))| *spawned);
+        assert_eq!(oldest, Some(now));
     }
 }
@@ -0,0 +1,127 @@
+use bevy::{
+    ecs::query::Has,
+    prelude::{
+        default, Camera, Camera2dBundle, Commands, Component, DespawnRecursiveExt, Entity, Local,
+        OrthographicProjection, Query, Res, Transform, With, Without,
+    },
+    render::camera::{RenderTarget, ScalingMode},
+    window::{Monitor, PrimaryMonitor, PrimaryWindow, Window, WindowLevel, WindowPosition, WindowRef},
+};
+use log::info;
+
+use crate::{config::Config, monitor::select_monitor};
+
+/// Marker for the optional second window that shows the party on another monitor
+#[derive(Component)]
+pub(crate) struct MirrorWindow {}
+
+/// Camera drawing the party into the mirror window
+#[derive(Component)]
+pub(crate) struct MirrorCamera {
+    window: Entity,
+}
+
+/// Monitor the mirror was last placed on
+#[derive(Default)]
+pub(crate) struct MirrorPlacement {
+    target: Option<Option<String>>,
+    maximize_next_frame: bool,
+}
+
+pub(crate) fn setup_mirror_window(mut commands: Commands, config: Res<Config>) {
+    if !config.mirror_enabled {
+        return;
+    }
+    let window = commands
+        .spawn((
+            Window {
+                title: "Minawan Watch Party Mirror".to_string(),
+                transparent: true,
+                ..default()
+            },
+            MirrorWindow {},
+        ))
+        .id();
+    commands.spawn((
+        Camera2dBundle {
+            camera: Camera {
+                target: RenderTarget::Window(WindowRef::Entity(window)),
+                ..default()
+            },
+            ..default()
+        },
+        MirrorCamera { window },
+    ));
+}
+
+// Keep the mirror window on its monitor with the configured flags, and fit the overlay's
+// area into it. The world is shared, so everything else stays in sync by itself.
+pub(crate) fn update_mirror_window(
+    mut commands: Commands,
+    config: Res<Config>,
+    monitors: Query<(Entity, &Monitor, Has<PrimaryMonitor>)>,
+    primary_windows: Query<&Window, (With<PrimaryWindow>, Without<MirrorWindow>)>,
+    mut mirror_windows: Query<&mut Window, With<MirrorWindow>>,
+    mut cameras: Query<(Entity, &MirrorCamera, &mut OrthographicProjection, &mut Transform)>,
+    mut placement: Local<MirrorPlacement>,
+) {
+    let Ok(primary) = primary_windows.get_single() else {
+        return;
+    };
+    for (camera_entity, camera, mut projection, mut transform) in cameras.iter_mut() {
+        // Closing the mirror only closes the mirror
+        let Ok(mut window) = mirror_windows.get_mut(camera.window) else {
+            info!("Mirror window closed");
+            commands.entity(camera_entity).despawn_recursive();
+            continue;
+        };
+
+        if window.cursor.hit_test == config.mirror_click_through {
+            window.cursor.hit_test = !config.mirror_click_through;
+        }
+        let level = if config.mirror_always_on_top {
+            WindowLevel::AlwaysOnTop
+        } else {
+            WindowLevel::Normal
+        };
+        if window.window_level != level {
+            window.window_level = level;
+        }
+
+        if placement.maximize_next_frame {
+            placement.maximize_next_frame = false;
+            window.set_maximized(true);
+        } else if placement.target.as_ref() != Some(&config.mirror_monitor) {
+            let mut monitors: Vec<(Entity, &Monitor, bool)> = monitors.iter().collect();
+            if !monitors.is_empty() {
+                monitors.sort_by_key(|(entity, _, _)| *entity);
+                let monitor = select_monitor(&monitors, config.mirror_monitor.as_deref());
+                window.set_maximized(false);
+                window.position = WindowPosition::At(monitor.physical_position);
+                placement.maximize_next_frame = true;
+                placement.target = Some(config.mirror_monitor.clone());
+                info!("Placing mirror on monitor {}", monitor.name.as_deref().unwrap_or("unnamed"));
+            }
+        }
+
+        // Show the overlay's whole area, zoomed in by SCALE with the floor kept at the bottom
+        let (width, height) = (primary.width(), primary.height());
+        let zoom = 1.0 / config.mirror_scale;
+        let scaling_mode = ScalingMode::AutoMin {
+            min_width: width,
+            min_height: height,
+        };
+        if !matches!(projection.scaling_mode, ScalingMode::AutoMin { min_width, min_height }
+            if min_width == width && min_height == height)
+        {
+            projection.scaling_mode = scaling_mode;
+        }
+        if projection.scale != zoom {
+            projection.scale = zoom;
+        }
+        let y = -height / 2.0 * (1.0 - zoom);
+        if transform.translation.y != y {
+            transform.translation.y = y;
+        }
+    }
+}
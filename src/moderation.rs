@@ -0,0 +1,267 @@
+use std::{
+    collections::HashMap,
+    f32::consts::TAU,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    color::{Alpha, Color},
+    math::{Quat, Rect, Vec2},
+    prelude::{
+        default, Commands, Component, Entity, EventWriter, Query, Res, ResMut, Resource, Transform, With, Without,
+    },
+    sprite::{Sprite, SpriteBundle},
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::{
+    accessories::Accessories,
+    api::ApiEvent,
+    config::Config,
+    greetings::Greetings,
+    opacity::BaseAlpha,
+    pool::EntityPools,
+    sizes::AvatarSizes,
+    snapshot::PendingRestore,
+    state::unix_now,
+    streaks::ChatStreaks,
+    users::clamp_to_ranges,
+    AppState, UserMarker,
+};
+
+/// How long a returning minawan shakes the dust off
+const DUST_OFF_DURATION: Duration = Duration::from_millis(600);
+/// How far a returning minawan tips each way while shaking, in radians
+const SHAKE_ANGLE: f32 = 0.15;
+const SHAKES_PER_SEC: f32 = 6.0;
+const DUST_PUFFS: usize = 8;
+const DUST_SIZE: f32 = 5.0;
+const DUST_SPEED: f32 = 60.0;
+const DUST_COLOR: Color = Color::srgba(0.8, 0.75, 0.65, 0.8);
+/// How far under a minawan's center its feet are
+const FEET_DROP: f32 = 21.0;
+
+/// Timeouts and bans, from Twitch IRC's CLEARCHAT
+#[derive(Debug)]
+pub(crate) enum ModerationNotice {
+    TimedOut { user: String, duration: Duration },
+    Banned { user: String },
+}
+
+#[derive(Resource)]
+pub(crate) struct ModerationReceiver {
+    pub(crate) receiver: mpsc::Receiver<ModerationNotice>,
+}
+
+/// A timed out chatter and where their minawan stood
+struct Timeout {
+    until: Instant,
+    x: Option<f32>,
+}
+
+/// A timeout as saved with the party snapshot
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct SavedTimeout {
+    user: String,
+    /// Seconds since the Unix epoch
+    until: u64,
+    x: Option<f32>,
+}
+
+/// Timed out chatters by login. They stay here for RETURN_GRACE_SECS after the timeout ends, to
+/// come back where they stood.
+#[derive(Default)]
+pub(crate) struct Timeouts {
+    users: HashMap<String, Timeout>,
+}
+
+impl Timeouts {
+    /// Timeouts saved by the last run, however long ago they ended
+    pub(crate) fn restored(saved: Vec<SavedTimeout>) -> Self {
+        let (now, unix) = (Instant::now(), unix_now());
+        let users = saved
+            .into_iter()
+            .map(|saved| {
+                let left = Duration::from_secs(saved.until.saturating_sub(unix));
+                let ended = Duration::from_secs(unix.saturating_sub(saved.until));
+                let until = (now + left).checked_sub(ended).unwrap_or(now);
+                (saved.user, Timeout { until, x: saved.x })
+            })
+            .collect();
+        Self { users }
+    }
+
+    pub(crate) fn saved(&self) -> Vec<SavedTimeout> {
+        let (now, unix) = (Instant::now(), unix_now());
+        self.users
+            .iter()
+            .map(|(user, timeout)| SavedTimeout {
+                user: user.clone(),
+                until: unix + timeout.until.saturating_duration_since(now).as_secs(),
+                x: timeout.x,
+            })
+            .collect()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+
+    /// Whether a user's messages are dropped for now. Only Twitch users are timed out, their
+    /// AppState key is their name.
+    pub(crate) fn suppressed(&self, key: &str, now: Instant) -> bool {
+        self.users.get(&key.to_lowercase()).is_some_and(|timeout| now < timeout.until)
+    }
+
+    /// Where a user's minawan should come back, when their timeout ended less than
+    /// RETURN_GRACE_SECS ago. They're only brought back there once.
+    pub(crate) fn returning(&mut self, key: &str, now: Instant, config: &Config) -> Option<f32> {
+        let timeout = self.users.remove(&key.to_lowercase())?;
+        timeout.x.filter(|_| now.duration_since(timeout.until) <= config.moderation_return_grace)
+    }
+
+    /// Drop the ones whose grace period is over
+    fn prune(&mut self, now: Instant, config: &Config) {
+        self.users.retain(|_, timeout| {
+            now < timeout.until || now.duration_since(timeout.until) <= config.moderation_return_grace
+        });
+    }
+}
+
+/// A minawan back from a timeout, shaking itself off
+#[derive(Component)]
+pub(crate) struct DustingOff {
+    started: Instant,
+}
+
+/// Dust flying off a returning minawan
+#[derive(Component)]
+pub(crate) struct DustPuff {
+    started: Instant,
+    origin: Vec2,
+    velocity: Vec2,
+}
+
+/// Put a minawan back where it stood before its timeout, shaking off a cloud of dust
+pub(crate) fn return_to_spot(commands: &mut Commands, entity: Entity, x: f32, rect: Rect, config: &Config) {
+    let now = Instant::now();
+    let x = clamp_to_ranges(x, &config.walk_ranges(rect.max.x));
+    let y = config.floor_y(rect.max.y);
+    let feet = Vec2::new(x, y - FEET_DROP);
+    commands
+        .entity(entity)
+        .insert((Transform::from_xyz(x, y, 0.0), DustingOff { started: now }));
+    for puff in 0..DUST_PUFFS {
+        // Fanned out over the upper half, so the dust rises off the floor
+        let angle = TAU / 2.0 * (puff as f32 + 0.5) / DUST_PUFFS as f32;
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: DUST_COLOR,
+                    custom_size: Some(Vec2::splat(DUST_SIZE)),
+                    ..default()
+                },
+                transform: Transform::from_translation(feet.extend(1.0)),
+                ..default()
+            },
+            BaseAlpha(DUST_COLOR.alpha()),
+            DustPuff {
+                started: now,
+                origin: feet,
+                velocity: Vec2::from_angle(angle) * DUST_SPEED,
+            },
+        ));
+    }
+}
+
+// Take timed out and banned chatters' minawan off the party, and forget everything kept about
+// banned ones
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn handle_moderation(
+    mut commands: Commands,
+    mut receiver: ResMut<ModerationReceiver>,
+    mut app_state: ResMut<AppState>,
+    mut pools: ResMut<EntityPools>,
+    config: Res<Config>,
+    mut streaks: ResMut<ChatStreaks>,
+    mut accessories: ResMut<Accessories>,
+    mut sizes: ResMut<AvatarSizes>,
+    mut greetings: ResMut<Greetings>,
+    mut restore: ResMut<PendingRestore>,
+    avatar_query: Query<&Transform, With<UserMarker>>,
+    mut api_events: EventWriter<ApiEvent>,
+) {
+    let now = Instant::now();
+    app_state.timeouts.prune(now, &config);
+    while let Ok(notice) = receiver.receiver.try_recv() {
+        if !config.moderation_enabled {
+            continue;
+        }
+        let login = match &notice {
+            ModerationNotice::TimedOut { user, .. } | ModerationNotice::Banned { user } => user.to_lowercase(),
+        };
+        // Twitch users are keyed by their display name, which only differs from the login in case
+        let key = app_state.active_users.keys().find(|key| key.eq_ignore_ascii_case(&login)).cloned();
+        let mut x = restore.take(&login);
+        if let Some(user) = key.as_ref().and_then(|key| app_state.active_users.remove(key)) {
+            x = avatar_query.get(user.entity).ok().map(|transform| transform.translation.x);
+            pools.release_avatar(&mut commands, user.entity, &config);
+        }
+        if let Some(key) = key {
+            api_events.send(ApiEvent::UserDespawned { user: key });
+        }
+        app_state.spawn_queue.forget(&login);
+        match notice {
+            ModerationNotice::TimedOut { duration, .. } => {
+                info!("{} was timed out for {} seconds, removing their minawan", login, duration.as_secs());
+                // A longer timeout replaces a shorter one, where they stood is kept
+                let x = x.or_else(|| app_state.timeouts.users.get(&login).and_then(|timeout| timeout.x));
+                app_state.timeouts.users.insert(login, Timeout { until: now + duration, x });
+            }
+            ModerationNotice::Banned { .. } => {
+                info!("{} was banned, removing their minawan and forgetting them", login);
+                app_state.timeouts.users.remove(&login);
+                app_state.message_counts.forget(&login);
+                app_state.bit_totals.forget(&login);
+                streaks.forget(&login);
+                accessories.forget(&login);
+                sizes.forget(&login);
+                greetings.forget(&login);
+            }
+        }
+    }
+}
+
+// Shake returning minawan from side to side and blow their dust away
+pub(crate) fn animate_dust_offs(
+    mut commands: Commands,
+    mut avatar_query: Query<(Entity, &mut Transform, &DustingOff)>,
+    mut puff_query: Query<(Entity, &mut Transform, &mut BaseAlpha, &DustPuff), Without<DustingOff>>,
+) {
+    for (entity, mut transform, dusting) in avatar_query.iter_mut() {
+        let elapsed = dusting.started.elapsed().as_secs_f32();
+        let progress = elapsed / DUST_OFF_DURATION.as_secs_f32();
+        if progress >= 1.0 {
+            transform.rotation = Quat::IDENTITY;
+            commands.entity(entity).remove::<DustingOff>();
+            continue;
+        }
+        let shake = (elapsed * SHAKES_PER_SEC * TAU).sin() * SHAKE_ANGLE * (1.0 - progress);
+        transform.rotation = Quat::from_rotation_z(shake);
+    }
+    for (entity, mut transform, mut alpha, puff) in puff_query.iter_mut() {
+        let elapsed = puff.started.elapsed().as_secs_f32();
+        let progress = elapsed / DUST_OFF_DURATION.as_secs_f32();
+        if progress >= 1.0 {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        // Slows down as it spreads
+        let position = puff.origin + puff.velocity * elapsed * (1.0 - progress / 2.0);
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+        alpha.0 = DUST_COLOR.alpha() * (1.0 - progress);
+    }
+}
@@ -0,0 +1,141 @@
+use bevy::{
+    ecs::query::Has,
+    math::IVec2,
+    prelude::{Entity, Local, Query, Res, With},
+    window::{Monitor, PrimaryMonitor, PrimaryWindow, Window, WindowPosition},
+};
+use log::{info, warn};
+
+use crate::{
+    config::{Config, StripEdge, WindowGeometry, WindowStrip},
+    state::RuntimeState,
+};
+
+/// Where the window was last placed, it's only moved again when the config asks for somewhere else
+#[derive(Default)]
+pub(crate) struct Placement {
+    target: Option<(Option<String>, Option<WindowGeometry>, Option<WindowStrip>)>,
+    /// Maximizing waits a frame after a move so the window fills its new monitor, not the old one
+    maximize_next_frame: bool,
+}
+
+fn monitor_name(monitor: &Monitor) -> &str {
+    monitor.name.as_deref().unwrap_or("unnamed")
+}
+
+/// Pick the configured monitor by index or part of its name, falling back to the primary monitor
+pub(crate) fn select_monitor<'a>(monitors: &[(Entity, &'a Monitor, bool)], target: Option<&str>) -> &'a Monitor {
+    let primary = monitors
+        .iter()
+        .find(|(_, _, primary)| *primary)
+        .unwrap_or(&monitors[0])
+        .1;
+    let Some(target) = target else {
+        return primary;
+    };
+
+    let found = match target.parse::<usize>() {
+        Ok(index) => monitors.get(index),
+        Err(_) => monitors.iter().find(|(_, monitor, _)| {
+            monitor_name(monitor)
+                .to_lowercase()
+                .contains(&target.to_lowercase())
+        }),
+    };
+    match found {
+        Some((_, monitor, _)) => monitor,
+        None => {
+            let available: Vec<String> = monitors
+                .iter()
+                .enumerate()
+                .map(|(index, (_, monitor, _))| format!("{}: {}", index, monitor_name(monitor)))
+                .collect();
+            warn!(
+                "Monitor {} not found, using the primary monitor. Available monitors are {}",
+                target,
+                available.join(", ")
+            );
+            primary
+        }
+    }
+}
+
+// Move the overlay to the configured monitor, either maximized, as a strip along one edge or at an
+// explicit position and size. Runs again whenever TARGET_MONITOR or the window geometry is changed in the config.
+// On startup the window goes back to where it was last time, if that's still possible.
+pub(crate) fn place_window(
+    config: Res<Config>,
+    saved: Res<RuntimeState>,
+    monitors: Query<(Entity, &Monitor, Has<PrimaryMonitor>)>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+    mut placement: Local<Placement>,
+) {
+    let Ok(mut window) = windows.get_single_mut() else {
+        return;
+    };
+    if placement.maximize_next_frame {
+        placement.maximize_next_frame = false;
+        window.set_maximized(true);
+        return;
+    }
+
+    let target = (config.target_monitor.clone(), config.window_geometry, config.window_strip);
+    if placement.target.as_ref() == Some(&target) {
+        return;
+    }
+    // Monitors are listed by winit shortly after startup
+    let mut monitors: Vec<(Entity, &Monitor, bool)> = monitors.iter().collect();
+    if monitors.is_empty() {
+        return;
+    }
+    // Keep indexes in the order winit listed the monitors
+    monitors.sort_by_key(|(entity, _, _)| *entity);
+
+    if placement.target.is_none() {
+        let plugged_in: Vec<&Monitor> = monitors.iter().map(|(_, monitor, _)| *monitor).collect();
+        if let Some(saved) = saved.window.as_ref().filter(|saved| saved.usable(&config, &plugged_in)) {
+            window.position = WindowPosition::At(IVec2::new(saved.x, saved.y));
+            window.resolution.set_physical_resolution(saved.width, saved.height);
+            info!("Restored overlay to monitor {} from the last run", saved.monitor);
+            placement.target = Some(target);
+            return;
+        }
+    }
+
+    let monitor = select_monitor(&monitors, config.target_monitor.as_deref());
+    let origin = monitor.physical_position;
+    // A maximized window can't be moved to another monitor
+    window.set_maximized(false);
+    match config.window_geometry {
+        Some(geometry) => {
+            window.position = WindowPosition::At(origin + IVec2::new(geometry.x, geometry.y));
+            window
+                .resolution
+                .set_physical_resolution(geometry.width, geometry.height);
+        }
+        None => match config.window_strip {
+            Some(strip) => {
+                let height = strip.height.min(monitor.physical_height);
+                let y = match strip.edge {
+                    StripEdge::Top => 0,
+                    StripEdge::Bottom => (monitor.physical_height - height) as i32,
+                };
+                window.position = WindowPosition::At(origin + IVec2::new(0, y));
+                window
+                    .resolution
+                    .set_physical_resolution(monitor.physical_width, height);
+            }
+            None => {
+                window.position = WindowPosition::At(origin);
+                placement.maximize_next_frame = true;
+            }
+        },
+    }
+    info!(
+        "Placing overlay on monitor {} ({}x{})",
+        monitor_name(monitor),
+        monitor.physical_width,
+        monitor.physical_height
+    );
+    placement.target = Some(target);
+}
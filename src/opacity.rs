@@ -0,0 +1,79 @@
+use bevy::{
+    color::Alpha,
+    prelude::{Commands, Component, Entity, Has, Local, Mut, Query, Ref, Res, Without},
+    sprite::Sprite,
+    text::Text,
+    ui::Node,
+};
+
+use crate::{config::Config, pool::Pooled, stage_props::BehindProp};
+
+/// Lowest and highest values for OPACITY
+pub(crate) const MIN_OPACITY: f32 = 0.1;
+pub(crate) const MAX_OPACITY: f32 = 1.0;
+/// How much the opacity hotkeys change it by
+pub(crate) const OPACITY_STEP: f32 = 0.1;
+
+/// An element's own alpha before OPACITY is applied. Anything fading an element in or out
+/// should change this rather than the sprite or text colour, so the two multiply.
+#[derive(Component)]
+pub(crate) struct BaseAlpha(pub(crate) f32);
+
+/// Fade an element to `alpha`, with OPACITY still applied on top. Elements spawned without a
+/// BaseAlpha get theirs the frame after, until then there's nothing to fade.
+pub(crate) fn fade(base: Option<Mut<BaseAlpha>>, alpha: f32) {
+    if let Some(mut base) = base.filter(|base| base.0 != alpha) {
+        base.0 = alpha;
+    }
+}
+
+fn set_text_alpha(text: &mut Text, alpha: f32) {
+    for section in text.sections.iter_mut() {
+        section.style.color.set_alpha(alpha);
+    }
+}
+
+// Multiply avatars, emotes and messages by OPACITY, and minawan behind a stage prop by
+// BEHIND_OPACITY too. UI such as widgets and toasts is left alone.
+pub(crate) fn apply_opacity(
+    mut commands: Commands,
+    config: Res<Config>,
+    mut last_opacity: Local<Option<(f32, f32)>>,
+    mut new_sprites: Query<(Entity, &mut Sprite), (Without<BaseAlpha>, Without<Pooled>)>,
+    mut new_texts: Query<(Entity, &mut Text), (Without<BaseAlpha>, Without<Node>)>,
+    mut sprites: Query<(&mut Sprite, Ref<BaseAlpha>, Has<BehindProp>)>,
+    mut texts: Query<(&mut Text, Ref<BaseAlpha>, Has<BehindProp>), Without<Node>>,
+) {
+    let opacity = config.opacity;
+    let behind_opacity = config.stage_prop_behind_opacity;
+    let opacity_for = |behind: bool| if behind { opacity * behind_opacity } else { opacity };
+
+    // Remember the alpha new elements were spawned with, pooled ones once they're reused
+    for (entity, mut sprite) in new_sprites.iter_mut() {
+        let base = sprite.color.alpha();
+        sprite.color.set_alpha(base * opacity);
+        commands.entity(entity).insert(BaseAlpha(base));
+    }
+    for (entity, mut text) in new_texts.iter_mut() {
+        let base = text
+            .sections
+            .first()
+            .map(|section| section.style.color.alpha())
+            .unwrap_or(1.0);
+        set_text_alpha(&mut text, base * opacity);
+        commands.entity(entity).insert(BaseAlpha(base));
+    }
+
+    let opacity_changed = *last_opacity != Some((opacity, behind_opacity));
+    *last_opacity = Some((opacity, behind_opacity));
+    for (mut sprite, base, behind) in sprites.iter_mut() {
+        if opacity_changed || base.is_changed() {
+            sprite.color.set_alpha(base.0 * opacity_for(behind));
+        }
+    }
+    for (mut text, base, behind) in texts.iter_mut() {
+        if opacity_changed || base.is_changed() {
+            set_text_alpha(&mut text, base.0 * opacity_for(behind));
+        }
+    }
+}
@@ -0,0 +1,410 @@
+use std::{
+    ffi::{c_char, c_int, c_void, CString},
+    thread,
+};
+
+use bevy::{
+    app::{App, Plugin, Startup, Update},
+    asset::{Assets, Handle},
+    color::Color,
+    math::Vec2,
+    prelude::{
+        default, Camera, Camera2dBundle, ClearColorConfig, Commands, Component, IntoSystemConfigs, Local,
+        OrthographicProjection, Query, Res, ResMut, Resource, Transform, With, Without, World,
+    },
+    render::{
+        camera::{RenderTarget, ScalingMode},
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_asset::{RenderAssetUsages, RenderAssets},
+        render_graph::{Node, NodeRunError, RenderGraph, RenderGraphContext, RenderLabel},
+        render_resource::{
+            Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Extent3d, ImageCopyBuffer,
+            ImageDataLayout, Maintain, MapMode, TextureDimension, TextureFormat, TextureUsages,
+        },
+        renderer::{RenderContext, RenderDevice, RenderQueue},
+        texture::{GpuImage, Image},
+        Render, RenderApp, RenderSet,
+    },
+    window::{PrimaryWindow, Window},
+};
+use libloading::Library;
+use log::{error, info, warn};
+use tokio::sync::mpsc;
+
+use crate::{
+    config::{Config, OutputBackend},
+    OverlayCamera,
+};
+
+/// Frame rate reported to NDI receivers, frames are really sent as fast as the overlay renders
+const NDI_FRAME_RATE: c_int = 60;
+
+/// Renders the party into a texture as well as the window and sends every frame to OBS
+/// through the OUTPUT backend. Does nothing when OUTPUT is none.
+pub(crate) struct FrameOutputPlugin;
+
+impl Plugin for FrameOutputPlugin {
+    fn build(&self, app: &mut App) {
+        let config = app.world().resource::<Config>();
+        let sender = match config.output {
+            OutputBackend::None => return,
+            OutputBackend::Ndi => match NdiSender::new(&config.output_name) {
+                Ok(ndi) => start_output(ndi),
+                Err(err) => {
+                    error!("NDI output isn't available: {}", err);
+                    return;
+                }
+            },
+        };
+        info!(
+            "Sending the overlay to NDI as {} at {}x{}",
+            config.output_name, config.output_width, config.output_height
+        );
+
+        app.add_plugins(ExtractResourcePlugin::<FrameCopier>::default())
+            .add_systems(Startup, setup_output_camera)
+            .add_systems(Update, sync_output_camera);
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app
+            .insert_resource(sender)
+            .add_systems(Render, send_frame.after(RenderSet::Render));
+        let mut graph = render_app.world_mut().resource_mut::<RenderGraph>();
+        graph.add_node(FrameCopy, FrameCopyNode);
+        graph.add_node_edge(bevy::render::graph::CameraDriverLabel, FrameCopy);
+    }
+}
+
+/// Marker for the camera rendering the party into the output texture
+#[derive(Component)]
+pub(crate) struct OutputCamera {}
+
+/// The output texture and the buffer it's copied into so the CPU can read it
+#[derive(Resource, Clone, ExtractResource)]
+struct FrameCopier {
+    image: Handle<Image>,
+    buffer: Buffer,
+    width: u32,
+    height: u32,
+    /// Rows are padded to the alignment wgpu requires for copies
+    bytes_per_row: u32,
+}
+
+/// One rendered frame, RGBA with premultiplied alpha
+struct Frame {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    bytes_per_row: u32,
+}
+
+/// Hands frames from the render world to the output thread
+#[derive(Resource)]
+struct FrameSender {
+    sender: mpsc::Sender<Frame>,
+}
+
+trait FrameOutput: Send + 'static {
+    fn send(&mut self, frame: &Frame);
+}
+
+/// Start the thread that sends frames on. Only one frame waits at a time, if the backend
+/// falls behind the newest frames are dropped rather than piling up.
+fn start_output(mut output: impl FrameOutput) -> FrameSender {
+    let (sender, mut receiver) = mpsc::channel::<Frame>(1);
+    thread::spawn(move || {
+        while let Some(mut frame) = receiver.blocking_recv() {
+            unpremultiply(&mut frame.data);
+            output.send(&frame);
+        }
+    });
+    FrameSender { sender }
+}
+
+/// Blending leaves colours multiplied by their alpha, receivers expect them straight
+pub(crate) fn unpremultiply(data: &mut [u8]) {
+    for pixel in data.chunks_exact_mut(4) {
+        let alpha = pixel[3] as u32;
+        if alpha == 0 || alpha == 255 {
+            continue;
+        }
+        for channel in &mut pixel[..3] {
+            *channel = (*channel as u32 * 255 / alpha).min(255) as u8;
+        }
+    }
+}
+
+fn setup_output_camera(
+    mut commands: Commands,
+    config: Res<Config>,
+    render_device: Res<RenderDevice>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let size = Extent3d {
+        width: config.output_width,
+        height: config.output_height,
+        depth_or_array_layers: 1,
+    };
+    let mut image = Image::new_fill(
+        size,
+        TextureDimension::D2,
+        &[0; 4],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    image.texture_descriptor.usage |=
+        TextureUsages::COPY_SRC | TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING;
+    let image = images.add(image);
+
+    let bytes_per_row = RenderDevice::align_copy_bytes_per_row(size.width as usize * 4) as u32;
+    let buffer = render_device.create_buffer(&BufferDescriptor {
+        label: Some("output_frame_buffer"),
+        size: bytes_per_row as u64 * size.height as u64,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    commands.insert_resource(FrameCopier {
+        image: image.clone(),
+        buffer,
+        width: size.width,
+        height: size.height,
+        bytes_per_row,
+    });
+
+    commands.spawn((
+        Camera2dBundle {
+            camera: Camera {
+                target: RenderTarget::Image(image),
+                // Rendered before the window's camera
+                order: -1,
+                clear_color: ClearColorConfig::Custom(Color::NONE),
+                ..default()
+            },
+            ..default()
+        },
+        OutputCamera {},
+    ));
+}
+
+// Show the same area as the window, stretched to the output size
+fn sync_output_camera(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    overlay_cameras: Query<&Transform, (With<OverlayCamera>, Without<OutputCamera>)>,
+    mut output_cameras: Query<(&mut OrthographicProjection, &mut Transform), With<OutputCamera>>,
+    mut window_size: Local<Option<Vec2>>,
+) {
+    let (Ok(window), Ok(overlay_transform)) = (windows.get_single(), overlay_cameras.get_single()) else {
+        return;
+    };
+    let size = Vec2::new(window.width(), window.height());
+    let resized = *window_size != Some(size);
+    *window_size = Some(size);
+    for (mut projection, mut transform) in output_cameras.iter_mut() {
+        if resized {
+            projection.scaling_mode = ScalingMode::Fixed {
+                width: size.x,
+                height: size.y,
+            };
+        }
+        if *transform != *overlay_transform {
+            *transform = *overlay_transform;
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, RenderLabel)]
+struct FrameCopy;
+
+/// Copies the output texture into the readback buffer once the cameras have rendered
+#[derive(Default)]
+struct FrameCopyNode;
+
+impl Node for FrameCopyNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let Some(copier) = world.get_resource::<FrameCopier>() else {
+            return Ok(());
+        };
+        let Some(image) = world.resource::<RenderAssets<GpuImage>>().get(&copier.image) else {
+            return Ok(());
+        };
+
+        let mut encoder = render_context
+            .render_device()
+            .create_command_encoder(&CommandEncoderDescriptor::default());
+        encoder.copy_texture_to_buffer(
+            image.texture.as_image_copy(),
+            ImageCopyBuffer {
+                buffer: &copier.buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(copier.bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            Extent3d {
+                width: copier.width,
+                height: copier.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        world
+            .resource::<RenderQueue>()
+            .submit(std::iter::once(encoder.finish()));
+        Ok(())
+    }
+}
+
+// Read the copied frame back and pass it to the output thread
+fn send_frame(copier: Option<Res<FrameCopier>>, render_device: Res<RenderDevice>, sender: Res<FrameSender>) {
+    let Some(copier) = copier else {
+        return;
+    };
+    // Skip reading it back at all while the last frame is still being sent
+    let Ok(permit) = sender.sender.try_reserve() else {
+        return;
+    };
+
+    let slice = copier.buffer.slice(..);
+    let (tx, rx) = std::sync::mpsc::sync_channel(1);
+    slice.map_async(MapMode::Read, move |result| {
+        let _ = tx.send(result);
+    });
+    let _ = render_device.poll(Maintain::wait());
+    match rx.recv() {
+        Ok(Ok(())) => {
+            let data = slice.get_mapped_range().to_vec();
+            copier.buffer.unmap();
+            permit.send(Frame {
+                data,
+                width: copier.width,
+                height: copier.height,
+                bytes_per_row: copier.bytes_per_row,
+            });
+        }
+        Ok(Err(err)) => warn!("Failed to read back the output frame: {}", err),
+        Err(_) => warn!("Failed to read back the output frame"),
+    }
+}
+
+#[repr(C)]
+struct NdiSendCreate {
+    ndi_name: *const c_char,
+    groups: *const c_char,
+    clock_video: bool,
+    clock_audio: bool,
+}
+
+#[repr(C)]
+struct NdiVideoFrame {
+    xres: c_int,
+    yres: c_int,
+    four_cc: u32,
+    frame_rate_n: c_int,
+    frame_rate_d: c_int,
+    picture_aspect_ratio: f32,
+    frame_format_type: c_int,
+    timecode: i64,
+    data: *const u8,
+    line_stride_in_bytes: c_int,
+    metadata: *const c_char,
+    timestamp: i64,
+}
+
+const NDI_FOURCC_RGBA: u32 = u32::from_le_bytes(*b"RGBA");
+const NDI_FRAME_FORMAT_PROGRESSIVE: c_int = 1;
+/// Asks NDI to fill in the timecode
+const NDI_TIMECODE_SYNTHESIZE: i64 = i64::MAX;
+
+/// Library names the NDI runtime is installed as
+fn ndi_library_paths() -> Vec<String> {
+    if cfg!(windows) {
+        ["NDI_RUNTIME_DIR_V6", "NDI_RUNTIME_DIR_V5"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .map(|dir| format!("{}\\Processing.NDI.Lib.x64.dll", dir))
+            .chain(["Processing.NDI.Lib.x64.dll".to_string()])
+            .collect()
+    } else if cfg!(target_os = "macos") {
+        vec!["/usr/local/lib/libndi.dylib".to_string(), "libndi.dylib".to_string()]
+    } else {
+        vec!["libndi.so.6".to_string(), "libndi.so.5".to_string(), "libndi.so".to_string()]
+    }
+}
+
+/// An NDI source, using the NDI runtime loaded when the overlay starts
+struct NdiSender {
+    instance: *mut c_void,
+    send_video: unsafe extern "C" fn(*mut c_void, *const NdiVideoFrame),
+    /// Keeps the functions above loaded
+    _library: Library,
+}
+
+// The NDI instance may be used from any thread, just not from two at once
+unsafe impl Send for NdiSender {}
+
+impl NdiSender {
+    fn new(name: &str) -> Result<Self, String> {
+        let library = ndi_library_paths()
+            .iter()
+            .find_map(|path| unsafe { Library::new(path) }.ok())
+            .ok_or("the NDI runtime isn't installed, get it from ndi.video")?;
+        let name = CString::new(name).map_err(|err| err.to_string())?;
+
+        unsafe {
+            let initialize = *library
+                .get::<unsafe extern "C" fn() -> bool>(b"NDIlib_initialize\0")
+                .map_err(|err| err.to_string())?;
+            let send_create = *library
+                .get::<unsafe extern "C" fn(*const NdiSendCreate) -> *mut c_void>(b"NDIlib_send_create\0")
+                .map_err(|err| err.to_string())?;
+            let send_video = *library
+                .get::<unsafe extern "C" fn(*mut c_void, *const NdiVideoFrame)>(b"NDIlib_send_send_video_v2\0")
+                .map_err(|err| err.to_string())?;
+
+            if !initialize() {
+                return Err("this CPU isn't supported by NDI".to_string());
+            }
+            // NDI copies the name, it doesn't need to outlive this call
+            let instance = send_create(&NdiSendCreate {
+                ndi_name: name.as_ptr(),
+                groups: std::ptr::null(),
+                clock_video: false,
+                clock_audio: false,
+            });
+            if instance.is_null() {
+                return Err("failed to create the NDI source".to_string());
+            }
+            Ok(Self {
+                instance,
+                send_video,
+                _library: library,
+            })
+        }
+    }
+}
+
+impl FrameOutput for NdiSender {
+    fn send(&mut self, frame: &Frame) {
+        let video = NdiVideoFrame {
+            xres: frame.width as c_int,
+            yres: frame.height as c_int,
+            four_cc: NDI_FOURCC_RGBA,
+            frame_rate_n: NDI_FRAME_RATE,
+            frame_rate_d: 1,
+            picture_aspect_ratio: frame.width as f32 / frame.height as f32,
+            frame_format_type: NDI_FRAME_FORMAT_PROGRESSIVE,
+            timecode: NDI_TIMECODE_SYNTHESIZE,
+            data: frame.data.as_ptr(),
+            line_stride_in_bytes: frame.bytes_per_row as c_int,
+            metadata: std::ptr::null(),
+            timestamp: 0,
+        };
+        // Sending without _async copies the frame before returning
+        unsafe { (self.send_video)(self.instance, &video) };
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+};
+
+use log::{info, warn};
+
+/// Name of the folder in the platform config and data dirs the overlay's files live in
+pub(crate) const APP_DIR: &str = "minawan-watch-party";
+/// Put next to the executable to keep everything in the executable's folder, like `--portable`
+const PORTABLE_MARKER: &str = "portable.marker";
+const CONFIG_FILE: &str = "config.toml";
+const ASSETS_DIR: &str = "assets";
+
+static PORTABLE_FLAG: AtomicBool = AtomicBool::new(false);
+static PORTABLE_MARKER_FOUND: OnceLock<bool> = OnceLock::new();
+/// Where tests keep what the overlay saves instead of the data folder
+static DATA_DIR_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Folder the executable is in
+fn exe_dir() -> Option<PathBuf> {
+    env::current_exe().ok()?.parent().map(Path::to_path_buf)
+}
+
+fn marker_found() -> bool {
+    *PORTABLE_MARKER_FOUND.get_or_init(|| exe_dir().is_some_and(|dir| dir.join(PORTABLE_MARKER).exists()))
+}
+
+/// Turn on portable mode for `--portable`, before any of the overlay's files are read
+pub(crate) fn set_portable(portable: bool) {
+    if portable {
+        PORTABLE_FLAG.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Keep everything the overlay saves in `dir` instead of the data folder, for tests. Only the
+/// first call counts.
+pub(crate) fn set_data_dir(dir: PathBuf) {
+    let _ = DATA_DIR_OVERRIDE.set(dir);
+}
+
+/// Whether config.toml and everything the overlay writes are kept next to the executable
+pub(crate) fn portable() -> bool {
+    PORTABLE_FLAG.load(Ordering::Relaxed) || marker_found()
+}
+
+/// `dir` from the platform dirs with the overlay's own folder in it, or the executable's folder
+/// in portable mode. Falls back to the working directory when neither is known.
+fn app_dir(portable: bool, dir: Option<PathBuf>) -> PathBuf {
+    let dir = if portable {
+        exe_dir()
+    } else {
+        dir.map(|dir| dir.join(APP_DIR))
+    };
+    dir.unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Where the overlay keeps what it saves, its log and crash reports: `%LOCALAPPDATA%` on
+/// Windows, `~/.local/share` on Linux
+pub(crate) fn data_dir() -> PathBuf {
+    match DATA_DIR_OVERRIDE.get() {
+        Some(dir) => dir.clone(),
+        None => app_dir(portable(), dirs::data_local_dir()),
+    }
+}
+
+/// A path from the config relative to the data folder, absolute ones are kept as they are
+pub(crate) fn in_data_dir(path: &str) -> PathBuf {
+    data_dir().join(path)
+}
+
+/// The assets folder that ships with the overlay, found the way Bevy's asset server finds it:
+/// in `BEVY_ASSET_ROOT`, in the crate under `cargo run`, otherwise next to the executable. The
+/// same in portable mode, the working directory doesn't matter.
+pub(crate) fn assets_dir() -> PathBuf {
+    env::var_os("BEVY_ASSET_ROOT")
+        .or_else(|| env::var_os("CARGO_MANIFEST_DIR"))
+        .map(PathBuf::from)
+        .or_else(exe_dir)
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(ASSETS_DIR)
+}
+
+/// A path relative to the assets folder, as AVATAR_URL and the other settings name files
+pub(crate) fn in_assets_dir(path: impl AsRef<Path>) -> PathBuf {
+    assets_dir().join(path)
+}
+
+/// config.toml when `--config` isn't given, in `%APPDATA%` on Windows and `~/.config` on Linux.
+/// `portable` is whether `--portable` was given.
+pub(crate) fn default_config_path(portable: bool) -> String {
+    app_dir(portable || marker_found(), dirs::config_dir())
+        .join(CONFIG_FILE)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Move a file, copying it when it's on another drive
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(dir) = to.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to)?;
+    fs::remove_file(from)
+}
+
+/// Copy a file, making the folder it goes in
+fn copy_file(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(dir) = to.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::copy(from, to).map(|_| ())
+}
+
+/// Copy a config.toml or config.ini left in the working directory by an older version to the
+/// config folder, unless there's already a config there. The original stays where it is, it
+/// may be the one checked in next to the source.
+pub(crate) fn migrate_working_dir_config(config_path: &str) {
+    let target = Path::new(config_path);
+    let Some(target_dir) = target.parent() else {
+        return;
+    };
+    // Started from the config folder itself, there's nothing to move
+    let started_there = fs::canonicalize(".").ok() == fs::canonicalize(target_dir).ok();
+    if started_there || target.with_extension("toml").exists() || target.with_extension("ini").exists() {
+        return;
+    }
+    for extension in ["toml", "ini"] {
+        let old = Path::new(CONFIG_FILE).with_extension(extension);
+        if !old.exists() {
+            continue;
+        }
+        let new = target.with_extension(extension);
+        match copy_file(&old, &new) {
+            Ok(()) => info!(
+                "Copied {} from the working directory to {}, edit it there from now on",
+                old.display(),
+                new.display()
+            ),
+            Err(err) => warn!("Failed to copy {} to {}: {}", old.display(), new.display(), err),
+        }
+        return;
+    }
+}
+
+/// Move one of the overlay's own files from where older versions kept it, next to config.toml
+/// in the platform config folder, to the data folder
+pub(crate) fn migrate_data_file(file: &str) {
+    let new = data_dir().join(file);
+    let Some(old) = dirs::config_dir().map(|dir| dir.join(APP_DIR).join(file)) else {
+        return;
+    };
+    if portable() || DATA_DIR_OVERRIDE.get().is_some() || new.exists() || !old.exists() || old == new {
+        return;
+    }
+    match move_file(&old, &new) {
+        Ok(()) => info!("Moved {} to {}", old.display(), new.display()),
+        Err(err) => warn!("Failed to move {} to {}: {}", old.display(), new.display(), err),
+    }
+}
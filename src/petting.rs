@@ -0,0 +1,235 @@
+use std::time::Instant;
+
+use bevy::{
+    color::Color,
+    hierarchy::{BuildChildren, Children, DespawnRecursiveExt},
+    input::{mouse::MouseButton, ButtonInput},
+    math::{Vec2, Vec3},
+    prelude::{
+        default, Camera, Commands, Component, Entity, GlobalTransform, Has, Local, NodeBundle, Query, Res,
+        SpatialBundle, Transform, Visibility, With, Without,
+    },
+    sprite::{Sprite, SpriteBundle},
+    ui::{BorderColor, PositionType, Style, UiRect, Val, ZIndex},
+    window::{CursorIcon, PrimaryWindow, Window},
+};
+use log::info;
+use rand::Rng;
+
+use crate::{
+    config::Config,
+    locale::t,
+    logging::notify,
+    lurkers::Ghost,
+    opacity::{fade, BaseAlpha},
+    race::Racing,
+    sizes::{avatar_size, feet_y, head_y, SizeClass},
+    OverlayCamera, UserDetails, UserMarker, Wave,
+};
+
+const BORDER_WIDTH: f32 = 2.0;
+const BORDER_COLOR: Color = Color::srgba(1.0, 0.45, 0.7, 0.8);
+const HEARTS_PER_PET: usize = 3;
+/// How long a heart floats up and fades
+const HEART_SECS: f32 = 1.2;
+const HEART_RISE: f32 = 60.0;
+/// Side of one pixel of a heart
+const HEART_PIXEL: f32 = 2.5;
+const HEART_COLOR: Color = Color::srgb(1.0, 0.3, 0.45);
+/// A pixel heart seven wide, as runs of (first column, length) from the top row down
+const HEART_ROWS: [&[(u8, u8)]; 6] = [&[(1, 2), (4, 2)], &[(0, 7)], &[(0, 7)], &[(1, 5)], &[(2, 3)], &[(3, 1)]];
+
+/// Thin frame around the overlay shown while it accepts clicks
+#[derive(Component)]
+pub(crate) struct InteractionBorder {}
+
+/// A heart floating up from a petted minawan
+#[derive(Component)]
+pub(crate) struct Heart {
+    started: Instant,
+    base: Vec3,
+    /// Sideways drift over its whole rise
+    drift: f32,
+}
+
+pub(crate) fn setup_interaction_border(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                border: UiRect::all(Val::Px(BORDER_WIDTH)),
+                ..default()
+            },
+            border_color: BorderColor(BORDER_COLOR),
+            visibility: Visibility::Hidden,
+            z_index: ZIndex::Global(i32::MAX),
+            ..default()
+        },
+        InteractionBorder {},
+    ));
+}
+
+/// The minawan under the cursor, the one in front when they overlap
+fn avatar_at<'a>(
+    point: Vec2,
+    avatars: impl Iterator<Item = (Entity, &'a GlobalTransform, &'a Sprite, Option<&'a SizeClass>)>,
+) -> Option<Entity> {
+    avatars
+        .filter(|(_, transform, sprite, size_class)| {
+            let local = transform.affine().inverse().transform_point3(point.extend(0.0));
+            let size = avatar_size(sprite, *size_class);
+            // Standing on their feet, bigger minawan reach higher
+            let (feet, head) = (feet_y(sprite, size), head_y(sprite, size));
+            local.x.abs() <= size.x / 2.0 && local.y >= feet && local.y <= head
+        })
+        .max_by(|(_, a, ..), (_, b, ..)| a.translation().z.total_cmp(&b.translation().z))
+        .map(|(entity, ..)| entity)
+}
+
+pub(crate) fn spawn_heart(commands: &mut Commands, base: Vec3, drift: f32) {
+    let width = 7.0 * HEART_PIXEL;
+    commands
+        .spawn((
+            SpatialBundle::from_transform(Transform::from_translation(base)),
+            Heart {
+                started: Instant::now(),
+                base,
+                drift,
+            },
+        ))
+        .with_children(|heart| {
+            for (row, runs) in HEART_ROWS.iter().enumerate() {
+                for (column, length) in runs.iter() {
+                    let run = *length as f32 * HEART_PIXEL;
+                    heart.spawn((
+                        SpriteBundle {
+                            sprite: Sprite {
+                                color: HEART_COLOR,
+                                custom_size: Some(Vec2::new(run, HEART_PIXEL)),
+                                ..default()
+                            },
+                            transform: Transform::from_xyz(
+                                *column as f32 * HEART_PIXEL + run / 2.0 - width / 2.0,
+                                -(row as f32) * HEART_PIXEL,
+                                0.0,
+                            ),
+                            ..default()
+                        },
+                        BaseAlpha(1.0),
+                    ));
+                }
+            }
+        });
+}
+
+// While the overlay accepts clicks, frame it and pet the minawan that get clicked. With no
+// mouse movement for IDLE_SECS it goes back to click-through.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub(crate) fn pet_minawan(
+    mut commands: Commands,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    config: Res<Config>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<OverlayCamera>>,
+    avatar_query: Query<
+        (Entity, &GlobalTransform, &Sprite, &UserDetails, Has<Wave>, Has<Racing>, Option<&SizeClass>),
+        (With<UserMarker>, Without<Ghost>),
+    >,
+    mut border_query: Query<&mut Visibility, With<InteractionBorder>>,
+    mut last_cursor: Local<Option<Vec2>>,
+    mut last_activity: Local<Option<Instant>>,
+) {
+    let Ok(mut window) = windows.get_single_mut() else {
+        return;
+    };
+    let interactive = window.cursor.hit_test;
+    for mut visibility in border_query.iter_mut() {
+        let wanted = if interactive { Visibility::Inherited } else { Visibility::Hidden };
+        if *visibility != wanted {
+            *visibility = wanted;
+        }
+    }
+    if !interactive {
+        *last_activity = None;
+        return;
+    }
+
+    let now = Instant::now();
+    let cursor = window.cursor_position();
+    if last_activity.is_none() || cursor != *last_cursor || mouse.get_just_pressed().next().is_some() {
+        *last_activity = Some(now);
+    }
+    *last_cursor = cursor;
+    let idle = last_activity.is_some_and(|last| now.duration_since(last) >= config.petting_idle);
+    if idle && !config.petting_idle.is_zero() {
+        info!("No mouse movement for a while, the overlay is click-through again");
+        window.cursor.hit_test = false;
+        window.cursor.icon = CursorIcon::Default;
+        return;
+    }
+
+    let point = cursor.zip(camera_query.get_single().ok()).and_then(|(cursor, (camera, transform))| {
+        camera.viewport_to_world_2d(transform, cursor)
+    });
+    // Only chatters' own minawan, ghosts can't be petted
+    let pettable = avatar_query
+        .iter()
+        .map(|(entity, transform, sprite, _, _, _, size_class)| (entity, transform, sprite, size_class));
+    let hovered = if config.petting_enabled {
+        point.and_then(|point| avatar_at(point, pettable))
+    } else {
+        None
+    };
+    let icon = if hovered.is_some() { CursorIcon::Pointer } else { CursorIcon::Default };
+    if window.cursor.icon != icon {
+        window.cursor.icon = icon;
+    }
+
+    let Some(entity) = hovered.filter(|_| mouse.just_pressed(MouseButton::Left)) else {
+        return;
+    };
+    let Ok((_, transform, sprite, user, waving, racing, size_class)) = avatar_query.get(entity) else {
+        return;
+    };
+    notify(t!("petting.petted", user = user.name));
+    let translation = transform.translation();
+    // Racers keep their footing, they're busy
+    if !waving && !racing {
+        commands.entity(entity).insert(Wave {
+            started: now,
+            base_y: translation.y,
+        });
+    }
+    let top = translation.y + head_y(sprite, avatar_size(sprite, size_class));
+    let mut rng = rand::thread_rng();
+    for _ in 0..HEARTS_PER_PET {
+        let offset = rng.gen_range(-16.0..16.0);
+        spawn_heart(
+            &mut commands,
+            Vec3::new(translation.x + offset, top, 5.0),
+            rng.gen_range(-20.0..20.0),
+        );
+    }
+}
+
+// Float hearts up while they fade, and clear them away at the top
+pub(crate) fn animate_hearts(
+    mut commands: Commands,
+    mut heart_query: Query<(Entity, &mut Transform, &Heart, &Children)>,
+    mut alpha_query: Query<&mut BaseAlpha, Without<Heart>>,
+) {
+    for (entity, mut transform, heart, children) in heart_query.iter_mut() {
+        let progress = heart.started.elapsed().as_secs_f32() / HEART_SECS;
+        if progress >= 1.0 {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        transform.translation = heart.base + Vec3::new(heart.drift * progress, HEART_RISE * progress, 0.0);
+        transform.scale = Vec3::splat(0.6 + 0.4 * progress.min(0.3) / 0.3);
+        for child in children.iter() {
+            fade(alpha_query.get_mut(*child).ok(), 1.0 - progress);
+        }
+    }
+}
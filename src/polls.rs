@@ -0,0 +1,335 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Instant,
+};
+
+use bevy::{
+    asset::AssetServer,
+    color::{Alpha, Color},
+    hierarchy::{BuildChildren, DespawnRecursiveExt},
+    math::{Vec2, Vec3},
+    prelude::{
+        default, Camera, Commands, Component, Entity, EventReader, Query, Res, ResMut, Resource, Transform, With,
+        Without,
+    },
+    sprite::{Anchor, Sprite, SpriteBundle},
+    text::{Text, Text2dBundle, TextStyle},
+};
+use log::info;
+use rand::seq::SliceRandom;
+
+use crate::{
+    boop::Booping,
+    config::Config,
+    eventsub::{EventSubEvent, PollChoice},
+    locale::t,
+    users::range_for,
+    weather::burst_confetti,
+    AppState, OverlayCamera, UserAction, UserActionDetails,
+};
+
+/// How far above the avatars' feet the zones reach
+const ZONE_HEIGHT: f32 = 80.0;
+/// Space left between two zones
+const ZONE_GAP: f32 = 8.0;
+/// Behind the minawan, in front of the progress bar
+const ZONE_Z: f32 = -2.5;
+const ZONE_COLORS: [Color; 4] = [
+    Color::srgb(0.3, 0.6, 1.0),
+    Color::srgb(1.0, 0.5, 0.3),
+    Color::srgb(0.4, 0.9, 0.5),
+    Color::srgb(0.8, 0.4, 1.0),
+];
+const WINNER_COLOR: Color = Color::srgb(1.0, 0.85, 0.2);
+/// How far inside their zone voters stop walking back to it
+const ZONE_MARGIN: f32 = 16.0;
+const CONFETTI_PIECES: usize = 80;
+
+/// The Twitch poll running, and which zone each minawan stands in for it
+#[derive(Resource, Default)]
+pub(crate) struct Poll {
+    title: String,
+    choices: Vec<PollChoice>,
+    /// Voters are a sample of the party sized by the votes, Twitch doesn't say who voted for what
+    voters: HashMap<Entity, usize>,
+    winner: Option<usize>,
+    /// Once the poll has ended its results stay up until then
+    results_until: Option<Instant>,
+}
+
+impl Poll {
+    fn active(&self) -> bool {
+        !self.choices.is_empty()
+    }
+
+    /// Send minawan to the zones in proportion to the votes. Those already in a zone stay
+    /// there unless it has too many, so the party doesn't reshuffle with every vote.
+    fn assign_voters(&mut self, users: &[Entity]) {
+        let present: HashSet<&Entity> = users.iter().collect();
+        let zones = self.choices.len();
+        self.voters.retain(|entity, zone| present.contains(entity) && *zone < zones);
+        let total: u32 = self.choices.iter().map(|choice| choice.votes).sum();
+        if total == 0 {
+            self.voters.clear();
+            return;
+        }
+
+        let quotas: Vec<usize> = self
+            .choices
+            .iter()
+            .map(|choice| (users.len() as f32 * choice.votes as f32 / total as f32).round() as usize)
+            .collect();
+        for (zone, quota) in quotas.iter().enumerate() {
+            let extra: Vec<Entity> = self
+                .voters
+                .iter()
+                .filter(|(_, voted)| **voted == zone)
+                .map(|(entity, _)| *entity)
+                .skip(*quota)
+                .collect();
+            for entity in extra {
+                self.voters.remove(&entity);
+            }
+        }
+        let mut free: Vec<Entity> = users
+            .iter()
+            .filter(|entity| !self.voters.contains_key(entity))
+            .copied()
+            .collect();
+        free.shuffle(&mut rand::thread_rng());
+        for (zone, quota) in quotas.iter().enumerate() {
+            let have = self.voters.values().filter(|voted| **voted == zone).count();
+            let missing = quota.saturating_sub(have).min(free.len());
+            for entity in free.drain(..missing) {
+                self.voters.insert(entity, zone);
+            }
+        }
+    }
+}
+
+/// The area along the bottom for one of the poll's options
+#[derive(Component)]
+pub(crate) struct PollZone {
+    index: usize,
+}
+
+/// An option's title and votes, over its zone
+#[derive(Component)]
+pub(crate) struct PollZoneLabel {
+    index: usize,
+}
+
+/// World x range of a zone, the walk area is split evenly between the options
+fn zone_bounds(config: &Config, width: f32, zones: usize, index: usize) -> (f32, f32) {
+    let ranges = config.walk_ranges(width);
+    let left = ranges.first().map_or(-width / 2.0, |range| range.0);
+    let right = ranges.last().map_or(width / 2.0, |range| range.1);
+    let zone_width = (right - left) / zones.max(1) as f32;
+    let start = left + zone_width * index as f32;
+    (start, start + zone_width)
+}
+
+// Follow Twitch polls. Votes move minawan into the zones, the end throws confetti over the
+// winner and lets everyone wander off, and a poll that was taken down just goes away.
+pub(crate) fn handle_poll_events(
+    mut commands: Commands,
+    mut eventsub_events: EventReader<EventSubEvent>,
+    mut poll: ResMut<Poll>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+) {
+    for event in eventsub_events.read() {
+        match event {
+            EventSubEvent::PollProgress { title, choices } => {
+                if !config.polls_enabled {
+                    continue;
+                }
+                if !poll.active() || poll.results_until.is_some() || poll.title != *title {
+                    info!("Poll started: {}", title);
+                    *poll = Poll {
+                        title: title.clone(),
+                        ..default()
+                    };
+                }
+                poll.choices = choices.clone();
+                let users: Vec<Entity> = app_state.active_users.values().map(|user| user.entity).collect();
+                poll.assign_voters(&users);
+            }
+            EventSubEvent::PollEnd { cancelled: true, .. } => {
+                if poll.active() {
+                    info!("Poll cancelled: {}", poll.title);
+                }
+                *poll = Poll::default();
+            }
+            EventSubEvent::PollEnd { choices, .. } => {
+                if !config.polls_enabled {
+                    continue;
+                }
+                let best = choices.iter().map(|choice| choice.votes).max().unwrap_or_default();
+                let winner = (best > 0)
+                    .then(|| choices.iter().position(|choice| choice.votes == best))
+                    .flatten();
+                match winner {
+                    Some(winner) => info!("Poll ended, {} won", choices[winner].title),
+                    None => info!("Poll ended without votes"),
+                }
+                poll.choices = choices.clone();
+                poll.winner = winner;
+                poll.voters.clear();
+                poll.results_until = Some(Instant::now() + config.poll_results_time);
+
+                let (Some(winner), Some(rect)) = (winner, camera_query.single().logical_viewport_rect()) else {
+                    continue;
+                };
+                let bounds = zone_bounds(&config, rect.max.x, choices.len(), winner);
+                let floor = config.floor_y(rect.max.y);
+                burst_confetti(
+                    &mut commands,
+                    bounds,
+                    (floor + ZONE_HEIGHT, floor + ZONE_HEIGHT * 3.0),
+                    CONFETTI_PIECES,
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+fn spawn_zone(commands: &mut Commands, index: usize, config: &Config, asset_server: &AssetServer) {
+    commands
+        .spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    // Shown once it's been laid out
+                    color: Color::NONE,
+                    anchor: Anchor::BottomCenter,
+                    ..default()
+                },
+                ..default()
+            },
+            PollZone { index },
+        ))
+        .with_children(|zone| {
+            zone.spawn((
+                Text2dBundle {
+                    text: Text::from_section(
+                        "",
+                        TextStyle {
+                            font: asset_server.load(&config.font_url),
+                            font_size: config.font_size * 0.8,
+                            color: Color::WHITE,
+                        },
+                    ),
+                    text_anchor: Anchor::TopCenter,
+                    ..default()
+                },
+                PollZoneLabel { index },
+            ));
+        });
+}
+
+// Lay the zones out along the bottom of the walk area, following the window size, with each
+// option's votes over it. They're taken down once the results have been up long enough.
+#[allow(clippy::type_complexity)]
+pub(crate) fn update_poll_zones(
+    mut commands: Commands,
+    mut poll: ResMut<Poll>,
+    config: Res<Config>,
+    asset_server: Res<AssetServer>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    mut zone_query: Query<(Entity, &PollZone, &mut Transform, &mut Sprite), Without<PollZoneLabel>>,
+    mut label_query: Query<(&PollZoneLabel, &mut Transform, &mut Text), Without<PollZone>>,
+) {
+    if poll.results_until.is_some_and(|until| Instant::now() >= until) {
+        *poll = Poll::default();
+    }
+    let zones = poll.choices.len();
+    if zone_query.iter().count() != zones {
+        for (entity, _, _, _) in zone_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        for index in 0..zones {
+            spawn_zone(&mut commands, index, &config, &asset_server);
+        }
+        return;
+    }
+    if zones == 0 {
+        return;
+    }
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+
+    let bottom = -rect.max.y / 2.0;
+    let height = config.floor_y(rect.max.y) + ZONE_HEIGHT - bottom;
+    for (_, zone, mut transform, mut sprite) in zone_query.iter_mut() {
+        let (start, end) = zone_bounds(&config, rect.max.x, zones, zone.index);
+        let translation = Vec3::new((start + end) / 2.0, bottom, ZONE_Z);
+        if transform.translation != translation {
+            transform.translation = translation;
+        }
+        let size = Vec2::new((end - start - ZONE_GAP).max(0.0), height);
+        if sprite.custom_size != Some(size) {
+            sprite.custom_size = Some(size);
+        }
+        let color = match poll.winner {
+            Some(winner) if winner == zone.index => WINNER_COLOR.with_alpha(0.35),
+            _ => ZONE_COLORS[zone.index % ZONE_COLORS.len()].with_alpha(0.2),
+        };
+        if sprite.color != color {
+            sprite.color = color;
+        }
+    }
+
+    let total: u32 = poll.choices.iter().map(|choice| choice.votes).sum();
+    for (label, mut transform, mut text) in label_query.iter_mut() {
+        let Some(choice) = poll.choices.get(label.index) else {
+            continue;
+        };
+        transform.translation = Vec3::new(0.0, height - 4.0, 0.1);
+        let percent = if total > 0 { choice.votes * 100 / total } else { 0 };
+        let value = format!("{}\n{} ({}%)", choice.title, t!("poll.votes", count = choice.votes), percent);
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
+
+// Walk voters back into their zone whenever they wander out of it. Zones across a gap in
+// the walk area are met at the nearest end of the voter's own range.
+pub(crate) fn walk_to_poll_zones(
+    poll: Res<Poll>,
+    config: Res<Config>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    mut user_query: Query<(&Transform, &mut UserActionDetails), Without<Booping>>,
+) {
+    if poll.voters.is_empty() {
+        return;
+    }
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+    let ranges = config.walk_ranges(rect.max.x);
+    let now = Instant::now();
+    for (entity, zone) in poll.voters.iter() {
+        let Ok((transform, mut action)) = user_query.get_mut(*entity) else {
+            continue;
+        };
+        let x = transform.translation.x;
+        let (start, end) = zone_bounds(&config, rect.max.x, poll.choices.len(), *zone);
+        let (range_start, range_end) = range_for(x, &ranges);
+        let low = (start + ZONE_MARGIN).clamp(range_start, range_end);
+        let high = (end - ZONE_MARGIN).clamp(range_start, range_end);
+        let towards = if x < low {
+            UserAction::MoveRight
+        } else if x > high {
+            UserAction::MoveLeft
+        } else {
+            continue;
+        };
+        // Keep walking that way rather than letting move_users pick again
+        action.last_action = towards;
+        action.time = now;
+    }
+}
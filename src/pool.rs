@@ -0,0 +1,201 @@
+use bevy::{
+    ecs::system::EntityCommands,
+    hierarchy::BuildWorldChildren,
+    prelude::{
+        Bundle, Commands, Component, DespawnRecursiveExt, Entity, Resource, Visibility, World,
+    },
+    sprite::SpriteBundle,
+};
+
+use crate::config::Config;
+
+/// Marker for a hidden entity waiting in a pool to be reused
+#[derive(Component)]
+pub(crate) struct Pooled {}
+
+/// Hidden entities kept for reuse, with counts of how often reuse worked
+#[derive(Default)]
+pub(crate) struct Pool {
+    free: Vec<Entity>,
+    /// Released, but the command hiding and stripping them hasn't run yet
+    releasing: Vec<Entity>,
+    hits: u64,
+    misses: u64,
+}
+
+impl Pool {
+    /// Reuse a pooled entity for `bundle`, or spawn a new one when the pool is empty
+    fn spawn<'a, B: Bundle>(&mut self, commands: &'a mut Commands, bundle: B) -> EntityCommands<'a> {
+        while let Some(entity) = self.free.pop() {
+            // Anything can despawn a pooled entity, those are skipped
+            if commands.get_entity(entity).is_some() {
+                self.hits += 1;
+                let mut entity_commands = commands.entity(entity);
+                entity_commands.remove::<Pooled>().try_insert(bundle);
+                return entity_commands;
+            }
+        }
+        self.misses += 1;
+        commands.spawn(bundle)
+    }
+
+    /// Share of spawns that reused a pooled entity
+    pub(crate) fn hit_rate(&self) -> f32 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f32 / total as f32
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.free.len()
+    }
+}
+
+/// Pools for the entities that come and go the most, so a busy chat doesn't keep spawning
+/// and despawning them
+#[derive(Resource, Default)]
+pub(crate) struct EntityPools {
+    pub(crate) avatars: Pool,
+    pub(crate) bubbles: Pool,
+    pub(crate) footprints: Pool,
+    pub(crate) rain_drops: Pool,
+}
+
+/// Which of the pools an entity goes back to
+type PoolOf = fn(&mut EntityPools) -> &mut Pool;
+
+impl EntityPools {
+    /// Hide a sprite and keep it in `pool`, or despawn it when the pool already holds `capacity`.
+    /// Children are despawned and every component but the sprite's own is removed. It's only
+    /// free for reuse once that has happened, or a spawn whose commands are applied first would
+    /// get it and have the new bundle stripped straight after.
+    fn release(&mut self, commands: &mut Commands, entity: Entity, capacity: usize, pool: PoolOf) {
+        let kept = pool(self);
+        if kept.free.contains(&entity) || kept.releasing.contains(&entity) {
+            return;
+        }
+        if kept.free.len() + kept.releasing.len() >= capacity {
+            commands.entity(entity).despawn_recursive();
+            return;
+        }
+        kept.releasing.push(entity);
+        commands.add(move |world: &mut World| {
+            // A parent despawned earlier this frame may have taken it along
+            let reset = match world.get_entity_mut(entity) {
+                Some(mut entity) => {
+                    entity
+                        .remove_parent()
+                        .despawn_descendants()
+                        .retain::<SpriteBundle>()
+                        .insert((Pooled {}, Visibility::Hidden));
+                    true
+                }
+                None => false,
+            };
+            let mut pools = world.resource_mut::<EntityPools>();
+            let kept = pool(&mut pools);
+            kept.releasing.retain(|releasing| *releasing != entity);
+            if reset {
+                kept.free.push(entity);
+            }
+        });
+    }
+
+    pub(crate) fn spawn_avatar<'a, B: Bundle>(&mut self, commands: &'a mut Commands, bundle: B) -> EntityCommands<'a> {
+        self.avatars.spawn(commands, bundle)
+    }
+
+    pub(crate) fn spawn_bubble<'a, B: Bundle>(&mut self, commands: &'a mut Commands, bundle: B) -> EntityCommands<'a> {
+        self.bubbles.spawn(commands, bundle)
+    }
+
+    pub(crate) fn spawn_footprint<'a, B: Bundle>(
+        &mut self,
+        commands: &'a mut Commands,
+        bundle: B,
+    ) -> EntityCommands<'a> {
+        self.footprints.spawn(commands, bundle)
+    }
+
+    /// Take an avatar off screen, its messages go with it
+    pub(crate) fn release_avatar(&mut self, commands: &mut Commands, entity: Entity, config: &Config) {
+        self.release(commands, entity, config.avatar_pool_size, |pools| &mut pools.avatars);
+    }
+
+    /// Take a message off screen. Only speech bubbles are pooled, lone emotes are despawned.
+    pub(crate) fn release_message(&mut self, commands: &mut Commands, entity: Entity, bubble: bool, config: &Config) {
+        if bubble {
+            self.release(commands, entity, config.message_pool_size, |pools| &mut pools.bubbles);
+        } else {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    /// Take a footprint that has faded away off screen
+    pub(crate) fn release_footprint(&mut self, commands: &mut Commands, entity: Entity, config: &Config) {
+        self.release(commands, entity, config.footprint_max, |pools| &mut pools.footprints);
+    }
+
+    pub(crate) fn spawn_rain_drop<'a, B: Bundle>(
+        &mut self,
+        commands: &'a mut Commands,
+        bundle: B,
+    ) -> EntityCommands<'a> {
+        self.rain_drops.spawn(commands, bundle)
+    }
+
+    /// Take an emote that fell to the floor, or was falling when the rain stopped
+    pub(crate) fn release_rain_drop(&mut self, commands: &mut Commands, entity: Entity, config: &Config) {
+        self.release(commands, entity, config.rain_max_drops, |pools| &mut pools.rain_drops);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::world::CommandQueue;
+
+    use super::*;
+    use crate::config::config_from_str;
+
+    #[derive(Component)]
+    struct Chatter(&'static str);
+
+    #[test]
+    fn released_avatar_is_only_reused_after_it_was_reset() {
+        let config = config_from_str("").unwrap();
+        let mut world = World::new();
+        let old = world.spawn((SpriteBundle::default(), Chatter("old"))).id();
+        let mut pools = EntityPools::default();
+
+        // One system releases the avatar and another spawns one in the same frame, with the
+        // spawning system's commands applied first
+        let mut releasing = CommandQueue::default();
+        pools.release_avatar(&mut Commands::new(&mut releasing, &world), old, &config);
+        let mut spawning = CommandQueue::default();
+        let new = pools
+            .spawn_avatar(&mut Commands::new(&mut spawning, &world), (SpriteBundle::default(), Chatter("new")))
+            .id();
+        assert_ne!(new, old);
+
+        world.insert_resource(pools);
+        spawning.apply(&mut world);
+        releasing.apply(&mut world);
+        assert_eq!(world.get::<Chatter>(new).map(|chatter| chatter.0), Some("new"));
+        assert!(world.get::<Pooled>(old).is_some());
+        assert!(world.get::<Chatter>(old).is_none());
+
+        // The next frame gets the reset avatar back
+        let mut pools = world.remove_resource::<EntityPools>().unwrap();
+        let mut spawning = CommandQueue::default();
+        let reused = pools
+            .spawn_avatar(&mut Commands::new(&mut spawning, &world), (SpriteBundle::default(), Chatter("next")))
+            .id();
+        spawning.apply(&mut world);
+        assert_eq!(reused, old);
+        assert_eq!(world.get::<Chatter>(reused).map(|chatter| chatter.0), Some("next"));
+        assert!(world.get::<Pooled>(reused).is_none());
+    }
+}
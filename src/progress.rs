@@ -0,0 +1,390 @@
+use std::time::{Duration, Instant};
+
+use bevy::{
+    asset::AssetServer,
+    color::{Alpha, Color},
+    hierarchy::{BuildChildren, DespawnRecursiveExt},
+    math::{Quat, Vec2, Vec3},
+    prelude::{
+        default, Camera, Commands, Component, Entity, Event, EventReader, Query, Res, ResMut, Resource, Transform,
+        With, Without,
+    },
+    sprite::{Anchor, Sprite, SpriteBundle},
+    text::{Text, Text2dBundle, TextStyle},
+    time::Time,
+};
+use log::{debug, info};
+
+use crate::{
+    boop::Booping,
+    commands::is_moderator,
+    config::{Config, ScreenEdge},
+    users::range_for,
+    AppState, ChatMessage, OverlayCamera, UserAction, UserActionDetails,
+};
+
+/// How quickly the fill catches up with a new position, higher is faster
+const FILL_RATE: f32 = 3.0;
+const MARKER_SIZE: f32 = 10.0;
+const FILL_COLOR: Color = Color::srgb(0.45, 0.8, 1.0);
+/// Behind the minawan and the hype train bar
+const BAR_Z: f32 = -3.0;
+/// How far from the marker chatters wander when they gather at it
+const CLUSTER_RADIUS: f32 = 120.0;
+/// Chatters who said something this recently gather at the marker
+const CLUSTER_ACTIVE_TIME: Duration = Duration::from_secs(5 * 60);
+
+/// Move the progress bar, sent from chat
+#[derive(Event, Debug, Clone)]
+pub(crate) enum ProgressCommand {
+    /// `3/12 Episode title`, the title is optional
+    Set {
+        current: usize,
+        total: usize,
+        label: Option<String>,
+    },
+    /// Go to a segment of SEGMENTS
+    Jump(usize),
+    /// Go forward or back by this many segments
+    Step(isize),
+    Hide,
+    Unknown(String),
+}
+
+/// How far through the show the party is
+#[derive(Resource, Default)]
+pub(crate) struct WatchProgress {
+    /// Segment reached and how many there are, None while the bar is hidden
+    position: Option<(usize, usize)>,
+    /// Set by the command, otherwise the segment's name from SEGMENTS is used
+    label: Option<String>,
+    /// Share of the bar that's filled on screen, catching up with the position
+    shown: f32,
+    /// World x of the marker, for chatters to gather at
+    marker_x: Option<f32>,
+}
+
+impl WatchProgress {
+    fn target(&self) -> f32 {
+        self.position
+            .map_or(0.0, |(current, total)| current as f32 / total.max(1) as f32)
+    }
+
+    /// Whether the fill is still moving to a new position
+    pub(crate) fn animating(&self) -> bool {
+        self.position.is_some() && (self.target() - self.shown).abs() > f32::EPSILON
+    }
+}
+
+/// The bar's background, the fill, marker and label are its children
+#[derive(Component)]
+pub(crate) struct ProgressBar {}
+
+#[derive(Component)]
+pub(crate) struct ProgressFill {}
+
+#[derive(Component)]
+pub(crate) struct ProgressMarker {}
+
+#[derive(Component)]
+pub(crate) struct ProgressLabel {}
+
+/// Parse a progress command from the broadcaster, a moderator or an admin.
+/// Returns None when the message should go through the normal display pipeline.
+pub(crate) fn parse_progress_command(chat_message: &ChatMessage, config: &Config) -> Option<ProgressCommand> {
+    if !config.progress_enabled {
+        return None;
+    }
+    let mut words = chat_message.message.split_whitespace();
+    if !words.next()?.eq_ignore_ascii_case(&config.progress_command) {
+        return None;
+    }
+    if !is_moderator(chat_message, config) {
+        return None;
+    }
+
+    let unknown = || ProgressCommand::Unknown(chat_message.message.clone());
+    let command = match words.next().map(|word| word.to_lowercase()).as_deref() {
+        Some("next") => ProgressCommand::Step(1),
+        Some("back") => ProgressCommand::Step(-1),
+        Some("off") => ProgressCommand::Hide,
+        Some(word) => match word.split_once('/') {
+            Some((current, total)) => match (current.parse(), total.parse()) {
+                (Ok(current), Ok(total)) if total > 0 && current <= total => {
+                    let label = words.collect::<Vec<&str>>().join(" ");
+                    ProgressCommand::Set {
+                        current,
+                        total,
+                        label: (!label.is_empty()).then_some(label),
+                    }
+                }
+                _ => unknown(),
+            },
+            None => word.parse().map_or_else(|_| unknown(), ProgressCommand::Jump),
+        },
+        None => unknown(),
+    };
+    Some(command)
+}
+
+// Move the bar for progress commands
+pub(crate) fn handle_progress_commands(
+    mut events: EventReader<ProgressCommand>,
+    mut progress: ResMut<WatchProgress>,
+    config: Res<Config>,
+) {
+    for event in events.read() {
+        // Segments count from the list when there is one, or from the last position set
+        let total = match (config.progress_segments.len(), progress.position) {
+            (0, Some((_, total))) => total,
+            (segments, _) => segments,
+        };
+        let current = progress.position.map_or(0, |(current, _)| current);
+        match event {
+            ProgressCommand::Set { current, total, label } => {
+                progress.position = Some((*current, *total));
+                progress.label = label.clone();
+            }
+            ProgressCommand::Jump(segment) if total > 0 => {
+                progress.position = Some(((*segment).min(total), total));
+                progress.label = None;
+            }
+            ProgressCommand::Step(step) if total > 0 => {
+                progress.position = Some((current.saturating_add_signed(*step).min(total), total));
+                progress.label = None;
+            }
+            ProgressCommand::Jump(_) | ProgressCommand::Step(_) => {
+                debug!("No SEGMENTS and no progress set yet, use {} 3/12", config.progress_command);
+                continue;
+            }
+            ProgressCommand::Hide => {
+                info!("Hiding the progress bar");
+                progress.position = None;
+                progress.label = None;
+                continue;
+            }
+            ProgressCommand::Unknown(text) => {
+                debug!("Ignoring progress command without a position: {}", text);
+                continue;
+            }
+        }
+        if let Some((current, total)) = progress.position {
+            info!("Progress {}/{}", current, total);
+        }
+    }
+}
+
+/// Text over the bar, `3/12 Episode title`
+fn label_text(progress: &WatchProgress, config: &Config) -> String {
+    let Some((current, total)) = progress.position else {
+        return String::new();
+    };
+    let segment = current.checked_sub(1).and_then(|index| config.progress_segments.get(index));
+    match progress.label.as_ref().or(segment) {
+        Some(label) => format!("{}/{} {}", current, total, label),
+        None => format!("{}/{}", current, total),
+    }
+}
+
+fn spawn_bar(commands: &mut Commands, config: &Config, asset_server: &AssetServer) {
+    commands
+        .spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::BLACK.with_alpha(0.3),
+                    ..default()
+                },
+                transform: Transform::from_xyz(0.0, 0.0, BAR_Z),
+                ..default()
+            },
+            ProgressBar {},
+        ))
+        .with_children(|bar| {
+            bar.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: FILL_COLOR.with_alpha(0.8),
+                        anchor: Anchor::CenterLeft,
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(0.0, 0.0, 0.1),
+                    ..default()
+                },
+                ProgressFill {},
+            ));
+            bar.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::WHITE,
+                        custom_size: Some(Vec2::splat(MARKER_SIZE)),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(0.0, 0.0, 0.2)
+                        .with_rotation(Quat::from_rotation_z(std::f32::consts::FRAC_PI_4)),
+                    ..default()
+                },
+                ProgressMarker {},
+            ));
+            bar.spawn((
+                Text2dBundle {
+                    text: Text::from_section(
+                        "",
+                        TextStyle {
+                            font: asset_server.load(&config.font_url),
+                            font_size: config.font_size * 0.8,
+                            color: Color::WHITE,
+                        },
+                    ),
+                    ..default()
+                },
+                ProgressLabel {},
+            ));
+        });
+}
+
+// Lay the bar out along EDGE across the walk area, following the window size, and ease the
+// fill towards the position. The bar is only there while there's a position to show.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub(crate) fn update_progress_bar(
+    mut commands: Commands,
+    mut progress: ResMut<WatchProgress>,
+    config: Res<Config>,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    mut bar_query: Query<
+        (Entity, &mut Transform, &mut Sprite),
+        (With<ProgressBar>, Without<ProgressFill>, Without<ProgressMarker>, Without<ProgressLabel>),
+    >,
+    mut fill_query: Query<
+        (&mut Transform, &mut Sprite),
+        (With<ProgressFill>, Without<ProgressBar>, Without<ProgressMarker>, Without<ProgressLabel>),
+    >,
+    mut marker_query: Query<
+        &mut Transform,
+        (With<ProgressMarker>, Without<ProgressBar>, Without<ProgressFill>, Without<ProgressLabel>),
+    >,
+    mut label_query: Query<
+        (&mut Transform, &mut Text, &mut Anchor),
+        (With<ProgressLabel>, Without<ProgressBar>, Without<ProgressFill>, Without<ProgressMarker>),
+    >,
+) {
+    if !config.progress_enabled || progress.position.is_none() {
+        for (entity, _, _) in bar_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        progress.marker_x = None;
+        progress.shown = 0.0;
+        return;
+    }
+    let Ok((_, mut bar_transform, mut bar_sprite)) = bar_query.get_single_mut() else {
+        spawn_bar(&mut commands, &config, &asset_server);
+        return;
+    };
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+
+    let target = progress.target();
+    let ease = 1.0 - (-FILL_RATE * time.delta_seconds()).exp();
+    progress.shown += (target - progress.shown) * ease;
+    if (target - progress.shown).abs() < 0.001 {
+        progress.shown = target;
+    }
+
+    // Across the walk area, clear of a hype train bar on the same edge
+    let ranges = config.walk_ranges(rect.max.x);
+    let left = ranges.first().map_or(-rect.max.x / 2.0, |range| range.0);
+    let right = ranges.last().map_or(rect.max.x / 2.0, |range| range.1);
+    let width = (right - left).max(0.0);
+    let thickness = config.progress_thickness;
+    let hype_train = if config.hype_train_bar == Some(config.progress_edge) {
+        config.hype_train_bar_thickness
+    } else {
+        0.0
+    };
+    let (y, label_y, label_anchor) = match config.progress_edge {
+        ScreenEdge::Top => {
+            let y = rect.max.y / 2.0 - hype_train - thickness / 2.0;
+            (y, -(thickness / 2.0 + 4.0), Anchor::TopCenter)
+        }
+        _ => {
+            let y = -rect.max.y / 2.0 + hype_train + thickness / 2.0;
+            (y, thickness / 2.0 + 4.0, Anchor::BottomCenter)
+        }
+    };
+    let center = Vec3::new((left + right) / 2.0, y, BAR_Z);
+    if bar_transform.translation != center {
+        bar_transform.translation = center;
+    }
+    let size = Vec2::new(width, thickness);
+    if bar_sprite.custom_size != Some(size) {
+        bar_sprite.custom_size = Some(size);
+    }
+
+    let filled = width * progress.shown;
+    if let Ok((mut fill_transform, mut fill_sprite)) = fill_query.get_single_mut() {
+        fill_transform.translation.x = -width / 2.0;
+        fill_sprite.custom_size = Some(Vec2::new(filled, thickness));
+    }
+    if let Ok(mut marker_transform) = marker_query.get_single_mut() {
+        marker_transform.translation.x = filled - width / 2.0;
+    }
+    progress.marker_x = Some(left + filled);
+
+    if let Ok((mut label_transform, mut text, mut anchor)) = label_query.get_single_mut() {
+        label_transform.translation = Vec3::new(0.0, label_y, 0.3);
+        *anchor = label_anchor;
+        let value = label_text(&progress, &config);
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+        if config.is_changed() {
+            text.sections[0].style.font = asset_server.load(&config.font_url);
+            text.sections[0].style.font_size = config.font_size * 0.8;
+        }
+    }
+}
+
+// With CLUSTER on, chatters who spoke recently walk back towards the marker whenever they
+// wander too far from it. Those in another strip go to the end of theirs closest to it.
+pub(crate) fn cluster_at_progress(
+    progress: Res<WatchProgress>,
+    config: Res<Config>,
+    app_state: Res<AppState>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    mut user_query: Query<(&Transform, &mut UserActionDetails), Without<Booping>>,
+) {
+    if !config.progress_cluster {
+        return;
+    }
+    let Some(marker_x) = progress.marker_x else {
+        return;
+    };
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+    let ranges = config.walk_ranges(rect.max.x);
+    let now = Instant::now();
+    for user in app_state.active_users.values() {
+        if now.duration_since(user.last_message_time) > CLUSTER_ACTIVE_TIME {
+            continue;
+        }
+        let Ok((transform, mut action)) = user_query.get_mut(user.entity) else {
+            continue;
+        };
+        let (start, end) = range_for(transform.translation.x, &ranges);
+        let offset = transform.translation.x - marker_x.clamp(start, end);
+        if offset.abs() <= CLUSTER_RADIUS {
+            continue;
+        }
+        let towards = if offset > 0.0 {
+            UserAction::MoveLeft
+        } else {
+            UserAction::MoveRight
+        };
+        // Keep walking that way rather than letting move_users pick again
+        action.last_action = towards;
+        action.time = now;
+    }
+}
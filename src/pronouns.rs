@@ -0,0 +1,268 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use bevy::{
+    asset::AssetServer,
+    color::Color,
+    hierarchy::BuildChildren,
+    math::Vec3,
+    prelude::{default, Commands, Component, Entity, EventReader, Res, ResMut, Resource, Transform},
+    text::{Text, Text2dBundle, TextStyle},
+};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::{
+    api::ApiEvent,
+    config::Config,
+    framerate::{forward_with_wake, FrameWaker},
+    state::{read_state_file, unix_now, write_state_file},
+    AppState,
+};
+
+const PRONOUNS_API: &str = "https://api.pronouns.alejo.io/v1";
+const PRONOUNS_FILE: &str = "pronouns.toml";
+/// Least time between two lookups, so a raid doesn't flood the API
+const LOOKUP_INTERVAL: Duration = Duration::from_millis(250);
+/// Users waiting to be looked up, anyone past this is skipped until they spawn again
+const LOOKUP_QUEUE: usize = 200;
+/// Position of the pronouns relative to the avatar's center, over its head
+const LABEL_OFFSET: Vec3 = Vec3::new(0.0, 40.0, 1.0);
+
+/// A user's entry in the pronouns API
+#[derive(Deserialize)]
+struct PronounUser {
+    pronoun_id: String,
+    alt_pronoun_id: Option<String>,
+}
+
+/// One of the pronoun sets the API knows, by id such as `hehim`
+#[derive(Deserialize)]
+struct PronounSet {
+    subject: String,
+    object: String,
+    /// Sets like "Any" are shown on their own
+    singular: bool,
+}
+
+/// Lookups from earlier runs, kept for CACHE_HOURS
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct PronounCache {
+    users: HashMap<String, CachedPronouns>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct CachedPronouns {
+    /// None when the user hasn't set any
+    pronouns: Option<String>,
+    /// Seconds since the Unix epoch
+    looked_up: u64,
+}
+
+/// Pronouns looked up this session, and the channels to the task doing the lookups
+#[derive(Resource)]
+pub(crate) struct Pronouns {
+    requests: mpsc::Sender<String>,
+    results: mpsc::Receiver<(String, Option<String>)>,
+    /// None for users who haven't set any
+    known: HashMap<String, Option<String>>,
+    pending: HashSet<String>,
+}
+
+/// Pronouns shown over a minawan
+#[derive(Component)]
+pub(crate) struct PronounLabel {}
+
+/// Run condition for showing pronouns
+pub(crate) fn pronouns_enabled(config: Res<Config>) -> bool {
+    config.pronouns_enabled
+}
+
+/// Start looking up pronouns in the background, if ENABLED
+pub(crate) fn start_pronoun_lookups(config: &Config, waker: FrameWaker) -> Pronouns {
+    let (request_tx, request_rx) = mpsc::channel::<String>(LOOKUP_QUEUE);
+    let (result_tx, result_rx) = mpsc::channel::<(String, Option<String>)>(100);
+    if config.pronouns_enabled {
+        tokio::spawn(look_up_pronouns(request_rx, result_tx, config.pronouns_cache_time));
+    }
+    Pronouns {
+        requests: request_tx,
+        results: forward_with_wake(result_rx, waker),
+        known: HashMap::new(),
+        pending: HashSet::new(),
+    }
+}
+
+/// How a pronoun id reads, like He/Him, or He/They with an alternative set
+fn describe(sets: &HashMap<String, PronounSet>, user: &PronounUser) -> Option<String> {
+    let main = sets.get(&user.pronoun_id)?;
+    let text = match user.alt_pronoun_id.as_ref().and_then(|id| sets.get(id)) {
+        Some(alt) => format!("{}/{}", main.subject, alt.subject),
+        None if main.singular => main.subject.clone(),
+        None => format!("{}/{}", main.subject, main.object),
+    };
+    Some(text)
+}
+
+async fn fetch_pronoun_sets(client: &reqwest::Client) -> Result<HashMap<String, PronounSet>, reqwest::Error> {
+    client
+        .get(format!("{}/pronouns", PRONOUNS_API))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
+
+/// A user's pronouns, None when they haven't set any
+async fn fetch_user_pronouns(
+    client: &reqwest::Client,
+    sets: &HashMap<String, PronounSet>,
+    login: &str,
+) -> Result<Option<String>, reqwest::Error> {
+    let response = client
+        .get(format!("{}/users/{}", PRONOUNS_API, login))
+        .send()
+        .await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let user: PronounUser = response.error_for_status()?.json().await?;
+    Ok(describe(sets, &user))
+}
+
+/// Answer lookups one at a time, from the disk cache when it has them. Failed lookups are
+/// answered with no pronouns and not cached, so they're tried again next session.
+async fn look_up_pronouns(
+    mut requests: mpsc::Receiver<String>,
+    results: mpsc::Sender<(String, Option<String>)>,
+    cache_time: Duration,
+) {
+    let client = reqwest::Client::new();
+    let mut cache = PronounCache::default();
+    if !cache_time.is_zero() {
+        cache = read_state_file(PRONOUNS_FILE).unwrap_or_default();
+        let now = unix_now();
+        cache
+            .users
+            .retain(|_, cached| now.saturating_sub(cached.looked_up) < cache_time.as_secs());
+    }
+    let mut sets: Option<HashMap<String, PronounSet>> = None;
+    let mut unsaved = false;
+    let mut interval = tokio::time::interval(LOOKUP_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    while let Some(login) = requests.recv().await {
+        let pronouns = match cache.users.get(&login) {
+            Some(cached) => cached.pronouns.clone(),
+            None => {
+                interval.tick().await;
+                if sets.is_none() {
+                    match fetch_pronoun_sets(&client).await {
+                        Ok(fetched) => sets = Some(fetched),
+                        Err(err) => debug!("Failed to get the pronoun list: {}", err),
+                    }
+                }
+                let result = match &sets {
+                    Some(sets) => fetch_user_pronouns(&client, sets, &login).await,
+                    None => Ok(None),
+                };
+                match result {
+                    Ok(pronouns) if sets.is_some() => {
+                        cache.users.insert(
+                            login.clone(),
+                            CachedPronouns {
+                                pronouns: pronouns.clone(),
+                                looked_up: unix_now(),
+                            },
+                        );
+                        unsaved = true;
+                        pronouns
+                    }
+                    Ok(pronouns) => pronouns,
+                    Err(err) => {
+                        debug!("Failed to look up pronouns for {}: {}", login, err);
+                        None
+                    }
+                }
+            }
+        };
+        if results.send((login, pronouns)).await.is_err() {
+            break;
+        }
+        // Save once the queue is empty rather than after every lookup in a raid
+        if unsaved && requests.is_empty() && !cache_time.is_zero() {
+            unsaved = false;
+            if let Err(err) = write_state_file(PRONOUNS_FILE, &cache) {
+                debug!("Failed to save the pronoun cache: {}", err);
+            }
+        }
+    }
+}
+
+/// Put the pronouns over a minawan
+fn add_label(commands: &mut Commands, entity: Entity, pronouns: &str, config: &Config, asset_server: &AssetServer) {
+    commands.entity(entity).with_children(|parent| {
+        parent.spawn((
+            Text2dBundle {
+                text: Text::from_section(
+                    pronouns,
+                    TextStyle {
+                        font: asset_server.load(&config.font_url),
+                        font_size: config.font_size * 0.6,
+                        color: Color::srgba(1.0, 1.0, 1.0, 0.6),
+                    },
+                ),
+                transform: Transform::from_translation(LABEL_OFFSET),
+                ..default()
+            },
+            PronounLabel {},
+        ));
+    });
+}
+
+// Ask for the pronouns of Twitch users as they arrive, and show them once they're known
+pub(crate) fn show_pronouns(
+    mut commands: Commands,
+    mut party_events: EventReader<ApiEvent>,
+    mut pronouns: ResMut<Pronouns>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    asset_server: Res<AssetServer>,
+) {
+    for event in party_events.read() {
+        let ApiEvent::UserSpawned { user } = event else {
+            continue;
+        };
+        // Only Twitch users are in the pronouns API, the others have a platform prefix
+        if user.contains(':') || pronouns.pending.contains(user) {
+            continue;
+        }
+        match pronouns.known.get(user) {
+            Some(Some(known)) => {
+                if let Some(entity) = app_state.active_users.get(user).map(|user| user.entity) {
+                    add_label(&mut commands, entity, known, &config, &asset_server);
+                }
+            }
+            Some(None) => {}
+            None => {
+                // A full queue skips them, they're asked about again next time they spawn
+                if pronouns.requests.try_send(user.clone()).is_ok() {
+                    pronouns.pending.insert(user.clone());
+                }
+            }
+        }
+    }
+
+    while let Ok((user, found)) = pronouns.results.try_recv() {
+        pronouns.pending.remove(&user);
+        if let (Some(found), Some(entity)) = (&found, app_state.active_users.get(&user).map(|user| user.entity)) {
+            add_label(&mut commands, entity, found, &config, &asset_server);
+        }
+        pronouns.known.insert(user, found);
+    }
+}
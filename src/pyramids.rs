@@ -0,0 +1,411 @@
+use std::time::{Duration, Instant};
+
+use bevy::{
+    asset::AssetServer,
+    color::{Alpha, Color},
+    hierarchy::{BuildChildren, DespawnRecursiveExt},
+    math::{Vec2, Vec3},
+    prelude::{
+        default, Camera, Commands, Component, Entity, Query, Res, ResMut, Resource, Transform, With,
+    },
+    sprite::{Sprite, SpriteBundle, TextureAtlas},
+    text::TextStyle,
+    time::Time,
+    ui::{
+        node_bundles::{NodeBundle, TextBundle},
+        JustifyContent, PositionType, Style, UiRect, Val,
+    },
+};
+use log::info;
+use rand::Rng;
+use vleue_kinetoscope::AnimatedImageBundle;
+
+use crate::{config::Config, locale::t, scripts::Dance, AppState, EmoteStorage, OverlayCamera};
+
+/// How long the builder's minawan dances
+const FANFARE_TIME: Duration = Duration::from_secs(3);
+/// How long the banner stays up, it outlasts the rain a little
+const BANNER_TIME: Duration = Duration::from_secs(6);
+/// Emotes falling per second while it rains
+const RAIN_RATE: f32 = 8.0;
+const RAIN_SPEED: f32 = 180.0;
+/// Above the avatars and messages, with the weather
+const RAIN_Z: f32 = 10.0;
+const BANNER_COLOR: Color = Color::srgb(1.0, 0.85, 0.2);
+
+/// An emote pyramid someone finished
+struct Pyramid {
+    user: String,
+    emote: String,
+    height: usize,
+}
+
+/// A pyramid on its way up or down
+struct Building {
+    user: String,
+    emote: String,
+    /// Width of the last row
+    last: usize,
+    height: usize,
+    /// Whether it's past its peak
+    falling: bool,
+}
+
+impl Building {
+    fn new(user: &str, emote: &str) -> Self {
+        Self {
+            user: user.to_string(),
+            emote: emote.to_string(),
+            last: 1,
+            height: 1,
+            falling: false,
+        }
+    }
+}
+
+/// The emote falling for a finished pyramid
+struct EmoteRain {
+    emote: String,
+    until: Instant,
+    /// Emotes owed from earlier frames
+    pending: f32,
+}
+
+/// The pyramid being built in chat, and the celebrations for those that were finished
+#[derive(Resource, Default)]
+pub(crate) struct Pyramids {
+    building: Option<Building>,
+    completed: Vec<Pyramid>,
+    rain: Option<EmoteRain>,
+}
+
+/// An emote falling for a pyramid
+#[derive(Component)]
+pub(crate) struct EmoteRaindrop {}
+
+/// Banner crediting the builder
+#[derive(Component)]
+pub(crate) struct PyramidBanner {
+    until: Instant,
+}
+
+/// The emote a message repeats and how many times, if it's nothing but one emote
+fn pyramid_row<'a>(message: &'a str, is_emote: impl Fn(&str) -> bool) -> Option<(&'a str, usize)> {
+    let mut words = message.split_whitespace();
+    let emote = words.next().filter(|word| is_emote(word))?;
+    let mut width = 1;
+    for word in words {
+        if word != emote {
+            return None;
+        }
+        width += 1;
+    }
+    Some((emote, width))
+}
+
+impl Pyramids {
+    /// Follow a chat message. A row that doesn't fit the pyramid being built starts over,
+    /// or ends it when it isn't one emote wide.
+    pub(crate) fn observe(&mut self, user: &str, message: &str, is_emote: impl Fn(&str) -> bool, config: &Config) {
+        if !config.pyramids_enabled {
+            return;
+        }
+        if self.building.as_ref().is_some_and(|building| building.user != user) {
+            if !config.pyramid_interruptions_break {
+                return;
+            }
+            self.building = None;
+        }
+        let Some((emote, width)) = pyramid_row(message, is_emote) else {
+            self.building = None;
+            return;
+        };
+
+        let mut building = match self.building.take() {
+            Some(building) if building.emote == emote => building,
+            // Anyone can start a pyramid with a single emote
+            _ => {
+                self.building = (width == 1).then(|| Building::new(user, emote));
+                return;
+            }
+        };
+        if !building.falling && width == building.last + 1 {
+            building.height = width;
+        } else if width + 1 == building.last && building.height >= 2 {
+            building.falling = true;
+        } else {
+            self.building = (width == 1).then(|| Building::new(user, emote));
+            return;
+        }
+        building.last = width;
+        if width > 1 {
+            self.building = Some(building);
+        } else if building.height >= config.pyramid_min_height {
+            self.completed.push(Pyramid {
+                user: building.user,
+                emote: building.emote,
+                height: building.height,
+            });
+        }
+    }
+}
+
+// Celebrate finished pyramids. The builder's minawan dances, their emote rains down and a
+// banner says who built it.
+pub(crate) fn celebrate_pyramids(
+    mut commands: Commands,
+    mut pyramids: ResMut<Pyramids>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    asset_server: Res<AssetServer>,
+    banner_query: Query<Entity, With<PyramidBanner>>,
+) {
+    let now = Instant::now();
+    let completed = std::mem::take(&mut pyramids.completed);
+    let name = |pyramid: &Pyramid| {
+        app_state
+            .active_users
+            .get(&pyramid.user)
+            .map_or(pyramid.user.clone(), |user| user.name.clone())
+    };
+    for pyramid in completed.iter() {
+        info!("{} built a {} pyramid {} high", name(pyramid), pyramid.emote, pyramid.height);
+        if let Some(user) = app_state.active_users.get(&pyramid.user) {
+            commands.entity(user.entity).insert(Dance {
+                until: now + FANFARE_TIME,
+            });
+        }
+    }
+
+    // The newest pyramid takes over the banner and the rain
+    let Some(pyramid) = completed.last() else {
+        return;
+    };
+    for entity in banner_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(16.0),
+                    width: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            PyramidBanner {
+                until: now + BANNER_TIME,
+            },
+        ))
+        .with_children(|builder| {
+            builder.spawn(
+                TextBundle::from_section(
+                    t!("pyramid.built", user = name(pyramid), emote = pyramid.emote, height = pyramid.height),
+                    TextStyle {
+                        font: asset_server.load(&config.font_url),
+                        font_size: config.font_size * 1.5,
+                        color: BANNER_COLOR,
+                    },
+                )
+                .with_style(Style {
+                    padding: UiRect::all(Val::Px(8.0)),
+                    ..default()
+                })
+                .with_background_color(Color::BLACK.with_alpha(0.6)),
+            );
+        });
+    pyramids.rain = Some(EmoteRain {
+        emote: pyramid.emote.clone(),
+        until: now + config.pyramid_rain,
+        pending: 0.0,
+    });
+}
+
+/// A falling copy of an emote, if it was loaded for the messages it was in
+fn spawn_raindrop(
+    commands: &mut Commands,
+    emote_store: &EmoteStorage,
+    name: &str,
+    size: Vec2,
+    translation: Vec3,
+) -> Option<Entity> {
+    let sprite = Sprite {
+        custom_size: Some(size),
+        ..default()
+    };
+    let transform = Transform::from_translation(translation);
+    // Static emotes are drawn from the atlas once they're packed, like in messages
+    if let Some(region) = emote_store.atlas.get(name) {
+        let bundle = SpriteBundle {
+            sprite,
+            texture: region.image.clone(),
+            transform,
+            ..default()
+        };
+        let atlas = TextureAtlas {
+            layout: region.layout.clone(),
+            index: region.index,
+        };
+        return Some(commands.spawn((bundle, atlas, EmoteRaindrop {})).id());
+    }
+    let loaded = emote_store.loaded.get(name)?;
+    let entity = match (&loaded.animated_image, &loaded.static_image) {
+        (Some(animated_image), _) => commands
+            .spawn((
+                AnimatedImageBundle {
+                    animated_image: animated_image.clone(),
+                    sprite,
+                    transform,
+                    ..default()
+                },
+                EmoteRaindrop {},
+            ))
+            .id(),
+        (None, Some(texture)) => commands
+            .spawn((
+                SpriteBundle {
+                    sprite,
+                    texture: texture.clone(),
+                    transform,
+                    ..default()
+                },
+                EmoteRaindrop {},
+            ))
+            .id(),
+        (None, None) => return None,
+    };
+    Some(entity)
+}
+
+// Rain the pyramid's emote across the walk area for RAIN_SECS, and take the banner down once
+// it's been up long enough
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn run_emote_rain(
+    mut commands: Commands,
+    mut pyramids: ResMut<Pyramids>,
+    config: Res<Config>,
+    emote_store: Res<EmoteStorage>,
+    time: Res<Time>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    mut drop_query: Query<(Entity, &mut Transform), With<EmoteRaindrop>>,
+    banner_query: Query<(Entity, &PyramidBanner)>,
+) {
+    let now = Instant::now();
+    for (entity, banner) in banner_query.iter() {
+        if now >= banner.until {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+    let floor = config.floor_y(rect.max.y);
+    let delta = time.delta_seconds();
+    for (entity, mut transform) in drop_query.iter_mut() {
+        if transform.translation.y <= floor {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        transform.translation.y -= RAIN_SPEED * delta;
+    }
+
+    if pyramids.rain.as_ref().is_some_and(|rain| now >= rain.until) {
+        pyramids.rain = None;
+    }
+    let Some(rain) = pyramids.rain.as_mut() else {
+        return;
+    };
+    let height = config.font_height() * config.emote_size_multiplier * 1.5;
+    let size = match emote_store.all.get(&rain.emote).and_then(|emote| emote.width.zip(emote.height)) {
+        Some((width, emote_height)) if emote_height > 0 => {
+            Vec2::new(height * width as f32 / emote_height as f32, height)
+        }
+        _ => Vec2::splat(height),
+    };
+    let ranges = config.walk_ranges(rect.max.x);
+    let left = ranges.first().map_or(-rect.max.x / 2.0, |range| range.0);
+    let right = ranges.last().map_or(rect.max.x / 2.0, |range| range.1);
+    let top = rect.max.y / 2.0 + size.y;
+    let mut rng = rand::thread_rng();
+    let mut loaded = true;
+    rain.pending += RAIN_RATE * delta;
+    while loaded && rain.pending >= 1.0 {
+        rain.pending -= 1.0;
+        let x = rng.gen_range(left..=right.max(left));
+        let translation = Vec3::new(x, top, RAIN_Z);
+        loaded = spawn_raindrop(&mut commands, &emote_store, &rain.emote, size, translation).is_some();
+    }
+    // An emote that was never shown can't rain, there's nothing loaded to draw
+    if !loaded {
+        pyramids.rain = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::config_from_str;
+
+    fn config(interruptions_break: bool) -> Config {
+        config_from_str(&format!(
+            "[Pyramids]\nENABLED = true\nMIN_HEIGHT = 3\nINTERRUPTIONS_BREAK = {}\n",
+            interruptions_break
+        ))
+        .unwrap()
+    }
+
+    /// Heights of the pyramids finished by the messages, sent in order by `(user, message)`
+    fn built(messages: &[(&str, &str)], config: &Config) -> Vec<usize> {
+        let mut pyramids = Pyramids::default();
+        for (user, message) in messages {
+            pyramids.observe(user, message, |word| word == "K" || word == "L", config);
+        }
+        pyramids.completed.iter().map(|pyramid| pyramid.height).collect()
+    }
+
+    #[test]
+    fn rising_then_falling_rows_finish_a_pyramid() {
+        let rows = ["K", "K K", "K K K", "K K", "K"].map(|row| ("alice", row));
+        assert_eq!(built(&rows, &config(true)), vec![3]);
+
+        let taller = ["K", "K K", "K K K", "K K K K", "K K K", "K K", "K"].map(|row| ("alice", row));
+        assert_eq!(built(&taller, &config(true)), vec![4]);
+    }
+
+    #[test]
+    fn pyramids_below_the_min_height_are_ignored() {
+        let rows = ["K", "K K", "K"].map(|row| ("alice", row));
+        assert!(built(&rows, &config(true)).is_empty());
+    }
+
+    #[test]
+    fn rows_out_of_order_start_over() {
+        // Skipping a row ends it, going down too soon too
+        let skipped = ["K", "K K", "K K K K", "K K K", "K K", "K"].map(|row| ("alice", row));
+        assert!(built(&skipped, &config(true)).is_empty());
+        let dropped = ["K", "K K", "K K K", "K", "K K", "K K K", "K K", "K"].map(|row| ("alice", row));
+        assert_eq!(built(&dropped, &config(true)), vec![3]);
+        // So does another emote or anything that isn't all one emote
+        let swapped = ["K", "K K", "K K K", "L L", "L"].map(|row| ("alice", row));
+        assert!(built(&swapped, &config(true)).is_empty());
+        let chatter = ["K", "K K", "K K K", "K hi", "K"].map(|row| ("alice", row));
+        assert!(built(&chatter, &config(true)).is_empty());
+    }
+
+    #[test]
+    fn interruptions_break_pyramids_when_configured() {
+        let rows = [
+            ("alice", "K"),
+            ("alice", "K K"),
+            ("bob", "nice"),
+            ("alice", "K K K"),
+            ("alice", "K K"),
+            ("alice", "K"),
+        ];
+        assert!(built(&rows, &config(true)).is_empty());
+        assert_eq!(built(&rows, &config(false)), vec![3]);
+    }
+}
@@ -0,0 +1,416 @@
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    asset::AssetServer,
+    color::{Alpha, Color},
+    hierarchy::{BuildChildren, DespawnRecursiveExt},
+    prelude::{
+        default, Camera, Commands, Component, Entity, Event, EventReader, EventWriter, Query, Res, ResMut,
+        Resource, SpatialBundle, Transform, With, Without,
+    },
+    sprite::Sprite,
+    text::{Text, TextStyle},
+    time::Time,
+    ui::{
+        node_bundles::{NodeBundle, TextBundle},
+        JustifyContent, PositionType, Style, UiRect, Val,
+    },
+};
+use log::info;
+use rand::Rng;
+
+use crate::{
+    api::ApiEvent,
+    commands::is_admin,
+    config::Config,
+    giveaway::spawn_crown,
+    locale::t,
+    sizes::{avatar_size, head_y, HeadOffset, SizeClass},
+    AppState, ChatMessage, OverlayCamera, UserAction, UserActionDetails, UserMarker, Wave,
+};
+
+/// Fewest racers it takes to run a race
+const MIN_RACERS: usize = 2;
+/// How long racers take to walk to the start line
+const LINE_UP_TIME: Duration = Duration::from_secs(2);
+/// How long a race takes at an even pace
+const RACE_TIME: Duration = Duration::from_secs(8);
+/// How long "Go!" stays up once they're off
+const GO_TIME: Duration = Duration::from_secs(1);
+/// How long the winner keeps the crown and banner
+const WINNER_TIME: Duration = Duration::from_secs(10);
+/// Racers line up this much higher than the one before them, so they're all seen
+const LANE_STEP: f32 = 6.0;
+/// Most a racer's pace changes in one frame
+const PACE_JITTER: f32 = 0.15;
+const MIN_PACE: f32 = 0.6;
+const MAX_PACE: f32 = 1.4;
+const BANNER_COLOR: Color = Color::srgb(1.0, 0.84, 0.0);
+
+/// Open a race for viewers to join, sent by the race command
+#[derive(Event, Debug, Clone)]
+pub(crate) struct StartRace {}
+
+/// Parse the race command from the broadcaster or an admin.
+/// Returns None when the message should go through the normal display pipeline.
+pub(crate) fn parse_race_command(chat_message: &ChatMessage, config: &Config) -> Option<StartRace> {
+    if !config.race_enabled {
+        return None;
+    }
+    let mut words = chat_message.message.split_whitespace();
+    if !words.next()?.eq_ignore_ascii_case(&config.race_command) {
+        return None;
+    }
+    if !is_admin(chat_message, config) {
+        return None;
+    }
+    Some(StartRace {})
+}
+
+#[derive(Clone, Copy)]
+enum Stage {
+    /// Viewers can join until then
+    Joining { until: Instant },
+    LiningUp { started: Instant },
+    Running { started: Instant },
+}
+
+/// The race that's on, if there is one
+#[derive(Resource, Default)]
+pub(crate) struct Race {
+    stage: Option<Stage>,
+    /// Users who joined, with their minawan, in the order they joined
+    racers: Vec<(String, Entity)>,
+    /// Users who wanted to join once the race was full
+    overflow: HashSet<String>,
+    /// No race starts before then
+    next_race: Option<Instant>,
+    /// Text on the race banner, and when it comes down
+    announcement: Option<(String, Option<Instant>)>,
+}
+
+impl Race {
+    pub(crate) fn running(&self) -> bool {
+        matches!(self.stage, Some(Stage::LiningUp { .. } | Stage::Running { .. }))
+    }
+}
+
+/// A minawan in the race, it doesn't wander meanwhile
+#[derive(Component)]
+pub(crate) struct Racing {
+    /// Where it walked to the start line from
+    from: f32,
+    /// Height it stood at before lining up in its lane
+    base_y: f32,
+    lane: usize,
+    /// Share of the even pace it's running at, this changes every frame
+    pace: f32,
+}
+
+/// Crown on the race winner's head until `until`
+#[derive(Component)]
+pub(crate) struct RaceCrown {
+    until: Instant,
+}
+
+#[derive(Component)]
+pub(crate) struct RaceBanner {}
+
+#[derive(Component)]
+pub(crate) struct RaceBannerText {}
+
+// Open a race for the race command, unless one is on or the last one was too recent
+pub(crate) fn start_races(mut events: EventReader<StartRace>, mut race: ResMut<Race>, config: Res<Config>) {
+    let now = Instant::now();
+    for _ in events.read() {
+        if race.stage.is_some() {
+            info!("A race is already on");
+            continue;
+        }
+        if let Some(next_race) = race.next_race.filter(|next_race| now < *next_race) {
+            info!("The next race can start in {} seconds", (next_race - now).as_secs() + 1);
+            continue;
+        }
+        info!("Race open, {} to join", config.race_join_command);
+        race.racers.clear();
+        race.overflow.clear();
+        race.stage = Some(Stage::Joining {
+            until: now + config.race_join_time,
+        });
+    }
+}
+
+// Add minawan to the race when their chatter says the join command while it's open
+pub(crate) fn join_races(
+    mut party_events: EventReader<ApiEvent>,
+    mut race: ResMut<Race>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+) {
+    if !matches!(race.stage, Some(Stage::Joining { .. })) {
+        party_events.clear();
+        return;
+    }
+    for event in party_events.read() {
+        let ApiEvent::MessageDisplayed { user, message } = event else {
+            continue;
+        };
+        let joins = message
+            .split_whitespace()
+            .next()
+            .is_some_and(|word| word.eq_ignore_ascii_case(&config.race_join_command));
+        if !joins || race.racers.iter().any(|(racer, _)| racer == user) {
+            continue;
+        }
+        let Some(entity) = app_state.active_users.get(user).map(|user| user.entity) else {
+            continue;
+        };
+        if race.racers.len() < config.race_max_racers {
+            info!("{} joins the race", user);
+            race.racers.push((user.clone(), entity));
+        } else {
+            race.overflow.insert(user.clone());
+        }
+    }
+}
+
+/// Start and finish line, the ends of the walk area
+fn track(config: &Config, width: f32) -> (f32, f32) {
+    let ranges = config.walk_ranges(width);
+    let left = ranges.first().map_or(-width / 2.0, |range| range.0);
+    let right = ranges.last().map_or(width / 2.0, |range| range.1);
+    (left + config.edge_buffer, right - config.edge_buffer)
+}
+
+// Run the race through its stages: line the racers up on the left once joining closes, send
+// them off to the right with their pace jittering every frame, and crown whoever gets there
+// first. Racers whose minawan leave the party drop out.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub(crate) fn run_race(
+    mut commands: Commands,
+    mut race: ResMut<Race>,
+    mut api_events: EventWriter<ApiEvent>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    time: Res<Time>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    walker_query: Query<(&Transform, Option<&Wave>), (With<UserMarker>, Without<Racing>)>,
+    mut racer_query: Query<(&mut Transform, &mut Sprite, &mut Racing, &mut UserActionDetails, Option<&SizeClass>)>,
+    crown_query: Query<(Entity, &RaceCrown)>,
+) {
+    let now = Instant::now();
+    for (entity, crown) in crown_query.iter() {
+        if now >= crown.until {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+    if race
+        .announcement
+        .as_ref()
+        .is_some_and(|(_, until)| until.is_some_and(|until| now >= until))
+    {
+        race.announcement = None;
+    }
+    race.racers
+        .retain(|(user, entity)| app_state.active_users.get(user).is_some_and(|user| user.entity == *entity));
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+    let (start, finish) = track(&config, rect.max.x);
+
+    let stage = race.stage;
+    match stage {
+        None => {}
+        Some(Stage::Joining { until }) if now < until => {
+            let mut text = t!(
+                "race.join",
+                command = config.race_join_command,
+                secs = (until - now).as_secs() + 1,
+                count = race.racers.len(),
+                max = config.race_max_racers
+            );
+            if !race.overflow.is_empty() {
+                text = format!("{} {}", text, t!("race.overflow", count = race.overflow.len()));
+            }
+            race.announcement = Some((text, None));
+        }
+        Some(Stage::Joining { .. }) if race.racers.len() < MIN_RACERS => {
+            info!("Only {} joined the race, calling it off", race.racers.len());
+            race.stage = None;
+            race.announcement = None;
+        }
+        Some(Stage::Joining { .. }) => {
+            info!("{} minawan line up for the race", race.racers.len());
+            for (lane, (_, entity)) in race.racers.iter().enumerate() {
+                let Ok((transform, wave)) = walker_query.get(*entity) else {
+                    continue;
+                };
+                // A wave would put it back where it started hopping, mid race
+                let base_y = wave.map_or(transform.translation.y, |wave| wave.base_y);
+                commands.entity(*entity).remove::<Wave>().insert(Racing {
+                    from: transform.translation.x,
+                    base_y,
+                    lane,
+                    pace: 1.0,
+                });
+            }
+            race.stage = Some(Stage::LiningUp { started: now });
+            race.announcement = Some((t!("race.ready"), None));
+        }
+        Some(Stage::LiningUp { started }) => {
+            let progress = (now.duration_since(started).as_secs_f32() / LINE_UP_TIME.as_secs_f32()).min(1.0);
+            let eased = progress * progress * (3.0 - 2.0 * progress);
+            for (mut transform, mut sprite, racing, ..) in racer_query.iter_mut() {
+                transform.translation.x = racing.from + (start - racing.from) * eased;
+                transform.translation.y = racing.base_y + racing.lane as f32 * LANE_STEP;
+                sprite.flip_x = start < racing.from && progress < 1.0;
+            }
+            if progress >= 1.0 {
+                race.stage = Some(Stage::Running { started: now });
+                race.announcement = Some((t!("race.go"), Some(now + GO_TIME)));
+            }
+        }
+        Some(Stage::Running { started }) => {
+            let speed = (finish - start) / RACE_TIME.as_secs_f32();
+            let delta = time.delta_seconds();
+            let mut rng = rand::thread_rng();
+            let mut finished = false;
+            for (mut transform, mut sprite, mut racing, ..) in racer_query.iter_mut() {
+                racing.pace = (racing.pace + rng.gen_range(-PACE_JITTER..PACE_JITTER)).clamp(MIN_PACE, MAX_PACE);
+                transform.translation.x = (transform.translation.x + speed * racing.pace * delta).min(finish);
+                sprite.flip_x = false;
+                finished |= transform.translation.x >= finish;
+            }
+            if finished && !race.racers.is_empty() {
+                info!("Race finished in {:.1} seconds", started.elapsed().as_secs_f32());
+                finish_race(&mut commands, &mut race, &mut api_events, &app_state, &config, &mut racer_query);
+            }
+        }
+    }
+    if race.running() && race.racers.is_empty() {
+        info!("Everyone left the race");
+        race.stage = None;
+        race.announcement = None;
+    }
+}
+
+/// Crown the racer furthest along, tell the API clients the results and let everyone wander
+/// off again
+#[allow(clippy::type_complexity)]
+fn finish_race(
+    commands: &mut Commands,
+    race: &mut Race,
+    api_events: &mut EventWriter<ApiEvent>,
+    app_state: &AppState,
+    config: &Config,
+    racer_query: &mut Query<(&mut Transform, &mut Sprite, &mut Racing, &mut UserActionDetails, Option<&SizeClass>)>,
+) {
+    let now = Instant::now();
+    let mut results: Vec<(String, Entity, f32)> = race
+        .racers
+        .iter()
+        .filter_map(|(user, entity)| {
+            let (transform, ..) = racer_query.get(*entity).ok()?;
+            Some((user.clone(), *entity, transform.translation.x))
+        })
+        .collect();
+    results.sort_by(|a, b| b.2.total_cmp(&a.2));
+    if let Some((winner, entity, _)) = results.first() {
+        let name = app_state.active_users.get(winner).map_or(winner.as_str(), |user| user.name.as_str());
+        info!("{} wins the race", name);
+        race.announcement = Some((t!("race.winner", user = name), Some(now + WINNER_TIME)));
+        let (sprite, size_class) = racer_query
+            .get(*entity)
+            .map(|(_, sprite, _, _, size_class)| (sprite.clone(), size_class.copied()))
+            .unwrap_or_default();
+        let size = avatar_size(&sprite, size_class.as_ref());
+        commands.entity(*entity).with_children(|parent| {
+            parent
+                .spawn((
+                    SpatialBundle::from_transform(Transform::from_xyz(0.0, head_y(&sprite, size) + 6.0, 2.0)),
+                    RaceCrown {
+                        until: now + WINNER_TIME,
+                    },
+                    HeadOffset::of(&sprite),
+                ))
+                .with_children(|crown| spawn_crown(crown, size.x * 0.5));
+        });
+        api_events.send(ApiEvent::RaceFinished {
+            winner: winner.clone(),
+            results: results.iter().map(|(user, _, _)| user.clone()).collect(),
+        });
+    }
+
+    for (_, entity, _) in &results {
+        if let Ok((mut transform, _, racing, mut action, _)) = racer_query.get_mut(*entity) {
+            transform.translation.y = racing.base_y;
+            action.last_action = UserAction::Stop;
+            action.time = now;
+        }
+        commands.entity(*entity).remove::<Racing>();
+    }
+    race.stage = None;
+    race.racers.clear();
+    race.next_race = Some(now + config.race_cooldown);
+}
+
+// Keep the race banner up with the race's announcement, in the same place as the giveaway's
+pub(crate) fn show_race_banner(
+    mut commands: Commands,
+    race: Res<Race>,
+    config: Res<Config>,
+    asset_server: Res<AssetServer>,
+    banner_query: Query<Entity, With<RaceBanner>>,
+    mut text_query: Query<&mut Text, With<RaceBannerText>>,
+) {
+    let banner = banner_query.get_single().ok();
+    let Some((announcement, _)) = &race.announcement else {
+        if let Some(banner) = banner {
+            commands.entity(banner).despawn_recursive();
+        }
+        return;
+    };
+    if banner.is_some() {
+        if let Ok(mut text) = text_query.get_single_mut() {
+            if text.sections[0].value != *announcement {
+                text.sections[0].value.clone_from(announcement);
+            }
+        }
+        return;
+    }
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(16.0),
+                    width: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            RaceBanner {},
+        ))
+        .with_children(|builder| {
+            builder.spawn((
+                TextBundle::from_section(
+                    announcement.clone(),
+                    TextStyle {
+                        font: asset_server.load(&config.font_url),
+                        font_size: config.font_size * 2.0,
+                        color: BANNER_COLOR,
+                    },
+                )
+                .with_style(Style {
+                    padding: UiRect::all(Val::Px(8.0)),
+                    ..default()
+                })
+                .with_background_color(Color::BLACK.with_alpha(0.6)),
+                RaceBannerText {},
+            ));
+        });
+}
@@ -0,0 +1,263 @@
+use std::time::Instant;
+
+use bevy::{
+    asset::AssetServer,
+    math::{Vec2, Vec3},
+    prelude::{
+        default, Camera, Commands, Component, Entity, Event, EventReader, Query, Res, ResMut, Resource, Transform,
+        With,
+    },
+    sprite::{Sprite, SpriteBundle, TextureAtlas},
+    time::Time,
+};
+use log::{debug, info};
+use rand::Rng;
+use vleue_kinetoscope::AnimatedImageBundle;
+
+use crate::{
+    accessibility::EffectPolicy, commands::is_moderator, config::Config, emotes::emote_types::Emote,
+    frame_budget::Cosmetic, handles::HandleCache, marquee::load_emotes, pool::EntityPools, AppState, ChatMessage,
+    EmoteStorage, OverlayCamera,
+};
+
+/// Emotes that start falling per second, up to MAX_DROPS at once
+const DROPS_PER_SEC: f32 = 6.0;
+/// Range of falling speeds, in pixels per second
+const MIN_SPEED: f32 = 50.0;
+const MAX_SPEED: f32 = 120.0;
+/// Range of sizes, times the size of an emote in a message
+const MIN_SCALE: f32 = 0.8;
+const MAX_SCALE: f32 = 1.8;
+/// Over the campfire, behind the minawan and their messages
+const RAIN_Z: f32 = -0.5;
+
+/// Start or stop an emote shower from chat
+#[derive(Event, Debug, Clone)]
+pub(crate) enum RainCommand {
+    /// The emote, with the Twitch emotes in the message in case it's one of those
+    Start { emote: String, emotes: Vec<Emote> },
+    Stop,
+    Unknown(String),
+}
+
+/// The emote falling and until when
+struct Shower {
+    emote: String,
+    until: Instant,
+    /// Emotes owed from earlier frames
+    pending: f32,
+}
+
+#[derive(Resource, Default)]
+pub(crate) struct Rain {
+    shower: Option<Shower>,
+}
+
+/// An emote falling in the shower, from the rain drop pool
+#[derive(Component)]
+pub(crate) struct RainDrop {
+    speed: f32,
+}
+
+/// Parse a rain command from the broadcaster or a moderator.
+/// Returns None when the message should go through the normal display pipeline.
+pub(crate) fn parse_rain_command(chat_message: &ChatMessage, config: &Config) -> Option<RainCommand> {
+    if !config.rain_enabled {
+        return None;
+    }
+    let mut words = chat_message.message.split_whitespace();
+    if !words.next()?.eq_ignore_ascii_case(&config.rain_command) {
+        return None;
+    }
+    if !is_moderator(chat_message, config) {
+        return None;
+    }
+
+    let command = match (words.next(), words.next()) {
+        (Some(word), None) if word.eq_ignore_ascii_case("stop") => RainCommand::Stop,
+        (Some(emote), None) => RainCommand::Start {
+            emote: emote.to_string(),
+            emotes: chat_message.emotes.clone(),
+        },
+        _ => RainCommand::Unknown(chat_message.message.clone()),
+    };
+    Some(command)
+}
+
+// Start and stop the shower for rain commands. Emotes the overlay doesn't know are ignored.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn handle_rain_commands(
+    mut commands: Commands,
+    mut events: EventReader<RainCommand>,
+    mut rain: ResMut<Rain>,
+    mut pools: ResMut<EntityPools>,
+    mut emote_store: ResMut<EmoteStorage>,
+    mut handles: ResMut<HandleCache>,
+    asset_server: Res<AssetServer>,
+    config: Res<Config>,
+    drop_query: Query<Entity, With<RainDrop>>,
+) {
+    for event in events.read() {
+        match event {
+            RainCommand::Start { emote, emotes } => {
+                // Commands skip the pipeline that learns a message's emotes
+                for known in emotes {
+                    emote_store.all.entry(known.name.clone()).or_insert(known.clone());
+                }
+                if !emote_store.all.contains_key(emote) {
+                    debug!("Not raining {}, it isn't an emote the overlay knows", emote);
+                    continue;
+                }
+                info!("Raining {} for {} seconds", emote, config.rain_duration.as_secs());
+                load_emotes(emote, &mut emote_store, &mut handles, &asset_server);
+                rain.shower = Some(Shower {
+                    emote: emote.clone(),
+                    until: Instant::now() + config.rain_duration,
+                    pending: 0.0,
+                });
+            }
+            RainCommand::Stop => {
+                if rain.shower.take().is_some() {
+                    info!("Stopping the rain");
+                }
+                for entity in drop_query.iter() {
+                    pools.release_rain_drop(&mut commands, entity, &config);
+                }
+            }
+            RainCommand::Unknown(text) => debug!("Ignoring rain command without an emote: {}", text),
+        }
+    }
+}
+
+/// Start an emote falling, if it has loaded for drawing
+fn spawn_drop(
+    commands: &mut Commands,
+    pools: &mut EntityPools,
+    emote_store: &EmoteStorage,
+    name: &str,
+    size: Vec2,
+    translation: Vec3,
+    speed: f32,
+) -> bool {
+    let sprite = Sprite {
+        custom_size: Some(size),
+        ..default()
+    };
+    let transform = Transform::from_translation(translation);
+    let drop = RainDrop { speed };
+    // Static emotes are drawn from the atlas once they're packed, like in messages
+    if let Some(region) = emote_store.atlas.get(name) {
+        let bundle = SpriteBundle {
+            sprite,
+            texture: region.image.clone(),
+            transform,
+            ..default()
+        };
+        let atlas = TextureAtlas {
+            layout: region.layout.clone(),
+            index: region.index,
+        };
+        pools.spawn_rain_drop(commands, (bundle, atlas, drop));
+        return true;
+    }
+    let Some(loaded) = emote_store.loaded.get(name) else {
+        return false;
+    };
+    match (&loaded.animated_image, &loaded.static_image) {
+        (Some(animated_image), _) => {
+            let bundle = AnimatedImageBundle {
+                animated_image: animated_image.clone(),
+                sprite,
+                transform,
+                ..default()
+            };
+            pools.spawn_rain_drop(commands, (bundle, drop));
+        }
+        (None, Some(texture)) => {
+            let bundle = SpriteBundle {
+                sprite,
+                texture: texture.clone(),
+                transform,
+                ..default()
+            };
+            pools.spawn_rain_drop(commands, (bundle, drop));
+        }
+        (None, None) => return false,
+    }
+    true
+}
+
+// Let the shower's emote fall across the walk area in different sizes and speeds until the
+// floor. It takes a break in zen mode, with REDUCED_MOTION and while the frame budget has shed
+// particles, and carries on after if it has time left.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn run_rain(
+    mut commands: Commands,
+    mut rain: ResMut<Rain>,
+    mut pools: ResMut<EntityPools>,
+    config: Res<Config>,
+    app_state: Res<AppState>,
+    policy: Res<EffectPolicy>,
+    emote_store: Res<EmoteStorage>,
+    time: Res<Time>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    mut drop_query: Query<(Entity, &mut Transform, &RainDrop)>,
+) {
+    let now = Instant::now();
+    if rain.shower.as_ref().is_some_and(|shower| now >= shower.until) {
+        info!("The rain is over");
+        rain.shower = None;
+    }
+    if app_state.zen_mode || !policy.motion() || !policy.allows(Cosmetic::Particles) {
+        for (entity, _, _) in drop_query.iter() {
+            pools.release_rain_drop(&mut commands, entity, &config);
+        }
+        return;
+    }
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+    let floor = config.floor_y(rect.max.y);
+    let delta = time.delta_seconds();
+    let mut falling = 0;
+    for (entity, mut transform, drop) in drop_query.iter_mut() {
+        if transform.translation.y <= floor {
+            pools.release_rain_drop(&mut commands, entity, &config);
+            continue;
+        }
+        transform.translation.y -= drop.speed * delta;
+        falling += 1;
+    }
+
+    let Some(shower) = rain.shower.as_mut() else {
+        return;
+    };
+    let Some(emote) = emote_store.all.get(&shower.emote) else {
+        return;
+    };
+    let aspect = match emote.width.zip(emote.height) {
+        Some((width, height)) if height > 0 => width as f32 / height as f32,
+        _ => 1.0,
+    };
+    let base_height = config.font_height() * config.emote_size_multiplier;
+    let ranges = config.walk_ranges(rect.max.x);
+    let left = ranges.first().map_or(-rect.max.x / 2.0, |range| range.0);
+    let right = ranges.last().map_or(rect.max.x / 2.0, |range| range.1);
+    let mut rng = rand::thread_rng();
+    shower.pending += DROPS_PER_SEC * delta;
+    while shower.pending >= 1.0 && falling < config.rain_max_drops {
+        let height = base_height * rng.gen_range(MIN_SCALE..=MAX_SCALE);
+        let size = Vec2::new(height * aspect, height);
+        let x = rng.gen_range(left..=right.max(left));
+        let translation = Vec3::new(x, rect.max.y / 2.0 + size.y, RAIN_Z);
+        let speed = rng.gen_range(MIN_SPEED..=MAX_SPEED);
+        // Still loading, it starts falling once it can be drawn
+        if !spawn_drop(&mut commands, &mut pools, &emote_store, &shower.emote, size, translation, speed) {
+            break;
+        }
+        shower.pending -= 1.0;
+        falling += 1;
+    }
+    // Drops held back by MAX_DROPS or loading aren't owed later
+    shower.pending = shower.pending.min(1.0);
+}
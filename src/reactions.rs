@@ -0,0 +1,157 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    color::{Color, Mix},
+    math::{Quat, Vec3},
+    prelude::{Commands, Component, Entity, EventReader, Has, Local, Query, Res, Transform, With},
+    sprite::Sprite,
+};
+use log::debug;
+use rand::Rng;
+
+use crate::{
+    accessibility::EffectPolicy,
+    ambient::BaseTint,
+    api::ApiEvent,
+    config::Config,
+    petting::spawn_heart,
+    race::Racing,
+    sizes::{avatar_size, head_y, SizeClass},
+    AppState, UserMarker, Wave,
+};
+
+/// How long a minawan droops for
+const DROOP_DURATION: Duration = Duration::from_millis(1500);
+/// How far a drooping minawan tips forward at the lowest, in radians
+const DROOP_ANGLE: f32 = 0.3;
+const SAD_TINT: Color = Color::srgb(0.45, 0.6, 1.0);
+/// How much of SAD_TINT is mixed into the minawan's own colour
+const SAD_TINT_STRENGTH: f32 = 0.6;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Reaction {
+    Sad,
+    Hype,
+    Love,
+}
+
+/// A minawan drooping for a sad message, its own colour to go back to afterwards
+#[derive(Component)]
+pub(crate) struct Drooping {
+    started: Instant,
+    tint: Color,
+}
+
+/// The reaction for the first word in the message that has one. Words match in any case and
+/// with or without punctuation around them.
+fn reaction_for(message: &str, config: &Config) -> Option<Reaction> {
+    let lists = [
+        (Reaction::Sad, &config.reaction_sad),
+        (Reaction::Hype, &config.reaction_hype),
+        (Reaction::Love, &config.reaction_love),
+    ];
+    message.split_whitespace().find_map(|word| {
+        let word = word.to_lowercase();
+        let bare = word.trim_matches(|c: char| c.is_ascii_punctuation());
+        lists
+            .iter()
+            .find(|(_, words)| words.iter().any(|listed| *listed == word || listed == bare))
+            .map(|(reaction, _)| *reaction)
+    })
+}
+
+// Make minawan droop, jump or send up a heart for the sad, hype and loving words and emotes
+// in their chatter's messages, at most once every COOLDOWN_SECS each
+#[allow(clippy::type_complexity)]
+pub(crate) fn react_to_messages(
+    mut commands: Commands,
+    mut party_events: EventReader<ApiEvent>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    avatar_query: Query<
+        (&Transform, &Sprite, Option<&SizeClass>, Option<&BaseTint>, Has<Wave>, Has<Racing>, Has<Drooping>),
+        With<UserMarker>,
+    >,
+    mut last_reactions: Local<HashMap<String, Instant>>,
+) {
+    if !config.reactions_enabled {
+        party_events.clear();
+        last_reactions.clear();
+        return;
+    }
+    let now = Instant::now();
+    for event in party_events.read() {
+        let ApiEvent::MessageDisplayed { user, message } = event else {
+            continue;
+        };
+        if last_reactions
+            .get(user)
+            .is_some_and(|last| now.duration_since(*last) < config.reaction_cooldown)
+        {
+            continue;
+        }
+        let Some(reaction) = reaction_for(message, &config) else {
+            continue;
+        };
+        let Some(entity) = app_state.active_users.get(user).map(|user| user.entity) else {
+            continue;
+        };
+        let Ok((transform, sprite, size_class, tint, waving, racing, drooping)) = avatar_query.get(entity) else {
+            continue;
+        };
+        debug!("{}'s minawan reacts: {:?}", user, reaction);
+        last_reactions.insert(user.clone(), now);
+        let translation = transform.translation;
+        match reaction {
+            // Racers keep their footing, they're busy
+            Reaction::Sad if !drooping && !racing => {
+                let tint = tint.map_or(Color::WHITE, |tint| tint.0);
+                commands.entity(entity).insert((
+                    Drooping { started: now, tint },
+                    BaseTint(tint.mix(&SAD_TINT, SAD_TINT_STRENGTH)),
+                ));
+            }
+            Reaction::Hype if !waving && !racing => {
+                commands.entity(entity).insert(Wave {
+                    started: now,
+                    base_y: translation.y,
+                });
+            }
+            Reaction::Love => {
+                let top = translation.y + head_y(sprite, avatar_size(sprite, size_class));
+                let drift = rand::thread_rng().gen_range(-20.0..20.0);
+                spawn_heart(&mut commands, Vec3::new(translation.x, top, 5.0), drift);
+            }
+            _ => {}
+        }
+    }
+    last_reactions.retain(|_, last| now.duration_since(*last) < config.reaction_cooldown);
+}
+
+// Tip drooping minawan forward and back up, then give them their own colour back. With
+// REDUCED_MOTION they only turn blue.
+pub(crate) fn animate_droops(
+    mut commands: Commands,
+    policy: Res<EffectPolicy>,
+    mut droop_query: Query<(Entity, &mut Transform, &Sprite, &Drooping)>,
+) {
+    for (entity, mut transform, sprite, droop) in droop_query.iter_mut() {
+        let progress = droop.started.elapsed().as_secs_f32() / DROOP_DURATION.as_secs_f32();
+        if progress >= 1.0 {
+            transform.rotation = Quat::IDENTITY;
+            commands.entity(entity).remove::<Drooping>().insert(BaseTint(droop.tint));
+            continue;
+        }
+        if !policy.motion() {
+            transform.rotation = Quat::IDENTITY;
+            continue;
+        }
+        // Down quickly, back up slowly, towards the way it's facing
+        let depth = (progress * 4.0).min(1.0) * (1.0 - progress).min(0.5) * 2.0;
+        let facing = if sprite.flip_x { 1.0 } else { -1.0 };
+        transform.rotation = Quat::from_rotation_z(facing * DROOP_ANGLE * depth);
+    }
+}
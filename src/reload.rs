@@ -0,0 +1,95 @@
+use std::{
+    fs,
+    time::{Duration, Instant, SystemTime},
+};
+
+use bevy::prelude::{ResMut, Resource};
+use log::{error, info, warn};
+
+use crate::{
+    cli::CliArgs,
+    config::{load_config, Config},
+};
+
+/// How often to check whether the config file has changed
+const RELOAD_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Tracks the config file so edits can be applied while running
+#[derive(Resource)]
+pub(crate) struct ConfigWatcher {
+    path: String,
+    /// Reapplied on every reload so the command line keeps precedence over the file
+    args: CliArgs,
+    modified: Option<SystemTime>,
+    last_check: Instant,
+    /// Reload on the next frame even if the file hasn't changed
+    reload_requested: bool,
+}
+
+impl ConfigWatcher {
+    pub(crate) fn new(args: CliArgs) -> Self {
+        Self {
+            path: args.config_path.clone(),
+            modified: modified_time(&args.config_path),
+            args,
+            last_check: Instant::now(),
+            reload_requested: false,
+        }
+    }
+
+    pub(crate) fn request_reload(&mut self) {
+        self.reload_requested = true;
+    }
+
+    pub(crate) fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+// Reload the config file when it changes, applying the keys that are safe to change at runtime
+pub(crate) fn reload_config(
+    mut watcher: ResMut<ConfigWatcher>,
+    mut config: ResMut<Config>,
+) {
+    if watcher.last_check.elapsed() < RELOAD_POLL_INTERVAL && !watcher.reload_requested {
+        return;
+    }
+    watcher.last_check = Instant::now();
+
+    let modified = modified_time(&watcher.path);
+    if modified == watcher.modified && !watcher.reload_requested {
+        return;
+    }
+    watcher.modified = modified;
+    watcher.reload_requested = false;
+
+    // Keep running with the previous values when the edit is broken
+    let mut new_config = match load_config(&watcher.path, watcher.args.profile.as_deref()) {
+        Ok(new_config) => new_config,
+        Err(err) => {
+            error!("Not reloading {}, keeping the previous config: {}", watcher.path, err);
+            return;
+        }
+    };
+
+    watcher.args.apply(&mut new_config);
+
+    let (applied, needs_restart) = config.apply_reloaded(&new_config);
+
+    if applied.is_empty() && needs_restart.is_empty() {
+        info!("Reloaded {}, nothing changed", watcher.path);
+    }
+    if !applied.is_empty() {
+        info!("Reloaded {}, applied: {}", watcher.path, applied.join(", "));
+    }
+    if !needs_restart.is_empty() {
+        warn!(
+            "Changes to {} need a restart to take effect",
+            needs_restart.join(", ")
+        );
+    }
+}
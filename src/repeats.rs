@@ -0,0 +1,193 @@
+use std::time::Instant;
+
+use bevy::{
+    asset::AssetServer,
+    ecs::system::SystemParam,
+    math::Vec3,
+    prelude::{
+        default, BuildChildren, Changed, Children, Commands, Component, Entity, Has, Parent, Query, Res, ResMut,
+        Transform, With, Without,
+    },
+    sprite::{Anchor, Sprite},
+    text::{Text, Text2dBundle, TextStyle},
+};
+
+use crate::{
+    bubble_themes::Vanishing,
+    chat::dedupe::RecentMessages, config::Config, EmoteStorage, MessageBubble, MessageSpawnTime, MessageText,
+    SharedChatBorder,
+};
+
+/// Chat clients add this to the end of a message to get it past Twitch's check for sending
+/// the same thing twice
+const DUPLICATE_BYPASS: char = '\u{E0000}';
+/// Space between a repeat badge and the edge of its bubble or emote
+const BADGE_GAP: f32 = 4.0;
+/// Badges sit over the message text and its outline
+const BADGE_Z: f32 = 0.3;
+
+/// How many times in a row a message was sent, counting the first time
+#[derive(Component)]
+pub(crate) struct RepeatCount(pub(crate) u32);
+
+/// The "×N" on a repeated message
+#[derive(Component)]
+pub(crate) struct RepeatBadge {
+    /// How much wider its bubble was made to fit it
+    padding: f32,
+}
+
+/// Messages arriving again: the same delivery twice, dropped by its id, or a chatter saying
+/// the same thing again, counted on the message already shown
+#[derive(SystemParam)]
+pub(crate) struct Duplicates<'w, 's> {
+    pub(crate) recent: ResMut<'w, RecentMessages>,
+    messages: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Parent,
+            &'static MessageText,
+            &'static mut MessageSpawnTime,
+            Option<&'static RepeatCount>,
+        ),
+        // On its way out, it's shown again instead
+        Without<Vanishing>,
+    >,
+}
+
+impl Duplicates<'_, '_> {
+    /// Count `text` on a message `user` is still showing that says the same, and show that one
+    /// for longer. Returns false when it should be shown as a new message.
+    pub(crate) fn repeat(&mut self, commands: &mut Commands, user: Entity, text: &str, config: &Config) -> bool {
+        if !config.collapse_repeats {
+            return false;
+        }
+        let repeated = self.messages.iter_mut().find(|(_, parent, shown, _, _)| {
+            parent.get() == user && same_text(&shown.text, text, config.repeats_ignore_case)
+        });
+        let Some((message, _, _, mut spawn_time, count)) = repeated else {
+            return false;
+        };
+        // despawn_messages queues it again with its new spawn time
+        spawn_time.0 = Instant::now();
+        commands
+            .entity(message)
+            .insert(RepeatCount(count.map_or(2, |count| count.0 + 1)));
+        true
+    }
+}
+
+fn trimmed(text: &str) -> &str {
+    text.trim_end_matches(|c: char| c.is_whitespace() || c == DUPLICATE_BYPASS)
+}
+
+/// Whether two messages say the same, whatever is trailing after them
+fn same_text(shown: &str, text: &str, ignore_case: bool) -> bool {
+    let (shown, text) = (trimmed(shown), trimmed(text));
+    if ignore_case {
+        shown.to_lowercase() == text.to_lowercase()
+    } else {
+        shown == text
+    }
+}
+
+// Show how many times a message was sent on it. Bubbles are widened to fit the count beside
+// their text, lone emotes get it to their right.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub(crate) fn show_repeat_badges(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<Config>,
+    emote_store: Res<EmoteStorage>,
+    mut message_query: Query<
+        (
+            Entity,
+            &RepeatCount,
+            &MessageText,
+            &mut Transform,
+            Option<&mut Sprite>,
+            Option<&Children>,
+            Has<MessageBubble>,
+        ),
+        (Changed<RepeatCount>, Without<SharedChatBorder>),
+    >,
+    mut badge_query: Query<(&mut Text, &mut Transform, &mut RepeatBadge), Without<RepeatCount>>,
+    mut border_query: Query<&mut Sprite, (With<SharedChatBorder>, Without<RepeatCount>)>,
+) {
+    for (message, count, shown, mut transform, sprite, children, bubble) in message_query.iter_mut() {
+        let label = format!("×{}", count.0);
+        let badge = children.and_then(|children| children.iter().find(|child| badge_query.contains(**child)).copied());
+        let padding = badge
+            .and_then(|badge| badge_query.get(badge).ok())
+            .map_or(0.0, |(_, _, badge)| badge.padding);
+
+        let (badge_transform, anchor, padding) = match (bubble, sprite) {
+            (true, Some(mut sprite)) => {
+                let Some(mut size) = sprite.custom_size else {
+                    continue;
+                };
+                // Wide enough for the count and a space before it, it only ever grows
+                let needed = config.font_width() * (label.chars().count() as f32 + 1.0);
+                let grow = (needed - padding).max(0.0);
+                if grow > 0.0 {
+                    size.x += grow;
+                    sprite.custom_size = Some(size);
+                    // Stays centered over the speaker
+                    transform.translation.x -= grow / 2.0;
+                    for child in children.map(|children| children.iter()).into_iter().flatten() {
+                        if let Ok(mut border) = border_query.get_mut(*child) {
+                            if let Some(border_size) = border.custom_size.as_mut() {
+                                border_size.x += grow;
+                            }
+                        }
+                    }
+                }
+                let badge_transform =
+                    Transform::from_translation(Vec3::new(size.x - BADGE_GAP, -config.top_margin(), BADGE_Z));
+                (badge_transform, Anchor::TopRight, padding + grow)
+            }
+            _ => {
+                // Lone emotes are scaled down, the badge keeps its size
+                let scale = transform.scale.x.max(f32::EPSILON);
+                let width = emote_store
+                    .all
+                    .get(shown.text.trim())
+                    .and_then(|emote| emote.width)
+                    .unwrap_or(0) as f32;
+                let badge_transform =
+                    Transform::from_translation(Vec3::new(width / 2.0 + BADGE_GAP / scale, 0.0, BADGE_Z))
+                        .with_scale(Vec3::splat(1.0 / scale));
+                (badge_transform, Anchor::CenterLeft, 0.0)
+            }
+        };
+
+        if let Some((mut text, mut placed, mut badge)) = badge.and_then(|badge| badge_query.get_mut(badge).ok()) {
+            if let Some(section) = text.sections.first_mut() {
+                section.value = label;
+            }
+            *placed = badge_transform;
+            badge.padding = padding;
+            continue;
+        }
+        commands.entity(message).with_children(|builder| {
+            builder.spawn((
+                Text2dBundle {
+                    text: Text::from_section(
+                        label,
+                        TextStyle {
+                            font: asset_server.load(&config.font_url),
+                            font_size: config.font_size,
+                            color: config.text_color,
+                        },
+                    ),
+                    text_anchor: anchor,
+                    transform: badge_transform,
+                    ..default()
+                },
+                RepeatBadge { padding },
+            ));
+        });
+    }
+}
@@ -0,0 +1,96 @@
+use std::{fmt::Display, time::Instant};
+
+use bevy::prelude::{EventReader, Res, ResMut, Resource};
+use log::{debug, info, warn};
+use tokio::sync::mpsc::error::TrySendError;
+
+use crate::{api::ApiEvent, config::Config, eventsub::EventSubEvent, AppState, ChatOutbox};
+
+/// When the party command was last answered
+#[derive(Resource, Default)]
+pub(crate) struct PartyCommandCooldown {
+    last_answer: Option<Instant>,
+}
+
+/// A reply template with its `{name}` placeholders filled in
+fn fill(template: &str, values: &[(&str, &dyn Display)]) -> String {
+    let mut text = template.to_string();
+    for (name, value) in values {
+        text = text.replace(&format!("{{{}}}", name), &value.to_string());
+    }
+    text
+}
+
+/// Hand a reply to the Twitch task, which says it when the rate limit allows
+fn say(outbox: &ChatOutbox, text: String) {
+    match outbox.sender.try_send(text) {
+        Ok(()) => {}
+        Err(TrySendError::Full(text)) => warn!("Too many replies waiting for Twitch chat, not saying {:?}", text),
+        Err(TrySendError::Closed(text)) => debug!("Twitch chat isn't a chat source, not saying {:?}", text),
+    }
+}
+
+// Say the giveaway winner in chat, and answer the party command with how many minawan are
+// here. Only Twitch chatters are answered since the reply goes to Twitch chat.
+pub(crate) fn reply_to_party_events(
+    mut party_events: EventReader<ApiEvent>,
+    mut cooldown: ResMut<PartyCommandCooldown>,
+    outbox: Res<ChatOutbox>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+) {
+    for event in party_events.read() {
+        match event {
+            ApiEvent::GiveawayWon { user } => {
+                if let Some(template) = &config.reply_giveaway_winner {
+                    say(&outbox, fill(template, &[("user", user)]));
+                }
+            }
+            ApiEvent::MessageDisplayed { user, message } => {
+                let Some(template) = &config.reply_party else {
+                    continue;
+                };
+                let is_command = message
+                    .split_whitespace()
+                    .next()
+                    .is_some_and(|word| word.eq_ignore_ascii_case(&config.party_command));
+                if !is_command || user.contains(':') {
+                    continue;
+                }
+                if cooldown
+                    .last_answer
+                    .is_some_and(|time| time.elapsed() < config.party_cooldown)
+                {
+                    debug!("Not answering {} from {}, answered it too recently", config.party_command, user);
+                    continue;
+                }
+                cooldown.last_answer = Some(Instant::now());
+                let count = app_state.active_users.len();
+                say(&outbox, fill(template, &[("user", user), ("count", &count)]));
+            }
+            _ => {}
+        }
+    }
+}
+
+// Thank raiders in chat
+pub(crate) fn thank_raids(
+    mut eventsub_events: EventReader<EventSubEvent>,
+    outbox: Res<ChatOutbox>,
+    config: Res<Config>,
+) {
+    for event in eventsub_events.read() {
+        let EventSubEvent::Raid {
+            from_login,
+            from_name,
+            viewers,
+        } = event
+        else {
+            continue;
+        };
+        info!("{} raided with {} viewers", from_login, viewers);
+        if let Some(template) = &config.reply_raid {
+            say(&outbox, fill(template, &[("channel", from_name), ("viewers", viewers)]));
+        }
+    }
+}
@@ -0,0 +1,351 @@
+use std::{
+    collections::VecDeque,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::{Commands, Entity, EventWriter, Query, Res, ResMut, Resource, Transform, With, Without};
+use chrono::{NaiveDateTime, NaiveTime};
+use log::info;
+
+use crate::{
+    config::Config,
+    timer::{parse_duration, TimerCommand},
+    users::WAVE_DURATION,
+    weather::{WeatherEvent, WeatherKind},
+    AppState, UserMarker, Wave,
+};
+
+/// How often the schedule is looked at
+pub(crate) const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// When a scheduled event happens, in the streamer's local time
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum ScheduleTime {
+    /// `every 1h`, lined up with the clock so hours are on the hour
+    Every(Duration),
+    /// `daily 18:00`
+    Daily(NaiveTime),
+    /// `at 2026-12-31 23:59`, just once
+    Once(NaiveDateTime),
+    /// `02:00-08:00`, the whole time in between, past midnight when the end is earlier
+    Between(NaiveTime, NaiveTime),
+}
+
+/// What a scheduled event does
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum ScheduledAction {
+    /// Every minawan waves
+    Wave,
+    Countdown {
+        duration: Duration,
+        label: Option<String>,
+    },
+    Weather {
+        kind: WeatherKind,
+        duration: Option<Duration>,
+    },
+    /// No new minawan, and the ones at the party sleep through chat
+    Quiet,
+}
+
+/// An entry of the schedule, `when = action` such as `every 1h = wave`,
+/// `02:00-08:00 = quiet` or `at 2026-12-31 23:59 = countdown 1m Happy new year`
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ScheduledEvent {
+    pub(crate) when: ScheduleTime,
+    pub(crate) action: ScheduledAction,
+}
+
+fn parse_time(text: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(text.trim(), "%H:%M").map_err(|_| format!("{:?} isn't a time like 18:00", text))
+}
+
+impl FromStr for ScheduleTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (kind, rest) = s.split_once(' ').unwrap_or((s, ""));
+        match kind.to_lowercase().as_str() {
+            "every" => parse_duration(rest.trim())
+                .map(ScheduleTime::Every)
+                .ok_or_else(|| format!("{:?} isn't a time like 30m or 1h", rest)),
+            "daily" => parse_time(rest).map(ScheduleTime::Daily),
+            "at" => NaiveDateTime::parse_from_str(rest.trim(), "%Y-%m-%d %H:%M")
+                .map(ScheduleTime::Once)
+                .map_err(|_| format!("{:?} isn't a date and time like 2026-12-31 23:59", rest)),
+            _ => {
+                let (start, end) = s
+                    .split_once('-')
+                    .ok_or_else(|| format!("{:?} isn't every, daily, at or a range like 02:00-08:00", s))?;
+                Ok(ScheduleTime::Between(parse_time(start)?, parse_time(end)?))
+            }
+        }
+    }
+}
+
+impl FromStr for ScheduledAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let action = match words.next().map(str::to_lowercase).as_deref() {
+            Some("wave") => ScheduledAction::Wave,
+            Some("quiet") => ScheduledAction::Quiet,
+            Some("countdown") => {
+                let time = words.next().unwrap_or_default();
+                let duration =
+                    parse_duration(time).ok_or_else(|| format!("{:?} isn't a countdown time like 5m", time))?;
+                let label = words.by_ref().collect::<Vec<&str>>().join(" ");
+                ScheduledAction::Countdown {
+                    duration,
+                    label: (!label.is_empty()).then_some(label),
+                }
+            }
+            Some("weather") => {
+                let kind = words.next().unwrap_or_default().parse()?;
+                let duration = match words.next() {
+                    Some(secs) => Some(
+                        secs.parse()
+                            .map(Duration::from_secs)
+                            .map_err(|_| format!("{:?} isn't a number of seconds", secs))?,
+                    ),
+                    None => None,
+                };
+                ScheduledAction::Weather { kind, duration }
+            }
+            _ => return Err(format!("{:?} isn't wave, countdown, weather or quiet", s.trim())),
+        };
+        if let Some(extra) = words.next() {
+            return Err(format!("Unexpected {:?} after {}", extra, s.split_whitespace().next().unwrap_or_default()));
+        }
+        Ok(action)
+    }
+}
+
+impl FromStr for ScheduledEvent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Countdown labels may have an = of their own, times don't
+        let (when, action) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid event, expected when = action: {}", s))?;
+        let when: ScheduleTime = when.parse()?;
+        let action: ScheduledAction = action.parse()?;
+        match (&when, &action) {
+            (ScheduleTime::Between(..), ScheduledAction::Quiet) => {}
+            (ScheduleTime::Between(..), _) => {
+                return Err(format!("Only quiet can last from one time to another: {}", s));
+            }
+            (_, ScheduledAction::Quiet) => return Err(format!("Quiet needs a range like 02:00-08:00: {}", s)),
+            _ => {}
+        }
+        Ok(ScheduledEvent { when, action })
+    }
+}
+
+impl ScheduleTime {
+    /// Whether the event happened after `last` and by `now`
+    fn due(&self, last: NaiveDateTime, now: NaiveDateTime) -> bool {
+        match self {
+            ScheduleTime::Every(interval) => {
+                let interval = interval.as_secs().max(1) as i64;
+                // Local times are counted as if they were UTC so intervals line up with the clock
+                now.and_utc().timestamp().div_euclid(interval) > last.and_utc().timestamp().div_euclid(interval)
+            }
+            ScheduleTime::Daily(time) => [last.date(), now.date()]
+                .iter()
+                .map(|date| date.and_time(*time))
+                .any(|at| last < at && at <= now),
+            ScheduleTime::Once(at) => last < *at && *at <= now,
+            ScheduleTime::Between(..) => false,
+        }
+    }
+
+    /// Whether `now` is inside a range
+    fn covers(&self, now: NaiveTime) -> bool {
+        match self {
+            ScheduleTime::Between(start, end) if start <= end => *start <= now && now < *end,
+            ScheduleTime::Between(start, end) => now >= *start || now < *end,
+            _ => false,
+        }
+    }
+}
+
+impl ScheduledAction {
+    /// How long the action keeps the schedule busy, later events wait for it
+    fn busy_for(&self, config: &Config) -> Duration {
+        match self {
+            ScheduledAction::Wave => Duration::from_secs_f32(WAVE_DURATION),
+            ScheduledAction::Countdown { duration, .. } => *duration,
+            ScheduledAction::Weather { duration, .. } => duration.unwrap_or(config.weather_duration),
+            ScheduledAction::Quiet => Duration::ZERO,
+        }
+    }
+}
+
+/// Scheduled events that came due, run one after the other
+#[derive(Resource, Default)]
+pub(crate) struct Scheduler {
+    /// When the schedule was last looked at
+    last: Option<NaiveDateTime>,
+    queue: VecDeque<ScheduledAction>,
+    busy_until: Option<Instant>,
+}
+
+// Look at the schedule every second. Events that come due wait in line while another one is
+// still running, and quiet hours hold spawning and put the party to sleep.
+pub(crate) fn run_schedule(
+    mut commands: Commands,
+    mut scheduler: ResMut<Scheduler>,
+    mut app_state: ResMut<AppState>,
+    config: Res<Config>,
+    mut timer_events: EventWriter<TimerCommand>,
+    mut weather_events: EventWriter<WeatherEvent>,
+    avatar_query: Query<(Entity, &Transform), (With<UserMarker>, Without<Wave>)>,
+) {
+    let now = config.local_now();
+    // Nothing fires for the time before startup, or when the clock was turned back
+    let last = scheduler.last.filter(|last| *last <= now).unwrap_or(now);
+    scheduler.last = Some(now);
+    for event in config.schedule.iter().filter(|event| event.when.due(last, now)) {
+        info!("Scheduled {:?} is due", event.action);
+        scheduler.queue.push_back(event.action.clone());
+    }
+
+    let quiet = config
+        .schedule
+        .iter()
+        .any(|event| event.action == ScheduledAction::Quiet && event.when.covers(now.time()));
+    if quiet != app_state.quiet_hours {
+        if quiet {
+            info!("Quiet hours, no new minawan until they're over");
+        } else {
+            info!("Quiet hours are over");
+        }
+        app_state.quiet_hours = quiet;
+    }
+
+    let instant = Instant::now();
+    if scheduler.busy_until.is_some_and(|until| instant < until) {
+        return;
+    }
+    let Some(action) = scheduler.queue.pop_front() else {
+        return;
+    };
+    scheduler.busy_until = Some(instant + action.busy_for(&config));
+    match action {
+        ScheduledAction::Wave => {
+            for (entity, transform) in avatar_query.iter() {
+                commands.entity(entity).insert(Wave {
+                    started: instant,
+                    base_y: transform.translation.y,
+                });
+            }
+        }
+        ScheduledAction::Countdown { duration, label } => {
+            timer_events.send(TimerCommand::Start { duration, label });
+        }
+        ScheduledAction::Weather { kind, duration } => {
+            weather_events.send(WeatherEvent::Start { kind, duration });
+        }
+        ScheduledAction::Quiet => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn time(text: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(text, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn events_parse() {
+        let event: ScheduledEvent = "every 1h = wave".parse().unwrap();
+        assert_eq!(event.when, ScheduleTime::Every(Duration::from_secs(60 * 60)));
+        assert_eq!(event.action, ScheduledAction::Wave);
+
+        let event: ScheduledEvent = "Daily 18:00 = weather snow 30".parse().unwrap();
+        assert_eq!(event.when, ScheduleTime::Daily(time("18:00")));
+        assert_eq!(
+            event.action,
+            ScheduledAction::Weather {
+                kind: WeatherKind::Snow,
+                duration: Some(Duration::from_secs(30)),
+            }
+        );
+
+        let event: ScheduledEvent = "at 2026-12-31 23:59 = countdown 1m Happy new year = 2027".parse().unwrap();
+        assert_eq!(event.when, ScheduleTime::Once(at("2026-12-31 23:59:00")));
+        assert_eq!(
+            event.action,
+            ScheduledAction::Countdown {
+                duration: Duration::from_secs(60),
+                label: Some("Happy new year = 2027".to_string()),
+            }
+        );
+
+        let event: ScheduledEvent = "22:00-06:00 = quiet".parse().unwrap();
+        assert_eq!(event.when, ScheduleTime::Between(time("22:00"), time("06:00")));
+        assert_eq!(event.action, ScheduledAction::Quiet);
+    }
+
+    #[test]
+    fn bad_events_are_refused() {
+        for text in [
+            "every 1h wave",
+            "sometimes = wave",
+            "every 5x = wave",
+            "daily 25:00 = wave",
+            "at 2026-13-01 00:00 = wave",
+            "every 1h = wave now",
+            "every 1h = weather hail",
+            "daily 18:00 = countdown",
+            "02:00-08:00 = wave",
+            "daily 02:00 = quiet",
+        ] {
+            assert!(text.parse::<ScheduledEvent>().is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn intervals_line_up_with_the_clock() {
+        let hourly = ScheduleTime::Every(Duration::from_secs(60 * 60));
+        assert!(hourly.due(at("2026-10-15 17:59:59"), at("2026-10-15 18:00:00")));
+        assert!(!hourly.due(at("2026-10-15 18:00:00"), at("2026-10-15 18:59:59")));
+        assert!(hourly.due(at("2026-10-15 18:30:00"), at("2026-10-15 20:10:00")));
+    }
+
+    #[test]
+    fn daily_and_once_are_due_when_passed() {
+        let daily = ScheduleTime::Daily(time("18:00"));
+        assert!(daily.due(at("2026-10-15 17:59:59"), at("2026-10-15 18:00:00")));
+        assert!(!daily.due(at("2026-10-15 18:00:00"), at("2026-10-15 18:00:01")));
+        // Still caught when the overlay was closed over midnight
+        assert!(daily.due(at("2026-10-14 23:00:00"), at("2026-10-15 18:30:00")));
+        assert!(!daily.due(at("2026-10-14 18:30:00"), at("2026-10-15 17:00:00")));
+
+        let once = ScheduleTime::Once(at("2026-12-31 23:59:00"));
+        assert!(!once.due(at("2026-12-31 23:58:00"), at("2026-12-31 23:58:59")));
+        assert!(once.due(at("2026-12-31 23:58:59"), at("2026-12-31 23:59:00")));
+        assert!(!once.due(at("2026-12-31 23:59:00"), at("2027-01-01 00:00:00")));
+    }
+
+    #[test]
+    fn ranges_past_midnight_cover_both_days() {
+        let night = ScheduleTime::Between(time("22:00"), time("06:00"));
+        assert!(night.covers(time("23:00")));
+        assert!(night.covers(time("05:59")));
+        assert!(!night.covers(time("06:00")));
+        assert!(!night.covers(time("12:00")));
+        assert!(!night.due(at("2026-10-15 21:00:00"), at("2026-10-15 23:00:00")));
+    }
+}
@@ -0,0 +1,353 @@
+use std::{
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    app::{App, Plugin, Startup, Update},
+    asset::{Assets, Handle},
+    color::Color,
+    prelude::{
+        default, Camera, Camera2dBundle, ClearColorConfig, Commands, Component, Event, EventReader,
+        IntoSystemConfigs, OrthographicProjection, Query, Res, ResMut, Resource, Transform, With, Without,
+    },
+    render::{
+        camera::{RenderTarget, ScalingMode},
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_asset::{RenderAssetUsages, RenderAssets},
+        render_resource::{
+            Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Extent3d, ImageCopyBuffer,
+            ImageDataLayout, Maintain, MapMode, TextureDimension, TextureFormat, TextureUsages,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        texture::{GpuImage, Image},
+        Render, RenderApp, RenderSet,
+    },
+    window::{PrimaryWindow, Window},
+};
+use log::{info, warn};
+
+use crate::{
+    config::Config, framerate::FrameWaker, locale::t, logging::notify, output::unpremultiply, OverlayCamera,
+};
+
+/// How long a burst takes, its frames are spread evenly over it
+const BURST_TIME: Duration = Duration::from_secs(1);
+
+/// Save the party as a PNG, sent by the screenshot command, hotkey and tray menu
+#[derive(Event, Debug, Clone, Copy)]
+pub(crate) struct TakeScreenshot {
+    /// Take BURST_FRAMES over a second instead of one
+    pub(crate) burst: bool,
+}
+
+/// Renders the party into a texture on the frames a screenshot is taken, so it's saved
+/// with a transparent background whatever the window shows behind the minawan
+pub(crate) struct ScreenshotPlugin;
+
+impl Plugin for ScreenshotPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Screenshots>()
+            .add_plugins(ExtractResourcePlugin::<ScreenshotCapture>::default())
+            .add_systems(Startup, setup_screenshot_camera)
+            .add_systems(Update, (take_screenshots, sync_screenshot_camera.after(take_screenshots)));
+
+        app.sub_app_mut(RenderApp)
+            .add_systems(Render, capture_screenshot.after(RenderSet::Render).before(RenderSet::Cleanup));
+    }
+}
+
+/// Marker for the camera rendering screenshots
+#[derive(Component)]
+pub(crate) struct ScreenshotCamera {}
+
+/// A screenshot waiting to be written
+#[derive(Clone)]
+struct Shot {
+    path: PathBuf,
+    /// Burst frames are saved quietly, only the last one shows a toast
+    announce: bool,
+    waker: FrameWaker,
+}
+
+/// The screenshot texture, and the shot to copy out of it this frame
+#[derive(Resource, Clone, ExtractResource)]
+struct ScreenshotCapture {
+    image: Handle<Image>,
+    width: u32,
+    height: u32,
+    shot: Option<Shot>,
+}
+
+/// A burst of screenshots being taken
+struct Burst {
+    /// File name shared by its frames, which are numbered
+    name: String,
+    taken: u32,
+    frames: u32,
+    next: Instant,
+}
+
+/// Bursts in progress, the party is kept awake while one runs
+#[derive(Resource, Default)]
+pub(crate) struct Screenshots {
+    burst: Option<Burst>,
+}
+
+impl Screenshots {
+    pub(crate) fn capturing(&self) -> bool {
+        self.burst.is_some()
+    }
+}
+
+fn setup_screenshot_camera(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    // Sized to the window on the first frame
+    let size = Extent3d {
+        width: 1,
+        height: 1,
+        depth_or_array_layers: 1,
+    };
+    let mut image = Image::new_fill(
+        size,
+        TextureDimension::D2,
+        &[0; 4],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    image.texture_descriptor.usage |=
+        TextureUsages::COPY_SRC | TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING;
+    let image = images.add(image);
+    commands.insert_resource(ScreenshotCapture {
+        image: image.clone(),
+        width: size.width,
+        height: size.height,
+        shot: None,
+    });
+
+    commands.spawn((
+        Camera2dBundle {
+            camera: Camera {
+                target: RenderTarget::Image(image),
+                // Only renders on the frames a screenshot is taken
+                is_active: false,
+                // Rendered before the window's camera and the output camera
+                order: -2,
+                clear_color: ClearColorConfig::Custom(Color::NONE),
+                ..default()
+            },
+            ..default()
+        },
+        ScreenshotCamera {},
+    ));
+}
+
+/// Timestamped file name, without the extension
+fn file_name() -> String {
+    chrono::Local::now().format("minawan-%Y-%m-%d_%H-%M-%S-%3f").to_string()
+}
+
+// Queue screenshots as they're asked for, one per frame. A burst takes its frames evenly
+// over a second.
+fn take_screenshots(
+    mut events: EventReader<TakeScreenshot>,
+    mut screenshots: ResMut<Screenshots>,
+    mut capture: ResMut<ScreenshotCapture>,
+    config: Res<Config>,
+    waker: Res<FrameWaker>,
+) {
+    // The last frame's shot has been extracted by now
+    if capture.shot.is_some() {
+        capture.shot = None;
+    }
+    let folder = Path::new(&config.screenshot_folder);
+    let mut single = false;
+    for event in events.read() {
+        if !event.burst {
+            single = true;
+        } else if screenshots.burst.is_none() {
+            info!("Taking {} screenshots", config.screenshot_burst_frames);
+            screenshots.burst = Some(Burst {
+                name: file_name(),
+                taken: 0,
+                frames: config.screenshot_burst_frames,
+                next: Instant::now(),
+            });
+        }
+    }
+
+    let now = Instant::now();
+    let shot = match screenshots.burst.as_mut() {
+        Some(burst) if now >= burst.next => {
+            burst.taken += 1;
+            burst.next += BURST_TIME / burst.frames;
+            let shot = Shot {
+                path: folder.join(format!("{}-{:02}.png", burst.name, burst.taken)),
+                announce: burst.taken == burst.frames,
+                waker: waker.clone(),
+            };
+            if burst.taken == burst.frames {
+                screenshots.burst = None;
+            }
+            Some(shot)
+        }
+        // A burst running already has this moment in it
+        _ if single && screenshots.burst.is_none() => Some(Shot {
+            path: folder.join(format!("{}.png", file_name())),
+            announce: true,
+            waker: waker.clone(),
+        }),
+        _ => None,
+    };
+    if shot.is_some() {
+        capture.shot = shot;
+    }
+}
+
+// Render the same area as the window, at the window's resolution, on frames with a shot
+fn sync_screenshot_camera(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    overlay_cameras: Query<&Transform, (With<OverlayCamera>, Without<ScreenshotCamera>)>,
+    mut screenshot_cameras: Query<
+        (&mut Camera, &mut OrthographicProjection, &mut Transform),
+        With<ScreenshotCamera>,
+    >,
+    mut capture: ResMut<ScreenshotCapture>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let (Ok(window), Ok(overlay_transform)) = (windows.get_single(), overlay_cameras.get_single()) else {
+        return;
+    };
+    let Ok((mut camera, mut projection, mut transform)) = screenshot_cameras.get_single_mut() else {
+        return;
+    };
+    let active = capture.shot.is_some();
+    if camera.is_active != active {
+        camera.is_active = active;
+    }
+    if !active {
+        return;
+    }
+
+    let (width, height) = (window.physical_width().max(1), window.physical_height().max(1));
+    if (capture.width, capture.height) != (width, height) {
+        if let Some(image) = images.get_mut(&capture.image) {
+            image.resize(Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            });
+        }
+        capture.width = width;
+        capture.height = height;
+    }
+    projection.scaling_mode = ScalingMode::Fixed {
+        width: window.width(),
+        height: window.height(),
+    };
+    if *transform != *overlay_transform {
+        *transform = *overlay_transform;
+    }
+}
+
+// Copy the rendered shot into a buffer of its own and hand it to a thread that reads it back
+// and writes the PNG, so neither stalls rendering
+fn capture_screenshot(
+    capture: Option<Res<ScreenshotCapture>>,
+    images: Res<RenderAssets<GpuImage>>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    let Some(capture) = capture else {
+        return;
+    };
+    let Some(shot) = capture.shot.clone() else {
+        return;
+    };
+    let Some(image) = images.get(&capture.image) else {
+        return;
+    };
+    // The texture is resized a frame before it's drawn at the new size
+    if (image.size.x, image.size.y) != (capture.width, capture.height) {
+        warn!("The screenshot texture wasn't ready, try again");
+        return;
+    }
+
+    let bytes_per_row = RenderDevice::align_copy_bytes_per_row(capture.width as usize * 4) as u32;
+    let buffer = render_device.create_buffer(&BufferDescriptor {
+        label: Some("screenshot_buffer"),
+        size: bytes_per_row as u64 * capture.height as u64,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor::default());
+    encoder.copy_texture_to_buffer(
+        image.texture.as_image_copy(),
+        ImageCopyBuffer {
+            buffer: &buffer,
+            layout: ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: None,
+            },
+        },
+        Extent3d {
+            width: capture.width,
+            height: capture.height,
+            depth_or_array_layers: 1,
+        },
+    );
+    render_queue.submit(std::iter::once(encoder.finish()));
+
+    let render_device = render_device.clone();
+    let (width, height) = (capture.width, capture.height);
+    thread::spawn(move || {
+        let result = read_back(&render_device, &buffer, width, height, bytes_per_row)
+            .and_then(|data| save_png(&shot.path, data, width, height));
+        match result {
+            Ok(()) if shot.announce => notify(t!("screenshot.saved", path = shot.path.display())),
+            Ok(()) => {}
+            Err(err) => warn!("Failed to save the screenshot {}: {}", shot.path.display(), err),
+        }
+        shot.waker.wake();
+    });
+}
+
+/// Wait for the copy to finish and take the pixels out of the buffer, without the row padding
+fn read_back(
+    render_device: &RenderDevice,
+    buffer: &Buffer,
+    width: u32,
+    height: u32,
+    bytes_per_row: u32,
+) -> Result<Vec<u8>, String> {
+    let slice = buffer.slice(..);
+    let (tx, rx) = std::sync::mpsc::sync_channel(1);
+    slice.map_async(MapMode::Read, move |result| {
+        let _ = tx.send(result);
+    });
+    let _ = render_device.poll(Maintain::wait());
+    rx.recv()
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())?;
+
+    let row = width as usize * 4;
+    let mut data = Vec::with_capacity(row * height as usize);
+    {
+        let mapped = slice.get_mapped_range();
+        for line in mapped.chunks_exact(bytes_per_row as usize).take(height as usize) {
+            data.extend_from_slice(&line[..row]);
+        }
+    }
+    buffer.unmap();
+    Ok(data)
+}
+
+fn save_png(path: &Path, mut data: Vec<u8>, width: u32, height: u32) -> Result<(), String> {
+    unpremultiply(&mut data);
+    let image = image::RgbaImage::from_raw(width, height, data).ok_or("the frame is the wrong size")?;
+    if let Some(folder) = path.parent() {
+        std::fs::create_dir_all(folder).map_err(|err| err.to_string())?;
+    }
+    image.save(path).map_err(|err| err.to_string())
+}
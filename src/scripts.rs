@@ -0,0 +1,422 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use bevy::{
+    asset::AssetServer,
+    color::{Alpha, Color, Srgba},
+    prelude::{
+        default, BuildChildren, Commands, Component, DespawnRecursiveExt, Entity, EventReader, Has, NodeBundle,
+        Query, Res, ResMut, Resource, Transform, With, Without,
+    },
+    text::TextStyle,
+    ui::{node_bundles::TextBundle, JustifyContent, PositionType, Style, UiRect, Val},
+};
+use log::{error, info, warn};
+use rhai::{Array, CallFnOptions, Dynamic, Engine, EvalAltResult, FuncArgs, Scope, AST};
+
+use crate::{
+    accessibility::EffectPolicy,
+    ambient::BaseTint,
+    api::ApiEvent, config::Config, eventsub::EventSubEvent, frame_budget::Cosmetic, AppState, ChatMessage, ChatSender,
+    EmoteStorage, UserMarker, Wave,
+};
+
+/// How often the scripts folder is checked for changes
+const SCRIPT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// How long a minawan keeps hopping after `dance`
+const DANCE_DURATION: Duration = Duration::from_secs(3);
+/// How long a `banner` stays up
+const BANNER_DURATION: Duration = Duration::from_secs(5);
+
+/// Something a script asked for, run once the hooks are done
+enum ScriptEffect {
+    Jump(String),
+    Dance(String),
+    Tint(String, Color),
+    Banner(String),
+    SpawnExtra { user: String, message: String },
+}
+
+/// Shared between the functions scripts can call and the systems running them
+#[derive(Default)]
+struct ScriptContext {
+    effects: Vec<ScriptEffect>,
+    /// What `config(key)` answers with
+    config: HashMap<String, String>,
+}
+
+/// A call into the scripts, each one runs in every script that defines its function
+enum Hook {
+    Message { user: String, text: String, emotes: Array },
+    Spawn { user: String },
+    Redemption { reward: String, user: String },
+}
+
+impl Hook {
+    fn name(&self) -> &'static str {
+        match self {
+            Hook::Message { .. } => "on_message",
+            Hook::Spawn { .. } => "on_spawn",
+            Hook::Redemption { .. } => "on_redemption",
+        }
+    }
+}
+
+/// A `.rhai` file from the scripts folder, without an AST when it didn't compile
+struct Script {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    ast: Option<AST>,
+}
+
+impl Script {
+    fn name(&self) -> String {
+        self.path.file_name().unwrap_or_default().to_string_lossy().to_string()
+    }
+}
+
+/// The scripting engine and the scripts loaded from FOLDER, reloaded when the files change
+#[derive(Resource)]
+pub(crate) struct Scripts {
+    engine: Engine,
+    folder: PathBuf,
+    loaded: Vec<Script>,
+    context: Arc<Mutex<ScriptContext>>,
+    last_scan: Option<Instant>,
+}
+
+impl Scripts {
+    pub(crate) fn from_config(config: &Config) -> Self {
+        let context = Arc::new(Mutex::new(ScriptContext::default()));
+        let mut engine = Engine::new();
+        engine.set_max_operations(config.script_max_operations);
+        engine.on_print(|text| info!("Script: {}", text));
+
+        let queue = |context: &Arc<Mutex<ScriptContext>>| {
+            let context = context.clone();
+            move |effect: ScriptEffect| context.lock().unwrap().effects.push(effect)
+        };
+        let push = queue(&context);
+        engine.register_fn("jump", move |user: &str| push(ScriptEffect::Jump(user.to_string())));
+        let push = queue(&context);
+        engine.register_fn("dance", move |user: &str| push(ScriptEffect::Dance(user.to_string())));
+        let push = queue(&context);
+        engine.register_fn("tint", move |user: &str, color: &str| -> Result<(), Box<EvalAltResult>> {
+            let color = Srgba::hex(color).map_err(|_| format!("{:?} is not a color", color))?;
+            push(ScriptEffect::Tint(user.to_string(), color.into()));
+            Ok(())
+        });
+        let push = queue(&context);
+        engine.register_fn("banner", move |text: &str| push(ScriptEffect::Banner(text.to_string())));
+        let push = queue(&context);
+        engine.register_fn("spawn_extra", move |user: &str, message: &str| {
+            push(ScriptEffect::SpawnExtra {
+                user: user.to_string(),
+                message: message.to_string(),
+            })
+        });
+        let config_values = context.clone();
+        engine.register_fn("config", move |key: &str| {
+            config_values
+                .lock()
+                .unwrap()
+                .config
+                .get(&key.to_uppercase())
+                .cloned()
+                .map_or(Dynamic::UNIT, Dynamic::from)
+        });
+
+        let folder = PathBuf::from(&config.script_folder);
+        if config.scripts_enabled && !folder.is_dir() {
+            warn!("Scripts folder {} doesn't exist, no scripts will run until it does", folder.display());
+        }
+        Self {
+            engine,
+            folder,
+            loaded: vec![],
+            context,
+            last_scan: None,
+        }
+    }
+
+    /// Compile new and changed scripts, forget deleted ones
+    fn scan(&mut self) {
+        let mut found: Vec<PathBuf> = fs::read_dir(&self.folder)
+            .map(|entries| {
+                entries
+                    .filter_map(Result::ok)
+                    .map(|entry| entry.path())
+                    .filter(|path| path.extension().is_some_and(|extension| extension == "rhai"))
+                    .collect()
+            })
+            .unwrap_or_default();
+        // Scripts run in file name order
+        found.sort();
+
+        let mut previous: HashMap<PathBuf, Script> =
+            self.loaded.drain(..).map(|script| (script.path.clone(), script)).collect();
+        for path in found {
+            let modified = modified_time(&path);
+            match previous.remove(&path) {
+                Some(script) if script.modified == modified => self.loaded.push(script),
+                _ => {
+                    let ast = self.compile(&path);
+                    self.loaded.push(Script { path, modified, ast });
+                }
+            }
+        }
+        for script in previous.values() {
+            info!("Unloaded script {}", script.name());
+        }
+    }
+
+    /// Compile a script and run the code outside its functions, once
+    fn compile(&self, path: &Path) -> Option<AST> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let ast = match self.engine.compile_file(path.to_path_buf()) {
+            Ok(ast) => ast,
+            Err(err) => {
+                error!("Script {} doesn't compile: {}", name, err);
+                return None;
+            }
+        };
+        if let Err(err) = self.engine.run_ast(&ast) {
+            error!("Script {} failed while loading: {}", name, err);
+            return None;
+        }
+        info!("Loaded script {}", name);
+        Some(ast)
+    }
+
+    fn call(&self, hook: &Hook) {
+        for script in &self.loaded {
+            let Some(ast) = &script.ast else {
+                continue;
+            };
+            if !ast.iter_functions().any(|function| function.name == hook.name()) {
+                continue;
+            }
+            let result = match hook {
+                Hook::Message { user, text, emotes } => {
+                    self.call_fn(ast, hook.name(), (user.clone(), text.clone(), emotes.clone()))
+                }
+                Hook::Spawn { user } => self.call_fn(ast, hook.name(), (user.clone(),)),
+                Hook::Redemption { reward, user } => self.call_fn(ast, hook.name(), (reward.clone(), user.clone())),
+            };
+            if let Err(err) = result {
+                error!("Script {} failed in {}: {}", script.name(), hook.name(), err);
+            }
+        }
+    }
+
+    /// Call one function with a fresh operation budget. The code outside functions already ran on load.
+    fn call_fn(&self, ast: &AST, name: &str, args: impl FuncArgs) -> Result<(), Box<EvalAltResult>> {
+        let options = CallFnOptions::new().eval_ast(false);
+        self.engine
+            .call_fn_with_options::<Dynamic>(options, &mut Scope::new(), ast, name, args)
+            .map(|_| ())
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// A minawan hopping until `until`
+#[derive(Component)]
+pub(crate) struct Dance {
+    pub(crate) until: Instant,
+}
+
+/// Text put up by a script's `banner`
+#[derive(Component)]
+pub(crate) struct ScriptBanner {
+    shown: Instant,
+}
+
+/// Run condition for the scripting systems
+pub(crate) fn scripts_enabled(config: Res<Config>) -> bool {
+    config.scripts_enabled
+}
+
+// Load scripts that were added or changed since the last check
+pub(crate) fn reload_scripts(mut scripts: ResMut<Scripts>) {
+    if scripts.last_scan.is_some_and(|time| time.elapsed() < SCRIPT_POLL_INTERVAL) {
+        return;
+    }
+    scripts.last_scan = Some(Instant::now());
+    scripts.scan();
+}
+
+// Call the scripts' hooks for this frame's messages, new minawan and redemptions
+pub(crate) fn run_script_hooks(
+    mut scripts: ResMut<Scripts>,
+    config: Res<Config>,
+    emote_store: Res<EmoteStorage>,
+    mut party_events: EventReader<ApiEvent>,
+    mut eventsub_events: EventReader<EventSubEvent>,
+) {
+    if config.is_changed() {
+        scripts.engine.set_max_operations(config.script_max_operations);
+        let mut values: HashMap<String, String> = config
+            .runtime_values()
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect();
+        values.insert("CHANNEL_NAME".to_string(), config.channel_name.clone());
+        scripts.context.lock().unwrap().config = values;
+    }
+
+    let party_hooks = party_events.read().filter_map(|event| match event {
+        ApiEvent::UserSpawned { user } => Some(Hook::Spawn { user: user.clone() }),
+        ApiEvent::MessageDisplayed { user, message } => Some(Hook::Message {
+            user: user.clone(),
+            text: message.clone(),
+            emotes: message
+                .split_whitespace()
+                .filter(|word| emote_store.all.contains_key(*word))
+                .map(|emote| Dynamic::from(emote.to_string()))
+                .collect(),
+        }),
+        _ => None,
+    });
+    let redemption_hooks = eventsub_events.read().filter_map(|event| match event {
+        EventSubEvent::Redemption { user_login, reward_title, .. } => Some(Hook::Redemption {
+            reward: reward_title.clone(),
+            user: user_login.clone(),
+        }),
+        _ => None,
+    });
+    let hooks: Vec<Hook> = party_hooks.chain(redemption_hooks).collect();
+    for hook in hooks {
+        scripts.call(&hook);
+    }
+}
+
+// Carry out what the scripts asked for and take down banners that have been up long enough
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn apply_script_effects(
+    mut commands: Commands,
+    scripts: Res<Scripts>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    asset_server: Res<AssetServer>,
+    chat_sender: Res<ChatSender>,
+    avatar_query: Query<(&Transform, Has<Wave>), With<UserMarker>>,
+    banner_query: Query<(Entity, &ScriptBanner)>,
+) {
+    for (entity, banner) in banner_query.iter() {
+        if banner.shown.elapsed() > BANNER_DURATION {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    let effects: Vec<ScriptEffect> = scripts.context.lock().unwrap().effects.drain(..).collect();
+    let now = Instant::now();
+    let avatar = |user: &str| {
+        let user = user.trim_start_matches('@').to_lowercase();
+        let entity = app_state.active_users.get(&user).map(|user| user.entity);
+        if entity.is_none() {
+            info!("{} isn't at the party, ignoring a script effect for them", user);
+        }
+        entity
+    };
+    for effect in effects {
+        match effect {
+            ScriptEffect::Jump(user) => {
+                let Some(entity) = avatar(&user) else {
+                    continue;
+                };
+                if let Ok((transform, false)) = avatar_query.get(entity) {
+                    commands.entity(entity).insert(Wave {
+                        started: now,
+                        base_y: transform.translation.y,
+                    });
+                }
+            }
+            ScriptEffect::Dance(user) => {
+                if let Some(entity) = avatar(&user) {
+                    commands.entity(entity).insert(Dance {
+                        until: now + DANCE_DURATION,
+                    });
+                }
+            }
+            ScriptEffect::Tint(user, color) => {
+                let Some(entity) = avatar(&user) else {
+                    continue;
+                };
+                if avatar_query.contains(entity) {
+                    commands.entity(entity).insert(BaseTint(color.with_alpha(1.0)));
+                }
+            }
+            ScriptEffect::Banner(text) => {
+                // A new banner replaces the one that's up
+                for (entity, _) in banner_query.iter() {
+                    commands.entity(entity).despawn_recursive();
+                }
+                commands
+                    .spawn((
+                        NodeBundle {
+                            style: Style {
+                                position_type: PositionType::Absolute,
+                                top: Val::Px(16.0),
+                                width: Val::Percent(100.0),
+                                justify_content: JustifyContent::Center,
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        ScriptBanner { shown: now },
+                    ))
+                    .with_children(|builder| {
+                        builder.spawn(
+                            TextBundle::from_section(
+                                text,
+                                TextStyle {
+                                    font: asset_server.load(&config.font_url),
+                                    font_size: config.font_size * 1.5,
+                                    color: Color::WHITE,
+                                },
+                            )
+                            .with_style(Style {
+                                padding: UiRect::all(Val::Px(8.0)),
+                                ..default()
+                            })
+                            .with_background_color(Color::BLACK.with_alpha(0.6)),
+                        );
+                    });
+            }
+            ScriptEffect::SpawnExtra { user, message } => {
+                let name = user.trim_start_matches('@').to_lowercase();
+                if !chat_sender.send(ChatMessage::twitch(&name, &message)) {
+                    warn!("Chat is too busy for a script to spawn {}", user);
+                }
+            }
+        }
+    }
+}
+
+// Keep dancing minawan hopping until their dance is over. They stand still while the frame
+// budget has shed dances.
+pub(crate) fn animate_dances(
+    mut commands: Commands,
+    policy: Res<EffectPolicy>,
+    dance_query: Query<(Entity, &Transform, &Dance), Without<Wave>>,
+) {
+    let now = Instant::now();
+    for (entity, transform, dance) in dance_query.iter() {
+        if now >= dance.until {
+            commands.entity(entity).remove::<Dance>();
+        } else if policy.allows(Cosmetic::Dances) {
+            commands.entity(entity).insert(Wave {
+                started: now,
+                base_y: transform.translation.y,
+            });
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use std::time::Duration;
+
+use bevy::{
+    color::{Color, Srgba},
+    prelude::{
+        default, Commands, Component, DespawnRecursiveExt, Entity, Event, EventReader, Local, Query,
+        Res, ResMut, With,
+    },
+    window::{Window, WindowResolution},
+};
+use bevy_egui::{
+    egui::{self, Slider},
+    EguiContext,
+};
+use log::{info, warn};
+
+use crate::{
+    config::{save_config, Config},
+    opacity::{MAX_OPACITY, MIN_OPACITY},
+    reload::ConfigWatcher,
+};
+
+/// Open the settings window, or close it if it's already open
+#[derive(Event, Debug, Clone)]
+pub(crate) struct ToggleSettingsWindow {}
+
+/// Marker for the settings window. It's a separate OS window so the overlay stays click-through.
+#[derive(Component)]
+pub(crate) struct SettingsWindow {}
+
+// Open or close the settings window
+pub(crate) fn toggle_settings_window(
+    mut commands: Commands,
+    mut events: EventReader<ToggleSettingsWindow>,
+    window_query: Query<Entity, With<SettingsWindow>>,
+) {
+    // Several toggles in one frame cancel out in pairs
+    if events.read().count() % 2 == 0 {
+        return;
+    }
+
+    match window_query.get_single() {
+        Ok(window) => {
+            info!("Closing settings window");
+            commands.entity(window).despawn_recursive();
+        }
+        Err(_) => {
+            info!("Opening settings window");
+            commands.spawn((
+                Window {
+                    title: "Minawan Watch Party Settings".to_string(),
+                    resolution: WindowResolution::new(380.0, 460.0),
+                    ..default()
+                },
+                SettingsWindow {},
+            ));
+        }
+    }
+}
+
+/// Colour picker for a config colour, returns whether it changed
+fn color_edit(ui: &mut egui::Ui, label: &str, color: &mut Color) -> bool {
+    let srgba = color.to_srgba();
+    let mut rgba = [srgba.red, srgba.green, srgba.blue, srgba.alpha];
+    let changed = ui
+        .horizontal(|ui| {
+            ui.label(label);
+            ui.color_edit_button_rgba_unmultiplied(&mut rgba).changed()
+        })
+        .inner;
+    if changed {
+        *color = Color::Srgba(Srgba::new(rgba[0], rgba[1], rgba[2], rgba[3]));
+    }
+    changed
+}
+
+// Draw the settings window, changes apply to the running overlay straight away
+pub(crate) fn settings_ui(
+    mut context_query: Query<&mut EguiContext, With<SettingsWindow>>,
+    mut config: ResMut<Config>,
+    watcher: Res<ConfigWatcher>,
+    mut save_status: Local<String>,
+) {
+    let Ok(mut context) = context_query.get_single_mut() else {
+        return;
+    };
+
+    // Only flag the config as changed when a value actually changes, systems react to that
+    let mut changed = false;
+    egui::CentralPanel::default().show(context.get_mut(), |ui| {
+        let config = config.bypass_change_detection();
+
+        ui.heading("Avatars");
+        changed |= ui
+            .add(Slider::new(&mut config.scale, 0.25..=3.0).text("Scale"))
+            .changed();
+        changed |= ui
+            .add(Slider::new(&mut config.floor_offset, 0.0..=400.0).text("Floor offset"))
+            .changed();
+        changed |= ui
+            .add(Slider::new(&mut config.avatar_move_speed, 0.0..=500.0).text("Walk speed"))
+            .changed();
+        changed |= ui
+            .add(Slider::new(&mut config.opacity, MIN_OPACITY..=MAX_OPACITY).text("Opacity"))
+            .changed();
+
+        ui.separator();
+        ui.heading("Messages");
+        let mut message_secs = config.message_despawn_time.as_secs_f32();
+        if ui
+            .add(Slider::new(&mut message_secs, 1.0..=60.0).text("Message duration (s)"))
+            .changed()
+        {
+            config.message_despawn_time = Duration::from_secs_f32(message_secs);
+            changed = true;
+        }
+        changed |= ui
+            .add(Slider::new(&mut config.font_size, 8.0..=64.0).text("Font size"))
+            .changed();
+        changed |= ui
+            .add(Slider::new(&mut config.message_box_width, 50.0..=600.0).text("Bubble width"))
+            .changed();
+        changed |= color_edit(ui, "Bubble colour", &mut config.bubble_color);
+        changed |= color_edit(ui, "Text colour", &mut config.text_color);
+
+        ui.separator();
+        ui.heading("Shared Chat");
+        changed |= ui
+            .checkbox(&mut config.show_shared_chat, "Show shared chat messages")
+            .changed();
+        changed |= color_edit(ui, "Border colour", &mut config.shared_chat_border_color);
+
+        ui.separator();
+        if ui.button("Save").clicked() {
+            *save_status = match save_config(watcher.path(), config) {
+                Ok(()) => format!("Saved to {}", watcher.path()),
+                Err(err) => {
+                    warn!("Failed to save settings: {}", err);
+                    err
+                }
+            };
+        }
+        if !save_status.is_empty() {
+            ui.label(save_status.as_str());
+        }
+    });
+
+    if changed {
+        config.set_changed();
+    }
+}
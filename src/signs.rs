@@ -0,0 +1,324 @@
+use std::{collections::HashSet, time::Instant};
+
+use bevy::{
+    asset::AssetServer,
+    color::Color,
+    hierarchy::{BuildChildren, ChildBuilder, DespawnRecursiveExt, Parent},
+    math::{Vec2, Vec3},
+    prelude::{
+        default, Commands, Component, Entity, Event, EventReader, Query, Res, SpatialBundle, Transform, With,
+    },
+    sprite::{Anchor, Sprite, SpriteBundle, TextureAtlas},
+    text::{Text, Text2dBundle, TextStyle},
+};
+use log::info;
+use vleue_kinetoscope::AnimatedImageBundle;
+
+use crate::{
+    config::Config,
+    eventsub::EventSubEvent,
+    sizes::{avatar_size, head_y, HeadOffset, SizeClass},
+    AppState, EmoteStorage, UserMarker,
+};
+
+/// Over every minawan, bubble and crown so it can be read, under the weather
+const SIGN_Z: f32 = 6.0;
+/// Widest the text on a sign gets, longer text is shrunk to fit
+const MAX_TEXT_WIDTH: f32 = 160.0;
+/// Space between the text and the edge of the board
+const BOARD_PADDING: f32 = 6.0;
+const BOARD_BORDER: f32 = 2.0;
+const STICK_LENGTH: f32 = 24.0;
+const STICK_WIDTH: f32 = 4.0;
+const BOARD_COLOR: Color = Color::srgb(0.76, 0.58, 0.38);
+const BORDER_COLOR: Color = Color::srgb(0.45, 0.3, 0.17);
+const TEXT_COLOR: Color = Color::srgb(0.2, 0.12, 0.05);
+
+/// Take down a user's sign, sent by the sign clear admin command
+#[derive(Event, Debug, Clone)]
+pub(crate) struct ClearSign {
+    pub(crate) user: String,
+}
+
+/// A sign held up by the minawan it's a child of
+#[derive(Component)]
+pub(crate) struct Sign {
+    until: Instant,
+}
+
+/// A run of words or an emote on a sign
+pub(crate) enum Piece {
+    Text(String),
+    Emote { name: String, width: f32 },
+}
+
+/// The text for a sign, with BLOCKED_WORDS starred out and cut to MAX_LENGTH
+fn sign_text(user_input: &str, config: &Config) -> String {
+    let text = user_input
+        .split_whitespace()
+        .map(|word| {
+            let bare: String = word.chars().filter(|c| c.is_alphanumeric()).collect();
+            if config.sign_blocked_words.contains(&bare.to_lowercase()) {
+                "*".repeat(word.chars().count())
+            } else {
+                word.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    text.chars().take(config.sign_max_length).collect::<String>().trim_end().to_string()
+}
+
+/// Split a sign's text into emotes that are ready to draw and the words between them
+pub(crate) fn pieces(text: &str, emote_store: &EmoteStorage, config: &Config) -> Vec<Piece> {
+    let mut pieces = vec![];
+    let mut words: Vec<&str> = vec![];
+    for word in text.split(' ') {
+        if !emote_store.atlas.contains_key(word) && !emote_store.loaded.contains_key(word) {
+            words.push(word);
+            continue;
+        }
+        if !words.is_empty() {
+            pieces.push(Piece::Text(words.join(" ")));
+            words.clear();
+        }
+        let aspect = emote_store
+            .all
+            .get(word)
+            .and_then(|emote| Some(emote.width? as f32 / emote.height?.max(1) as f32))
+            .unwrap_or(1.0);
+        pieces.push(Piece::Emote {
+            name: word.to_string(),
+            width: config.font_height() * aspect,
+        });
+    }
+    if !words.is_empty() {
+        pieces.push(Piece::Text(words.join(" ")));
+    }
+    pieces
+}
+
+pub(crate) fn piece_width(piece: &Piece, config: &Config) -> f32 {
+    match piece {
+        Piece::Text(text) => text.chars().count() as f32 * config.font_width(),
+        Piece::Emote { width, .. } => *width,
+    }
+}
+
+/// Draw an emote on a sign, from the atlas like in messages once it's packed
+pub(crate) fn spawn_emote(parent: &mut ChildBuilder, emote_store: &EmoteStorage, name: &str, size: Vec2, translation: Vec3) {
+    let sprite = Sprite {
+        custom_size: Some(size),
+        ..default()
+    };
+    let transform = Transform::from_translation(translation);
+    if let Some(region) = emote_store.atlas.get(name) {
+        parent.spawn((
+            SpriteBundle {
+                sprite,
+                texture: region.image.clone(),
+                transform,
+                ..default()
+            },
+            TextureAtlas {
+                layout: region.layout.clone(),
+                index: region.index,
+            },
+        ));
+        return;
+    }
+    let Some(loaded) = emote_store.loaded.get(name) else {
+        return;
+    };
+    match (&loaded.animated_image, &loaded.static_image) {
+        (Some(animated_image), _) => {
+            parent.spawn(AnimatedImageBundle {
+                animated_image: animated_image.clone(),
+                sprite,
+                transform,
+                ..default()
+            });
+        }
+        (None, Some(texture)) => {
+            parent.spawn(SpriteBundle {
+                sprite,
+                texture: texture.clone(),
+                transform,
+                ..default()
+            });
+        }
+        (None, None) => {}
+    }
+}
+
+/// Put a sign over a minawan's head, on a stick it holds up
+#[allow(clippy::too_many_arguments)]
+fn spawn_sign(
+    parent: &mut ChildBuilder,
+    text: &str,
+    avatar: &Sprite,
+    size_class: Option<&SizeClass>,
+    until: Instant,
+    config: &Config,
+    asset_server: &AssetServer,
+    emote_store: &EmoteStorage,
+) {
+    let pieces = pieces(text, emote_store, config);
+    let space = config.font_width();
+    let text_width = pieces.iter().map(|piece| piece_width(piece, config)).sum::<f32>()
+        + space * pieces.len().saturating_sub(1) as f32;
+    let scale = (MAX_TEXT_WIDTH / text_width.max(1.0)).min(1.0);
+    let board_size = Vec2::new(text_width * scale, config.font_height() * scale) + Vec2::splat(BOARD_PADDING * 2.0);
+    let center = head_y(avatar, avatar_size(avatar, size_class)) + STICK_LENGTH + board_size.y / 2.0;
+
+    parent
+        .spawn((
+            SpatialBundle::from_transform(Transform::from_xyz(0.0, center, SIGN_Z)),
+            Sign { until },
+            HeadOffset::of(avatar),
+        ))
+        .with_children(|sign| {
+            sign.spawn(SpriteBundle {
+                sprite: Sprite {
+                    color: BORDER_COLOR,
+                    custom_size: Some(Vec2::new(STICK_WIDTH, STICK_LENGTH)),
+                    anchor: Anchor::TopCenter,
+                    ..default()
+                },
+                transform: Transform::from_xyz(0.0, -board_size.y / 2.0, -0.2),
+                ..default()
+            });
+            sign.spawn(SpriteBundle {
+                sprite: Sprite {
+                    color: BORDER_COLOR,
+                    custom_size: Some(board_size + Vec2::splat(BOARD_BORDER * 2.0)),
+                    ..default()
+                },
+                transform: Transform::from_xyz(0.0, 0.0, -0.2),
+                ..default()
+            });
+            sign.spawn(SpriteBundle {
+                sprite: Sprite {
+                    color: BOARD_COLOR,
+                    custom_size: Some(board_size),
+                    ..default()
+                },
+                transform: Transform::from_xyz(0.0, 0.0, -0.1),
+                ..default()
+            });
+            // Laid out at full size and shrunk as a whole
+            sign.spawn(SpatialBundle::from_transform(
+                Transform::from_xyz(-text_width * scale / 2.0, 0.0, 0.0).with_scale(Vec3::splat(scale)),
+            ))
+            .with_children(|line| {
+                let mut x = 0.0;
+                for piece in &pieces {
+                    let width = piece_width(piece, config);
+                    match piece {
+                        Piece::Text(text) => {
+                            line.spawn(Text2dBundle {
+                                text: Text::from_section(
+                                    text.clone(),
+                                    TextStyle {
+                                        font: asset_server.load(&config.font_url),
+                                        font_size: config.font_size,
+                                        color: TEXT_COLOR,
+                                    },
+                                ),
+                                text_anchor: Anchor::CenterLeft,
+                                transform: Transform::from_xyz(x, 0.0, 0.0),
+                                ..default()
+                            });
+                        }
+                        Piece::Emote { name, .. } => spawn_emote(
+                            line,
+                            emote_store,
+                            name,
+                            Vec2::new(width, config.font_height()),
+                            Vec3::new(x + width / 2.0, 0.0, 0.0),
+                        ),
+                    }
+                    x += width + space;
+                }
+            });
+        });
+}
+
+// Hold up signs for redemptions, taking down the one a minawan already holds, and take them
+// down again once they've been up long enough or the sign clear command says so
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn handle_sign_redemptions(
+    mut commands: Commands,
+    mut eventsub_events: EventReader<EventSubEvent>,
+    mut clear_events: EventReader<ClearSign>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    asset_server: Res<AssetServer>,
+    emote_store: Res<EmoteStorage>,
+    sign_query: Query<(Entity, &Parent, &Sign)>,
+    avatar_query: Query<(&Sprite, Option<&SizeClass>), With<UserMarker>>,
+) {
+    let now = Instant::now();
+    let mut taken_down = HashSet::new();
+    let mut take_down = |commands: &mut Commands, holder: Option<Entity>| {
+        for (entity, parent, sign) in sign_query.iter() {
+            let done = holder.map_or(now >= sign.until, |holder| parent.get() == holder);
+            if done && taken_down.insert(entity) {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    };
+
+    for event in clear_events.read() {
+        let user = event.user.trim_start_matches('@').to_lowercase();
+        match app_state.active_users.get(&user) {
+            Some(holder) => {
+                info!("Taking down {}'s sign", user);
+                take_down(&mut commands, Some(holder.entity));
+            }
+            None => info!("{} isn't at the party, there's no sign to take down", user),
+        }
+    }
+    for event in eventsub_events.read() {
+        let EventSubEvent::Redemption {
+            user_login,
+            reward_title,
+            user_input,
+        } = event
+        else {
+            continue;
+        };
+        if !config.sign_reward.as_deref().is_some_and(|reward| reward.eq_ignore_ascii_case(reward_title)) {
+            continue;
+        }
+        let user = user_login.to_lowercase();
+        let Some(entity) = app_state.active_users.get(&user).map(|user| user.entity) else {
+            info!("{} isn't at the party, can't hold up a sign", user);
+            continue;
+        };
+        let text = sign_text(user_input, &config);
+        if text.is_empty() {
+            info!("{} redeemed a sign without any text", user);
+            continue;
+        }
+        info!("{} holds up a sign: {}", user, text);
+        take_down(&mut commands, Some(entity));
+        let (avatar, size_class) = avatar_query
+            .get(entity)
+            .map(|(sprite, size_class)| (sprite.clone(), size_class.copied()))
+            .unwrap_or_default();
+        commands.entity(entity).with_children(|parent| {
+            spawn_sign(
+                parent,
+                &text,
+                &avatar,
+                size_class.as_ref(),
+                now + config.sign_duration,
+                &config,
+                &asset_server,
+                &emote_store,
+            );
+        });
+    }
+    take_down(&mut commands, None);
+}
@@ -0,0 +1,324 @@
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    math::Vec2,
+    prelude::{
+        Children, Commands, Component, Entity, Event, EventReader, Or, Query, Res, ResMut, Resource, Transform,
+        With, Without,
+    },
+    sprite::{Anchor, Sprite},
+};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility::EffectPolicy, api::ApiEvent, config::Config, eventsub::EventSubEvent, giant::Giant, AdjustScale,
+    AppState, MessageSpawnTime, UserMarker,
+};
+
+/// Height of a normal minawan. Avatars stand with their feet where a minawan this tall has them,
+/// and messages and accessories are placed for one this tall.
+pub(crate) const AVATAR_HEIGHT: f32 = 46.0;
+/// How long growing or shrinking to a new size takes
+const RESIZE_TIME: Duration = Duration::from_millis(500);
+
+/// How big a user's minawan is drawn, kept on the avatar as a component
+#[derive(Component, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SizeClass {
+    Smol,
+    #[default]
+    Normal,
+    Chonk,
+}
+
+impl FromStr for SizeClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "smol" => Ok(SizeClass::Smol),
+            "normal" => Ok(SizeClass::Normal),
+            "chonk" => Ok(SizeClass::Chonk),
+            _ => Err(format!("{:?} isn't smol, normal or chonk", s)),
+        }
+    }
+}
+
+impl SizeClass {
+    /// Height of the avatar in pixels
+    pub(crate) fn height(self) -> f32 {
+        match self {
+            SizeClass::Smol => 32.0,
+            SizeClass::Normal => AVATAR_HEIGHT,
+            SizeClass::Chonk => 72.0,
+        }
+    }
+}
+
+/// Change a user's size, sent by the size admin command
+#[derive(Event, Debug, Clone)]
+pub(crate) struct ChangeSize {
+    pub(crate) user: String,
+    pub(crate) size: SizeClass,
+}
+
+/// The size each user picked, saved with the party snapshot. Normal sized users aren't kept.
+#[derive(Resource, Default)]
+pub(crate) struct AvatarSizes {
+    chosen: HashMap<String, SizeClass>,
+}
+
+impl AvatarSizes {
+    pub(crate) fn new(chosen: HashMap<String, SizeClass>) -> Self {
+        Self { chosen }
+    }
+
+    pub(crate) fn chosen(&self) -> &HashMap<String, SizeClass> {
+        &self.chosen
+    }
+
+    /// Forget the size a Twitch user picked, whatever the case of their name
+    pub(crate) fn forget(&mut self, login: &str) {
+        self.chosen.retain(|user, _| !user.eq_ignore_ascii_case(login));
+    }
+}
+
+/// A minawan growing or shrinking to a new size
+#[derive(Component)]
+pub(crate) struct Resizing {
+    from: f32,
+    to: f32,
+    started: Instant,
+}
+
+/// How far something on a minawan's head was moved up from where it sits on a normal one
+#[derive(Component)]
+pub(crate) struct HeadOffset(f32);
+
+impl HeadOffset {
+    /// For something placed on the head of an avatar as it's drawn now, so it isn't moved again
+    pub(crate) fn of(sprite: &Sprite) -> Self {
+        Self(head_offset(sprite))
+    }
+}
+
+/// Anchor that keeps the feet of an avatar `height` tall where a normal minawan's are
+pub(crate) fn feet_anchor(height: f32) -> Anchor {
+    Anchor::Custom(Vec2::new(0.0, AVATAR_HEIGHT / 2.0 / height - 0.5))
+}
+
+/// Size an avatar is drawn at, or the height of its size class while its image is still loading
+pub(crate) fn avatar_size(sprite: &Sprite, size_class: Option<&SizeClass>) -> Vec2 {
+    sprite
+        .custom_size
+        .unwrap_or_else(|| Vec2::splat(size_class.copied().unwrap_or_default().height()))
+}
+
+/// Top of an avatar's head, above its translation
+pub(crate) fn head_y(sprite: &Sprite, fallback: Vec2) -> f32 {
+    let height = sprite.custom_size.unwrap_or(fallback).y;
+    (0.5 - sprite.anchor.as_vec().y) * height
+}
+
+/// Bottom of an avatar's feet, below its translation, so negative
+pub(crate) fn feet_y(sprite: &Sprite, fallback: Vec2) -> f32 {
+    let height = sprite.custom_size.unwrap_or(fallback).y;
+    -(0.5 + sprite.anchor.as_vec().y) * height
+}
+
+/// How much higher an avatar's head is than a normal minawan's
+fn head_offset(sprite: &Sprite) -> f32 {
+    head_y(sprite, Vec2::splat(AVATAR_HEIGHT)) - AVATAR_HEIGHT / 2.0
+}
+
+/// The size a redemption asks for, from the first word of the text that names one
+fn choose_size(user_input: &str) -> Option<SizeClass> {
+    user_input
+        .split(|c: char| !c.is_alphanumeric())
+        .find_map(|word| word.parse().ok())
+}
+
+// Change sizes for redemptions and the size command. A minawan at the party grows or shrinks
+// to it, the choice is kept for when the user is back.
+pub(crate) fn handle_size_changes(
+    mut commands: Commands,
+    mut size_events: EventReader<ChangeSize>,
+    mut eventsub_events: EventReader<EventSubEvent>,
+    mut sizes: ResMut<AvatarSizes>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+) {
+    let redemptions = eventsub_events.read().filter_map(|event| match event {
+        EventSubEvent::Redemption {
+            user_login,
+            reward_title,
+            user_input,
+        } if config.size_reward.as_deref().is_some_and(|reward| reward.eq_ignore_ascii_case(reward_title)) => {
+            match choose_size(user_input) {
+                Some(size) => Some((user_login.to_lowercase(), size)),
+                None => {
+                    warn!(
+                        "{} redeemed {} but {:?} isn't smol, normal or chonk, nothing changes",
+                        user_login, reward_title, user_input
+                    );
+                    None
+                }
+            }
+        }
+        _ => None,
+    });
+    let changes: Vec<(String, SizeClass)> = size_events
+        .read()
+        .map(|event| (event.user.trim_start_matches('@').to_lowercase(), event.size))
+        .chain(redemptions)
+        .collect();
+
+    for (user, size) in changes {
+        info!("{} is {:?} now", user, size);
+        if let Some(user) = app_state.active_users.get(&user) {
+            commands.entity(user.entity).insert(size);
+        }
+        if size == SizeClass::Normal {
+            sizes.chosen.remove(&user);
+        } else {
+            sizes.chosen.insert(user, size);
+        }
+    }
+}
+
+// Give minawan the size their user picked when they arrive
+pub(crate) fn apply_saved_sizes(
+    mut commands: Commands,
+    mut party_events: EventReader<ApiEvent>,
+    sizes: Res<AvatarSizes>,
+    app_state: Res<AppState>,
+) {
+    for event in party_events.read() {
+        let ApiEvent::UserSpawned { user } = event else {
+            continue;
+        };
+        let Some(size) = sizes.chosen.get(user) else {
+            continue;
+        };
+        if let Some(user) = app_state.active_users.get(user) {
+            commands.entity(user.entity).insert(*size);
+        }
+    }
+}
+
+// Grow and shrink minawan to their user's size, feet staying on the floor. Giants wait until
+// they're back to normal, and with REDUCED_MOTION the new size is taken right away.
+pub(crate) fn resize_avatars(
+    mut commands: Commands,
+    policy: Res<EffectPolicy>,
+    mut avatar_query: Query<
+        (Entity, &SizeClass, &mut Sprite, Option<&Resizing>),
+        (With<UserMarker>, Without<AdjustScale>, Without<Giant>),
+    >,
+) {
+    let now = Instant::now();
+    for (entity, size_class, mut sprite, resizing) in avatar_query.iter_mut() {
+        let Some(size) = sprite.custom_size else {
+            continue;
+        };
+        let target = size_class.height();
+        let height = match resizing {
+            Some(resizing) if resizing.to == target && policy.motion() => {
+                let progress = (now - resizing.started).as_secs_f32() / RESIZE_TIME.as_secs_f32();
+                if progress >= 1.0 {
+                    commands.entity(entity).remove::<Resizing>();
+                    target
+                } else {
+                    let eased = progress * progress * (3.0 - 2.0 * progress);
+                    resizing.from + (target - resizing.from) * eased
+                }
+            }
+            // Picked another size halfway, it heads there from where it got to
+            _ if size.y != target && policy.motion() => {
+                commands.entity(entity).insert(Resizing {
+                    from: size.y,
+                    to: target,
+                    started: now,
+                });
+                continue;
+            }
+            Some(_) => {
+                commands.entity(entity).remove::<Resizing>();
+                target
+            }
+            None if size.y != target => target,
+            None => continue,
+        };
+        sprite.custom_size = Some(size * (height / size.y));
+        sprite.anchor = feet_anchor(height);
+    }
+}
+
+// Keep messages and what sits on a minawan's head above it, however tall it's drawn. They're
+// placed for a normal minawan and moved up or down by the difference.
+#[allow(clippy::type_complexity)]
+pub(crate) fn follow_heads(
+    mut commands: Commands,
+    avatar_query: Query<(&Sprite, &Children), With<UserMarker>>,
+    mut child_query: Query<(&mut Transform, Option<&HeadOffset>), Or<(With<MessageSpawnTime>, With<HeadOffset>)>>,
+) {
+    for (sprite, children) in avatar_query.iter() {
+        if sprite.custom_size.is_none() {
+            continue;
+        }
+        let offset = head_offset(sprite);
+        for child in children.iter() {
+            let Ok((mut transform, applied)) = child_query.get_mut(*child) else {
+                continue;
+            };
+            let applied = applied.map_or(0.0, |applied| applied.0);
+            if applied != offset {
+                transform.translation.y += offset - applied;
+                commands.entity(*child).insert(HeadOffset(offset));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::{system::RunSystemOnce, world::World};
+
+    use super::*;
+
+    fn avatar(world: &mut World, size: SizeClass) -> Entity {
+        let sprite = Sprite {
+            custom_size: Some(Vec2::new(61.0, AVATAR_HEIGHT)),
+            ..Default::default()
+        };
+        world.spawn((UserMarker {}, size, sprite)).id()
+    }
+
+    fn height(world: &World, avatar: Entity) -> Option<f32> {
+        world.get::<Sprite>(avatar)?.custom_size.map(|size| size.y)
+    }
+
+    #[test]
+    fn avatars_grow_to_the_size_on_them() {
+        let mut world = World::new();
+        world.insert_resource(EffectPolicy {
+            reduced_motion: true,
+            ..Default::default()
+        });
+        let chonk = avatar(&mut world, SizeClass::Chonk);
+        let normal = avatar(&mut world, SizeClass::Normal);
+        world.run_system_once(resize_avatars);
+
+        assert_eq!(height(&world, chonk), Some(SizeClass::Chonk.height()));
+        assert_eq!(height(&world, normal), Some(AVATAR_HEIGHT));
+
+        world.entity_mut(chonk).insert(SizeClass::Smol);
+        world.run_system_once(resize_avatars);
+        assert_eq!(height(&world, chonk), Some(SizeClass::Smol.height()));
+    }
+}
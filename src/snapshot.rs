@@ -0,0 +1,315 @@
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    app::AppExit,
+    asset::AssetServer,
+    color::{Alpha, Color, Srgba},
+    prelude::{
+        Camera, Commands, EventReader, EventWriter, Handle, Image, Local, Query, Res,
+        ResMut, Resource, Transform, With,
+    },
+};
+use chrono::NaiveDate;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessories::Accessories,
+    ambient::BaseTint,
+    cheers::{counting_month, BitTotals},
+    api::ApiEvent,
+    config::Config,
+    greetings::{GreetedToday, Greetings},
+    handles::HandleCache,
+    leaderboard::{counting_day, MessageCounts},
+    marquee::Marquee,
+    moderation::{SavedTimeout, Timeouts},
+    pool::EntityPools,
+    sizes::{AvatarSizes, SizeClass},
+    state::{read_state_file, unix_now, write_state_file},
+    streaks::ChatStreaks,
+    users::{clamp_to_ranges, spawn_user},
+    AppState, ChatMessage, OverlayCamera, Platform, User,
+};
+
+const SNAPSHOT_FILE: &str = "party.toml";
+
+/// Who was at the party, written now and then so a restart or crash can bring them back
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct PartySnapshot {
+    /// Seconds since the Unix epoch
+    saved_at: u64,
+    users: Vec<SavedUser>,
+    /// Days each user chatted on, kept however old the party is
+    chat_days: HashMap<String, BTreeSet<NaiveDate>>,
+    /// Today's messages per user, for a leaderboard that counts the whole day
+    message_counts: Option<MessageCounts>,
+    /// This month's bits per user, when the bits crown counts the whole month
+    bit_totals: Option<BitTotals>,
+    /// The accessory each user has on, whether or not they're at the party
+    accessories: HashMap<String, String>,
+    /// The size each user picked, whether or not they're at the party
+    sizes: HashMap<String, SizeClass>,
+    /// What the marquee says, kept however old the party is
+    marquee: Option<String>,
+    /// Who was greeted today, so they aren't greeted again after a restart
+    greeted: Option<GreetedToday>,
+    /// Timed out chatters, so a restart doesn't let them back early
+    timeouts: Vec<SavedTimeout>,
+}
+
+/// What the last run left behind
+pub(crate) struct Restored {
+    pub(crate) party: PendingRestore,
+    pub(crate) streaks: ChatStreaks,
+    pub(crate) message_counts: MessageCounts,
+    pub(crate) bit_totals: BitTotals,
+    pub(crate) accessories: Accessories,
+    pub(crate) sizes: AvatarSizes,
+    pub(crate) marquee: Marquee,
+    pub(crate) greetings: Greetings,
+    pub(crate) timeouts: Timeouts,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct SavedUser {
+    /// Key in AppState, which says which platform they're from
+    key: String,
+    name: String,
+    /// Path or URL of the avatar they had, so a random avatar stays theirs
+    avatar: Option<String>,
+    x: f32,
+    /// Colour a script tinted them, as hex
+    tint: Option<String>,
+    /// Seconds since their last message, so they leave when they would have
+    idle_secs: u64,
+}
+
+/// Users from the snapshot still to be brought back, one every STAGGER_MILIS
+#[derive(Resource, Default)]
+pub(crate) struct PendingRestore {
+    users: VecDeque<SavedUser>,
+    next: Option<Instant>,
+}
+
+impl PendingRestore {
+    /// Take a Twitch user out of the party still to be restored, returning where they stood
+    pub(crate) fn take(&mut self, login: &str) -> Option<f32> {
+        let index = self.users.iter().position(|saved| saved.key.eq_ignore_ascii_case(login))?;
+        self.users.remove(index).map(|saved| saved.x)
+    }
+
+    /// Whether a user is still to be restored, their key matching in any case
+    pub(crate) fn contains(&self, key: &str) -> bool {
+        self.users.iter().any(|saved| saved.key.eq_ignore_ascii_case(key))
+    }
+}
+
+/// Platform and name from a user's AppState key
+pub(crate) fn split_key(key: &str) -> (Platform, &str) {
+    if let Some(name) = key.strip_prefix("youtube:") {
+        (Platform::YouTube, name)
+    } else if let Some(name) = key.strip_prefix("kick:") {
+        (Platform::Kick, name)
+    } else {
+        (Platform::Twitch, key)
+    }
+}
+
+/// Read the party, chat days, message counts, bits, accessories, sizes, marquee, greetings and timeouts saved by the
+/// last run. The party only comes back if it's recent enough, a missing or broken snapshot leaves nothing to restore.
+pub(crate) fn load_snapshot(config: &Config) -> Restored {
+    let snapshot: PartySnapshot = read_state_file(SNAPSHOT_FILE).unwrap_or_default();
+    let mut restored = Restored {
+        party: PendingRestore::default(),
+        streaks: ChatStreaks::new(snapshot.chat_days, config.today()),
+        message_counts: MessageCounts::restored(snapshot.message_counts, counting_day(config)),
+        bit_totals: BitTotals::restored(snapshot.bit_totals, counting_month(config)),
+        accessories: Accessories::new(snapshot.accessories),
+        sizes: AvatarSizes::new(snapshot.sizes),
+        marquee: Marquee::new(snapshot.marquee),
+        greetings: Greetings::restored(snapshot.greeted, config.today()),
+        timeouts: Timeouts::restored(snapshot.timeouts),
+    };
+    if !config.snapshot_enabled {
+        return restored;
+    }
+    let age = Duration::from_secs(unix_now().saturating_sub(snapshot.saved_at));
+    if age > config.snapshot_max_age {
+        info!(
+            "Party snapshot is {} minutes old, starting with an empty party",
+            age.as_secs() / 60
+        );
+        return restored;
+    }
+    if !snapshot.users.is_empty() {
+        info!(
+            "Bringing back {} minawan from {} seconds ago",
+            snapshot.users.len(),
+            age.as_secs()
+        );
+    }
+    // Pinned users first, they don't wait their turn
+    let mut users = snapshot.users;
+    users.sort_by_key(|saved| !config.is_pinned(&saved.key));
+    restored.party = PendingRestore {
+        users: users.into(),
+        next: None,
+    };
+    restored
+}
+
+/// Run condition for the restore system
+pub(crate) fn restore_pending(restore: Res<PendingRestore>) -> bool {
+    !restore.users.is_empty()
+}
+
+// Respawn the saved party where they were standing, pinned minawan straight away and the rest
+// one at a time
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn restore_party(
+    mut commands: Commands,
+    mut restore: ResMut<PendingRestore>,
+    mut pools: ResMut<EntityPools>,
+    mut handles: ResMut<HandleCache>,
+    asset_server: Res<AssetServer>,
+    mut app_state: ResMut<AppState>,
+    config: Res<Config>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    mut api_events: EventWriter<ApiEvent>,
+) {
+    let now = Instant::now();
+    if restore.next.is_some_and(|next| now < next) {
+        return;
+    }
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+    // Anyone who chatted since startup is already back
+    let Some(saved) = restore.users.pop_front() else {
+        return;
+    };
+    if !config.is_pinned(&saved.key) {
+        restore.next = Some(now + config.snapshot_stagger);
+    }
+    if app_state.active_users.contains_key(&saved.key) {
+        return;
+    }
+
+    let (platform, name) = split_key(&saved.key);
+    let entity = spawn_user(
+        &mut commands,
+        &mut pools,
+        &mut handles,
+        &asset_server,
+        &ChatMessage::synthetic(platform, name),
+        &config,
+        rect,
+    );
+    let x = clamp_to_ranges(saved.x, &config.walk_ranges(rect.max.x));
+    let mut entity_commands = commands.entity(entity);
+    entity_commands.insert(Transform::from_xyz(x, config.floor_y(rect.max.y), 0.0));
+    if let Some(avatar) = &saved.avatar {
+        entity_commands.insert(handles.load::<Image>(&asset_server, avatar));
+    }
+    if let Some(color) = saved.tint.as_deref().and_then(|tint| Srgba::hex(tint).ok()) {
+        entity_commands.insert(BaseTint(color.into()));
+    }
+
+    api_events.send(ApiEvent::UserSpawned {
+        user: saved.key.clone(),
+    });
+    let last_message = now
+        .checked_sub(Duration::from_secs(saved.idle_secs))
+        .unwrap_or(now);
+    app_state.insert_user(saved.key, User::new(entity, saved.name, last_message));
+}
+
+// Save the party, chat days, today's message counts, the month's bits, accessories, sizes, the marquee, today's
+// greetings and timeouts every AUTOSAVE_SECS, in case of a crash, and when the app exits
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn save_snapshot(
+    exit_events: EventReader<AppExit>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    asset_server: Res<AssetServer>,
+    restore: Res<PendingRestore>,
+    streaks: Res<ChatStreaks>,
+    accessories: Res<Accessories>,
+    sizes: Res<AvatarSizes>,
+    marquee: Res<Marquee>,
+    greetings: Res<Greetings>,
+    avatar_query: Query<(&Transform, Option<&BaseTint>, &Handle<Image>)>,
+    mut last_save: Local<Option<Instant>>,
+) {
+    if !config.snapshot_enabled
+        && !config.streaks_enabled
+        && !config.greetings_enabled
+        && !config.leaderboard_daily
+        && !config.bits_monthly
+        && config.accessory_rewards.is_empty()
+        && accessories.equipped().is_empty()
+        && config.size_reward.is_none()
+        && sizes.chosen().is_empty()
+        && marquee.text().is_none()
+        && app_state.timeouts.is_empty()
+    {
+        return;
+    }
+    let exiting = !exit_events.is_empty();
+    let last_save = last_save.get_or_insert_with(Instant::now);
+    let due =
+        !config.snapshot_autosave.is_zero() && last_save.elapsed() >= config.snapshot_autosave;
+    if !exiting && !due {
+        return;
+    }
+    *last_save = Instant::now();
+
+    let now = Instant::now();
+    let users = app_state.active_users.iter().filter_map(|(key, user)| {
+        let (transform, base_tint, texture) = avatar_query.get(user.entity).ok()?;
+        // The colour a script gave them, not the time of day's
+        let tint = base_tint.map_or(Color::WHITE, |base| base.0.with_alpha(1.0));
+        Some(SavedUser {
+            key: key.clone(),
+            name: user.name.clone(),
+            avatar: asset_server
+                .get_path(texture.id())
+                .map(|path| path.to_string()),
+            x: transform.translation.x,
+            tint: (tint != Color::WHITE).then(|| tint.to_srgba().to_hex()),
+            idle_secs: now.duration_since(user.last_message_time).as_secs(),
+        })
+    });
+    // Users not yet restored are still part of the party. Without ENABLED only what's kept about
+    // users is saved.
+    let snapshot = PartySnapshot {
+        saved_at: unix_now(),
+        users: if config.snapshot_enabled {
+            users.chain(restore.users.iter().cloned()).collect()
+        } else {
+            vec![]
+        },
+        chat_days: streaks.days().clone(),
+        message_counts: app_state.message_counts.daily(),
+        bit_totals: app_state.bit_totals.monthly(),
+        accessories: accessories.equipped().clone(),
+        sizes: sizes.chosen().clone(),
+        marquee: marquee.text().cloned(),
+        greeted: greetings.greeted(),
+        timeouts: app_state.timeouts.saved(),
+    };
+
+    match write_state_file(SNAPSHOT_FILE, &snapshot) {
+        Ok(path) => debug!(
+            "Saved {} minawan to {}",
+            snapshot.users.len(),
+            path.display()
+        ),
+        Err(err) => warn!("Failed to save the party snapshot: {}", err),
+    }
+}
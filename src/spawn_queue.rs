@@ -0,0 +1,167 @@
+use std::{collections::VecDeque, time::Instant};
+
+use bevy::{
+    asset::AssetServer,
+    prelude::{Camera, Commands, EventWriter, Query, Res, ResMut, With},
+};
+use log::{debug, info};
+
+use crate::{
+    api::ApiEvent, config::Config, handles::HandleCache, messages::display_message, moderation::return_to_spot,
+    pool::EntityPools, users::{make_room, spawn_user}, AppState, ChatMessage, EmoteStorage, OverlayCamera, User,
+};
+
+/// A new chatter waiting for their minawan, with the message it arrives saying
+struct QueuedSpawn {
+    key: String,
+    message: ChatMessage,
+    queued_at: Instant,
+}
+
+/// New chatters over SPAWNS_PER_SEC, spawned one after another as the budget allows. The
+/// budget fills back up at SPAWNS_PER_SEC and holds at most a second's worth, so a few can
+/// arrive together before the rest are spread out.
+#[derive(Default)]
+pub(crate) struct SpawnQueue {
+    waiting: VecDeque<QueuedSpawn>,
+    budget: f32,
+    refilled: Option<Instant>,
+    /// Chatters left out because the queue was full or their turn came too late
+    dropped: u64,
+}
+
+impl SpawnQueue {
+    fn refill(&mut self, now: Instant, config: &Config) {
+        let elapsed = self.refilled.map_or(1.0, |refilled| now.duration_since(refilled).as_secs_f32());
+        self.budget = (self.budget + elapsed * config.spawn_rate).min(config.spawn_rate.max(1.0));
+        self.refilled = Some(now);
+    }
+
+    /// Whether a new minawan can spawn right away, taking it from the budget if so. Nobody
+    /// jumps the queue.
+    pub(crate) fn admit(&mut self, now: Instant, config: &Config) -> bool {
+        if config.spawn_rate <= 0.0 {
+            return true;
+        }
+        if !self.waiting.is_empty() {
+            return false;
+        }
+        self.take(now, config)
+    }
+
+    fn take(&mut self, now: Instant, config: &Config) -> bool {
+        self.refill(now, config);
+        if self.budget < 1.0 {
+            return false;
+        }
+        self.budget -= 1.0;
+        true
+    }
+
+    /// Line a new chatter up for their minawan. One already waiting arrives with their latest
+    /// message instead, one who'd arrive after their message is gone is left out.
+    pub(crate) fn push(&mut self, message: ChatMessage, now: Instant, config: &Config) {
+        let key = message.user_key();
+        if let Some(queued) = self.waiting.iter_mut().find(|queued| queued.key == key) {
+            queued.message = message;
+            queued.queued_at = now;
+            return;
+        }
+        let wait = self.waiting.len() as f32 / config.spawn_rate.max(f32::EPSILON);
+        if self.waiting.len() >= config.spawn_queue_max || wait > config.message_despawn_time.as_secs_f32() {
+            debug!("Spawn queue is full, leaving out: {}", key);
+            self.dropped += 1;
+            return;
+        }
+        self.waiting.push_back(QueuedSpawn {
+            key,
+            message,
+            queued_at: now,
+        });
+    }
+
+    /// Take a Twitch user out of the queue, whatever the case of their name
+    pub(crate) fn forget(&mut self, login: &str) {
+        self.waiting.retain(|queued| !queued.key.eq_ignore_ascii_case(login));
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.waiting.len()
+    }
+
+    pub(crate) fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+// Spawn the minawan of queued chatters as the SPAWNS_PER_SEC budget allows, each showing the
+// message it was queued with. Ones that waited longer than the message would have stayed up
+// are left out, and the queue is emptied when spawning stops for any reason.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn spawn_queued_users(
+    mut commands: Commands,
+    mut pools: ResMut<EntityPools>,
+    mut handles: ResMut<HandleCache>,
+    asset_server: Res<AssetServer>,
+    mut emote_rec: ResMut<EmoteStorage>,
+    mut app_state: ResMut<AppState>,
+    config: Res<Config>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    mut api_events: EventWriter<ApiEvent>,
+) {
+    if app_state.spawn_queue.waiting.is_empty() {
+        return;
+    }
+    if app_state.offline_idle(&config) || app_state.spawning_paused || app_state.quiet_hours {
+        let queue = &mut app_state.spawn_queue;
+        info!("Spawning stopped, leaving out {} queued chatters", queue.waiting.len());
+        queue.dropped += queue.waiting.len() as u64;
+        queue.waiting.clear();
+        return;
+    }
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+    let now = Instant::now();
+    let zen_mode = app_state.zen_mode;
+    while let Some(queued) = app_state.spawn_queue.waiting.front() {
+        if now.duration_since(queued.queued_at) > config.message_despawn_time {
+            debug!("Waited too long to spawn, leaving out: {}", queued.key);
+            app_state.spawn_queue.waiting.pop_front();
+            app_state.spawn_queue.dropped += 1;
+            continue;
+        }
+        if !app_state.spawn_queue.take(now, &config) {
+            break;
+        }
+        let Some(QueuedSpawn { key, message, .. }) = app_state.spawn_queue.waiting.pop_front() else {
+            break;
+        };
+        // Restored from the snapshot while they waited
+        if app_state.active_users.contains_key(&key) {
+            continue;
+        }
+        make_room(&mut commands, &mut app_state, &mut pools, &config, &mut api_events);
+        let entity = spawn_user(&mut commands, &mut pools, &mut handles, &asset_server, &message, &config, rect);
+        let name = message.name().to_string();
+        if let Some(x) = app_state.timeouts.returning(&key, now, &config) {
+            return_to_spot(&mut commands, entity, x, rect, &config);
+        }
+        api_events.send(ApiEvent::UserSpawned { user: key.clone() });
+        if !zen_mode {
+            api_events.send_batch(ApiEvent::for_message(&key, &message.message, &emote_rec));
+            display_message(
+                &mut commands,
+                &mut pools,
+                &mut handles,
+                &asset_server,
+                &mut emote_rec,
+                &config,
+                entity,
+                message.message,
+                message.origin_room_id.is_some(),
+            );
+        }
+        app_state.insert_user(key, User::new(entity, name, now));
+    }
+}
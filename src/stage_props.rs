@@ -0,0 +1,205 @@
+use bevy::{
+    color::Color,
+    math::{Rect, Vec2},
+    prelude::{
+        default, Camera, Children, Commands, Component, DespawnRecursiveExt, DetectChangesMut, Entity, Has, Local,
+        NodeBundle, Parent, Query, Res, Transform, With, Without,
+    },
+    sprite::Sprite,
+    ui::{BorderColor, PositionType, Style, UiRect, Val, ZIndex},
+};
+
+use crate::{
+    config::Config, debug::DebugOverlay, opacity::BaseAlpha, pool::Pooled, MessageBubble, MessageSpawnTime,
+    OverlayCamera, UserMarker,
+};
+
+/// Space left between a message box and a stage prop it moved away from
+const BUBBLE_GAP: f32 = 4.0;
+const OUTLINE_WIDTH: f32 = 2.0;
+const WALL_OUTLINE_COLOR: Color = Color::srgba(1.0, 0.3, 0.3, 0.8);
+const BEHIND_OUTLINE_COLOR: Color = Color::srgba(0.3, 0.7, 1.0, 0.8);
+
+/// A minawan, or something it carries, while the minawan is behind a stage prop.
+/// `apply_opacity` fades these by BEHIND_OPACITY.
+#[derive(Component)]
+pub(crate) struct BehindProp {}
+
+/// How far a message box was moved sideways to keep clear of stage props
+#[derive(Component)]
+pub(crate) struct PropOffset(f32);
+
+/// Marker for the debug outline of a stage prop
+#[derive(Component)]
+pub(crate) struct StagePropOutline {}
+
+/// World rectangles of the stage props for a window of the given size
+fn prop_rects(config: &Config, size: Vec2) -> Vec<Rect> {
+    config
+        .stage_props
+        .iter()
+        .map(|prop| {
+            let (left, right) = prop.x_range(size.x);
+            let (bottom, top) = prop.y_range(size.y);
+            Rect::new(left, bottom, right, top)
+        })
+        .collect()
+}
+
+/// Whether two rectangles overlap by more than a touching edge
+fn overlaps(a: Rect, b: Rect) -> bool {
+    !a.intersect(b).is_empty()
+}
+
+// Fade minawan while they're behind a stage prop that lets them walk behind it. What they carry
+// fades with them, their messages don't.
+pub(crate) fn walk_behind_props(
+    mut commands: Commands,
+    config: Res<Config>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    avatar_query: Query<(Entity, &Transform, Option<&Children>), (With<UserMarker>, Without<Pooled>)>,
+    mut part_query: Query<(Has<BehindProp>, Option<&mut BaseAlpha>), Without<MessageSpawnTime>>,
+) {
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+    let behind_ranges: Vec<(f32, f32)> = config
+        .stage_props
+        .iter()
+        .filter(|prop| prop.behind)
+        .map(|prop| prop.x_range(rect.max.x))
+        .collect();
+    for (avatar, transform, children) in avatar_query.iter() {
+        let x = transform.translation.x;
+        let behind = behind_ranges.iter().any(|(left, right)| (*left..=*right).contains(&x));
+        let parts = std::iter::once(avatar).chain(children.into_iter().flat_map(|children| children.iter().copied()));
+        for part in parts {
+            let Ok((was_behind, base)) = part_query.get_mut(part) else {
+                continue;
+            };
+            if was_behind == behind {
+                continue;
+            }
+            if behind {
+                commands.entity(part).insert(BehindProp {});
+            } else {
+                commands.entity(part).remove::<BehindProp>();
+            }
+            // So apply_opacity works the alpha out again
+            if let Some(mut base) = base {
+                base.set_changed();
+            }
+        }
+    }
+}
+
+// Move message boxes that would cover a stage prop sideways until they're clear of it, over to
+// the side of the speaker away from the prop when that fits on screen and the other way if not.
+#[allow(clippy::type_complexity)]
+pub(crate) fn keep_bubbles_clear_of_props(
+    mut commands: Commands,
+    config: Res<Config>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    avatar_query: Query<&Transform, (With<UserMarker>, Without<MessageBubble>)>,
+    mut bubble_query: Query<(Entity, &Parent, &mut Transform, &Sprite, Option<&PropOffset>), With<MessageBubble>>,
+) {
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+    let props = prop_rects(&config, rect.max);
+    let half_width = rect.max.x / 2.0;
+    for (entity, avatar, mut transform, sprite, applied) in bubble_query.iter_mut() {
+        let applied = applied.map_or(0.0, |applied| applied.0);
+        let (Ok(avatar), Some(size)) = (avatar_query.get(avatar.get()), sprite.custom_size) else {
+            continue;
+        };
+        // Where the box would be without moving it, its translation is its top left corner
+        let top_left = avatar.translation.truncate() + transform.translation.truncate() - Vec2::new(applied, 0.0);
+        let bubble = Rect::from_corners(top_left, top_left + Vec2::new(size.x, -size.y));
+        let clear = |shift: f32| {
+            let moved = Rect::from_center_size(bubble.center() + Vec2::new(shift, 0.0), bubble.size());
+            props.iter().all(|prop| !overlaps(moved, *prop))
+        };
+        let on_screen = |shift: f32| bubble.min.x + shift >= -half_width && bubble.max.x + shift <= half_width;
+
+        let shift = if clear(0.0) {
+            0.0
+        } else {
+            props
+                .iter()
+                .filter(|prop| overlaps(bubble, **prop))
+                .flat_map(|prop| {
+                    let to_left = prop.min.x - BUBBLE_GAP - bubble.max.x;
+                    let to_right = prop.max.x + BUBBLE_GAP - bubble.min.x;
+                    if avatar.translation.x < prop.center().x {
+                        [to_left, to_right]
+                    } else {
+                        [to_right, to_left]
+                    }
+                })
+                .find(|shift| clear(*shift) && on_screen(*shift))
+                .unwrap_or(0.0)
+        };
+        if shift != applied {
+            transform.translation.x += shift - applied;
+            commands.entity(entity).insert(PropOffset(shift));
+        }
+    }
+}
+
+// Draw stage props as outlines while the debug overlay is showing, walls in red and props
+// minawan walk behind in blue. They're drawn again when the window or the regions change.
+pub(crate) fn outline_stage_props(
+    mut commands: Commands,
+    config: Res<Config>,
+    overlay: Res<DebugOverlay>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    outline_query: Query<Entity, With<StagePropOutline>>,
+    mut drawn: Local<Vec<(Rect, bool)>>,
+) {
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+    let size = rect.size();
+    // In UI space, measured down from the top left corner
+    let outlines: Vec<(Rect, bool)> = if overlay.visible {
+        config
+            .stage_props
+            .iter()
+            .map(|prop| {
+                let min = Vec2::new(prop.left.resolve(size.x), prop.top.resolve(size.y));
+                let max = min + Vec2::new(prop.width.resolve(size.x), prop.height.resolve(size.y));
+                (Rect::from_corners(min, max), prop.behind)
+            })
+            .collect()
+    } else {
+        vec![]
+    };
+    if *drawn == outlines {
+        return;
+    }
+
+    for entity in outline_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    for (outline, behind) in outlines.iter() {
+        commands.spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(outline.min.x),
+                    top: Val::Px(outline.min.y),
+                    width: Val::Px(outline.width()),
+                    height: Val::Px(outline.height()),
+                    border: UiRect::all(Val::Px(OUTLINE_WIDTH)),
+                    ..default()
+                },
+                border_color: BorderColor(if *behind { BEHIND_OUTLINE_COLOR } else { WALL_OUTLINE_COLOR }),
+                z_index: ZIndex::Global(i32::MAX - 1),
+                ..default()
+            },
+            StagePropOutline {},
+        ));
+    }
+    *drawn = outlines;
+}
@@ -0,0 +1,313 @@
+use std::time::{Duration, Instant};
+
+use bevy::{
+    asset::AssetServer,
+    color::{Alpha, Color},
+    prelude::{
+        default, BuildChildren, Commands, Component, DespawnRecursiveExt, NodeBundle, Parent, Query, Res, ResMut,
+        Resource,
+    },
+    text::{Text, TextStyle},
+    ui::{
+        node_bundles::TextBundle, AlignItems, BackgroundColor, FlexDirection, JustifyContent, PositionType, Style,
+        UiRect, Val,
+    },
+};
+use log::{info, warn};
+use tokio::sync::mpsc;
+
+use crate::{config::Config, locale::t, AppState, ProgramState};
+
+/// How long a step may go without an answer before it's shown as failed
+const STEP_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long the loading panel takes to fade out once everything is ready
+const FADE_OUT: Duration = Duration::from_secs(1);
+const PANEL_ALPHA: f32 = 0.75;
+const RUNNING_COLOR: Color = Color::srgb(0.8, 0.8, 0.8);
+const DONE_COLOR: Color = Color::srgb(0.6, 1.0, 0.6);
+const FAILED_COLOR: Color = Color::srgb(1.0, 0.45, 0.4);
+
+/// Something the overlay does on startup, listed on the loading panel in this order
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum StartupStep {
+    TwitchChat,
+    ChannelEmotes,
+    EventSub,
+    StreamStatus,
+}
+
+impl StartupStep {
+    fn label(self) -> String {
+        match self {
+            StartupStep::TwitchChat => t!("startup.twitch_chat"),
+            StartupStep::ChannelEmotes => t!("startup.channel_emotes"),
+            StartupStep::EventSub => t!("startup.eventsub"),
+            StartupStep::StreamStatus => t!("startup.stream_status"),
+        }
+    }
+}
+
+/// What came of a step that worked. Translated on the Bevy side, the tasks reporting these can
+/// start before the locale is set.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum StepResult {
+    Ok,
+    /// Connected to chat without a login
+    ReadOnly,
+    EmotesLoaded(usize),
+    Subscribed(usize),
+    /// None of the enabled features needed it
+    NotNeeded,
+    Live,
+    Offline,
+}
+
+impl StepResult {
+    fn text(self) -> String {
+        match self {
+            StepResult::Ok => t!("startup.ok"),
+            StepResult::ReadOnly => t!("startup.read_only"),
+            StepResult::EmotesLoaded(count) => t!("startup.emotes_loaded", count = count),
+            StepResult::Subscribed(count) => t!("startup.subscribed", count = count),
+            StepResult::NotNeeded => t!("startup.not_needed"),
+            StepResult::Live => t!("startup.live"),
+            StepResult::Offline => t!("startup.offline"),
+        }
+    }
+}
+
+/// How far a step has got
+#[derive(Clone, Debug)]
+enum StepState {
+    Running,
+    Done(StepResult),
+    Failed(String),
+}
+
+pub(crate) struct StartupUpdate {
+    step: StartupStep,
+    state: StepState,
+}
+
+/// Lets the tasks started outside Bevy report how their step went. A step can report again,
+/// one that failed and then worked shows as done.
+#[derive(Clone)]
+pub(crate) struct StartupReporter {
+    sender: mpsc::Sender<StartupUpdate>,
+}
+
+impl StartupReporter {
+    pub(crate) fn new(sender: mpsc::Sender<StartupUpdate>) -> Self {
+        Self { sender }
+    }
+
+    pub(crate) fn done(&self, step: StartupStep, result: StepResult) {
+        self.send(step, StepState::Done(result));
+    }
+
+    pub(crate) fn failed(&self, step: StartupStep, reason: String) {
+        self.send(step, StepState::Failed(reason));
+    }
+
+    fn send(&self, step: StartupStep, state: StepState) {
+        // Nothing is listening in Bevy any more, or it's behind and a later report will do
+        let _ = self.sender.try_send(StartupUpdate { step, state });
+    }
+}
+
+/// The startup steps under way and how each went, shown on the loading panel
+#[derive(Resource)]
+pub(crate) struct StartupProgress {
+    receiver: mpsc::Receiver<StartupUpdate>,
+    steps: Vec<(StartupStep, StepState)>,
+    started: Instant,
+    /// The panel needs redrawing
+    changed: bool,
+}
+
+impl StartupProgress {
+    pub(crate) fn new(steps: Vec<StartupStep>, receiver: mpsc::Receiver<StartupUpdate>) -> Self {
+        Self {
+            receiver,
+            steps: steps.into_iter().map(|step| (step, StepState::Running)).collect(),
+            started: Instant::now(),
+            changed: true,
+        }
+    }
+
+    fn settled(&self) -> bool {
+        !self.steps.iter().any(|(_, state)| matches!(state, StepState::Running))
+    }
+
+    fn failed(&self) -> bool {
+        self.steps.iter().any(|(_, state)| matches!(state, StepState::Failed(_)))
+    }
+}
+
+/// The card listing the startup steps, faded out once they're all done
+#[derive(Component)]
+pub(crate) struct LoadingPanel {
+    fading_since: Option<Instant>,
+}
+
+/// A line of text on the loading panel, for one step or the title
+#[derive(Component)]
+pub(crate) struct PanelLine {
+    step: Option<StartupStep>,
+}
+
+fn step_line(step: StartupStep, state: &StepState) -> (String, Color) {
+    match state {
+        StepState::Running => (format!("{}...", step.label()), RUNNING_COLOR),
+        StepState::Done(result) => (format!("{}... {}", step.label(), result.text()), DONE_COLOR),
+        StepState::Failed(reason) => (
+            format!("{}... {}", step.label(), t!("startup.failed", reason = reason)),
+            FAILED_COLOR,
+        ),
+    }
+}
+
+pub(crate) fn setup_loading_panel(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<Config>,
+    progress: Res<StartupProgress>,
+) {
+    if progress.steps.is_empty() {
+        return;
+    }
+    let font = asset_server.load(&config.font_url);
+    let font_size = config.font_size * 0.8;
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Column,
+                            row_gap: Val::Px(4.0),
+                            padding: UiRect::all(Val::Px(12.0)),
+                            ..default()
+                        },
+                        background_color: Color::BLACK.with_alpha(PANEL_ALPHA).into(),
+                        ..default()
+                    },
+                    LoadingPanel { fading_since: None },
+                ))
+                .with_children(|card| {
+                    card.spawn((
+                        TextBundle::from_section(
+                            t!("startup.title"),
+                            TextStyle {
+                                font: font.clone(),
+                                font_size,
+                                color: Color::WHITE,
+                            },
+                        ),
+                        PanelLine { step: None },
+                    ));
+                    for (step, state) in progress.steps.iter() {
+                        let (text, color) = step_line(*step, state);
+                        card.spawn((
+                            TextBundle::from_section(
+                                text,
+                                TextStyle {
+                                    font: font.clone(),
+                                    font_size,
+                                    color,
+                                },
+                            ),
+                            PanelLine { step: Some(*step) },
+                        ));
+                    }
+                });
+        });
+}
+
+// Take in what the startup tasks report and show it on the loading panel. Once no step is
+// under way the party is running, and the panel fades out unless a step failed.
+pub(crate) fn update_startup_progress(
+    mut commands: Commands,
+    mut progress: ResMut<StartupProgress>,
+    mut app_state: ResMut<AppState>,
+    mut panel_query: Query<(&Parent, &mut LoadingPanel, &mut BackgroundColor)>,
+    mut line_query: Query<(&mut Text, &PanelLine)>,
+) {
+    let progress = &mut *progress;
+    while let Ok(update) = progress.receiver.try_recv() {
+        let Some((_, state)) = progress.steps.iter_mut().find(|(step, _)| *step == update.step) else {
+            continue;
+        };
+        if let StepState::Failed(reason) = &update.state {
+            warn!("Startup step {:?} failed: {}", update.step, reason);
+        }
+        *state = update.state;
+        progress.changed = true;
+    }
+    if progress.started.elapsed() >= STEP_TIMEOUT {
+        for (step, state) in progress.steps.iter_mut() {
+            if matches!(state, StepState::Running) {
+                warn!("Startup step {:?} didn't answer in {} seconds", step, STEP_TIMEOUT.as_secs());
+                *state = StepState::Failed(t!("startup.timed_out", secs = STEP_TIMEOUT.as_secs()));
+                progress.changed = true;
+            }
+        }
+    }
+    if matches!(app_state.program_state, ProgramState::Loading) && progress.settled() {
+        info!("Startup finished");
+        app_state.program_state = ProgramState::Running;
+    }
+
+    let Ok((parent, mut loading_panel, mut background)) = panel_query.get_single_mut() else {
+        return;
+    };
+    let ready = matches!(app_state.program_state, ProgramState::Running) && !progress.failed();
+    if ready && loading_panel.fading_since.is_none() {
+        loading_panel.fading_since = Some(Instant::now());
+    }
+    let fade = match loading_panel.fading_since {
+        Some(since) if ready => since.elapsed().as_secs_f32() / FADE_OUT.as_secs_f32(),
+        _ => 0.0,
+    };
+    if fade >= 1.0 {
+        // Along with the full screen node it's centered in
+        commands.entity(parent.get()).despawn_recursive();
+        return;
+    }
+    // A step that fails while it's fading brings it back
+    if !ready {
+        loading_panel.fading_since = None;
+    }
+    if !progress.changed && fade == 0.0 {
+        return;
+    }
+    progress.changed = false;
+    let alpha = 1.0 - fade;
+    background.0 = Color::BLACK.with_alpha(PANEL_ALPHA * alpha);
+    for (mut text, line) in line_query.iter_mut() {
+        let Some(section) = text.sections.first_mut() else {
+            continue;
+        };
+        let (value, color) = match line.step {
+            Some(step) => progress
+                .steps
+                .iter()
+                .find(|(listed, _)| *listed == step)
+                .map_or((section.value.clone(), section.style.color), |(_, state)| step_line(step, state)),
+            None => (section.value.clone(), Color::WHITE),
+        };
+        section.value = value;
+        section.style.color = color.with_alpha(alpha);
+    }
+}
@@ -0,0 +1,145 @@
+use std::{
+    fs,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{
+    app::AppExit,
+    prelude::{EventReader, Query, Res, Resource, With},
+    window::{Monitor, PrimaryWindow, Window, WindowPosition},
+};
+use log::{info, warn};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    config::{Config, WindowGeometry, WindowStrip},
+    paths::{data_dir, migrate_data_file},
+    AppState,
+};
+
+const STATE_FILE: &str = "state.toml";
+
+/// Things changed while running that should survive a restart. Kept apart from config.toml so
+/// the overlay never rewrites the user's settings.
+#[derive(Resource, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub(crate) struct RuntimeState {
+    pub(crate) window: Option<SavedWindow>,
+    pub(crate) spawning_paused: bool,
+    pub(crate) zen_mode: bool,
+}
+
+/// Where the overlay window was when it was last closed, in physical screen pixels
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) struct SavedWindow {
+    pub(crate) monitor: String,
+    pub(crate) x: i32,
+    pub(crate) y: i32,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    /// TARGET_MONITOR at the time, if the config has changed since then the config wins
+    pub(crate) target_monitor: Option<String>,
+    pub(crate) window_geometry: Option<WindowGeometry>,
+    #[serde(default)]
+    pub(crate) window_strip: Option<WindowStrip>,
+}
+
+impl SavedWindow {
+    /// The saved window is only used while the config still asks for the same placement
+    /// and the monitor it was on is still plugged in
+    pub(crate) fn usable(&self, config: &Config, monitors: &[&Monitor]) -> bool {
+        self.target_monitor == config.target_monitor
+            && self.window_geometry == config.window_geometry
+            && self.window_strip == config.window_strip
+            && monitors
+                .iter()
+                .any(|monitor| monitor.name.as_deref() == Some(self.monitor.as_str()))
+    }
+}
+
+fn state_path(file: &str) -> PathBuf {
+    data_dir().join(file)
+}
+
+/// Seconds since the Unix epoch, for saved files that expire
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+/// Read one of the overlay's own files. A missing file is normal, a broken one is ignored.
+pub(crate) fn read_state_file<T: DeserializeOwned>(file: &str) -> Option<T> {
+    // Older versions kept them next to config.toml
+    migrate_data_file(file);
+    let path = state_path(file);
+    let text = fs::read_to_string(&path).ok()?;
+    toml::from_str(&text)
+        .map_err(|err| warn!("Ignoring unreadable {}: {}", path.display(), err))
+        .ok()
+}
+
+/// Write one of the overlay's own files, every file the overlay keeps goes through here
+pub(crate) fn write_state_file<T: Serialize>(file: &str, value: &T) -> Result<PathBuf, String> {
+    let path = state_path(file);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|err| format!("Failed to create {}: {}", dir.display(), err))?;
+    }
+    let text = toml::to_string(value).map_err(|err| format!("Failed to serialise {}: {}", file, err))?;
+    fs::write(&path, text).map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+    Ok(path)
+}
+
+/// Read the state saved by the last run
+pub(crate) fn load_state() -> RuntimeState {
+    read_state_file(STATE_FILE).unwrap_or_default()
+}
+
+// Save the window position and runtime toggles when the app exits
+pub(crate) fn save_state_on_exit(
+    exit_events: EventReader<AppExit>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    monitors: Query<&Monitor>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    saved: Res<RuntimeState>,
+) {
+    if exit_events.is_empty() {
+        return;
+    }
+
+    let window = windows.get_single().ok().and_then(|window| {
+        let WindowPosition::At(position) = window.position else {
+            return None;
+        };
+        // The monitor the window's top left corner is on
+        let monitor = monitors.iter().find(|monitor| {
+            let offset = position - monitor.physical_position;
+            offset.x >= 0
+                && offset.y >= 0
+                && (offset.x as u32) < monitor.physical_width
+                && (offset.y as u32) < monitor.physical_height
+        })?;
+        Some(SavedWindow {
+            monitor: monitor.name.clone()?,
+            x: position.x,
+            y: position.y,
+            width: window.resolution.physical_width(),
+            height: window.resolution.physical_height(),
+            target_monitor: config.target_monitor.clone(),
+            window_geometry: config.window_geometry,
+            window_strip: config.window_strip,
+        })
+    });
+    let state = RuntimeState {
+        // Keep the last known window if it has already closed
+        window: window.or_else(|| saved.window.clone()),
+        spawning_paused: app_state.spawning_paused,
+        zen_mode: app_state.zen_mode,
+    };
+    match write_state_file(STATE_FILE, &state) {
+        Ok(path) => info!("Saved window state to {}", path.display()),
+        Err(err) => warn!("Failed to save window state: {}", err),
+    }
+}
@@ -0,0 +1,228 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::{Ipv4Addr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::{EventReader, Local, Res, ResMut, Resource};
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::watch,
+    time::timeout,
+};
+
+use crate::{api::ApiEvent, config::Config, leaderboard::shown_name, AppState};
+
+/// Emotes and chatters listed on the page
+const TOP_COUNT: usize = 5;
+/// Longest request the server reads, it only needs the first line
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+/// Time a client gets to send its request before it's dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const PAGE: &str = include_str!("stats_page.html");
+
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct EmoteCount {
+    emote: String,
+    count: u32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct ChatterCount {
+    user: String,
+    messages: u32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct RecentMessage {
+    user: String,
+    message: String,
+}
+
+/// What the stats page shows, published from Bevy every REFRESH_SECS
+#[derive(Serialize, Debug, Clone, Default)]
+pub(crate) struct StatsSnapshot {
+    /// How often the page asks for these again
+    refresh_secs: u64,
+    stream_live: bool,
+    viewer_count: Option<u64>,
+    active_users: Vec<String>,
+    top_emotes: Vec<EmoteCount>,
+    leaderboard: Vec<ChatterCount>,
+    /// Newest last
+    recent_messages: Vec<RecentMessage>,
+}
+
+/// Emote use since startup and the last few messages, kept for the stats page
+#[derive(Resource, Default)]
+pub(crate) struct PartyStats {
+    emotes: HashMap<String, u32>,
+    recent: VecDeque<RecentMessage>,
+}
+
+/// Hands snapshots to the stats server, only there while the page is enabled
+#[derive(Resource)]
+pub(crate) struct StatsPublisher {
+    pub(crate) sender: watch::Sender<Arc<StatsSnapshot>>,
+}
+
+// Count emotes and keep the last messages as the party shows them
+pub(crate) fn collect_party_stats(
+    mut party_events: EventReader<ApiEvent>,
+    mut stats: ResMut<PartyStats>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+) {
+    for event in party_events.read() {
+        match event {
+            ApiEvent::EmoteUsed { emote, .. } => *stats.emotes.entry(emote.clone()).or_default() += 1,
+            ApiEvent::MessageDisplayed { user, message } => {
+                stats.recent.push_back(RecentMessage {
+                    user: shown_name(&app_state, user).to_string(),
+                    message: message.clone(),
+                });
+            }
+            _ => {}
+        }
+    }
+    while stats.recent.len() > config.stats_page_recent_messages {
+        stats.recent.pop_front();
+    }
+}
+
+// Put together a snapshot for the server every REFRESH_SECS. The server only ever reads the
+// last one, so a slow client never holds up the party.
+pub(crate) fn publish_party_stats(
+    publisher: Res<StatsPublisher>,
+    stats: Res<PartyStats>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    mut last_publish: Local<Option<Instant>>,
+) {
+    if last_publish.is_some_and(|last| last.elapsed() < config.stats_page_refresh) {
+        return;
+    }
+    *last_publish = Some(Instant::now());
+
+    let mut active_users: Vec<String> = app_state.active_users.values().map(|user| user.name.clone()).collect();
+    active_users.sort_by_key(|name| name.to_lowercase());
+    let mut top_emotes: Vec<EmoteCount> = stats
+        .emotes
+        .iter()
+        .map(|(emote, count)| EmoteCount {
+            emote: emote.clone(),
+            count: *count,
+        })
+        .collect();
+    top_emotes.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.emote.cmp(&b.emote)));
+    top_emotes.truncate(TOP_COUNT);
+    let leaderboard = app_state
+        .message_counts
+        .top(TOP_COUNT)
+        .into_iter()
+        .map(|(user, messages)| ChatterCount {
+            user: shown_name(&app_state, user).to_string(),
+            messages,
+        })
+        .collect();
+
+    publisher.sender.send_replace(Arc::new(StatsSnapshot {
+        refresh_secs: config.stats_page_refresh.as_secs(),
+        stream_live: app_state.stream_live,
+        viewer_count: app_state.viewer_count,
+        active_users,
+        top_emotes,
+        leaderboard,
+        recent_messages: stats.recent.iter().cloned().collect(),
+    }));
+}
+
+/// A tiny HTTP server on loopback with the stats as JSON at `/stats.json` and a page showing
+/// them at `/`
+pub(crate) struct StatsServer {
+    port: u16,
+    stats: watch::Receiver<Arc<StatsSnapshot>>,
+}
+
+impl StatsServer {
+    pub(crate) fn from_config(config: &Config, stats: watch::Receiver<Arc<StatsSnapshot>>) -> Self {
+        Self {
+            port: config.stats_page_port,
+            stats,
+        }
+    }
+
+    pub(crate) async fn run(self) {
+        let addr = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), self.port);
+        let listener = match TcpListener::bind(addr).await {
+            Ok(listener) => listener,
+            Err(err) => {
+                error!("Could not start the stats page on {}: {}", addr, err);
+                return;
+            }
+        };
+        info!("Stats page at http://{}", addr);
+
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(err) => {
+                    warn!("Stats page connection failed: {}", err);
+                    continue;
+                }
+            };
+            let stats = self.stats.clone();
+            tokio::spawn(async move {
+                if let Err(err) = serve(stream, stats).await {
+                    debug!("Stats page client {} dropped: {}", peer, err);
+                }
+            });
+        }
+    }
+}
+
+/// Answer one request and close the connection
+async fn serve(mut stream: TcpStream, stats: watch::Receiver<Arc<StatsSnapshot>>) -> std::io::Result<()> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    // Only the request line matters, the rest of the headers are read and ignored
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") && request.len() < MAX_REQUEST_SIZE {
+        let read = timeout(REQUEST_TIMEOUT, stream.read(&mut buffer))
+            .await
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "request took too long"))??;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut line = request.lines().next().unwrap_or_default().split_whitespace();
+    let (method, target) = (line.next().unwrap_or_default(), line.next().unwrap_or_default());
+    let path = target.split('?').next().unwrap_or_default();
+
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/") | ("GET", "/index.html") => ("200 OK", "text/html; charset=utf-8", PAGE.to_string()),
+        ("GET", "/stats.json") => {
+            let snapshot = stats.borrow().clone();
+            match serde_json::to_string(&*snapshot) {
+                Ok(json) => ("200 OK", "application/json", json),
+                Err(err) => ("500 Internal Server Error", "text/plain", err.to_string()),
+            }
+        }
+        ("GET", _) => ("404 Not Found", "text/plain", "Not found".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain", "Only GET is supported".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
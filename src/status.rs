@@ -0,0 +1,336 @@
+use std::time::{Duration, Instant};
+
+use bevy::{
+    asset::AssetServer,
+    color::{Alpha, Color},
+    prelude::{
+        default, BackgroundColor, BuildChildren, Changed, Commands, Component, NodeBundle, Query, Res, ResMut,
+        Resource, Visibility, With, Without,
+    },
+    text::{Text, TextStyle},
+    ui::{
+        node_bundles::TextBundle, AlignItems, BorderRadius, Display, Interaction, PositionType, Style, UiRect, Val,
+    },
+};
+use log::info;
+
+use crate::{
+    config::Config, locale::t, reload::ConfigWatcher, widgets::corner_style, ChatReconnect, Platform, SourceStatus,
+    SourceStatusReceiver,
+};
+
+/// Chat that has been down this long is shown in red
+const DOWN_WARNING: Duration = Duration::from_secs(60);
+const DOT_SIZE: f32 = 10.0;
+const CONNECTED_COLOR: Color = Color::srgb(0.2, 0.8, 0.3);
+const RECONNECTING_COLOR: Color = Color::srgb(1.0, 0.8, 0.2);
+const DOWN_COLOR: Color = Color::srgb(0.9, 0.2, 0.2);
+
+/// Marker for the chip warning that the Twitch login failed
+#[derive(Component)]
+pub(crate) struct AuthWarningChip {}
+
+/// Marker for the chip warning that a chat source stopped for good
+#[derive(Component)]
+pub(crate) struct SourceStoppedChip {}
+
+/// Marker for the notice shown until a channel has been configured
+#[derive(Component)]
+pub(crate) struct SetupNeededChip {}
+
+/// Marker for the chip showing how the connection to Twitch chat is doing, clicked to reconnect
+#[derive(Component)]
+pub(crate) struct ConnectionChip {}
+
+/// Marker for the connection chip's coloured dot
+#[derive(Component)]
+pub(crate) struct ConnectionDot {}
+
+/// Marker for the text beside the dot saying what changed
+#[derive(Component)]
+pub(crate) struct ConnectionDetail {}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ConnectionState {
+    Connecting,
+    Connected,
+    Reconnecting { attempt: u32 },
+}
+
+/// How the connection to Twitch chat is doing, as Twitch chat reports it
+#[derive(Resource)]
+pub(crate) struct ChatConnection {
+    state: ConnectionState,
+    /// When chat went down, while it's down
+    down_since: Option<Instant>,
+    /// What changed, shown beside the dot until the given time
+    detail: Option<(String, Instant)>,
+}
+
+impl Default for ChatConnection {
+    fn default() -> Self {
+        Self {
+            state: ConnectionState::Connecting,
+            down_since: None,
+            detail: None,
+        }
+    }
+}
+
+impl ChatConnection {
+    fn color(&self, now: Instant) -> Color {
+        match self.state {
+            ConnectionState::Connected => CONNECTED_COLOR,
+            _ if self.down_since.is_some_and(|since| now.duration_since(since) > DOWN_WARNING) => DOWN_COLOR,
+            _ => RECONNECTING_COLOR,
+        }
+    }
+
+    /// What the chip says while it's hovered
+    fn status(&self, now: Instant) -> String {
+        let down_for = self.down_since.map(|since| now.duration_since(since).as_secs());
+        match (self.state, down_for) {
+            (ConnectionState::Connected, _) => t!("connection.connected"),
+            (_, Some(secs)) if secs >= DOWN_WARNING.as_secs() => t!("connection.down", secs = secs),
+            (ConnectionState::Reconnecting { attempt }, _) => t!("connection.reconnecting", attempt = attempt),
+            (ConnectionState::Connecting, Some(_)) => t!("connection.disconnected"),
+            (ConnectionState::Connecting, None) => t!("connection.connecting"),
+        }
+    }
+
+    fn show(&mut self, detail: String, config: &Config) {
+        self.detail = Some((detail, Instant::now() + config.connection_chip_detail));
+    }
+}
+
+/// Spawn the hidden auth warning chip in the top left corner, and the setup notice when needed
+pub(crate) fn setup_status_chips(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<Config>,
+    watcher: Res<ConfigWatcher>,
+) {
+    if config.setup_needed() {
+        commands.spawn((
+            TextBundle::from_section(
+                t!("status.setup_needed", path = watcher.path()),
+                TextStyle {
+                    font: asset_server.load(&config.font_url),
+                    font_size: config.font_size,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(40.0),
+                left: Val::Px(8.0),
+                padding: UiRect::all(Val::Px(4.0)),
+                ..default()
+            })
+            .with_background_color(Color::BLACK.with_alpha(0.6)),
+            SetupNeededChip {},
+        ));
+    }
+
+    let mut chip = TextBundle::from_section(
+        t!("status.auth_failed"),
+        TextStyle {
+            font: asset_server.load(&config.font_url),
+            font_size: config.font_size * 0.8,
+            color: Color::srgb(1.0, 0.8, 0.2),
+        },
+    )
+    .with_style(Style {
+        position_type: PositionType::Absolute,
+        top: Val::Px(8.0),
+        left: Val::Px(8.0),
+        padding: UiRect::all(Val::Px(4.0)),
+        ..default()
+    })
+    .with_background_color(Color::BLACK.with_alpha(0.6));
+    chip.visibility = Visibility::Hidden;
+    commands.spawn((chip, AuthWarningChip {}));
+
+    // Under the auth warning, filled in when a source stops
+    let mut chip = TextBundle::from_section(
+        "",
+        TextStyle {
+            font: asset_server.load(&config.font_url),
+            font_size: config.font_size * 0.8,
+            color: Color::srgb(1.0, 0.8, 0.2),
+        },
+    )
+    .with_style(Style {
+        position_type: PositionType::Absolute,
+        top: Val::Px(8.0 + config.font_size),
+        left: Val::Px(8.0),
+        padding: UiRect::all(Val::Px(4.0)),
+        ..default()
+    })
+    .with_background_color(Color::BLACK.with_alpha(0.6));
+    chip.visibility = Visibility::Hidden;
+    commands.spawn((chip, SourceStoppedChip {}));
+
+    let twitch = config.chat_sources.iter().any(|name| name == "twitch") && !config.channel_name.is_empty();
+    if !config.connection_chip_enabled || !twitch {
+        return;
+    }
+    let mut style = corner_style(config.connection_chip_corner);
+    style.align_items = AlignItems::Center;
+    commands
+        .spawn((
+            NodeBundle {
+                style,
+                background_color: Color::BLACK.with_alpha(0.6).into(),
+                border_radius: BorderRadius::all(Val::Px(DOT_SIZE)),
+                ..default()
+            },
+            Interaction::default(),
+            ConnectionChip {},
+        ))
+        .with_children(|chip| {
+            chip.spawn((
+                NodeBundle {
+                    style: Style {
+                        width: Val::Px(DOT_SIZE),
+                        height: Val::Px(DOT_SIZE),
+                        ..default()
+                    },
+                    background_color: RECONNECTING_COLOR.into(),
+                    border_radius: BorderRadius::MAX,
+                    ..default()
+                },
+                ConnectionDot {},
+            ));
+            let detail = TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load(&config.font_url),
+                    font_size: config.font_size * 0.8,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                margin: UiRect::left(Val::Px(4.0)),
+                display: Display::None,
+                ..default()
+            });
+            chip.spawn((detail, ConnectionDetail {}));
+        });
+}
+
+/// The chat the connection chip follows, the first source in SOURCES that connects somewhere
+fn followed_platform(config: &Config) -> Option<Platform> {
+    config.chat_sources.iter().find_map(|source| match source.as_str() {
+        "twitch" => Some(Platform::Twitch),
+        "kick" => Some(Platform::Kick),
+        "youtube" => Some(Platform::YouTube),
+        _ => None,
+    })
+}
+
+// Show or hide status chips as chat sources report their state, and say what changed on the
+// connection chip
+pub(crate) fn handle_source_status(
+    mut status_receiver: ResMut<SourceStatusReceiver>,
+    mut connection: ResMut<ChatConnection>,
+    config: Res<Config>,
+    mut chip_query: Query<&mut Visibility, (With<AuthWarningChip>, Without<SourceStoppedChip>)>,
+    mut stopped_query: Query<(&mut Text, &mut Visibility), With<SourceStoppedChip>>,
+) {
+    while let Ok(status) = status_receiver.receiver.try_recv() {
+        match status {
+            SourceStatus::Authenticated => {
+                for mut visibility in chip_query.iter_mut() {
+                    *visibility = Visibility::Hidden;
+                }
+            }
+            SourceStatus::AuthFailed { reason } => {
+                info!("Showing auth warning: {}", reason);
+                for mut visibility in chip_query.iter_mut() {
+                    *visibility = Visibility::Visible;
+                }
+            }
+            // The connection chip follows one source, the others only log
+            SourceStatus::Connected { platform, .. }
+            | SourceStatus::Disconnected { platform }
+            | SourceStatus::Reconnecting { platform, .. }
+                if Some(platform) != followed_platform(&config) => {}
+            SourceStatus::Connected { down_for, missed, .. } => {
+                connection.state = ConnectionState::Connected;
+                connection.down_since = None;
+                match down_for.map(|down_for| down_for.as_secs()) {
+                    Some(secs) if missed > 0 => {
+                        connection.show(t!("connection.missed", secs = secs, count = missed), &config)
+                    }
+                    Some(secs) => connection.show(t!("connection.reconnected", secs = secs), &config),
+                    None => {}
+                }
+            }
+            SourceStatus::Disconnected { .. } => {
+                connection.state = ConnectionState::Connecting;
+                connection.down_since.get_or_insert_with(Instant::now);
+                connection.show(t!("connection.disconnected"), &config);
+            }
+            SourceStatus::Reconnecting { attempt, .. } => {
+                connection.state = ConnectionState::Reconnecting { attempt };
+                connection.down_since.get_or_insert_with(Instant::now);
+                connection.show(t!("connection.reconnecting", attempt = attempt), &config);
+            }
+            SourceStatus::Stopped { source, reason } => {
+                info!("Showing {} stopped warning: {}", source, reason);
+                for (mut text, mut visibility) in stopped_query.iter_mut() {
+                    text.sections[0].value = t!("status.source_stopped", source = source, reason = reason);
+                    *visibility = Visibility::Visible;
+                }
+            }
+        }
+    }
+}
+
+// Colour the connection dot and show what changed beside it for DETAIL_SECS, or the status while
+// it's hovered. Clicking it while the overlay takes clicks reconnects Twitch chat straight away.
+pub(crate) fn update_connection_chip(
+    mut connection: ResMut<ChatConnection>,
+    config: Res<Config>,
+    reconnect: Res<ChatReconnect>,
+    chip_query: Query<&Interaction, With<ConnectionChip>>,
+    clicked_query: Query<&Interaction, (With<ConnectionChip>, Changed<Interaction>)>,
+    mut dot_query: Query<&mut BackgroundColor, With<ConnectionDot>>,
+    mut detail_query: Query<(&mut Text, &mut Style), With<ConnectionDetail>>,
+) {
+    let now = Instant::now();
+    let twitch = followed_platform(&config) == Some(Platform::Twitch);
+    if twitch && clicked_query.iter().any(|interaction| *interaction == Interaction::Pressed) {
+        info!("Reconnecting to chat from the connection chip");
+        reconnect.notify.notify_one();
+        connection.show(t!("connection.reconnect_asked"), &config);
+    }
+    if connection.detail.as_ref().is_some_and(|(_, until)| now >= *until) {
+        connection.detail = None;
+    }
+
+    let color = connection.color(now);
+    for mut background in dot_query.iter_mut() {
+        if background.0 != color {
+            background.0 = color;
+        }
+    }
+    let hovered = chip_query.iter().any(|interaction| *interaction != Interaction::None);
+    let detail = match &connection.detail {
+        Some((detail, _)) => Some(detail.clone()),
+        None => hovered.then(|| connection.status(now)),
+    };
+    for (mut text, mut style) in detail_query.iter_mut() {
+        // Out of the layout while it's hidden, leaving just the dot
+        let wanted = if detail.is_some() { Display::Flex } else { Display::None };
+        if style.display != wanted {
+            style.display = wanted;
+        }
+        if let (Some(detail), Some(section)) = (&detail, text.sections.first_mut()) {
+            if section.value != *detail {
+                section.value = detail.clone();
+            }
+        }
+    }
+}
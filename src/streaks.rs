@@ -0,0 +1,164 @@
+use std::collections::{BTreeSet, HashMap};
+
+use bevy::{
+    asset::AssetServer,
+    color::Color,
+    hierarchy::BuildChildren,
+    math::{Quat, Vec2},
+    prelude::{default, Commands, Component, EventReader, Res, Resource, SpatialBundle, Transform},
+    sprite::{Anchor, Sprite, SpriteBundle},
+    text::{Text, Text2dBundle, TextStyle},
+};
+use chrono::{Days, NaiveDate};
+use log::debug;
+
+use crate::{api::ApiEvent, config::Config, AppState};
+
+/// Chat days older than this are forgotten, longer streaks stop counting up at it
+const KEEP_DAYS: u64 = 366;
+/// Position of the flame relative to the avatar's center, across from the platform badge
+const BADGE_OFFSET: Vec2 = Vec2::new(20.0, 20.0);
+const FLAME_SIZE: f32 = 8.0;
+const FLAME_COLOR: Color = Color::srgb(1.0, 0.45, 0.1);
+const FLAME_CORE_COLOR: Color = Color::srgb(1.0, 0.85, 0.2);
+
+/// The calendar days each user chatted on, saved with the party snapshot
+#[derive(Resource, Default)]
+pub(crate) struct ChatStreaks {
+    days: HashMap<String, BTreeSet<NaiveDate>>,
+}
+
+impl ChatStreaks {
+    /// Days saved by the last run, without any too old to matter
+    pub(crate) fn new(mut days: HashMap<String, BTreeSet<NaiveDate>>, today: NaiveDate) -> Self {
+        if let Some(oldest) = today.checked_sub_days(Days::new(KEEP_DAYS)) {
+            days.retain(|_, days| {
+                days.retain(|day| *day >= oldest);
+                !days.is_empty()
+            });
+        }
+        Self { days }
+    }
+
+    pub(crate) fn days(&self) -> &HashMap<String, BTreeSet<NaiveDate>> {
+        &self.days
+    }
+
+    /// Note that a user chatted today
+    pub(crate) fn record(&mut self, user: &str, today: NaiveDate) {
+        // Nearly every message is from someone who already chatted today
+        if self.days.get(user).is_some_and(|days| days.contains(&today)) {
+            return;
+        }
+        self.days.entry(user.to_string()).or_default().insert(today);
+    }
+
+    /// Forget every day a Twitch user chatted on, whatever the case of their name
+    pub(crate) fn forget(&mut self, login: &str) {
+        self.days.retain(|user, _| !user.eq_ignore_ascii_case(login));
+    }
+
+    /// Whether the user chatted on any day before today
+    pub(crate) fn chatted_before(&self, user: &str, today: NaiveDate) -> bool {
+        self.days
+            .get(user)
+            .and_then(|days| days.first())
+            .is_some_and(|first| *first < today)
+    }
+
+    /// Days in a row the user chatted on. A streak that reached yesterday is still going,
+    /// they just haven't said anything yet today.
+    pub(crate) fn streak(&self, user: &str, today: NaiveDate) -> u32 {
+        let Some(days) = self.days.get(user) else {
+            return 0;
+        };
+        let mut day = if days.contains(&today) {
+            today
+        } else {
+            match today.pred_opt() {
+                Some(yesterday) => yesterday,
+                None => return 0,
+            }
+        };
+        let mut streak = 0;
+        while days.contains(&day) {
+            streak += 1;
+            match day.pred_opt() {
+                Some(previous) => day = previous,
+                None => break,
+            }
+        }
+        streak
+    }
+}
+
+/// The flame and number on a minawan whose chatter is on a streak
+#[derive(Component)]
+pub(crate) struct StreakBadge {}
+
+// Give minawan a flame with their streak when they arrive, once it's at least THRESHOLD days
+pub(crate) fn show_streak_badges(
+    mut commands: Commands,
+    mut party_events: EventReader<ApiEvent>,
+    streaks: Res<ChatStreaks>,
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    asset_server: Res<AssetServer>,
+) {
+    if !config.streaks_enabled {
+        party_events.clear();
+        return;
+    }
+    let today = config.today();
+    for event in party_events.read() {
+        let ApiEvent::UserSpawned { user } = event else {
+            continue;
+        };
+        let streak = streaks.streak(user, today);
+        if streak < config.streak_threshold {
+            continue;
+        }
+        let Some(entity) = app_state.active_users.get(user).map(|user| user.entity) else {
+            continue;
+        };
+        debug!("{} is on a {} day streak", user, streak);
+        commands.entity(entity).with_children(|parent| {
+            parent
+                .spawn((
+                    SpatialBundle::from_transform(Transform::from_translation(BADGE_OFFSET.extend(1.0))),
+                    StreakBadge {},
+                ))
+                .with_children(|badge| {
+                    // An orange diamond with a yellow core low down
+                    for (color, size, y, z) in [
+                        (FLAME_COLOR, FLAME_SIZE, 0.0, 0.0),
+                        (FLAME_CORE_COLOR, FLAME_SIZE * 0.5, -FLAME_SIZE * 0.3, 0.1),
+                    ] {
+                        badge.spawn(SpriteBundle {
+                            sprite: Sprite {
+                                color,
+                                custom_size: Some(Vec2::splat(size)),
+                                ..default()
+                            },
+                            transform: Transform::from_xyz(0.0, y, z)
+                                .with_rotation(Quat::from_rotation_z(std::f32::consts::FRAC_PI_4)),
+                            ..default()
+                        });
+                    }
+                    badge.spawn(Text2dBundle {
+                        text: Text::from_section(
+                            streak.to_string(),
+                            TextStyle {
+                                font: asset_server.load(&config.font_url),
+                                font_size: config.font_size * 0.6,
+                                color: Color::WHITE,
+                            },
+                        ),
+                        text_anchor: Anchor::CenterLeft,
+                        transform: Transform::from_xyz(FLAME_SIZE, 0.0, 0.1),
+                        ..default()
+                    });
+                });
+        });
+    }
+}
@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+use bevy::prelude::{Res, ResMut};
+use log::{debug, info, warn};
+use tokio::{sync::mpsc, time::sleep};
+
+use crate::{
+    config::Config,
+    helix::HelixClient,
+    startup::{StartupReporter, StartupStep, StepResult},
+    AppState, StreamStatus, StreamStatusReceiver,
+};
+
+/// Poll the Helix streams endpoint and forward the online/offline state to Bevy
+pub(crate) async fn poll_stream_status(
+    helix: HelixClient,
+    channel_id: String,
+    tx: mpsc::Sender<StreamStatus>,
+    interval: Duration,
+    startup: StartupReporter,
+) {
+    // Until the first answer, for the loading panel
+    let mut startup = Some(startup);
+    loop {
+        match helix.get_stream(&channel_id).await {
+            Ok(stream) => {
+                if let Some(stream) = &stream {
+                    debug!("Stream live since {}", stream.started_at);
+                }
+                if let Some(startup) = startup.take() {
+                    let result = if stream.is_some() { StepResult::Live } else { StepResult::Offline };
+                    startup.done(StartupStep::StreamStatus, result);
+                }
+                let status = StreamStatus {
+                    live: stream.is_some(),
+                    viewer_count: stream.map(|stream| stream.viewer_count),
+                };
+                if tx.send(status).await.is_err() {
+                    // Bevy side has shut down
+                    break;
+                }
+            }
+            Err(err) => {
+                warn!("Failed to poll stream status: {}", err);
+                if let Some(startup) = &startup {
+                    startup.failed(StartupStep::StreamStatus, err.to_string());
+                }
+            }
+        }
+        sleep(interval).await;
+    }
+}
+
+// Update the app state when the stream goes online or offline, `update_frame_rate` throttles rendering
+pub(crate) fn handle_stream_status(
+    mut status_receiver: ResMut<StreamStatusReceiver>,
+    mut app_state: ResMut<AppState>,
+    config: Res<Config>,
+) {
+    while let Ok(status) = status_receiver.receiver.try_recv() {
+        app_state.viewer_count = status.viewer_count;
+        if status.live == app_state.stream_live {
+            continue;
+        }
+        app_state.stream_live = status.live;
+        if !config.idle_when_offline {
+            continue;
+        }
+
+        if status.live {
+            info!("Stream is live, resuming overlay");
+        } else {
+            info!("Stream is offline, idling overlay");
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! Building the overlay's app for tests the way main.rs does, from a config given as text
+//! rather than a file and without the command line. Everything the overlay saves goes to a
+//! folder in the system's temp folder, one for each test executable.
+
+use std::{env, process, time::Duration};
+
+use bevy::prelude::*;
+use tokio::sync::mpsc;
+
+use crate::{
+    cli::CliArgs, config::config_from_str, paths::set_data_dir, pool::Pooled, AppState, ChatMessage, ChatReceiver,
+    Config, MessageBubble, MessageSpawnTime, MessageText, MinawanPlugin, UserMarker,
+};
+
+/// Messages a TestChatDriver holds until the next update
+const DRIVER_CAPACITY: usize = 100;
+
+/// Keep what tests save out of the user's own data folder
+fn use_test_data_dir() {
+    set_data_dir(env::temp_dir().join(format!("minawan-watch-party-test-{}", process::id())));
+}
+
+/// A config with `text` merged over the defaults, panics if it doesn't parse
+pub fn config(text: &str) -> Config {
+    config_from_str(text).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// The overlay with its real window and renderer. Needs a display, and has to be built inside
+/// the tokio runtime and run on the main thread.
+pub fn window_app(config: Config) -> App {
+    use_test_data_dir();
+    let plugin = MinawanPlugin::with_config(config);
+    let mut app = App::new();
+    app.add_plugins(plugin.downloads())
+        .add_plugins(
+            DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: Some(plugin.primary_window()),
+                    ..default()
+                })
+                .set(plugin.render_plugin()),
+        )
+        .add_plugins(plugin);
+    app
+}
+
+/// The overlay without a window or GPU on MinimalPlugins, as `--headless` runs it. The test
+/// drives it with `App::update`. Has to be built inside the tokio runtime.
+pub fn headless_app(config: Config) -> App {
+    use_test_data_dir();
+    let args = CliArgs {
+        headless: true,
+        ..CliArgs::default()
+    };
+    let plugin = MinawanPlugin::with_config(config).with_cli_args(args);
+    let mut app = App::new();
+    app.add_plugins(plugin.downloads())
+        .add_plugins(plugin.headless_plugins(Duration::ZERO))
+        .add_plugins(plugin);
+    app
+}
+
+/// Keys of the users at the party, sorted
+pub fn users(app: &App) -> Vec<String> {
+    let mut users: Vec<String> = app.world().resource::<AppState>().active_users.keys().cloned().collect();
+    users.sort();
+    users
+}
+
+/// Minawan on screen, not counting the hidden ones kept for reuse
+pub fn minawan_count(app: &mut App) -> usize {
+    app.world_mut()
+        .query_filtered::<(), (With<UserMarker>, Without<Pooled>)>()
+        .iter(app.world())
+        .count()
+}
+
+/// Text of the speech bubbles on screen, oldest first
+pub fn bubble_texts(app: &mut App) -> Vec<String> {
+    let mut bubbles: Vec<(std::time::Instant, String)> = app
+        .world_mut()
+        .query_filtered::<(&MessageSpawnTime, &MessageText), (With<MessageBubble>, Without<Pooled>)>()
+        .iter(app.world())
+        .map(|(spawn_time, text)| (spawn_time.0, text.text.clone()))
+        .collect();
+    bubbles.sort();
+    bubbles.into_iter().map(|(_, text)| text).collect()
+}
+
+/// Chat for a test, replacing the chat sources. Messages sent are read on the next update,
+/// without waiting on the tasks that forward real chat.
+pub struct TestChatDriver {
+    sender: mpsc::Sender<ChatMessage>,
+}
+
+impl TestChatDriver {
+    /// Take over the app's chat, messages from its sources are no longer read
+    pub fn attach(app: &mut App) -> Self {
+        let (sender, receiver) = mpsc::channel(DRIVER_CAPACITY);
+        app.insert_resource(ChatReceiver { receiver });
+        Self { sender }
+    }
+
+    /// A Twitch chat message from `user`, panics when more are waiting than an update reads
+    pub fn send(&self, user: &str, message: &str) {
+        self.sender
+            .try_send(ChatMessage::twitch(user, message))
+            .expect("test chat is full, update the app first");
+    }
+}
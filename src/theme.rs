@@ -0,0 +1,258 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use log::{info, warn};
+use toml_edit::{value, DocumentMut, Item, Table, Value};
+
+use crate::{
+    config::{apply_profile, default_document, set_value},
+    paths::in_assets_dir,
+};
+
+/// Format of the theme files this build writes. Files from newer builds still import, the
+/// settings this build doesn't know about are skipped.
+const THEME_FORMAT: i64 = 1;
+/// Sections that are all about how the overlay looks, shared whole
+const THEME_SECTIONS: [&str; 7] = [
+    "Avatars",
+    "Messages",
+    "Ambient",
+    "FloorGlow",
+    "Campfire",
+    "Footprints",
+    "Reactions",
+];
+/// Effects whose ENABLED is shared, the rest of their settings are the streamer's own
+const THEME_TOGGLES: [&str; 5] = ["Streaks", "Greetings", "Petting", "Marquee", "Bits"];
+/// Settings naming a file in the assets folder, which goes in the theme with them
+const ASSET_KEYS: [(&str, &str); 4] = [
+    ("Avatars", "AVATAR_URL"),
+    ("Messages", "FONT_URL"),
+    ("Campfire", "IMAGE"),
+    ("Footprints", "IMAGE"),
+];
+/// Where RANDOM_AVATARS picks from, relative to the assets folder
+const AVATAR_SET_DIR: &str = "avatars";
+/// Where imported themes keep their files, relative to the assets folder
+const THEMES_DIR: &str = "themes";
+
+/// Every setting a theme carries, by section and key
+fn theme_keys(defaults: &DocumentMut) -> Vec<(String, String)> {
+    let mut keys = vec![];
+    for section in THEME_SECTIONS {
+        if let Some(table) = defaults.get(section).and_then(Item::as_table) {
+            keys.extend(table.iter().map(|(key, _)| (section.to_string(), key.to_string())));
+        }
+    }
+    keys.extend(THEME_TOGGLES.map(|section| (section.to_string(), "ENABLED".to_string())));
+    keys
+}
+
+fn get_value<'a>(document: &'a DocumentMut, section: &str, key: &str) -> Option<&'a Value> {
+    document.get(section)?.get(key)?.as_value()
+}
+
+/// A value as written, without the spacing and comment around it
+fn bare(value: &Value) -> Value {
+    let mut value = value.clone();
+    value.decor_mut().clear();
+    value
+}
+
+/// Whether a path from a theme stays inside the folder it's unpacked in
+fn safe_path(path: &str) -> bool {
+    !path.is_empty() && Path::new(path).components().all(|part| matches!(part, Component::Normal(_)))
+}
+
+/// Write the look of the overlay to a theme file: the settings in THEME_SECTIONS, the ENABLED of
+/// each of THEME_TOGGLES and the files they use from the assets folder, base64 encoded. The
+/// active profile's values are used where it has any, defaults where the config has none.
+pub(crate) fn export_theme(config_path: &str, profile: Option<&str>, theme_path: &Path) -> Result<(), String> {
+    if config_path.ends_with(".ini") {
+        return Err("Themes can only be exported from config.toml".to_string());
+    }
+    let text = fs::read_to_string(config_path).map_err(|err| format!("Failed to load {}: {}", config_path, err))?;
+    let mut document = text
+        .parse::<DocumentMut>()
+        .map_err(|err| format!("Failed to parse {}: {}", config_path, err))?;
+    if let Some(profile) = profile {
+        apply_profile(&text, &mut document, profile)?;
+    }
+    let defaults = default_document();
+
+    let mut theme = DocumentMut::new();
+    theme["format"] = value(THEME_FORMAT);
+    let mut settings = Table::new();
+    settings.set_implicit(true);
+    for (section, key) in theme_keys(&defaults) {
+        let Some(setting) = get_value(&document, &section, &key).or_else(|| get_value(&defaults, &section, &key))
+        else {
+            continue;
+        };
+        let table = settings.entry(&section).or_insert_with(|| Item::Table(Table::new()));
+        table[key.as_str()] = Item::Value(bare(setting));
+    }
+    theme["settings"] = Item::Table(settings);
+
+    let mut assets = Table::new();
+    for (section, key) in ASSET_KEYS {
+        let Some(path) = settings_str(&theme, section, key) else {
+            continue;
+        };
+        // Links work the same for whoever imports the theme
+        if path.contains("://") || assets.contains_key(&path) {
+            continue;
+        }
+        match fs::read(in_assets_dir(&path)) {
+            Ok(bytes) => {
+                assets[path.as_str()] = value(STANDARD.encode(bytes));
+            }
+            Err(err) => warn!("Leaving {} out of the theme, it can't be read: {}", path, err),
+        }
+    }
+    theme["assets"] = Item::Table(assets);
+
+    let random_avatars = get_value(&document, "Avatars", "RANDOM_AVATARS").and_then(Value::as_bool) == Some(true);
+    if random_avatars {
+        let mut avatar_set = Table::new();
+        let dir = in_assets_dir(AVATAR_SET_DIR);
+        let entries = fs::read_dir(&dir).map_err(|err| format!("Failed to list {}: {}", dir.display(), err))?;
+        for entry in entries.filter_map(Result::ok) {
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            match fs::read(entry.path()) {
+                Ok(bytes) => {
+                    avatar_set[name.as_str()] = value(STANDARD.encode(bytes));
+                }
+                Err(err) => warn!("Leaving {} out of the theme, it can't be read: {}", name, err),
+            }
+        }
+        theme["avatar_set"] = Item::Table(avatar_set);
+    }
+
+    fs::write(theme_path, theme.to_string())
+        .map_err(|err| format!("Failed to write {}: {}", theme_path.display(), err))?;
+    info!("Exported the theme to {}", theme_path.display());
+    Ok(())
+}
+
+fn settings_str(theme: &DocumentMut, section: &str, key: &str) -> Option<String> {
+    theme.get("settings")?.get(section)?.get(key)?.as_str().map(str::to_string)
+}
+
+/// Unpack a theme's files into assets/themes/<theme name>, and its avatar set into assets/avatars
+/// without replacing any avatar already there. Returns where each of the theme's files ended
+/// up, relative to the assets folder.
+fn unpack_assets(theme: &DocumentMut, name: &str) -> Result<Vec<(String, String)>, String> {
+    let mut unpacked = vec![];
+    let theme_dir = Path::new(THEMES_DIR).join(name);
+    let tables = [("assets", theme_dir.as_path(), true), ("avatar_set", Path::new(AVATAR_SET_DIR), false)];
+    for (table, dir, replace) in tables {
+        let Some(files) = theme.get(table).and_then(Item::as_table) else {
+            continue;
+        };
+        for (path, data) in files.iter() {
+            if !safe_path(path) {
+                warn!("Skipping {} from the theme, it points outside the assets folder", path);
+                continue;
+            }
+            let Some(bytes) = data.as_str().and_then(|data| STANDARD.decode(data).ok()) else {
+                warn!("Skipping {} from the theme, it isn't valid base64", path);
+                continue;
+            };
+            let relative: PathBuf = dir.join(path);
+            let destination = in_assets_dir(&relative);
+            if !replace && destination.exists() {
+                info!("Keeping the {} already in {}", path, AVATAR_SET_DIR);
+                continue;
+            }
+            if let Some(parent) = destination.parent() {
+                fs::create_dir_all(parent).map_err(|err| format!("Failed to create {}: {}", parent.display(), err))?;
+            }
+            fs::write(&destination, bytes)
+                .map_err(|err| format!("Failed to write {}: {}", destination.display(), err))?;
+            // Asset paths use forward slashes on every platform
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            unpacked.push((path.to_string(), relative));
+        }
+    }
+    Ok(unpacked)
+}
+
+/// Merge a theme into the config file, keeping its comments, and unpack the theme's files.
+/// Settings go into the base sections, not a profile. Returns each setting that changed, as
+/// `[Section] KEY: old -> new`.
+pub(crate) fn import_theme(config_path: &str, theme_path: &Path) -> Result<Vec<String>, String> {
+    if config_path.ends_with(".ini") {
+        return Err("Themes can only be imported into config.toml".to_string());
+    }
+    let theme = fs::read_to_string(theme_path)
+        .map_err(|err| format!("Failed to load {}: {}", theme_path.display(), err))?
+        .parse::<DocumentMut>()
+        .map_err(|err| format!("Failed to parse {}: {}", theme_path.display(), err))?;
+    let format = theme.get("format").and_then(Item::as_integer).unwrap_or(0);
+    if format < 1 {
+        return Err(format!("{} isn't a theme file", theme_path.display()));
+    }
+    if format > THEME_FORMAT {
+        warn!("{} is from a newer version, anything this version doesn't know is skipped", theme_path.display());
+    }
+    let name: String = theme_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, '-' | '_'))
+        .collect();
+    let name = if name.is_empty() { "theme".to_string() } else { name };
+
+    let text = fs::read_to_string(config_path).map_err(|err| format!("Failed to load {}: {}", config_path, err))?;
+    let mut document = text
+        .parse::<DocumentMut>()
+        .map_err(|err| format!("Failed to parse {}: {}", config_path, err))?;
+    let defaults = default_document();
+    let unpacked = unpack_assets(&theme, &name)?;
+
+    let known = theme_keys(&defaults);
+    let mut changes = vec![];
+    let Some(settings) = theme.get("settings").and_then(Item::as_table) else {
+        return Ok(changes);
+    };
+    for (section, table) in settings.iter() {
+        let Some(table) = table.as_table() else {
+            continue;
+        };
+        for (key, item) in table.iter() {
+            let Some(setting) = item.as_value() else {
+                continue;
+            };
+            if !known.iter().any(|(known_section, known_key)| known_section == section && known_key == key) {
+                info!("Skipping [{}] {} from the theme, this version doesn't have it", section, key);
+                continue;
+            }
+            let mut setting = bare(setting);
+            // Point the setting at the theme's own copy of its file
+            let is_asset = ASSET_KEYS.contains(&(section, key));
+            if let Some((_, relative)) = unpacked
+                .iter()
+                .find(|(path, _)| is_asset && setting.as_str() == Some(path.as_str()))
+            {
+                setting = Value::from(relative.as_str());
+            }
+            let old = get_value(&document, section, key).or_else(|| get_value(&defaults, section, key)).map(bare);
+            let (old, new) = (old.map(|old| old.to_string()), setting.to_string());
+            if old.as_ref() == Some(&new) {
+                continue;
+            }
+            changes.push(format!("[{}] {}: {} -> {}", section, key, old.unwrap_or_default(), new));
+            set_value(&mut document, section, key, setting);
+        }
+    }
+
+    fs::write(config_path, document.to_string()).map_err(|err| format!("Failed to write {}: {}", config_path, err))?;
+    Ok(changes)
+}
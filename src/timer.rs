@@ -0,0 +1,263 @@
+use std::time::{Duration, Instant};
+
+use bevy::{
+    asset::AssetServer,
+    color::{Alpha, Color},
+    hierarchy::DespawnRecursiveExt,
+    prelude::{
+        Commands, Component, DetectChangesMut, Entity, Event, EventReader, Query, Res, ResMut, Resource,
+        Visibility, With,
+    },
+    text::{Text, TextStyle},
+    ui::{node_bundles::TextBundle, Style},
+};
+use log::{debug, info};
+
+use crate::{
+    commands::is_moderator, config::Config, locale::t, scripts::Dance, widgets::corner_style, ChatMessage, UserMarker,
+};
+
+/// Longest countdown that can be started, anything longer is most likely a typo
+const MAX_TIMER: Duration = Duration::from_secs(24 * 60 * 60);
+/// How long the badge stays on and off while it flashes
+const FLASH_INTERVAL: Duration = Duration::from_millis(400);
+/// How long the minawan dance once time is up
+const CELEBRATION_TIME: Duration = Duration::from_secs(3);
+
+/// Start or cancel the countdown, sent from chat
+#[derive(Event, Debug, Clone)]
+pub(crate) enum TimerCommand {
+    /// Replaces the countdown that's running, if there is one
+    Start {
+        duration: Duration,
+        label: Option<String>,
+    },
+    Cancel,
+    Unknown(String),
+}
+
+/// The countdown on screen
+struct Countdown {
+    label: Option<String>,
+    ends: Instant,
+    /// Whether the minawan already danced for this one
+    celebrated: bool,
+    badge: Entity,
+}
+
+/// The one countdown that can run at a time. It's kept here rather than on the badge so it
+/// carries on through config reloads.
+#[derive(Resource, Default)]
+pub(crate) struct CountdownTimer {
+    countdown: Option<Countdown>,
+}
+
+/// Marker for the countdown badge
+#[derive(Component)]
+pub(crate) struct TimerBadge {}
+
+/// Parse a time like `5m`, `90s` or `1h30m`. Plain numbers are seconds.
+pub(crate) fn parse_duration(text: &str) -> Option<Duration> {
+    let mut secs: u64 = 0;
+    let mut number = String::new();
+    for c in text.to_lowercase().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            'h' => 60 * 60,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        secs = secs.checked_add(number.parse::<u64>().ok()?.checked_mul(unit)?)?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        secs = secs.checked_add(number.parse().ok()?)?;
+    }
+    Some(Duration::from_secs(secs)).filter(|duration| !duration.is_zero() && *duration <= MAX_TIMER)
+}
+
+/// Parse a timer command from the broadcaster, a moderator or an admin.
+/// Returns None when the message should go through the normal display pipeline.
+pub(crate) fn parse_timer_command(chat_message: &ChatMessage, config: &Config) -> Option<TimerCommand> {
+    if !config.timer_enabled {
+        return None;
+    }
+    let mut words = chat_message.message.split_whitespace();
+    if !words.next()?.eq_ignore_ascii_case(&config.timer_command) {
+        return None;
+    }
+    if !is_moderator(chat_message, config) {
+        return None;
+    }
+
+    let command = match words.next() {
+        Some(word) if word.eq_ignore_ascii_case("cancel") => TimerCommand::Cancel,
+        Some(word) => match parse_duration(word) {
+            Some(duration) => {
+                let label = words.collect::<Vec<&str>>().join(" ");
+                TimerCommand::Start {
+                    duration,
+                    label: (!label.is_empty()).then_some(label),
+                }
+            }
+            None => TimerCommand::Unknown(chat_message.message.clone()),
+        },
+        None => TimerCommand::Unknown(chat_message.message.clone()),
+    };
+    Some(command)
+}
+
+/// Countdown text, `m:ss` or `h:mm:ss` for an hour or more. Partial seconds count as a whole
+/// one so it only reads 0:00 once time is up.
+fn clock(remaining: Duration) -> String {
+    let secs = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+    if secs >= 60 * 60 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+fn badge_text(label: &Option<String>, value: String) -> String {
+    match label {
+        Some(label) => format!("{}  {}", label, value),
+        None => value,
+    }
+}
+
+fn badge_style(config: &Config, asset_server: &AssetServer) -> TextStyle {
+    TextStyle {
+        font: asset_server.load(&config.timer_font_url),
+        font_size: config.timer_font_size,
+        color: Color::WHITE,
+    }
+}
+
+// Start and cancel the countdown for timer commands
+pub(crate) fn handle_timer_commands(
+    mut commands: Commands,
+    mut events: EventReader<TimerCommand>,
+    mut timer: ResMut<CountdownTimer>,
+    config: Res<Config>,
+    asset_server: Res<AssetServer>,
+) {
+    for event in events.read() {
+        match event {
+            TimerCommand::Start { duration, label } => {
+                if let Some(countdown) = timer.countdown.take() {
+                    commands.entity(countdown.badge).despawn_recursive();
+                }
+                info!("Counting down {} {}", clock(*duration), label.as_deref().unwrap_or_default());
+                let badge = commands
+                    .spawn((
+                        TextBundle::from_section(
+                            badge_text(label, clock(*duration)),
+                            badge_style(&config, &asset_server),
+                        )
+                        .with_style(corner_style(config.timer_corner))
+                        .with_background_color(Color::BLACK.with_alpha(0.6)),
+                        TimerBadge {},
+                    ))
+                    .id();
+                timer.countdown = Some(Countdown {
+                    label: label.clone(),
+                    ends: Instant::now() + *duration,
+                    celebrated: false,
+                    badge,
+                });
+            }
+            TimerCommand::Cancel => {
+                if let Some(countdown) = timer.countdown.take() {
+                    info!("Timer cancelled");
+                    commands.entity(countdown.badge).despawn_recursive();
+                }
+            }
+            TimerCommand::Unknown(text) => debug!("Ignoring timer command without a time: {}", text),
+        }
+    }
+}
+
+// Tick the countdown down. At zero the minawan dance and the badge flashes for FLASH_SECS
+// before it goes away.
+pub(crate) fn update_timer(
+    mut commands: Commands,
+    mut timer: ResMut<CountdownTimer>,
+    config: Res<Config>,
+    asset_server: Res<AssetServer>,
+    mut badge_query: Query<(&mut Text, &mut Style, &mut Visibility), With<TimerBadge>>,
+    avatar_query: Query<Entity, With<UserMarker>>,
+) {
+    let Some(countdown) = timer.countdown.as_mut() else {
+        return;
+    };
+    let Ok((mut text, mut style, mut visibility)) = badge_query.get_mut(countdown.badge) else {
+        timer.countdown = None;
+        return;
+    };
+    // Follow CORNER and the font when the config changes, without starting over
+    if config.is_changed() {
+        *style = corner_style(config.timer_corner);
+        text.sections[0].style = badge_style(&config, &asset_server);
+    }
+
+    let now = Instant::now();
+    let value = match countdown.ends.checked_duration_since(now) {
+        Some(remaining) if !remaining.is_zero() => clock(remaining),
+        _ => {
+            let over = now.duration_since(countdown.ends);
+            if over >= config.timer_flash {
+                commands.entity(countdown.badge).despawn_recursive();
+                timer.countdown = None;
+                return;
+            }
+            if !countdown.celebrated {
+                countdown.celebrated = true;
+                info!("Time's up, {}", countdown.label.as_deref().unwrap_or("the minawan celebrate"));
+                for entity in avatar_query.iter() {
+                    commands.entity(entity).insert(Dance {
+                        until: now + CELEBRATION_TIME,
+                    });
+                }
+            }
+            let lit = (over.as_millis() / FLASH_INTERVAL.as_millis()) % 2 == 0;
+            visibility.set_if_neq(if lit { Visibility::Inherited } else { Visibility::Hidden });
+            t!("timer.done")
+        }
+    };
+    let value = badge_text(&countdown.label, value);
+    if text.sections[0].value != value {
+        text.sections[0].value = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_add_up_their_units() {
+        assert_eq!(parse_duration("1h30m"), Some(Duration::from_secs(90 * 60)));
+        assert_eq!(parse_duration("2M5S"), Some(Duration::from_secs(125)));
+        assert_eq!(parse_duration("90"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("1m30"), Some(Duration::from_secs(90)));
+    }
+
+    #[test]
+    fn zero_and_too_long_timers_are_refused() {
+        assert_eq!(parse_duration("0s"), None);
+        assert_eq!(parse_duration("24h"), Some(MAX_TIMER));
+        assert_eq!(parse_duration("24h1s"), None);
+        assert_eq!(parse_duration("18446744073709551615h"), None);
+        assert_eq!(parse_duration("99999999999999999999"), None);
+    }
+
+    #[test]
+    fn unknown_units_are_refused() {
+        assert_eq!(parse_duration("5x"), None);
+        assert_eq!(parse_duration("5 m"), None);
+    }
+}
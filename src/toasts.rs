@@ -0,0 +1,110 @@
+use std::time::Instant;
+
+use bevy::{
+    asset::AssetServer,
+    color::{Alpha, Color},
+    prelude::{
+        default, BuildChildren, Commands, Component, DespawnRecursiveExt, Entity, NodeBundle, Query,
+        Res, With,
+    },
+    text::TextStyle,
+    ui::{node_bundles::TextBundle, FlexDirection, Style, UiRect, Val},
+};
+use log::Level;
+
+use crate::{
+    config::{Config, ScreenCorner},
+    logging::take_toasts,
+    widgets::corner_style,
+};
+
+/// Toasts shown at once, the oldest goes when another arrives
+const MAX_VISIBLE_TOASTS: usize = 4;
+
+/// Column in the bottom left corner that toasts stack up in
+#[derive(Component)]
+pub(crate) struct ToastContainer {}
+
+/// A warning or notice shown on the overlay until it expires
+#[derive(Component)]
+pub(crate) struct ToastMessage {
+    shown: Instant,
+}
+
+pub(crate) fn setup_toasts(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::ColumnReverse,
+                row_gap: Val::Px(4.0),
+                ..corner_style(ScreenCorner::BottomLeft)
+            },
+            ..default()
+        },
+        ToastContainer {},
+    ));
+}
+
+// Show logged warnings, errors and notices as toasts, removing them after TOAST_SECS
+pub(crate) fn show_toasts(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<Config>,
+    container_query: Query<Entity, With<ToastContainer>>,
+    toast_query: Query<(Entity, &ToastMessage)>,
+) {
+    let mut visible: Vec<(Entity, Instant)> = vec![];
+    for (entity, toast) in toast_query.iter() {
+        if !config.show_toasts || toast.shown.elapsed() > config.toast_duration {
+            commands.entity(entity).despawn_recursive();
+        } else {
+            visible.push((entity, toast.shown));
+        }
+    }
+
+    let toasts = take_toasts();
+    if !config.show_toasts || toasts.is_empty() {
+        return;
+    }
+    let Ok(container) = container_query.get_single() else {
+        return;
+    };
+
+    // Make room for the new ones, oldest first
+    visible.sort_by_key(|(_, shown)| *shown);
+    let excess = (visible.len() + toasts.len()).saturating_sub(MAX_VISIBLE_TOASTS);
+    for (entity, _) in visible.iter().take(excess) {
+        commands.entity(*entity).despawn_recursive();
+    }
+
+    let skip = toasts.len().saturating_sub(MAX_VISIBLE_TOASTS);
+    for toast in toasts.into_iter().skip(skip) {
+        let color = match toast.level {
+            Level::Error => Color::srgb(1.0, 0.4, 0.4),
+            Level::Info => Color::srgb(0.6, 1.0, 0.6),
+            _ => Color::srgb(1.0, 0.8, 0.2),
+        };
+        let toast_entity = commands
+            .spawn((
+                TextBundle::from_section(
+                    toast.message,
+                    TextStyle {
+                        font: asset_server.load(&config.font_url),
+                        font_size: config.font_size * 0.8,
+                        color,
+                    },
+                )
+                .with_style(Style {
+                    padding: UiRect::all(Val::Px(4.0)),
+                    max_width: Val::Px(config.message_box_width * 3.0),
+                    ..default()
+                })
+                .with_background_color(Color::BLACK.with_alpha(0.6)),
+                ToastMessage {
+                    shown: Instant::now(),
+                },
+            ))
+            .id();
+        commands.entity(container).add_child(toast_entity);
+    }
+}
@@ -0,0 +1,134 @@
+use bevy::prelude::{EventWriter, ResMut, Resource, World};
+use log::{info, warn};
+use tokio::sync::mpsc;
+use tray_icon::{
+    menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem},
+    Icon, TrayIcon, TrayIconBuilder,
+};
+
+use crate::{actions::OverlayAction, framerate::FrameWaker, paths::in_assets_dir};
+
+/// Image used for the tray icon, relative to the assets folder
+const TRAY_ICON_PATH: &str = "avatars/avatar.png";
+/// Size the icon is scaled down to
+const TRAY_ICON_SIZE: u32 = 64;
+
+const MENU_ITEMS: [(&str, OverlayAction); 10] = [
+    ("Show/Hide overlay", OverlayAction::ToggleVisibility),
+    ("Show/Hide in screen capture", OverlayAction::ToggleCapture),
+    ("Zen mode", OverlayAction::ToggleZenMode),
+    ("Clear party", OverlayAction::ClearParty),
+    ("Pick a winner", OverlayAction::PickWinner),
+    ("Save screenshot", OverlayAction::Screenshot),
+    ("Open config file", OverlayAction::OpenConfig),
+    ("Export theme", OverlayAction::ExportTheme),
+    ("Reload config", OverlayAction::ReloadConfig),
+    ("Quit", OverlayAction::Quit),
+];
+
+/// Tray menu clicks waiting to be run
+#[derive(Resource)]
+pub(crate) struct TrayReceiver {
+    receiver: mpsc::UnboundedReceiver<OverlayAction>,
+}
+
+/// What the tray needs to be built, taken by `setup_tray`
+#[derive(Resource)]
+pub(crate) struct TraySetup {
+    sender: mpsc::UnboundedSender<OverlayAction>,
+    waker: FrameWaker,
+}
+
+/// Channel between the tray menu and Bevy
+pub(crate) fn tray_channel(waker: FrameWaker) -> (TraySetup, TrayReceiver) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    (TraySetup { sender, waker }, TrayReceiver { receiver })
+}
+
+fn load_icon() -> Option<Icon> {
+    let path = in_assets_dir(TRAY_ICON_PATH);
+    let image = image::open(&path)
+        .map_err(|err| warn!("Failed to load tray icon {}: {}", path.display(), err))
+        .ok()?
+        .thumbnail(TRAY_ICON_SIZE, TRAY_ICON_SIZE)
+        .into_rgba8();
+    let (width, height) = image.dimensions();
+    Icon::from_rgba(image.into_raw(), width, height)
+        .map_err(|err| warn!("Invalid tray icon: {}", err))
+        .ok()
+}
+
+/// Build the tray icon and forward menu clicks to Bevy. Must run on the thread that owns
+/// the platform's UI loop.
+fn build_tray(setup: TraySetup) -> Result<TrayIcon, String> {
+    let menu = Menu::new();
+    let mut items = vec![];
+    for (label, action) in MENU_ITEMS {
+        if action == OverlayAction::Quit {
+            menu.append(&PredefinedMenuItem::separator())
+                .map_err(|err| err.to_string())?;
+        }
+        let item = MenuItem::new(label, true, None);
+        menu.append(&item).map_err(|err| err.to_string())?;
+        items.push((item.id().clone(), action));
+    }
+
+    let TraySetup { sender, waker } = setup;
+    MenuEvent::set_event_handler(Some(move |event: MenuEvent| {
+        if let Some((_, action)) = items.iter().find(|(id, _)| *id == event.id) {
+            let _ = sender.send(*action);
+            waker.wake();
+        }
+    }));
+
+    let mut builder = TrayIconBuilder::new()
+        .with_menu(Box::new(menu))
+        .with_tooltip("Minawan Watch Party");
+    if let Some(icon) = load_icon() {
+        builder = builder.with_icon(icon);
+    }
+    builder.build().map_err(|err| err.to_string())
+}
+
+// Create the tray icon. Windows and macOS want it on the main thread once the event loop is
+// running, which is where exclusive startup systems run. Linux needs a GTK loop of its own.
+pub(crate) fn setup_tray(world: &mut World) {
+    let Some(setup) = world.remove_resource::<TraySetup>() else {
+        return;
+    };
+
+    #[cfg(target_os = "linux")]
+    std::thread::spawn(move || {
+        if let Err(err) = gtk::init() {
+            warn!("No tray icon, GTK is unavailable: {}", err);
+            return;
+        }
+        match build_tray(setup) {
+            Ok(tray) => {
+                info!("Tray icon created");
+                gtk::main();
+                drop(tray);
+            }
+            Err(err) => warn!("Failed to create the tray icon: {}", err),
+        }
+    });
+
+    #[cfg(not(target_os = "linux"))]
+    match build_tray(setup) {
+        Ok(tray) => {
+            info!("Tray icon created");
+            world.insert_non_send_resource(tray);
+        }
+        Err(err) => warn!("Failed to create the tray icon: {}", err),
+    }
+}
+
+// Pass tray menu clicks on as overlay actions
+pub(crate) fn handle_tray_events(
+    mut tray_receiver: ResMut<TrayReceiver>,
+    mut actions: EventWriter<OverlayAction>,
+) {
+    while let Ok(action) = tray_receiver.receiver.try_recv() {
+        actions.send(action);
+    }
+}
@@ -1,41 +1,272 @@
-use std::time::Instant;
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use bevy::{
     prelude::{Bundle, Component, Entity, Resource}, sprite::SpriteBundle, utils::HashMap
 };
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch, Notify};
+
+use crate::{
+    cheers::BitTotals,
+    config::Config,
+    emotes::emote_types::{AtlasRegion, Emote, EmoteHandles},
+    eventsub::EventSubEvent,
+    leaderboard::MessageCounts,
+    moderation::Timeouts,
+    sizes::SizeClass,
+    spawn_queue::SpawnQueue,
+};
 
-use crate::emotes::emote_types::{Emote, EmoteHandles};
+/// Marker for the camera that draws the overlay window, other cameras only render copies of it
+#[derive(Component)]
+pub(crate) struct OverlayCamera {}
 
 /// Marker component to identify avatars that need their scale adjusted
 #[derive(Component, Default)]
 pub(crate) struct AdjustScale {}
 
-/// Twitch message struct
-pub(crate) struct TwitchMessage {
+/// Platform a chat message was received from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Platform {
+    Twitch,
+    YouTube,
+    Kick,
+}
+
+/// Platform neutral chat message struct
+pub struct ChatMessage {
+    /// Platform message id, used to drop duplicate deliveries
+    pub(crate) id: Option<String>,
+    pub(crate) platform: Platform,
+    /// Who sent the message, a name on Twitch and Kick and a channel id on YouTube
     pub(crate) user: String,
+    /// Name to show for the user when `user` isn't one
+    pub(crate) display_name: Option<String>,
     pub(crate) message: String,
     pub(crate) emotes: Vec<Emote>,
+    /// Room id of the channel the message was sent in when it came from another channel in a Shared Chat session
+    pub(crate) origin_room_id: Option<String>,
+    /// Badge names such as `moderator`, `subscriber` or `member`
+    pub(crate) badges: Vec<String>,
+    /// Twitch badges with their versions as `set/version`, like `subscriber/3012` for a
+    /// tier 3 sub of a year. Empty on other platforms.
+    pub(crate) badge_versions: Vec<String>,
+    /// Exact months subscribed from Twitch's badge info, badge versions stop at the last milestone
+    pub(crate) sub_months: Option<u32>,
+    /// Sent privately to the logged in account rather than in the channel
+    pub(crate) whisper: bool,
+    /// Bits cheered with the message, only Twitch has them
+    pub(crate) bits: u64,
+}
+
+impl ChatMessage {
+    /// Message that didn't come from chat, used to spawn avatars for other events
+    pub(crate) fn synthetic(platform: Platform, user: &str) -> Self {
+        Self {
+            id: None,
+            platform,
+            user: user.to_string(),
+            display_name: None,
+            message: String::new(),
+            emotes: vec![],
+            origin_room_id: None,
+            badges: vec![],
+            badge_versions: vec![],
+            sub_months: None,
+            whisper: false,
+            bits: 0,
+        }
+    }
+
+    /// A Twitch chat message from `user`, for scripts and apps embedding the overlay
+    pub fn twitch(user: &str, message: &str) -> Self {
+        Self {
+            message: message.to_string(),
+            ..Self::synthetic(Platform::Twitch, user)
+        }
+    }
+
+    /// Name to show for the sender
+    pub(crate) fn name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.user)
+    }
+
+    /// Key identifying the sender in AppState, users on different platforms are distinct
+    pub(crate) fn user_key(&self) -> String {
+        match self.platform {
+            Platform::Twitch => self.user.clone(),
+            Platform::YouTube => format!("youtube:{}", self.user),
+            Platform::Kick => format!("kick:{}", self.user),
+        }
+    }
+}
+
+// Wrap the mpsc::Receiver in a struct and derive Resource
+#[derive(Resource)]
+pub(crate) struct ChatReceiver {
+    pub(crate) receiver: mpsc::Receiver<ChatMessage>,
+}
+
+/// Puts messages into the chat pipeline from inside Bevy, as if they came from a chat source
+#[derive(Resource)]
+pub struct ChatSender {
+    pub(crate) sender: mpsc::Sender<ChatMessage>,
+}
+
+impl ChatSender {
+    /// Queue a message for the next frames, false when chat is too busy to take it
+    pub fn send(&self, chat_message: ChatMessage) -> bool {
+        self.sender.try_send(chat_message).is_ok()
+    }
+}
+
+/// Connection and login changes reported by chat sources
+#[derive(Debug, Clone)]
+pub(crate) enum SourceStatus {
+    Authenticated,
+    AuthFailed { reason: String },
+    /// A source got into chat. After an outage, with how long it was down and about how many
+    /// messages went by meanwhile.
+    Connected { platform: Platform, down_for: Option<Duration>, missed: usize },
+    Disconnected { platform: Platform },
+    Reconnecting { platform: Platform, attempt: u32 },
+    /// A source gave up for good, like a YouTube chat that ended
+    Stopped { source: String, reason: String },
+}
+
+// Wrap the mpsc::Receiver in a struct and derive Resource
+#[derive(Resource)]
+pub(crate) struct SourceStatusReceiver {
+    pub(crate) receiver: mpsc::Receiver<SourceStatus>,
+}
+
+/// Signals the chat sources to stop
+#[derive(Resource)]
+pub(crate) struct ChatShutdown {
+    pub(crate) sender: watch::Sender<bool>,
+}
+
+/// Makes Twitch chat drop its connection and connect again straight away
+#[derive(Resource)]
+pub(crate) struct ChatReconnect {
+    pub(crate) notify: Arc<Notify>,
+}
+
+/// Messages for Twitch chat, said by the logged in account
+#[derive(Resource)]
+pub(crate) struct ChatOutbox {
+    pub(crate) sender: mpsc::Sender<String>,
+}
+
+/// Online state of the monitored stream, sent from the Helix poller
+pub(crate) struct StreamStatus {
+    pub(crate) live: bool,
+    pub(crate) viewer_count: Option<u64>,
 }
 
 // Wrap the mpsc::Receiver in a struct and derive Resource
 #[derive(Resource)]
-pub(crate) struct TwitchReceiver {
-    pub(crate) receiver: mpsc::Receiver<TwitchMessage>,
+pub(crate) struct StreamStatusReceiver {
+    pub(crate) receiver: mpsc::Receiver<StreamStatus>,
+}
+
+// Wrap the mpsc::Receiver in a struct and derive Resource
+#[derive(Resource)]
+pub(crate) struct EventSubReceiver {
+    pub(crate) receiver: mpsc::Receiver<EventSubEvent>,
+}
+
+/// Channel emotes fetched from 7TV in the background, closed once there's nothing more to come
+#[derive(Resource)]
+pub(crate) struct ChannelEmoteReceiver {
+    pub(crate) receiver: mpsc::Receiver<HashMap<String, Emote>>,
+}
+
+/// Current hype train state, inactive when there is no expiry
+#[derive(Resource, Default)]
+pub(crate) struct HypeTrain {
+    pub(crate) level: u32,
+    pub(crate) progress: f32,
+    pub(crate) expires_at: Option<Instant>,
+}
+
+impl HypeTrain {
+    pub(crate) fn active(&self) -> bool {
+        self.expires_at.is_some()
+    }
+
+    /// Multiplier applied to avatar walk speed, grows with the hype train level
+    pub(crate) fn speed_multiplier(&self, config: &Config) -> f32 {
+        if self.active() {
+            1.0 + self.level as f32 * config.hype_train_speed_per_level
+        } else {
+            1.0
+        }
+    }
 }
 
 /// Struct to store all emotes that have not been loaded yet
 #[derive(Resource)]
-pub(crate) struct EmoteStorage {
+pub struct EmoteStorage {
     pub(crate) all: HashMap<String, Emote>,
     pub(crate) loaded: HashMap<String, EmoteHandles>,
+    /// Static emotes that can be drawn from the emote atlas
+    pub(crate) atlas: HashMap<String, AtlasRegion>,
 }
 
 /// App State struct stored as a Resource
 #[derive(Resource)]
-pub(crate) struct AppState {
+pub struct AppState {
     pub(crate) active_users: HashMap<String, User>,
+    /// Users by the time they were last checked, oldest first, so despawning only looks at
+    /// the ones that may be due
+    pub(crate) despawn_queue: BinaryHeap<Reverse<(Instant, String)>>,
+    /// New chatters waiting for their minawan during a raid
+    pub(crate) spawn_queue: SpawnQueue,
     pub(crate) program_state: ProgramState,
+    pub(crate) stream_live: bool,
+    pub(crate) viewer_count: Option<u64>,
+    pub(crate) spawning_paused: bool,
+    /// Set by the [Schedule] during quiet hours, no one new joins and the party sleeps
+    pub(crate) quiet_hours: bool,
+    /// Minawan walk around but their messages aren't shown
+    pub(crate) zen_mode: bool,
+    pub(crate) message_counts: MessageCounts,
+    pub(crate) bit_totals: BitTotals,
+    /// Timed out chatters, whose messages are dropped until the timeout ends
+    pub(crate) timeouts: Timeouts,
+}
+
+impl AppState {
+    /// Whether the overlay should idle because the stream is offline
+    pub(crate) fn offline_idle(&self, config: &Config) -> bool {
+        config.idle_when_offline && !self.stream_live
+    }
+
+    /// Bring a user to the party and queue their despawn check
+    pub(crate) fn insert_user(&mut self, key: String, mut user: User) {
+        user.queued_at = user.last_message_time;
+        self.despawn_queue.push(Reverse((user.queued_at, key.clone())));
+        self.active_users.insert(key, user);
+    }
+
+    /// Move a user kept under the same key in another case to `key`. Pinned users spawned on
+    /// startup go by their name as configured until they chat.
+    pub(crate) fn rekey_user(&mut self, key: &str) {
+        if self.active_users.contains_key(key) {
+            return;
+        }
+        let Some(old) = self.active_users.keys().find(|old| old.eq_ignore_ascii_case(key)).cloned() else {
+            return;
+        };
+        if let Some(user) = self.active_users.remove(&old) {
+            self.insert_user(key.to_string(), user);
+        }
+    }
 }
 
 #[derive(Resource, Debug)]
@@ -47,8 +278,21 @@ pub(crate) enum ProgramState {
 /// Struct to store User in App State
 pub(crate) struct User {
     pub(crate) entity: Entity,
-    pub(crate) _name: String,
+    pub(crate) name: String,
     pub(crate) last_message_time: Instant,
+    /// Time of the user's entry in the despawn queue, any other entry for them is stale
+    pub(crate) queued_at: Instant,
+}
+
+impl User {
+    pub(crate) fn new(entity: Entity, name: String, now: Instant) -> Self {
+        Self {
+            entity,
+            name,
+            last_message_time: now,
+            queued_at: now,
+        }
+    }
 }
 /// Marker component to identify user entities
 #[derive(Component)]
@@ -57,7 +301,7 @@ pub(crate) struct UserMarker {}
 /// Component to store the user's Twitch details
 #[derive(Component)]
 pub(crate) struct UserDetails {
-    pub(crate) _name: String,
+    pub(crate) name: String,
 }
 
 /// Emum representing possible actions for a user
@@ -81,10 +325,25 @@ pub(crate) struct UserActionDetails {
 pub(crate) struct UserBundle {
     pub(crate) marker: UserMarker,
     pub(crate) details: UserDetails,
+    /// How big the minawan is drawn, normal until the size their user picked is applied
+    pub(crate) size: SizeClass,
     pub(crate) sprite: SpriteBundle,
     pub(crate) last_action: UserActionDetails,
 }
 
+/// Component making an avatar hop in place, removed once the wave is over
+#[derive(Component)]
+pub(crate) struct Wave {
+    pub(crate) started: Instant,
+    pub(crate) base_y: f32,
+}
+
+/// Component asking avatars within the radius of this one to wave
+#[derive(Component)]
+pub(crate) struct WaveNearby {
+    pub(crate) radius: f32,
+}
+
 // MessageSpawnTime
 #[derive(Component)]
 pub(crate) struct MessageSpawnTime(pub(crate) Instant);
@@ -94,3 +353,26 @@ impl From<Instant> for MessageSpawnTime {
         Self(time)
     }
 }
+
+/// Marker for a message's speech bubble, which can be pooled, as opposed to a lone emote
+#[derive(Component)]
+pub(crate) struct MessageBubble {}
+
+/// Marker for the tail under a speech bubble, pointing at whoever is speaking
+#[derive(Component)]
+pub(crate) struct BubbleTail {}
+
+/// Marker for the outline around a Shared Chat message's bubble
+#[derive(Component)]
+pub(crate) struct SharedChatBorder {}
+
+/// Marker for an emote drawn in a message, whether on its own or inside a bubble
+#[derive(Component)]
+pub(crate) struct EmoteSprite {}
+
+/// Text a message was displayed from, so it can be redrawn once its emotes are known
+#[derive(Component)]
+pub(crate) struct MessageText {
+    pub(crate) text: String,
+    pub(crate) shared_chat: bool,
+}
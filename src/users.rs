@@ -1,55 +1,88 @@
-use std::time::Instant;
+use std::{
+    cmp::Reverse,
+    f32::consts::PI,
+    time::{Duration, Instant},
+};
 
 use bevy::{
     asset::AssetServer,
-    math::{Rect, Vec3},
+    color::Color,
+    math::{Rect, Vec2, Vec3},
     prelude::{
-        default, Camera, Commands, DespawnRecursiveExt, Entity, Query, Res, ResMut, Transform, Visibility, With
+        default, Camera, Commands, Entity, EventWriter, Local, Query, Res, ResMut, Transform, Visibility, With,
+        Without,
     },
     sprite::{Sprite, SpriteBundle},
     time::Time,
 };
-use log::info;
-use rand::Rng;
+use log::{info, warn};
+use rand::{seq::SliceRandom, Rng};
 
 use crate::{
-    config::Config, AdjustScale, AppState, TwitchMessage, UserAction, UserActionDetails, UserBundle, UserDetails, UserMarker
+    api::ApiEvent, badges::choose_badge, boop::Booping, config::Config, race::Racing, handles::HandleCache, paths::in_assets_dir, pool::EntityPools, sizes::SizeClass, snapshot::{split_key, PendingRestore}, AdjustScale, AppState, HypeTrain, ChatMessage, OverlayCamera, Platform, User, UserAction, UserActionDetails, UserBundle, UserDetails, UserMarker, Wave, WaveNearby
 };
 
-/// Spawn a new user entity in a random position
+/// How often avatars are checked for inactivity
+pub(crate) const USER_DESPAWN_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// How long a wave lasts in seconds
+pub(crate) const WAVE_DURATION: f32 = 1.0;
+/// How high avatars hop while waving
+const WAVE_HEIGHT: f32 = 12.0;
+
+/// Size of the badge marking users from other platforms
+const PLATFORM_BADGE_SIZE: f32 = 8.0;
+/// Position of the platform badge relative to the avatar's center
+const PLATFORM_BADGE_OFFSET: Vec2 = Vec2::new(-20.0, 20.0);
+
+/// A random file from assets/avatars, or AVATAR_URL when there's nothing there
+fn random_avatar(config: &Config) -> String {
+    let avatar_files: Vec<String> = match std::fs::read_dir(in_assets_dir("avatars")) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect(),
+        Err(err) => {
+            warn!("Can't list assets/avatars for RANDOM_AVATARS: {}", err);
+            vec![]
+        }
+    };
+    avatar_files
+        .choose(&mut rand::thread_rng())
+        .map_or_else(|| config.avatar_url.clone(), |file| format!("avatars/{}", file))
+}
+
+/// Spawn a new user entity in a random position, reusing a pooled avatar if there is one
 pub(crate) fn spawn_user(
     commands: &mut Commands,
+    pools: &mut EntityPools,
+    handles: &mut HandleCache,
     asset_server: &Res<AssetServer>,
-    twitch_message: &TwitchMessage,
+    chat_message: &ChatMessage,
     config: &Config,
     rect: Rect,
 ) -> Entity {
-    info!("New user: {}", twitch_message.user);
+    info!("New user: {}", chat_message.name());
     let translation = Vec3::new(
-        rand::thread_rng().gen_range((rect.max.x / -3.0)..(rect.max.x / 3.0)),
-        -(rect.max.y / 2.0) + 25.0,
+        spawn_x(&config.arrival_ranges(rect.max.x)),
+        config.floor_y(rect.max.y),
         0.0,
     );
     // If config.random_avatars is true, then look for a random file in ./assets/avatars using os
     // Otherwise, use the same avatar for all users
     let avatar_url = if config.random_avatars {
-        let avatar_files = std::fs::read_dir("assets/avatars").unwrap();
-        let avatar_files: Vec<String> = avatar_files
-            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
-            .collect();
-        let random_avatar = rand::thread_rng().gen_range(0..avatar_files.len());
-        format!("avatars/{}", avatar_files[random_avatar])
+        random_avatar(config)
     } else {
         config.avatar_url.clone()
     };
-    commands
-        .spawn(UserBundle {
+    let entity = pools
+        .spawn_avatar(commands, UserBundle {
             marker: UserMarker {},
             details: UserDetails {
-                _name: twitch_message.user.clone(),
+                name: chat_message.name().to_string(),
             },
+            size: SizeClass::Normal,
             sprite: SpriteBundle {
-                texture: asset_server.load(&avatar_url),
+                texture: handles.load(asset_server, &avatar_url),
                 transform: Transform::from_translation(translation),
                 visibility: Visibility::Hidden,
                 ..default()
@@ -59,21 +92,103 @@ pub(crate) fn spawn_user(
                 time: Instant::now(),
             },
         }).insert(AdjustScale{})
-        .id()
+        .id();
+
+    // Twitch chatters show their highest badge once its image is known
+    if config.badges_enabled {
+        if let Some(badge) = choose_badge(chat_message) {
+            commands.entity(entity).insert(badge);
+        }
+    }
+
+    // Mark users from other platforms with a small badge
+    if let Some(color) = platform_badge_color(chat_message.platform) {
+        let member = chat_message.badges.iter().any(|badge| badge == "member");
+        commands.entity(entity).with_children(|parent| {
+            if member {
+                parent.spawn(SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::srgb_u8(0xFF, 0xD7, 0x00),
+                        custom_size: Some(Vec2::splat(PLATFORM_BADGE_SIZE + 4.0)),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(PLATFORM_BADGE_OFFSET.extend(0.9)),
+                    ..default()
+                });
+            }
+            parent.spawn(SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(Vec2::splat(PLATFORM_BADGE_SIZE)),
+                    ..default()
+                },
+                transform: Transform::from_translation(PLATFORM_BADGE_OFFSET.extend(1.0)),
+                ..default()
+            });
+        });
+    }
+
+    entity
 }
 
-// Move avatars left and right randomly
+/// Pick a spot in one of the walk ranges, wider ranges get more minawan.
+/// Within a range avatars start in its middle two thirds.
+fn spawn_x(ranges: &[(f32, f32)]) -> f32 {
+    let mut rng = rand::thread_rng();
+    let total: f32 = ranges.iter().map(|(start, end)| end - start).sum();
+    let mut pick = rng.gen_range(0.0..total);
+    for (start, end) in ranges {
+        let width = end - start;
+        if pick < width {
+            return start + width / 2.0 + rng.gen_range((width / -3.0)..=(width / 3.0));
+        }
+        pick -= width;
+    }
+    ranges[0].0
+}
+
+/// The walk range an avatar belongs to, the closest one if it's outside all of them
+pub(crate) fn range_for(x: f32, ranges: &[(f32, f32)]) -> (f32, f32) {
+    let distance = |(start, end): &(f32, f32)| (start - x).max(x - end).max(0.0);
+    *ranges
+        .iter()
+        .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+        .expect("There is always a walk range")
+}
+
+/// Move x into the closest walk range
+pub(crate) fn clamp_to_ranges(x: f32, ranges: &[(f32, f32)]) -> f32 {
+    let (start, end) = range_for(x, ranges);
+    x.clamp(start, end)
+}
+
+/// Badge colour for each platform, Twitch is the default so has none
+fn platform_badge_color(platform: Platform) -> Option<Color> {
+    match platform {
+        Platform::Twitch => None,
+        Platform::YouTube => Some(Color::srgb_u8(0xFF, 0x00, 0x00)),
+        Platform::Kick => Some(Color::srgb_u8(0x53, 0xFC, 0x18)),
+    }
+}
+
+// Move avatars left and right randomly, unless they're on their way to boop someone
 pub(crate) fn move_users(
-    mut user_query: Query<(&mut Transform, &mut Sprite, &mut UserActionDetails), With<UserMarker>>,
-    camera_query: Query<&Camera>,
+    mut user_query: Query<(&mut Transform, &mut Sprite, &mut UserActionDetails), (With<UserMarker>, Without<Booping>, Without<Racing>)>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
     time: Res<Time>,
     config: Res<Config>,
+    hype_train: Res<HypeTrain>,
 ) {
     let mut rng = rand::thread_rng();
-    let rect = camera_query.single().logical_viewport_rect().unwrap();
+    let move_speed = config.avatar_move_speed * hype_train.speed_multiplier(&config);
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+    let ranges = config.walk_ranges(rect.max.x);
     for (mut transform, mut sprite, mut action) in user_query.iter_mut() {
         let now = Instant::now();
         let delta = time.delta_seconds();
+        let (start, end) = range_for(transform.translation.x, &ranges);
 
         let wait_duration = match action.last_action {
             UserAction::Stop => config.wait_duration,
@@ -81,10 +196,10 @@ pub(crate) fn move_users(
         };
         // Check if it's time to change the action
         if now.duration_since(action.time) > wait_duration {
-            // Check if the user is close to the left edge
-            let close_to_left_edge = transform.translation.x <= (rect.max.x / -2.0) + config.edge_buffer;
-            // Check if the user is close to the right edge
-            let close_to_right_edge = transform.translation.x >= (rect.max.x / 2.0) - config.edge_buffer;
+            // Check if the user is close to the left edge of their range
+            let close_to_left_edge = transform.translation.x <= start + config.edge_buffer;
+            // Check if the user is close to the right edge of their range
+            let close_to_right_edge = transform.translation.x >= end - config.edge_buffer;
 
             action.last_action = match rng.gen_range(0..3) {
                 0 if close_to_left_edge => UserAction::MoveRight,
@@ -99,29 +214,349 @@ pub(crate) fn move_users(
         // Perform the action
         match action.last_action {
             UserAction::MoveLeft => {
-                transform.translation.x -= config.avatar_move_speed * delta;
+                transform.translation.x -= move_speed * delta;
                 sprite.flip_x = true;
             }
             UserAction::MoveRight => {
-                transform.translation.x += config.avatar_move_speed * delta;
+                transform.translation.x += move_speed * delta;
                 sprite.flip_x = false;
             }
             UserAction::Stop => {}
             UserAction::_Bark => {}
         }
+
+        // Range edges are walls, turn around on reaching one
+        if transform.translation.x < start {
+            transform.translation.x = start;
+            action.last_action = UserAction::MoveRight;
+        } else if transform.translation.x > end {
+            transform.translation.x = end;
+            action.last_action = UserAction::MoveLeft;
+        }
     }
 }
 
-// Check if avatars need to despawn due to inactivity
-pub(crate) fn despawn_users(mut commands: Commands, mut app_state: ResMut<AppState>, config: Res<Config>) {
+// Despawn avatars that have been inactive for too long. Only queue entries old enough to be
+// due are looked at, users who chatted since their entry was queued are queued again.
+pub(crate) fn despawn_users(
+    mut commands: Commands,
+    mut app_state: ResMut<AppState>,
+    mut pools: ResMut<EntityPools>,
+    config: Res<Config>,
+    mut api_events: EventWriter<ApiEvent>,
+) {
     let now = Instant::now();
-    app_state.active_users.retain(|user_name, user| {
-        if now.duration_since(user.last_message_time) > config.user_despawn_time {
+    // Clear out the party faster while the stream is offline
+    let despawn_time = if app_state.offline_idle(&config) {
+        config.offline_user_despawn_time
+    } else {
+        config.user_despawn_time
+    };
+    let AppState {
+        active_users,
+        despawn_queue,
+        ..
+    } = &mut *app_state;
+    while let Some(Reverse((queued_at, _))) = despawn_queue.peek() {
+        if now.duration_since(*queued_at) <= despawn_time {
+            break;
+        }
+        let Some(Reverse((queued_at, user_name))) = despawn_queue.pop() else {
+            break;
+        };
+        // Users that left or were queued again since have another entry
+        let Some(user) = active_users.get_mut(&user_name).filter(|user| user.queued_at == queued_at) else {
+            continue;
+        };
+        // Checked again later, in case they're unpinned meanwhile
+        if config.is_pinned(&user_name) {
+            user.queued_at = now;
+            despawn_queue.push(Reverse((user.queued_at, user_name)));
+        } else if now.duration_since(user.last_message_time) > despawn_time {
             info!("Despawning user: {}", user_name);
-            commands.entity(user.entity).despawn_recursive();
-            false
+            pools.release_avatar(&mut commands, user.entity, &config);
+            active_users.remove(&user_name);
+            api_events.send(ApiEvent::UserDespawned { user: user_name });
         } else {
-            true
+            user.queued_at = user.last_message_time;
+            despawn_queue.push(Reverse((user.queued_at, user_name)));
+        }
+    }
+}
+
+// Send the minawan that has been quiet for longest home while the party is at MAX_USERS, so a
+// new chatter's minawan can join. Pinned users are never sent home, with only them left the
+// party goes over. Their despawn queue entry is left to go stale.
+pub(crate) fn make_room(
+    commands: &mut Commands,
+    app_state: &mut AppState,
+    pools: &mut EntityPools,
+    config: &Config,
+    api_events: &mut EventWriter<ApiEvent>,
+) {
+    if config.max_users == 0 {
+        return;
+    }
+    while app_state.active_users.len() >= config.max_users {
+        let Some(user_name) = app_state
+            .active_users
+            .iter()
+            .filter(|(user_name, _)| !config.is_pinned(user_name))
+            .min_by_key(|(_, user)| user.last_message_time)
+            .map(|(user_name, _)| user_name.clone())
+        else {
+            return;
+        };
+        info!("Party is full, sending home user: {}", user_name);
+        if let Some(user) = app_state.active_users.remove(&user_name) {
+            pools.release_avatar(commands, user.entity, config);
+        }
+        api_events.send(ApiEvent::UserDespawned { user: user_name });
+    }
+}
+
+// Bring the pinned users to the party once on startup with SPAWN_ON_START, after the ones the
+// snapshot is bringing back. They're restored first, where they stood.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn spawn_pinned_users(
+    mut commands: Commands,
+    mut pools: ResMut<EntityPools>,
+    mut handles: ResMut<HandleCache>,
+    asset_server: Res<AssetServer>,
+    mut app_state: ResMut<AppState>,
+    config: Res<Config>,
+    restore: Res<PendingRestore>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    mut api_events: EventWriter<ApiEvent>,
+    mut done: Local<bool>,
+) {
+    if *done {
+        return;
+    }
+    if !config.pinned_spawn_on_start {
+        *done = true;
+        return;
+    }
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+    *done = true;
+    let now = Instant::now();
+    for key in config.pinned_keys() {
+        let here = app_state.active_users.keys().any(|active| active.eq_ignore_ascii_case(&key));
+        if here || restore.contains(&key) {
+            continue;
         }
-    });
-}
\ No newline at end of file
+        info!("Bringing pinned user {} to the party", key);
+        let (platform, name) = split_key(&key);
+        let message = ChatMessage::synthetic(platform, name);
+        let entity = spawn_user(&mut commands, &mut pools, &mut handles, &asset_server, &message, &config, rect);
+        api_events.send(ApiEvent::UserSpawned { user: key.clone() });
+        app_state.insert_user(key, User::new(entity, message.name().to_string(), now));
+    }
+}
+
+// Start waves for avatars close to any avatar that asked for one
+pub(crate) fn trigger_nearby_waves(
+    mut commands: Commands,
+    source_query: Query<(Entity, &Transform, &WaveNearby)>,
+    avatar_query: Query<(Entity, &Transform), (With<UserMarker>, Without<Wave>)>,
+) {
+    let now = Instant::now();
+    for (source, source_transform, wave_nearby) in source_query.iter() {
+        for (entity, transform) in avatar_query.iter() {
+            if entity != source
+                && (transform.translation.x - source_transform.translation.x).abs() <= wave_nearby.radius
+            {
+                commands.entity(entity).insert(Wave {
+                    started: now,
+                    base_y: transform.translation.y,
+                });
+            }
+        }
+        commands.entity(source).remove::<WaveNearby>();
+    }
+}
+
+// Make waving avatars do two quick hops
+pub(crate) fn animate_waves(mut commands: Commands, mut query: Query<(Entity, &mut Transform, &Wave)>) {
+    for (entity, mut transform, wave) in query.iter_mut() {
+        let elapsed = wave.started.elapsed().as_secs_f32();
+        if elapsed >= WAVE_DURATION {
+            transform.translation.y = wave.base_y;
+            commands.entity(entity).remove::<Wave>();
+            continue;
+        }
+        let phase = (elapsed / WAVE_DURATION * 2.0).fract();
+        transform.translation.y = wave.base_y + (phase * PI).sin() * WAVE_HEIGHT;
+    }
+}
+
+// Move avatars onto the floor when the floor offset changes
+pub(crate) fn snap_users_to_floor(
+    config: Res<Config>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    mut user_query: Query<&mut Transform, (With<UserMarker>, Without<Wave>)>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+    let floor_y = config.floor_y(rect.max.y);
+    for mut transform in user_query.iter_mut() {
+        transform.translation.y = floor_y;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BinaryHeap, HashMap};
+
+    use bevy::ecs::{event::Events, system::RunSystemOnce, world::World};
+
+    use super::*;
+    use crate::{config::config_from_str, spawn_queue::SpawnQueue, ProgramState};
+
+    /// Synthetic users at the party, as after a long stream
+    const USERS: usize = 5_000;
+    /// How many of them have been quiet long enough to leave
+    const DUE: usize = 50;
+    /// How many were queued long enough ago but chatted since
+    const REFRESHED: usize = 25;
+
+    fn app_state() -> AppState {
+        AppState {
+            active_users: HashMap::new(),
+            despawn_queue: BinaryHeap::new(),
+            spawn_queue: SpawnQueue::default(),
+            program_state: ProgramState::Running,
+            stream_live: true,
+            viewer_count: None,
+            spawning_paused: false,
+            quiet_hours: false,
+            zen_mode: false,
+            message_counts: default(),
+            bit_totals: default(),
+            timeouts: default(),
+        }
+    }
+
+    /// A world with USERS users, the first DUE of them quiet for longer than the despawn time
+    /// and the next REFRESHED queued as long ago but chatting since
+    fn crowded_world(now: Instant, long_ago: Instant) -> World {
+        let mut world = World::new();
+        let mut app_state = app_state();
+        for index in 0..USERS {
+            let entity = world.spawn(SpriteBundle::default()).id();
+            let last_message = if index < DUE { long_ago } else { now };
+            app_state.insert_user(format!("user{}", index), User::new(entity, format!("User{}", index), last_message));
+        }
+        for index in DUE..DUE + REFRESHED {
+            let key = format!("user{}", index);
+            app_state.active_users.get_mut(&key).unwrap().queued_at = long_ago;
+            app_state.despawn_queue.push(Reverse((long_ago, key)));
+        }
+        world.insert_resource(app_state);
+        world.insert_resource(config_from_str("[Avatars]\nUSER_DESPAWN_TIME_SECS = 1\n").unwrap());
+        world.init_resource::<EntityPools>();
+        world.init_resource::<Events<ApiEvent>>();
+        world
+    }
+
+    fn despawned(world: &World) -> usize {
+        world
+            .resource::<Events<ApiEvent>>()
+            .iter_current_update_events()
+            .filter(|event| matches!(event, ApiEvent::UserDespawned { .. }))
+            .count()
+    }
+
+    #[test]
+    fn despawn_only_touches_due_users() {
+        let now = Instant::now();
+        let long_ago = now - Duration::from_secs(2);
+        let mut world = crowded_world(now, long_ago);
+        world.run_system_once(despawn_users);
+
+        assert_eq!(despawned(&world), DUE);
+        let app_state = world.resource::<AppState>();
+        assert_eq!(app_state.active_users.len(), USERS - DUE);
+        // The refreshed users' stale entries were replaced, everyone else's were left alone
+        assert_eq!(app_state.despawn_queue.len(), USERS - DUE + REFRESHED);
+        for (key, user) in app_state.active_users.iter() {
+            assert_eq!(user.queued_at, user.last_message_time, "{} was queued again", key);
+        }
+        let oldest = app_state.despawn_queue.peek().map(|Reverse((queued_at, _))| *queued_at);
+        assert_eq!(oldest, Some(now));
+    }
+
+    #[test]
+    fn despawn_with_nothing_due_touches_nothing() {
+        let now = Instant::now();
+        let mut world = crowded_world(now, now);
+        world.run_system_once(despawn_users);
+        world.run_system_once(despawn_users);
+
+        assert_eq!(despawned(&world), 0);
+        let app_state = world.resource::<AppState>();
+        assert_eq!(app_state.active_users.len(), USERS);
+        assert_eq!(app_state.despawn_queue.len(), USERS + REFRESHED);
+    }
+
+    /// A party of users who last chatted in the order given, the first longest ago
+    fn party(users: &[&str], config: &str) -> World {
+        let mut world = World::new();
+        let mut app_state = app_state();
+        let start = Instant::now() - Duration::from_secs(60);
+        for (index, user) in users.iter().enumerate() {
+            let entity = world.spawn(SpriteBundle::default()).id();
+            let last_message = start + Duration::from_secs(index as u64);
+            app_state.insert_user(user.to_string(), User::new(entity, user.to_string(), last_message));
+        }
+        world.insert_resource(app_state);
+        world.insert_resource(config_from_str(config).unwrap());
+        world.init_resource::<EntityPools>();
+        world.init_resource::<Events<ApiEvent>>();
+        world
+    }
+
+    fn run_make_room(world: &mut World) {
+        world.run_system_once(
+            |mut commands: Commands,
+             mut app_state: ResMut<AppState>,
+             mut pools: ResMut<EntityPools>,
+             config: Res<Config>,
+             mut api_events: EventWriter<ApiEvent>| {
+                make_room(&mut commands, &mut app_state, &mut pools, &config, &mut api_events);
+            },
+        );
+    }
+
+    fn party_members(world: &World) -> Vec<String> {
+        let mut users: Vec<String> = world.resource::<AppState>().active_users.keys().cloned().collect();
+        users.sort();
+        users
+    }
+
+    #[test]
+    fn full_party_spares_pinned_users() {
+        let config = "[Avatars]\nMAX_USERS = 3\n[Pinned]\nUSERS = [\"Regular\"]\nBROADCASTER = false\n";
+        let mut world = party(&["regular", "lurker", "chatty"], config);
+        run_make_room(&mut world);
+
+        assert_eq!(party_members(&world), vec!["chatty".to_string(), "regular".to_string()]);
+        assert_eq!(despawned(&world), 1);
+    }
+
+    #[test]
+    fn party_of_pinned_users_goes_over_the_cap() {
+        let config = "[Avatars]\nMAX_USERS = 1\n[Pinned]\nUSERS = [\"regular\", \"cohost\"]\nBROADCASTER = false\n";
+        let mut world = party(&["regular", "cohost"], config);
+        run_make_room(&mut world);
+
+        assert_eq!(party_members(&world), vec!["cohost".to_string(), "regular".to_string()]);
+        assert_eq!(despawned(&world), 0);
+    }
+}
@@ -0,0 +1,289 @@
+use std::{
+    f32::consts::TAU,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    color::Color,
+    math::{Quat, Vec2, Vec3},
+    prelude::{
+        default, Camera, Commands, Component, DespawnRecursiveExt, Entity, Event, EventReader, Local, Query, Res,
+        ResMut, Resource, Transform, With,
+    },
+    sprite::{Sprite, SpriteBundle},
+    time::Time,
+};
+use chrono::{Datelike, Local as LocalTime};
+use log::info;
+use rand::Rng;
+
+use crate::{
+    accessibility::EffectPolicy, config::Config, eventsub::EventSubEvent, frame_budget::Cosmetic, OverlayCamera,
+};
+
+/// How often the date is checked for seasonal snow
+const SEASON_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Above the avatars and messages, below UI
+const WEATHER_Z: f32 = 10.0;
+const CONFETTI_COLORS: [Color; 6] = [
+    Color::srgb(1.0, 0.3, 0.3),
+    Color::srgb(1.0, 0.8, 0.2),
+    Color::srgb(0.3, 0.9, 0.4),
+    Color::srgb(0.3, 0.6, 1.0),
+    Color::srgb(0.8, 0.4, 1.0),
+    Color::srgb(1.0, 0.5, 0.8),
+];
+
+/// What can fall on the party
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WeatherKind {
+    Rain,
+    Snow,
+    Confetti,
+}
+
+impl FromStr for WeatherKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rain" => Ok(WeatherKind::Rain),
+            "snow" => Ok(WeatherKind::Snow),
+            "confetti" => Ok(WeatherKind::Confetti),
+            _ => Err(format!("{:?} isn't rain, snow or confetti", s)),
+        }
+    }
+}
+
+impl WeatherKind {
+    /// Falling speed in pixels per second
+    fn fall_speed(self) -> f32 {
+        match self {
+            WeatherKind::Rain => 600.0,
+            WeatherKind::Snow => 60.0,
+            WeatherKind::Confetti => 150.0,
+        }
+    }
+
+    /// How much each kind is blown around by WIND
+    fn wind_factor(self) -> f32 {
+        match self {
+            WeatherKind::Rain => 1.0,
+            WeatherKind::Snow => 1.5,
+            WeatherKind::Confetti => 1.2,
+        }
+    }
+}
+
+/// Start or stop weather, from the weather command, redemptions or anything else that wants
+/// to celebrate
+#[derive(Event, Debug, Clone)]
+pub(crate) enum WeatherEvent {
+    /// Falls for `duration`, or DURATION_SECS when it's None
+    Start {
+        kind: WeatherKind,
+        duration: Option<Duration>,
+    },
+    /// Clear the sky, seasonal snow comes back on its own
+    Stop,
+}
+
+/// Weather that's falling until `until`
+struct Storm {
+    kind: WeatherKind,
+    until: Instant,
+    /// Particles owed from earlier frames, so low densities still fall
+    pending: f32,
+}
+
+#[derive(Resource, Default)]
+pub(crate) struct Weather {
+    storms: Vec<Storm>,
+    /// Particles owed to seasonal snow
+    seasonal_pending: f32,
+}
+
+/// A raindrop, snowflake or piece of confetti
+#[derive(Component)]
+pub(crate) struct WeatherParticle {
+    kind: WeatherKind,
+    /// Snowflakes sway from side to side, each at its own point in the sway
+    phase: f32,
+    spin: f32,
+}
+
+/// The storm for a redeemed reward, if it's one of the weather rewards
+fn reward_weather(config: &Config, reward_title: &str) -> Option<WeatherKind> {
+    [
+        (&config.weather_rain_reward, WeatherKind::Rain),
+        (&config.weather_snow_reward, WeatherKind::Snow),
+        (&config.weather_confetti_reward, WeatherKind::Confetti),
+    ]
+    .into_iter()
+    .find(|(reward, _)| reward.as_deref().is_some_and(|reward| reward.eq_ignore_ascii_case(reward_title)))
+    .map(|(_, kind)| kind)
+}
+
+// Start and stop storms for commands and channel point redemptions
+pub(crate) fn handle_weather_events(
+    mut weather_events: EventReader<WeatherEvent>,
+    mut eventsub_events: EventReader<EventSubEvent>,
+    mut weather: ResMut<Weather>,
+    config: Res<Config>,
+) {
+    let redemptions = eventsub_events.read().filter_map(|event| match event {
+        EventSubEvent::Redemption { reward_title, .. } => reward_weather(&config, reward_title),
+        _ => None,
+    });
+    let events: Vec<WeatherEvent> = weather_events
+        .read()
+        .cloned()
+        .chain(redemptions.map(|kind| WeatherEvent::Start { kind, duration: None }))
+        .collect();
+
+    let now = Instant::now();
+    for event in events {
+        match event {
+            WeatherEvent::Start { kind, duration } => {
+                let until = now + duration.unwrap_or(config.weather_duration);
+                info!("{:?} starts falling", kind);
+                // The same weather again lasts longer rather than falling twice as thick
+                match weather.storms.iter().position(|storm| storm.kind == kind) {
+                    Some(index) => weather.storms[index].until = weather.storms[index].until.max(until),
+                    None => weather.storms.push(Storm {
+                        kind,
+                        until,
+                        pending: 0.0,
+                    }),
+                }
+            }
+            WeatherEvent::Stop => {
+                info!("Clearing the weather");
+                weather.storms.clear();
+            }
+        }
+    }
+}
+
+// Spawn particles across the top of the walk area for every storm, December's snow included,
+// move them down with some wind and take them away at the floor. MAX_PARTICLES caps how many
+// are on screen at once, and none start falling while the frame budget has shed particles.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn run_weather(
+    mut commands: Commands,
+    mut weather: ResMut<Weather>,
+    config: Res<Config>,
+    policy: Res<EffectPolicy>,
+    time: Res<Time>,
+    camera_query: Query<&Camera, With<OverlayCamera>>,
+    mut particle_query: Query<(Entity, &mut Transform, &WeatherParticle)>,
+    mut season: Local<Option<(Instant, bool)>>,
+) {
+    let Some(rect) = camera_query.single().logical_viewport_rect() else {
+        return;
+    };
+    let now = Instant::now();
+    let delta = time.delta_seconds();
+    let elapsed = time.elapsed_seconds();
+    weather.storms.retain(|storm| now < storm.until);
+
+    if season.map_or(true, |(checked, _)| checked.elapsed() >= SEASON_CHECK_INTERVAL) {
+        *season = Some((now, LocalTime::now().month() == 12));
+    }
+    let december = season.is_some_and(|(_, december)| december);
+
+    let floor = config.floor_y(rect.max.y);
+    let mut count = 0;
+    for (entity, mut transform, particle) in particle_query.iter_mut() {
+        if transform.translation.y <= floor {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        count += 1;
+        transform.translation.y -= particle.kind.fall_speed() * delta;
+        transform.translation.x += config.weather_wind * particle.kind.wind_factor() * delta;
+        match particle.kind {
+            WeatherKind::Snow => transform.translation.x += (elapsed * 2.0 + particle.phase).sin() * 20.0 * delta,
+            WeatherKind::Confetti => transform.rotate_z(particle.spin * delta),
+            WeatherKind::Rain => {}
+        }
+    }
+
+    // Everything falls across the walk ranges, from just above the top of the screen
+    let ranges = config.walk_ranges(rect.max.x);
+    let left = ranges.first().map_or(-rect.max.x / 2.0, |range| range.0);
+    let right = ranges.last().map_or(rect.max.x / 2.0, |range| range.1);
+    let top = rect.max.y / 2.0 + 10.0;
+    let mut rng = rand::thread_rng();
+    let mut spawn = |commands: &mut Commands, kind: WeatherKind, pending: &mut f32| {
+        if !policy.allows(Cosmetic::Particles) {
+            *pending = 0.0;
+            return;
+        }
+        *pending += config.weather_density * delta;
+        while *pending >= 1.0 {
+            *pending -= 1.0;
+            if count >= config.weather_max_particles {
+                // Nothing owed piles up while the cap is reached
+                *pending = 0.0;
+                return;
+            }
+            count += 1;
+            let x = rng.gen_range(left..=right.max(left));
+            spawn_particle(commands, &mut rng, kind, Vec3::new(x, top, WEATHER_Z));
+        }
+    };
+
+    let mut storms = std::mem::take(&mut weather.storms);
+    for storm in storms.iter_mut() {
+        spawn(&mut commands, storm.kind, &mut storm.pending);
+    }
+    weather.storms = storms;
+    let snowing = weather.storms.iter().any(|storm| storm.kind == WeatherKind::Snow);
+    if config.weather_seasonal_snow && december && !snowing {
+        let mut pending = weather.seasonal_pending;
+        spawn(&mut commands, WeatherKind::Snow, &mut pending);
+        weather.seasonal_pending = pending;
+    }
+}
+
+/// Throw `count` pieces of confetti over part of the party, between `left` and `right` and
+/// from `bottom` up to `top`. They fall and land like any other weather.
+pub(crate) fn burst_confetti(commands: &mut Commands, (left, right): (f32, f32), (bottom, top): (f32, f32), count: usize) {
+    let mut rng = rand::thread_rng();
+    for _ in 0..count {
+        let x = rng.gen_range(left..=right.max(left));
+        let y = rng.gen_range(bottom..=top.max(bottom));
+        spawn_particle(commands, &mut rng, WeatherKind::Confetti, Vec3::new(x, y, WEATHER_Z));
+    }
+}
+
+fn spawn_particle(commands: &mut Commands, rng: &mut impl Rng, kind: WeatherKind, translation: Vec3) {
+    // Rain falls straight, the rest lands at any angle
+    let rotation = match kind {
+        WeatherKind::Rain => Quat::IDENTITY,
+        _ => Quat::from_rotation_z(rng.gen_range(0.0..TAU)),
+    };
+    let (color, size) = match kind {
+        WeatherKind::Rain => (Color::srgba(0.6, 0.7, 1.0, 0.6), Vec2::new(2.0, 14.0)),
+        WeatherKind::Snow => (Color::srgba(1.0, 1.0, 1.0, 0.9), Vec2::splat(rng.gen_range(3.0..6.0))),
+        WeatherKind::Confetti => (CONFETTI_COLORS[rng.gen_range(0..CONFETTI_COLORS.len())], Vec2::new(6.0, 10.0)),
+    };
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color,
+                custom_size: Some(size),
+                ..default()
+            },
+            transform: Transform::from_translation(translation).with_rotation(rotation),
+            ..default()
+        },
+        WeatherParticle {
+            kind,
+            phase: rng.gen_range(0.0..TAU),
+            spin: rng.gen_range(-6.0..6.0),
+        },
+    ));
+}
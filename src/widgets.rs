@@ -0,0 +1,114 @@
+use std::time::{Duration, Instant};
+
+use bevy::{
+    asset::AssetServer,
+    color::{Alpha, Color},
+    prelude::{default, Commands, Component, Local, Query, Res, Visibility, With},
+    text::{Text, TextStyle},
+    ui::{node_bundles::TextBundle, PositionType, Style, UiRect, Val},
+};
+
+use crate::{
+    config::{Config, ScreenCorner},
+    locale::t,
+    AppState,
+};
+
+/// Distance between corner widgets and the edge of the screen
+const CORNER_MARGIN: f32 = 8.0;
+
+/// Default for how often corner widgets refresh
+pub(crate) const DEFAULT_WIDGET_UPDATE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Style placing a UI node in a corner of the screen
+pub(crate) fn corner_style(corner: ScreenCorner) -> Style {
+    let margin = Val::Px(CORNER_MARGIN);
+    let mut style = Style {
+        position_type: PositionType::Absolute,
+        padding: UiRect::all(Val::Px(4.0)),
+        ..default()
+    };
+    match corner {
+        ScreenCorner::TopLeft => {
+            style.top = margin;
+            style.left = margin;
+        }
+        ScreenCorner::TopRight => {
+            style.top = margin;
+            style.right = margin;
+        }
+        ScreenCorner::BottomLeft => {
+            style.bottom = margin;
+            style.left = margin;
+        }
+        ScreenCorner::BottomRight => {
+            style.bottom = margin;
+            style.right = margin;
+        }
+    }
+    style
+}
+
+/// Marker for the viewer and chatter count widget
+#[derive(Component)]
+pub(crate) struct ViewerCountWidget {}
+
+/// Spawn the viewer count widget in its configured corner
+pub(crate) fn setup_viewer_widget(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<Config>,
+) {
+    if !config.viewer_widget_enabled {
+        return;
+    }
+
+    let mut widget = TextBundle::from_section(
+        "",
+        TextStyle {
+            font: asset_server.load(&config.viewer_widget_font_url),
+            font_size: config.viewer_widget_font_size,
+            color: Color::WHITE,
+        },
+    )
+    .with_style(corner_style(config.viewer_widget_corner))
+    .with_background_color(Color::BLACK.with_alpha(0.4));
+    widget.visibility = Visibility::Hidden;
+    commands.spawn((widget, ViewerCountWidget {}));
+}
+
+// Refresh the viewer and minawan counts, hiding the widget while the stream is offline
+pub(crate) fn update_viewer_widget(
+    app_state: Res<AppState>,
+    config: Res<Config>,
+    mut last_update: Local<Option<Instant>>,
+    mut widget_query: Query<(&mut Text, &mut Visibility), With<ViewerCountWidget>>,
+) {
+    if last_update.is_some_and(|time| time.elapsed() < config.viewer_widget_update_interval) {
+        return;
+    }
+    *last_update = Some(Instant::now());
+
+    for (mut text, mut visibility) in widget_query.iter_mut() {
+        if !app_state.stream_live {
+            *visibility = Visibility::Hidden;
+            continue;
+        }
+
+        let mut parts = vec![];
+        if config.viewer_widget_show_viewers {
+            if let Some(viewer_count) = app_state.viewer_count {
+                parts.push(t!("widget.viewers", count = viewer_count));
+            }
+        }
+        if config.viewer_widget_show_minawan {
+            parts.push(t!("widget.minawan", count = app_state.active_users.len()));
+        }
+        *visibility = if parts.is_empty() {
+            Visibility::Hidden
+        } else {
+            Visibility::Visible
+        };
+        text.sections[0].value = parts.join("  ");
+    }
+}
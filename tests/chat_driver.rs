@@ -0,0 +1,123 @@
+//! The party driven by TestChatDriver on the headless app: minawan arriving, talking, leaving
+//! and making room for each other
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::app::App;
+use minawan_watch_party::testing::{self, TestChatDriver};
+use tokio::runtime::Runtime;
+
+/// No snapshot from an earlier run brings anyone back, and nobody waits in the spawn queue
+const CONFIG: &str = "
+[Snapshot]
+ENABLED = false
+
+[SpawnQueue]
+SPAWNS_PER_SEC = 0.0
+";
+
+/// A headless party reading chat from the returned driver, past its first frames. Has to be
+/// called inside the tokio runtime, which has to outlive it.
+fn party(extra: &str) -> (App, TestChatDriver) {
+    let mut app = testing::headless_app(testing::config(&format!("{}{}", CONFIG, extra)));
+    let driver = TestChatDriver::attach(&mut app);
+    for _ in 0..3 {
+        app.update();
+    }
+    (app, driver)
+}
+
+/// Update the app until `done` or five seconds have gone by
+fn update_until(app: &mut App, done: impl Fn(&mut App) -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !done(app) && Instant::now() < deadline {
+        app.update();
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn first_message_spawns_a_minawan() {
+    let runtime = Runtime::new().unwrap();
+    let _runtime = runtime.enter();
+    let (mut app, chat) = party("");
+    assert_eq!(testing::minawan_count(&mut app), 0);
+
+    chat.send("minawan_fan", "hello party");
+    app.update();
+    app.update();
+    assert_eq!(testing::users(&app), vec!["minawan_fan".to_string()]);
+    assert_eq!(testing::minawan_count(&mut app), 1);
+    assert_eq!(testing::bubble_texts(&mut app), vec!["hello party".to_string()]);
+}
+
+#[test]
+fn second_message_shows_a_new_bubble() {
+    let runtime = Runtime::new().unwrap();
+    let _runtime = runtime.enter();
+    let (mut app, chat) = party("");
+    chat.send("minawan_fan", "first");
+    app.update();
+    app.update();
+    chat.send("minawan_fan", "second");
+    app.update();
+    app.update();
+
+    assert_eq!(testing::minawan_count(&mut app), 1);
+    let bubbles = testing::bubble_texts(&mut app);
+    assert_eq!(bubbles.last().map(String::as_str), Some("second"));
+    assert_eq!(bubbles.iter().filter(|text| *text == "second").count(), 1);
+}
+
+#[test]
+fn quiet_minawan_leaves_after_the_despawn_time() {
+    let runtime = Runtime::new().unwrap();
+    let _runtime = runtime.enter();
+    let (mut app, chat) = party(
+        "
+[Avatars]
+USER_DESPAWN_TIME_SECS = 1
+",
+    );
+    chat.send("minawan_fan", "brb");
+    app.update();
+    app.update();
+    assert_eq!(testing::minawan_count(&mut app), 1);
+
+    let sent = Instant::now();
+    update_until(&mut app, |app| testing::users(app).is_empty());
+    assert!(testing::users(&app).is_empty());
+    assert!(sent.elapsed() > Duration::from_secs(1));
+    // Their avatar is hidden for reuse once the release is applied
+    app.update();
+    assert_eq!(testing::minawan_count(&mut app), 0);
+}
+
+#[test]
+fn full_party_sends_the_quietest_minawan_home() {
+    let runtime = Runtime::new().unwrap();
+    let _runtime = runtime.enter();
+    let (mut app, chat) = party(
+        "
+[Avatars]
+MAX_USERS = 2
+",
+    );
+    for user in ["first", "second"] {
+        chat.send(user, "hi");
+        app.update();
+        app.update();
+    }
+    // The first one speaks up again, which makes the second the quietest
+    chat.send("first", "still here");
+    app.update();
+    chat.send("third", "room for me?");
+    app.update();
+    app.update();
+
+    assert_eq!(testing::users(&app), vec!["first".to_string(), "third".to_string()]);
+    assert_eq!(testing::minawan_count(&mut app), 2);
+}
@@ -0,0 +1,58 @@
+//! Frame time of the headless party with 200 minawan walking around. Ignored by default, run it
+//! in release with `cargo test --release --test frame_time -- --ignored --nocapture`.
+
+use std::time::{Duration, Instant};
+
+use minawan_watch_party::testing::{self, TestChatDriver};
+use tokio::runtime::Runtime;
+
+/// Minawan at the party while frames are timed
+const MINAWAN: usize = 200;
+/// Frames run before timing starts, so every avatar has loaded and started walking
+const WARMUP_FRAMES: usize = 120;
+const TIMED_FRAMES: usize = 1_000;
+
+/// Nobody from an earlier run comes back and nobody waits in the spawn queue
+const CONFIG: &str = "
+[Snapshot]
+ENABLED = false
+
+[SpawnQueue]
+SPAWNS_PER_SEC = 0.0
+";
+
+#[test]
+#[ignore = "measures performance, run on its own in release"]
+fn frame_time_with_200_minawan() {
+    let runtime = Runtime::new().unwrap();
+    let _runtime = runtime.enter();
+    let mut app = testing::headless_app(testing::config(CONFIG));
+    let chat = TestChatDriver::attach(&mut app);
+    for index in 0..MINAWAN {
+        chat.send(&format!("minawan_{}", index), "hi");
+        // The driver only holds so many messages between updates
+        if index % 50 == 49 {
+            app.update();
+        }
+    }
+    for _ in 0..WARMUP_FRAMES {
+        app.update();
+    }
+    assert_eq!(testing::minawan_count(&mut app), MINAWAN);
+
+    let mut frames: Vec<Duration> = (0..TIMED_FRAMES)
+        .map(|_| {
+            let start = Instant::now();
+            app.update();
+            start.elapsed()
+        })
+        .collect();
+    frames.sort();
+    let mean = frames.iter().sum::<Duration>() / TIMED_FRAMES as u32;
+    let median = frames[TIMED_FRAMES / 2];
+    let p95 = frames[TIMED_FRAMES * 95 / 100];
+    println!(
+        "{} minawan over {} frames: mean {:?}, median {:?}, 95th percentile {:?}",
+        MINAWAN, TIMED_FRAMES, mean, median, p95
+    );
+}
@@ -0,0 +1,41 @@
+//! The overlay as a library: a headless app with MinawanPlugin, fed chat through ChatSender
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use minawan_watch_party::{testing, ChatMessage, ChatSender};
+
+/// No snapshot from an earlier run brings anyone back
+const CONFIG: &str = "
+[Snapshot]
+ENABLED = false
+";
+
+#[test]
+fn chat_message_spawns_a_minawan() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let _runtime = runtime.enter();
+    let mut app = testing::headless_app(testing::config(CONFIG));
+    for _ in 0..3 {
+        app.update();
+    }
+    assert!(testing::users(&app).is_empty());
+
+    let sender = app.world().resource::<ChatSender>();
+    assert!(sender.send(ChatMessage::twitch("minawan_fan", "hello party")));
+    // The message is forwarded to Bevy by a task on the runtime
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while testing::users(&app).is_empty() && Instant::now() < deadline {
+        app.update();
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(testing::users(&app), vec!["minawan_fan".to_string()]);
+
+    // It stays for more frames
+    for _ in 0..10 {
+        app.update();
+    }
+    assert_eq!(testing::users(&app), vec!["minawan_fan".to_string()]);
+}
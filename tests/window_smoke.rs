@@ -0,0 +1,53 @@
+//! Opens the overlay's window for a few frames to catch transparency and click-through setup
+//! breaking. Needs a display, on CI run it under Xvfb:
+//! `xvfb-run cargo test --features window-smoke-test --test window_smoke`
+
+use bevy::{
+    prelude::*,
+    window::{PrimaryWindow, RawHandleWrapper},
+};
+use minawan_watch_party::testing;
+
+/// Frames to run before checking, enough for winit to create the window and the Linux
+/// click-through setup to run
+const FRAMES: u32 = 30;
+
+fn check_window(
+    windows: Query<(&Window, Option<&RawHandleWrapper>), With<PrimaryWindow>>,
+    mut frames: Local<u32>,
+    mut exit: EventWriter<AppExit>,
+) {
+    *frames += 1;
+    if *frames < FRAMES {
+        return;
+    }
+    let result = match windows.get_single() {
+        Ok((window, Some(_))) if window.transparent && !window.cursor.hit_test => AppExit::Success,
+        Ok((window, Some(_))) => {
+            eprintln!(
+                "The window should be transparent and click-through, transparent: {} hit test: {}",
+                window.transparent, window.cursor.hit_test
+            );
+            AppExit::from_code(1)
+        }
+        Ok((_, None)) => {
+            eprintln!("No OS window was created after {} frames", FRAMES);
+            AppExit::from_code(1)
+        }
+        Err(err) => {
+            eprintln!("There's no primary window: {}", err);
+            AppExit::from_code(1)
+        }
+    };
+    exit.send(result);
+}
+
+fn main() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let _guard = runtime.enter();
+    let mut app = testing::window_app(testing::config(""));
+    app.add_systems(Update, check_window);
+    let exit = app.run();
+    assert!(exit.is_success(), "window smoke test failed: {:?}", exit);
+    println!("window smoke test passed");
+}